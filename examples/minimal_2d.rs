@@ -0,0 +1,58 @@
+//! Draws text straight into the swapchain, which is created without any depth image.
+
+use miel::{
+    application::WindowSize,
+    gfx::{
+        render_graph::passes::text_overlay::{TextOverlay, TextOverlayPass},
+        swapchain::DepthConfig,
+    },
+    prelude::*,
+};
+
+#[derive(Default)]
+struct Minimal2D {
+    overlay: Option<TextOverlay>,
+}
+
+impl ApplicationState for Minimal2D {
+    fn on_attach(&mut self, ctx: &mut Context) {
+        let overlay_pass = TextOverlayPass::new(ResourceID::SwapchainColorAttachment, ctx)
+            .expect("text overlay should be creatable");
+        self.overlay = Some(overlay_pass.overlay());
+
+        let rendergraph_info = RenderGraphInfo::new(ResourceInfoRegistry::new())
+            .clear_color(Color::rgb(0.1, 0.1, 0.3))
+            .push_render_pass(overlay_pass);
+        ctx.bind_rendergraph(rendergraph_info)
+            .expect("rendergraph should be valid and bound");
+    }
+
+    fn update(&mut self, _ctx: &mut Context, frame: &FrameInput) -> ControlFlow {
+        if let Some(overlay) = &self.overlay {
+            let frame_time_ms = frame.delta_time.as_secs_f64() * 1000.0;
+            overlay.print(
+                8,
+                8,
+                &format!("no depth attachment\n{frame_time_ms:.2} ms"),
+                Color::WHITE,
+            );
+        }
+
+        ControlFlow::Continue
+    }
+}
+
+fn main() {
+    let app_info = WindowCreationInfo {
+        title: "minimal 2D".to_owned(),
+        inner_size: Some(WindowSize::Physical(1280, 720)),
+        ..Default::default()
+    };
+    let gfx_info =
+        ContextCreateInfo::new("minimal 2D", (0, 1, 0)).with_swapchain_depth(DepthConfig::Disabled);
+    let app = Application::build(app_info, gfx_info, Minimal2D::default())
+        .expect("app should be buildable")
+        .with_target_fps(Some(60));
+
+    app.run().expect("app should be able to run");
+}
@@ -0,0 +1,14 @@
+// Compiled to object_id.frag.spv, embedded by src/scene.rs, from the reime directory:
+// glslc assets/shaders/object_id.frag -o assets/shaders/object_id.frag.spv
+// Drawn with scene.vert, whose model matrix comes first in the push constants.
+#version 450
+
+layout(push_constant) uniform Object {
+    layout(offset = 64) uint id;
+} object;
+
+layout(location = 0) out uint out_id;
+
+void main() {
+    out_id = object.id;
+}
@@ -0,0 +1,29 @@
+// Compiled to scene.frag.spv, embedded by src/scene.rs, from the reime directory:
+// glslc -I ../src/gfx/shaders assets/shaders/scene.frag -o assets/shaders/scene.frag.spv
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "frame_constants.glsl"
+
+layout(set = 1, binding = 0) uniform sampler2D albedo;
+
+layout(location = 0) in vec3 in_world_position;
+layout(location = 1) in vec3 in_normal;
+layout(location = 2) in vec2 in_uv;
+
+layout(location = 0) out vec4 out_color;
+
+const vec3 LIGHT_DIRECTION = normalize(vec3(0.4, 1.0, 0.3));
+const float AMBIENT = 0.15;
+
+void main() {
+    vec3 normal = normalize(in_normal);
+    vec3 view_direction = normalize(frame.camera_position.xyz - in_world_position);
+    vec3 half_vector = normalize(LIGHT_DIRECTION + view_direction);
+
+    float diffuse = max(dot(normal, LIGHT_DIRECTION), 0.0);
+    float specular = pow(max(dot(normal, half_vector), 0.0), 32.0) * step(0.0, diffuse);
+
+    vec3 base_color = texture(albedo, in_uv).rgb;
+    out_color = vec4(base_color * (AMBIENT + diffuse) + vec3(0.25 * specular), 1.0);
+}
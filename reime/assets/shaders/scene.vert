@@ -0,0 +1,28 @@
+// Compiled to scene.vert.spv, embedded by src/scene.rs, from the reime directory:
+// glslc -I ../src/gfx/shaders assets/shaders/scene.vert -o assets/shaders/scene.vert.spv
+#version 450
+#extension GL_GOOGLE_include_directive : require
+
+#include "frame_constants.glsl"
+
+layout(location = 0) in vec3 in_position;
+layout(location = 1) in vec3 in_normal;
+layout(location = 2) in vec2 in_uv;
+
+layout(push_constant) uniform Object {
+    mat4 model;
+} object;
+
+layout(location = 0) out vec3 out_world_position;
+layout(location = 1) out vec3 out_normal;
+layout(location = 2) out vec2 out_uv;
+
+void main() {
+    vec4 world_position = object.model * vec4(in_position, 1.0);
+    gl_Position = frame.view_projection * world_position;
+
+    out_world_position = world_position.xyz;
+    // models are only scaled uniformly
+    out_normal = mat3(object.model) * in_normal;
+    out_uv = in_uv;
+}
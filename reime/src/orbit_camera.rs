@@ -0,0 +1,174 @@
+use miel::{
+    input::MouseState,
+    math::{Mat4, Vec3},
+    winit::event::MouseButton,
+};
+
+// radians per physical pixel dragged
+const ROTATION_SPEED: f32 = 0.005;
+// distance factor per scrolled line, touchpads scroll in pixels
+const ZOOM_PER_LINE: f32 = 0.9;
+const PIXELS_PER_LINE: f32 = 40.0;
+const MIN_DISTANCE: f32 = 1.5;
+const MAX_DISTANCE: f32 = 50.0;
+// looking straight up or down would make the up vector degenerate
+const MAX_PITCH: f32 = 1.5;
+
+const NEAR_PLANE: f32 = 0.1;
+const FAR_PLANE: f32 = 100.0;
+
+/// Camera turning around a target point, dragged with the left mouse button and zoomed with the
+/// wheel.
+#[derive(Debug, Clone)]
+pub struct OrbitCamera {
+    pub target: Vec3,
+    pub distance: f32,
+    pub yaw: f32,
+    pub pitch: f32,
+    pub fov_y: f32,
+}
+
+impl OrbitCamera {
+    pub fn new(target: Vec3, distance: f32) -> Self {
+        Self {
+            target,
+            distance,
+            yaw: 0.6,
+            pitch: 0.4,
+            fov_y: 60f32.to_radians(),
+        }
+    }
+
+    pub fn handle_input(&mut self, mouse: &MouseState) {
+        if mouse.is_button_pressed(MouseButton::Left) {
+            self.yaw -= mouse.delta[0] * ROTATION_SPEED;
+            self.pitch =
+                (self.pitch + mouse.delta[1] * ROTATION_SPEED).clamp(-MAX_PITCH, MAX_PITCH);
+        }
+
+        let scrolled_lines = mouse.scroll_lines[1] + mouse.scroll_pixels[1] / PIXELS_PER_LINE;
+        self.distance =
+            (self.distance * ZOOM_PER_LINE.powf(scrolled_lines)).clamp(MIN_DISTANCE, MAX_DISTANCE);
+    }
+
+    pub fn position(&self) -> Vec3 {
+        let offset = Vec3::new(
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            self.pitch.cos() * self.yaw.cos(),
+        );
+
+        self.target + offset * self.distance
+    }
+
+    pub fn view(&self) -> Mat4 {
+        Mat4::look_at_rh(self.position(), self.target, Vec3::Y)
+    }
+
+    /// Vulkan clip space, whose Y axis points down, with depth going from 1 at the near plane to 0
+    /// at infinity when `reverse_z` is set.
+    pub fn projection(&self, aspect_ratio: f32, reverse_z: bool) -> Mat4 {
+        let mut projection = if reverse_z {
+            Mat4::perspective_infinite_reverse_rh(self.fov_y, aspect_ratio, NEAR_PLANE)
+        } else {
+            Mat4::perspective_rh(self.fov_y, aspect_ratio, NEAR_PLANE, FAR_PLANE)
+        };
+        projection.y_axis.y = -projection.y_axis.y;
+
+        projection
+    }
+
+    /// Origin and direction of the ray going through `cursor`, in pixels from the top-left of a
+    /// `viewport` rendered with [`Self::projection`].
+    pub fn picking_ray(
+        &self,
+        cursor: [f32; 2],
+        viewport: [f32; 2],
+        reverse_z: bool,
+    ) -> (Vec3, Vec3) {
+        let ndc_x = cursor[0] / viewport[0] * 2.0 - 1.0;
+        let ndc_y = cursor[1] / viewport[1] * 2.0 - 1.0;
+        let clip_to_world =
+            (self.projection(viewport[0] / viewport[1], reverse_z) * self.view()).inverse();
+
+        // depth 0.5 lies beyond the near plane either way, the far one being at infinity with
+        // reverse z
+        let near_depth = if reverse_z { 1.0 } else { 0.0 };
+        let near = clip_to_world.project_point3(Vec3::new(ndc_x, ndc_y, near_depth));
+        let farther = clip_to_world.project_point3(Vec3::new(ndc_x, ndc_y, 0.5));
+
+        (near, (farther - near).normalize())
+    }
+}
+
+/// Whether the ray hits the sphere in front of its origin, or starts inside it.
+pub fn ray_hits_sphere(origin: Vec3, direction: Vec3, center: Vec3, radius: f32) -> bool {
+    let to_origin = origin - center;
+    let half_b = to_origin.dot(direction);
+    let c = to_origin.length_squared() - radius * radius;
+    let discriminant = half_b * half_b - c;
+
+    discriminant >= 0.0 && -half_b + discriminant.sqrt() >= 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VIEWPORT: [f32; 2] = [800.0, 600.0];
+
+    #[test]
+    fn center_ray_points_at_the_target() {
+        let camera = OrbitCamera::new(Vec3::ZERO, 4.0);
+
+        for reverse_z in [false, true] {
+            let (origin, direction) = camera.picking_ray([400.0, 300.0], VIEWPORT, reverse_z);
+
+            let to_target = (camera.target - camera.position()).normalize();
+            assert!(direction.dot(to_target) > 0.9999, "{direction} {to_target}");
+            assert!(origin.distance(camera.position()) < NEAR_PLANE * 2.0);
+        }
+    }
+
+    #[test]
+    fn top_of_the_screen_is_above_the_target() {
+        let camera = OrbitCamera::new(Vec3::ZERO, 4.0);
+
+        let (_, top) = camera.picking_ray([400.0, 0.0], VIEWPORT, false);
+        let (_, bottom) = camera.picking_ray([400.0, 600.0], VIEWPORT, false);
+
+        assert!(top.y > bottom.y);
+    }
+
+    #[test]
+    fn unit_sphere_is_hit_at_the_center_and_missed_in_the_corners() {
+        let camera = OrbitCamera::new(Vec3::ZERO, 4.0);
+
+        let hits = |cursor| {
+            let (origin, direction) = camera.picking_ray(cursor, VIEWPORT, true);
+            ray_hits_sphere(origin, direction, Vec3::ZERO, 1.0)
+        };
+
+        assert!(hits([400.0, 300.0]));
+        assert!(!hits([0.0, 0.0]));
+        assert!(!hits([800.0, 600.0]));
+    }
+
+    #[test]
+    fn spheres_behind_the_ray_are_missed() {
+        assert!(!ray_hits_sphere(
+            Vec3::ZERO,
+            Vec3::X,
+            Vec3::new(-5.0, 0.0, 0.0),
+            1.0
+        ));
+        assert!(ray_hits_sphere(
+            Vec3::ZERO,
+            Vec3::X,
+            Vec3::new(5.0, 0.0, 0.0),
+            1.0
+        ));
+        // from the inside, whatever the direction
+        assert!(ray_hits_sphere(Vec3::ZERO, Vec3::NEG_Y, Vec3::ZERO, 1.0));
+    }
+}
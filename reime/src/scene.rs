@@ -0,0 +1,533 @@
+use std::{f32::consts::PI, mem::offset_of};
+
+use miel::{
+    ash::vk,
+    gfx::{
+        context::Context,
+        device::Device,
+        mesh::{Mesh, MeshTopology, upload_mesh_data},
+        pipeline::{GraphicsPipeline, PipelineOutputs, spirv_from_bytes},
+        render_graph::{
+            render_pass::AttachmentOps,
+            resource::{FrameResources, ResourceAccessType, ResourceID},
+            typed_pass::{ColorTarget, DepthTarget, TypedRenderPass},
+        },
+        texture::Texture,
+        vertex::{Vertex, VertexInputDescription},
+    },
+    math::Mat4,
+    utils::{ThreadSafeRef, ThreadSafeRwRef},
+};
+
+const TEXTURE_SET: u32 = 1;
+
+// compiled from the GLSL sources next to them, see their headers
+const SCENE_VERT_SPV: &[u8] = include_bytes!("../assets/shaders/scene.vert.spv");
+const SCENE_FRAG_SPV: &[u8] = include_bytes!("../assets/shaders/scene.frag.spv");
+const OBJECT_ID_FRAG_SPV: &[u8] = include_bytes!("../assets/shaders/object_id.frag.spv");
+
+/// Written to the object id attachment where the sphere is drawn, 0 being the background.
+pub const SPHERE_OBJECT_ID: u32 = 1;
+pub const OBJECT_ID_FORMAT: vk::Format = vk::Format::R32_UINT;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct SceneVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+}
+
+impl Vertex for SceneVertex {
+    fn vertex_input_description() -> VertexInputDescription {
+        let binding = vk::VertexInputBindingDescription::default()
+            .binding(0)
+            .stride(std::mem::size_of::<SceneVertex>() as u32)
+            .input_rate(vk::VertexInputRate::VERTEX);
+        let attribute = |location, format, offset: usize| {
+            vk::VertexInputAttributeDescription::default()
+                .location(location)
+                .binding(0)
+                .format(format)
+                .offset(offset as u32)
+        };
+
+        VertexInputDescription {
+            bindings: vec![binding],
+            attributes: vec![
+                attribute(
+                    0,
+                    vk::Format::R32G32B32_SFLOAT,
+                    offset_of!(SceneVertex, position),
+                ),
+                attribute(
+                    1,
+                    vk::Format::R32G32B32_SFLOAT,
+                    offset_of!(SceneVertex, normal),
+                ),
+                attribute(2, vk::Format::R32G32_SFLOAT, offset_of!(SceneVertex, uv)),
+            ],
+        }
+    }
+}
+
+/// Unit sphere whose texture coordinates wrap around it once horizontally.
+pub fn uv_sphere(rings: u32, segments: u32) -> (Vec<SceneVertex>, Vec<u32>) {
+    let mut vertices = vec![];
+    for ring in 0..=rings {
+        let v = ring as f32 / rings as f32;
+        let polar = v * PI;
+        for segment in 0..=segments {
+            let u = segment as f32 / segments as f32;
+            let azimuth = u * 2.0 * PI;
+            let normal = [
+                polar.sin() * azimuth.cos(),
+                polar.cos(),
+                polar.sin() * azimuth.sin(),
+            ];
+            vertices.push(SceneVertex {
+                position: normal,
+                normal,
+                uv: [u, v],
+            });
+        }
+    }
+
+    // the seam is duplicated for the texture coordinates to wrap
+    let row_length = segments + 1;
+    let mut indices = vec![];
+    for ring in 0..rings {
+        for segment in 0..segments {
+            let top_left = ring * row_length + segment;
+            let bottom_left = top_left + row_length;
+            indices.extend([top_left, top_left + 1, bottom_left]);
+            indices.extend([bottom_left, top_left + 1, bottom_left + 1]);
+        }
+    }
+
+    (vertices, indices)
+}
+
+/// RGBA8 checkerboard of `cells`×`cells` squares, `size` pixels wide.
+pub fn checkerboard(size: u32, cells: u32) -> Vec<u8> {
+    let cell_size = (size / cells).max(1);
+    (0..size * size)
+        .flat_map(|index| {
+            let (x, y) = (index % size, index / size);
+            if (x / cell_size + y / cell_size).is_multiple_of(2) {
+                [230, 180, 90, 255]
+            } else {
+                [60, 40, 30, 255]
+            }
+        })
+        .collect()
+}
+
+pub fn build_scene_pipeline(
+    texture: &Texture,
+    color_format: vk::Format,
+    ctx: &Context,
+) -> Result<GraphicsPipeline, Box<dyn std::error::Error>> {
+    let vertex_shader = spirv_from_bytes(SCENE_VERT_SPV)?;
+    let fragment_shader = spirv_from_bytes(SCENE_FRAG_SPV)?;
+
+    let depth_compare_op = if ctx.is_reverse_z() {
+        vk::CompareOp::GREATER
+    } else {
+        vk::CompareOp::LESS
+    };
+    let depth_format = ctx
+        .swapchain_depth_format()
+        .ok_or("the scene needs the swapchain depth attachment")?;
+
+    let pipeline = GraphicsPipeline::builder("scene")
+        .with_vertex_shader(&vertex_shader)
+        .with_fragment_shader(&fragment_shader)
+        .with_vertex_input(SceneVertex::vertex_input_description())
+        .with_cull_mode(vk::CullModeFlags::BACK, vk::FrontFace::COUNTER_CLOCKWISE)
+        .with_color_formats(&[color_format])
+        .with_depth(depth_format, depth_compare_op, true)
+        .with_frame_constants()
+        .with_descriptor_set_layouts(&[texture.set_layout()])
+        .with_push_constant_ranges(&[vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX,
+            offset: 0,
+            size: std::mem::size_of::<Mat4>() as u32,
+        }])
+        .build(ctx)?;
+
+    Ok(pipeline)
+}
+
+miel::pass_resources! {
+    pub struct ForwardTargets => ForwardViews {
+        color: ColorTarget,
+        depth: DepthTarget,
+    }
+}
+
+impl ForwardTargets {
+    /// Depth is always the swapchain's.
+    pub fn new(color: ResourceID) -> Self {
+        Self {
+            color: ColorTarget::new(color, ResourceAccessType::WriteOnly),
+            depth: DepthTarget::new(
+                ResourceID::SwapchainDSAttachment,
+                ResourceAccessType::ReadWrite,
+                AttachmentOps::clear_store(),
+            ),
+        }
+    }
+}
+
+/// Draws a single textured mesh, lit by a directional light, with the camera of the frame
+/// constants.
+pub struct ForwardPass {
+    pub targets: ForwardTargets,
+    pub pipeline: GraphicsPipeline,
+    pub texture: Texture,
+    pub mesh: ThreadSafeRef<Mesh<SceneVertex>>,
+    /// Updated by the state every frame.
+    pub model: ThreadSafeRef<Mat4>,
+}
+
+impl ForwardPass {
+    /// Textured sphere drawn to `targets`, whose color attachment has the `color_format`.
+    pub fn new(
+        targets: ForwardTargets,
+        color_format: vk::Format,
+        model: ThreadSafeRef<Mat4>,
+        ctx: &mut Context,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let (vertices, indices) = uv_sphere(32, 64);
+        let upload_data = upload_mesh_data("sphere", &vertices, &indices, ctx)?;
+        let mesh = ThreadSafeRef::new(Mesh::<SceneVertex> {
+            name: "sphere".to_owned(),
+            vertices,
+            indices,
+            topology: MeshTopology::TriangleList,
+            vertex_buffer: upload_data.vertex_buffer,
+            index_buffer: upload_data.index_buffer,
+        });
+
+        let texture = Texture::from_rgba8("checkerboard", 256, 256, &checkerboard(256, 8), ctx)?;
+        let pipeline = build_scene_pipeline(&texture, color_format, ctx)?;
+
+        Ok(Self {
+            targets,
+            pipeline,
+            texture,
+            mesh,
+            model,
+        })
+    }
+}
+
+impl TypedRenderPass for ForwardPass {
+    type Resources = ForwardTargets;
+
+    fn name(&self) -> &str {
+        "forward"
+    }
+
+    fn resources(&self) -> &ForwardTargets {
+        &self.targets
+    }
+
+    fn pipeline_outputs(&self) -> Vec<PipelineOutputs> {
+        vec![self.pipeline.outputs()]
+    }
+
+    fn record(
+        &mut self,
+        _views: &ForwardViews,
+        resources: &mut FrameResources,
+        cmd_buffer: &vk::CommandBuffer,
+        device_ref: ThreadSafeRwRef<Device>,
+    ) {
+        let device = device_ref.read();
+        let model = self.model.lock().to_cols_array();
+        let mesh = self.mesh.lock();
+
+        self.pipeline.cmd_bind(cmd_buffer, &device);
+        resources.bind_frame_constants(cmd_buffer, self.pipeline.layout, &device);
+        self.texture
+            .cmd_bind(cmd_buffer, self.pipeline.layout, TEXTURE_SET, &device);
+        unsafe {
+            device.cmd_set_viewport(*cmd_buffer, 0, &[resources.viewport_full()]);
+            device.cmd_set_scissor(*cmd_buffer, 0, &[resources.current_scissor()]);
+            device.cmd_push_constants(
+                *cmd_buffer,
+                self.pipeline.layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                bytemuck::cast_slice(&model),
+            );
+            device.cmd_bind_vertex_buffers(*cmd_buffer, 0, &[mesh.vertex_buffer.handle], &[0]);
+            device.cmd_bind_index_buffer(
+                *cmd_buffer,
+                mesh.index_buffer.handle,
+                0,
+                vk::IndexType::UINT32,
+            );
+            device.cmd_draw_indexed(*cmd_buffer, mesh.indices.len() as u32, 1, 0, 0, 0);
+        }
+    }
+}
+
+miel::pass_resources! {
+    pub struct ObjectIdTargets => ObjectIdViews {
+        ids: ColorTarget,
+    }
+}
+
+impl ObjectIdTargets {
+    pub fn new(ids: ResourceID) -> Self {
+        Self {
+            ids: ColorTarget::new(ids, ResourceAccessType::WriteOnly),
+        }
+    }
+}
+
+/// Writes [`SPHERE_OBJECT_ID`] where the sphere of a [`ForwardPass`] covers the screen, for the
+/// pixel under the cursor to be read back when picking.
+pub struct ObjectIdPass {
+    pub targets: ObjectIdTargets,
+    pub pipeline: GraphicsPipeline,
+    pub mesh: ThreadSafeRef<Mesh<SceneVertex>>,
+    pub model: ThreadSafeRef<Mat4>,
+}
+
+impl ObjectIdPass {
+    /// Draws the same mesh, with the same model matrix, as `forward_pass`.
+    pub fn new(
+        targets: ObjectIdTargets,
+        forward_pass: &ForwardPass,
+        ctx: &Context,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let vertex_shader = spirv_from_bytes(SCENE_VERT_SPV)?;
+        let fragment_shader = spirv_from_bytes(OBJECT_ID_FRAG_SPV)?;
+
+        // the sphere is convex, culling alone hides its far side without a depth test
+        let pipeline = GraphicsPipeline::builder("object ids")
+            .with_vertex_shader(&vertex_shader)
+            .with_fragment_shader(&fragment_shader)
+            .with_vertex_input(SceneVertex::vertex_input_description())
+            .with_cull_mode(vk::CullModeFlags::BACK, vk::FrontFace::COUNTER_CLOCKWISE)
+            .with_color_formats(&[OBJECT_ID_FORMAT])
+            .with_frame_constants()
+            .with_push_constant_ranges(&[
+                vk::PushConstantRange {
+                    stage_flags: vk::ShaderStageFlags::VERTEX,
+                    offset: 0,
+                    size: std::mem::size_of::<Mat4>() as u32,
+                },
+                vk::PushConstantRange {
+                    stage_flags: vk::ShaderStageFlags::FRAGMENT,
+                    offset: std::mem::size_of::<Mat4>() as u32,
+                    size: std::mem::size_of::<u32>() as u32,
+                },
+            ])
+            .build(ctx)?;
+
+        Ok(Self {
+            targets,
+            pipeline,
+            mesh: forward_pass.mesh.clone(),
+            model: forward_pass.model.clone(),
+        })
+    }
+}
+
+impl TypedRenderPass for ObjectIdPass {
+    type Resources = ObjectIdTargets;
+
+    fn name(&self) -> &str {
+        "object ids"
+    }
+
+    fn resources(&self) -> &ObjectIdTargets {
+        &self.targets
+    }
+
+    fn pipeline_outputs(&self) -> Vec<PipelineOutputs> {
+        vec![self.pipeline.outputs()]
+    }
+
+    fn record(
+        &mut self,
+        _views: &ObjectIdViews,
+        resources: &mut FrameResources,
+        cmd_buffer: &vk::CommandBuffer,
+        device_ref: ThreadSafeRwRef<Device>,
+    ) {
+        let device = device_ref.read();
+        let model = self.model.lock().to_cols_array();
+        let mesh = self.mesh.lock();
+
+        self.pipeline.cmd_bind(cmd_buffer, &device);
+        resources.bind_frame_constants(cmd_buffer, self.pipeline.layout, &device);
+        unsafe {
+            device.cmd_set_viewport(*cmd_buffer, 0, &[resources.viewport_full()]);
+            device.cmd_set_scissor(*cmd_buffer, 0, &[resources.current_scissor()]);
+            device.cmd_push_constants(
+                *cmd_buffer,
+                self.pipeline.layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                bytemuck::cast_slice(&model),
+            );
+            device.cmd_push_constants(
+                *cmd_buffer,
+                self.pipeline.layout,
+                vk::ShaderStageFlags::FRAGMENT,
+                std::mem::size_of::<Mat4>() as u32,
+                &SPHERE_OBJECT_ID.to_le_bytes(),
+            );
+            device.cmd_bind_vertex_buffers(*cmd_buffer, 0, &[mesh.vertex_buffer.handle], &[0]);
+            device.cmd_bind_index_buffer(
+                *cmd_buffer,
+                mesh.index_buffer.handle,
+                0,
+                vk::IndexType::UINT32,
+            );
+            device.cmd_draw_indexed(*cmd_buffer, mesh.indices.len() as u32, 1, 0, 0, 0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use miel::{
+        gfx::{
+            context::ContextCreateInfo,
+            frame_constants::FrameConstants,
+            render_graph::{
+                RenderGraphInfo, resource::ResourceInfoRegistry, typed_pass::TypedPass,
+            },
+        },
+        math::Vec3,
+    };
+
+    use crate::orbit_camera::OrbitCamera;
+
+    use super::*;
+
+    const GOLDEN_EXTENT: vk::Extent2D = vk::Extent2D {
+        width: 128,
+        height: 128,
+    };
+    // tightly packed RGBA8 rows, written when missing or when REIME_BLESS is set
+    const GOLDEN_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden/scene.rgba");
+    // drivers may round and rasterize edges slightly differently
+    const CHANNEL_TOLERANCE: u8 = 4;
+    const MAX_MISMATCH_RATIO: f64 = 0.01;
+
+    fn count_mismatches(expected: &[u8], actual: &[u8]) -> usize {
+        expected
+            .chunks_exact(4)
+            .zip(actual.chunks_exact(4))
+            .filter(|(expected, actual)| {
+                expected
+                    .iter()
+                    .zip(actual.iter())
+                    .any(|(&expected, &actual)| expected.abs_diff(actual) > CHANNEL_TOLERANCE)
+            })
+            .count()
+    }
+
+    #[test]
+    fn mismatches_are_counted_per_pixel() {
+        let expected = [10, 20, 30, 255, 0, 0, 0, 255, 200, 200, 200, 255];
+        let actual = [
+            10 + CHANNEL_TOLERANCE,
+            20,
+            30,
+            255,
+            CHANNEL_TOLERANCE + 1,
+            CHANNEL_TOLERANCE + 1,
+            0,
+            255,
+            200,
+            200,
+            200,
+            250,
+        ];
+
+        assert_eq!(count_mismatches(&expected, &actual), 2);
+        assert_eq!(count_mismatches(&expected, &expected), 0);
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan device"]
+    fn scene_matches_golden_image() {
+        let mut ctx = Context::new_headless(
+            &ContextCreateInfo::new("golden scene", (0, 1, 0)),
+            GOLDEN_EXTENT,
+        )
+        .expect("a headless context should be created");
+        let forward_pass = ForwardPass::new(
+            ForwardTargets::new(ResourceID::SwapchainColorAttachment),
+            ctx.surface_properties().format,
+            ThreadSafeRef::new(Mat4::IDENTITY),
+            &mut ctx,
+        )
+        .expect("the scene should be created");
+        ctx.bind_rendergraph(
+            RenderGraphInfo::new(ResourceInfoRegistry::new())
+                .push_render_pass(TypedPass::new(forward_pass)),
+        )
+        .expect("the render graph should be bound");
+        let camera = OrbitCamera::new(Vec3::ZERO, 4.0);
+        ctx.set_frame_constants(FrameConstants::new(
+            camera.view(),
+            camera.projection(1.0, ctx.is_reverse_z()),
+            camera.position(),
+            0.0,
+        ));
+
+        // readbacks are resolved once their frame has completed
+        let capture = ctx
+            .capture_next_frame()
+            .expect("headless frames should be readable");
+        for _ in 0..8 {
+            if capture.is_ready() {
+                break;
+            }
+            ctx.render_offscreen_frame().expect("a frame should render");
+        }
+        let image = capture
+            .poll()
+            .expect("the capture should be resolved")
+            .expect("the capture should succeed");
+        let actual = image.to_rgba8().expect("headless images are RGBA8");
+
+        let golden_path = Path::new(GOLDEN_PATH);
+        if std::env::var_os("REIME_BLESS").is_some() || !golden_path.exists() {
+            std::fs::create_dir_all(golden_path.parent().expect("the path has a parent"))
+                .expect("the golden directory should be created");
+            std::fs::write(golden_path, &actual).expect("the golden image should be written");
+            log::info!("golden image written to {}", golden_path.display());
+            return;
+        }
+
+        let expected = std::fs::read(golden_path).expect("the golden image should be readable");
+        assert_eq!(expected.len(), actual.len(), "golden image size changed");
+        let mismatches = count_mismatches(&expected, &actual);
+        let max_mismatches =
+            (MAX_MISMATCH_RATIO * (GOLDEN_EXTENT.width * GOLDEN_EXTENT.height) as f64) as usize;
+        if mismatches > max_mismatches {
+            let actual_path = std::env::temp_dir().join("reime_scene_actual.png");
+            let saved = image.save_png(&actual_path);
+            panic!(
+                "{mismatches} pixels differ from the golden image (at most {max_mismatches} may), rendered image {}",
+                match saved {
+                    Ok(()) => format!("saved to {}", actual_path.display()),
+                    Err(err) => format!("not saved: {err}"),
+                }
+            );
+        }
+    }
+}
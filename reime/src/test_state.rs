@@ -1,109 +1,364 @@
-use std::path::Path;
+use std::path::PathBuf;
 
 use miel::{
     application,
     ash::vk,
     gfx::{
         self,
-        device::Device,
-        mesh::Mesh,
+        color::Color,
+        context::FullscreenMode,
+        frame_constants::FrameConstants,
+        mesh::{Mesh, MeshTopology, upload_mesh_data},
+        readback::{PixelReadback, PixelValue},
         render_graph::{
             RenderGraphInfo,
-            render_pass::SimpleRenderPass,
-            resource::{
-                FrameResources, ImageAttachmentInfo, ResourceAccessType, ResourceID,
-                ResourceInfoRegistry,
+            passes::{
+                bloom::{BloomEffect, BloomHandle, BloomSettings, BloomShaders},
+                text_overlay::{TextOverlay, TextOverlayPass},
             },
+            resource::{AttachmentSize, ImageAttachmentInfo, ResourceID, ResourceInfoRegistry},
+            typed_pass::TypedPass,
         },
         vertex::simple::SimpleVertex,
     },
-    utils::{ThreadSafeRef, ThreadSafeRwRef},
+    math::{Mat4, Vec3},
+    user_event::UserEvent,
+    utils::ThreadSafeRef,
+    winit::{
+        event::{ElementState, KeyEvent, MouseButton},
+        keyboard::{KeyCode, PhysicalKey},
+    },
+};
+
+use crate::{
+    orbit_camera::{OrbitCamera, ray_hits_sphere},
+    scene::{
+        ForwardPass, ForwardTargets, OBJECT_ID_FORMAT, ObjectIdPass, ObjectIdTargets,
+        SPHERE_OBJECT_ID,
+    },
 };
 
-struct GBufferData {
-    pub albedo: ResourceID,
-    pub normal: ResourceID,
-    pub sc_color: ResourceID,
-    pub sc_depth: ResourceID,
+// radians per second
+const MODEL_SPIN_SPEED: f32 = 0.3;
+const HDR_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
 
-    pub cube: ThreadSafeRef<Mesh<SimpleVertex>>,
+// object id read back under the cursor, along with what the picking ray hit when clicking
+struct PendingPick {
+    readback: PixelReadback,
+    ray_hit: bool,
 }
-fn record_gbuffer(
-    resource_handles: &mut GBufferData,
-    resources: &mut FrameResources,
-    _cmd_buffer: &vk::CommandBuffer,
-    _device_ref: ThreadSafeRwRef<Device>,
-) {
-    let albedo = resources.get(&resource_handles.albedo).unwrap();
-    let normal = resources.get(&resource_handles.normal).unwrap();
-    log::info!(
-        "found albedo and normal attachments: {:?}, {:?}",
-        albedo,
-        normal
-    );
-
-    let sc_color = resources.get(&resource_handles.sc_color).unwrap();
-    let sc_depth = resources.get(&resource_handles.sc_depth).unwrap();
-    log::info!(
-        "found swapchain color and depth attachments: {:?} {:?}",
-        sc_color,
-        sc_depth
-    );
-
-    log::info!("cube loaded: {:?}", resource_handles.cube);
+
+// parsed on a worker thread, uploaded once posted back to the update loop
+struct BackgroundMesh {
+    path: PathBuf,
+    vertices: Vec<SimpleVertex>,
+    indices: Vec<u32>,
 }
 
 pub struct TestState {
-    cube: ThreadSafeRef<Mesh<SimpleVertex>>,
+    camera: OrbitCamera,
+    model: ThreadSafeRef<Mat4>,
+    elapsed: f32,
+    // why the scene is not drawn, if it is not
+    scene_error: Option<String>,
+    background_mesh: Option<ThreadSafeRef<Mesh<SimpleVertex>>>,
+    bloom: Option<BloomHandle>,
+
+    object_ids: Option<ResourceID>,
+    pending_pick: Option<PendingPick>,
+    picked: Option<bool>,
+    pick_button_was_pressed: bool,
+
+    overlay: Option<TextOverlay>,
+    show_stats: bool,
+}
+
+impl TestState {
+    pub fn new(_ctx: &mut gfx::context::Context) -> Self {
+        Self {
+            camera: OrbitCamera::new(Vec3::ZERO, 4.0),
+            model: ThreadSafeRef::new(Mat4::IDENTITY),
+            elapsed: 0.0,
+            scene_error: None,
+            background_mesh: None,
+            bloom: None,
+            object_ids: None,
+            pending_pick: None,
+            picked: None,
+            pick_button_was_pressed: false,
+            overlay: None,
+            show_stats: true,
+        }
+    }
 }
 
 impl TestState {
-    pub fn new(ctx: &mut gfx::context::Context) -> Self {
-        let cube = SimpleVertex::load_model_from_path_obj(Path::new("assets/meshes/cube.obj"), ctx)
-            .expect("failed to load mesh");
-        Self { cube }
+    // a right click reads the object id under the cursor back, which arrives a few frames later
+    // and is cross-checked against the picking ray of the click
+    fn update_picking(&mut self, ctx: &mut gfx::context::Context, frame: &miel::input::FrameInput) {
+        if let Some(pick) = &self.pending_pick
+            && let Some(result) = pick.readback.poll()
+        {
+            match result {
+                Ok(PixelValue::U32(id)) => {
+                    let picked = id == SPHERE_OBJECT_ID;
+                    if picked == pick.ray_hit {
+                        log::info!("picked object {id}, matching the picking ray");
+                    } else {
+                        // the sphere is tessellated, its silhouette differs slightly from the ray's
+                        log::warn!(
+                            "picked object {id}, but the picking ray {} the sphere",
+                            if pick.ray_hit { "hit" } else { "missed" }
+                        );
+                    }
+                    self.picked = Some(picked);
+                }
+                Ok(value) => log::error!("object ids should be read as u32, got {value:?}"),
+                Err(err) => log::warn!("object id readback failed: {err}"),
+            }
+            self.pending_pick = None;
+        }
+
+        let mouse = &frame.input.mouse;
+        let pick_button_pressed = mouse.is_button_pressed(MouseButton::Right);
+        let clicked = pick_button_pressed && !self.pick_button_was_pressed;
+        self.pick_button_was_pressed = pick_button_pressed;
+        let (Some(object_ids), Some(cursor), true) = (self.object_ids, mouse.position, clicked)
+        else {
+            return;
+        };
+
+        match ctx.read_pixel(object_ids, cursor[0] as u32, cursor[1] as u32) {
+            Ok(readback) => {
+                let extent = ctx.surface_properties().display_extent();
+                let (origin, direction) = self.camera.picking_ray(
+                    cursor,
+                    [extent.width as f32, extent.height as f32],
+                    ctx.is_reverse_z(),
+                );
+                self.pending_pick = Some(PendingPick {
+                    readback,
+                    // the sphere only spins around its center
+                    ray_hit: ray_hits_sphere(origin, direction, Vec3::ZERO, 1.0),
+                });
+            }
+            Err(err) => log::warn!("object id under the cursor cannot be read: {err}"),
+        }
     }
 }
 
 impl application::ApplicationState for TestState {
     fn on_attach(&mut self, ctx: &mut gfx::context::Context) {
-        let mut resources = ResourceInfoRegistry::new();
-        let albedo = resources
+        // the scene is rendered in HDR, the bloom composite writing it to the swapchain
+        let mut registry = ResourceInfoRegistry::new();
+        let hdr_color = registry
             .add_image_attachment(
-                ImageAttachmentInfo::new("albedo").format(vk::Format::R8G8B8A8_SRGB),
+                ImageAttachmentInfo::new("scene hdr color")
+                    .size(AttachmentSize::SwapchainBased)
+                    .format(HDR_FORMAT)
+                    .usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED),
             )
-            .expect("resource should be unique");
-        let normal = resources
+            .expect("scene hdr color should be a valid attachment");
+        // copied from when picking
+        let object_ids = registry
             .add_image_attachment(
-                ImageAttachmentInfo::new("normal").format(vk::Format::A2B10G10R10_UNORM_PACK32),
+                ImageAttachmentInfo::new("object ids")
+                    .size(AttachmentSize::SwapchainBased)
+                    .format(OBJECT_ID_FORMAT)
+                    .usage(
+                        vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+                    ),
             )
-            .expect("resource should be unique");
+            .expect("object ids should be a valid attachment");
+        // a visible background makes presentation issues obvious
+        let mut rendergraph_info =
+            RenderGraphInfo::new(registry).clear_color(Color::rgb(0.1, 0.1, 0.3));
 
-        let sc_color = ResourceID::SwapchainColorAttachment;
-        let sc_depth = ResourceID::SwapchainDSAttachment;
+        let forward_pass = ForwardPass::new(
+            ForwardTargets::new(hdr_color),
+            HDR_FORMAT,
+            self.model.clone(),
+            ctx,
+        );
+        match forward_pass {
+            Ok(forward_pass) => {
+                let object_id_pass =
+                    ObjectIdPass::new(ObjectIdTargets::new(object_ids), &forward_pass, ctx);
+                rendergraph_info.add_render_pass(TypedPass::new(forward_pass));
+                match object_id_pass {
+                    Ok(object_id_pass) => {
+                        rendergraph_info.add_render_pass(TypedPass::new(object_id_pass));
+                        self.object_ids = Some(object_ids);
+                    }
+                    Err(err) => {
+                        log::error!("object id pass creation failed, picking is off: {err}")
+                    }
+                }
+                match BloomEffect::add_to_graph(
+                    &mut rendergraph_info,
+                    hdr_color,
+                    ResourceID::SwapchainColorAttachment,
+                    BloomSettings::default().with_intensity(0.1),
+                    &BloomShaders::default(),
+                    ctx,
+                ) {
+                    Ok(bloom) => self.bloom = Some(bloom),
+                    Err(err) => {
+                        log::error!("bloom creation failed, the scene is not shown: {err}");
+                        self.scene_error = Some(err.to_string());
+                    }
+                }
+            }
+            Err(err) => {
+                log::error!("scene creation failed, only the overlay is drawn: {err}");
+                self.scene_error = Some(err.to_string());
+            }
+        }
 
-        let gbuffer_data = GBufferData {
-            albedo,
-            normal,
-            sc_color,
-            sc_depth,
-
-            cube: self.cube.clone(),
-        };
-        let rendergraph_info = RenderGraphInfo::new(resources).push_render_pass(Box::new(
-            SimpleRenderPass::new("g-buffer", gbuffer_data)
-                .add_color_attachment(albedo, ResourceAccessType::WriteOnly)
-                .add_color_attachment(normal, ResourceAccessType::WriteOnly)
-                .add_color_attachment(sc_color, ResourceAccessType::WriteOnly)
-                .set_depth_stencil_attachment(sc_depth)
-                .set_command_recorder(Box::new(record_gbuffer)),
-        ));
+        // stats colors are picked in sRGB, like most UI
+        match TextOverlayPass::new(ResourceID::SwapchainColorAttachmentUnorm, ctx) {
+            Ok(overlay_pass) => {
+                let overlay_pass = overlay_pass.with_srgb_colors();
+                self.overlay = Some(overlay_pass.overlay());
+                rendergraph_info.add_render_pass(overlay_pass);
+            }
+            Err(err) => log::error!("text overlay creation failed, stats are not shown: {err}"),
+        }
 
         ctx.bind_rendergraph(rendergraph_info)
             .expect("rendergraph should be valid and bound");
+
+        if let Some(event_proxy) = ctx.event_proxy::<BackgroundMesh>() {
+            std::thread::spawn(move || {
+                let path = PathBuf::from("assets/meshes/cube.obj");
+                match SimpleVertex::read_obj(&path) {
+                    Ok((vertices, indices)) => {
+                        let _ = event_proxy.send(BackgroundMesh {
+                            path,
+                            vertices,
+                            indices,
+                        });
+                    }
+                    Err(err) => log::error!("background mesh loading failed: {err}"),
+                }
+            });
+        }
+    }
+
+    fn on_user_event(&mut self, ctx: &mut gfx::context::Context, event: UserEvent) {
+        let Ok(mesh) = event.downcast::<BackgroundMesh>() else {
+            return;
+        };
+
+        let name = mesh.path.display().to_string();
+        match upload_mesh_data(&name, &mesh.vertices, &mesh.indices, ctx) {
+            Ok(upload_data) => {
+                log::info!("background mesh \"{name}\" loaded");
+                self.background_mesh = Some(ThreadSafeRef::new(Mesh {
+                    name,
+                    vertices: mesh.vertices,
+                    indices: mesh.indices,
+                    topology: MeshTopology::TriangleList,
+                    vertex_buffer: upload_data.vertex_buffer,
+                    index_buffer: upload_data.index_buffer,
+                }));
+            }
+            Err(err) => log::error!("background mesh upload failed: {err}"),
+        }
     }
 
-    fn update(&mut self, _ctx: &mut gfx::context::Context) -> miel::application::ControlFlow {
+    fn update(
+        &mut self,
+        ctx: &mut gfx::context::Context,
+        frame: &miel::input::FrameInput,
+    ) -> miel::application::ControlFlow {
+        if frame.input.is_key_pressed(KeyCode::Escape) {
+            return miel::application::ControlFlow::Exit;
+        }
+
+        let frame_time = frame.delta_time;
+        self.elapsed += frame_time.as_secs_f32();
+
+        self.camera.handle_input(&frame.input.mouse);
+        self.update_picking(ctx, frame);
+        *self.model.lock() = Mat4::from_rotation_y(self.elapsed * MODEL_SPIN_SPEED);
+        let extent = ctx.surface_properties().display_extent();
+        let aspect_ratio = extent.width as f32 / extent.height.max(1) as f32;
+        ctx.set_frame_constants(FrameConstants::new(
+            self.camera.view(),
+            ctx.pre_rotation() * self.camera.projection(aspect_ratio, ctx.is_reverse_z()),
+            self.camera.position(),
+            self.elapsed,
+        ));
+
+        if let Some(overlay) = self.overlay.as_ref().filter(|_| self.show_stats) {
+            let fps = 1.0 / frame_time.as_secs_f32().max(f32::EPSILON);
+            let resize_stats = ctx.resize_stats();
+            overlay.print(
+                8,
+                8,
+                &format!(
+                    "{fps:.0} fps\n{:.2} ms\n{} resizes, {} swapchain recreations\nbackground mesh {}\nbloom {} (B)\npicked {} (right click)\n{}",
+                    frame_time.as_secs_f64() * 1000.0,
+                    resize_stats.resize_events,
+                    resize_stats.swapchain_recreations,
+                    if self.background_mesh.is_some() {
+                        "loaded"
+                    } else {
+                        "loading"
+                    },
+                    match &self.bloom {
+                        Some(bloom) if bloom.is_enabled() => "on",
+                        _ => "off",
+                    },
+                    match self.picked {
+                        Some(true) => "sphere",
+                        Some(false) => "nothing",
+                        None => "-",
+                    },
+                    match &self.scene_error {
+                        Some(err) => format!("scene unavailable: {err}"),
+                        None => "drag to orbit, scroll to zoom".to_owned(),
+                    },
+                ),
+                Color::WHITE,
+            );
+        }
+
         miel::application::ControlFlow::Continue
     }
+
+    fn on_key_event(
+        &mut self,
+        ctx: &mut gfx::context::Context,
+        event: &KeyEvent,
+    ) -> application::InputResponse {
+        // holding a key would otherwise toggle on every repeat
+        if event.state != ElementState::Pressed || event.repeat {
+            return application::InputResponse::Ignored;
+        }
+
+        match event.physical_key {
+            PhysicalKey::Code(KeyCode::F1) => {
+                self.show_stats = !self.show_stats;
+                return application::InputResponse::Handled;
+            }
+            PhysicalKey::Code(KeyCode::KeyB) => {
+                if let Some(bloom) = &self.bloom {
+                    bloom.set_enabled(!bloom.is_enabled());
+                }
+                return application::InputResponse::Handled;
+            }
+            PhysicalKey::Code(KeyCode::F11) => {
+                ctx.set_fullscreen(match ctx.fullscreen_mode() {
+                    FullscreenMode::Windowed => FullscreenMode::Borderless,
+                    _ => FullscreenMode::Windowed,
+                });
+                return application::InputResponse::Handled;
+            }
+            _ => (),
+        }
+
+        application::InputResponse::Ignored
+    }
 }
@@ -1,109 +1,1237 @@
+use std::{
+    any::Any,
+    collections::VecDeque,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use ash::vk;
 use thiserror::Error;
 
+#[cfg(feature = "png")]
+use crate::capture::CaptureHotkeys;
 use crate::{
+    benchmark::{FrameRecorder, FrameStats},
     debug::ScopeTimer,
-    gfx::context::{Context, ContextCreateError, ContextCreateInfo},
+    gfx::context::{
+        Context, ContextCreateError, ContextCreateInfo, CursorMode, FullscreenMode, RenderError,
+    },
+    input::{FrameInput, InputState},
+    monitor::{MonitorInfo, VideoModeRequest},
+    replay::{ReplayLogError, ReplayMode, ReplayReader, ReplayRecorder},
+    user_event::{EventProxy, UserEvent},
 };
 
+/// Size of the inside of a window, the swapchain always being sized in physical pixels.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum WindowSize {
+    /// Multiplied by the scale factor of the monitor, e.g. 800 points are 1600 pixels at 200%.
+    Logical(f64, f64),
+    Physical(u32, u32),
+}
+
+impl From<WindowSize> for winit::dpi::Size {
+    fn from(value: WindowSize) -> Self {
+        match value {
+            WindowSize::Logical(width, height) => {
+                winit::dpi::LogicalSize::new(width, height).into()
+            }
+            WindowSize::Physical(width, height) => {
+                winit::dpi::PhysicalSize::new(width, height).into()
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct WindowCreationInfo {
     pub title: String,
+    /// The platform picks a size when `None`.
+    pub inner_size: Option<WindowSize>,
+    pub resizable: bool,
+    /// Borderless, on the monitor the window would have opened on unless `fullscreen_monitor`
+    /// names another one.
+    pub fullscreen: bool,
+    /// Name of the monitor to go fullscreen on, see [`MonitorInfo::name`].
+    pub fullscreen_monitor: Option<String>,
+    /// Switches the monitor to the closest mode for exclusive fullscreen, rather than borderless.
+    pub exclusive_video_mode: Option<VideoModeRequest>,
+    pub decorations: bool,
+    pub maximized: bool,
+    pub min_inner_size: Option<WindowSize>,
+    pub max_inner_size: Option<WindowSize>,
+    /// Width to height ratio the inside of the window is kept at when resized, e.g. `(16, 9)`.
+    /// Not enforced while maximized or fullscreen, where the platform dictates the size.
+    pub aspect_ratio: Option<(u32, u32)>,
+}
+
+impl Default for WindowCreationInfo {
+    fn default() -> Self {
+        Self {
+            title: "miel".to_owned(),
+            inner_size: None,
+            resizable: true,
+            fullscreen: false,
+            fullscreen_monitor: None,
+            exclusive_video_mode: None,
+            decorations: true,
+            maximized: false,
+            min_inner_size: None,
+            max_inner_size: None,
+            aspect_ratio: None,
+        }
+    }
 }
 
 impl From<WindowCreationInfo> for winit::window::WindowAttributes {
     fn from(value: WindowCreationInfo) -> Self {
-        Self::default().with_title(value.title)
+        let mut attributes = Self::default()
+            .with_title(value.title)
+            .with_resizable(value.resizable)
+            .with_fullscreen(
+                value
+                    .fullscreen
+                    .then_some(winit::window::Fullscreen::Borderless(None)),
+            )
+            .with_decorations(value.decorations)
+            .with_maximized(value.maximized);
+        if let Some(inner_size) = value.inner_size {
+            attributes = attributes.with_inner_size(inner_size);
+        }
+        if let Some(min_inner_size) = value.min_inner_size {
+            attributes = attributes.with_min_inner_size(min_inner_size);
+        }
+        if let Some(max_inner_size) = value.max_inner_size {
+            attributes = attributes.with_max_inner_size(max_inner_size);
+        }
+
+        attributes
     }
 }
 
+/// States are kept in a stack, of which only the top one is updated and receives events.
 pub enum ControlFlow {
     Continue,
+    /// Replaces the top state, which is dropped.
     SwitchState(Box<dyn ApplicationState>),
+    /// Replaces the top state, handing the payload to the new one through
+    /// [`ApplicationState::on_attach_with`], e.g. resources loaded by the previous state. The
+    /// replaced state is dropped after the new one is attached.
+    SwitchStateWith(Box<dyn ApplicationState>, Box<dyn Any + Send>),
+    /// Covers the top state with a new one, e.g. a pause menu, until it pops itself.
+    Push(Box<dyn ApplicationState>),
+    /// Drops the top state and returns to the one it covered, exiting if there is none.
+    Pop,
+    Exit,
+}
+
+impl ControlFlow {
+    pub fn switch_to(state: impl ApplicationState + 'static) -> Self {
+        Self::SwitchState(Box::new(state))
+    }
+
+    pub fn switch_to_with(
+        state: impl ApplicationState + 'static,
+        payload: impl Any + Send + 'static,
+    ) -> Self {
+        Self::SwitchStateWith(Box::new(state), Box::new(payload))
+    }
+
+    pub fn push(state: impl ApplicationState + 'static) -> Self {
+        Self::Push(Box::new(state))
+    }
+}
+
+/// How the application saves power while its window is in the background.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum ThrottlePolicy {
+    /// Renders as usual whether the window is focused and visible or not.
+    #[default]
+    Off,
+    /// Stops rendering while the window is occluded, states still being updated
+    /// [`OCCLUDED_UPDATE_RATE`] times per second.
+    SkipRenderWhenOccluded,
+    /// Caps the frame rate to the given one while the window is unfocused, and stops rendering
+    /// while it is occluded, states still being updated at that rate.
+    ReduceTo(u32),
+}
+
+/// Updates per second while the window is occluded under
+/// [`ThrottlePolicy::SkipRenderWhenOccluded`].
+pub const OCCLUDED_UPDATE_RATE: u32 = 10;
+
+impl ThrottlePolicy {
+    // `None` when frames are rendered anyway
+    fn occluded_update_interval(self) -> Option<Duration> {
+        let update_rate = match self {
+            Self::Off => return None,
+            Self::SkipRenderWhenOccluded => OCCLUDED_UPDATE_RATE,
+            Self::ReduceTo(frame_rate) => frame_rate,
+        };
+
+        Some(Duration::from_secs_f64(1.0 / update_rate.max(1) as f64))
+    }
+}
+
+// attempts at a failing operation before giving up, whatever the state responds
+const MAX_ERROR_ATTEMPTS: u32 = 3;
+// window events kept until the context exists, the oldest are dropped past it
+const MAX_EARLY_EVENTS: usize = 256;
+
+/// Window events received before the context exists, kept in arrival order.
+#[derive(Debug, Default)]
+struct EarlyEvents(VecDeque<winit::event::WindowEvent>);
+
+impl EarlyEvents {
+    /// Keeps `event` for later, or hands it back when it has to be handled right away.
+    fn defer(&mut self, event: winit::event::WindowEvent) -> Option<winit::event::WindowEvent> {
+        if matches!(
+            event,
+            winit::event::WindowEvent::CloseRequested | winit::event::WindowEvent::RedrawRequested
+        ) {
+            return Some(event);
+        }
+
+        if self.0.len() == MAX_EARLY_EVENTS {
+            self.0.pop_front();
+        }
+        self.0.push_back(event);
+        None
+    }
+
+    /// Empties the buffer, yielding the events oldest first.
+    fn take(&mut self) -> VecDeque<winit::event::WindowEvent> {
+        std::mem::take(&mut self.0)
+    }
+}
+
+/// Failure reported to [`ApplicationState::on_error`], and returned by [`Application::run`] when
+/// it ended the application.
+#[derive(Debug, Error)]
+pub enum ApplicationError {
+    #[error("window creation failed")]
+    WindowCreation(#[from] winit::error::OsError),
+
+    #[error("vulkan context creation failed")]
+    ContextCreation(#[from] ContextCreateError),
+
+    #[error("frame rendering failed")]
+    Render(#[from] RenderError),
+}
+
+impl ApplicationError {
+    /// Fatal errors always exit the application, whatever the state responds.
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            ApplicationError::ContextCreation(
+                ContextCreateError::VulkanLoad(_)
+                    | ContextCreateError::InstanceCreation(_)
+                    | ContextCreateError::PhysicalDeviceSelection(_)
+                    | ContextCreateError::DeviceCreation(_)
+            )
+        )
+    }
+
+    /// Only [`ErrorResponse::RebuildContext`] carries on after a device loss.
+    pub fn is_device_lost(&self) -> bool {
+        matches!(self, ApplicationError::Render(RenderError::DeviceLost(_)))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ErrorResponse {
+    /// Attempts the failed operation again right away, a few times at most.
+    Retry,
+    /// Gives up on the current frame and carries on with the next one. Exits when the window, the
+    /// context or its surface could not be created, as there is nothing to carry on with.
+    SkipFrame,
+    /// Drops the context and creates it again, the only way to carry on after a device loss.
+    /// Every state is told through [`ApplicationState::on_device_lost`] beforehand, and the top
+    /// one attached again afterwards. Exits for any other error.
+    RebuildContext,
     Exit,
 }
 
+impl ErrorResponse {
+    // what the application does when a state responds with `self`
+    fn applicable_to(self, error: &ApplicationError, attempt: u32, can_skip: bool) -> Self {
+        match self {
+            _ if error.is_fatal() => Self::Exit,
+            Self::RebuildContext if !error.is_device_lost() => Self::Exit,
+            // nothing else can be done with a lost device
+            Self::Retry | Self::SkipFrame if error.is_device_lost() => Self::Exit,
+            Self::Retry if attempt >= MAX_ERROR_ATTEMPTS => Self::Exit,
+            Self::SkipFrame if !can_skip => Self::Exit,
+            response => response,
+        }
+    }
+}
+
+/// Whether a state consumed an input event, keeping the engine from acting on it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InputResponse {
+    Ignored,
+    Handled,
+}
+
 pub trait ApplicationState {
+    /// Called whenever the state becomes the top of the stack, including when the state covering
+    /// it is popped.
     fn on_attach(&mut self, _ctx: &mut Context) {}
 
-    fn update(&mut self, _ctx: &mut Context) -> ControlFlow {
+    /// Called instead of [`on_attach`](Self::on_attach) when the state is attached through
+    /// [`ControlFlow::SwitchStateWith`], the payload being downcast to what the previous state
+    /// sent. Ignores the payload by default.
+    fn on_attach_with(&mut self, ctx: &mut Context, _payload: Box<dyn Any + Send>) {
+        log::warn!("state attached with a payload it does not take, dropping it");
+        self.on_attach(ctx);
+    }
+
+    /// Called before the surface and the swapchain are destroyed when the application is
+    /// suspended, e.g. sent to the background on Android, to pause streaming work. The context and
+    /// everything created from it are kept.
+    fn on_suspend(&mut self, _ctx: &mut Context) {}
+
+    /// Called once the surface and the swapchain were created again after a suspension.
+    fn on_resume(&mut self, _ctx: &mut Context) {}
+
+    /// Called whenever the state stops being the top of the stack, covered or removed, e.g. to
+    /// release what it bound to the context. Not called when the application exits.
+    fn on_detach(&mut self, _ctx: &mut Context) {}
+
+    /// Called on every state of the stack, top first, before the context is rebuilt after a device
+    /// loss, see [`ErrorResponse::RebuildContext`]. Everything the state created from the context
+    /// must be dropped here, the top state creates it again in [`Self::on_attach`].
+    fn on_device_lost(&mut self, _ctx: &mut Context) {}
+
+    /// Called on every state of the stack, top first, when the application exits for any reason.
+    /// The device is idle and the context still alive, states are dropped right after, before it.
+    fn on_exit(&mut self, _ctx: &mut Context) {}
+
+    fn update(&mut self, _ctx: &mut Context, _frame: &FrameInput) -> ControlFlow {
         ControlFlow::Continue
     }
+
+    /// Called before the engine acts on the key itself, e.g. for capture hotkeys, and before the
+    /// next [`Self::update`]. Auto-repeated presses have `event.repeat` set. Keys held down can
+    /// also be polled from the [`FrameInput`] given to `update`.
+    fn on_key_event(
+        &mut self,
+        _ctx: &mut Context,
+        _event: &winit::event::KeyEvent,
+    ) -> InputResponse {
+        InputResponse::Ignored
+    }
+
+    /// Mouse hooks are called as events arrive, before the next [`Self::update`]. The state of
+    /// the mouse over the whole frame is also available from the [`FrameInput`].
+    fn on_mouse_button(
+        &mut self,
+        _ctx: &mut Context,
+        _button: winit::event::MouseButton,
+        _state: winit::event::ElementState,
+    ) {
+    }
+
+    /// `position` is in physical pixels from the top-left of the window.
+    fn on_mouse_move(&mut self, _ctx: &mut Context, _position: [f32; 2]) {}
+
+    fn on_scroll(&mut self, _ctx: &mut Context, _delta: winit::event::MouseScrollDelta) {}
+
+    /// Called once the swapchain was recreated for the new size of the window, in physical
+    /// pixels. Resizes are applied before the next update, only the latest one when several were
+    /// reported since the last frame. Frames are not rendered while either dimension is 0.
+    fn on_resize(&mut self, _ctx: &mut Context, _new_size: (u32, u32)) {}
+
+    /// Called when the window moved to a monitor with another scale factor, or the scale of the
+    /// monitor changed. The resize to the new physical size, if any, follows.
+    fn on_scale_factor_changed(&mut self, _ctx: &mut Context, _scale_factor: f64) {}
+
+    fn on_focus_changed(&mut self, _ctx: &mut Context, _focused: bool) {}
+
+    /// Called when the window becomes fully hidden, e.g. minimized or covered, or visible again.
+    /// Not every platform reports it.
+    fn on_occlusion_changed(&mut self, _ctx: &mut Context, _occluded: bool) {}
+
+    /// Called with every event posted through an [`EventProxy`] since the last update, in order,
+    /// right before the next one.
+    fn on_user_event(&mut self, _ctx: &mut Context, _event: UserEvent) {}
+
+    /// Called for every file dragged over the window, before it is dropped. The files being
+    /// hovered can also be queried with [`Context::hovered_files`].
+    fn on_file_hovered(&mut self, _ctx: &mut Context, _path: PathBuf) {}
+
+    /// Called when the files hovering the window were dragged away without being dropped.
+    fn on_file_hover_cancelled(&mut self, _ctx: &mut Context) {}
+
+    /// Called once per file when several are dropped together, in the order the platform lists
+    /// them.
+    fn on_file_dropped(&mut self, _ctx: &mut Context, _path: PathBuf) {}
+
+    /// Called when the window or the context could not be created, or when a frame failed to
+    /// render and the context could not recover on its own.
+    fn on_error(&mut self, _error: &ApplicationError) -> ErrorResponse {
+        ErrorResponse::Exit
+    }
+}
+
+// keeps states boxed before being given to the application working, at the cost of a second box
+impl<S: ApplicationState + ?Sized> ApplicationState for Box<S> {
+    fn on_attach(&mut self, ctx: &mut Context) {
+        (**self).on_attach(ctx);
+    }
+
+    fn on_attach_with(&mut self, ctx: &mut Context, payload: Box<dyn Any + Send>) {
+        (**self).on_attach_with(ctx, payload);
+    }
+
+    fn on_suspend(&mut self, ctx: &mut Context) {
+        (**self).on_suspend(ctx);
+    }
+
+    fn on_resume(&mut self, ctx: &mut Context) {
+        (**self).on_resume(ctx);
+    }
+
+    fn on_detach(&mut self, ctx: &mut Context) {
+        (**self).on_detach(ctx);
+    }
+
+    fn on_device_lost(&mut self, ctx: &mut Context) {
+        (**self).on_device_lost(ctx);
+    }
+
+    fn on_exit(&mut self, ctx: &mut Context) {
+        (**self).on_exit(ctx);
+    }
+
+    fn update(&mut self, ctx: &mut Context, frame: &FrameInput) -> ControlFlow {
+        (**self).update(ctx, frame)
+    }
+
+    fn on_key_event(&mut self, ctx: &mut Context, event: &winit::event::KeyEvent) -> InputResponse {
+        (**self).on_key_event(ctx, event)
+    }
+
+    fn on_mouse_button(
+        &mut self,
+        ctx: &mut Context,
+        button: winit::event::MouseButton,
+        state: winit::event::ElementState,
+    ) {
+        (**self).on_mouse_button(ctx, button, state);
+    }
+
+    fn on_mouse_move(&mut self, ctx: &mut Context, position: [f32; 2]) {
+        (**self).on_mouse_move(ctx, position);
+    }
+
+    fn on_scroll(&mut self, ctx: &mut Context, delta: winit::event::MouseScrollDelta) {
+        (**self).on_scroll(ctx, delta);
+    }
+
+    fn on_resize(&mut self, ctx: &mut Context, new_size: (u32, u32)) {
+        (**self).on_resize(ctx, new_size);
+    }
+
+    fn on_scale_factor_changed(&mut self, ctx: &mut Context, scale_factor: f64) {
+        (**self).on_scale_factor_changed(ctx, scale_factor);
+    }
+
+    fn on_focus_changed(&mut self, ctx: &mut Context, focused: bool) {
+        (**self).on_focus_changed(ctx, focused);
+    }
+
+    fn on_occlusion_changed(&mut self, ctx: &mut Context, occluded: bool) {
+        (**self).on_occlusion_changed(ctx, occluded);
+    }
+
+    fn on_user_event(&mut self, ctx: &mut Context, event: UserEvent) {
+        (**self).on_user_event(ctx, event);
+    }
+
+    fn on_file_hovered(&mut self, ctx: &mut Context, path: PathBuf) {
+        (**self).on_file_hovered(ctx, path);
+    }
+
+    fn on_file_hover_cancelled(&mut self, ctx: &mut Context) {
+        (**self).on_file_hover_cancelled(ctx);
+    }
+
+    fn on_file_dropped(&mut self, ctx: &mut Context, path: PathBuf) {
+        (**self).on_file_dropped(ctx, path);
+    }
+
+    fn on_error(&mut self, error: &ApplicationError) -> ErrorResponse {
+        (**self).on_error(error)
+    }
+}
+
+enum ReplaySession {
+    Off,
+    Recording(ReplayRecorder),
+    Replaying(ReplayReader),
 }
 
 pub struct Application {
-    state: Box<dyn ApplicationState>,
+    // only the last state is active, empty once the application is exiting
+    states: Vec<Box<dyn ApplicationState>>,
+
+    // created early for proxies to be handed out before running
+    event_loop: Option<winit::event_loop::EventLoop<UserEvent>>,
+    event_proxy: winit::event_loop::EventLoopProxy<UserEvent>,
+    // delivered to the active state before its next update
+    user_events: VecDeque<UserEvent>,
+    // window events received before the context was created, replayed once the state is attached
+    early_events: EarlyEvents,
 
     gfx_context_create_info: ContextCreateInfo,
     gfx_context: Option<crate::gfx::context::Context>,
 
     window_create_info: WindowCreationInfo,
     window: Option<winit::window::Window>,
+    // size and position to restore when leaving fullscreen
+    windowed_placement: Option<(
+        winit::dpi::PhysicalSize<u32>,
+        Option<winit::dpi::PhysicalPosition<i32>>,
+    )>,
+    // last size requested from the window to keep its aspect ratio
+    aspect_correction: Option<winit::dpi::PhysicalSize<u32>>,
+
+    modifiers: winit::keyboard::ModifiersState,
+    // raw mouse motion is reported even when another window has the focus
+    focused: bool,
+    occluded: bool,
+    throttle_policy: ThrottlePolicy,
+    // frame rate limit to restore once the window is focused again
+    unthrottled_frame_rate_limit: Option<Option<f32>>,
+    cursor_locked: bool,
+    #[cfg(feature = "png")]
+    capture_hotkeys: Option<CaptureHotkeys>,
+
+    input: InputState,
+    last_update: Option<Instant>,
+    target_fps: Option<u32>,
+    replay_mode: ReplayMode,
+    replay: ReplaySession,
+    // why the event loop was stopped, if it was because of an error
+    exit_error: Option<ApplicationError>,
+    // set by `run_frames`, the application exits once it is done
+    benchmark: Option<FrameRecorder>,
 }
 
 #[derive(Debug, Error)]
 pub enum ApplicationBuildError {
     #[error("vulkan context creation failed")]
     VkContextCreation(#[from] ContextCreateError),
+
+    #[error("event loop creation failed")]
+    EventLoopCreation(#[from] winit::error::EventLoopError),
 }
 
 #[derive(Debug, Error)]
 pub enum ApplicationStartError {
-    #[error("event loop creation failed")]
-    EventLoopCreation(winit::error::EventLoopError),
-
     #[error("application run failed")]
     ApplicationRun(winit::error::EventLoopError),
+
+    #[error("replay log opening failed")]
+    ReplayLog(#[from] ReplayLogError),
+
+    #[error("application exited on error")]
+    Exited(#[from] ApplicationError),
 }
 
 impl Application {
     pub fn build(
         window_create_info: WindowCreationInfo,
         vulkan_context_create_info: ContextCreateInfo,
-        start_state: Box<dyn ApplicationState>,
+        start_state: impl ApplicationState + 'static,
     ) -> Result<Self, ApplicationBuildError> {
+        let event_loop = winit::event_loop::EventLoop::with_user_event().build()?;
+        let event_proxy = event_loop.create_proxy();
+
         Ok(Self {
             window_create_info,
             window: None,
+            windowed_placement: None,
+            aspect_correction: None,
 
             gfx_context_create_info: vulkan_context_create_info,
             gfx_context: None,
 
-            state: start_state,
+            states: vec![Box::new(start_state)],
+
+            event_loop: Some(event_loop),
+            event_proxy,
+            user_events: VecDeque::new(),
+            early_events: EarlyEvents::default(),
+
+            modifiers: winit::keyboard::ModifiersState::empty(),
+            focused: true,
+            occluded: false,
+            throttle_policy: ThrottlePolicy::Off,
+            unthrottled_frame_rate_limit: None,
+            cursor_locked: false,
+            #[cfg(feature = "png")]
+            capture_hotkeys: None,
+
+            input: InputState::default(),
+            last_update: None,
+            target_fps: None,
+            replay_mode: ReplayMode::Off,
+            replay: ReplaySession::Off,
+            exit_error: None,
+            benchmark: None,
         })
     }
 
+    /// F12 saves a screenshot and Shift+F12 every attachment of the bound render graph, as PNGs
+    /// next to the executable. States can still take these keys through
+    /// [`ApplicationState::on_key_event`].
+    #[cfg(feature = "png")]
+    pub fn with_capture_hotkeys(mut self, enabled: bool) -> Self {
+        self.capture_hotkeys = enabled.then(CaptureHotkeys::default);
+        self
+    }
+
+    /// Caps the frame rate, the event loop sleeping between frames instead of polling. Can be
+    /// changed afterwards through [`Context::set_frame_rate_limit`].
+    pub fn with_target_fps(mut self, target_fps: Option<u32>) -> Self {
+        self.target_fps = target_fps;
+        self
+    }
+
+    /// Saves power while the window is in the background, see [`ThrottlePolicy`].
+    pub fn with_throttle_policy(mut self, policy: ThrottlePolicy) -> Self {
+        self.throttle_policy = policy;
+        self
+    }
+
+    /// Records the input and delta time of every update to a log, or feeds them from one in place
+    /// of the live ones. The window keeps presenting during replays, which fall back to live input
+    /// once the log is exhausted.
+    pub fn with_replay(mut self, replay_mode: ReplayMode) -> Self {
+        self.replay_mode = replay_mode;
+        self
+    }
+
+    /// Handle to post events to the states from other threads, also available to them through
+    /// [`Context::event_proxy`].
+    pub fn event_proxy<T: Send + 'static>(&self) -> EventProxy<T> {
+        EventProxy::new(self.event_proxy.clone())
+    }
+
+    /// Runs the application until `frames` frames are presented, e.g. to track performance.
+    /// States cannot exit before, but closing the window still ends the run early.
+    pub fn run_frames(mut self, frames: u64) -> Result<FrameStats, ApplicationStartError> {
+        self.benchmark = Some(FrameRecorder::new(frames));
+        self.run_event_loop()?;
+
+        Ok(self
+            .benchmark
+            .take()
+            .expect("benchmark should be kept until the run ends")
+            .stats())
+    }
+
     pub fn run(mut self) -> Result<(), ApplicationStartError> {
-        let event_loop = winit::event_loop::EventLoop::new()
-            .map_err(ApplicationStartError::EventLoopCreation)?;
+        self.run_event_loop()
+    }
+
+    fn run_event_loop(&mut self) -> Result<(), ApplicationStartError> {
+        self.replay = match &self.replay_mode {
+            ReplayMode::Off => ReplaySession::Off,
+            ReplayMode::Record(path) => ReplaySession::Recording(ReplayRecorder::create(path)?),
+            ReplayMode::Replay(path) => ReplaySession::Replaying(ReplayReader::open(path)?),
+        };
+
+        let event_loop = self
+            .event_loop
+            .take()
+            .expect("event loop should only be taken by run");
 
         event_loop.set_control_flow(winit::event_loop::ControlFlow::Poll);
         event_loop
-            .run_app(&mut self)
+            .run_app(self)
             .map_err(ApplicationStartError::ApplicationRun)?;
 
+        match self.exit_error.take() {
+            Some(err) => Err(err.into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Asks the active state how to handle the error, returns whether to retry the operation. The
+    /// event loop is stopped when giving up.
+    fn handle_error(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        error: ApplicationError,
+        attempt: u32,
+        can_skip: bool,
+    ) -> bool {
+        let requested = self
+            .states
+            .last_mut()
+            .map_or(ErrorResponse::Exit, |state| state.on_error(&error));
+        let response = requested.applicable_to(&error, attempt, can_skip);
+
+        match response {
+            ErrorResponse::Retry => {
+                log::warn!("{error}, retrying (attempt {attempt})");
+                true
+            }
+            ErrorResponse::SkipFrame => {
+                log::warn!("{error}, skipping frame");
+                false
+            }
+            ErrorResponse::RebuildContext => {
+                log::warn!("{error}, rebuilding the context");
+                if let Err(err) = self.rebuild_context() {
+                    log::error!("{err}, exiting");
+                    self.exit_error = Some(err);
+                    event_loop.exit();
+                }
+                false
+            }
+            ErrorResponse::Exit => {
+                log::error!("{error}, exiting");
+                self.exit_error = Some(error);
+                event_loop.exit();
+                false
+            }
+        }
+    }
+
+    // the context is gone when the rebuild fails, the application exits right after
+    fn rebuild_context(&mut self) -> Result<(), ApplicationError> {
+        let (Some(window), Some(mut context)) = (self.window.as_ref(), self.gfx_context.take())
+        else {
+            return Ok(());
+        };
+
+        for state in self.states.iter_mut().rev() {
+            state.on_device_lost(&mut context);
+        }
+        let context = self.gfx_context.insert(context.rebuild(window)?);
+        if let Some(state) = self.states.last_mut() {
+            state.on_attach(context);
+        }
+
         Ok(())
     }
+
+    fn create_window_and_context(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+    ) -> Result<(), ApplicationError> {
+        let window = match self.window.take() {
+            Some(window) => window,
+            None => {
+                let mut attributes: winit::window::WindowAttributes =
+                    self.window_create_info.clone().into();
+                if self.window_create_info.fullscreen {
+                    attributes.fullscreen =
+                        initial_fullscreen(&self.window_create_info, event_loop);
+                }
+                event_loop.create_window(attributes)?
+            }
+        };
+        // kept on failure, only the context is created again on retries
+        let context = Context::new(&window, &self.gfx_context_create_info);
+        let initial_size = window.inner_size();
+        self.window = Some(window);
+        let mut context = context?;
+        context.set_event_proxy(self.event_proxy.clone());
+        context.set_frame_rate_limit(self.target_fps.map(|target_fps| target_fps as f32));
+        self.gfx_context = Some(context);
+        // the requested size may not match the aspect ratio to begin with
+        if let Some(size) = self.enforce_aspect_ratio(initial_size)
+            && size != initial_size
+            && let Some(context) = self.gfx_context.as_mut()
+        {
+            context.resize(vk::Extent2D {
+                width: size.width,
+                height: size.height,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Size to recreate the swapchain for after the window reported `size`, or `None` when a
+    /// corrected size was requested from the window instead, reported by a later resize.
+    fn enforce_aspect_ratio(
+        &mut self,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) -> Option<winit::dpi::PhysicalSize<u32>> {
+        let requested = self.aspect_correction.take();
+        let Some(aspect_ratio) = self
+            .window_create_info
+            .aspect_ratio
+            .filter(|&(width, height)| width > 0 && height > 0)
+        else {
+            return Some(size);
+        };
+        let (Some(window), Some(context)) = (self.window.as_ref(), self.gfx_context.as_ref())
+        else {
+            return Some(size);
+        };
+        if size.width == 0
+            || size.height == 0
+            || window.is_maximized()
+            || window.fullscreen().is_some()
+        {
+            return Some(size);
+        }
+
+        let previous = context.physical_extent();
+        let previous = winit::dpi::PhysicalSize::new(previous.width, previous.height);
+        let corrected = aspect_locked_size(size, previous, aspect_ratio);
+        // asking again for the size the platform just refused would never settle
+        if corrected == size || requested == Some(corrected) {
+            return Some(size);
+        }
+
+        match window.request_inner_size(corrected) {
+            Some(applied) => Some(applied),
+            None => {
+                self.aspect_correction = Some(corrected);
+                None
+            }
+        }
+    }
+
+    fn render_frame(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        for attempt in 1.. {
+            let (Some(window), Some(context)) = (self.window.as_ref(), self.gfx_context.as_mut())
+            else {
+                return;
+            };
+            let Err(err) = context.render_frame(window) else {
+                return;
+            };
+            if !self.handle_error(event_loop, err.into(), attempt, true) {
+                return;
+            }
+        }
+    }
+
+    fn resume_context(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        for attempt in 1.. {
+            let (Some(window), Some(context)) = (self.window.as_ref(), self.gfx_context.as_mut())
+            else {
+                return;
+            };
+            let Err(err) = context.resume(window) else {
+                if let Some(state) = self.states.last_mut() {
+                    state.on_resume(context);
+                }
+                return;
+            };
+            if !self.handle_error(event_loop, err.into(), attempt, false) {
+                return;
+            }
+        }
+    }
+
+    // once per frame, however many resizes were reported since the last one
+    fn apply_pending_resize(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        for attempt in 1.. {
+            let Some(context) = self.gfx_context.as_mut() else {
+                return;
+            };
+            let err = match context.apply_pending_resize() {
+                Ok(Some(extent)) => {
+                    if let Some(state) = self.states.last_mut() {
+                        state.on_resize(context, (extent.width, extent.height));
+                    }
+                    return;
+                }
+                Ok(None) => return,
+                Err(err) => err,
+            };
+            if !self.handle_error(event_loop, err.into(), attempt, true) {
+                return;
+            }
+        }
+    }
+
+    /// Delivers the window events buffered while there was no context, in their arrival order.
+    fn replay_early_events(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        let Some(window_id) = self.window.as_ref().map(|window| window.id()) else {
+            return;
+        };
+        let events = self.early_events.take();
+        if !events.is_empty() {
+            log::debug!(
+                "replaying {} window events received before the context",
+                events.len()
+            );
+        }
+
+        for event in events {
+            winit::application::ApplicationHandler::window_event(
+                self, event_loop, window_id, event,
+            );
+        }
+    }
+
+    fn apply_control_flow(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        flow: ControlFlow,
+    ) {
+        let context = self.gfx_context.as_mut().unwrap();
+        let exits = match &flow {
+            ControlFlow::Exit => true,
+            ControlFlow::Pop => self.states.len() == 1,
+            _ => false,
+        };
+        if exits && self.benchmark.is_some() {
+            log::debug!("ignoring state exit until the benchmarked frames are presented");
+            return;
+        }
+
+        match flow {
+            ControlFlow::Continue => (),
+            ControlFlow::SwitchState(mut new_state) => {
+                if let Some(mut previous_state) = self.states.pop() {
+                    previous_state.on_detach(context);
+                }
+
+                new_state.on_attach(context);
+                self.states.push(new_state);
+            }
+            ControlFlow::SwitchStateWith(mut new_state, payload) => {
+                let mut previous_state = self.states.pop();
+                if let Some(previous_state) = &mut previous_state {
+                    previous_state.on_detach(context);
+                }
+
+                new_state.on_attach_with(context, payload);
+                // shared resources outlive the handoff, the new state holding them by now
+                drop(previous_state);
+                self.states.push(new_state);
+            }
+            ControlFlow::Push(mut new_state) => {
+                if let Some(covered_state) = self.states.last_mut() {
+                    covered_state.on_detach(context);
+                }
+
+                new_state.on_attach(context);
+                self.states.push(new_state);
+            }
+            ControlFlow::Pop => {
+                if let Some(mut popped_state) = self.states.pop() {
+                    popped_state.on_detach(context);
+                }
+
+                match self.states.last_mut() {
+                    Some(revealed_state) => revealed_state.on_attach(context),
+                    None => event_loop.exit(),
+                }
+            }
+            ControlFlow::Exit => event_loop.exit(),
+        }
+    }
+
+    fn apply_window_requests(&mut self) {
+        let (Some(window), Some(context)) = (self.window.as_ref(), self.gfx_context.as_mut())
+        else {
+            return;
+        };
+        context.apply_window_requests(window);
+        if let Some(mode) = context.take_cursor_mode_request() {
+            log::debug!("switching cursor to {mode:?}");
+            apply_cursor_mode(window, mode);
+            self.cursor_locked = mode == CursorMode::Locked;
+        }
+
+        let Some(mode) = context.take_fullscreen_request() else {
+            return;
+        };
+
+        log::debug!("switching window to {mode:?}");
+        match &mode {
+            FullscreenMode::Windowed => {
+                window.set_fullscreen(None);
+                if let Some((size, position)) = self.windowed_placement.take() {
+                    let _ = window.request_inner_size(size);
+                    if let Some(position) = position {
+                        window.set_outer_position(position);
+                    }
+                }
+            }
+            _ => {
+                if window.fullscreen().is_none() {
+                    self.windowed_placement =
+                        Some((window.inner_size(), window.outer_position().ok()));
+                }
+                window.set_fullscreen(mode.to_winit());
+            }
+        }
+
+        // not every platform reports a resize when only the video mode changes
+        if let FullscreenMode::Exclusive(video_mode) = &mode {
+            context.resize(vk::Extent2D {
+                width: video_mode.size.0,
+                height: video_mode.size.1,
+            });
+        }
+    }
+
+    /// Updates the top state, rendering the frame unless the window is throttled while occluded.
+    fn run_frame(&mut self, event_loop: &winit::event_loop::ActiveEventLoop, render: bool) {
+        self.apply_pending_resize(event_loop);
+        if event_loop.exiting() {
+            return;
+        }
+
+        let frame_start = Instant::now();
+        let frame = self.next_frame_input();
+        let state = self.states.last_mut().unwrap();
+
+        let Some(context) = self.gfx_context.as_mut() else {
+            log::warn!("no valid context for update state, skipping");
+            return;
+        };
+        // nothing to present to until resumed
+        if context.is_suspended() {
+            return;
+        }
+        if let Some(benchmark) = &mut self.benchmark {
+            benchmark.start(context);
+        }
+
+        for event in self.user_events.drain(..) {
+            state.on_user_event(context, event);
+        }
+        let flow = state.update(context, &frame);
+        // skipped frames acquire and submit nothing, the present fences are left signaled for the
+        // next rendered one
+        if render {
+            self.render_frame(event_loop);
+        }
+        if let (Some(benchmark), Some(context)) = (&mut self.benchmark, &self.gfx_context) {
+            benchmark.record(context, frame_start);
+            if benchmark.is_done() {
+                event_loop.exit();
+                return;
+            }
+        }
+        #[cfg(feature = "png")]
+        if let Some(capture_hotkeys) = &mut self.capture_hotkeys {
+            capture_hotkeys.poll();
+        }
+
+        if event_loop.exiting() {
+            return;
+        }
+        self.apply_window_requests();
+        self.apply_control_flow(event_loop, flow);
+    }
+
+    /// Caps the frame rate while the window is unfocused under [`ThrottlePolicy::ReduceTo`], and
+    /// restores the previous limit once it is focused again.
+    fn apply_throttle_policy(&mut self) {
+        let Some(context) = self.gfx_context.as_mut() else {
+            return;
+        };
+        let ThrottlePolicy::ReduceTo(frame_rate) = self.throttle_policy else {
+            return;
+        };
+
+        let throttled = !self.focused || self.occluded;
+        match (throttled, self.unthrottled_frame_rate_limit) {
+            (true, None) => {
+                let limit = context.frame_rate_limit();
+                self.unthrottled_frame_rate_limit = Some(limit);
+                let throttled_limit =
+                    limit.map_or(frame_rate as f32, |limit| limit.min(frame_rate as f32));
+                context.set_frame_rate_limit(Some(throttled_limit));
+            }
+            (false, Some(limit)) => {
+                self.unthrottled_frame_rate_limit = None;
+                context.set_frame_rate_limit(limit);
+            }
+            _ => (),
+        }
+    }
+
+    fn next_frame_input(&mut self) -> FrameInput {
+        let now = Instant::now();
+        let live_frame = FrameInput {
+            delta_time: self
+                .last_update
+                .map_or(Duration::ZERO, |last_update| now - last_update),
+            input: self.input.clone(),
+        };
+        self.last_update = Some(now);
+        self.input.end_frame();
+
+        match &mut self.replay {
+            ReplaySession::Off => live_frame,
+            ReplaySession::Recording(recorder) => {
+                if let Err(err) = recorder.record(&live_frame) {
+                    log::error!("replay recording failed, stopping it: {err}");
+                    self.replay = ReplaySession::Off;
+                }
+                live_frame
+            }
+            ReplaySession::Replaying(reader) => match reader.next_frame() {
+                Ok(Some(frame)) => frame,
+                Ok(None) => {
+                    log::info!("replay log exhausted, switching to live input");
+                    self.replay = ReplaySession::Off;
+                    live_frame
+                }
+                Err(err) => {
+                    log::error!("replay log reading failed, switching to live input: {err}");
+                    self.replay = ReplaySession::Off;
+                    live_frame
+                }
+            },
+        }
+    }
+}
+
+/// Fullscreen the window is created in, on the requested monitor when it is connected.
+fn initial_fullscreen(
+    info: &WindowCreationInfo,
+    event_loop: &winit::event_loop::ActiveEventLoop,
+) -> Option<winit::window::Fullscreen> {
+    let monitor = info.fullscreen_monitor.as_ref().and_then(|name| {
+        let monitor = event_loop
+            .available_monitors()
+            .find(|monitor| monitor.name().as_ref() == Some(name));
+        if monitor.is_none() {
+            log::warn!("no monitor named {name:?}, going fullscreen on the current one");
+        }
+        monitor
+    });
+
+    let Some(request) = info.exclusive_video_mode else {
+        return Some(winit::window::Fullscreen::Borderless(monitor));
+    };
+    let monitor = monitor.or_else(|| event_loop.primary_monitor())?;
+    match MonitorInfo::new(monitor.clone()).closest_video_mode(request) {
+        Some(video_mode) => Some(winit::window::Fullscreen::Exclusive(
+            video_mode.handle.clone(),
+        )),
+        None => {
+            log::warn!("no video mode close to {request:?}, going borderless fullscreen instead");
+            Some(winit::window::Fullscreen::Borderless(Some(monitor)))
+        }
+    }
+}
+
+/// Closest size to `size` with the given width to height ratio, keeping the dimension that changed
+/// the most since `previous`, i.e. the one the user is dragging.
+fn aspect_locked_size(
+    size: winit::dpi::PhysicalSize<u32>,
+    previous: winit::dpi::PhysicalSize<u32>,
+    (aspect_width, aspect_height): (u32, u32),
+) -> winit::dpi::PhysicalSize<u32> {
+    let scaled = |length: u32, numerator: u32, denominator: u32| {
+        let numerator = length as u64 * numerator as u64;
+        ((numerator + denominator as u64 / 2) / denominator as u64).max(1) as u32
+    };
+    let height_for_width = scaled(size.width, aspect_height, aspect_width);
+    let width_for_height = scaled(size.height, aspect_width, aspect_height);
+    // rounding leaves some sizes a pixel away from the exact ratio
+    if height_for_width == size.height || width_for_height == size.width {
+        return size;
+    }
+
+    let width_change = size.width.abs_diff(previous.width) as u64 * aspect_height as u64;
+    let height_change = size.height.abs_diff(previous.height) as u64 * aspect_width as u64;
+    match width_change >= height_change {
+        true => winit::dpi::PhysicalSize::new(size.width, height_for_width),
+        false => winit::dpi::PhysicalSize::new(width_for_height, size.height),
+    }
+}
+
+fn apply_cursor_mode(window: &winit::window::Window, mode: CursorMode) {
+    use winit::window::CursorGrabMode;
+
+    window.set_cursor_visible(mode == CursorMode::Normal);
+    let grab_result = match mode {
+        CursorMode::Normal | CursorMode::Hidden => window.set_cursor_grab(CursorGrabMode::None),
+        CursorMode::Locked => window
+            .set_cursor_grab(CursorGrabMode::Locked)
+            .or_else(|_| window.set_cursor_grab(CursorGrabMode::Confined)),
+    };
+    if let Err(err) = grab_result {
+        log::warn!("cursor grab failed: {err}");
+    }
+}
+
+// only does something when the event loop stopped without exiting, e.g. when it failed to run
+impl Drop for Application {
+    fn drop(&mut self) {
+        self.states.clear();
+        if let Some(context) = self.gfx_context.take() {
+            context.destroy();
+        }
+        self.window = None;
+    }
 }
 
-impl winit::application::ApplicationHandler for Application {
+impl winit::application::ApplicationHandler<UserEvent> for Application {
     fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
         let _timer = ScopeTimer::new(log::Level::Info, "application \"resumed\" step".to_owned());
 
-        match event_loop.create_window(self.window_create_info.clone().into()) {
-            Ok(window) => {
-                self.gfx_context = Some(
-                    Context::new(&window, &self.gfx_context_create_info)
-                        .expect("context should be creatable"),
-                );
-                self.window = Some(window);
+        // resumed again after a suspension, only the presentation is gone
+        if self.gfx_context.is_some() {
+            self.resume_context(event_loop);
+            return;
+        }
 
-                self.state.on_attach(self.gfx_context.as_mut().unwrap());
-            }
-            Err(e) => {
-                log::error!("failed to create window after resume event: {e}");
-                todo!()
+        for attempt in 1.. {
+            match self.create_window_and_context(event_loop) {
+                Ok(()) => break,
+                Err(err) => {
+                    if !self.handle_error(event_loop, err, attempt, false) {
+                        return;
+                    }
+                }
             }
         }
+
+        if let Some(state) = self.states.last_mut() {
+            state.on_attach(self.gfx_context.as_mut().unwrap());
+        }
+        self.replay_early_events(event_loop);
+    }
+
+    fn exiting(&mut self, _event_loop: &winit::event_loop::ActiveEventLoop) {
+        let Some(mut context) = self.gfx_context.take() else {
+            return;
+        };
+
+        context.wait_idle();
+        for state in self.states.iter_mut().rev() {
+            state.on_exit(&mut context);
+        }
+
+        // states may hold resources created from the context, which must outlive them, and the
+        // context presents to the window
+        self.states.clear();
+        context.destroy();
+        self.window = None;
+    }
+
+    fn suspended(&mut self, _event_loop: &winit::event_loop::ActiveEventLoop) {
+        let Some(context) = self.gfx_context.as_mut() else {
+            return;
+        };
+
+        if let Some(state) = self.states.last_mut() {
+            state.on_suspend(context);
+        }
+        context.suspend();
     }
 
     fn window_event(
@@ -112,43 +1240,324 @@ impl winit::application::ApplicationHandler for Application {
         _window_id: winit::window::WindowId,
         event: winit::event::WindowEvent,
     ) {
+        // some platforms deliver events before `resumed` or while the context is being created
+        let event = match self.gfx_context {
+            Some(_) => event,
+            None => match self.early_events.defer(event) {
+                Some(event) => event,
+                None => return,
+            },
+        };
+
+        self.input.handle_window_event(&event, self.cursor_locked);
+        let Some(state) = self.states.last_mut() else {
+            return;
+        };
+
         match event {
             winit::event::WindowEvent::CloseRequested => {
                 event_loop.exit();
             }
+            winit::event::WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+            }
+            winit::event::WindowEvent::Focused(focused) => {
+                self.focused = focused;
+                // platforms release the grab along with the focus
+                if focused
+                    && let (Some(window), Some(context)) = (&self.window, &self.gfx_context)
+                    && context.cursor_mode() != CursorMode::Normal
+                {
+                    apply_cursor_mode(window, context.cursor_mode());
+                }
+                if let Some(context) = self.gfx_context.as_mut() {
+                    state.on_focus_changed(context, focused);
+                }
+                self.apply_throttle_policy();
+            }
+            winit::event::WindowEvent::Occluded(occluded) => {
+                self.occluded = occluded;
+                if let Some(context) = self.gfx_context.as_mut() {
+                    state.on_occlusion_changed(context, occluded);
+                }
+                self.apply_throttle_policy();
+            }
+            winit::event::WindowEvent::MouseInput {
+                state: button_state,
+                button,
+                ..
+            } => {
+                if let Some(context) = self.gfx_context.as_mut() {
+                    state.on_mouse_button(context, button, button_state);
+                }
+            }
+            winit::event::WindowEvent::CursorMoved { position, .. } => {
+                if let Some(context) = self.gfx_context.as_mut() {
+                    state.on_mouse_move(context, [position.x as f32, position.y as f32]);
+                }
+            }
+            winit::event::WindowEvent::MouseWheel { delta, .. } => {
+                if let Some(context) = self.gfx_context.as_mut() {
+                    state.on_scroll(context, delta);
+                }
+            }
+            winit::event::WindowEvent::KeyboardInput { event, .. } => {
+                let Some(context) = self.gfx_context.as_mut() else {
+                    return;
+                };
+
+                let response = state.on_key_event(context, &event);
+                #[cfg(feature = "png")]
+                if response == InputResponse::Ignored
+                    && let Some(capture_hotkeys) = &mut self.capture_hotkeys
+                {
+                    capture_hotkeys.handle_key(context, &event, self.modifiers);
+                }
+                #[cfg(not(feature = "png"))]
+                let _ = response;
+            }
+            winit::event::WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                if let Some(context) = self.gfx_context.as_mut() {
+                    context.set_scale_factor(scale_factor);
+                    // the window likely moved to another monitor
+                    context.update_display_refresh_rate(self.window.as_ref().unwrap());
+                    context.update_monitors(self.window.as_ref().unwrap());
+                    state.on_scale_factor_changed(context, scale_factor);
+                }
+            }
+            winit::event::WindowEvent::Moved(_) => {
+                if let Some(context) = self.gfx_context.as_mut() {
+                    context.update_display_refresh_rate(self.window.as_ref().unwrap());
+                    context.update_monitors(self.window.as_ref().unwrap());
+                }
+            }
+            winit::event::WindowEvent::Resized(size) => {
+                // the swapchain is only resized once the corrected size is reported
+                let Some(size) = self.enforce_aspect_ratio(size) else {
+                    return;
+                };
+                if let Some(context) = self.gfx_context.as_mut() {
+                    context.resize(vk::Extent2D {
+                        width: size.width,
+                        height: size.height,
+                    });
+                }
+            }
+            // platforms report every file of a gesture as its own event, in a stable order
+            winit::event::WindowEvent::HoveredFile(path) => {
+                if let Some(context) = self.gfx_context.as_mut() {
+                    context.add_hovered_file(path.clone());
+                    state.on_file_hovered(context, path);
+                }
+            }
+            winit::event::WindowEvent::HoveredFileCancelled => {
+                if let Some(context) = self.gfx_context.as_mut() {
+                    context.clear_hovered_files();
+                    state.on_file_hover_cancelled(context);
+                }
+            }
+            winit::event::WindowEvent::DroppedFile(path) => {
+                if let Some(context) = self.gfx_context.as_mut() {
+                    context.remove_hovered_file(&path);
+                    state.on_file_dropped(context, path);
+                }
+            }
             winit::event::WindowEvent::RedrawRequested => {
-                let window = self.window.as_ref().unwrap();
+                self.run_frame(event_loop, true);
+            }
+
+            _ => (),
+        }
+    }
+
+    // redraws are requested once every pending event is handled, right away unless the frame rate
+    // is limited
+    fn about_to_wait(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        let (Some(window), Some(context)) = (self.window.as_ref(), self.gfx_context.as_ref())
+        else {
+            return;
+        };
+        if context.is_suspended() {
+            event_loop.set_control_flow(winit::event_loop::ControlFlow::Wait);
+            return;
+        }
+
+        // platforms may not deliver redraws to occluded windows, updates are run from here
+        if self.occluded
+            && let Some(interval) = self.throttle_policy.occluded_update_interval()
+        {
+            let next_update = |app: &Self| {
+                app.last_update
+                    .map_or_else(Instant::now, |last_update| last_update + interval)
+            };
+            if Instant::now() >= next_update(self) {
+                self.run_frame(event_loop, false);
+            }
+            event_loop
+                .set_control_flow(winit::event_loop::ControlFlow::WaitUntil(next_update(self)));
+            return;
+        }
+
+        match context.next_frame_wake_time() {
+            Some(wake_time) if Instant::now() < wake_time => {
+                event_loop.set_control_flow(winit::event_loop::ControlFlow::WaitUntil(wake_time));
+            }
+            _ => {
+                event_loop.set_control_flow(winit::event_loop::ControlFlow::Poll);
                 window.request_redraw();
+            }
+        }
+    }
 
-                let gfx_ctx = self.gfx_context.as_mut();
-                let flow = match gfx_ctx {
-                    Some(context) => {
-                        let flow = self.state.update(context);
+    fn user_event(&mut self, _event_loop: &winit::event_loop::ActiveEventLoop, event: UserEvent) {
+        self.user_events.push_back(event);
+    }
 
-                        context
-                            .render_frame(window)
-                            .expect("frame should render correctly");
+    fn device_event(
+        &mut self,
+        _event_loop: &winit::event_loop::ActiveEventLoop,
+        _device_id: winit::event::DeviceId,
+        event: winit::event::DeviceEvent,
+    ) {
+        if let winit::event::DeviceEvent::MouseMotion { delta } = event
+            && self.focused
+        {
+            self.input.handle_mouse_motion(delta, self.cursor_locked);
+        }
+    }
+}
 
-                        flow
-                    }
-                    _ => {
-                        log::warn!("no valid context for update state, skipping");
-                        ControlFlow::Continue
-                    }
-                };
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gfx::{
+        breadcrumbs::DeviceLostReport, device::DeviceCreateError, swapchain::PresentError,
+    };
 
-                match flow {
-                    ControlFlow::Continue => (),
-                    ControlFlow::SwitchState(new_state) => {
-                        self.state = new_state;
+    fn device_lost() -> ApplicationError {
+        RenderError::DeviceLost(DeviceLostReport::default()).into()
+    }
 
-                        self.state.on_attach(self.gfx_context.as_mut().unwrap());
-                    }
-                    ControlFlow::Exit => event_loop.exit(),
-                }
-            }
+    fn surface_lost() -> ApplicationError {
+        RenderError::SwapchainPresent(PresentError::Present(vk::Result::ERROR_SURFACE_LOST_KHR))
+            .into()
+    }
 
-            _ => (),
+    #[test]
+    fn device_loss_is_only_survived_by_rebuilding() {
+        let error = device_lost();
+        assert!(error.is_device_lost());
+        assert!(!error.is_fatal());
+
+        assert_eq!(
+            ErrorResponse::RebuildContext.applicable_to(&error, 1, true),
+            ErrorResponse::RebuildContext
+        );
+        for response in [
+            ErrorResponse::Retry,
+            ErrorResponse::SkipFrame,
+            ErrorResponse::Exit,
+        ] {
+            assert_eq!(response.applicable_to(&error, 1, true), ErrorResponse::Exit);
         }
     }
+
+    #[test]
+    fn rebuilding_exits_on_other_errors() {
+        let error = surface_lost();
+        assert!(!error.is_device_lost());
+
+        assert_eq!(
+            ErrorResponse::RebuildContext.applicable_to(&error, 1, true),
+            ErrorResponse::Exit
+        );
+        assert_eq!(
+            ErrorResponse::SkipFrame.applicable_to(&error, 1, true),
+            ErrorResponse::SkipFrame
+        );
+    }
+
+    #[test]
+    fn fatal_errors_always_exit() {
+        let error = ApplicationError::ContextCreation(ContextCreateError::DeviceCreation(
+            DeviceCreateError::VulkanCreation(vk::Result::ERROR_INITIALIZATION_FAILED),
+        ));
+        assert!(error.is_fatal());
+
+        for response in [
+            ErrorResponse::Retry,
+            ErrorResponse::SkipFrame,
+            ErrorResponse::RebuildContext,
+        ] {
+            assert_eq!(response.applicable_to(&error, 1, true), ErrorResponse::Exit);
+        }
+    }
+
+    #[test]
+    fn retries_and_skips_are_bounded() {
+        let error = surface_lost();
+
+        assert_eq!(
+            ErrorResponse::Retry.applicable_to(&error, MAX_ERROR_ATTEMPTS - 1, true),
+            ErrorResponse::Retry
+        );
+        assert_eq!(
+            ErrorResponse::Retry.applicable_to(&error, MAX_ERROR_ATTEMPTS, true),
+            ErrorResponse::Exit
+        );
+        assert_eq!(
+            ErrorResponse::SkipFrame.applicable_to(&error, 1, false),
+            ErrorResponse::Exit
+        );
+    }
+
+    fn moved(x: i32) -> winit::event::WindowEvent {
+        winit::event::WindowEvent::Moved(winit::dpi::PhysicalPosition::new(x, 0))
+    }
+
+    #[test]
+    fn early_events_are_replayed_in_arrival_order() {
+        let queued = [
+            winit::event::WindowEvent::Resized(winit::dpi::PhysicalSize::new(800, 600)),
+            moved(10),
+            winit::event::WindowEvent::Focused(true),
+            winit::event::WindowEvent::Resized(winit::dpi::PhysicalSize::new(1024, 768)),
+        ];
+
+        let mut early_events = EarlyEvents::default();
+        for event in queued.iter().cloned() {
+            assert_eq!(early_events.defer(event), None);
+        }
+
+        assert_eq!(Vec::from(early_events.take()), queued);
+        assert!(early_events.take().is_empty());
+    }
+
+    #[test]
+    fn early_events_let_close_and_redraw_through() {
+        let mut early_events = EarlyEvents::default();
+        assert_eq!(early_events.defer(moved(1)), None);
+        assert_eq!(
+            early_events.defer(winit::event::WindowEvent::CloseRequested),
+            Some(winit::event::WindowEvent::CloseRequested)
+        );
+        assert_eq!(
+            early_events.defer(winit::event::WindowEvent::RedrawRequested),
+            Some(winit::event::WindowEvent::RedrawRequested)
+        );
+        assert_eq!(Vec::from(early_events.take()), [moved(1)]);
+    }
+
+    #[test]
+    fn early_events_drop_the_oldest_past_the_bound() {
+        let mut early_events = EarlyEvents::default();
+        for x in 0..MAX_EARLY_EVENTS as i32 + 2 {
+            early_events.defer(moved(x));
+        }
+
+        let replayed = early_events.take();
+        assert_eq!(replayed.len(), MAX_EARLY_EVENTS);
+        assert_eq!(replayed.front(), Some(&moved(2)));
+        assert_eq!(replayed.back(), Some(&moved(MAX_EARLY_EVENTS as i32 + 1)));
+    }
 }
@@ -0,0 +1,150 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    application::{ApplicationState, ControlFlow},
+    gfx::context::{Context, RenderError},
+    input::FrameInput,
+};
+
+// older frame times are dropped past this, the mean still covers every frame
+const MAX_RECORDED_FRAMES: usize = 100_000;
+
+/// Timings of the frames presented by [`Application::run_frames`](crate::application::Application::run_frames)
+/// or [`run_headless_frames`]. Frame times are measured on the CPU, from the start of the update
+/// to the return of the present.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct FrameStats {
+    /// Fewer than requested when the window was closed before.
+    pub frames: u64,
+    pub total: Duration,
+    pub mean: Duration,
+    pub median: Duration,
+    pub p99: Duration,
+    pub swapchain_recreations: u64,
+}
+
+impl std::fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} frames in {:.2?}: mean {:.3?}, median {:.3?}, p99 {:.3?}, {} swapchain recreations",
+            self.frames, self.total, self.mean, self.median, self.p99, self.swapchain_recreations
+        )
+    }
+}
+
+/// Counts the frames presented since it was started, until the target is reached.
+#[derive(Debug)]
+pub(crate) struct FrameRecorder {
+    target_frames: u64,
+    // counters of the context before the first frame
+    start: Option<(u64, u64)>,
+    frames: u64,
+    total: Duration,
+    frame_times: VecDeque<Duration>,
+    swapchain_recreations: u64,
+}
+
+impl FrameRecorder {
+    pub fn new(target_frames: u64) -> Self {
+        Self {
+            target_frames,
+            start: None,
+            frames: 0,
+            total: Duration::ZERO,
+            frame_times: VecDeque::new(),
+            swapchain_recreations: 0,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.frames >= self.target_frames
+    }
+
+    /// Called before every frame, only the first call matters.
+    pub fn start(&mut self, ctx: &Context) {
+        self.start.get_or_insert((
+            ctx.presented_frame_count(),
+            ctx.resize_stats().swapchain_recreations,
+        ));
+    }
+
+    /// Records the frame started at `frame_start` if the context presented it.
+    pub fn record(&mut self, ctx: &Context, frame_start: Instant) {
+        let Some((start_presented, start_recreations)) = self.start else {
+            return;
+        };
+        let presented_frames = ctx.presented_frame_count();
+        self.swapchain_recreations = ctx.resize_stats().swapchain_recreations - start_recreations;
+        if presented_frames - start_presented == self.frames {
+            return;
+        }
+
+        let frame_time = frame_start.elapsed();
+        self.frames += 1;
+        self.total += frame_time;
+        if self.frame_times.len() == MAX_RECORDED_FRAMES {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(frame_time);
+    }
+
+    pub fn stats(&self) -> FrameStats {
+        let mut frame_times = self.frame_times.iter().copied().collect::<Vec<_>>();
+        frame_times.sort_unstable();
+        let percentile = |percentile: usize| {
+            let index = (frame_times.len() * percentile / 100).min(frame_times.len().max(1) - 1);
+            frame_times.get(index).copied().unwrap_or_default()
+        };
+
+        FrameStats {
+            frames: self.frames,
+            total: self.total,
+            mean: match self.frames {
+                0 => Duration::ZERO,
+                frames => self.total.div_f64(frames as f64),
+            },
+            median: percentile(50),
+            p99: percentile(99),
+            swapchain_recreations: self.swapchain_recreations,
+        }
+    }
+}
+
+/// Updates the state and renders `frames` frames of a [headless](Context::new_headless) context,
+/// e.g. to track performance in CI. States are given no input, with the real time elapsed between
+/// updates, and switching or exiting states is ignored.
+pub fn run_headless_frames(
+    ctx: &mut Context,
+    state: &mut dyn ApplicationState,
+    frames: u64,
+) -> Result<FrameStats, RenderError> {
+    let mut recorder = FrameRecorder::new(frames);
+    let mut last_update = None;
+
+    state.on_attach(ctx);
+    while !recorder.is_done() {
+        recorder.start(ctx);
+        let frame_start = Instant::now();
+        let frame = FrameInput {
+            delta_time: last_update.map_or(Duration::ZERO, |last_update| {
+                frame_start.duration_since(last_update)
+            }),
+            ..Default::default()
+        };
+        last_update = Some(frame_start);
+
+        if !matches!(state.update(ctx, &frame), ControlFlow::Continue) {
+            log::debug!("ignoring state control flow while benchmarking");
+        }
+        ctx.render_offscreen_frame()?;
+        recorder.record(ctx, frame_start);
+    }
+    ctx.wait_idle();
+    state.on_exit(ctx);
+
+    Ok(recorder.stats())
+}
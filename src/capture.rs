@@ -0,0 +1,139 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use winit::{
+    event::{ElementState, KeyEvent},
+    keyboard::{Key, ModifiersState, NamedKey},
+};
+
+use crate::gfx::{context::Context, readback::ImageReadback};
+
+struct PendingCapture {
+    // stable across presses, unlike the timestamped path
+    label: String,
+    path: PathBuf,
+    readback: ImageReadback,
+}
+
+/// F12 saves a screenshot and Shift+F12 dumps every attachment, as PNGs next to the executable.
+#[derive(Default)]
+pub(crate) struct CaptureHotkeys {
+    pending: Vec<PendingCapture>,
+    // failures tend to repeat on every press, each is only worth reporting once
+    reported_failures: HashSet<String>,
+}
+
+impl CaptureHotkeys {
+    /// Returns whether the key was one of the capture hotkeys.
+    pub fn handle_key(
+        &mut self,
+        ctx: &mut Context,
+        event: &KeyEvent,
+        modifiers: ModifiersState,
+    ) -> bool {
+        if event.logical_key != Key::Named(NamedKey::F12) {
+            return false;
+        }
+        if event.state == ElementState::Pressed && !event.repeat {
+            match modifiers.shift_key() {
+                true => self.dump_attachments(ctx),
+                false => self.screenshot(ctx),
+            }
+        }
+
+        true
+    }
+
+    fn screenshot(&mut self, ctx: &mut Context) {
+        let path = capture_directory().join(format!("screenshot-{}.png", timestamp()));
+        match ctx.capture_next_frame() {
+            Ok(readback) => self.pending.push(PendingCapture {
+                label: "screenshot".to_owned(),
+                path,
+                readback,
+            }),
+            Err(err) => self.report_failure(format!("screenshot capture failed: {err}")),
+        }
+    }
+
+    fn dump_attachments(&mut self, ctx: &mut Context) {
+        let directory = capture_directory().join(format!("attachments-{}", timestamp()));
+        if let Err(err) = std::fs::create_dir_all(&directory) {
+            self.report_failure(format!("attachment dump directory creation failed: {err}"));
+            return;
+        }
+
+        for (name, readback) in ctx.read_all_attachments() {
+            match readback {
+                Ok(readback) => self.pending.push(PendingCapture {
+                    path: directory.join(format!("{}.png", file_name(&name))),
+                    label: format!("\"{name}\" attachment"),
+                    readback,
+                }),
+                Err(err) => {
+                    self.report_failure(format!("\"{name}\" attachment capture failed: {err}"))
+                }
+            }
+        }
+    }
+
+    /// Writes the captures resolved since the last call.
+    pub fn poll(&mut self) {
+        let (resolved, pending) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition::<Vec<_>, _>(|capture| capture.readback.is_ready());
+        self.pending = pending;
+
+        for capture in resolved {
+            let result = match capture.readback.poll() {
+                Some(Ok(image)) => image.save_png(&capture.path).map_err(|err| err.to_string()),
+                Some(Err(err)) => Err(err.to_string()),
+                None => unreachable!("only resolved captures are polled"),
+            };
+
+            match result {
+                Ok(()) => log::info!("capture written to {}", capture.path.display()),
+                Err(err) => self.report_failure(format!("{} capture failed: {err}", capture.label)),
+            }
+        }
+    }
+
+    fn report_failure(&mut self, message: String) {
+        if self.reported_failures.insert(message.clone()) {
+            log::warn!("{message}");
+        }
+    }
+}
+
+fn capture_directory() -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+        .unwrap_or_default()
+}
+
+fn timestamp() -> String {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    format!(
+        "{}-{:03}",
+        since_epoch.as_secs(),
+        since_epoch.subsec_millis()
+    )
+}
+
+fn file_name(attachment_name: &str) -> String {
+    attachment_name
+        .chars()
+        .map(
+            |c| match c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                true => c,
+                false => '_',
+            },
+        )
+        .collect()
+}
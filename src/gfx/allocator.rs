@@ -1,5 +1,6 @@
 use std::{
-    fmt::Debug,
+    collections::HashMap,
+    fmt::{Debug, Display},
     ops::{Deref, DerefMut},
 };
 
@@ -8,12 +9,114 @@ use thiserror::Error;
 use crate::utils::ThreadSafeRef;
 
 use super::{
+    defrag::BlockOccupancy,
     device::{Device, PhysicalDevice},
     instance::Instance,
 };
 
+/// Subsystem an allocation is attributed to in [`AllocationReport`]s.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum AllocTag {
+    Mesh,
+    #[default]
+    Texture,
+    Uniform,
+    RenderTarget,
+    Staging,
+    User(&'static str),
+}
+
+impl Display for AllocTag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AllocTag::Mesh => write!(f, "mesh"),
+            AllocTag::Texture => write!(f, "texture"),
+            AllocTag::Uniform => write!(f, "uniform"),
+            AllocTag::RenderTarget => write!(f, "render target"),
+            AllocTag::Staging => write!(f, "staging"),
+            AllocTag::User(name) => write!(f, "user ({name})"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct TagUsage {
+    pub count: usize,
+    pub bytes: u64,
+}
+
+/// Snapshot of the live allocations, see [`crate::gfx::context::Context::allocation_report`].
+#[derive(Debug, Clone)]
+pub struct AllocationReport {
+    pub allocation_count: usize,
+    pub total_allocated_bytes: u64,
+    pub total_reserved_bytes: u64,
+
+    by_tag: HashMap<AllocTag, TagUsage>,
+}
+
+impl AllocationReport {
+    pub fn by_tag(&self) -> &HashMap<AllocTag, TagUsage> {
+        &self.by_tag
+    }
+
+    /// Tags sorted by decreasing byte usage.
+    pub fn sorted_by_tag(&self) -> Vec<(AllocTag, TagUsage)> {
+        let mut usages = self
+            .by_tag
+            .iter()
+            .map(|(&tag, &usage)| (tag, usage))
+            .collect::<Vec<_>>();
+        usages.sort_by(|(tag_a, a), (tag_b, b)| b.bytes.cmp(&a.bytes).then(tag_a.cmp(tag_b)));
+
+        usages
+    }
+}
+
+impl Display for AllocationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} allocations, {} bytes allocated ({} bytes reserved)",
+            self.allocation_count, self.total_allocated_bytes, self.total_reserved_bytes
+        )?;
+        for (tag, usage) in self.sorted_by_tag() {
+            writeln!(
+                f,
+                "  {tag}: {} allocations, {} bytes",
+                usage.count, usage.bytes
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+// live allocations summed per tag, tags without any being removed
+#[derive(Debug, Default)]
+struct TagUsages(HashMap<AllocTag, TagUsage>);
+
+impl TagUsages {
+    fn add(&mut self, tag: AllocTag, bytes: u64) {
+        let usage = self.0.entry(tag).or_default();
+        usage.count += 1;
+        usage.bytes += bytes;
+    }
+
+    fn remove(&mut self, tag: AllocTag, bytes: u64) {
+        if let Some(usage) = self.0.get_mut(&tag) {
+            usage.count -= 1;
+            usage.bytes -= bytes;
+            if usage.count == 0 {
+                self.0.remove(&tag);
+            }
+        }
+    }
+}
+
 pub(crate) struct Allocator {
     inner: gpu_allocator::vulkan::Allocator,
+    tag_usages: TagUsages,
 }
 
 #[derive(Debug, Error)]
@@ -47,24 +150,88 @@ impl Allocator {
         };
         let inner = gpu_allocator::vulkan::Allocator::new(&create_info)?;
 
-        Ok(Self { inner })
+        Ok(Self {
+            inner,
+            tag_usages: TagUsages::default(),
+        })
     }
 
     pub fn allocate(
         &mut self,
         desc: &gpu_allocator::vulkan::AllocationCreateDesc<'_>,
+        tag: AllocTag,
         allocator_ref: ThreadSafeRef<Self>,
     ) -> Result<Allocation, gpu_allocator::AllocationError> {
-        self.inner.allocate(desc).map(|handle| Allocation {
+        let handle = self.inner.allocate(desc)?;
+
+        self.tag_usages.add(tag, handle.size());
+
+        Ok(Allocation {
             handle: Some(handle),
+            tag,
             allocator_ref,
         })
     }
+
+    fn free(&mut self, allocation: gpu_allocator::vulkan::Allocation, tag: AllocTag) {
+        self.tag_usages.remove(tag, allocation.size());
+        let _ = self.inner.free(allocation);
+    }
+
+    pub fn report(&self) -> AllocationReport {
+        let inner_report = self.inner.generate_report();
+
+        AllocationReport {
+            allocation_count: inner_report.allocations.len(),
+            total_allocated_bytes: inner_report.total_allocated_bytes,
+            total_reserved_bytes: inner_report.total_reserved_bytes,
+            by_tag: self.tag_usages.0.clone(),
+        }
+    }
+
+    pub fn block_occupancy(&self) -> Vec<BlockOccupancy> {
+        let inner_report = self.inner.generate_report();
+
+        inner_report
+            .blocks
+            .iter()
+            .map(|block| BlockOccupancy {
+                size: block.size,
+                allocations: inner_report.allocations[block.allocations.clone()]
+                    .iter()
+                    .map(|allocation| (allocation.name.clone(), allocation.size))
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+/// Logs the vulkan objects and allocations still alive, meant to be called right after every
+/// resource owned by the context was destroyed. Returns how many there were.
+pub(crate) fn report_leaks(device: &Device, allocator: &Allocator) -> usize {
+    let survivor_count = device.handle_registry.report_survivors();
+
+    let report = allocator.report();
+    if report.by_tag().is_empty() {
+        return survivor_count;
+    }
+
+    log::warn!("allocations outliving the context:");
+    for (tag, usage) in report.sorted_by_tag() {
+        log::warn!(
+            "  {} {tag} allocations ({} bytes)",
+            usage.count,
+            usage.bytes
+        );
+    }
+
+    survivor_count + report.allocation_count
 }
 
 // A useful wrapper type to hold an allocation and destroy it on drop
 pub(crate) struct Allocation {
     handle: Option<gpu_allocator::vulkan::Allocation>,
+    tag: AllocTag,
     allocator_ref: ThreadSafeRef<Allocator>,
 }
 
@@ -72,6 +239,7 @@ impl Debug for Allocation {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Allocation")
             .field("handle", &self.handle)
+            .field("tag", &self.tag)
             .finish()
     }
 }
@@ -97,7 +265,131 @@ impl DerefMut for Allocation {
 impl Drop for Allocation {
     fn drop(&mut self) {
         if let Some(allocation) = self.handle.take() {
-            let _ = self.allocator_ref.lock().inner.free(allocation);
+            self.allocator_ref.lock().free(allocation, self.tag);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // allocations made then freed, by tag and size
+    fn tag_usages(allocations: &[(AllocTag, u64)], frees: &[(AllocTag, u64)]) -> TagUsages {
+        let mut usages = TagUsages::default();
+        for &(tag, bytes) in allocations {
+            usages.add(tag, bytes);
+        }
+        for &(tag, bytes) in frees {
+            usages.remove(tag, bytes);
         }
+
+        usages
+    }
+
+    fn report(usages: TagUsages) -> AllocationReport {
+        let by_tag = usages.0;
+        AllocationReport {
+            allocation_count: by_tag.values().map(|usage| usage.count).sum(),
+            total_allocated_bytes: by_tag.values().map(|usage| usage.bytes).sum(),
+            total_reserved_bytes: 1 << 20,
+            by_tag,
+        }
+    }
+
+    #[test]
+    fn allocations_are_summed_per_tag() {
+        let usages = tag_usages(
+            &[
+                (AllocTag::Mesh, 1024),
+                (AllocTag::Texture, 4096),
+                (AllocTag::Mesh, 512),
+                (AllocTag::User("particles"), 64),
+                (AllocTag::User("decals"), 32),
+            ],
+            &[],
+        );
+
+        let report = report(usages);
+        assert_eq!(
+            report.by_tag()[&AllocTag::Mesh],
+            TagUsage {
+                count: 2,
+                bytes: 1536
+            }
+        );
+        assert_eq!(report.by_tag()[&AllocTag::Texture].count, 1);
+        // user tags are told apart by their name
+        assert_eq!(report.by_tag()[&AllocTag::User("particles")].bytes, 64);
+        assert_eq!(report.by_tag()[&AllocTag::User("decals")].bytes, 32);
+        assert_eq!(report.allocation_count, 5);
+        assert_eq!(report.total_allocated_bytes, 5728);
+    }
+
+    #[test]
+    fn freed_allocations_are_subtracted_and_empty_tags_dropped() {
+        let usages = tag_usages(
+            &[
+                (AllocTag::Staging, 256),
+                (AllocTag::Staging, 128),
+                (AllocTag::Uniform, 64),
+            ],
+            &[(AllocTag::Staging, 256), (AllocTag::Uniform, 64)],
+        );
+
+        let report = report(usages);
+        assert_eq!(
+            report.by_tag()[&AllocTag::Staging],
+            TagUsage {
+                count: 1,
+                bytes: 128
+            }
+        );
+        assert!(!report.by_tag().contains_key(&AllocTag::Uniform));
+    }
+
+    #[test]
+    fn freeing_an_untracked_tag_is_ignored() {
+        let usages = tag_usages(&[(AllocTag::Mesh, 16)], &[(AllocTag::Texture, 16)]);
+
+        assert_eq!(usages.0.len(), 1);
+        assert_eq!(usages.0[&AllocTag::Mesh].bytes, 16);
+    }
+
+    #[test]
+    fn report_lists_tags_by_decreasing_usage() {
+        let report = report(tag_usages(
+            &[
+                (AllocTag::RenderTarget, 8192),
+                (AllocTag::Mesh, 100),
+                (AllocTag::Texture, 100),
+                (AllocTag::Staging, 2048),
+            ],
+            &[],
+        ));
+
+        let tags = report
+            .sorted_by_tag()
+            .into_iter()
+            .map(|(tag, _)| tag)
+            .collect::<Vec<_>>();
+        // equal usages keep the declaration order of the tags
+        assert_eq!(
+            tags,
+            [
+                AllocTag::RenderTarget,
+                AllocTag::Staging,
+                AllocTag::Mesh,
+                AllocTag::Texture
+            ]
+        );
+        assert_eq!(
+            report.to_string(),
+            "4 allocations, 10440 bytes allocated (1048576 bytes reserved)\n  \
+             render target: 1 allocations, 8192 bytes\n  \
+             staging: 1 allocations, 2048 bytes\n  \
+             mesh: 1 allocations, 100 bytes\n  \
+             texture: 1 allocations, 100 bytes\n"
+        );
     }
 }
@@ -0,0 +1,323 @@
+use ash::vk;
+
+use super::device::Device;
+
+/// Collects the barriers needed before a pass so they are recorded with a single
+/// `cmd_pipeline_barrier2` call, each barrier keeping its own stage masks.
+#[derive(Debug, Default)]
+pub struct BarrierBatch {
+    memory_barriers: Vec<vk::MemoryBarrier2<'static>>,
+    image_barriers: Vec<vk::ImageMemoryBarrier2<'static>>,
+    buffer_barriers: Vec<vk::BufferMemoryBarrier2<'static>>,
+}
+
+impl BarrierBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push_memory_barrier(&mut self, barrier: vk::MemoryBarrier2<'static>) {
+        self.memory_barriers.push(barrier);
+    }
+
+    pub fn push_image_barrier(&mut self, barrier: vk::ImageMemoryBarrier2<'static>) {
+        self.image_barriers.push(barrier);
+    }
+
+    pub fn push_buffer_barrier(&mut self, barrier: vk::BufferMemoryBarrier2<'static>) {
+        self.buffer_barriers.push(barrier);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.memory_barriers.is_empty()
+            && self.image_barriers.is_empty()
+            && self.buffer_barriers.is_empty()
+    }
+
+    pub fn memory_barriers(&self) -> &[vk::MemoryBarrier2<'static>] {
+        &self.memory_barriers
+    }
+
+    pub fn image_barriers(&self) -> &[vk::ImageMemoryBarrier2<'static>] {
+        &self.image_barriers
+    }
+
+    pub fn buffer_barriers(&self) -> &[vk::BufferMemoryBarrier2<'static>] {
+        &self.buffer_barriers
+    }
+
+    /// Records every queued barrier at once and empties the batch. Does nothing if it is empty.
+    pub fn cmd_flush(&mut self, device: &Device, cmd_buffer: vk::CommandBuffer) {
+        if self.is_empty() {
+            return;
+        }
+
+        let dependency_info = vk::DependencyInfo::default()
+            .memory_barriers(&self.memory_barriers)
+            .buffer_memory_barriers(&self.buffer_barriers)
+            .image_memory_barriers(&self.image_barriers);
+        unsafe { device.cmd_pipeline_barrier2(cmd_buffer, &dependency_info) };
+        self.clear();
+    }
+
+    pub fn clear(&mut self) {
+        self.memory_barriers.clear();
+        self.image_barriers.clear();
+        self.buffer_barriers.clear();
+    }
+}
+
+/// Stages and accesses an image in `layout` was last used with, to be waited on before its next
+/// transition. Guessed from the layout alone, e.g. sampled images are assumed to have been read by
+/// vertex or fragment shaders.
+pub(crate) fn layout_src_scope(
+    layout: vk::ImageLayout,
+) -> (vk::PipelineStageFlags2, vk::AccessFlags2) {
+    match layout {
+        // swapchain images are acquired before color output, their transition must come after
+        vk::ImageLayout::UNDEFINED | vk::ImageLayout::PRESENT_SRC_KHR => (
+            vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+            vk::AccessFlags2::NONE,
+        ),
+        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL => (
+            vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+            vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+        ),
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL => (
+            vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+            vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        ),
+        // reads only need their execution to be done before the image is written again
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL => (
+            vk::PipelineStageFlags2::VERTEX_SHADER | vk::PipelineStageFlags2::FRAGMENT_SHADER,
+            vk::AccessFlags2::NONE,
+        ),
+        vk::ImageLayout::TRANSFER_SRC_OPTIMAL => (
+            vk::PipelineStageFlags2::ALL_TRANSFER,
+            vk::AccessFlags2::NONE,
+        ),
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL => (
+            vk::PipelineStageFlags2::ALL_TRANSFER,
+            vk::AccessFlags2::TRANSFER_WRITE,
+        ),
+        _ => (
+            vk::PipelineStageFlags2::ALL_COMMANDS,
+            vk::AccessFlags2::MEMORY_WRITE,
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk::Handle;
+
+    use super::*;
+    use crate::gfx::image::ImageState;
+
+    fn image(raw_handle: u64, layout: vk::ImageLayout) -> ImageState {
+        ImageState {
+            handle: vk::Image::from_raw(raw_handle),
+            view: vk::ImageView::null(),
+            layout,
+            format: vk::Format::R8G8B8A8_UNORM,
+            usage: vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            extent: vk::Extent3D::default(),
+            extent_2d: vk::Extent2D::default(),
+            view_subresource_range: vk::ImageSubresourceRange::default(),
+        }
+    }
+
+    fn transition_to(layout: vk::ImageLayout) -> vk::ImageMemoryBarrier2<'static> {
+        let (src_stage_mask, src_access_mask) = layout_src_scope(vk::ImageLayout::UNDEFINED);
+        vk::ImageMemoryBarrier2::default()
+            .src_stage_mask(src_stage_mask)
+            .src_access_mask(src_access_mask)
+            .dst_stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)
+            .new_layout(layout)
+    }
+
+    #[test]
+    fn batch_is_empty_until_any_barrier_is_pushed() {
+        let mut batch = BarrierBatch::new();
+        assert!(batch.is_empty());
+
+        batch.push_memory_barrier(vk::MemoryBarrier2::default());
+        assert!(!batch.is_empty());
+        batch.clear();
+        assert!(batch.is_empty());
+
+        batch.push_buffer_barrier(vk::BufferMemoryBarrier2::default());
+        assert!(!batch.is_empty());
+        batch.clear();
+
+        batch.push_image_barrier(vk::ImageMemoryBarrier2::default());
+        assert!(!batch.is_empty());
+        batch.clear();
+        assert!(batch.is_empty());
+    }
+
+    #[test]
+    fn batched_barriers_keep_their_own_stage_masks_in_order() {
+        let mut batch = BarrierBatch::new();
+        batch.push_image_barrier(
+            vk::ImageMemoryBarrier2::default()
+                .src_stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)
+                .dst_stage_mask(vk::PipelineStageFlags2::FRAGMENT_SHADER),
+        );
+        batch.push_image_barrier(
+            vk::ImageMemoryBarrier2::default()
+                .src_stage_mask(vk::PipelineStageFlags2::ALL_TRANSFER)
+                .dst_stage_mask(vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS),
+        );
+        batch.push_buffer_barrier(
+            vk::BufferMemoryBarrier2::default()
+                .src_stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
+                .dst_stage_mask(vk::PipelineStageFlags2::VERTEX_INPUT),
+        );
+
+        let stages = batch
+            .image_barriers()
+            .iter()
+            .map(|barrier| (barrier.src_stage_mask, barrier.dst_stage_mask))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            stages,
+            [
+                (
+                    vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                    vk::PipelineStageFlags2::FRAGMENT_SHADER
+                ),
+                (
+                    vk::PipelineStageFlags2::ALL_TRANSFER,
+                    vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                ),
+            ]
+        );
+        assert_eq!(batch.buffer_barriers().len(), 1);
+        assert_eq!(
+            batch.buffer_barriers()[0].src_stage_mask,
+            vk::PipelineStageFlags2::COMPUTE_SHADER
+        );
+        assert!(batch.memory_barriers().is_empty());
+    }
+
+    #[test]
+    fn queued_transitions_fill_in_the_image_and_tracked_layout() {
+        let mut batch = BarrierBatch::new();
+        let mut color = image(1, vk::ImageLayout::UNDEFINED);
+        let mut depth = image(2, vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+
+        color.queue_into(
+            &mut batch,
+            transition_to(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL),
+        );
+        depth.queue_into(
+            &mut batch,
+            transition_to(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+        );
+        color.queue_into(
+            &mut batch,
+            transition_to(vk::ImageLayout::TRANSFER_SRC_OPTIMAL),
+        );
+
+        // the tracked layout moves right away, so a second transition starts from the first's
+        assert_eq!(color.layout, vk::ImageLayout::TRANSFER_SRC_OPTIMAL);
+        assert_eq!(depth.layout, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        let transitions = batch
+            .image_barriers()
+            .iter()
+            .map(|barrier| {
+                (
+                    barrier.image.as_raw(),
+                    barrier.old_layout,
+                    barrier.new_layout,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            transitions,
+            [
+                (
+                    1,
+                    vk::ImageLayout::UNDEFINED,
+                    vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+                ),
+                (
+                    2,
+                    vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+                ),
+                (
+                    1,
+                    vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn layout_src_scope_waits_on_the_last_usage_of_each_layout() {
+        let cases = [
+            (
+                vk::ImageLayout::UNDEFINED,
+                vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                vk::AccessFlags2::NONE,
+            ),
+            (
+                vk::ImageLayout::PRESENT_SRC_KHR,
+                vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                vk::AccessFlags2::NONE,
+            ),
+            (
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+            ),
+            (
+                vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+                vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            ),
+            (
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::PipelineStageFlags2::VERTEX_SHADER | vk::PipelineStageFlags2::FRAGMENT_SHADER,
+                vk::AccessFlags2::NONE,
+            ),
+            (
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::PipelineStageFlags2::ALL_TRANSFER,
+                vk::AccessFlags2::NONE,
+            ),
+            (
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::PipelineStageFlags2::ALL_TRANSFER,
+                vk::AccessFlags2::TRANSFER_WRITE,
+            ),
+        ];
+
+        for (layout, stage, access) in cases {
+            assert_eq!(layout_src_scope(layout), (stage, access), "{layout:?}");
+        }
+    }
+
+    #[test]
+    fn unknown_layouts_fall_back_to_a_full_barrier() {
+        for layout in [
+            vk::ImageLayout::GENERAL,
+            vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            vk::ImageLayout::PREINITIALIZED,
+        ] {
+            assert_eq!(
+                layout_src_scope(layout),
+                (
+                    vk::PipelineStageFlags2::ALL_COMMANDS,
+                    vk::AccessFlags2::MEMORY_WRITE
+                ),
+                "{layout:?}"
+            );
+        }
+    }
+}
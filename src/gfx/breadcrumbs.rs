@@ -0,0 +1,353 @@
+use std::{ffi::CStr, fmt::Display};
+
+use ash::vk;
+
+use crate::utils::{ThreadSafeRef, ThreadSafeRwRef};
+
+use super::{
+    allocator::{AllocTag, Allocator},
+    barrier::BarrierBatch,
+    buffer::{Buffer, BufferBuilder},
+    device::Device,
+};
+
+/// How pass completion markers are written, selected at device creation.
+#[derive(Clone)]
+pub(crate) enum BreadcrumbBackend {
+    Disabled,
+    NvCheckpoints(ash::nv::device_diagnostic_checkpoints::Device),
+    AmdBufferMarker(ash::amd::buffer_marker::Device),
+    // portable fallback, serializes passes with the marker write
+    FillBuffer,
+}
+
+impl std::fmt::Debug for BreadcrumbBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BreadcrumbBackend::Disabled => write!(f, "Disabled"),
+            BreadcrumbBackend::NvCheckpoints(_) => write!(f, "NvCheckpoints"),
+            BreadcrumbBackend::AmdBufferMarker(_) => write!(f, "AmdBufferMarker"),
+            BreadcrumbBackend::FillBuffer => write!(f, "FillBuffer"),
+        }
+    }
+}
+
+impl BreadcrumbBackend {
+    /// `extension` is the breadcrumb extension enabled on `device`, if any.
+    pub(crate) fn select(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        extension: Option<&CStr>,
+    ) -> Self {
+        if !cfg!(debug_assertions) {
+            return Self::Disabled;
+        }
+
+        match extension {
+            Some(name) if name == ash::nv::device_diagnostic_checkpoints::NAME => {
+                Self::NvCheckpoints(ash::nv::device_diagnostic_checkpoints::Device::new(
+                    instance, device,
+                ))
+            }
+            Some(name) if name == ash::amd::buffer_marker::NAME => {
+                Self::AmdBufferMarker(ash::amd::buffer_marker::Device::new(instance, device))
+            }
+            _ => Self::FillBuffer,
+        }
+    }
+}
+
+/// Where the GPU stopped making progress, as far as the breadcrumbs can tell.
+#[derive(Debug, Clone)]
+pub struct GpuHangReport {
+    /// Index of the last pass known to have completed, `None` if no pass completed.
+    pub last_completed_pass: Option<usize>,
+    pub pass_names: Vec<String>,
+}
+
+impl Display for GpuHangReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.last_completed_pass {
+            Some(index) if index + 1 >= self.pass_names.len() => {
+                write!(f, "GPU hung after the last pass")?
+            }
+            Some(index) => write!(
+                f,
+                "GPU hung after pass '{}' ({}/{})",
+                self.pass_names[index],
+                index + 1,
+                self.pass_names.len()
+            )?,
+            None => write!(f, "GPU hung before any pass completed")?,
+        }
+
+        write!(f, ", passes: [{}]", self.pass_names.join(", "))
+    }
+}
+
+/// Address reported by `VK_EXT_device_fault`, the faulting access was within `precision` bytes
+/// of it.
+#[derive(Debug, Copy, Clone)]
+pub struct FaultAddress {
+    pub kind: vk::DeviceFaultAddressTypeEXT,
+    pub address: vk::DeviceAddress,
+    pub precision: vk::DeviceSize,
+}
+
+/// Vendor specific fault reported by `VK_EXT_device_fault`.
+#[derive(Debug, Clone)]
+pub struct VendorFault {
+    pub description: String,
+    pub code: u64,
+    pub data: u64,
+}
+
+/// What the driver reports about a device loss through `VK_EXT_device_fault`, when the device
+/// supports it.
+#[derive(Debug, Clone)]
+pub struct DeviceFaultReport {
+    pub description: String,
+    pub addresses: Vec<FaultAddress>,
+    pub vendor_faults: Vec<VendorFault>,
+}
+
+impl DeviceFaultReport {
+    /// Only meaningful once the device has been lost. The vendor binary dump is not collected.
+    pub(crate) fn query(loader: &ash::ext::device_fault::Device) -> Option<Self> {
+        let get_fault_info = loader.fp().get_device_fault_info_ext;
+
+        let mut counts = vk::DeviceFaultCountsEXT::default();
+        // SAFETY: a null info only queries the counts
+        let result = unsafe { get_fault_info(loader.device(), &mut counts, std::ptr::null_mut()) };
+        if result != vk::Result::SUCCESS {
+            log::warn!("device fault counts query failed: {result}");
+            return None;
+        }
+
+        let mut addresses =
+            vec![vk::DeviceFaultAddressInfoEXT::default(); counts.address_info_count as usize];
+        let mut vendor_infos =
+            vec![vk::DeviceFaultVendorInfoEXT::default(); counts.vendor_info_count as usize];
+        counts.vendor_binary_size = 0;
+        let mut info = vk::DeviceFaultInfoEXT {
+            p_address_infos: addresses.as_mut_ptr(),
+            p_vendor_infos: vendor_infos.as_mut_ptr(),
+            ..Default::default()
+        };
+        // SAFETY: the arrays are as long as the counts given along them
+        let result = unsafe { get_fault_info(loader.device(), &mut counts, &mut info) };
+        if result != vk::Result::SUCCESS && result != vk::Result::INCOMPLETE {
+            log::warn!("device fault info query failed: {result}");
+            return None;
+        }
+        addresses.truncate(counts.address_info_count as usize);
+        vendor_infos.truncate(counts.vendor_info_count as usize);
+
+        let description = |description: Result<&CStr, _>| {
+            description.map_or_else(
+                |_| String::new(),
+                |s: &CStr| s.to_string_lossy().into_owned(),
+            )
+        };
+        Some(Self {
+            description: description(info.description_as_c_str()),
+            addresses: addresses
+                .iter()
+                .map(|address| FaultAddress {
+                    kind: address.address_type,
+                    address: address.reported_address,
+                    precision: address.address_precision,
+                })
+                .collect(),
+            vendor_faults: vendor_infos
+                .iter()
+                .map(|vendor_info| VendorFault {
+                    description: description(vendor_info.description_as_c_str()),
+                    code: vendor_info.vendor_fault_code,
+                    data: vendor_info.vendor_fault_data,
+                })
+                .collect(),
+        })
+    }
+}
+
+impl Display for DeviceFaultReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "device fault: {}", self.description)?;
+        for address in &self.addresses {
+            write!(
+                f,
+                ", {:?} at {:#x} (±{:#x})",
+                address.kind, address.address, address.precision
+            )?;
+        }
+        for vendor_fault in &self.vendor_faults {
+            write!(
+                f,
+                ", {} (code {:#x}, data {:#x})",
+                vendor_fault.description, vendor_fault.code, vendor_fault.data
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Everything gathered after a device loss, carried by
+/// [`RenderError::DeviceLost`](super::context::RenderError::DeviceLost).
+#[derive(Debug, Clone, Default)]
+pub struct DeviceLostReport {
+    /// Read back from the breadcrumbs, see [`GpuHangReport`].
+    pub hang: Option<GpuHangReport>,
+    /// Only available on devices supporting `VK_EXT_device_fault`.
+    pub fault: Option<DeviceFaultReport>,
+}
+
+impl Display for DeviceLostReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.hang, &self.fault) {
+            (None, None) => write!(f, "no diagnostics available"),
+            (Some(hang), None) => write!(f, "{hang}"),
+            (None, Some(fault)) => write!(f, "{fault}"),
+            (Some(hang), Some(fault)) => write!(f, "{hang}; {fault}"),
+        }
+    }
+}
+
+/// Records a marker after every render pass, read back after a device loss to find out which pass
+/// hung the GPU.
+pub(crate) struct Breadcrumbs {
+    backend: BreadcrumbBackend,
+    // only used by the buffer-based backends
+    marker_buffer: Option<Buffer>,
+    pass_names: Vec<String>,
+}
+
+impl Breadcrumbs {
+    pub fn new(
+        device_ref: &ThreadSafeRwRef<Device>,
+        allocator_ref: &ThreadSafeRef<Allocator>,
+    ) -> Self {
+        let backend = device_ref.read().breadcrumb_backend.clone();
+
+        let marker_buffer = match backend {
+            BreadcrumbBackend::AmdBufferMarker(_) | BreadcrumbBackend::FillBuffer => {
+                BufferBuilder::default(std::mem::size_of::<u32>() as u64)
+                    .with_name("gpu breadcrumbs")
+                    .with_tag(AllocTag::User("breadcrumbs"))
+                    .with_usage(vk::BufferUsageFlags::TRANSFER_DST)
+                    .with_memory_location(gpu_allocator::MemoryLocation::GpuToCpu)
+                    .build_internal(device_ref.clone(), allocator_ref.clone())
+                    .inspect_err(|err| {
+                        log::warn!("breadcrumb buffer creation failed, disabling them: {err}")
+                    })
+                    .ok()
+            }
+            _ => None,
+        };
+        let backend = match (&backend, &marker_buffer) {
+            (BreadcrumbBackend::AmdBufferMarker(_) | BreadcrumbBackend::FillBuffer, None) => {
+                BreadcrumbBackend::Disabled
+            }
+            _ => backend,
+        };
+
+        Self {
+            backend,
+            marker_buffer,
+            pass_names: vec![],
+        }
+    }
+
+    pub fn cmd_begin_frame<'a>(
+        &mut self,
+        pass_names: impl IntoIterator<Item = &'a str>,
+        cmd_buffer: vk::CommandBuffer,
+        device: &Device,
+    ) {
+        if matches!(self.backend, BreadcrumbBackend::Disabled) {
+            return;
+        }
+
+        self.pass_names.clear();
+        self.pass_names
+            .extend(pass_names.into_iter().map(str::to_owned));
+        self.cmd_write_marker(0, cmd_buffer, device);
+    }
+
+    pub fn cmd_pass_completed(
+        &self,
+        pass_index: usize,
+        cmd_buffer: vk::CommandBuffer,
+        device: &Device,
+    ) {
+        self.cmd_write_marker(pass_index as u32 + 1, cmd_buffer, device);
+    }
+
+    fn cmd_write_marker(&self, marker: u32, cmd_buffer: vk::CommandBuffer, device: &Device) {
+        match (&self.backend, &self.marker_buffer) {
+            (BreadcrumbBackend::NvCheckpoints(loader), _) => unsafe {
+                // the marker is an opaque pointer-sized value handed back as-is
+                loader.cmd_set_checkpoint(cmd_buffer, marker as usize as *const std::ffi::c_void)
+            },
+            // ash only wraps the legacy entry point, which still takes `PipelineStageFlags`
+            (BreadcrumbBackend::AmdBufferMarker(loader), Some(buffer)) => unsafe {
+                loader.cmd_write_buffer_marker(
+                    cmd_buffer,
+                    vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                    buffer.handle,
+                    0,
+                    marker,
+                )
+            },
+            (BreadcrumbBackend::FillBuffer, Some(buffer)) => {
+                let mut barriers = BarrierBatch::new();
+                barriers.push_memory_barrier(
+                    vk::MemoryBarrier2::default()
+                        .src_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                        .src_access_mask(vk::AccessFlags2::MEMORY_WRITE)
+                        .dst_stage_mask(vk::PipelineStageFlags2::CLEAR)
+                        .dst_access_mask(vk::AccessFlags2::TRANSFER_WRITE),
+                );
+                barriers.cmd_flush(device, cmd_buffer);
+                unsafe {
+                    device.cmd_fill_buffer(cmd_buffer, buffer.handle, 0, vk::WHOLE_SIZE, marker)
+                };
+            }
+            _ => (),
+        }
+    }
+
+    /// Only meaningful once the device has been lost, the markers are otherwise still being written.
+    pub fn hang_report(&self, device: &Device) -> Option<GpuHangReport> {
+        let last_marker = match (&self.backend, &self.marker_buffer) {
+            (BreadcrumbBackend::NvCheckpoints(loader), _) => {
+                let queue = device.graphics_queue.handle;
+                let mut checkpoints = vec![
+                    vk::CheckpointDataNV::default();
+                    unsafe { loader.get_queue_checkpoint_data_len(queue) }
+                ];
+                unsafe { loader.get_queue_checkpoint_data(queue, &mut checkpoints) };
+                // like the markers, checkpoints are only exposed through the legacy stage flags
+
+                checkpoints
+                    .iter()
+                    .filter(|checkpoint| {
+                        checkpoint
+                            .stage
+                            .contains(vk::PipelineStageFlags::BOTTOM_OF_PIPE)
+                    })
+                    .map(|checkpoint| checkpoint.p_checkpoint_marker as usize as u32)
+                    .max()
+                    .unwrap_or(0)
+            }
+            (_, Some(buffer)) => *buffer.mapped::<u32>()?.first()?,
+            _ => return None,
+        };
+
+        Some(GpuHangReport {
+            last_completed_pass: (last_marker as usize).checked_sub(1),
+            pass_names: self.pass_names.clone(),
+        })
+    }
+}
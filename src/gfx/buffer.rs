@@ -5,7 +5,7 @@ use thiserror::Error;
 
 use crate::{
     gfx::{
-        allocator::{Allocation, Allocator},
+        allocator::{AllocTag, Allocation, Allocator},
         context::Context,
         device::Device,
     },
@@ -64,18 +64,54 @@ impl Buffer {
     }
 
     pub fn upload_data(&mut self, data: &[u8]) -> Result<(), BufferDataUploadError> {
-        self.allocation
-            .mapped_slice_mut()
+        self.mapped_mut::<u8>()
             .ok_or(BufferDataUploadError::MemoryMapping)?[..data.len()]
             .copy_from_slice(data);
 
         Ok(())
     }
+
+    /// Persistently mapped memory of the buffer, viewed as many `T` as fit in its size. Returns
+    /// `None` if the buffer is not host-visible or its memory is not aligned for `T`.
+    ///
+    /// Memory allocated for every `MemoryLocation` but `GpuOnly` is host-coherent, so writes are
+    /// visible to the device once the next submission is made, without any flush. The GPU must
+    /// not be using the range while it is written to, which usually means waiting for the frame
+    /// that last read it.
+    pub fn mapped_mut<T: bytemuck::Pod>(&mut self) -> Option<&mut [T]> {
+        let len = self.mapped_len::<T>()?;
+        let bytes = self.allocation.mapped_slice_mut()?;
+
+        bytemuck::try_cast_slice_mut(&mut bytes[..len]).ok()
+    }
+
+    /// Read access to the persistently mapped memory, meant for `GpuToCpu` buffers whose writes
+    /// from the device are known to be complete. See [`Self::mapped_mut`].
+    pub fn mapped<T: bytemuck::Pod>(&self) -> Option<&[T]> {
+        let len = self.mapped_len::<T>()?;
+        let bytes = self.allocation.mapped_slice()?;
+
+        bytemuck::try_cast_slice(&bytes[..len]).ok()
+    }
+
+    // Byte length of the largest whole number of `T` fitting in the buffer
+    fn mapped_len<T>(&self) -> Option<usize> {
+        let size = usize::try_from(self.size).ok()?;
+        let element_size = std::mem::size_of::<T>();
+        if element_size == 0 {
+            return None;
+        }
+
+        Some(size - size % element_size)
+    }
 }
 
 impl Drop for Buffer {
     fn drop(&mut self) {
-        unsafe { self.device_ref.read().destroy_buffer(self.handle, None) };
+        let device = self.device_ref.read();
+
+        device.handle_registry.unregister(self.handle);
+        unsafe { device.destroy_buffer(self.handle, None) };
     }
 }
 
@@ -110,12 +146,17 @@ pub enum BufferBuildWithDataError {
     DataUploadFailed(#[from] BufferDataUploadError),
 }
 
+#[derive(Clone)]
 pub struct BufferBuilder {
     pub name: String,
 
     pub size: u64,
     pub usage: vk::BufferUsageFlags,
     pub memory_location: gpu_allocator::MemoryLocation,
+    pub tag: AllocTag,
+    /// Queue families using the buffer concurrently, exclusive to the first one using it when
+    /// empty.
+    pub shared_queue_families: Vec<u32>,
 }
 
 /// @TODO(Ithyx): create new type with MemoryLocation::GpuOnly
@@ -131,6 +172,8 @@ impl BufferBuilder {
             usage: vk::BufferUsageFlags::UNIFORM_BUFFER,
             memory_location: gpu_allocator::MemoryLocation::CpuToGpu,
             name: String::from("unnamed buffer"),
+            tag: AllocTag::Uniform,
+            shared_queue_families: vec![],
         }
     }
 
@@ -140,6 +183,8 @@ impl BufferBuilder {
             usage: vk::BufferUsageFlags::TRANSFER_SRC,
             memory_location: gpu_allocator::MemoryLocation::CpuToGpu,
             name: String::from("unnamed staging buffer"),
+            tag: AllocTag::Staging,
+            shared_queue_families: vec![],
         }
     }
 
@@ -158,8 +203,20 @@ impl BufferBuilder {
         self
     }
 
+    pub fn with_tag(mut self, tag: AllocTag) -> Self {
+        self.tag = tag;
+        self
+    }
+
+    /// Spares transferring the ownership of the buffer between queues of different families, at
+    /// the cost of some access performance on some devices. Ignored for less than two families.
+    pub fn with_shared_queue_families(mut self, queue_families: &[u32]) -> Self {
+        queue_families.clone_into(&mut self.shared_queue_families);
+        self
+    }
+
     pub fn build(self, ctx: &mut Context) -> Result<Buffer, BufferBuildError> {
-        self.build_internal(ctx.device_ref.clone(), ctx.allocator_ref.clone())
+        self.build_internal(ctx.core.device_ref.clone(), ctx.core.allocator_ref.clone())
     }
 
     pub fn build_with_pod<T: bytemuck::Pod>(
@@ -191,16 +248,40 @@ impl BufferBuilder {
         device_ref: ThreadSafeRwRef<Device>,
         allocator_ref: ThreadSafeRef<Allocator>,
     ) -> Result<Buffer, BufferBuildError> {
-        let buffer_info = vk::BufferCreateInfo {
-            size: self.size,
-            usage: self.usage,
-            sharing_mode: vk::SharingMode::EXCLUSIVE,
-            ..Default::default()
+        self.build_with_scheme(device_ref, allocator_ref, true)
+    }
+
+    /// Same as [`Self::build_internal`], sharing a memory block with other allocations instead of
+    /// getting its own, which lets it be moved out of a sparse block.
+    pub(crate) fn build_suballocated(
+        self,
+        device_ref: ThreadSafeRwRef<Device>,
+        allocator_ref: ThreadSafeRef<Allocator>,
+    ) -> Result<Buffer, BufferBuildError> {
+        self.build_with_scheme(device_ref, allocator_ref, false)
+    }
+
+    fn build_with_scheme(
+        self,
+        device_ref: ThreadSafeRwRef<Device>,
+        allocator_ref: ThreadSafeRef<Allocator>,
+        dedicated: bool,
+    ) -> Result<Buffer, BufferBuildError> {
+        let buffer_info = vk::BufferCreateInfo::default()
+            .size(self.size)
+            .usage(self.usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        let buffer_info = match self.shared_queue_families.len() {
+            0 | 1 => buffer_info,
+            _ => buffer_info
+                .sharing_mode(vk::SharingMode::CONCURRENT)
+                .queue_family_indices(&self.shared_queue_families),
         };
 
         let device = device_ref.read();
         let handle = unsafe { device.create_buffer(&buffer_info, None) }
             .map_err(BufferBuildError::VulkanCreation)?;
+        device.handle_registry.register(handle, &self.name);
 
         let memory_req = unsafe { device.get_buffer_memory_requirements(handle) };
         let allocation = allocator_ref.lock().allocate(
@@ -209,8 +290,12 @@ impl BufferBuilder {
                 requirements: memory_req,
                 location: self.memory_location,
                 linear: true,
-                allocation_scheme: gpu_allocator::vulkan::AllocationScheme::DedicatedBuffer(handle),
+                allocation_scheme: match dedicated {
+                    true => gpu_allocator::vulkan::AllocationScheme::DedicatedBuffer(handle),
+                    false => gpu_allocator::vulkan::AllocationScheme::GpuAllocatorManaged,
+                },
             },
+            self.tag,
             allocator_ref.clone(),
         )?;
 
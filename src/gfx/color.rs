@@ -0,0 +1,63 @@
+use ash::vk;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Color {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+impl Color {
+    pub const BLACK: Self = Self::rgb(0.0, 0.0, 0.0);
+    pub const WHITE: Self = Self::rgb(1.0, 1.0, 1.0);
+    pub const RED: Self = Self::rgb(1.0, 0.0, 0.0);
+    pub const GREEN: Self = Self::rgb(0.0, 1.0, 0.0);
+    pub const BLUE: Self = Self::rgb(0.0, 0.0, 1.0);
+    pub const YELLOW: Self = Self::rgb(1.0, 1.0, 0.0);
+    pub const TRANSPARENT: Self = Self::rgba(0.0, 0.0, 0.0, 0.0);
+
+    pub const fn rgb(r: f32, g: f32, b: f32) -> Self {
+        Self::rgba(r, g, b, 1.0)
+    }
+
+    pub const fn rgba(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Self { r, g, b, a }
+    }
+
+    /// Decodes a color authored in sRGB, so that writing it through an sRGB view stores it as
+    /// authored. Alpha is always linear and left as is.
+    pub fn srgb_to_linear(self) -> Self {
+        let decode = |c: f32| {
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+
+        Self::rgba(decode(self.r), decode(self.g), decode(self.b), self.a)
+    }
+}
+
+impl Default for Color {
+    fn default() -> Self {
+        Self::BLACK
+    }
+}
+
+impl From<Color> for vk::ClearColorValue {
+    fn from(value: Color) -> Self {
+        Self {
+            float32: [value.r, value.g, value.b, value.a],
+        }
+    }
+}
+
+impl From<Color> for vk::ClearValue {
+    fn from(value: Color) -> Self {
+        Self {
+            color: value.into(),
+        }
+    }
+}
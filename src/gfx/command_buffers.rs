@@ -0,0 +1,410 @@
+use std::{
+    cell::Cell,
+    collections::{HashMap, hash_map::Entry},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread::{self, ThreadId},
+};
+
+use ash::vk;
+use thiserror::Error;
+
+use crate::utils::ThreadSafeRwRef;
+
+use super::{commands::FRAMES_IN_FLIGHT, device::Device};
+
+/// Recording state of an [`OwnedCommandBuffer`], tracked by its methods and by
+/// [`CommandBufferAllocator::submit`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandBufferState {
+    Initial,
+    Recording,
+    Executable,
+    /// Submitted after being recorded with `ONE_TIME_SUBMIT`, it has to be recorded again.
+    Invalid,
+}
+
+#[derive(Debug, Error)]
+pub enum CommandBufferAllocateError {
+    #[error("vulkan call to create the command pool of the thread failed")]
+    PoolCreation(vk::Result),
+
+    #[error("vulkan call to allocate command buffers failed")]
+    Allocation(vk::Result),
+}
+
+#[derive(Debug, Error)]
+pub enum CommandBufferRecordError {
+    #[error("command buffer allocated by another thread, its pool is not synchronized")]
+    WrongThread,
+
+    #[error("command buffer is expected to be recording, it is {0:?}")]
+    NotRecording(CommandBufferState),
+
+    #[error("command buffer is already recording")]
+    AlreadyRecording,
+
+    #[error("vulkan call to begin command buffer failed")]
+    Begin(vk::Result),
+
+    #[error("vulkan call to end command buffer failed")]
+    End(vk::Result),
+}
+
+#[derive(Debug, Error)]
+pub enum CommandSubmitError {
+    #[error("command buffer was not allocated by this context")]
+    ForeignBuffer,
+
+    #[error("secondary command buffers can only be executed by primary ones")]
+    SecondaryBuffer,
+
+    #[error("command buffer is {0:?}, only executable ones can be submitted")]
+    NotExecutable(CommandBufferState),
+
+    #[error("command buffer submission failed")]
+    Submission(vk::Result),
+}
+
+/// Synchronization of a [`CommandBufferAllocator::submit`], every semaphore must be binary.
+#[derive(Debug, Clone, Default)]
+pub struct SubmitSync {
+    pub wait: Vec<(vk::Semaphore, vk::PipelineStageFlags2)>,
+    pub signal: Vec<vk::Semaphore>,
+    pub fence: vk::Fence,
+}
+
+impl SubmitSync {
+    pub fn with_wait(mut self, semaphore: vk::Semaphore, stage: vk::PipelineStageFlags2) -> Self {
+        self.wait.push((semaphore, stage));
+        self
+    }
+
+    pub fn with_signal(mut self, semaphore: vk::Semaphore) -> Self {
+        self.signal.push(semaphore);
+        self
+    }
+
+    pub fn with_fence(mut self, fence: vk::Fence) -> Self {
+        self.fence = fence;
+        self
+    }
+}
+
+struct FreeCommandBuffer {
+    handle: vk::CommandBuffer,
+    level: vk::CommandBufferLevel,
+    // `None` when never submitted, otherwise the index of the first frame submitted after it was
+    // dropped, whose completion guarantees the buffer is not pending anymore
+    reusable_after_frame: Option<u64>,
+}
+
+struct ThreadCommandPool {
+    handle: vk::CommandPool,
+    free_buffers: Vec<FreeCommandBuffer>,
+}
+
+pub(crate) struct CommandPools {
+    // command pools are externally synchronized, every thread allocates from its own
+    pools: Mutex<HashMap<ThreadId, ThreadCommandPool>>,
+    frame_counter: Arc<AtomicU64>,
+    device_ref: ThreadSafeRwRef<Device>,
+}
+
+impl CommandPools {
+    pub(crate) fn new(device_ref: ThreadSafeRwRef<Device>, frame_counter: Arc<AtomicU64>) -> Self {
+        Self {
+            pools: Mutex::default(),
+            frame_counter,
+            device_ref,
+        }
+    }
+
+    fn release(&self, thread: ThreadId, free_buffer: FreeCommandBuffer) {
+        let mut pools = self
+            .pools
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(pool) = pools.get_mut(&thread) {
+            pool.free_buffers.push(free_buffer);
+        }
+    }
+}
+
+impl Drop for CommandPools {
+    fn drop(&mut self) {
+        let device = self.device_ref.read();
+        let pools = self
+            .pools
+            .get_mut()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        for (_, pool) in pools.drain() {
+            unsafe { device.destroy_command_pool(pool.handle, None) };
+        }
+    }
+}
+
+/// Hands out command buffers recorded by the application, from a pool per thread so that several
+/// threads can record in parallel, e.g. secondary buffers executed by a render pass. Cheap to
+/// clone and to send to worker threads.
+#[derive(Clone)]
+pub struct CommandBufferAllocator {
+    pools: Arc<CommandPools>,
+}
+
+impl CommandBufferAllocator {
+    pub(crate) fn new(pools: Arc<CommandPools>) -> Self {
+        Self { pools }
+    }
+
+    /// Allocates from the pool of the calling thread, which is the only one allowed to record the
+    /// returned buffers. They can be sent to other threads to be submitted or executed, and go
+    /// back to the pool when dropped.
+    pub fn allocate(
+        &self,
+        level: vk::CommandBufferLevel,
+        count: u32,
+    ) -> Result<Vec<OwnedCommandBuffer>, CommandBufferAllocateError> {
+        let thread = thread::current().id();
+        let submitted_frames = self.pools.frame_counter.load(Ordering::Acquire);
+        let device = self.pools.device_ref.read();
+        let mut pools = self
+            .pools
+            .pools
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let pool = match pools.entry(thread) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let pool_info = vk::CommandPoolCreateInfo::default()
+                    .queue_family_index(device.graphics_queue.family_index)
+                    .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER);
+                let handle = unsafe { device.create_command_pool(&pool_info, None) }
+                    .map_err(CommandBufferAllocateError::PoolCreation)?;
+
+                entry.insert(ThreadCommandPool {
+                    handle,
+                    free_buffers: vec![],
+                })
+            }
+        };
+
+        let mut handles = vec![];
+        pool.free_buffers.retain(|free_buffer| {
+            let reusable = free_buffer.level == level
+                && free_buffer
+                    .reusable_after_frame
+                    .is_none_or(|frame| frame + (FRAMES_IN_FLIGHT as u64) < submitted_frames);
+            if reusable && handles.len() < count as usize {
+                handles.push(free_buffer.handle);
+                return false;
+            }
+
+            true
+        });
+
+        let missing_count = count - handles.len() as u32;
+        if missing_count > 0 {
+            let cmd_buffer_info = vk::CommandBufferAllocateInfo::default()
+                .level(level)
+                .command_buffer_count(missing_count)
+                .command_pool(pool.handle);
+            let allocated = unsafe { device.allocate_command_buffers(&cmd_buffer_info) }
+                .map_err(CommandBufferAllocateError::Allocation)?;
+            handles.extend(allocated);
+        }
+
+        Ok(handles
+            .into_iter()
+            .map(|handle| OwnedCommandBuffer {
+                handle,
+                level,
+                thread,
+                state: Cell::new(CommandBufferState::Initial),
+                one_time_submit: false,
+                submitted: Cell::new(false),
+                pools: self.pools.clone(),
+            })
+            .collect())
+    }
+
+    /// Submits primary buffers to the graphics queue. Their states are only checked in debug
+    /// builds. A buffer dropped after being submitted is only reused once a frame submitted later
+    /// completes, so `sync.fence` only has to be waited on to read the results.
+    pub fn submit(
+        &self,
+        cmd_buffers: &[OwnedCommandBuffer],
+        sync: &SubmitSync,
+    ) -> Result<(), CommandSubmitError> {
+        for cmd_buffer in cmd_buffers {
+            if !Arc::ptr_eq(&cmd_buffer.pools, &self.pools) {
+                return Err(CommandSubmitError::ForeignBuffer);
+            }
+            if cmd_buffer.level != vk::CommandBufferLevel::PRIMARY {
+                return Err(CommandSubmitError::SecondaryBuffer);
+            }
+            if cfg!(debug_assertions) && cmd_buffer.state() != CommandBufferState::Executable {
+                return Err(CommandSubmitError::NotExecutable(cmd_buffer.state()));
+            }
+        }
+
+        let cmd_buffer_infos = cmd_buffers
+            .iter()
+            .map(|cmd_buffer| {
+                vk::CommandBufferSubmitInfo::default().command_buffer(cmd_buffer.handle)
+            })
+            .collect::<Vec<_>>();
+        let wait_semaphores = sync
+            .wait
+            .iter()
+            .map(|&(semaphore, stage_mask)| {
+                vk::SemaphoreSubmitInfo::default()
+                    .semaphore(semaphore)
+                    .stage_mask(stage_mask)
+            })
+            .collect::<Vec<_>>();
+        // signaled once every command completed
+        let signal_semaphores = sync
+            .signal
+            .iter()
+            .map(|&semaphore| {
+                vk::SemaphoreSubmitInfo::default()
+                    .semaphore(semaphore)
+                    .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+            })
+            .collect::<Vec<_>>();
+        let device = self.pools.device_ref.read();
+        unsafe {
+            device.queue_submit2(
+                device.graphics_queue.handle,
+                &[vk::SubmitInfo2::default()
+                    .command_buffer_infos(&cmd_buffer_infos)
+                    .wait_semaphore_infos(&wait_semaphores)
+                    .signal_semaphore_infos(&signal_semaphores)],
+                sync.fence,
+            )
+        }
+        .map_err(CommandSubmitError::Submission)?;
+
+        for cmd_buffer in cmd_buffers {
+            cmd_buffer.submitted.set(true);
+            if cmd_buffer.one_time_submit {
+                cmd_buffer.state.set(CommandBufferState::Invalid);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for CommandBufferAllocator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CommandBufferAllocator")
+            .finish_non_exhaustive()
+    }
+}
+
+/// Command buffer allocated through a [`CommandBufferAllocator`], returned to the pool of its
+/// thread when dropped. Recorded with the raw device from [`Self::handle`] between
+/// [`Self::begin`] and [`Self::end`].
+pub struct OwnedCommandBuffer {
+    handle: vk::CommandBuffer,
+    level: vk::CommandBufferLevel,
+    thread: ThreadId,
+    state: Cell<CommandBufferState>,
+    one_time_submit: bool,
+    submitted: Cell<bool>,
+    pools: Arc<CommandPools>,
+}
+
+impl OwnedCommandBuffer {
+    pub fn handle(&self) -> vk::CommandBuffer {
+        self.handle
+    }
+
+    pub fn level(&self) -> vk::CommandBufferLevel {
+        self.level
+    }
+
+    pub fn state(&self) -> CommandBufferState {
+        self.state.get()
+    }
+
+    /// Resets the buffer and starts recording it. Secondary buffers need an `inheritance` info,
+    /// chained with a `vk::CommandBufferInheritanceRenderingInfo` when executed inside a pass.
+    pub fn begin(
+        &mut self,
+        flags: vk::CommandBufferUsageFlags,
+        inheritance: Option<&vk::CommandBufferInheritanceInfo>,
+    ) -> Result<(), CommandBufferRecordError> {
+        if thread::current().id() != self.thread {
+            return Err(CommandBufferRecordError::WrongThread);
+        }
+        if self.state() == CommandBufferState::Recording {
+            return Err(CommandBufferRecordError::AlreadyRecording);
+        }
+
+        let mut begin_info = vk::CommandBufferBeginInfo::default().flags(flags);
+        if let Some(inheritance) = inheritance {
+            begin_info = begin_info.inheritance_info(inheritance);
+        }
+        // begin implicitly resets the buffer, its pool allows it
+        unsafe {
+            self.pools
+                .device_ref
+                .read()
+                .begin_command_buffer(self.handle, &begin_info)
+        }
+        .map_err(CommandBufferRecordError::Begin)?;
+
+        self.one_time_submit = flags.contains(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        self.state.set(CommandBufferState::Recording);
+
+        Ok(())
+    }
+
+    pub fn end(&mut self) -> Result<(), CommandBufferRecordError> {
+        if thread::current().id() != self.thread {
+            return Err(CommandBufferRecordError::WrongThread);
+        }
+        if self.state() != CommandBufferState::Recording {
+            return Err(CommandBufferRecordError::NotRecording(self.state()));
+        }
+
+        unsafe { self.pools.device_ref.read().end_command_buffer(self.handle) }
+            .map_err(CommandBufferRecordError::End)?;
+        self.state.set(CommandBufferState::Executable);
+
+        Ok(())
+    }
+}
+
+impl Drop for OwnedCommandBuffer {
+    fn drop(&mut self) {
+        let reusable_after_frame = self
+            .submitted
+            .get()
+            .then(|| self.pools.frame_counter.load(Ordering::Acquire));
+        self.pools.release(
+            self.thread,
+            FreeCommandBuffer {
+                handle: self.handle,
+                level: self.level,
+                reusable_after_frame,
+            },
+        );
+    }
+}
+
+impl std::fmt::Debug for OwnedCommandBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OwnedCommandBuffer")
+            .field("handle", &self.handle)
+            .field("level", &self.level)
+            .field("state", &self.state.get())
+            .finish_non_exhaustive()
+    }
+}
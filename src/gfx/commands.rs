@@ -1,21 +1,78 @@
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread::ThreadId,
+    time::{Duration, Instant},
+};
+
 use ash::vk::{self, CommandBufferLevel};
 use thiserror::Error;
 
-use crate::utils::ThreadSafeRwRef;
+use crate::utils::{ThreadSafeRef, ThreadSafeRwRef};
 
 use super::{
-    device::Device,
+    command_buffers::{CommandBufferAllocator, CommandPools},
+    debug,
+    device::{Device, DeviceQueue},
     render_graph::RenderGraphRunError,
-    swapchain::{ImageResources, Swapchain},
+    swapchain::{FrameSemaphores, ImageResources, Swapchain},
+    timeline::{TimelineSemaphore, TimelineSemaphoreError},
 };
 
+/// Most frames recorded or executed at once, the CPU recording a frame while the GPU may still be
+/// executing the previous ones. The count actually used is
+/// [`EngineTunables::frames_in_flight`](super::overrides::EngineTunables::frames_in_flight).
+pub const FRAMES_IN_FLIGHT: usize = 2;
+
+/// Slot of the frame being recorded among the frames in flight, cycling in order. Sync objects and
+/// resources reused once the GPU is done with a frame are indexed with it, while those tied to a
+/// presented image use a [`SwapchainImageIndex`](super::swapchain::SwapchainImageIndex) instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct FrameSlotIndex(pub u32);
+
+impl FrameSlotIndex {
+    pub fn from_frame_counter(frame_counter: u64, frames_in_flight: usize) -> Self {
+        Self((frame_counter % frames_in_flight as u64) as u32)
+    }
+
+    pub fn as_usize(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Whether the commands of `frame` are complete once the frame slot of `current_frame` was waited
+/// on, frames completing in submission order.
+pub(crate) fn is_frame_complete(frame: u64, current_frame: u64) -> bool {
+    frame + FRAMES_IN_FLIGHT as u64 <= current_frame
+}
+
 pub struct CommandManager {
     pub(crate) cmd_pool: vk::CommandPool,
+    // number of frames submitted so far, shared with resources duplicated per frame slot
+    pub(crate) frame_counter: Arc<AtomicU64>,
 
-    pub(crate) rendering_cmd_buffer: vk::CommandBuffer,
+    pub(crate) frames_in_flight: usize,
+    // per frame slot, one command buffer per submission batch, grown on demand
+    pub(crate) rendering_cmd_buffers: Vec<Vec<vk::CommandBuffer>>,
+    // per frame slot, signaled by batch `i` and waited on by batch `i + 1`
+    pub(crate) batch_semaphores: Vec<Vec<vk::Semaphore>>,
+    pub(crate) last_frame_submit_times: Vec<Instant>,
 
-    pub(crate) immediate_cmd_buffer: vk::CommandBuffer,
-    pub(crate) immediate_fence: vk::Fence,
+    immediate: ImmediateQueue,
+    // on the transfer queue family, which may be the graphics one
+    transfer: ImmediateQueue,
+
+    // on the compute queue family, only used when it differs from the graphics one. Per frame
+    // slot, one command buffer and semaphore per async compute submission, grown on demand
+    compute_cmd_pool: Option<vk::CommandPool>,
+    compute_cmd_buffers: Vec<Vec<vk::CommandBuffer>>,
+    compute_semaphores: Vec<Vec<vk::Semaphore>>,
+
+    // pools of the command buffers recorded by the application, one per recording thread
+    user_pools: Arc<CommandPools>,
 
     //bookkeeping
     device_ref: ThreadSafeRwRef<Device>,
@@ -29,12 +86,18 @@ pub enum CommandManagerCreateError {
     #[error("vulkan call to allocated command buffer failed")]
     CmdBufferAllocation(vk::Result),
 
-    #[error("vulkan call to create fence failed")]
-    FenceCreation(vk::Result),
+    #[error("immediate command timeline creation failed")]
+    TimelineCreation(#[from] TimelineSemaphoreError),
 }
 
 #[derive(Debug, Error)]
 pub enum ImmediateCommandError {
+    #[error("vulkan call to create the immediate command pool of the thread failed")]
+    CmdPoolCreation(vk::Result),
+
+    #[error("vulkan call to allocate the immediate command buffer failed")]
+    CmdBufferAllocation(vk::Result),
+
     #[error("immediate command buffer begin failed")]
     Begin(vk::Result),
 
@@ -44,44 +107,47 @@ pub enum ImmediateCommandError {
     #[error("immediate command buffer submission failed")]
     Submission(vk::Result),
 
-    #[error("immediate command fence waiting failed")]
-    FenceWaiting(vk::Result),
-
-    #[error("immediate command resources resetting failed")]
-    Reset(vk::Result),
+    #[error("immediate command timeline waiting failed")]
+    TimelineWaiting(#[from] TimelineSemaphoreError),
 }
 
 #[derive(Debug, Error)]
-pub enum RenderCommandError {
-    #[error("presentation fence sync failed")]
-    FenceSync(vk::Result),
-
-    #[error("presentation fence reset failed")]
-    FenceReset(vk::Result),
+pub enum BatchSubmitError {
+    #[error("vulkan call to allocate the batch command buffer failed")]
+    CmdBufferAllocation(vk::Result),
 
-    #[error("render command resources resetting failed")]
-    Reset(vk::Result),
+    #[error("vulkan call to create the batch semaphore failed")]
+    SemaphoreCreation(vk::Result),
 
-    #[error("render command buffer begin failed")]
+    #[error("batch command buffer begin failed")]
     Begin(vk::Result),
 
-    #[error("render graph execution failed")]
-    RenderGraphRun(#[from] RenderGraphRunError),
-
     #[error("vulkan call to end command buffer failed")]
     CommandBufferEnd(vk::Result),
 
-    #[error("render command buffer submission failed")]
+    #[error("batch command buffer submission failed")]
     Submission(vk::Result),
+}
+
+#[derive(Debug, Error)]
+pub enum RenderCommandError {
+    #[error("render graph execution failed")]
+    RenderGraphRun(#[from] RenderGraphRunError),
+
+    #[error("final batch submission failed")]
+    BatchSubmission(#[from] BatchSubmitError),
 
     #[error("render command fence waiting failed")]
     FenceWaiting(vk::Result),
 }
 
 impl CommandManager {
+    /// `frames_in_flight` is clamped between 1 and [`FRAMES_IN_FLIGHT`].
     pub(crate) fn try_new(
         device_ref: ThreadSafeRwRef<Device>,
+        frames_in_flight: usize,
     ) -> Result<Self, CommandManagerCreateError> {
+        let frames_in_flight = frames_in_flight.clamp(1, FRAMES_IN_FLIGHT);
         let device = device_ref.read();
 
         let cmd_pool_info = vk::CommandPoolCreateInfo::default()
@@ -92,128 +158,583 @@ impl CommandManager {
 
         let cmd_buffer_info = vk::CommandBufferAllocateInfo::default()
             .level(CommandBufferLevel::PRIMARY)
-            .command_buffer_count(2)
+            .command_buffer_count(frames_in_flight as u32)
             .command_pool(cmd_pool);
         let cmd_buffers = unsafe { device.allocate_command_buffers(&cmd_buffer_info) }
             .map_err(CommandManagerCreateError::CmdBufferAllocation)?;
+        drop(device);
 
-        let fence_info = vk::FenceCreateInfo::default();
-        let immediate_fence = unsafe { device.create_fence(&fence_info, None) }
-            .map_err(CommandManagerCreateError::FenceCreation)?;
+        let immediate = ImmediateQueue::new(
+            "immediate command timeline",
+            |device| &device.graphics_queue,
+            device_ref.clone(),
+        )?;
+        let transfer = ImmediateQueue::new(
+            "transfer command timeline",
+            |device| &device.transfer_queue,
+            device_ref.clone(),
+        )?;
+        let device = device_ref.read();
+
+        let compute_cmd_pool = match device.has_async_compute() {
+            true => {
+                let compute_cmd_pool_info = vk::CommandPoolCreateInfo::default()
+                    .queue_family_index(device.compute_queue.family_index)
+                    .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER);
+                Some(
+                    unsafe { device.create_command_pool(&compute_cmd_pool_info, None) }
+                        .map_err(CommandManagerCreateError::CmdPoolCreation)?,
+                )
+            }
+            false => None,
+        };
+
+        let frame_counter = Arc::<AtomicU64>::default();
+        let user_pools = Arc::new(CommandPools::new(device_ref.clone(), frame_counter.clone()));
 
         Ok(Self {
             cmd_pool,
-            rendering_cmd_buffer: cmd_buffers[0],
-            immediate_cmd_buffer: cmd_buffers[1],
-            immediate_fence,
+            frame_counter,
+            frames_in_flight,
+            rendering_cmd_buffers: cmd_buffers.into_iter().map(|buffer| vec![buffer]).collect(),
+            batch_semaphores: vec![vec![]; frames_in_flight],
+            last_frame_submit_times: vec![],
+            immediate,
+            transfer,
+            compute_cmd_pool,
+            compute_cmd_buffers: vec![vec![]; frames_in_flight],
+            compute_semaphores: vec![vec![]; frames_in_flight],
+            user_pools,
             device_ref: device_ref.clone(),
         })
     }
 
+    pub(crate) fn command_buffer_allocator(&self) -> CommandBufferAllocator {
+        CommandBufferAllocator::new(self.user_pools.clone())
+    }
+
+    /// Slot of the next frame to be submitted.
+    pub(crate) fn frame_slot(&self) -> FrameSlotIndex {
+        FrameSlotIndex::from_frame_counter(
+            self.frame_counter.load(Ordering::Acquire),
+            self.frames_in_flight,
+        )
+    }
+
     pub(crate) fn render_command<Fn>(
-        &self,
+        &mut self,
         swapchain: &mut Swapchain,
+        semaphores: FrameSemaphores,
         f: Fn,
     ) -> Result<(), RenderCommandError>
     where
-        Fn: FnOnce(&vk::CommandBuffer, ImageResources) -> Result<(), RenderGraphRunError>,
+        Fn: FnOnce(&mut FrameSubmission, ImageResources) -> Result<(), RenderGraphRunError>,
     {
-        {
-            let device = self.device_ref.read();
+        self.last_frame_submit_times.clear();
+
+        let mut submission = FrameSubmission {
+            frame_slot: self.frame_slot().as_usize(),
+            image_acquired_semaphore: semaphores.image_acquired,
+            acquire_waited: false,
+            batch_index: 0,
+            compute_index: 0,
+            compute_waits: vec![],
+            manager: self,
+        };
+        submission.begin_batch()?;
+        submission.cmd_wait_previous_frames();
+
+        f(&mut submission, swapchain.current_image_resources())?;
+        swapchain.ensure_presentable(&submission.cmd_buffer());
+
+        submission.submit_batch(true, semaphores.render_finished, semaphores.present_fence)?;
+        swapchain.end_frame();
+        self.frame_counter.fetch_add(1, Ordering::Release);
 
-            unsafe {
-                device.reset_command_buffer(
-                    self.rendering_cmd_buffer,
-                    vk::CommandBufferResetFlags::default(),
-                )
-            }
-            .map_err(RenderCommandError::Reset)?;
-
-            let begin_info = vk::CommandBufferBeginInfo::default()
-                .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
-            unsafe { device.begin_command_buffer(self.rendering_cmd_buffer, &begin_info) }
-                .map_err(RenderCommandError::Begin)?;
-        }
+        Ok(())
+    }
 
-        f(
-            &self.rendering_cmd_buffer,
-            swapchain.current_image_resources(),
-        )?;
-        swapchain.ensure_presentable(&self.rendering_cmd_buffer);
+    /// Records `f` and submits it to the graphics queue, blocking until it completed.
+    pub fn immediate_command<Fn, ReturnType>(
+        &self,
+        f: Fn,
+    ) -> Result<ReturnType, ImmediateCommandError>
+    where
+        Fn: FnOnce(&vk::CommandBuffer) -> ReturnType,
+    {
+        self.immediate.submit_and_wait(f)
+    }
 
-        {
-            let device = self.device_ref.read();
-            unsafe { device.end_command_buffer(self.rendering_cmd_buffer) }
-                .map_err(RenderCommandError::CommandBufferEnd)?;
-
-            let cmd_buffers = [self.rendering_cmd_buffer];
-            unsafe {
-                device.queue_submit(
-                    device.graphics_queue.handle,
-                    &[vk::SubmitInfo::default()
-                        .command_buffers(&cmd_buffers)
-                        .wait_dst_stage_mask(&[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT])
-                        .wait_semaphores(&[swapchain.image_acquired_semaphore])
-                        .signal_semaphores(&[
-                            swapchain.images[swapchain.current_image_index].render_semaphore
-                        ])],
-                    swapchain.present_fence,
-                )
-            }
-            .map_err(RenderCommandError::Submission)?;
-        }
+    /// Same as [`Self::immediate_command`] without blocking. The returned value is reached by
+    /// [`Self::immediate_timeline`] once the commands completed, immediate commands completing in
+    /// submission order.
+    pub fn submit_immediate_command<Fn, ReturnType>(
+        &self,
+        f: Fn,
+    ) -> Result<(ReturnType, u64), ImmediateCommandError>
+    where
+        Fn: FnOnce(&vk::CommandBuffer) -> ReturnType,
+    {
+        self.immediate.submit(f)
+    }
 
-        Ok(())
+    pub fn immediate_timeline(&self) -> &TimelineSemaphore {
+        &self.immediate.timeline
     }
 
-    pub fn immediate_command<Fn, ReturnType>(
+    /// Same as [`Self::immediate_command`] on the transfer queue, which lets the graphics queue
+    /// keep rendering during uploads when it is a dedicated one. Only transfer commands are
+    /// allowed, and resources used by the graphics queue afterwards must either be shared with it,
+    /// see [`Device::upload_queue_families`], or have their ownership transferred.
+    pub fn transfer_command<Fn, ReturnType>(
         &self,
         f: Fn,
     ) -> Result<ReturnType, ImmediateCommandError>
     where
         Fn: FnOnce(&vk::CommandBuffer) -> ReturnType,
     {
+        self.transfer.submit_and_wait(f)
+    }
+
+    /// Same as [`Self::submit_immediate_command`] on the transfer queue, whose completion is
+    /// tracked by [`Self::transfer_timeline`]. Render submissions can wait on it with
+    /// [`TimelineSemaphore::wait_info`].
+    pub fn submit_transfer_command<Fn, ReturnType>(
+        &self,
+        f: Fn,
+    ) -> Result<(ReturnType, u64), ImmediateCommandError>
+    where
+        Fn: FnOnce(&vk::CommandBuffer) -> ReturnType,
+    {
+        self.transfer.submit(f)
+    }
+
+    pub fn transfer_timeline(&self) -> &TimelineSemaphore {
+        &self.transfer.timeline
+    }
+}
+
+/// Command buffers submitted outside of frames to a queue, each one signaling the next value of
+/// the timeline once complete.
+struct ImmediateQueue {
+    timeline: TimelineSemaphore,
+    state: ThreadSafeRef<ImmediateQueueState>,
+    queue: fn(&Device) -> &DeviceQueue,
+
+    //bookkeeping
+    device_ref: ThreadSafeRwRef<Device>,
+}
+
+struct ImmediateQueueState {
+    // command pools are externally synchronized, every thread records from its own so that
+    // commands are recorded without holding the lock
+    pools: HashMap<ThreadId, ImmediatePool>,
+    last_value: u64,
+}
+
+#[derive(Default)]
+struct ImmediatePool {
+    handle: vk::CommandPool,
+    // reusable once the timeline reached their value
+    pending: Vec<(vk::CommandBuffer, u64)>,
+    free: Vec<vk::CommandBuffer>,
+}
+
+impl ImmediatePool {
+    fn recycle(&mut self, completed_value: u64) {
+        let (completed, pending) = self
+            .pending
+            .drain(..)
+            .partition::<Vec<_>, _>(|&(_, value)| value <= completed_value);
+        self.pending = pending;
+        self.free
+            .extend(completed.into_iter().map(|(cmd_buffer, _)| cmd_buffer));
+    }
+}
+
+// goes back to the pool of its thread unless submitted, reset as it may still be recording
+struct ImmediateCommandBuffer<'a> {
+    handle: vk::CommandBuffer,
+    thread: ThreadId,
+    queue: &'a ImmediateQueue,
+    submitted: bool,
+}
+
+impl Drop for ImmediateCommandBuffer<'_> {
+    fn drop(&mut self) {
+        if self.submitted {
+            return;
+        }
+
+        let reset = unsafe {
+            self.queue
+                .device_ref
+                .read()
+                .reset_command_buffer(self.handle, vk::CommandBufferResetFlags::empty())
+        };
+        if let Err(err) = reset {
+            log::warn!("immediate command buffer reset failed, dropping it: {err}");
+            return;
+        }
+        if let Some(pool) = self.queue.state.lock().pools.get_mut(&self.thread) {
+            pool.free.push(self.handle);
+        }
+    }
+}
+
+impl ImmediateQueue {
+    fn new(
+        name: &str,
+        queue: fn(&Device) -> &DeviceQueue,
+        device_ref: ThreadSafeRwRef<Device>,
+    ) -> Result<Self, CommandManagerCreateError> {
+        let timeline = TimelineSemaphore::new(name, 0, device_ref.clone())?;
+
+        Ok(Self {
+            timeline,
+            state: ThreadSafeRef::new(ImmediateQueueState {
+                pools: HashMap::new(),
+                last_value: 0,
+            }),
+            queue,
+            device_ref,
+        })
+    }
+
+    fn submit_and_wait<Fn, ReturnType>(&self, f: Fn) -> Result<ReturnType, ImmediateCommandError>
+    where
+        Fn: FnOnce(&vk::CommandBuffer) -> ReturnType,
+    {
+        let (result, value) = self.submit(f)?;
+        self.timeline.wait(value, Duration::MAX)?;
+
+        Ok(result)
+    }
+
+    // from the pool of the calling thread, which is the only one recording it
+    fn acquire(&self) -> Result<ImmediateCommandBuffer<'_>, ImmediateCommandError> {
+        let thread = std::thread::current().id();
+        let completed_value = self.timeline.current_value()?;
+        let device = self.device_ref.read();
+        let mut state = self.state.lock();
+
+        let pool = match state.pools.entry(thread) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let cmd_pool_info = vk::CommandPoolCreateInfo::default()
+                    .queue_family_index((self.queue)(&device).family_index)
+                    .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER);
+                let handle = unsafe { device.create_command_pool(&cmd_pool_info, None) }
+                    .map_err(ImmediateCommandError::CmdPoolCreation)?;
+
+                entry.insert(ImmediatePool {
+                    handle,
+                    ..Default::default()
+                })
+            }
+        };
+        pool.recycle(completed_value);
+
+        let handle = match pool.free.pop() {
+            Some(cmd_buffer) => cmd_buffer,
+            None => {
+                let cmd_buffer_info = vk::CommandBufferAllocateInfo::default()
+                    .level(CommandBufferLevel::PRIMARY)
+                    .command_buffer_count(1)
+                    .command_pool(pool.handle);
+                unsafe { device.allocate_command_buffers(&cmd_buffer_info) }
+                    .map_err(ImmediateCommandError::CmdBufferAllocation)?[0]
+            }
+        };
+
+        Ok(ImmediateCommandBuffer {
+            handle,
+            thread,
+            queue: self,
+            submitted: false,
+        })
+    }
+
+    fn submit<Fn, ReturnType>(&self, f: Fn) -> Result<(ReturnType, u64), ImmediateCommandError>
+    where
+        Fn: FnOnce(&vk::CommandBuffer) -> ReturnType,
+    {
+        let mut cmd_buffer = self.acquire()?;
+
+        // recorded without the lock, `f` may record immediate commands of its own
         {
             let device = self.device_ref.read();
+            // begin implicitly resets the buffer, its pool allows it
             let begin_info = vk::CommandBufferBeginInfo::default()
                 .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
-            unsafe { device.begin_command_buffer(self.immediate_cmd_buffer, &begin_info) }
+            unsafe { device.begin_command_buffer(cmd_buffer.handle, &begin_info) }
                 .map_err(ImmediateCommandError::Begin)?;
         }
+        let result = f(&cmd_buffer.handle);
 
-        let result = f(&self.immediate_cmd_buffer);
+        let device = self.device_ref.read();
+        unsafe { device.end_command_buffer(cmd_buffer.handle) }
+            .map_err(ImmediateCommandError::CommandBufferEnd)?;
+
+        let mut state = self.state.lock();
+        // waiting on the previous submission keeps the signaled values increasing, and makes
+        // immediate commands depend on the ones submitted before them
+        let value = state.last_value + 1;
+        let cmd_buffer_infos =
+            [vk::CommandBufferSubmitInfo::default().command_buffer(cmd_buffer.handle)];
+        let wait_semaphores = [self
+            .timeline
+            .wait_info(state.last_value, vk::PipelineStageFlags2::ALL_COMMANDS)];
+        let signal_semaphores = [self
+            .timeline
+            .signal_info(value, vk::PipelineStageFlags2::ALL_COMMANDS)];
+        let submit_info = vk::SubmitInfo2::default()
+            .command_buffer_infos(&cmd_buffer_infos)
+            .wait_semaphore_infos(&wait_semaphores)
+            .signal_semaphore_infos(&signal_semaphores);
+        unsafe {
+            device.queue_submit2(
+                (self.queue)(&device).handle,
+                &[submit_info],
+                vk::Fence::null(),
+            )
+        }
+        .map_err(ImmediateCommandError::Submission)?;
 
-        {
-            let device = self.device_ref.read();
-            unsafe { device.end_command_buffer(self.immediate_cmd_buffer) }
-                .map_err(ImmediateCommandError::CommandBufferEnd)?;
-
-            let cmd_buffers = [self.immediate_cmd_buffer];
-            let submit_info = vk::SubmitInfo::default().command_buffers(&cmd_buffers);
-            unsafe {
-                device.queue_submit(
-                    device.graphics_queue.handle,
-                    &[submit_info],
-                    self.immediate_fence,
-                )
-            }
-            .map_err(ImmediateCommandError::Submission)?;
+        state.last_value = value;
+        if let Some(pool) = state.pools.get_mut(&cmd_buffer.thread) {
+            pool.pending.push((cmd_buffer.handle, value));
+        }
+        cmd_buffer.submitted = true;
 
-            let fences = [self.immediate_fence];
-            unsafe { device.wait_for_fences(&fences, true, u64::MAX) }
-                .map_err(ImmediateCommandError::FenceWaiting)?;
+        Ok((result, value))
+    }
+}
 
-            unsafe { device.reset_fences(&fences) }.map_err(ImmediateCommandError::Reset)?;
-            unsafe {
-                device.reset_command_buffer(
-                    self.immediate_cmd_buffer,
-                    vk::CommandBufferResetFlags::default(),
-                )
-            }
-            .map_err(ImmediateCommandError::Reset)?;
+impl Drop for ImmediateQueue {
+    fn drop(&mut self) {
+        // the command manager waits for the device to be idle before its fields are dropped
+        let device = self.device_ref.read();
+        for (_, pool) in self.state.lock().pools.drain() {
+            unsafe { device.destroy_command_pool(pool.handle, None) };
         }
+    }
+}
 
-        Ok(result)
+/// Recording state of the current frame, which can be split into several queue submissions so the
+/// GPU starts working on early passes while later ones are still being recorded.
+pub(crate) struct FrameSubmission<'a> {
+    manager: &'a mut CommandManager,
+
+    frame_slot: usize,
+    image_acquired_semaphore: vk::Semaphore,
+    acquire_waited: bool,
+    batch_index: usize,
+    compute_index: usize,
+    // signaled by async compute submissions, waited on by the next batch
+    compute_waits: Vec<vk::Semaphore>,
+}
+
+impl FrameSubmission<'_> {
+    pub fn cmd_buffer(&self) -> vk::CommandBuffer {
+        self.manager.rendering_cmd_buffers[self.frame_slot][self.batch_index]
+    }
+
+    fn batch_semaphores(&mut self) -> &mut Vec<vk::Semaphore> {
+        &mut self.manager.batch_semaphores[self.frame_slot]
+    }
+
+    /// Submits everything recorded so far and starts a new batch. `uses_swapchain_image` must be
+    /// set if the batch touched the swapchain color image, which is only usable once acquired.
+    pub fn split(&mut self, uses_swapchain_image: bool) -> Result<(), BatchSubmitError> {
+        if self.batch_semaphores().len() <= self.batch_index {
+            let device = self.manager.device_ref.read();
+            let semaphore =
+                unsafe { device.create_semaphore(&vk::SemaphoreCreateInfo::default(), None) }
+                    .map_err(BatchSubmitError::SemaphoreCreation)?;
+            device
+                .handle_registry
+                .register(semaphore, "render graph batch semaphore");
+            drop(device);
+            self.batch_semaphores().push(semaphore);
+        }
+
+        let signal_semaphore = self.manager.batch_semaphores[self.frame_slot][self.batch_index];
+        self.submit_batch(uses_swapchain_image, signal_semaphore, vk::Fence::null())?;
+
+        self.batch_index += 1;
+        self.begin_batch()
+    }
+
+    /// Records `f` into a command buffer of its own submitted right away to the compute queue when
+    /// the device has an async one, in the current batch otherwise. The next batch submitted waits
+    /// for it, so that the passes recorded after it see its writes, while the batches submitted
+    /// before overlap with it.
+    pub fn async_compute<Fn>(&mut self, f: Fn) -> Result<(), BatchSubmitError>
+    where
+        Fn: FnOnce(vk::CommandBuffer),
+    {
+        let Some(compute_cmd_pool) = self.manager.compute_cmd_pool else {
+            f(self.cmd_buffer());
+            return Ok(());
+        };
+
+        let _operation =
+            debug::operation(|| format!("async compute submission {}", self.compute_index));
+        let device = self.manager.device_ref.read();
+        let cmd_buffers = &mut self.manager.compute_cmd_buffers[self.frame_slot];
+        if cmd_buffers.len() <= self.compute_index {
+            let cmd_buffer_info = vk::CommandBufferAllocateInfo::default()
+                .level(CommandBufferLevel::PRIMARY)
+                .command_buffer_count(1)
+                .command_pool(compute_cmd_pool);
+            let allocated = unsafe { device.allocate_command_buffers(&cmd_buffer_info) }
+                .map_err(BatchSubmitError::CmdBufferAllocation)?;
+            cmd_buffers.extend(allocated);
+        }
+        let semaphores = &mut self.manager.compute_semaphores[self.frame_slot];
+        if semaphores.len() <= self.compute_index {
+            let semaphore =
+                unsafe { device.create_semaphore(&vk::SemaphoreCreateInfo::default(), None) }
+                    .map_err(BatchSubmitError::SemaphoreCreation)?;
+            device
+                .handle_registry
+                .register(semaphore, "async compute semaphore");
+            semaphores.push(semaphore);
+        }
+
+        let cmd_buffer = self.manager.compute_cmd_buffers[self.frame_slot][self.compute_index];
+        let semaphore = self.manager.compute_semaphores[self.frame_slot][self.compute_index];
+        let begin_info = vk::CommandBufferBeginInfo::default()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        unsafe { device.begin_command_buffer(cmd_buffer, &begin_info) }
+            .map_err(BatchSubmitError::Begin)?;
+        f(cmd_buffer);
+        unsafe { device.end_command_buffer(cmd_buffer) }
+            .map_err(BatchSubmitError::CommandBufferEnd)?;
+
+        let cmd_buffer_infos = [vk::CommandBufferSubmitInfo::default().command_buffer(cmd_buffer)];
+        let signal_semaphores = [vk::SemaphoreSubmitInfo::default()
+            .semaphore(semaphore)
+            .stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)];
+        unsafe {
+            device.queue_submit2(
+                device.compute_queue.handle,
+                &[vk::SubmitInfo2::default()
+                    .command_buffer_infos(&cmd_buffer_infos)
+                    .signal_semaphore_infos(&signal_semaphores)],
+                vk::Fence::null(),
+            )
+        }
+        .map_err(BatchSubmitError::Submission)?;
+        drop(device);
+
+        self.compute_waits.push(semaphore);
+        self.compute_index += 1;
+
+        Ok(())
+    }
+
+    // graph attachments are shared by the frames in flight and their layouts tracked across frames,
+    // barriers recorded by the graph only order the passes of a frame
+    fn cmd_wait_previous_frames(&self) {
+        if self.manager.frames_in_flight == 1 {
+            return;
+        }
+
+        let barrier = vk::MemoryBarrier2::default()
+            .src_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+            .src_access_mask(vk::AccessFlags2::MEMORY_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+            .dst_access_mask(vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE);
+        let device = self.manager.device_ref.read();
+        let dependency_info =
+            vk::DependencyInfo::default().memory_barriers(std::slice::from_ref(&barrier));
+        unsafe { device.cmd_pipeline_barrier2(self.cmd_buffer(), &dependency_info) };
+    }
+
+    fn begin_batch(&mut self) -> Result<(), BatchSubmitError> {
+        let device = self.manager.device_ref.read();
+
+        let cmd_buffers = &mut self.manager.rendering_cmd_buffers[self.frame_slot];
+        if cmd_buffers.len() <= self.batch_index {
+            let cmd_buffer_info = vk::CommandBufferAllocateInfo::default()
+                .level(CommandBufferLevel::PRIMARY)
+                .command_buffer_count(1)
+                .command_pool(self.manager.cmd_pool);
+            let allocated = unsafe { device.allocate_command_buffers(&cmd_buffer_info) }
+                .map_err(BatchSubmitError::CmdBufferAllocation)?;
+            cmd_buffers.extend(allocated);
+        }
+
+        let cmd_buffer = cmd_buffers[self.batch_index];
+        // begin implicitly resets the buffer, its pool allows it
+        let begin_info = vk::CommandBufferBeginInfo::default()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        unsafe { device.begin_command_buffer(cmd_buffer, &begin_info) }
+            .map_err(BatchSubmitError::Begin)?;
+
+        Ok(())
+    }
+
+    fn submit_batch(
+        &mut self,
+        uses_swapchain_image: bool,
+        signal_semaphore: vk::Semaphore,
+        fence: vk::Fence,
+    ) -> Result<(), BatchSubmitError> {
+        let _operation =
+            debug::operation(|| format!("frame submission batch {}", self.batch_index));
+        let cmd_buffer = self.cmd_buffer();
+        let device = self.manager.device_ref.read();
+        unsafe { device.end_command_buffer(cmd_buffer) }
+            .map_err(BatchSubmitError::CommandBufferEnd)?;
+
+        let wait_semaphore = |semaphore, stage_mask| {
+            vk::SemaphoreSubmitInfo::default()
+                .semaphore(semaphore)
+                .stage_mask(stage_mask)
+        };
+        let mut wait_semaphores = vec![];
+        if let Some(index) = self.batch_index.checked_sub(1) {
+            wait_semaphores.push(wait_semaphore(
+                self.manager.batch_semaphores[self.frame_slot][index],
+                vk::PipelineStageFlags2::ALL_COMMANDS,
+            ));
+        }
+        if uses_swapchain_image && !self.acquire_waited {
+            wait_semaphores.push(wait_semaphore(
+                self.image_acquired_semaphore,
+                vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+            ));
+            self.acquire_waited = true;
+        }
+        for semaphore in self.compute_waits.drain(..) {
+            wait_semaphores.push(wait_semaphore(
+                semaphore,
+                vk::PipelineStageFlags2::ALL_COMMANDS,
+            ));
+        }
+
+        let cmd_buffer_infos = [vk::CommandBufferSubmitInfo::default().command_buffer(cmd_buffer)];
+        let signal_semaphores = [vk::SemaphoreSubmitInfo::default()
+            .semaphore(signal_semaphore)
+            .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)];
+        unsafe {
+            device.queue_submit2(
+                device.graphics_queue.handle,
+                &[vk::SubmitInfo2::default()
+                    .command_buffer_infos(&cmd_buffer_infos)
+                    .wait_semaphore_infos(&wait_semaphores)
+                    .signal_semaphore_infos(&signal_semaphores)],
+                fence,
+            )
+        }
+        .map_err(BatchSubmitError::Submission)?;
+
+        self.manager.last_frame_submit_times.push(Instant::now());
+
+        Ok(())
     }
 }
 
@@ -221,10 +742,141 @@ impl Drop for CommandManager {
     fn drop(&mut self) {
         let device = self.device_ref.read();
         log::debug!("Waiting for device to be idle before destroying command manager");
-        unsafe { device.device_wait_idle() }.expect("device should wait before shutting down");
+        // a lost device is still torn down, resources can be destroyed whatever their GPU state
+        if let Err(err) = unsafe { device.device_wait_idle() } {
+            log::warn!("waiting for the device to be idle failed: {err}");
+        }
 
         log::debug!("destroying command manager");
-        unsafe { device.destroy_fence(self.immediate_fence, None) };
+        for &semaphore in self.batch_semaphores.iter().flatten() {
+            device.handle_registry.unregister(semaphore);
+            unsafe { device.destroy_semaphore(semaphore, None) };
+        }
+        for &semaphore in self.compute_semaphores.iter().flatten() {
+            device.handle_registry.unregister(semaphore);
+            unsafe { device.destroy_semaphore(semaphore, None) };
+        }
+        if let Some(compute_cmd_pool) = self.compute_cmd_pool {
+            unsafe { device.destroy_command_pool(compute_cmd_pool, None) };
+        }
         unsafe { device.destroy_command_pool(self.cmd_pool, None) };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ash::vk::Handle;
+
+    use super::*;
+    use crate::gfx::{
+        buffer::BufferBuilder,
+        context::{Context, ContextCreateInfo},
+    };
+
+    #[test]
+    fn frames_complete_once_their_slot_comes_back() {
+        let frames_in_flight = FRAMES_IN_FLIGHT as u64;
+
+        assert!(!is_frame_complete(5, 5));
+        assert!(!is_frame_complete(5, 5 + frames_in_flight - 1));
+        assert!(is_frame_complete(5, 5 + frames_in_flight));
+        assert!(is_frame_complete(0, frames_in_flight));
+    }
+
+    #[test]
+    fn immediate_buffers_are_reused_once_their_value_is_reached() {
+        let (first, second, third) = (
+            vk::CommandBuffer::from_raw(1),
+            vk::CommandBuffer::from_raw(2),
+            vk::CommandBuffer::from_raw(3),
+        );
+        let mut pool = ImmediatePool {
+            pending: vec![(first, 1), (second, 2), (third, 3)],
+            ..Default::default()
+        };
+
+        pool.recycle(0);
+        assert!(pool.free.is_empty());
+
+        pool.recycle(2);
+        assert_eq!(pool.free, [first, second]);
+        assert_eq!(pool.pending, [(third, 3)]);
+
+        pool.recycle(3);
+        assert_eq!(pool.free, [first, second, third]);
+        assert!(pool.pending.is_empty());
+    }
+
+    fn headless_context() -> Context {
+        let extent = vk::Extent2D {
+            width: 16,
+            height: 16,
+        };
+        Context::new_headless(&ContextCreateInfo::new("immediate", (0, 1, 0)), extent)
+            .expect("a headless context should be created")
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan device"]
+    fn dependent_immediate_commands_complete_in_order() {
+        let mut ctx = headless_context();
+        let builder = |name| {
+            BufferBuilder::default(64)
+                .with_name(name)
+                .with_usage(vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST)
+        };
+        let source = builder("source").build(&mut ctx).unwrap();
+        let destination = builder("destination").build(&mut ctx).unwrap();
+
+        let commands = &ctx.core.command_manager;
+        let device = ctx.core.device_ref.clone();
+        let ((), fill_value) = commands
+            .submit_immediate_command(|&cmd_buffer| unsafe {
+                device
+                    .read()
+                    .cmd_fill_buffer(cmd_buffer, source.handle, 0, vk::WHOLE_SIZE, 7)
+            })
+            .unwrap();
+        // only ordered after the fill by the timeline
+        let ((), copy_value) = commands
+            .submit_immediate_command(|&cmd_buffer| unsafe {
+                let region = vk::BufferCopy::default().size(64);
+                device.read().cmd_copy_buffer(
+                    cmd_buffer,
+                    source.handle,
+                    destination.handle,
+                    &[region],
+                )
+            })
+            .unwrap();
+        assert_eq!(copy_value, fill_value + 1);
+
+        let timeline = commands.immediate_timeline();
+        assert!(timeline.wait(copy_value, Duration::from_secs(5)).unwrap());
+        assert!(timeline.current_value().unwrap() >= copy_value);
+        assert!(
+            destination
+                .mapped::<u32>()
+                .unwrap()
+                .iter()
+                .all(|&value| value == 7)
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan device"]
+    fn immediate_commands_can_be_nested() {
+        let ctx = headless_context();
+        let commands = &ctx.core.command_manager;
+
+        let inner_value = commands
+            .immediate_command(|_| commands.submit_immediate_command(|_| ()).unwrap().1)
+            .unwrap();
+        assert!(
+            commands
+                .immediate_timeline()
+                .wait(inner_value, Duration::from_secs(5))
+                .unwrap()
+        );
+    }
+}
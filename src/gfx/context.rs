@@ -1,46 +1,346 @@
-use std::ffi::CString;
+use std::{
+    ffi::{CStr, CString},
+    mem::ManuallyDrop,
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
 
 use ash::vk;
 use thiserror::Error;
 use winit::{
+    event_loop::EventLoopProxy,
     raw_window_handle::{HasDisplayHandle, HasWindowHandle},
-    window::Window,
+    window::{Fullscreen, Window},
 };
 
-use crate::utils::{ThreadSafeRef, ThreadSafeRwRef};
+use crate::{
+    math::Mat4,
+    monitor::{self, MonitorInfo, VideoModeInfo},
+    user_event::{EventProxy, UserEvent},
+    utils::ThreadSafeRef,
+    window_control::{WindowControl, WindowRequests},
+};
 
 use super::{
-    allocator::{Allocator, AllocatorCreateError},
-    commands::{CommandManager, CommandManagerCreateError, RenderCommandError},
-    debug::{DUMCreationError, DUMessenger},
-    device::{Device, DeviceCreateError, PhysicalDevice, PhysicalDeviceSelectError},
-    instance::{Instance, InstanceCreateError},
-    render_graph::{RenderGraph, RenderGraphCreateError, RenderGraphInfo},
-    surface::{DeviceSetupError, Surface, SurfaceCreateError},
+    allocator::{AllocationReport, AllocatorCreateError},
+    breadcrumbs::{Breadcrumbs, DeviceLostReport},
+    buffer::BufferDataUploadError,
+    command_buffers::{
+        CommandBufferAllocateError, CommandBufferAllocator, CommandSubmitError, OwnedCommandBuffer,
+        SubmitSync,
+    },
+    commands::{BatchSubmitError, CommandManagerCreateError, RenderCommandError},
+    debug::{self, DUMCreationError, ValidationStats},
+    deferred::DeferredResourceQueue,
+    defrag::{
+        self, DEFAULT_SPARSE_THRESHOLD, DefragCandidate, DefragError, DefragReport, DefragStats,
+        Defragmenter, IdleDefragmentation,
+    },
+    deletion_queue::DeletionQueue,
+    device::{DeviceCreateError, PhysicalDeviceSelectError},
+    features::{DeviceFeatureRequest, ExtensionRequirement},
+    frame_constants::{FrameConstants, FrameConstantsBlock, FrameConstantsCreateError},
+    frame_hooks::{FrameHook, FrameHookContext, FrameHooks, FrameStage, HookId},
+    frame_limiter::FrameLimiter,
+    frame_timing::{FrameTiming, FrameTimingHistory},
+    gpu_core::{GpuCore, InstanceObjects, MIN_API_VERSION},
+    image::{ImageBuildError, ImageState},
+    instance::InstanceCreateError,
+    overrides::{DeviceSelection, EngineOverrides, EngineTunables, ValidationMode},
+    presentation::{Presentation, SwapchainSummary},
+    readback::{ImageReadback, PixelReadError, PixelReadback, PixelReadbackQueue},
+    render_graph::{
+        RenderGraph, RenderGraphCreateError, RenderGraphDiff, RenderGraphInfo,
+        resource::{FrameInfo, ResourceID},
+        snapshot::RenderGraphSnapshot,
+    },
+    render_scale::{MAX_RENDER_SCALE, RenderScaleController, clamp_render_scale},
+    resize::{ResizeCoalescer, ResizeStats},
+    staging::StagingBelt,
+    surface::{DeviceSetupError, SurfaceCreateError},
     swapchain::{
-        NextImageAcquireError, NextImageState, PresentError, Swapchain, SwapchainCreateError,
+        CompositeAlphaPreference, DepthConfig, FrameBeginError, NextImageAcquireError,
+        NextImageState, PresentError, PresentPreference, PresentState, SurfaceFormatPreference,
+        SurfaceProperties, SwapchainCreateError, SwapchainOptions,
     },
 };
 
+pub type SurfaceChangeListener = Box<dyn FnMut(&SurfaceProperties)>;
+pub type RenderGraphChangeListener = Box<dyn FnMut(&RenderGraphDiff)>;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ListenerID(u64);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FullscreenMode {
+    Windowed,
+    /// Covers the monitor the window is on, without changing its video mode.
+    Borderless,
+    /// Covers the given monitor, without changing its video mode.
+    BorderlessOn(MonitorInfo),
+    /// Switches the monitor of the video mode to it, and covers it.
+    Exclusive(VideoModeInfo),
+}
+
+impl FullscreenMode {
+    pub(crate) fn from_window(window: &Window) -> Self {
+        match window.fullscreen() {
+            None => Self::Windowed,
+            Some(Fullscreen::Borderless(None)) => Self::Borderless,
+            Some(Fullscreen::Borderless(Some(monitor))) => {
+                Self::BorderlessOn(MonitorInfo::new(monitor))
+            }
+            Some(Fullscreen::Exclusive(video_mode)) => {
+                Self::Exclusive(VideoModeInfo::new(video_mode))
+            }
+        }
+    }
+
+    pub(crate) fn to_winit(&self) -> Option<Fullscreen> {
+        match self {
+            Self::Windowed => None,
+            Self::Borderless => Some(Fullscreen::Borderless(None)),
+            Self::BorderlessOn(monitor) => {
+                Some(Fullscreen::Borderless(Some(monitor.handle.clone())))
+            }
+            Self::Exclusive(video_mode) => Some(Fullscreen::Exclusive(video_mode.handle.clone())),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum CursorMode {
+    #[default]
+    Normal,
+    /// Invisible over the window, but free to leave it.
+    Hidden,
+    /// Invisible and kept in place, or confined to the window where the platform cannot lock it,
+    /// e.g. on X11. Mouse deltas then come from raw device motion, as for a first-person camera.
+    Locked,
+}
+
+#[derive(Clone)]
 pub struct ContextCreateInfo {
     pub application_name: CString,
     pub application_version: u32,
+    /// Vulkan version asked of the instance and required of the device, at least 1.3.
+    pub requested_api_version: u32,
+    /// Enabled on the instance besides the ones the engine needs, e.g. a capture layer.
+    pub extra_instance_layers: Vec<CString>,
+    pub extra_instance_extensions: Vec<CString>,
+    /// Enabled on the device besides the ones the engine needs, devices lacking a required one
+    /// are not selected.
+    pub extra_device_extensions: Vec<(CString, ExtensionRequirement)>,
+    /// Maps the near plane to depth 1.0 and the far plane to 0.0, for better depth precision.
+    pub reverse_z: bool,
+    pub swapchain_depth: DepthConfig,
+    pub swapchain_options: SwapchainOptions,
+    /// Devices lacking a required feature are not selected, see [`Context::enabled_features`] for
+    /// the optional ones.
+    pub requested_features: DeviceFeatureRequest,
+    /// Environment overrides, see [`EngineOverrides`], are applied on top of these.
+    pub tunables: EngineTunables,
+}
+
+impl ContextCreateInfo {
+    /// `name` must not contain NUL bytes. The version is packed the way Vulkan expects it.
+    pub fn new(name: &str, version: (u32, u32, u32)) -> Self {
+        let (major, minor, patch) = version;
+
+        Self {
+            application_name: CString::new(name).expect("application name should not contain NUL"),
+            application_version: vk::make_api_version(0, major, minor, patch),
+            requested_api_version: MIN_API_VERSION,
+            extra_instance_layers: vec![],
+            extra_instance_extensions: vec![],
+            extra_device_extensions: vec![],
+            reverse_z: false,
+            swapchain_depth: DepthConfig::default(),
+            swapchain_options: SwapchainOptions::default(),
+            requested_features: DeviceFeatureRequest::default(),
+            tunables: EngineTunables::default(),
+        }
+    }
+
+    pub fn with_api_version(mut self, major: u32, minor: u32) -> Self {
+        self.requested_api_version = vk::make_api_version(0, major, minor, 0);
+        self
+    }
+
+    /// `name` must not contain NUL bytes.
+    pub fn with_instance_layer(mut self, name: &str) -> Self {
+        self.extra_instance_layers
+            .push(CString::new(name).expect("layer name should not contain NUL"));
+        self
+    }
+
+    /// `name` must not contain NUL bytes.
+    pub fn with_instance_extension(mut self, name: &str) -> Self {
+        self.extra_instance_extensions
+            .push(CString::new(name).expect("extension name should not contain NUL"));
+        self
+    }
+
+    /// `name` must not contain NUL bytes, e.g. `"VK_KHR_push_descriptor"`.
+    pub fn with_device_extension(mut self, name: &str, requirement: ExtensionRequirement) -> Self {
+        self.extra_device_extensions.push((
+            CString::new(name).expect("extension name should not contain NUL"),
+            requirement,
+        ));
+        self
+    }
+
+    /// Shorthand for the validation of the [tunables](Self::with_tunables), which `MIEL_VALIDATION`
+    /// still overrides.
+    pub fn with_validation(mut self, validation: ValidationMode) -> Self {
+        self.tunables.validation = validation;
+        self
+    }
+
+    pub fn with_reverse_z(mut self, reverse_z: bool) -> Self {
+        self.reverse_z = reverse_z;
+        self
+    }
+
+    pub fn with_swapchain_depth(mut self, swapchain_depth: DepthConfig) -> Self {
+        self.swapchain_depth = swapchain_depth;
+        self
+    }
+
+    /// E.g. 3 for triple buffering, clamped to the image counts the surface supports.
+    pub fn with_swapchain_image_count(mut self, image_count: u32) -> Self {
+        self.swapchain_options.desired_image_count = Some(image_count);
+        self
+    }
+
+    /// E.g. [`SurfaceFormatPreference::hdr`] for HDR displays, whose selected format and color
+    /// space are given by [`Context::surface_properties`].
+    pub fn with_surface_formats(mut self, formats: SurfaceFormatPreference) -> Self {
+        self.swapchain_options.formats = formats;
+        self
+    }
+
+    pub fn with_composite_alpha(mut self, composite_alpha: CompositeAlphaPreference) -> Self {
+        self.swapchain_options.composite_alpha = composite_alpha;
+        self
+    }
+
+    /// Replaces the default request, whose features should be kept optional for line and
+    /// non-solid pipelines to keep working.
+    pub fn with_requested_features(mut self, requested_features: DeviceFeatureRequest) -> Self {
+        self.requested_features = requested_features;
+        self
+    }
+
+    /// Shorthand for the device selection of the [tunables](Self::with_tunables), which
+    /// `MIEL_DEVICE` still overrides.
+    pub fn with_device_selection(mut self, device: DeviceSelection) -> Self {
+        self.tunables.device = device;
+        self
+    }
+
+    /// Shorthand for the present preference of the [tunables](Self::with_tunables), which
+    /// `MIEL_PRESENT_MODE` still overrides.
+    pub fn with_present_preference(mut self, present_preference: PresentPreference) -> Self {
+        self.tunables.present_preference = present_preference;
+        self
+    }
+
+    pub fn with_tunables(mut self, tunables: EngineTunables) -> Self {
+        self.tunables = tunables;
+        self
+    }
+}
+
+/// Parses a `major.minor.patch` version, e.g. `env!("CARGO_PKG_VERSION")`, missing or invalid
+/// components being 0 and pre-release suffixes ignored.
+pub fn parse_version(version: &str) -> (u32, u32, u32) {
+    let mut components = version
+        .split(['.', '-', '+'])
+        .map(|component| component.parse::<u32>().unwrap_or(0))
+        .chain(std::iter::repeat(0));
+
+    (
+        components.next().unwrap_or(0),
+        components.next().unwrap_or(0),
+        components.next().unwrap_or(0),
+    )
 }
 
+/// Torn down by [`Self::destroy`], or when dropped, after waiting for the device to be idle:
+/// 1. frame hooks and listeners, which may capture resources
+/// 2. render graphs, deferred requests and deferred deletions
+/// 3. per-frame resources: staging belt, readbacks, breadcrumbs and frame constants
+/// 4. swapchain, then surface
+/// 5. command manager, allocator, device, debug messenger and instance
+///
+/// Buffers, images and meshes created from the context must be dropped before it, leaks are
+/// reported and fail a debug assertion.
 pub struct Context {
-    pub(crate) render_graph: RenderGraph,
+    pub(crate) render_graph: ManuallyDrop<RenderGraph>,
+    // bound at the start of the next frame, the current one may still be recorded against
+    pending_render_graph: Option<RenderGraph>,
+    // shared with resources reallocating their buffers outside of the context
+    pub(crate) deletion_queue: ThreadSafeRef<DeletionQueue>,
+    // filled by render passes, drained by a frame hook
+    deferred_resources: ThreadSafeRef<DeferredResourceQueue>,
 
-    pub(crate) command_manager: CommandManager,
-    pub(crate) swapchain: Swapchain,
+    // none while suspended, the summary is kept to size attachments and answer queries
+    presentation: Option<Presentation>,
+    swapchain_summary: SwapchainSummary,
+    // kept to create the presentation again when resumed
+    swapchain_options: SwapchainOptions,
+    // frames are skipped while the window has no area
+    resizes: ResizeCoalescer,
+    scale_factor: f64,
+    // of the monitor showing the window, when known
+    display_refresh_rate: Option<f32>,
+    pub(crate) pixel_readbacks: ManuallyDrop<PixelReadbackQueue>,
+    breadcrumbs: ManuallyDrop<Breadcrumbs>,
+    staging_belt: ManuallyDrop<StagingBelt>,
+    frame_limiter: FrameLimiter,
+    // the swapchain is recreated for it before the next frame
+    pending_present_preference: Option<PresentPreference>,
+    // the swapchain is recreated after presenting the frame
+    suboptimal_acquire: bool,
+    // the surface reported a 0x0 extent before the window did, frames are skipped like when
+    // minimized
+    surface_without_area: bool,
+    // window extent and surface transform of the last recreation for a suboptimal swapchain
+    suboptimal_recreation: Option<(vk::Extent2D, vk::SurfaceTransformFlagsKHR)>,
+    render_scale: f32,
+    render_scale_controller: Option<RenderScaleController>,
+    fullscreen_mode: FullscreenMode,
+    // applied to the window by the application once the state is done updating
+    pending_fullscreen_mode: Option<FullscreenMode>,
+    cursor_mode: CursorMode,
+    pending_cursor_mode: Option<CursorMode>,
+    // set by the application owning the event loop
+    event_proxy: Option<EventLoopProxy<UserEvent>>,
+    window_requests: WindowRequests,
+    // refreshed by the application whenever the window changes monitor
+    monitors: Vec<MonitorInfo>,
+    // files dragged over the window, in the order the platform reported them
+    hovered_files: Vec<PathBuf>,
+    presented_frames: u64,
+    frame_timings: FrameTimingHistory,
+    pub(crate) defragmenter: Defragmenter,
+    pub(crate) frame_constants: ManuallyDrop<FrameConstantsBlock>,
+    surface_listeners: Vec<(ListenerID, SurfaceChangeListener)>,
+    render_graph_listeners: Vec<(ListenerID, RenderGraphChangeListener)>,
+    next_listener_id: u64,
+    frame_hooks: ManuallyDrop<FrameHooks>,
 
-    pub(crate) allocator_ref: ThreadSafeRef<Allocator>,
+    pub(crate) core: ManuallyDrop<GpuCore>,
 
-    pub(crate) device_ref: ThreadSafeRwRef<Device>,
-    pub(crate) _physical_device: PhysicalDevice,
-    pub(crate) surface: Surface,
-    pub(crate) _du_messenger: Option<DUMessenger>,
-    pub(crate) instance: Instance,
-    pub(crate) _entry: ash::Entry,
+    pub(crate) reverse_z: bool,
+    pub(crate) tunables: EngineTunables,
+    overrides: EngineOverrides,
+    // kept to rebuild the context after a device loss
+    create_info: ContextCreateInfo,
+    // set by the first teardown, the context is then only dropped
+    torn_down: bool,
 }
 
 #[derive(Debug, Error)]
@@ -77,6 +377,12 @@ pub enum ContextCreateError {
 
     #[error("command manager creation failed")]
     CommandManagerCreation(#[from] CommandManagerCreateError),
+
+    #[error("requested Vulkan version {}.{} is older than the required 1.3", vk::api_version_major(*.0), vk::api_version_minor(*.0))]
+    UnsupportedApiVersion(u32),
+
+    #[error("frame constants block creation failed")]
+    FrameConstantsCreation(#[from] FrameConstantsCreateError),
 }
 
 #[derive(Debug, Error)]
@@ -90,136 +396,1985 @@ pub enum RenderError {
     #[error("image acquisition failed")]
     ImageAcquisition(#[from] NextImageAcquireError),
 
+    #[error("surface properties refresh failed")]
+    SurfaceSetup(#[from] DeviceSetupError),
+
     #[error("swapchain creation failed")]
     SwapchainCreation(#[from] SwapchainCreateError),
 
+    #[error("frame sync failed")]
+    FrameBegin(#[from] FrameBeginError),
+
+    #[error("frame constants upload failed")]
+    FrameConstantsUpload(#[from] BufferDataUploadError),
+
     #[error("render command execution failed")]
     RenderCommand(#[from] RenderCommandError),
 
     #[error("swapchain presentation failed")]
     SwapchainPresent(#[from] PresentError),
+
+    #[error("unable to get necessary handles from window")]
+    InvalidWindow(#[from] winit::raw_window_handle::HandleError),
+
+    #[error("surface recreation failed")]
+    SurfaceCreation(#[from] SurfaceCreateError),
+
+    #[error("render graph attachment resize failed")]
+    AttachmentResize(#[from] ImageBuildError),
+
+    /// Carries whatever diagnostics could be gathered, the context must be rebuilt with
+    /// [`Context::rebuild`] to keep rendering
+    #[error("device lost ({0})")]
+    DeviceLost(DeviceLostReport),
+}
+
+impl RenderError {
+    fn is_surface_lost(&self) -> bool {
+        let result = match self {
+            RenderError::ImageAcquisition(NextImageAcquireError::NextIndexAcquisition(result))
+            | RenderError::SurfaceSetup(
+                DeviceSetupError::PresentMoodeEnumeration(result)
+                | DeviceSetupError::FormatEnumeration(result),
+            )
+            | RenderError::SwapchainCreation(
+                SwapchainCreateError::CapabilitiesFetching(result)
+                | SwapchainCreateError::VulkanCreation(result),
+            )
+            | RenderError::SwapchainPresent(PresentError::Present(result)) => result,
+            _ => return false,
+        };
+
+        *result == vk::Result::ERROR_SURFACE_LOST_KHR
+    }
+
+    fn is_device_lost(&self) -> bool {
+        let result = match self {
+            RenderError::ImageAcquisition(NextImageAcquireError::NextIndexAcquisition(result))
+            | RenderError::FrameBegin(
+                FrameBeginError::UnsubmittedFrameRelease(result)
+                | FrameBeginError::FenceSync(result),
+            )
+            | RenderError::RenderCommand(
+                RenderCommandError::BatchSubmission(BatchSubmitError::Submission(result))
+                | RenderCommandError::FenceWaiting(result),
+            )
+            | RenderError::ImageAcquisition(NextImageAcquireError::OffscreenAcquisition(result))
+            | RenderError::SwapchainPresent(
+                PresentError::Present(result) | PresentError::OffscreenPresent(result),
+            ) => result,
+            RenderError::DeviceLost(_) => return true,
+            _ => return false,
+        };
+
+        *result == vk::Result::ERROR_DEVICE_LOST
+    }
 }
 
 impl Context {
     pub fn new(
         window: &Window,
         create_info: &ContextCreateInfo,
+    ) -> Result<Self, ContextCreateError> {
+        Self::create(window, create_info, None)
+    }
+
+    fn create(
+        window: &Window,
+        create_info: &ContextCreateInfo,
+        instance_objects: Option<InstanceObjects>,
     ) -> Result<Self, ContextCreateError> {
         let window_handle = window.window_handle()?.as_raw();
         let display_handle = window.display_handle()?.as_raw();
 
-        let vk_version = vk::make_api_version(0, 1, 3, 0);
+        let overrides = EngineOverrides::from_env();
+        let mut tunables = create_info.tunables.clone();
+        overrides.apply(&mut tunables);
 
-        // SAFETY: This is basically foreign code execution, and there is not way to properly ensure safety
-        // here. It is unfortunately an uncontrollable risk we must accept.
-        let entry = unsafe { ash::Entry::load() }?;
-        let instance = Instance::create(
-            &entry,
-            &create_info.application_name,
-            create_info.application_version,
-            vk_version,
+        let core = GpuCore::new(
+            create_info,
+            &tunables,
+            Some((display_handle, window_handle)),
+            instance_objects,
+        )?;
+        let window_size = window.inner_size();
+        let window_extent = vk::Extent2D {
+            width: window_size.width,
+            height: window_size.height,
+        };
+        let fullscreen_mode = FullscreenMode::from_window(window);
+        let presentation = Presentation::new(
+            &core,
             display_handle,
+            window_handle,
+            window_extent,
+            tunables.present_preference,
+            &create_info.swapchain_depth,
+            create_info.swapchain_options.clone(),
         )?;
-        let du_messenger = DUMessenger::create(&entry, &instance)?;
-        let mut surface = Surface::create(&entry, &instance, display_handle, window_handle)?;
-        let physical_device = PhysicalDevice::select(&instance, vk_version, &surface)?;
-        surface.setup_from_device(&physical_device)?;
-
-        // These reesources need to be stored as shared reeferences as they are often needed for
-        // destruction anbd thus have to be stored in every sub-resource.
-        let device_ref = ThreadSafeRwRef::new(Device::create(&instance, &physical_device)?);
-        let allocator_ref = ThreadSafeRef::new(Allocator::create(
-            &instance,
-            &physical_device,
-            &device_ref.read(),
-        )?);
 
-        let swapchain = Swapchain::new(
-            &instance,
-            device_ref.clone(),
-            &surface,
-            vk::Extent2D {
-                width: 1280,
-                height: 720,
-            },
-            allocator_ref.clone(),
+        let mut context = Self::with_presentation(
+            core,
+            presentation,
+            window_extent,
+            create_info,
+            tunables,
+            overrides,
         )?;
+        context.scale_factor = window.scale_factor();
+        context.display_refresh_rate = monitor_refresh_rate(window);
+        context.fullscreen_mode = fullscreen_mode;
+        context.monitors = monitor::available_monitors(window);
+
+        Ok(context)
+    }
+
+    /// Context rendering to an `R8G8B8A8_SRGB` image of the given extent rather than to a window,
+    /// e.g. to render in CI. Frames are rendered with [`Self::render_offscreen_frame`], and their
+    /// result is read back as [`ResourceID::SwapchainColorAttachment`] with [`Self::read_image`].
+    pub fn new_headless(
+        create_info: &ContextCreateInfo,
+        extent: vk::Extent2D,
+    ) -> Result<Self, ContextCreateError> {
+        Self::create_headless(create_info, extent, None)
+    }
+
+    fn create_headless(
+        create_info: &ContextCreateInfo,
+        extent: vk::Extent2D,
+        instance_objects: Option<InstanceObjects>,
+    ) -> Result<Self, ContextCreateError> {
+        let overrides = EngineOverrides::from_env();
+        let mut tunables = create_info.tunables.clone();
+        overrides.apply(&mut tunables);
+
+        let core = GpuCore::new(create_info, &tunables, None, instance_objects)?;
+        let presentation = Presentation::offscreen(&core, extent, &create_info.swapchain_depth)?;
+
+        Self::with_presentation(core, presentation, extent, create_info, tunables, overrides)
+    }
+
+    /// Destroys the context then creates it again from the same [`ContextCreateInfo`], e.g. after
+    /// a [`RenderError::DeviceLost`]. The instance is kept, the device and everything below it are
+    /// created again: anything created from the old context (buffers, images, pipelines, render
+    /// graphs...) must be dropped beforehand and created again afterwards. The event proxy, frame
+    /// rate limit and cursor mode are carried over, other runtime settings are reset.
+    pub fn rebuild(self, window: &Window) -> Result<Self, ContextCreateError> {
+        self.rebuild_with(|create_info, instance_objects| {
+            Self::create(window, create_info, instance_objects)
+        })
+    }
+
+    /// Same as [`Self::rebuild`] for contexts created with [`Self::new_headless`], keeping their
+    /// extent.
+    pub fn rebuild_headless(self) -> Result<Self, ContextCreateError> {
+        let extent = self.physical_extent();
+        self.rebuild_with(|create_info, instance_objects| {
+            Self::create_headless(create_info, extent, instance_objects)
+        })
+    }
+
+    fn rebuild_with(
+        mut self,
+        create: impl FnOnce(
+            &ContextCreateInfo,
+            Option<InstanceObjects>,
+        ) -> Result<Self, ContextCreateError>,
+    ) -> Result<Self, ContextCreateError> {
+        log::info!("rebuilding the graphics context");
+        let event_proxy = self.event_proxy.take();
+        let frame_rate_limit = self.frame_rate_limit();
+        let idle_defragmentation = self.idle_defragmentation();
+        // applied to the window, which outlives the context
+        let cursor_mode = self.cursor_mode;
+        let create_info = self.create_info.clone();
+
+        // a window only takes a single surface, the old one is destroyed before creating another
+        let instance_objects = self.teardown();
+        drop(self);
+
+        let mut context = create(&create_info, instance_objects)?;
+        context.event_proxy = event_proxy;
+        context.set_frame_rate_limit(frame_rate_limit);
+        context.set_idle_defragmentation(idle_defragmentation);
+        context.cursor_mode = cursor_mode;
+
+        Ok(context)
+    }
 
-        let command_manager = CommandManager::try_new(device_ref.clone())?;
+    fn with_presentation(
+        core: GpuCore,
+        presentation: Presentation,
+        extent: vk::Extent2D,
+        create_info: &ContextCreateInfo,
+        tunables: EngineTunables,
+        overrides: EngineOverrides,
+    ) -> Result<Self, ContextCreateError> {
+        let breadcrumbs = Breadcrumbs::new(&core.device_ref, &core.allocator_ref);
+        let frame_constants = FrameConstantsBlock::new(&core.device_ref, &core.allocator_ref)?;
+
+        let deferred_resources = ThreadSafeRef::new(DeferredResourceQueue::default());
+        let mut frame_hooks = FrameHooks::new();
+        {
+            let deferred_resources = deferred_resources.clone();
+            let (device_ref, allocator_ref) = (core.device_ref.clone(), core.allocator_ref.clone());
+            frame_hooks.add(
+                FrameStage::AfterSubmit,
+                Box::new(move |_| {
+                    deferred_resources
+                        .lock()
+                        .create_pending(&device_ref, &allocator_ref)
+                }),
+            );
+        }
 
         Ok(Self {
-            render_graph: RenderGraph::empty(),
+            render_graph: ManuallyDrop::new(RenderGraph::empty()),
+            pending_render_graph: None,
+            deletion_queue: ThreadSafeRef::new(DeletionQueue::default()),
+            deferred_resources,
 
-            command_manager,
-            swapchain,
+            swapchain_summary: presentation.summary(),
+            swapchain_options: create_info.swapchain_options.clone(),
+            presentation: Some(presentation),
+            resizes: ResizeCoalescer::new(extent),
+            scale_factor: 1.0,
+            display_refresh_rate: None,
+            pixel_readbacks: ManuallyDrop::new(PixelReadbackQueue::default()),
+            breadcrumbs: ManuallyDrop::new(breadcrumbs),
+            staging_belt: ManuallyDrop::new(StagingBelt::new(
+                core.device_ref.clone(),
+                core.allocator_ref.clone(),
+            )),
+            frame_limiter: FrameLimiter::new(),
+            render_scale: MAX_RENDER_SCALE,
+            render_scale_controller: None,
+            fullscreen_mode: FullscreenMode::Windowed,
+            pending_fullscreen_mode: None,
+            cursor_mode: CursorMode::Normal,
+            pending_cursor_mode: None,
+            pending_present_preference: None,
+            suboptimal_acquire: false,
+            surface_without_area: false,
+            suboptimal_recreation: None,
+            event_proxy: None,
+            window_requests: WindowRequests::default(),
+            monitors: vec![],
+            hovered_files: vec![],
+            presented_frames: 0,
+            frame_timings: FrameTimingHistory::default(),
+            defragmenter: Defragmenter::default(),
+            frame_constants: ManuallyDrop::new(frame_constants),
+            surface_listeners: vec![],
+            render_graph_listeners: vec![],
+            next_listener_id: 0,
+            frame_hooks: ManuallyDrop::new(frame_hooks),
 
-            allocator_ref,
+            core: ManuallyDrop::new(core),
 
-            device_ref,
-            _physical_device: physical_device,
-            surface,
-            _du_messenger: du_messenger,
-            instance,
-            _entry: entry,
+            reverse_z: create_info.reverse_z,
+            tunables,
+            overrides,
+            create_info: create_info.clone(),
+            torn_down: false,
         })
     }
 
+    /// The graph replaces the current one at the start of the next frame, binding several graphs
+    /// before that only keeps the last one.
     pub fn bind_rendergraph(&mut self, info: RenderGraphInfo) -> Result<(), RenderGraphBindError> {
         let new_rendergraph = RenderGraph::new(info, self)?;
-        self.render_graph = new_rendergraph;
+        // never rendered, nothing can reference it
+        self.pending_render_graph = Some(new_rendergraph);
 
         Ok(())
     }
 
-    pub(crate) fn render_frame(&mut self, window: &Window) -> Result<(), RenderError> {
-        unsafe {
-            self.device_ref
-                .read()
-                .wait_for_fences(&[self.swapchain.present_fence], true, u64::MAX)
-        }
-        .map_err(RenderCommandError::FenceSync)?;
-        unsafe {
-            self.device_ref
-                .read()
-                .reset_fences(&[self.swapchain.present_fence])
+    fn swap_pending_render_graph(&mut self) {
+        let Some(new_rendergraph) = self.pending_render_graph.take() else {
+            return;
+        };
+
+        let diff = self.render_graph.diff(&new_rendergraph);
+        diff.log();
+        if !diff.is_empty() {
+            for (_, listener) in &mut self.render_graph_listeners {
+                listener(&diff);
+            }
         }
-        .map_err(RenderCommandError::FenceReset)?;
 
-        match self.swapchain.next_image()? {
-            NextImageState::OutOfDate => {
-                log::warn!("swapchain is out of date, recreating");
+        // the frame in flight may still be using the previous graph's attachments
+        let previous_rendergraph = std::mem::replace(&mut *self.render_graph, new_rendergraph);
+        self.deletion_queue.lock().defer(previous_rendergraph);
+    }
 
-                // recreate and try again next frame
-                self.swapchain = Swapchain::new(
-                    &self.instance,
-                    self.device_ref.clone(),
-                    &self.surface,
-                    self.swapchain.extent,
-                    self.allocator_ref.clone(),
-                )?;
+    pub fn is_reverse_z(&self) -> bool {
+        self.reverse_z
+    }
 
-                return Ok(());
-            }
-            NextImageState::Suboptimal => {
-                log::debug!("acquired image is suboptimal");
-            }
-            _ => (),
+    /// Features enabled on the device, which
+    /// [`DeviceFeature::is_set`](super::features::DeviceFeature::is_set) checks, e.g. to fall back
+    /// when an optional one is missing.
+    pub fn enabled_features(&self) -> vk::PhysicalDeviceFeatures {
+        self.core.device_ref.read().enabled_features
+    }
+
+    /// Whether an extension, requested or enabled by the engine itself, is enabled on the device.
+    pub fn is_device_extension_enabled(&self, name: &CStr) -> bool {
+        self.core
+            .device_ref
+            .read()
+            .enabled_extensions
+            .iter()
+            .any(|extension| extension.as_c_str() == name)
+    }
+
+    /// Tunables overridden from the environment when the context was created, worth including in
+    /// bug reports.
+    pub fn active_overrides(&self) -> &EngineOverrides {
+        &self.overrides
+    }
+
+    /// Properties of the last swapchain while the context is suspended.
+    pub fn surface_properties(&self) -> SurfaceProperties {
+        self.swapchain_summary.properties
+    }
+
+    /// Rotation to apply after the projection, `pre_rotation * projection`, for the rendered
+    /// images to be presented without the compositor rotating them. The aspect ratio of the
+    /// projection is the one of [`SurfaceProperties::display_extent`].
+    pub fn pre_rotation(&self) -> Mat4 {
+        let transform = self.swapchain_summary.properties.transform;
+        let quarter_turns = if transform.intersects(
+            vk::SurfaceTransformFlagsKHR::ROTATE_90
+                | vk::SurfaceTransformFlagsKHR::HORIZONTAL_MIRROR_ROTATE_90,
+        ) {
+            1.0
+        } else if transform.intersects(
+            vk::SurfaceTransformFlagsKHR::ROTATE_180
+                | vk::SurfaceTransformFlagsKHR::HORIZONTAL_MIRROR_ROTATE_180,
+        ) {
+            2.0
+        } else if transform.intersects(
+            vk::SurfaceTransformFlagsKHR::ROTATE_270
+                | vk::SurfaceTransformFlagsKHR::HORIZONTAL_MIRROR_ROTATE_270,
+        ) {
+            3.0
+        } else {
+            0.0
         };
 
-        self.command_manager.render_command(
-            &mut self.swapchain,
-            |cmd_buffer, current_image_resources| {
-                self.render_graph
-                    .render(current_image_resources, cmd_buffer, &self.device_ref)?;
+        Mat4::from_rotation_z(quarter_turns * std::f32::consts::FRAC_PI_2)
+    }
 
-                Ok(())
-            },
-        )?;
+    pub(crate) fn swapchain_extent(&self) -> vk::Extent2D {
+        self.swapchain_summary.properties.extent
+    }
+
+    pub fn is_suspended(&self) -> bool {
+        self.presentation.is_none()
+    }
+
+    /// Destroys the surface and the swapchain, e.g. when the application is suspended, keeping
+    /// the device and everything created from the context. Frames are skipped until resumed.
+    pub(crate) fn suspend(&mut self) {
+        if let Some(presentation) = self.presentation.take() {
+            log::debug!("suspending, destroying presentation");
+            drop(presentation);
+        }
+    }
 
-        window.pre_present_notify();
+    /// Creates a surface and a swapchain for the window again, after [`Self::suspend`].
+    pub(crate) fn resume(&mut self, window: &Window) -> Result<(), RenderError> {
+        if self.presentation.is_some() {
+            return Ok(());
+        }
+
+        log::debug!("resuming, creating presentation");
+        let window_handle = window.window_handle()?.as_raw();
+        let display_handle = window.display_handle()?.as_raw();
+        let window_size = window.inner_size();
+        let window_extent = vk::Extent2D {
+            width: window_size.width,
+            height: window_size.height,
+        };
 
-        self.swapchain.present()?;
+        let previous_properties = self.swapchain_summary.properties;
+        self.presentation = Some(Presentation::with_depth_format::<RenderError>(
+            &self.core,
+            display_handle,
+            window_handle,
+            window_extent,
+            self.tunables.present_preference,
+            self.swapchain_summary.depth_format,
+            self.swapchain_options.clone(),
+        )?);
+        self.resizes.mark_applied(window_extent);
+        self.notify_surface_changed(previous_properties)?;
 
         Ok(())
     }
+
+    /// Registers a callback run after every swapchain recreation that changed the surface
+    /// properties, e.g. to rebuild pipelines created against the previous format.
+    pub fn add_surface_listener(&mut self, listener: SurfaceChangeListener) -> ListenerID {
+        let id = self.next_listener_id();
+        self.surface_listeners.push((id, listener));
+
+        id
+    }
+
+    /// Registers a callback run when binding a render graph whose attachments differ from the
+    /// previous one, e.g. to rebuild pipelines targeting a removed or reformatted attachment.
+    pub fn add_render_graph_listener(&mut self, listener: RenderGraphChangeListener) -> ListenerID {
+        let id = self.next_listener_id();
+        self.render_graph_listeners.push((id, listener));
+
+        id
+    }
+
+    pub fn remove_listener(&mut self, id: ListenerID) {
+        self.surface_listeners
+            .retain(|(listener_id, _)| *listener_id != id);
+        self.render_graph_listeners
+            .retain(|(listener_id, _)| *listener_id != id);
+    }
+
+    /// Registers a callback run at `stage` of every rendered frame, after the hooks previously
+    /// registered for the same stage. Stages following [`FrameStage::AfterFenceWait`] are skipped
+    /// for frames abandoned to recreate the swapchain.
+    pub fn add_frame_hook(&mut self, stage: FrameStage, hook: FrameHook) -> HookId {
+        self.frame_hooks.add(stage, hook)
+    }
+
+    pub fn remove_frame_hook(&mut self, id: HookId) {
+        self.frame_hooks.remove(id);
+    }
+
+    fn next_listener_id(&mut self) -> ListenerID {
+        let id = ListenerID(self.next_listener_id);
+        self.next_listener_id += 1;
+
+        id
+    }
+
+    /// Records the new size of the window, applied by [`Self::apply_pending_resize`].
+    pub(crate) fn resize(&mut self, window_extent: vk::Extent2D) {
+        self.resizes.request(window_extent);
+    }
+
+    /// Recreates the swapchain for the latest size of the window, along with the attachments sized
+    /// after it, and returns that size. Rendering is skipped while either dimension is 0, e.g.
+    /// when minimized.
+    pub(crate) fn apply_pending_resize(&mut self) -> Result<Option<vk::Extent2D>, RenderError> {
+        let Some(window_extent) = self.resizes.pending() else {
+            return Ok(None);
+        };
+
+        if window_extent.width == 0 || window_extent.height == 0 {
+            log::debug!("window has no area, skipping frames until it is resized");
+        } else {
+            self.recreate_swapchain(window_extent)?;
+        }
+        self.resizes.mark_applied(window_extent);
+
+        Ok(Some(window_extent))
+    }
+
+    /// Physical pixels per logical point of the monitor the window is on, e.g. 2.0 at 200%.
+    pub fn scale_factor(&self) -> f64 {
+        self.scale_factor
+    }
+
+    pub(crate) fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.scale_factor = scale_factor;
+    }
+
+    /// Latest size of the inside of the window in pixels, which the swapchain is created for.
+    pub fn physical_extent(&self) -> vk::Extent2D {
+        self.resizes.pending().unwrap_or(self.resizes.applied())
+    }
+
+    /// [`Self::physical_extent`] in points, e.g. to lay out UI independently of the monitor scale.
+    pub fn logical_extent(&self) -> (f64, f64) {
+        let physical_extent = self.physical_extent();
+
+        (
+            f64::from(physical_extent.width) / self.scale_factor,
+            f64::from(physical_extent.height) / self.scale_factor,
+        )
+    }
+
+    /// Frames rendered before the one being prepared, which render passes find in their
+    /// [`FrameInfo`] while recording it.
+    pub fn frame_index(&self) -> u64 {
+        self.core
+            .command_manager
+            .frame_counter
+            .load(Ordering::Acquire)
+    }
+
+    /// Slot of the frame being prepared among the [`frames_in_flight`](Self::frames_in_flight)
+    /// ones.
+    pub fn frame_slot(&self) -> usize {
+        self.core.command_manager.frame_slot().as_usize()
+    }
+
+    /// Frames recorded or executed at once, set by
+    /// [`EngineTunables::frames_in_flight`](super::overrides::EngineTunables::frames_in_flight).
+    pub fn frames_in_flight(&self) -> usize {
+        self.core.command_manager.frames_in_flight
+    }
+
+    /// Frames rendered and presented since the context was created, skipped frames excluded.
+    pub fn presented_frame_count(&self) -> u64 {
+        self.presented_frames
+    }
+
+    pub fn resize_stats(&self) -> ResizeStats {
+        self.resizes.stats()
+    }
+
+    /// Timings of the last rendered frames, see [`FrameTimingHistory`].
+    pub fn frame_stats(&self) -> &FrameTimingHistory {
+        &self.frame_timings
+    }
+
+    /// Switches the window between windowed and fullscreen once the current update returns, the
+    /// swapchain being recreated on the resize that follows. Going back to windowed restores the
+    /// size and position the window had before.
+    pub fn set_fullscreen(&mut self, mode: FullscreenMode) {
+        if mode == self.fullscreen_mode {
+            self.pending_fullscreen_mode = None;
+            return;
+        }
+
+        self.pending_fullscreen_mode = Some(mode);
+    }
+
+    /// Mode the window is in, or will be in once the pending change is applied.
+    pub fn fullscreen_mode(&self) -> &FullscreenMode {
+        self.pending_fullscreen_mode
+            .as_ref()
+            .unwrap_or(&self.fullscreen_mode)
+    }
+
+    pub(crate) fn take_fullscreen_request(&mut self) -> Option<FullscreenMode> {
+        let mode = self.pending_fullscreen_mode.take()?;
+        self.fullscreen_mode = mode.clone();
+
+        Some(mode)
+    }
+
+    /// Monitors connected when the window was created or last moved to another monitor, to pick
+    /// one for [`FullscreenMode::BorderlessOn`] or [`FullscreenMode::Exclusive`]. Empty for
+    /// headless contexts.
+    pub fn monitors(&self) -> &[MonitorInfo] {
+        &self.monitors
+    }
+
+    pub(crate) fn update_monitors(&mut self, window: &Window) {
+        self.monitors = monitor::available_monitors(window);
+    }
+
+    /// Recreates the swapchain with the new preference before the next frame, see
+    /// [`present_mode`](Self::present_mode) for the mode it resolved to once applied.
+    pub fn set_present_mode(&mut self, present_preference: PresentPreference) {
+        if present_preference == self.tunables.present_preference {
+            return;
+        }
+
+        // also used by surfaces created later on, e.g. when resumed
+        self.tunables.present_preference = present_preference;
+        self.pending_present_preference = Some(present_preference);
+    }
+
+    pub fn present_preference(&self) -> PresentPreference {
+        self.tunables.present_preference
+    }
+
+    /// Mode the swapchain presents with, `None` when there is no surface, e.g. when rendering
+    /// offscreen or while suspended.
+    pub fn present_mode(&self) -> Option<vk::PresentModeKHR> {
+        self.presentation
+            .as_ref()?
+            .surface
+            .as_ref()
+            .map(|surface| surface.present_mode)
+    }
+
+    fn apply_present_preference(&mut self) -> Result<(), RenderError> {
+        let Some(present_preference) = self.pending_present_preference.take() else {
+            return Ok(());
+        };
+        let Some(presentation) = self.presentation.as_mut() else {
+            return Ok(());
+        };
+
+        log::info!("present preference changed to {present_preference:?}");
+        presentation.present_preference = present_preference;
+        self.recreate_swapchain(self.resizes.applied())
+    }
+
+    /// Applied to the window once the current update returns, and again whenever the window
+    /// regains the focus.
+    pub fn set_cursor_mode(&mut self, mode: CursorMode) {
+        if mode == self.cursor_mode {
+            self.pending_cursor_mode = None;
+            return;
+        }
+
+        self.pending_cursor_mode = Some(mode);
+    }
+
+    /// Mode the cursor is in, or will be in once the pending change is applied.
+    pub fn cursor_mode(&self) -> CursorMode {
+        self.pending_cursor_mode.unwrap_or(self.cursor_mode)
+    }
+
+    pub(crate) fn take_cursor_mode_request(&mut self) -> Option<CursorMode> {
+        let mode = self.pending_cursor_mode.take()?;
+        self.cursor_mode = mode;
+
+        Some(mode)
+    }
+
+    /// Title and attributes of the window, changed once the current update returns. Changes made
+    /// to headless contexts are ignored.
+    pub fn window(&mut self) -> WindowControl<'_> {
+        WindowControl::new(&mut self.window_requests)
+    }
+
+    pub(crate) fn apply_window_requests(&mut self, window: &Window) {
+        self.window_requests.apply(window);
+    }
+
+    /// Handle to post events to the application from other threads, see [`EventProxy`]. `None` for
+    /// contexts not created by an [`Application`](crate::application::Application).
+    pub fn event_proxy<T: Send + 'static>(&self) -> Option<EventProxy<T>> {
+        self.event_proxy.clone().map(EventProxy::new)
+    }
+
+    pub(crate) fn set_event_proxy(&mut self, proxy: EventLoopProxy<UserEvent>) {
+        self.event_proxy = Some(proxy);
+    }
+
+    /// Files being dragged over the window, e.g. to highlight a drop target while updating.
+    /// Empty once they are dropped or dragged away.
+    pub fn hovered_files(&self) -> &[PathBuf] {
+        &self.hovered_files
+    }
+
+    pub fn is_file_hovered(&self) -> bool {
+        !self.hovered_files.is_empty()
+    }
+
+    pub(crate) fn add_hovered_file(&mut self, path: PathBuf) {
+        self.hovered_files.push(path);
+    }
+
+    pub(crate) fn remove_hovered_file(&mut self, path: &Path) {
+        self.hovered_files.retain(|hovered| hovered != path);
+    }
+
+    pub(crate) fn clear_hovered_files(&mut self) {
+        self.hovered_files.clear();
+    }
+
+    fn is_minimized(&self) -> bool {
+        let window_extent = self.resizes.applied();
+        window_extent.width == 0 || window_extent.height == 0 || self.surface_without_area
+    }
+
+    fn recreate_swapchain(&mut self, window_extent: vk::Extent2D) -> Result<(), RenderError> {
+        // created for the latest size when resumed
+        let Some(presentation) = self.presentation.as_mut() else {
+            return Ok(());
+        };
+
+        let previous_properties = self.swapchain_summary.properties;
+        match presentation.recreate_swapchain(&self.core, window_extent) {
+            // the previous swapchain is kept, frames are skipped until the surface has an area
+            Err(RenderError::SwapchainCreation(SwapchainCreateError::NoArea)) => {
+                if !self.surface_without_area {
+                    log::debug!("surface has no area, skipping frames until it has one");
+                }
+                self.surface_without_area = true;
+                return Ok(());
+            }
+            result => result?,
+        }
+        self.surface_without_area = false;
+        self.resizes.record_recreation();
+        self.notify_surface_changed(previous_properties)?;
+
+        Ok(())
+    }
+
+    fn notify_surface_changed(
+        &mut self,
+        previous_properties: SurfaceProperties,
+    ) -> Result<(), RenderError> {
+        if let Some(presentation) = self.presentation.as_ref() {
+            self.swapchain_summary = presentation.summary();
+        }
+        let new_properties = self.swapchain_summary.properties;
+
+        // done on every recreation rather than on changes only, attachments already at the right
+        // size are kept and ones a previous failed resize left behind catch up
+        let ctx_refs = (&self.core.device_ref, &self.core.allocator_ref);
+        for render_graph in
+            std::iter::once(&mut *self.render_graph).chain(&mut self.pending_render_graph)
+        {
+            render_graph.resize_swapchain_dependent(
+                new_properties.extent,
+                ctx_refs,
+                &self.deletion_queue,
+            )?;
+        }
+
+        if new_properties != previous_properties {
+            log::debug!("surface properties changed to {new_properties:?}");
+
+            self.render_graph
+                .notify_surface_changed(&new_properties, &self.core.device_ref);
+            for (_, listener) in &mut self.surface_listeners {
+                listener(&new_properties);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Validation messages logged since the last frame started rendering, `None` when validation
+    /// is disabled.
+    pub fn validation_stats(&self) -> Option<ValidationStats> {
+        self.core
+            .validation_messenger()
+            .map(|messenger| messenger.stats())
+    }
+
+    /// Describes the passes of the bound render graph as of the last rendered frame.
+    pub fn render_graph_info(&self) -> RenderGraphSnapshot {
+        let depth_format = self
+            .swapchain_depth_format()
+            .unwrap_or(vk::Format::UNDEFINED);
+
+        self.render_graph.snapshot(
+            self.swapchain_summary.properties.format,
+            self.swapchain_unorm_format(),
+            depth_format,
+        )
+    }
+
+    /// Format of the view of [`ResourceID::SwapchainColorAttachmentUnorm`], which pipelines drawing
+    /// to it must output. It only differs from the swapchain format when the device supports
+    /// viewing swapchain images as UNORM and the surface format is sRGB.
+    pub fn swapchain_unorm_format(&self) -> vk::Format {
+        let summary = &self.swapchain_summary;
+        summary.unorm_format.unwrap_or(summary.properties.format)
+    }
+
+    /// Format of [`ResourceID::SwapchainDSAttachment`], `None` when the swapchain depth is
+    /// [disabled](DepthConfig::Disabled).
+    pub fn swapchain_depth_format(&self) -> Option<vk::Format> {
+        self.swapchain_summary.depth_format
+    }
+
+    /// Uploads written to the belt are copied at the start of the next rendered frame.
+    pub fn staging_belt(&mut self) -> &mut StagingBelt {
+        &mut self.staging_belt
+    }
+
+    /// When each submission batch of the last frame was handed to the queue, in batch order.
+    pub fn last_frame_submit_times(&self) -> &[Instant] {
+        &self.core.command_manager.last_frame_submit_times
+    }
+
+    /// Constants uploaded at the start of every following frame, until replaced. `extent` and
+    /// `frame_index` are overwritten by the engine.
+    pub fn set_frame_constants(&mut self, constants: FrameConstants) {
+        self.frame_constants.constants = constants;
+    }
+
+    pub fn frame_constants(&self) -> &FrameConstants {
+        &self.frame_constants.constants
+    }
+
+    /// Layout of the frame constants descriptor set, bound at
+    /// [`FRAME_CONSTANTS_SET`](super::frame_constants::FRAME_CONSTANTS_SET).
+    pub fn frame_constants_layout(&self) -> vk::DescriptorSetLayout {
+        self.frame_constants.set_layout
+    }
+
+    /// Caps the number of frames rendered per second, measured from the start of the previous
+    /// frame. Ignored while presenting with FIFO at a refresh rate already at or below the cap.
+    /// `None` or a non-positive rate removes the cap.
+    pub fn set_frame_rate_limit(&mut self, frame_rate: Option<f32>) {
+        let frame_rate =
+            frame_rate.filter(|&frame_rate| frame_rate.is_finite() && frame_rate > 0.0);
+        self.frame_limiter.set_limit(frame_rate);
+    }
+
+    pub fn frame_rate_limit(&self) -> Option<f32> {
+        self.frame_limiter.limit()
+    }
+
+    /// Time between the starts of the last two rendered frames, including the frame rate limiter
+    /// wait.
+    pub fn last_frame_interval(&self) -> Duration {
+        self.frame_limiter.last_frame_interval()
+    }
+
+    /// Time spent rendering the last frame, from its start to its presentation, which blocks
+    /// while waiting for a vblank with FIFO.
+    pub fn last_frame_duration(&self) -> Duration {
+        self.frame_limiter.last_frame_duration()
+    }
+
+    /// When the event loop should wake up to render the next frame, `None` if it is not limited.
+    pub(crate) fn next_frame_wake_time(&self) -> Option<Instant> {
+        self.frame_limiter.wake_time()
+    }
+
+    pub(crate) fn update_display_refresh_rate(&mut self, window: &Window) {
+        self.display_refresh_rate = monitor_refresh_rate(window);
+        self.update_vsync_refresh_rate();
+    }
+
+    fn update_vsync_refresh_rate(&mut self) {
+        let vsync = self.presentation.as_ref().is_some_and(|presentation| {
+            matches!(
+                presentation
+                    .surface
+                    .as_ref()
+                    .map(|surface| surface.present_mode),
+                Some(vk::PresentModeKHR::FIFO | vk::PresentModeKHR::FIFO_RELAXED)
+            )
+        });
+        self.frame_limiter
+            .set_vsync_refresh_rate(self.display_refresh_rate.filter(|_| vsync));
+    }
+
+    /// Multiplies the size of [`SwapchainRelative`](super::render_graph::resource::AttachmentSize::SwapchainRelative)
+    /// attachments, which are the only ones recreated when it changes. Clamped between
+    /// [`MIN_RENDER_SCALE`](super::render_scale::MIN_RENDER_SCALE) and [`MAX_RENDER_SCALE`].
+    pub fn set_render_scale(&mut self, render_scale: f32) -> Result<(), ImageBuildError> {
+        let render_scale = clamp_render_scale(render_scale);
+        if render_scale == self.render_scale {
+            return Ok(());
+        }
+
+        log::debug!("render scale changed to {render_scale}");
+        self.render_scale = render_scale;
+        let swapchain_extent = self.swapchain_extent();
+        let ctx_refs = (&self.core.device_ref, &self.core.allocator_ref);
+        for render_graph in
+            std::iter::once(&mut *self.render_graph).chain(&mut self.pending_render_graph)
+        {
+            render_graph.set_render_scale(
+                render_scale,
+                swapchain_extent,
+                ctx_refs,
+                &self.deletion_queue,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn render_scale(&self) -> f32 {
+        self.render_scale
+    }
+
+    /// Lets a [`RenderScaleController`] adjust the render scale before every frame, based on the
+    /// [last frame interval](Self::last_frame_interval). The target should be above the interval
+    /// enforced by the present mode and the frame rate limit, or the scale only ever decreases.
+    /// `None` stops the adjustments, keeping the current scale.
+    pub fn auto_scale(&mut self, target_frame_ms: Option<f32>) {
+        self.render_scale_controller = target_frame_ms
+            .filter(|&target_frame_ms| target_frame_ms.is_finite() && target_frame_ms > 0.0)
+            .map(RenderScaleController::new);
+    }
+
+    /// Tears the context down in the order documented on [`Context`], as dropping it does.
+    pub fn destroy(self) {
+        drop(self);
+    }
+
+    /// Waits for every submitted frame to complete, failures are only logged as there is nothing
+    /// left to do about them.
+    pub(crate) fn wait_idle(&self) {
+        if let Err(err) = unsafe { self.core.device_ref.read().device_wait_idle() } {
+            log::error!("waiting for the device to be idle failed: {err}");
+        }
+    }
+
+    /// Hands back the instance objects to create another device from, `None` when they were
+    /// leaked or the context was already torn down.
+    fn teardown(&mut self) -> Option<InstanceObjects> {
+        if std::mem::replace(&mut self.torn_down, true) {
+            return None;
+        }
+
+        log::debug!("waiting for the device to be idle before destroying the context");
+        self.wait_idle();
+
+        // SAFETY: this only runs once, when dropping or rebuilding the context, every
+        // `ManuallyDrop` field is dropped exactly once and never used afterwards
+        log::debug!("destroying frame hooks and listeners");
+        self.surface_listeners.clear();
+        self.render_graph_listeners.clear();
+        unsafe { ManuallyDrop::drop(&mut self.frame_hooks) };
+
+        log::debug!("destroying render graphs and pending deletions");
+        self.pending_render_graph = None;
+        unsafe { ManuallyDrop::drop(&mut self.render_graph) };
+        *self.deferred_resources.lock() = DeferredResourceQueue::default();
+        self.deletion_queue.lock().clear();
+
+        log::debug!("destroying frame resources");
+        unsafe {
+            ManuallyDrop::drop(&mut self.staging_belt);
+            ManuallyDrop::drop(&mut self.pixel_readbacks);
+            ManuallyDrop::drop(&mut self.breadcrumbs);
+            ManuallyDrop::drop(&mut self.frame_constants);
+        }
+
+        log::debug!("destroying presentation");
+        self.presentation = None;
+
+        log::debug!("destroying GPU core");
+        unsafe { ManuallyDrop::take(&mut self.core) }.destroy()
+    }
+
+    /// Command buffers recorded by the application, from the pool of the calling thread, see
+    /// [`CommandBufferAllocator::allocate`].
+    pub fn allocate_command_buffers(
+        &self,
+        level: vk::CommandBufferLevel,
+        count: u32,
+    ) -> Result<Vec<OwnedCommandBuffer>, CommandBufferAllocateError> {
+        self.core
+            .command_manager
+            .command_buffer_allocator()
+            .allocate(level, count)
+    }
+
+    /// Allocator to send to worker threads recording command buffers in parallel.
+    pub fn command_buffer_allocator(&self) -> CommandBufferAllocator {
+        self.core.command_manager.command_buffer_allocator()
+    }
+
+    /// See [`CommandBufferAllocator::submit`].
+    pub fn submit_commands(
+        &self,
+        cmd_buffers: &[OwnedCommandBuffer],
+        sync: SubmitSync,
+    ) -> Result<(), CommandSubmitError> {
+        self.core
+            .command_manager
+            .command_buffer_allocator()
+            .submit(cmd_buffers, &sync)
+    }
+
+    pub fn allocation_report(&self) -> AllocationReport {
+        self.core.allocator_ref.lock().report()
+    }
+
+    /// How fragmented the memory blocks of the allocator are, see [`DEFAULT_SPARSE_THRESHOLD`].
+    pub fn defrag_stats(&self) -> DefragStats {
+        let blocks = self.core.allocator_ref.lock().block_occupancy();
+        DefragStats::from_blocks(&blocks, DEFAULT_SPARSE_THRESHOLD)
+    }
+
+    /// Allocations to move to release the sparsest blocks, within `budget_bytes`. Only
+    /// [relocatable buffers](defrag::RelocatableBuffer) are moved by [`Self::defragment_now`],
+    /// the owners of the other allocations are the ones able to recreate them elsewhere.
+    pub fn defrag_candidates(&self, budget_bytes: u64) -> Vec<DefragCandidate> {
+        let blocks = self.core.allocator_ref.lock().block_occupancy();
+        defrag::select_candidates(&blocks, DEFAULT_SPARSE_THRESHOLD, budget_bytes)
+    }
+
+    /// Moves the [relocatable buffers](defrag::RelocatableBuffer) among the
+    /// [candidates](Self::defrag_candidates) within `budget_bytes` to denser blocks, copying them
+    /// on the transfer queue. Waits for the device to be idle first when anything can move, and
+    /// the sparse blocks are released once the frames in flight are done with the previous
+    /// buffers.
+    pub fn defragment_now(&mut self, budget_bytes: u64) -> Result<DefragReport, DefragError> {
+        self.defragmenter
+            .defragment(budget_bytes, &self.core, &self.deletion_queue)
+    }
+
+    /// Runs [`Self::defragment_now`] after each frame recorded and presented within the time
+    /// given by `idle`, `None` disables it.
+    pub fn set_idle_defragmentation(&mut self, idle: Option<IdleDefragmentation>) {
+        self.defragmenter.idle = idle;
+    }
+
+    pub fn idle_defragmentation(&self) -> Option<IdleDefragmentation> {
+        self.defragmenter.idle
+    }
+
+    /// Number of buffers, images, image views, semaphores and fences created by the engine and not
+    /// destroyed yet. Always 0 in release builds, where they are not tracked.
+    pub fn live_vulkan_object_count(&self) -> usize {
+        self.core.device_ref.read().handle_registry.live_count()
+    }
+
+    /// Schedules a copy of the pixel at (`x`, `y`) of the given resource, taken after the last pass
+    /// writing to it during the next rendered frame. The resource must have been created with
+    /// `TRANSFER_SRC` usage.
+    pub fn read_pixel(
+        &mut self,
+        resource: ResourceID,
+        x: u32,
+        y: u32,
+    ) -> Result<PixelReadback, PixelReadError> {
+        let image_state = self.readable_image_state(resource)?;
+        let (extent, format) = (image_state.extent_2d, image_state.format);
+
+        self.pixel_readbacks
+            .request(resource, (x, y), extent, format)
+    }
+
+    /// Schedules a copy of the whole resource, taken like [`Self::read_pixel`] does.
+    pub fn read_image(&mut self, resource: ResourceID) -> Result<ImageReadback, PixelReadError> {
+        let image_state = self.readable_image_state(resource)?;
+        let (extent, format) = (image_state.extent_2d, image_state.format);
+
+        self.pixel_readbacks.request_image(resource, extent, format)
+    }
+
+    /// Schedules a copy of the next rendered frame as presented, taken once every pass is done
+    /// with the swapchain color image. [`ImageData::to_rgba8`](super::readback::ImageData::to_rgba8)
+    /// and `save_png` then convert it from the surface format. Fails with
+    /// [`PixelReadError::NotTransferSource`] on surfaces not allowing swapchain images to be copied
+    /// from.
+    pub fn capture_next_frame(&mut self) -> Result<ImageReadback, PixelReadError> {
+        self.read_image(ResourceID::SwapchainColorAttachment)
+    }
+
+    /// Schedules a copy of the swapchain color image and of every attachment of the bound render
+    /// graph, named after the attachments.
+    pub fn read_all_attachments(&mut self) -> Vec<(String, Result<ImageReadback, PixelReadError>)> {
+        let attachments = self
+            .pending_render_graph
+            .as_ref()
+            .unwrap_or(&self.render_graph)
+            .resources()
+            .attachments
+            .iter()
+            .map(|(&uuid, attachment)| (attachment.info.name.clone(), ResourceID::Other(uuid)))
+            .collect::<Vec<_>>();
+
+        std::iter::once(("swapchain".to_owned(), ResourceID::SwapchainColorAttachment))
+            .chain(attachments)
+            .map(|(name, resource)| (name, self.read_image(resource)))
+            .collect()
+    }
+
+    fn readable_image_state(&self, resource: ResourceID) -> Result<&ImageState, PixelReadError> {
+        // swapchain images all share their extent, format and usage, whichever gets acquired
+        let image_state = match resource {
+            ResourceID::SwapchainColorAttachment | ResourceID::SwapchainColorAttachmentUnorm => {
+                self.presentation
+                    .as_ref()
+                    .ok_or(PixelReadError::Suspended)?
+                    .swapchain
+                    .images
+                    .first()
+                    .map(|image| &image.color_attachment)
+            }
+            ResourceID::SwapchainDSAttachment => self
+                .presentation
+                .as_ref()
+                .ok_or(PixelReadError::Suspended)?
+                .swapchain
+                .images
+                .first()
+                .and_then(|image| image.depth_attachment.as_ref())
+                .map(|depth_attachment| &depth_attachment.state),
+            ResourceID::Other(uuid) => self
+                .pending_render_graph
+                .as_ref()
+                .unwrap_or(&self.render_graph)
+                .resources()
+                .get(&uuid)
+                .map(|attachment| &attachment.image.state),
+        }
+        .ok_or(PixelReadError::UnknownResource(resource))?;
+
+        if !image_state
+            .usage
+            .contains(vk::ImageUsageFlags::TRANSFER_SRC)
+        {
+            return Err(PixelReadError::NotTransferSource(resource));
+        }
+
+        Ok(image_state)
+    }
+
+    pub(crate) fn render_frame(&mut self, window: &Window) -> Result<(), RenderError> {
+        self.render(Some(window))
+    }
+
+    /// Renders a frame of a [headless](Self::new_headless) context, there being no window to
+    /// notify before presenting. Readbacks of a frame are resolved when the next one starts.
+    pub fn render_offscreen_frame(&mut self) -> Result<(), RenderError> {
+        self.render(None)
+    }
+
+    /// Resizes the image of a [headless](Self::new_headless) context before the next frame, along
+    /// with the attachments sized after it.
+    pub fn resize_offscreen(&mut self, extent: vk::Extent2D) -> Result<(), RenderError> {
+        self.resize(extent);
+        self.apply_pending_resize()?;
+
+        Ok(())
+    }
+
+    fn render(&mut self, window: Option<&Window>) -> Result<(), RenderError> {
+        // restoring the window may not be reported as a resize when its size is the same as
+        // before, only querying the surface tells
+        if self.surface_without_area
+            && let Some(presentation) = &self.presentation
+            && presentation.surface_has_area(&self.core)?
+        {
+            self.recreate_swapchain(self.resizes.applied())?;
+        }
+        if self.is_minimized() || self.is_suspended() {
+            return Ok(());
+        }
+        self.apply_present_preference()?;
+
+        match self.try_render_frame(window) {
+            Err(err) if err.is_surface_lost() => {
+                let Some(window) = window else {
+                    return Err(err);
+                };
+                log::warn!("surface lost ({err}), recreating it");
+
+                self.recreate_surface(window)
+            }
+            Err(err) if err.is_device_lost() => {
+                let device = self.core.device_ref.read();
+                let report = DeviceLostReport {
+                    hang: self.breadcrumbs.hang_report(&device),
+                    fault: device.fault_report(),
+                };
+                log::error!("device lost: {report}");
+
+                Err(RenderError::DeviceLost(report))
+            }
+            result => result,
+        }
+    }
+
+    /// Replaces a lost surface with a new one created from the window, along with the swapchain
+    /// presenting to it. Device-level resources are kept as is.
+    fn recreate_surface(&mut self, window: &Window) -> Result<(), RenderError> {
+        let window_handle = window.window_handle()?.as_raw();
+        let display_handle = window.display_handle()?.as_raw();
+
+        let Some(presentation) = self.presentation.as_mut() else {
+            return Ok(());
+        };
+
+        let previous_properties = self.swapchain_summary.properties;
+        presentation.recreate_surface(&self.core, display_handle, window_handle)?;
+        self.notify_surface_changed(previous_properties)?;
+
+        Ok(())
+    }
+
+    fn try_render_frame(&mut self, window: Option<&Window>) -> Result<(), RenderError> {
+        // the present mode may have changed along with the presentation
+        self.update_vsync_refresh_rate();
+        self.frame_limiter.wait();
+        self.frame_limiter.begin_frame();
+        if let Some(messenger) = self.core.validation_messenger() {
+            messenger.reset_stats();
+        }
+        self.swap_pending_render_graph();
+        if let Some(controller) = &mut self.render_scale_controller {
+            let frame_ms = self.frame_limiter.last_frame_interval().as_secs_f32() * 1000.0;
+            let render_scale = controller.update(frame_ms, self.render_scale);
+            self.set_render_scale(render_scale)?;
+        }
+
+        let frame_slot = self.core.command_manager.frame_slot();
+        let fence_wait_start = Instant::now();
+        self.presentation
+            .as_mut()
+            .expect("frames should not be rendered while suspended")
+            .swapchain
+            .begin_frame(frame_slot)?;
+        let fence_wait = fence_wait_start.elapsed();
+
+        let frame_index = self
+            .core
+            .command_manager
+            .frame_counter
+            .load(Ordering::Acquire);
+        self.run_frame_hooks(FrameStage::AfterFenceWait, frame_index);
+
+        let upload_operation = debug::operation(|| "frame constants upload");
+        self.frame_constants
+            .upload(self.swapchain_summary.properties.extent, frame_slot)?;
+        drop(upload_operation);
+        self.pixel_readbacks.resolve_completed(frame_index);
+        self.pixel_readbacks
+            .prepare(&self.core.device_ref, &self.core.allocator_ref);
+
+        let acquire_operation = debug::operation(|| "swapchain image acquisition");
+        let presentation = self
+            .presentation
+            .as_mut()
+            .expect("frames should not be rendered while suspended");
+        let acquire_start = Instant::now();
+        let next_image = presentation.swapchain.next_image(frame_slot)?;
+        let acquire_wait = acquire_start.elapsed();
+        drop(acquire_operation);
+        match next_image {
+            NextImageState::OutOfDate => {
+                log::warn!("swapchain is out of date, recreating");
+
+                // recreate and try again next frame
+                self.recreate_swapchain(self.resizes.applied())?;
+
+                return Ok(());
+            }
+            NextImageState::Suboptimal => {
+                log::debug!(
+                    "acquired image is suboptimal, recreating the swapchain after the frame"
+                );
+                self.suboptimal_acquire = true;
+            }
+            _ => (),
+        };
+
+        let frame_info = FrameInfo {
+            index: frame_index,
+            slot: frame_slot.as_usize(),
+            swapchain_image_index: presentation.swapchain.current_image_index().0,
+        };
+        let semaphores = presentation.swapchain.frame_semaphores(frame_slot);
+        let record_start = Instant::now();
+        let core = &mut *self.core;
+        core.command_manager.render_command(
+            &mut presentation.swapchain,
+            semaphores,
+            |submission, current_image_resources| {
+                self.frame_hooks.run(
+                    &mut FrameHookContext::new(
+                        FrameStage::BeforeGraphRecord,
+                        frame_index,
+                        &mut self.staging_belt,
+                        &self.deletion_queue,
+                    )
+                    .with_cmd_buffer(submission.cmd_buffer()),
+                );
+
+                // transfer phase, uploads are visible to every pass
+                let copy_operation = debug::operation(|| "staging belt copies");
+                self.staging_belt.record_copies(submission.cmd_buffer());
+                drop(copy_operation);
+
+                self.render_graph.render(
+                    current_image_resources,
+                    self.frame_constants.descriptor_set(frame_slot),
+                    submission,
+                    &core.device_ref,
+                    &mut self.pixel_readbacks,
+                    &mut self.breadcrumbs,
+                    &self.deferred_resources,
+                    frame_info,
+                )?;
+
+                Ok(())
+            },
+        )?;
+        let record = record_start.elapsed();
+        self.run_frame_hooks(FrameStage::AfterSubmit, frame_index);
+
+        if let Some(window) = window {
+            window.pre_present_notify();
+        }
+
+        let present_operation = debug::operation(|| "swapchain present");
+        let present_start = Instant::now();
+        let present_state = self
+            .presentation
+            .as_ref()
+            .expect("frames should not be rendered while suspended")
+            .swapchain
+            .present()?;
+        let presented_at = Instant::now();
+        drop(present_operation);
+        self.frame_timings.push(FrameTiming {
+            frame_index,
+            fence_wait,
+            acquire_wait,
+            suboptimal_acquire: self.suboptimal_acquire,
+            record,
+            present: presented_at - present_start,
+            presented_at: (present_state != PresentState::OutOfDate).then_some(presented_at),
+        });
+        if present_state != PresentState::OutOfDate {
+            self.run_frame_hooks(FrameStage::AfterPresent, frame_index);
+            self.presented_frames += 1;
+        }
+        if let Some(idle) = self.defragmenter.idle
+            && self
+                .frame_timings
+                .latest()
+                .is_some_and(|timing| timing.total() < idle.max_frame_time)
+            && let Err(err) = self.defragment_now(idle.budget_bytes)
+        {
+            log::error!("idle defragmentation failed: {err}");
+        }
+        self.frame_limiter.end_frame();
+
+        let suboptimal = std::mem::take(&mut self.suboptimal_acquire)
+            || present_state == PresentState::PresentedSuboptimal;
+        match present_state {
+            PresentState::OutOfDate => {
+                log::warn!("swapchain is out of date after presenting, recreating");
+                self.recreate_swapchain(self.resizes.applied())?;
+            }
+            _ if suboptimal => self.recreate_suboptimal_swapchain()?,
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    // some surfaces stay suboptimal whatever the swapchain, it is only recreated once for the same
+    // size and rotation of the surface
+    fn recreate_suboptimal_swapchain(&mut self) -> Result<(), RenderError> {
+        let Some(presentation) = &self.presentation else {
+            return Ok(());
+        };
+        let surface_state = (
+            self.resizes.applied(),
+            presentation.surface_transform(&self.core)?,
+        );
+        if self.suboptimal_recreation == Some(surface_state) {
+            return Ok(());
+        }
+
+        log::debug!("swapchain is suboptimal, recreating");
+        self.suboptimal_recreation = Some(surface_state);
+        self.recreate_swapchain(surface_state.0)
+    }
+
+    fn run_frame_hooks(&mut self, stage: FrameStage, frame_index: u64) {
+        self.frame_hooks.run(&mut FrameHookContext::new(
+            stage,
+            frame_index,
+            &mut self.staging_belt,
+            &self.deletion_queue,
+        ));
+    }
+}
+
+impl Drop for Context {
+    fn drop(&mut self) {
+        drop(self.teardown());
+    }
+}
+
+fn monitor_refresh_rate(window: &Window) -> Option<f32> {
+    let refresh_rate_millihertz = window.current_monitor()?.refresh_rate_millihertz()?;
+    Some(refresh_rate_millihertz as f32 / 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        gfx::{
+            buffer::Buffer,
+            commands::FRAMES_IN_FLIGHT,
+            defrag::RelocatableBuffer,
+            device::Device,
+            feedback::FeedbackBuffer,
+            image::ImageCreateInfo,
+            mesh::primitives,
+            render_graph::{
+                FormatChange,
+                render_pass::{AttachmentInfo, RenderPass},
+                resource::{
+                    AttachmentSize, FrameResources, ImageAttachmentInfo, ResourceAccessType,
+                    ResourceID, ResourceInfoRegistry,
+                },
+            },
+        },
+        math::Vec2,
+        utils::ThreadSafeRwRef,
+    };
+
+    // remembers the formats it was notified of
+    struct SurfaceRecordingPass {
+        attachment_infos: AttachmentInfo,
+        formats: ThreadSafeRef<Vec<vk::Format>>,
+    }
+
+    impl RenderPass for SurfaceRecordingPass {
+        fn name(&self) -> &str {
+            "surface recording"
+        }
+
+        fn attachment_infos(&self) -> &AttachmentInfo {
+            &self.attachment_infos
+        }
+
+        fn record_commands(
+            &mut self,
+            _resources: &mut FrameResources,
+            _cmd_buffer: &vk::CommandBuffer,
+            _device_ref: ThreadSafeRwRef<Device>,
+        ) {
+        }
+
+        fn on_surface_changed(
+            &mut self,
+            new_properties: &SurfaceProperties,
+            _device_ref: ThreadSafeRwRef<Device>,
+        ) {
+            self.formats.lock().push(new_properties.format);
+        }
+    }
+
+    #[test]
+    fn device_losses_are_detected_on_submit_wait_and_present() {
+        let lost = vk::Result::ERROR_DEVICE_LOST;
+        let errors = [
+            RenderError::FrameBegin(FrameBeginError::FenceSync(lost)),
+            RenderError::ImageAcquisition(NextImageAcquireError::NextIndexAcquisition(lost)),
+            RenderError::RenderCommand(RenderCommandError::BatchSubmission(
+                BatchSubmitError::Submission(lost),
+            )),
+            RenderError::RenderCommand(RenderCommandError::FenceWaiting(lost)),
+            RenderError::SwapchainPresent(PresentError::Present(lost)),
+            RenderError::SwapchainPresent(PresentError::OffscreenPresent(lost)),
+            RenderError::DeviceLost(DeviceLostReport::default()),
+        ];
+
+        for error in errors {
+            assert!(error.is_device_lost(), "{error:?}");
+            assert!(!error.is_surface_lost(), "{error:?}");
+        }
+    }
+
+    #[test]
+    fn other_failures_are_not_device_losses() {
+        let errors = [
+            RenderError::RenderCommand(RenderCommandError::FenceWaiting(
+                vk::Result::ERROR_OUT_OF_DEVICE_MEMORY,
+            )),
+            RenderError::SwapchainPresent(PresentError::Present(
+                vk::Result::ERROR_SURFACE_LOST_KHR,
+            )),
+        ];
+
+        for error in errors {
+            assert!(!error.is_device_lost(), "{error:?}");
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan device"]
+    fn headless_rebuild_keeps_settings() {
+        let extent = vk::Extent2D {
+            width: 64,
+            height: 32,
+        };
+        let mut context =
+            Context::new_headless(&ContextCreateInfo::new("rebuild", (0, 1, 0)), extent)
+                .expect("a headless context should be created");
+        context.set_frame_rate_limit(Some(30.0));
+        context
+            .render_offscreen_frame()
+            .expect("a frame should render");
+
+        let mut context = context
+            .rebuild_headless()
+            .expect("the context should be rebuilt");
+        assert_eq!(context.frame_rate_limit(), Some(30.0));
+        assert_eq!(context.physical_extent(), extent);
+        context
+            .render_offscreen_frame()
+            .expect("the rebuilt context should render");
+    }
+
+    // fills a feedback counter with the index of the frame, outside of any rendering scope
+    struct CounterFillPass {
+        attachment_infos: AttachmentInfo,
+        counter: vk::Buffer,
+        frame_index: u32,
+    }
+
+    impl RenderPass for CounterFillPass {
+        fn name(&self) -> &str {
+            "counter fill"
+        }
+
+        fn attachment_infos(&self) -> &AttachmentInfo {
+            &self.attachment_infos
+        }
+
+        fn begins_rendering(&self) -> bool {
+            false
+        }
+
+        fn record_commands(
+            &mut self,
+            _resources: &mut FrameResources,
+            cmd_buffer: &vk::CommandBuffer,
+            device_ref: ThreadSafeRwRef<Device>,
+        ) {
+            unsafe {
+                device_ref.read().cmd_fill_buffer(
+                    *cmd_buffer,
+                    self.counter,
+                    0,
+                    vk::WHOLE_SIZE,
+                    self.frame_index,
+                )
+            };
+            self.frame_index += 1;
+        }
+    }
+
+    // a graph whose single pass writes to a "scene" attachment of the given format
+    fn scene_graph(format: vk::Format) -> RenderGraphInfo {
+        let mut registry = ResourceInfoRegistry::new();
+        let scene = registry
+            .add_image_attachment(ImageAttachmentInfo::new("scene").format(format))
+            .expect("the attachment should be added");
+        let mut attachment_infos = AttachmentInfo::default();
+        attachment_infos.add_color_attachment(scene, ResourceAccessType::WriteOnly);
+
+        RenderGraphInfo::new(registry).push_render_pass(SurfaceRecordingPass {
+            attachment_infos,
+            formats: ThreadSafeRef::new(vec![]),
+        })
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan device"]
+    fn headless_rebind_retires_previous_graph() {
+        let mut context = Context::new_headless(
+            &ContextCreateInfo::new("rebind", (0, 1, 0)).with_validation(ValidationMode::ForceOn),
+            vk::Extent2D {
+                width: 64,
+                height: 32,
+            },
+        )
+        .expect("a headless context should be created");
+        let diffs = ThreadSafeRef::new(vec![]);
+        let recorded_diffs = diffs.clone();
+        context.add_render_graph_listener(Box::new(move |diff| {
+            recorded_diffs.lock().push(diff.clone())
+        }));
+
+        context
+            .bind_rendergraph(scene_graph(vk::Format::R8G8B8A8_UNORM))
+            .expect("graph A should be bound");
+        context
+            .render_offscreen_frame()
+            .expect("graph A should render");
+        diffs.lock().clear();
+
+        context
+            .bind_rendergraph(scene_graph(vk::Format::R16G16B16A16_SFLOAT))
+            .expect("graph B should be bound");
+        context
+            .render_offscreen_frame()
+            .expect("graph B should render");
+        // the next fence wait releases graph A
+        context
+            .render_offscreen_frame()
+            .expect("graph B should keep rendering");
+
+        let diffs = diffs.lock();
+        assert_eq!(diffs.len(), 1);
+        assert!(diffs[0].added.is_empty() && diffs[0].removed.is_empty());
+        assert_eq!(
+            diffs[0].format_changed,
+            [FormatChange {
+                name: "scene".to_owned(),
+                old: vk::Format::R8G8B8A8_UNORM,
+                new: vk::Format::R16G16B16A16_SFLOAT,
+            }]
+        );
+        let stats = context
+            .validation_stats()
+            .expect("validation should be enabled");
+        assert_eq!(stats.errors, 0);
+    }
+
+    // remembers the extents of a swapchain-sized and of a custom-sized attachment every frame
+    struct ExtentRecordingPass {
+        attachment_infos: AttachmentInfo,
+        scene: ResourceID,
+        probe: ResourceID,
+        extents: ThreadSafeRef<Vec<(vk::Extent2D, vk::Extent2D)>>,
+    }
+
+    impl RenderPass for ExtentRecordingPass {
+        fn name(&self) -> &str {
+            "extent recording"
+        }
+
+        fn attachment_infos(&self) -> &AttachmentInfo {
+            &self.attachment_infos
+        }
+
+        fn record_commands(
+            &mut self,
+            resources: &mut FrameResources,
+            _cmd_buffer: &vk::CommandBuffer,
+            _device_ref: ThreadSafeRwRef<Device>,
+        ) {
+            let extent_of = |id| {
+                resources
+                    .attachment_extent(id)
+                    .expect("the attachment should exist")
+            };
+            let extents = (extent_of(&self.scene), extent_of(&self.probe));
+            self.extents.lock().push(extents);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan device"]
+    fn headless_resize_keeps_graph_attachments_in_sync() {
+        let probe_extent = vk::Extent2D {
+            width: 16,
+            height: 16,
+        };
+        let mut context = Context::new_headless(
+            &ContextCreateInfo::new("resize", (0, 1, 0)).with_validation(ValidationMode::ForceOn),
+            vk::Extent2D {
+                width: 64,
+                height: 32,
+            },
+        )
+        .expect("a headless context should be created");
+
+        let mut registry = ResourceInfoRegistry::new();
+        let scene = registry
+            .add_image_attachment(
+                ImageAttachmentInfo::new("scene").format(vk::Format::R8G8B8A8_UNORM),
+            )
+            .expect("the attachment should be added");
+        let probe = registry
+            .add_image_attachment(
+                ImageAttachmentInfo::new("probe")
+                    .format(vk::Format::R8G8B8A8_UNORM)
+                    .size(AttachmentSize::Custom(probe_extent.into())),
+            )
+            .expect("the attachment should be added");
+        let mut attachment_infos = AttachmentInfo::default();
+        attachment_infos.add_color_attachment(scene, ResourceAccessType::WriteOnly);
+        attachment_infos.add_color_attachment(probe, ResourceAccessType::WriteOnly);
+        let extents = ThreadSafeRef::new(vec![]);
+        context
+            .bind_rendergraph(RenderGraphInfo::new(registry).push_render_pass(
+                ExtentRecordingPass {
+                    attachment_infos,
+                    scene,
+                    probe,
+                    extents: extents.clone(),
+                },
+            ))
+            .expect("the graph should be bound");
+
+        for extent in [
+            vk::Extent2D {
+                width: 64,
+                height: 32,
+            },
+            vk::Extent2D {
+                width: 96,
+                height: 48,
+            },
+            vk::Extent2D {
+                width: 40,
+                height: 72,
+            },
+        ] {
+            context
+                .resize_offscreen(extent)
+                .expect("the context should be resized");
+            context
+                .render_offscreen_frame()
+                .expect("a frame should render");
+
+            assert_eq!(context.physical_extent(), extent);
+            assert_eq!(context.swapchain_extent(), extent);
+            assert_eq!(
+                extents.lock().last(),
+                Some(&(extent, probe_extent)),
+                "the graph should follow the swapchain, custom sizes left untouched"
+            );
+        }
+
+        let stats = context
+            .validation_stats()
+            .expect("validation should be enabled");
+        assert_eq!(stats.errors, 0);
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan device"]
+    fn headless_context_leaks_no_vulkan_objects() {
+        let mut context = Context::new_headless(
+            &ContextCreateInfo::new("leaks", (0, 1, 0)).with_validation(ValidationMode::ForceOn),
+            vk::Extent2D {
+                width: 64,
+                height: 32,
+            },
+        )
+        .expect("a headless context should be created");
+        context
+            .render_offscreen_frame()
+            .expect("a frame should render");
+        // objects are only tracked in debug builds
+        let tracked = cfg!(debug_assertions);
+        let baseline = context.live_vulkan_object_count();
+        assert_eq!(baseline > 0, tracked);
+
+        let buffer = Buffer::builder(256)
+            .with_name("leak test buffer")
+            .build(&mut context)
+            .expect("the buffer should be created");
+        let plane = primitives::strip_plane("leak test plane", 2, 2, Vec2::ONE, &mut context)
+            .expect("the mesh should be created");
+        assert_eq!(context.live_vulkan_object_count() > baseline, tracked);
+
+        drop(buffer);
+        drop(plane);
+        context
+            .render_offscreen_frame()
+            .expect("a frame should render");
+        assert_eq!(context.live_vulkan_object_count(), baseline);
+
+        // survivors fail a debug assertion when tearing the context down
+        context.destroy();
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan device"]
+    fn headless_teardown_survives_hostile_drop_order() {
+        let mut context = Context::new_headless(
+            &ContextCreateInfo::new("hostile drops", (0, 1, 0))
+                .with_validation(ValidationMode::ForceOn),
+            vk::Extent2D {
+                width: 64,
+                height: 32,
+            },
+        )
+        .expect("a headless context should be created");
+        let plane = primitives::strip_plane("hostile plane", 4, 4, Vec2::ONE, &mut context)
+            .expect("the mesh should be created");
+        let buffer = Buffer::builder(1024)
+            .with_name("hostile buffer")
+            .build(&mut context)
+            .expect("the buffer should be created");
+        let image = ImageCreateInfo::offscreen_color_image(
+            vk::Extent3D {
+                width: 16,
+                height: 16,
+                depth: 1,
+            },
+            vk::Format::R8G8B8A8_UNORM,
+        )
+        .build(&context)
+        .expect("the image should be created");
+
+        // dropped in no particular order, right after submitting work and without waiting for it
+        context
+            .render_offscreen_frame()
+            .expect("a frame should render");
+        drop(buffer);
+        drop(plane);
+        context
+            .render_offscreen_frame()
+            .expect("a frame should render");
+        drop(image);
+
+        let stats = context
+            .validation_stats()
+            .expect("validation should be enabled");
+        assert_eq!(stats.errors, 0);
+        // survivors fail a debug assertion when tearing the context down
+        context.destroy();
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan device"]
+    fn headless_feedback_is_read_back_two_frames_later() {
+        let mut context = Context::new_headless(
+            &ContextCreateInfo::new("feedback", (0, 1, 0)).with_validation(ValidationMode::ForceOn),
+            vk::Extent2D {
+                width: 64,
+                height: 32,
+            },
+        )
+        .expect("a headless context should be created");
+        let feedback = FeedbackBuffer::<u32>::new("frame index", &mut context)
+            .expect("the feedback should be created");
+        context
+            .bind_rendergraph(
+                RenderGraphInfo::new(ResourceInfoRegistry::new())
+                    .push_render_pass(CounterFillPass {
+                        attachment_infos: AttachmentInfo::default(),
+                        counter: feedback.counter_buffer(),
+                        frame_index: 0,
+                    })
+                    .with_feedback(&feedback),
+            )
+            .expect("the render graph should be bound");
+
+        for frame_index in 0..6 {
+            context
+                .render_offscreen_frame()
+                .expect("a frame should render");
+
+            // the fence of a frame is only waited on once every frame in flight after it started
+            let expected = (frame_index as u64)
+                .checked_sub(FRAMES_IN_FLIGHT as u64)
+                .map(|complete_frame| (complete_frame, complete_frame as u32));
+            assert_eq!(feedback.latest(), expected);
+        }
+        let stats = context
+            .validation_stats()
+            .expect("validation should be enabled");
+        assert_eq!(stats.errors, 0);
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan device"]
+    fn headless_defragmentation_moves_buffers_out_of_sparse_blocks() {
+        // a 16th of the default 256 MiB device memory block
+        const CHUNK_SIZE: u64 = 16 << 20;
+
+        let mut context = Context::new_headless(
+            &ContextCreateInfo::new("defragmentation", (0, 1, 0)),
+            vk::Extent2D {
+                width: 64,
+                height: 32,
+            },
+        )
+        .expect("a headless context should be created");
+        let mut chunks = (0..20)
+            .map(|index| {
+                RelocatableBuffer::new(
+                    Buffer::builder(CHUNK_SIZE)
+                        .with_name(&format!("chunk {index}"))
+                        .with_usage(vk::BufferUsageFlags::STORAGE_BUFFER),
+                    &mut context,
+                )
+                .expect("the buffer should be created")
+            })
+            .collect::<Vec<_>>();
+
+        // half of the first block is freed, the second one only keeps its last chunk
+        let mut kept = chunks.split_off(8);
+        kept.drain(8..11);
+        let moved = kept.last().expect("chunks should be kept").clone();
+        let previous_handle = moved.handle();
+        drop(chunks);
+        assert_eq!(context.defrag_stats().sparse_block_count, 1);
+
+        let report = context
+            .defragment_now(u64::MAX)
+            .expect("the defragmentation should succeed");
+        assert_eq!(
+            report,
+            DefragReport {
+                moved_buffers: 1,
+                moved_bytes: CHUNK_SIZE,
+            }
+        );
+        assert_eq!(moved.generation(), 1);
+        assert_ne!(moved.handle(), previous_handle);
+
+        // the previous buffer is released once the frames in flight are done with it
+        for _ in 0..=FRAMES_IN_FLIGHT {
+            context
+                .render_offscreen_frame()
+                .expect("a frame should render");
+        }
+        assert_eq!(context.defrag_stats().sparse_block_count, 0);
+        assert_eq!(
+            context
+                .defragment_now(u64::MAX)
+                .expect("the defragmentation should succeed"),
+            DefragReport::default()
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan device"]
+    fn surface_format_changes_reach_passes_and_listeners() {
+        let mut context = Context::new_headless(
+            &ContextCreateInfo::new("surface change", (0, 1, 0)),
+            vk::Extent2D {
+                width: 64,
+                height: 64,
+            },
+        )
+        .expect("a headless context should be created");
+        let pass_formats = ThreadSafeRef::new(vec![]);
+        let mut attachment_infos = AttachmentInfo::default();
+        attachment_infos.add_color_attachment(
+            ResourceID::SwapchainColorAttachment,
+            ResourceAccessType::WriteOnly,
+        );
+        context
+            .bind_rendergraph(
+                RenderGraphInfo::new(ResourceInfoRegistry::new()).push_render_pass(
+                    SurfaceRecordingPass {
+                        attachment_infos,
+                        formats: pass_formats.clone(),
+                    },
+                ),
+            )
+            .expect("the render graph should be bound");
+        context
+            .render_offscreen_frame()
+            .expect("a frame should render");
+        let listener_formats = ThreadSafeRef::new(vec![]);
+        let recorded_formats = listener_formats.clone();
+        context.add_surface_listener(Box::new(move |properties| {
+            recorded_formats.lock().push(properties.format)
+        }));
+
+        // recreations keeping the same properties notify nobody
+        let current = context.surface_properties();
+        context
+            .notify_surface_changed(current)
+            .expect("the notification should succeed");
+        assert!(pass_formats.lock().is_empty());
+        assert!(listener_formats.lock().is_empty());
+
+        // as if the swapchain was recreated from one with another format
+        let previous = SurfaceProperties {
+            format: match current.format {
+                vk::Format::B8G8R8A8_UNORM => vk::Format::R8G8B8A8_UNORM,
+                _ => vk::Format::B8G8R8A8_UNORM,
+            },
+            ..current
+        };
+        context
+            .notify_surface_changed(previous)
+            .expect("the notification should succeed");
+        assert_eq!(*pass_formats.lock(), [current.format]);
+        assert_eq!(*listener_formats.lock(), [current.format]);
+    }
 }
@@ -1,23 +1,91 @@
-use std::ffi::CStr;
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    ffi::CStr,
+    sync::{
+        Arc,
+        atomic::{AtomicU32, AtomicUsize, Ordering},
+    },
+};
 
 use ash::{ext, vk};
+use smallvec::SmallVec;
 use thiserror::Error;
 
 use super::instance::Instance;
 
+// operations are only tracked while a messenger may report them
+static LIVE_MESSENGERS: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static OPERATIONS: RefCell<SmallVec<[Cow<'static, str>; 8]>> = RefCell::default();
+}
+
+/// Engine operation running on the current thread, appended to the validation messages it
+/// triggers. Popped when dropped.
+#[must_use = "the operation ends as soon as its scope is dropped"]
+pub(crate) struct OperationScope {
+    pushed: bool,
+}
+
+/// Enters an operation described by `describe`, which is only called while a messenger exists.
+pub(crate) fn operation<S: Into<Cow<'static, str>>>(
+    describe: impl FnOnce() -> S,
+) -> OperationScope {
+    let pushed = LIVE_MESSENGERS.load(Ordering::Relaxed) > 0;
+    if pushed {
+        OPERATIONS.with_borrow_mut(|operations| operations.push(describe().into()));
+    }
+
+    OperationScope { pushed }
+}
+
+impl Drop for OperationScope {
+    fn drop(&mut self) {
+        if self.pushed {
+            OPERATIONS.with_borrow_mut(|operations| operations.pop());
+        }
+    }
+}
+
+/// Validation messages logged since the current frame started rendering.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ValidationStats {
+    pub errors: u32,
+    pub warnings: u32,
+}
+
+#[derive(Debug, Default)]
+struct ValidationCounters {
+    errors: AtomicU32,
+    warnings: AtomicU32,
+}
+
 unsafe extern "system" fn vulkan_debug_callback(
     message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
     message_type: vk::DebugUtilsMessageTypeFlagsEXT,
     callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
-    _user_data: *mut std::ffi::c_void,
+    user_data: *mut std::ffi::c_void,
 ) -> u32 {
     let callback_data_deref = unsafe { *callback_data };
     let message_id_str = callback_data_deref.message_id_number.to_string();
     let message = if callback_data_deref.p_message.is_null() {
-        std::borrow::Cow::from("")
+        Cow::from("")
     } else {
         unsafe { CStr::from_ptr(callback_data_deref.p_message) }.to_string_lossy()
     };
+    let message = match OPERATIONS.with_borrow(|operations| operations.last().cloned()) {
+        Some(operation) => format!("{message} (during {operation})"),
+        None => message.into_owned(),
+    };
+
+    // SAFETY: the counters are owned by the messenger, which is destroyed before them.
+    let counters = unsafe { &*user_data.cast::<ValidationCounters>() };
+    if message_severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR) {
+        counters.errors.fetch_add(1, Ordering::Relaxed);
+    } else if message_severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::WARNING) {
+        counters.warnings.fetch_add(1, Ordering::Relaxed);
+    }
 
     match message_severity {
         vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE => {
@@ -46,16 +114,19 @@ pub enum DUMCreationError {
 pub(crate) struct DUMessenger {
     pub handle: vk::DebugUtilsMessengerEXT,
     pub loader: ext::debug_utils::Instance,
+    counters: Arc<ValidationCounters>,
 }
 
 impl DUMessenger {
     pub(crate) fn create(
         entry: &ash::Entry,
         instance: &Instance,
+        validation: bool,
     ) -> Result<Option<Self>, DUMCreationError> {
-        match cfg!(debug_assertions) {
+        match validation {
             true => {
                 let loader = ext::debug_utils::Instance::new(entry, instance);
+                let counters = Arc::new(ValidationCounters::default());
 
                 let create_info = vk::DebugUtilsMessengerCreateInfoEXT::default()
                     .message_severity(
@@ -67,16 +138,35 @@ impl DUMessenger {
                             | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
                             | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
                     )
-                    .pfn_user_callback(Some(vulkan_debug_callback));
+                    .pfn_user_callback(Some(vulkan_debug_callback))
+                    .user_data(Arc::as_ptr(&counters).cast_mut().cast());
                 // SAFETY: This is safe as long as the entry used to create the loader is still alive.
                 let handle = unsafe { loader.create_debug_utils_messenger(&create_info, None) }
                     .map_err(DUMCreationError::VulkanCreation)?;
 
-                Ok(Some(Self { handle, loader }))
+                LIVE_MESSENGERS.fetch_add(1, Ordering::Relaxed);
+
+                Ok(Some(Self {
+                    handle,
+                    loader,
+                    counters,
+                }))
             }
             false => Ok(None),
         }
     }
+
+    pub fn stats(&self) -> ValidationStats {
+        ValidationStats {
+            errors: self.counters.errors.load(Ordering::Relaxed),
+            warnings: self.counters.warnings.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn reset_stats(&self) {
+        self.counters.errors.store(0, Ordering::Relaxed);
+        self.counters.warnings.store(0, Ordering::Relaxed);
+    }
 }
 
 impl Drop for DUMessenger {
@@ -84,5 +174,6 @@ impl Drop for DUMessenger {
         log::debug!("destroying DUMessenger");
         // SAFETY: This is safe as long as the entry used to create the loader is still alive.
         unsafe { self.loader.destroy_debug_utils_messenger(self.handle, None) };
+        LIVE_MESSENGERS.fetch_sub(1, Ordering::Relaxed);
     }
 }
@@ -0,0 +1,183 @@
+use std::sync::{Mutex, Weak};
+
+use ash::vk;
+
+use crate::utils::{ThreadSafeRef, ThreadSafeRwRef};
+
+use super::{
+    allocator::Allocator,
+    buffer::{Buffer, BufferBuildError, BufferBuilder},
+    device::Device,
+    image::{Image, ImageBuildError, ImageCreateInfo},
+    render_graph::resource::ImageAttachmentInfo,
+};
+
+type Slot<T, E> = Mutex<Option<Result<T, E>>>;
+
+/// Resource requested while recording a frame through
+/// [`FrameResources`](super::render_graph::resource::FrameResources), created once the commands of
+/// that frame have been submitted.
+///
+/// The resource can never be used by the frame requesting it: the earliest frame able to use it
+/// is the next one. Dropping an unresolved handle cancels its creation, or destroys the resource
+/// if it was already created.
+#[derive(Debug)]
+pub struct DeferredHandle<T, E> {
+    slot: ThreadSafeRef<Option<Result<T, E>>>,
+}
+
+pub type DeferredBuffer = DeferredHandle<Buffer, BufferBuildError>;
+pub type DeferredImage = DeferredHandle<Image, ImageBuildError>;
+
+impl<T, E> DeferredHandle<T, E> {
+    fn new() -> (Self, Weak<Slot<T, E>>) {
+        let slot = ThreadSafeRef::new(None);
+        let weak_slot = slot.downgrade();
+
+        (Self { slot }, weak_slot)
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.slot.lock().is_some()
+    }
+
+    /// Gives the created resource or the creation error once the request was processed, and the
+    /// handle back otherwise.
+    pub fn try_resolve(self) -> Result<Result<T, E>, Self> {
+        let result = self.slot.lock().take();
+        result.ok_or(self)
+    }
+}
+
+enum DeferredRequest {
+    Buffer(BufferBuilder, Weak<Slot<Buffer, BufferBuildError>>),
+    // always sized in pixels, sizes depending on the swapchain are resolved when requested
+    Image(ImageAttachmentInfo, Weak<Slot<Image, ImageBuildError>>),
+}
+
+#[derive(Default)]
+pub(crate) struct DeferredResourceQueue {
+    requests: Vec<DeferredRequest>,
+}
+
+impl DeferredResourceQueue {
+    pub fn request_buffer(&mut self, builder: BufferBuilder) -> DeferredBuffer {
+        let (handle, slot) = DeferredHandle::new();
+        self.requests.push(DeferredRequest::Buffer(builder, slot));
+
+        handle
+    }
+
+    pub fn request_image(&mut self, info: ImageAttachmentInfo) -> DeferredImage {
+        let (handle, slot) = DeferredHandle::new();
+        self.requests.push(DeferredRequest::Image(info, slot));
+
+        handle
+    }
+
+    /// Creates every requested resource whose handle is still alive.
+    pub fn create_pending(
+        &mut self,
+        device_ref: &ThreadSafeRwRef<Device>,
+        allocator_ref: &ThreadSafeRef<Allocator>,
+    ) {
+        for request in self.requests.drain(..) {
+            match request {
+                DeferredRequest::Buffer(builder, slot) => {
+                    let name = builder.name.clone();
+                    let created = fulfill(&slot, || {
+                        builder.build_internal(device_ref.clone(), allocator_ref.clone())
+                    });
+                    if !created {
+                        log::debug!("deferred buffer \"{name}\" was cancelled");
+                    }
+                }
+                DeferredRequest::Image(info, slot) => {
+                    let created = fulfill(&slot, || {
+                        // custom sizes resolve to themselves, whatever the swapchain
+                        let extent = info.size.resolve(vk::Extent2D::default(), 1.0);
+                        ImageCreateInfo::from_attachment_info(&info, extent)
+                            .build_from_base_structs(device_ref.clone(), allocator_ref.clone())
+                    });
+                    if !created {
+                        log::debug!("deferred image \"{}\" was cancelled", info.name);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// the resource is only created if its handle is still alive, false otherwise
+fn fulfill<T, E>(slot: &Weak<Slot<T, E>>, create: impl FnOnce() -> Result<T, E>) -> bool {
+    let Some(slot) = ThreadSafeRef::upgrade(slot) else {
+        return false;
+    };
+
+    *slot.lock() = Some(create());
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    type TestHandle = DeferredHandle<u32, &'static str>;
+
+    #[test]
+    fn handles_are_unresolved_until_their_request_is_processed() {
+        let (handle, _slot) = TestHandle::new();
+
+        assert!(!handle.is_ready());
+        // the handle comes back, still usable
+        let handle = handle.try_resolve().unwrap_err();
+        assert!(!handle.is_ready());
+    }
+
+    #[test]
+    fn processed_requests_resolve_to_their_resource() {
+        let (handle, slot) = TestHandle::new();
+
+        assert!(fulfill(&slot, || Ok(42)));
+
+        assert!(handle.is_ready());
+        assert!(matches!(handle.try_resolve(), Ok(Ok(42))));
+    }
+
+    #[test]
+    fn creation_errors_are_handed_to_the_handle() {
+        let (handle, slot) = TestHandle::new();
+
+        assert!(fulfill(&slot, || Err("out of memory")));
+
+        assert!(matches!(handle.try_resolve(), Ok(Err("out of memory"))));
+    }
+
+    #[test]
+    fn dropped_handles_cancel_their_creation() {
+        let (handle, slot) = TestHandle::new();
+        drop(handle);
+
+        let mut created = false;
+        assert!(!fulfill(&slot, || {
+            created = true;
+            Ok(0)
+        }));
+        assert!(!created);
+    }
+
+    #[test]
+    fn resources_of_handles_dropped_after_creation_are_released() {
+        let (handle, slot) = DeferredHandle::<Arc<()>, ()>::new();
+        let resource = Arc::new(());
+
+        fulfill(&slot, || Ok(resource.clone()));
+        assert_eq!(Arc::strong_count(&resource), 2);
+        drop(handle);
+
+        assert_eq!(Arc::strong_count(&resource), 1);
+        assert!(slot.upgrade().is_none());
+    }
+}
@@ -0,0 +1,466 @@
+use std::{
+    sync::{Mutex, Weak},
+    time::Duration,
+};
+
+use ash::vk;
+use thiserror::Error;
+
+use crate::utils::ThreadSafeRef;
+
+use super::{
+    buffer::{Buffer, BufferBuildError, BufferBuilder},
+    commands::ImmediateCommandError,
+    context::Context,
+    deletion_queue::DeletionQueue,
+    gpu_core::GpuCore,
+};
+
+/// Blocks whose allocations fill less than this fraction of them are considered sparse.
+pub const DEFAULT_SPARSE_THRESHOLD: f32 = 0.25;
+
+/// Live allocations of a single memory block of the allocator.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlockOccupancy {
+    pub size: u64,
+    /// Name and size of every allocation in the block.
+    pub allocations: Vec<(String, u64)>,
+}
+
+impl BlockOccupancy {
+    pub fn used_bytes(&self) -> u64 {
+        self.allocations.iter().map(|(_, size)| size).sum()
+    }
+
+    fn occupancy(&self) -> f32 {
+        if self.size == 0 {
+            return 1.0;
+        }
+
+        self.used_bytes() as f32 / self.size as f32
+    }
+}
+
+/// An allocation worth moving out of a sparse block, so that the block can be released.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DefragCandidate {
+    pub block_index: usize,
+    pub name: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct DefragStats {
+    pub block_count: usize,
+    pub sparse_block_count: usize,
+    /// Bytes reserved by sparse blocks, released once their allocations have moved.
+    pub reclaimable_bytes: u64,
+    /// Bytes that would have to move to release every sparse block.
+    pub movable_bytes: u64,
+}
+
+impl DefragStats {
+    pub fn from_blocks(blocks: &[BlockOccupancy], sparse_threshold: f32) -> Self {
+        let sparse_blocks = sparse_block_indices(blocks, sparse_threshold);
+
+        Self {
+            block_count: blocks.len(),
+            sparse_block_count: sparse_blocks.len(),
+            reclaimable_bytes: sparse_blocks.iter().map(|&index| blocks[index].size).sum(),
+            movable_bytes: sparse_blocks
+                .iter()
+                .map(|&index| blocks[index].used_bytes())
+                .sum(),
+        }
+    }
+}
+
+// sparsest first, empty blocks are left for the allocator to release on its own
+fn sparse_block_indices(blocks: &[BlockOccupancy], sparse_threshold: f32) -> Vec<usize> {
+    let mut sparse_blocks = blocks
+        .iter()
+        .enumerate()
+        .filter(|(_, block)| !block.allocations.is_empty() && block.occupancy() < sparse_threshold)
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    sparse_blocks.sort_by(|&a, &b| {
+        blocks[a]
+            .occupancy()
+            .total_cmp(&blocks[b].occupancy())
+            .then(a.cmp(&b))
+    });
+
+    sparse_blocks
+}
+
+/// Picks the allocations to move within `budget_bytes`, emptying the sparsest blocks first and
+/// their smallest allocations first. Nothing is picked when moving would only fill another sparse
+/// block, i.e. when every block holding allocations is sparse.
+pub fn select_candidates(
+    blocks: &[BlockOccupancy],
+    sparse_threshold: f32,
+    budget_bytes: u64,
+) -> Vec<DefragCandidate> {
+    let sparse_blocks = sparse_block_indices(blocks, sparse_threshold);
+    let used_block_count = blocks
+        .iter()
+        .filter(|block| !block.allocations.is_empty())
+        .count();
+    if sparse_blocks.len() == used_block_count {
+        return vec![];
+    }
+
+    let mut candidates = vec![];
+    let mut remaining_budget = budget_bytes;
+    for block_index in sparse_blocks {
+        let mut allocations = blocks[block_index].allocations.clone();
+        allocations.sort_by(|(name_a, size_a), (name_b, size_b)| {
+            size_a.cmp(size_b).then(name_a.cmp(name_b))
+        });
+
+        for (name, size) in allocations {
+            if size > remaining_budget {
+                return candidates;
+            }
+
+            remaining_budget -= size;
+            candidates.push(DefragCandidate {
+                block_index,
+                name,
+                size,
+            });
+        }
+    }
+
+    candidates
+}
+
+// allocations are only told apart by name in the reports of the allocator
+fn block_index_of(blocks: &[BlockOccupancy], allocation_name: &str) -> Option<usize> {
+    blocks.iter().position(|block| {
+        block
+            .allocations
+            .iter()
+            .any(|(name, _)| name == allocation_name)
+    })
+}
+
+/// Moves relocatable buffers during the frames leaving some headroom, see
+/// [`Context::set_idle_defragmentation`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct IdleDefragmentation {
+    /// Frames whose [CPU time](super::frame_timing::FrameTiming::total) is above this move
+    /// nothing.
+    pub max_frame_time: Duration,
+    /// Bytes moved at most after a single frame.
+    pub budget_bytes: u64,
+}
+
+/// What a defragmentation moved.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct DefragReport {
+    pub moved_buffers: usize,
+    pub moved_bytes: u64,
+}
+
+#[derive(Debug, Error)]
+pub enum DefragError {
+    #[error("waiting for the device to be idle failed")]
+    DeviceWait(vk::Result),
+
+    #[error("creation of the moved buffer failed")]
+    BufferCreation(#[from] BufferBuildError),
+
+    #[error("copy to the moved buffer failed")]
+    Copy(#[from] ImmediateCommandError),
+}
+
+struct RelocatableSlot {
+    buffer: Buffer,
+    // creates the buffer again elsewhere, once renamed after the next generation
+    builder: BufferBuilder,
+    id: u64,
+    generation: u64,
+}
+
+impl RelocatableSlot {
+    fn allocation_name(&self, generation: u64) -> String {
+        relocatable_allocation_name(&self.builder.name, self.id, generation)
+    }
+}
+
+// unique to every buffer and generation, see `block_index_of`
+fn relocatable_allocation_name(name: &str, id: u64, generation: u64) -> String {
+    format!("{name} (relocatable {id}, generation {generation})")
+}
+
+/// GPU-only buffer which [`Context::defragment_now`] may move out of a sparse memory block.
+///
+/// Moving the buffer changes its [handle](Self::handle) and bumps its
+/// [generation](Self::generation), descriptors and command buffers referencing the previous
+/// handle must then be updated, as when a growing buffer is replaced. The previous buffer is kept
+/// alive until the frames in flight are done with it.
+#[derive(Clone)]
+pub struct RelocatableBuffer {
+    slot: ThreadSafeRef<RelocatableSlot>,
+}
+
+impl RelocatableBuffer {
+    /// The buffer is made GPU-only, usable as a transfer source and destination for the copies
+    /// moving it, and shared with the transfer queue they are recorded on.
+    pub fn new(builder: BufferBuilder, ctx: &mut Context) -> Result<Self, BufferBuildError> {
+        let upload_queue_families = ctx.core.device_ref.read().upload_queue_families();
+        let usage =
+            builder.usage | vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST;
+        let builder = builder
+            .with_usage(usage)
+            .with_memory_location(gpu_allocator::MemoryLocation::GpuOnly)
+            .with_shared_queue_families(&upload_queue_families);
+        let id = ctx.defragmenter.next_id();
+        let buffer = builder
+            .clone()
+            .with_name(&relocatable_allocation_name(&builder.name, id, 0))
+            .build_suballocated(ctx.core.device_ref.clone(), ctx.core.allocator_ref.clone())?;
+        let slot = ThreadSafeRef::new(RelocatableSlot {
+            buffer,
+            builder,
+            id,
+            generation: 0,
+        });
+        ctx.defragmenter.buffers.push(slot.downgrade());
+
+        Ok(Self { slot })
+    }
+
+    pub fn handle(&self) -> vk::Buffer {
+        self.slot.lock().buffer.handle
+    }
+
+    pub fn size(&self) -> u64 {
+        self.slot.lock().buffer.size()
+    }
+
+    /// Bumped every time the buffer moves.
+    pub fn generation(&self) -> u64 {
+        self.slot.lock().generation
+    }
+}
+
+/// Relocatable buffers created from a context, which are the only allocations it can move.
+#[derive(Default)]
+pub(crate) struct Defragmenter {
+    buffers: Vec<Weak<Mutex<RelocatableSlot>>>,
+    next_id: u64,
+    pub idle: Option<IdleDefragmentation>,
+}
+
+impl Defragmenter {
+    fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    /// Moves the relocatable buffers picked by [`select_candidates`] within `budget_bytes`,
+    /// waiting for the device to be idle first when there is any.
+    pub fn defragment(
+        &mut self,
+        budget_bytes: u64,
+        core: &GpuCore,
+        deletion_queue: &ThreadSafeRef<DeletionQueue>,
+    ) -> Result<DefragReport, DefragError> {
+        self.buffers.retain(|slot| slot.strong_count() > 0);
+        let blocks = core.allocator_ref.lock().block_occupancy();
+        let moves = select_candidates(&blocks, DEFAULT_SPARSE_THRESHOLD, budget_bytes)
+            .into_iter()
+            .filter_map(|candidate| {
+                self.buffers
+                    .iter()
+                    .filter_map(ThreadSafeRef::upgrade)
+                    .find(|slot| {
+                        let slot = slot.lock();
+                        slot.allocation_name(slot.generation) == candidate.name
+                    })
+            })
+            .collect::<Vec<_>>();
+        if moves.is_empty() {
+            return Ok(DefragReport::default());
+        }
+
+        // buffers may still be read or written by the frames in flight
+        unsafe { core.device_ref.read().device_wait_idle() }.map_err(DefragError::DeviceWait)?;
+
+        let mut report = DefragReport::default();
+        for slot in moves {
+            let mut slot = slot.lock();
+            let generation = slot.generation + 1;
+            let allocation_name = slot.allocation_name(generation);
+            let moved = slot
+                .builder
+                .clone()
+                .with_name(&allocation_name)
+                .build_suballocated(core.device_ref.clone(), core.allocator_ref.clone())?;
+
+            // the allocator picks the block, landing in a sparse one would not release anything
+            let blocks = core.allocator_ref.lock().block_occupancy();
+            let landed_in_sparse_block =
+                block_index_of(&blocks, &allocation_name).is_none_or(|block_index| {
+                    blocks[block_index].occupancy() < DEFAULT_SPARSE_THRESHOLD
+                });
+            if landed_in_sparse_block {
+                log::debug!("no dense block has room for \"{allocation_name}\", not moving it");
+                continue;
+            }
+
+            let size = slot.buffer.size();
+            core.command_manager.transfer_command(|cmd_buffer| {
+                let region = vk::BufferCopy::default().size(size);
+                unsafe {
+                    core.device_ref.read().cmd_copy_buffer(
+                        *cmd_buffer,
+                        slot.buffer.handle,
+                        moved.handle,
+                        std::slice::from_ref(&region),
+                    )
+                };
+            })?;
+
+            let previous = std::mem::replace(&mut slot.buffer, moved);
+            deletion_queue.lock().defer(previous);
+            slot.generation = generation;
+            report.moved_buffers += 1;
+            report.moved_bytes += size;
+        }
+        log::debug!(
+            "defragmentation moved {} buffers ({} bytes)",
+            report.moved_buffers,
+            report.moved_bytes
+        );
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(size: u64, allocations: &[(&str, u64)]) -> BlockOccupancy {
+        BlockOccupancy {
+            size,
+            allocations: allocations
+                .iter()
+                .map(|&(name, size)| (name.to_owned(), size))
+                .collect(),
+        }
+    }
+
+    fn picked(candidates: &[DefragCandidate]) -> Vec<(usize, &str)> {
+        candidates
+            .iter()
+            .map(|candidate| (candidate.block_index, candidate.name.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn sparse_blocks_are_ordered_by_occupancy_then_index() {
+        let blocks = [
+            block(100, &[("a", 20)]),
+            block(100, &[("b", 10)]),
+            block(100, &[("c", 90)]),
+            block(100, &[]),
+            block(100, &[("d", 10)]),
+            block(0, &[("zero sized", 0)]),
+        ];
+
+        assert_eq!(sparse_block_indices(&blocks, 0.25), [1, 4, 0]);
+    }
+
+    #[test]
+    fn threshold_is_exclusive() {
+        let blocks = [block(100, &[("a", 25)]), block(100, &[("b", 24)])];
+
+        assert_eq!(sparse_block_indices(&blocks, 0.25), [1]);
+    }
+
+    #[test]
+    fn stats_count_sparse_blocks_and_their_bytes() {
+        let blocks = [
+            block(256, &[("a", 16), ("b", 16)]),
+            block(128, &[("c", 120)]),
+            block(512, &[]),
+            block(64, &[("d", 8)]),
+        ];
+
+        assert_eq!(
+            DefragStats::from_blocks(&blocks, DEFAULT_SPARSE_THRESHOLD),
+            DefragStats {
+                block_count: 4,
+                sparse_block_count: 2,
+                reclaimable_bytes: 256 + 64,
+                movable_bytes: 32 + 8,
+            }
+        );
+    }
+
+    #[test]
+    fn sparsest_blocks_are_emptied_first_smallest_allocations_first() {
+        let blocks = [
+            block(100, &[("large", 15), ("small", 5)]),
+            block(100, &[("dense", 90)]),
+            block(100, &[("b", 5), ("a", 5)]),
+        ];
+
+        let candidates = select_candidates(&blocks, DEFAULT_SPARSE_THRESHOLD, u64::MAX);
+
+        // equal sizes fall back to the names
+        assert_eq!(
+            picked(&candidates),
+            [(2, "a"), (2, "b"), (0, "small"), (0, "large")]
+        );
+        assert_eq!(candidates[3].size, 15);
+    }
+
+    #[test]
+    fn budget_stops_at_the_first_allocation_that_does_not_fit() {
+        let blocks = [
+            block(100, &[("first", 4), ("second", 10)]),
+            block(100, &[("dense", 90)]),
+            block(100, &[("later", 1), ("later too", 12)]),
+        ];
+
+        let candidates = select_candidates(&blocks, DEFAULT_SPARSE_THRESHOLD, 10);
+
+        // the allocations of the next block would fit, but are not picked out of order
+        assert_eq!(picked(&candidates), [(2, "later")]);
+        assert!(select_candidates(&blocks, DEFAULT_SPARSE_THRESHOLD, 0).is_empty());
+    }
+
+    #[test]
+    fn nothing_is_picked_when_every_used_block_is_sparse() {
+        let blocks = [
+            block(100, &[("a", 10)]),
+            block(100, &[]),
+            block(100, &[("b", 20)]),
+        ];
+
+        assert!(select_candidates(&blocks, DEFAULT_SPARSE_THRESHOLD, u64::MAX).is_empty());
+        assert!(select_candidates(&[], DEFAULT_SPARSE_THRESHOLD, u64::MAX).is_empty());
+    }
+
+    #[test]
+    fn moved_allocations_are_found_by_their_unique_name() {
+        let first = relocatable_allocation_name("particles", 1, 0);
+        let moved = relocatable_allocation_name("particles", 1, 1);
+        let other = relocatable_allocation_name("particles", 2, 0);
+        let blocks = [
+            block(100, &[(&other, 10)]),
+            block(100, &[("dense", 80), (&moved, 10)]),
+        ];
+
+        assert_ne!(first, moved);
+        assert_ne!(first, other);
+        assert_eq!(block_index_of(&blocks, &moved), Some(1));
+        assert_eq!(block_index_of(&blocks, &other), Some(0));
+        assert_eq!(block_index_of(&blocks, &first), None);
+    }
+}
@@ -0,0 +1,30 @@
+use std::any::Any;
+
+use super::commands::is_frame_complete;
+
+/// Keeps GPU resources alive until the frames that may still reference them have completed.
+#[derive(Default)]
+pub(crate) struct DeletionQueue {
+    // tagged with the frame being recorded when they were deferred
+    pending: Vec<(u64, Box<dyn Any>)>,
+    frame_index: u64,
+}
+
+impl DeletionQueue {
+    pub fn defer<T: 'static>(&mut self, resource: T) {
+        self.pending.push((self.frame_index, Box::new(resource)));
+    }
+
+    /// Drops the resources no frame in flight can reference anymore, called once the frame slot of
+    /// `frame_index` was waited on.
+    pub fn flush(&mut self, frame_index: u64) {
+        self.pending
+            .retain(|&(frame, _)| !is_frame_complete(frame, frame_index));
+        self.frame_index = frame_index;
+    }
+
+    /// Must only be called once the device is idle.
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+}
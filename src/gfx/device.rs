@@ -1,9 +1,20 @@
-use std::{cmp::Ordering, collections::HashMap, ffi::CStr, ops::Deref};
+use std::{
+    collections::HashMap,
+    ffi::{CStr, CString},
+    ops::Deref,
+};
 
 use ash::vk::{self, QueueFlags};
 use thiserror::Error;
 
-use super::{instance::Instance, surface::Surface};
+use super::{
+    breadcrumbs::{BreadcrumbBackend, DeviceFaultReport},
+    features::{DeviceFeatureRequest, ExtensionRequirement},
+    handle_registry::HandleRegistry,
+    instance::{Instance, dedup_names},
+    overrides::DeviceSelection,
+    surface::Surface,
+};
 
 fn vendor_id_to_str(vendor_id: u32) -> &'static str {
     match vendor_id {
@@ -31,6 +42,11 @@ pub struct PhysicalDevice {
     pub handle: vk::PhysicalDevice,
     pub properties: vk::PhysicalDeviceProperties,
     pub graphics_qf_index: u32,
+    pub present_qf_index: u32,
+    /// Same as the graphics one when the device has no family for transfers only.
+    pub transfer_qf_index: u32,
+    /// Same as the graphics one when the device has no compute family without graphics.
+    pub compute_qf_index: u32,
 }
 
 #[derive(Debug, Error)]
@@ -43,13 +59,47 @@ pub enum PhysicalDeviceSelectError {
     DeviceNameConversion(#[from] std::str::Utf8Error),
     #[error("no valid device detected")]
     NoDevice,
+    #[error("requested device {0:?} is not available or not compatible")]
+    RequestedDeviceUnavailable(DeviceSelection),
+    #[error("custom device selection returned index {index} among {candidates} candidates")]
+    CustomSelectionOutOfRange { index: usize, candidates: usize },
+}
+
+/// Compatible device given to [`DeviceSelection::Custom`].
+#[derive(Debug, Clone)]
+pub struct PhysicalDeviceInfo {
+    /// Index in the order devices are enumerated by the driver.
+    pub enumeration_index: usize,
+    pub name: String,
+    pub properties: vk::PhysicalDeviceProperties,
+    pub graphics_qf_index: u32,
+    /// Same as the graphics one when it can present.
+    pub present_qf_index: u32,
+}
+
+// lower comes first
+fn device_type_rank(
+    device_type: vk::PhysicalDeviceType,
+    preferred_type: vk::PhysicalDeviceType,
+) -> u8 {
+    match device_type {
+        _ if device_type == preferred_type => 0,
+        vk::PhysicalDeviceType::DISCRETE_GPU => 1,
+        vk::PhysicalDeviceType::INTEGRATED_GPU => 2,
+        vk::PhysicalDeviceType::VIRTUAL_GPU => 3,
+        vk::PhysicalDeviceType::CPU => 4,
+        _ => 5,
+    }
 }
 
 impl PhysicalDevice {
     pub(crate) fn select(
         instance: &Instance,
         minimum_vk_version: u32,
-        target_surface: &Surface,
+        target_surface: Option<&Surface>,
+        selection: &DeviceSelection,
+        requested_features: &DeviceFeatureRequest,
+        extra_extensions: &[(CString, ExtensionRequirement)],
     ) -> Result<Self, PhysicalDeviceSelectError> {
         log::debug!("Started physical device selection");
         // SAFETY: This is safe as long as the entry used to create the instance is still alive.
@@ -66,6 +116,8 @@ impl PhysicalDevice {
                 })
             })
             .collect();
+        let enumeration_order: Vec<_> =
+            physical_devices.iter().map(|&(handle, _)| handle).collect();
 
         log::debug!("Initial device list:");
         for (_, device_info) in &physical_devices {
@@ -86,11 +138,19 @@ impl PhysicalDevice {
 
                 // Device extension check
                 let mut required_extensions: HashMap<&CStr, bool> = [
-                    (ash::khr::swapchain::NAME, false),
                     (ash::khr::dynamic_rendering::NAME, false),
                     // Other required device extensions go here
                 ]
                 .into();
+                // offscreen rendering has no swapchain
+                if target_surface.is_some() {
+                    required_extensions.insert(ash::khr::swapchain::NAME, false);
+                }
+                for (name, requirement) in extra_extensions {
+                    if *requirement == ExtensionRequirement::Required {
+                        required_extensions.insert(name.as_c_str(), false);
+                    }
+                }
                 // SAFETY: This is safe as long as the entry used to create the instance is still alive.
                 let supported_extensions = unsafe {
                     instance.enumerate_device_extension_properties(device_handle)
@@ -120,6 +180,26 @@ impl PhysicalDevice {
                     }
                 }
 
+                // SAFETY: This is safe as long as the entry used to create the instance is still alive.
+                let supported_features =
+                    unsafe { instance.get_physical_device_features(device_handle) };
+                let missing_features = requested_features.missing_required(&supported_features);
+                if !missing_features.is_empty() {
+                    log::debug!(
+                        "\t{} lacks required features {:?}",
+                        device_info
+                            .device_name_as_c_str()
+                            .unwrap_or(c"INVALID")
+                            .to_str()
+                            .unwrap_or("INVALID"),
+                        missing_features
+                            .iter()
+                            .map(|feature| feature.name())
+                            .collect::<Vec<_>>()
+                    );
+                    return false;
+                }
+
                 true
             })
             .collect();
@@ -139,23 +219,13 @@ impl PhysicalDevice {
                 // SAFETY: This is safe as long as the entry used to create the instance is still alive.
                 let qf_properties =
                     unsafe { instance.get_physical_device_queue_family_properties(device_handle) };
-                for (qf_index, queue_family) in qf_properties.iter().enumerate() {
-                    let qf_index = qf_index as u32;
-                    if !queue_family.queue_flags.contains(QueueFlags::GRAPHICS) {
-                        continue;
-                    }
-                    if !queue_family.queue_flags.contains(QueueFlags::COMPUTE) {
-                        continue;
-                    }
-
-                    let device = Self {
-                        handle: device_handle,
-                        properties: device_info,
-                        graphics_qf_index: qf_index,
+                let supports_present = |qf_index: u32| {
+                    // without a surface there is nothing to present to, any family will do
+                    let Some(target_surface) = target_surface else {
+                        return true;
                     };
-
                     // SAFETY: This is safe as long as the entry used to create this loader is still alive.
-                    let is_surface_compatible = unsafe {
+                    unsafe {
                         target_surface.loader.get_physical_device_surface_support(
                             device_handle,
                             qf_index,
@@ -164,19 +234,66 @@ impl PhysicalDevice {
                     }
                     .inspect_err(|err| {
                         log::warn!(
-                            "Failed to get surface compatibility for device {} ({err}), ignoring.",
-                            device.debug_string()
+                            "Failed to get surface compatibility for queue family {qf_index} ({err}), ignoring."
                         );
                     })
-                    .unwrap_or(false);
-                    if !is_surface_compatible {
-                        continue;
-                    }
-
-                    return Some(device);
-                }
-
-                None
+                    .unwrap_or(false)
+                };
+
+                let graphics_families: Vec<_> = qf_properties
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, queue_family)| {
+                        queue_family
+                            .queue_flags
+                            .contains(QueueFlags::GRAPHICS | QueueFlags::COMPUTE)
+                    })
+                    .map(|(qf_index, _)| qf_index as u32)
+                    .collect();
+
+                // a single family doing both keeps presentation on the graphics queue
+                let (graphics_qf_index, present_qf_index) = match graphics_families
+                    .iter()
+                    .find(|&&qf_index| supports_present(qf_index))
+                {
+                    Some(&qf_index) => (qf_index, qf_index),
+                    None => (
+                        *graphics_families.first()?,
+                        (0..qf_properties.len() as u32).find(|&qf_index| supports_present(qf_index))?,
+                    ),
+                };
+
+                // uploads run alongside rendering on a family without graphics, ideally one made for
+                // transfers only
+                let transfer_qf_index = qf_properties
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, queue_family)| {
+                        queue_family.queue_flags.contains(QueueFlags::TRANSFER)
+                            && !queue_family.queue_flags.contains(QueueFlags::GRAPHICS)
+                    })
+                    .min_by_key(|(_, queue_family)| {
+                        queue_family.queue_flags.contains(QueueFlags::COMPUTE)
+                    })
+                    .map_or(graphics_qf_index, |(qf_index, _)| qf_index as u32);
+
+                // async compute overlaps rendering only from a family the graphics queue is not in
+                let compute_qf_index = qf_properties
+                    .iter()
+                    .position(|queue_family| {
+                        queue_family.queue_flags.contains(QueueFlags::COMPUTE)
+                            && !queue_family.queue_flags.contains(QueueFlags::GRAPHICS)
+                    })
+                    .map_or(graphics_qf_index, |qf_index| qf_index as u32);
+
+                Some(Self {
+                    handle: device_handle,
+                    properties: device_info,
+                    graphics_qf_index,
+                    present_qf_index,
+                    transfer_qf_index,
+                    compute_qf_index,
+                })
             })
             .collect();
 
@@ -185,20 +302,17 @@ impl PhysicalDevice {
             log::debug!("\t{}", device.debug_string());
         }
 
-        compatible_queue_families.sort_by(|a, b| {
-            let mut ordering = Ordering::Equal;
-            if a.properties.device_type == vk::PhysicalDeviceType::DISCRETE_GPU
-                && b.properties.device_type != vk::PhysicalDeviceType::DISCRETE_GPU
-            {
-                ordering = Ordering::Greater;
-            }
-            if a.properties.device_type != vk::PhysicalDeviceType::DISCRETE_GPU
-                && b.properties.device_type == vk::PhysicalDeviceType::DISCRETE_GPU
-            {
-                ordering = Ordering::Less;
-            }
-
-            ordering
+        // discrete GPUs first, then presenting from the graphics family which spares sharing the
+        // swapchain images, the sort being stable the driver order breaks ties
+        let preferred_type = match selection {
+            DeviceSelection::PreferIntegrated => vk::PhysicalDeviceType::INTEGRATED_GPU,
+            _ => vk::PhysicalDeviceType::DISCRETE_GPU,
+        };
+        compatible_queue_families.sort_by_key(|device| {
+            (
+                device_type_rank(device.properties.device_type, preferred_type),
+                device.has_separate_present_queue(),
+            )
         });
 
         log::debug!("Device list after ordering:");
@@ -206,27 +320,101 @@ impl PhysicalDevice {
             log::debug!("\t{}", device.debug_string());
         }
 
-        let selected_device = compatible_queue_families
-            .into_iter()
-            .next()
-            .ok_or(PhysicalDeviceSelectError::NoDevice)?;
+        let enumeration_index = |device: &PhysicalDevice| {
+            enumeration_order
+                .iter()
+                .position(|&handle| handle == device.handle)
+                .unwrap_or_default()
+        };
+        let (position, reason) = match selection {
+            DeviceSelection::Auto | DeviceSelection::PreferDiscrete => {
+                (0, "discrete GPUs preferred".to_owned())
+            }
+            DeviceSelection::PreferIntegrated => (0, "integrated GPUs preferred".to_owned()),
+            DeviceSelection::ByIndex(index) => (
+                compatible_queue_families
+                    .iter()
+                    .position(|device| enumeration_index(device) == *index)
+                    .ok_or_else(|| {
+                        PhysicalDeviceSelectError::RequestedDeviceUnavailable(selection.clone())
+                    })?,
+                format!("requested by index {index}"),
+            ),
+            DeviceSelection::ByName(name) => (
+                compatible_queue_families
+                    .iter()
+                    .position(|device| device.name().to_lowercase().contains(&name.to_lowercase()))
+                    .ok_or_else(|| {
+                        PhysicalDeviceSelectError::RequestedDeviceUnavailable(selection.clone())
+                    })?,
+                format!("requested by name \"{name}\""),
+            ),
+            DeviceSelection::Custom(scorer) => {
+                let candidates = compatible_queue_families
+                    .iter()
+                    .map(|device| PhysicalDeviceInfo {
+                        enumeration_index: enumeration_index(device),
+                        name: device.name(),
+                        properties: device.properties,
+                        graphics_qf_index: device.graphics_qf_index,
+                        present_qf_index: device.present_qf_index,
+                    })
+                    .collect::<Vec<_>>();
+                let position = scorer.select(&candidates);
+                if position >= candidates.len() {
+                    return Err(PhysicalDeviceSelectError::CustomSelectionOutOfRange {
+                        index: position,
+                        candidates: candidates.len(),
+                    });
+                }
+
+                (position, "chosen by the custom selection".to_owned())
+            }
+        };
+        if compatible_queue_families.is_empty() {
+            return Err(PhysicalDeviceSelectError::NoDevice);
+        }
+        let selected_device = compatible_queue_families.swap_remove(position);
 
-        log::info!("Physical device selection result:");
+        log::info!("Physical device selection result ({reason}):");
         log::info!("{}", selected_device.debug_string());
 
         Ok(selected_device)
     }
 
-    pub fn debug_string(&self) -> String {
-        let device_name = self
-            .properties
+    pub fn name(&self) -> String {
+        self.properties
             .device_name_as_c_str()
-            .unwrap_or(c"INVALID")
-            .to_str()
-            .unwrap_or("INVALID");
+            .ok()
+            .and_then(|name| name.to_str().ok())
+            .unwrap_or("INVALID")
+            .to_owned()
+    }
+
+    pub fn debug_string(&self) -> String {
+        let device_name = self.name();
         let device_type = device_type_to_str(self.properties.device_type);
         let device_vendor = vendor_id_to_str(self.properties.vendor_id);
-        format!("{} [{}]: {}", device_name, device_vendor, device_type)
+        let present_family = match self.has_separate_present_queue() {
+            true => format!(", presenting from queue family {}", self.present_qf_index),
+            false => String::new(),
+        };
+        format!(
+            "{} [{}]: {}{}",
+            device_name, device_vendor, device_type, present_family
+        )
+    }
+
+    pub fn has_separate_present_queue(&self) -> bool {
+        self.graphics_qf_index != self.present_qf_index
+    }
+
+    pub fn has_dedicated_transfer_queue(&self) -> bool {
+        self.graphics_qf_index != self.transfer_qf_index
+    }
+
+    pub fn has_async_compute_queue(&self) -> bool {
+        self.graphics_qf_index != self.compute_qf_index
     }
 }
 
@@ -246,6 +434,25 @@ impl Deref for DeviceQueue {
 pub struct Device {
     pub loader: ash::Device,
     pub graphics_queue: DeviceQueue,
+    /// Same queue as `graphics_queue` unless the graphics family cannot present.
+    pub present_queue: DeviceQueue,
+    /// Same queue as `graphics_queue` unless the device has a family without graphics for
+    /// transfers.
+    pub transfer_queue: DeviceQueue,
+    /// Same queue as `graphics_queue` unless the device has a compute family without graphics,
+    /// see [`Self::has_async_compute`].
+    pub compute_queue: DeviceQueue,
+
+    pub enabled_extensions: Vec<CString>,
+    pub enabled_features: vk::PhysicalDeviceFeatures,
+    /// Whether swapchain images can be viewed with another format than theirs, which gives
+    /// [`ResourceID::SwapchainColorAttachmentUnorm`](super::render_graph::resource::ResourceID)
+    /// a view of its own.
+    pub swapchain_mutable_format: bool,
+    pub(crate) breadcrumb_backend: BreadcrumbBackend,
+    // set when VK_EXT_device_fault is enabled
+    pub(crate) fault_loader: Option<ash::ext::device_fault::Device>,
+    pub(crate) handle_registry: HandleRegistry,
 }
 
 impl Deref for Device {
@@ -266,26 +473,131 @@ impl Device {
     pub(crate) fn create(
         instance: &Instance,
         physical_device: &PhysicalDevice,
+        requested_features: &DeviceFeatureRequest,
+        extra_extensions: &[(CString, ExtensionRequirement)],
     ) -> Result<Self, DeviceCreateError> {
-        let features = vk::PhysicalDeviceFeatures::default();
+        // SAFETY: This is safe as long as the entry used to create the instance is still alive.
+        let supported_features =
+            unsafe { instance.get_physical_device_features(physical_device.handle) };
+        let features = requested_features.enabled(&supported_features);
+        for &(feature, _) in &requested_features.features {
+            if !feature.is_set(&features) {
+                log::info!(
+                    "optional device feature {} is not supported",
+                    feature.name()
+                );
+            }
+        }
         let mut dynamic_rendering_feature =
             vk::PhysicalDeviceDynamicRenderingFeatures::default().dynamic_rendering(true);
+        // core and always supported since Vulkan 1.3, barriers and submissions rely on it
+        let mut synchronization2_feature =
+            vk::PhysicalDeviceSynchronization2Features::default().synchronization2(true);
+        // core and always supported since Vulkan 1.2, immediate commands signal one
+        let mut timeline_semaphore_feature =
+            vk::PhysicalDeviceTimelineSemaphoreFeatures::default().timeline_semaphore(true);
 
-        let extensions = [
-            ash::khr::swapchain::NAME.as_ptr(),
-            ash::khr::dynamic_rendering::NAME.as_ptr(),
-        ];
+        let mut extensions = vec![ash::khr::dynamic_rendering::NAME.as_ptr()];
+
+        // SAFETY: This is safe as long as the entry used to create the instance is still alive.
+        let available_extensions =
+            unsafe { instance.enumerate_device_extension_properties(physical_device.handle) }
+                .unwrap_or_default();
+        let is_available = |name: &CStr| {
+            available_extensions
+                .iter()
+                .any(|extension| extension.extension_name_as_c_str() == Ok(name))
+        };
+
+        // required when selecting the device for a surface, headless devices may lack it
+        let swapchain = is_available(ash::khr::swapchain::NAME);
+        if swapchain {
+            extensions.push(ash::khr::swapchain::NAME.as_ptr());
+        }
+
+        // its image format list and maintenance2 dependencies are part of Vulkan 1.2
+        let swapchain_mutable_format =
+            swapchain && is_available(ash::khr::swapchain_mutable_format::NAME);
+        if swapchain_mutable_format {
+            extensions.push(ash::khr::swapchain_mutable_format::NAME.as_ptr());
+        }
+
+        // GPU breadcrumbs are a debugging aid, vendor extensions are only worth enabling for them
+        let breadcrumb_extension = if cfg!(debug_assertions) {
+            [
+                ash::nv::device_diagnostic_checkpoints::NAME,
+                ash::amd::buffer_marker::NAME,
+            ]
+            .into_iter()
+            .find(|&name| is_available(name))
+        } else {
+            None
+        };
+        if let Some(name) = breadcrumb_extension {
+            extensions.push(name.as_ptr());
+        }
+
+        // reports what caused a device loss, only enabled when the feature is there as well
+        let device_fault = is_available(ash::ext::device_fault::NAME) && {
+            let mut fault_features = vk::PhysicalDeviceFaultFeaturesEXT::default();
+            let mut features2 =
+                vk::PhysicalDeviceFeatures2::default().push_next(&mut fault_features);
+            // SAFETY: This is safe as long as the entry used to create the instance is still alive.
+            unsafe {
+                instance.get_physical_device_features2(physical_device.handle, &mut features2)
+            };
+            fault_features.device_fault == vk::TRUE
+        };
+        let mut fault_feature = vk::PhysicalDeviceFaultFeaturesEXT::default().device_fault(true);
+        if device_fault {
+            extensions.push(ash::ext::device_fault::NAME.as_ptr());
+        }
+
+        // required ones were checked when selecting the device
+        for (name, requirement) in extra_extensions {
+            if *requirement == ExtensionRequirement::Required || is_available(name) {
+                extensions.push(name.as_ptr());
+            } else {
+                log::info!("optional device extension {name:?} is not supported");
+            }
+        }
+        // the application may request the ones the engine enables itself
+        dedup_names(&mut extensions);
+        // SAFETY: every pointer comes from a NUL-terminated string outliving the device creation
+        let enabled_extensions = extensions
+            .iter()
+            .map(|&ptr| unsafe { CStr::from_ptr(ptr) }.to_owned())
+            .collect();
 
         let queue_priorities = [1.0];
-        let queue_infos = [vk::DeviceQueueCreateInfo::default()
-            .queue_family_index(physical_device.graphics_qf_index)
-            .queue_priorities(&queue_priorities)];
+        // a single queue per family, whichever roles it has
+        let mut queue_families = vec![
+            physical_device.graphics_qf_index,
+            physical_device.present_qf_index,
+            physical_device.transfer_qf_index,
+            physical_device.compute_qf_index,
+        ];
+        queue_families.sort_unstable();
+        queue_families.dedup();
+        let queue_infos = queue_families
+            .into_iter()
+            .map(|qf_index| {
+                vk::DeviceQueueCreateInfo::default()
+                    .queue_family_index(qf_index)
+                    .queue_priorities(&queue_priorities)
+            })
+            .collect::<Vec<_>>();
 
-        let create_info = vk::DeviceCreateInfo::default()
+        let mut create_info = vk::DeviceCreateInfo::default()
             .enabled_features(&features)
             .enabled_extension_names(&extensions)
             .queue_create_infos(&queue_infos)
-            .push_next(&mut dynamic_rendering_feature);
+            .push_next(&mut dynamic_rendering_feature)
+            .push_next(&mut synchronization2_feature)
+            .push_next(&mut timeline_semaphore_feature);
+        if device_fault {
+            create_info = create_info.push_next(&mut fault_feature);
+        }
 
         // SAFETY: This is safe as long as the entry used to create the instance is still alive.
         let loader = unsafe { instance.create_device(physical_device.handle, &create_info, None) }
@@ -298,12 +610,104 @@ impl Device {
             handle: graphics_queue_handle,
             family_index: physical_device.graphics_qf_index,
         };
+        // SAFETY: This is safe as long as the entry used to create this loader is still alive.
+        let present_queue_handle =
+            unsafe { loader.get_device_queue(physical_device.present_qf_index, 0) };
+        let present_queue = DeviceQueue {
+            handle: present_queue_handle,
+            family_index: physical_device.present_qf_index,
+        };
+        if physical_device.has_separate_present_queue() {
+            log::info!(
+                "presenting from queue family {} instead of graphics family {}",
+                present_queue.family_index,
+                graphics_queue.family_index
+            );
+        }
+        // SAFETY: This is safe as long as the entry used to create this loader is still alive.
+        let transfer_queue_handle =
+            unsafe { loader.get_device_queue(physical_device.transfer_qf_index, 0) };
+        let transfer_queue = DeviceQueue {
+            handle: transfer_queue_handle,
+            family_index: physical_device.transfer_qf_index,
+        };
+        if physical_device.has_dedicated_transfer_queue() {
+            log::info!(
+                "uploading from dedicated transfer queue family {}",
+                transfer_queue.family_index
+            );
+        }
+
+        // SAFETY: This is safe as long as the entry used to create this loader is still alive.
+        let compute_queue_handle =
+            unsafe { loader.get_device_queue(physical_device.compute_qf_index, 0) };
+        let compute_queue = DeviceQueue {
+            handle: compute_queue_handle,
+            family_index: physical_device.compute_qf_index,
+        };
+        if physical_device.has_async_compute_queue() {
+            log::info!(
+                "running async compute on queue family {}",
+                compute_queue.family_index
+            );
+        }
+
+        let breadcrumb_backend = BreadcrumbBackend::select(instance, &loader, breadcrumb_extension);
+        log::debug!("GPU breadcrumbs backend: {breadcrumb_backend:?}");
+        let fault_loader =
+            device_fault.then(|| ash::ext::device_fault::Device::new(instance, &loader));
 
         Ok(Self {
             loader,
             graphics_queue,
+            present_queue,
+            transfer_queue,
+            compute_queue,
+            enabled_extensions,
+            enabled_features: features,
+            swapchain_mutable_format,
+            breadcrumb_backend,
+            fault_loader,
+            handle_registry: HandleRegistry::new(),
         })
     }
+
+    /// What the driver reports about a device loss, `None` without `VK_EXT_device_fault` or when
+    /// the query fails. Only meaningful once the device has been lost.
+    pub fn fault_report(&self) -> Option<DeviceFaultReport> {
+        DeviceFaultReport::query(self.fault_loader.as_ref()?)
+    }
+
+    /// Families to share the buffers filled on the transfer queue and used on the graphics one
+    /// between, given to [`BufferBuilder`](super::buffer::BufferBuilder). Empty when both are the
+    /// same family.
+    pub fn upload_queue_families(&self) -> Vec<u32> {
+        match self.graphics_queue.family_index == self.transfer_queue.family_index {
+            true => vec![],
+            false => vec![
+                self.graphics_queue.family_index,
+                self.transfer_queue.family_index,
+            ],
+        }
+    }
+
+    /// Whether compute passes marked as async run on a queue of their own, instead of being
+    /// recorded along the graphics commands.
+    pub fn has_async_compute(&self) -> bool {
+        self.graphics_queue.family_index != self.compute_queue.family_index
+    }
+
+    /// Same as [`Self::upload_queue_families`] for the resources written by async compute passes
+    /// and used by the graphics queue, or the other way around.
+    pub fn async_compute_queue_families(&self) -> Vec<u32> {
+        match self.has_async_compute() {
+            true => vec![
+                self.graphics_queue.family_index,
+                self.compute_queue.family_index,
+            ],
+            false => vec![],
+        }
+    }
 }
 
 impl Drop for Device {
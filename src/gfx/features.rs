@@ -0,0 +1,162 @@
+use ash::vk;
+
+/// Core Vulkan 1.0 feature which can be requested through [`DeviceFeatureRequest`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum DeviceFeature {
+    SamplerAnisotropy,
+    FillModeNonSolid,
+    WideLines,
+    LargePoints,
+    DepthBiasClamp,
+    DepthBounds,
+    DepthClamp,
+    MultiDrawIndirect,
+    DrawIndirectFirstInstance,
+    GeometryShader,
+    TessellationShader,
+    ShaderInt64,
+    ShaderFloat64,
+    ShaderInt16,
+    IndependentBlend,
+    TextureCompressionBc,
+}
+
+impl DeviceFeature {
+    /// Name of the feature in the Vulkan specification, e.g. `samplerAnisotropy`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::SamplerAnisotropy => "samplerAnisotropy",
+            Self::FillModeNonSolid => "fillModeNonSolid",
+            Self::WideLines => "wideLines",
+            Self::LargePoints => "largePoints",
+            Self::DepthBiasClamp => "depthBiasClamp",
+            Self::DepthBounds => "depthBounds",
+            Self::DepthClamp => "depthClamp",
+            Self::MultiDrawIndirect => "multiDrawIndirect",
+            Self::DrawIndirectFirstInstance => "drawIndirectFirstInstance",
+            Self::GeometryShader => "geometryShader",
+            Self::TessellationShader => "tessellationShader",
+            Self::ShaderInt64 => "shaderInt64",
+            Self::ShaderFloat64 => "shaderFloat64",
+            Self::ShaderInt16 => "shaderInt16",
+            Self::IndependentBlend => "independentBlend",
+            Self::TextureCompressionBc => "textureCompressionBC",
+        }
+    }
+
+    fn flag(self, features: &mut vk::PhysicalDeviceFeatures) -> &mut vk::Bool32 {
+        match self {
+            Self::SamplerAnisotropy => &mut features.sampler_anisotropy,
+            Self::FillModeNonSolid => &mut features.fill_mode_non_solid,
+            Self::WideLines => &mut features.wide_lines,
+            Self::LargePoints => &mut features.large_points,
+            Self::DepthBiasClamp => &mut features.depth_bias_clamp,
+            Self::DepthBounds => &mut features.depth_bounds,
+            Self::DepthClamp => &mut features.depth_clamp,
+            Self::MultiDrawIndirect => &mut features.multi_draw_indirect,
+            Self::DrawIndirectFirstInstance => &mut features.draw_indirect_first_instance,
+            Self::GeometryShader => &mut features.geometry_shader,
+            Self::TessellationShader => &mut features.tessellation_shader,
+            Self::ShaderInt64 => &mut features.shader_int64,
+            Self::ShaderFloat64 => &mut features.shader_float64,
+            Self::ShaderInt16 => &mut features.shader_int16,
+            Self::IndependentBlend => &mut features.independent_blend,
+            Self::TextureCompressionBc => &mut features.texture_compression_bc,
+        }
+    }
+
+    /// Whether the feature is set in `features`, e.g. the ones given by
+    /// [`Context::enabled_features`](super::context::Context::enabled_features).
+    pub fn is_set(self, features: &vk::PhysicalDeviceFeatures) -> bool {
+        let mut features = *features;
+        *self.flag(&mut features) == vk::TRUE
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FeatureLevel {
+    /// Devices without the feature are not selected.
+    Required,
+    /// Enabled when the device supports it.
+    Optional,
+}
+
+/// Whether a device extension given to
+/// [`ContextCreateInfo::with_device_extension`](super::context::ContextCreateInfo::with_device_extension)
+/// is needed for a device to be selected.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ExtensionRequirement {
+    Required,
+    /// Enabled when the device supports it, see
+    /// [`Context::is_device_extension_enabled`](super::context::Context::is_device_extension_enabled).
+    Optional,
+}
+
+/// Features enabled on the device on top of the ones Vulkan 1.3 makes core. The default one
+/// optionally enables the features the engine's own validation checks for: non-solid fill modes,
+/// wide lines, large points, depth bias clamping and depth bounds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceFeatureRequest {
+    pub features: Vec<(DeviceFeature, FeatureLevel)>,
+}
+
+impl Default for DeviceFeatureRequest {
+    fn default() -> Self {
+        Self::none()
+            .with_optional(DeviceFeature::FillModeNonSolid)
+            .with_optional(DeviceFeature::WideLines)
+            .with_optional(DeviceFeature::LargePoints)
+            .with_optional(DeviceFeature::DepthBiasClamp)
+            .with_optional(DeviceFeature::DepthBounds)
+    }
+}
+
+impl DeviceFeatureRequest {
+    pub fn none() -> Self {
+        Self { features: vec![] }
+    }
+
+    /// Overrides a previous request of the same feature.
+    pub fn with(mut self, feature: DeviceFeature, level: FeatureLevel) -> Self {
+        self.features.retain(|&(requested, _)| requested != feature);
+        self.features.push((feature, level));
+        self
+    }
+
+    pub fn with_required(self, feature: DeviceFeature) -> Self {
+        self.with(feature, FeatureLevel::Required)
+    }
+
+    pub fn with_optional(self, feature: DeviceFeature) -> Self {
+        self.with(feature, FeatureLevel::Optional)
+    }
+
+    /// Required features `supported` lacks.
+    pub(crate) fn missing_required(
+        &self,
+        supported: &vk::PhysicalDeviceFeatures,
+    ) -> Vec<DeviceFeature> {
+        self.features
+            .iter()
+            .filter(|&&(feature, level)| {
+                level == FeatureLevel::Required && !feature.is_set(supported)
+            })
+            .map(|&(feature, _)| feature)
+            .collect()
+    }
+
+    /// Requested features among the `supported` ones.
+    pub(crate) fn enabled(
+        &self,
+        supported: &vk::PhysicalDeviceFeatures,
+    ) -> vk::PhysicalDeviceFeatures {
+        let mut enabled = vk::PhysicalDeviceFeatures::default();
+        for &(feature, _) in &self.features {
+            if feature.is_set(supported) {
+                *feature.flag(&mut enabled) = vk::TRUE;
+            }
+        }
+
+        enabled
+    }
+}
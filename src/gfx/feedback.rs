@@ -0,0 +1,188 @@
+use std::{
+    marker::PhantomData,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use ash::vk;
+
+use crate::utils::ThreadSafeRef;
+
+use super::{
+    allocator::AllocTag,
+    barrier::BarrierBatch,
+    buffer::{Buffer, BufferBuildError},
+    commands::FRAMES_IN_FLIGHT,
+    context::Context,
+    device::Device,
+};
+
+// the newest completed frame keeps its slot while every frame in flight and the one being
+// recorded copy into their own
+const SLOT_COUNT: usize = FRAMES_IN_FLIGHT + 2;
+
+struct FeedbackStorage {
+    counter: Buffer,
+    readbacks: Vec<Buffer>,
+    // indexed by slot, frame whose copy the readback buffer last received
+    copied_frames: Vec<Option<u64>>,
+}
+
+/// Small value written by the GPU every frame, e.g. visible instances or alive particles, read
+/// back by the CPU without waiting on the GPU.
+///
+/// Passes write the value to the [counter buffer](Self::counter_buffer), which the render graph
+/// copies to a host-visible buffer per frame slot at the end of every frame once the feedback is
+/// [declared to it](super::render_graph::RenderGraphInfo::with_feedback). The value of a frame is
+/// available once the frame is known to be complete, a couple of frames later.
+pub struct FeedbackBuffer<T: bytemuck::Pod> {
+    storage: ThreadSafeRef<FeedbackStorage>,
+    counter_handle: vk::Buffer,
+    frame_counter: Arc<AtomicU64>,
+    _value: PhantomData<T>,
+}
+
+impl<T: bytemuck::Pod> FeedbackBuffer<T> {
+    pub fn new(name: &str, ctx: &mut Context) -> Result<Self, BufferBuildError> {
+        let size = (std::mem::size_of::<T>() as u64).max(1);
+        let counter = Buffer::builder(size)
+            .with_name(&format!("{name} feedback counter"))
+            .with_tag(AllocTag::User("feedback"))
+            .with_usage(
+                vk::BufferUsageFlags::STORAGE_BUFFER
+                    | vk::BufferUsageFlags::TRANSFER_SRC
+                    | vk::BufferUsageFlags::TRANSFER_DST,
+            )
+            .with_memory_location(gpu_allocator::MemoryLocation::GpuOnly)
+            .build(ctx)?;
+        let readbacks = (0..SLOT_COUNT)
+            .map(|slot_index| {
+                Buffer::builder(size)
+                    .with_name(&format!("{name} feedback readback {slot_index}"))
+                    .with_tag(AllocTag::Staging)
+                    .with_usage(vk::BufferUsageFlags::TRANSFER_DST)
+                    .with_memory_location(gpu_allocator::MemoryLocation::GpuToCpu)
+                    .build(ctx)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            counter_handle: counter.handle,
+            storage: ThreadSafeRef::new(FeedbackStorage {
+                counter,
+                readbacks,
+                copied_frames: vec![None; SLOT_COUNT],
+            }),
+            frame_counter: ctx.core.command_manager.frame_counter.clone(),
+            _value: PhantomData,
+        })
+    }
+
+    /// Device-local buffer holding a single `T`, usable as a storage buffer and as a transfer
+    /// destination, e.g. to reset it at the start of a frame.
+    pub fn counter_buffer(&self) -> vk::Buffer {
+        self.counter_handle
+    }
+
+    /// Value of the newest frame known to be complete, along with the index of that frame.
+    pub fn latest(&self) -> Option<(u64, T)> {
+        let submitted_frames = self.frame_counter.load(Ordering::Acquire);
+        let storage = self.storage.lock();
+
+        // the fence of a frame is waited on before recording the frames in flight after it
+        let (slot_index, frame_index) = storage
+            .copied_frames
+            .iter()
+            .enumerate()
+            .filter_map(|(slot_index, frame)| frame.map(|frame| (slot_index, frame)))
+            .filter(|&(_, frame)| frame + (FRAMES_IN_FLIGHT as u64) < submitted_frames)
+            .max_by_key(|&(_, frame)| frame)?;
+        let value = *storage.readbacks[slot_index].mapped::<T>()?.first()?;
+
+        Some((frame_index, value))
+    }
+
+    pub(crate) fn copy(&self) -> FeedbackCopy {
+        FeedbackCopy {
+            storage: self.storage.clone(),
+            frame_counter: self.frame_counter.clone(),
+        }
+    }
+}
+
+/// End-of-frame copy of a [`FeedbackBuffer`], recorded by the render graph it was declared to.
+pub(crate) struct FeedbackCopy {
+    storage: ThreadSafeRef<FeedbackStorage>,
+    frame_counter: Arc<AtomicU64>,
+}
+
+impl FeedbackCopy {
+    /// Records the copies of every feedback, with a single barrier before and after them.
+    pub fn cmd_record_all(copies: &[Self], cmd_buffer: vk::CommandBuffer, device: &Device) {
+        if copies.is_empty() {
+            return;
+        }
+
+        // writes may come from any stage of the frame
+        let mut barriers = BarrierBatch::new();
+        for copy in copies {
+            let storage = copy.storage.lock();
+            barriers.push_buffer_barrier(
+                buffer_barrier(storage.counter.handle)
+                    .src_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                    .src_access_mask(
+                        vk::AccessFlags2::SHADER_WRITE | vk::AccessFlags2::TRANSFER_WRITE,
+                    )
+                    .dst_stage_mask(vk::PipelineStageFlags2::COPY)
+                    .dst_access_mask(vk::AccessFlags2::TRANSFER_READ),
+            );
+        }
+        barriers.cmd_flush(device, cmd_buffer);
+
+        for copy in copies {
+            let mut storage = copy.storage.lock();
+            let frame_index = copy.frame_counter.load(Ordering::Acquire);
+            let slot_index = (frame_index % SLOT_COUNT as u64) as usize;
+
+            let readback = &storage.readbacks[slot_index];
+            let region = vk::BufferCopy::default().size(storage.counter.size());
+            unsafe {
+                device.cmd_copy_buffer(
+                    cmd_buffer,
+                    storage.counter.handle,
+                    readback.handle,
+                    std::slice::from_ref(&region),
+                )
+            };
+            barriers.push_buffer_barrier(
+                buffer_barrier(readback.handle)
+                    .src_stage_mask(vk::PipelineStageFlags2::COPY)
+                    .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                    .dst_stage_mask(vk::PipelineStageFlags2::HOST)
+                    .dst_access_mask(vk::AccessFlags2::HOST_READ),
+            );
+            // the next frame may write the counter again as soon as it is copied
+            barriers.push_buffer_barrier(
+                buffer_barrier(storage.counter.handle)
+                    .src_stage_mask(vk::PipelineStageFlags2::COPY)
+                    .src_access_mask(vk::AccessFlags2::TRANSFER_READ)
+                    .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                    .dst_access_mask(
+                        vk::AccessFlags2::SHADER_WRITE | vk::AccessFlags2::TRANSFER_WRITE,
+                    ),
+            );
+            storage.copied_frames[slot_index] = Some(frame_index);
+        }
+        barriers.cmd_flush(device, cmd_buffer);
+    }
+}
+
+fn buffer_barrier(buffer: vk::Buffer) -> vk::BufferMemoryBarrier2<'static> {
+    vk::BufferMemoryBarrier2::default()
+        .buffer(buffer)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .size(vk::WHOLE_SIZE)
+}
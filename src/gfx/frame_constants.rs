@@ -0,0 +1,207 @@
+use ash::vk;
+use thiserror::Error;
+
+use crate::{
+    math::{Mat4, Vec3, Vec4},
+    utils::{ThreadSafeRef, ThreadSafeRwRef},
+};
+
+use super::{
+    allocator::{AllocTag, Allocator},
+    buffer::{Buffer, BufferBuildError, BufferBuilder, BufferDataUploadError},
+    commands::{FRAMES_IN_FLIGHT, FrameSlotIndex},
+    device::Device,
+};
+
+/// GLSL declaration of the [`FrameConstants`] block, meant to be `#include`d by shaders of
+/// pipelines built with
+/// [`with_frame_constants`](super::pipeline::GraphicsPipelineBuilder::with_frame_constants).
+pub const FRAME_CONSTANTS_GLSL: &str = include_str!("shaders/frame_constants.glsl");
+
+/// Descriptor set index the frame constants are bound to.
+pub const FRAME_CONSTANTS_SET: u32 = 0;
+
+/// Values shared by every pass of a frame, laid out to match the std140 block of
+/// [`FRAME_CONSTANTS_GLSL`]. `extent` and `frame_index` are filled by the engine before upload.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct FrameConstants {
+    pub view: Mat4,
+    pub projection: Mat4,
+    pub view_projection: Mat4,
+    pub camera_position: Vec4,
+    pub extent: [f32; 2],
+    pub time: f32,
+    pub frame_index: u32,
+}
+
+// std140 puts every member at the offset it has here, the block must not gain implicit padding
+const _: () = assert!(std::mem::size_of::<FrameConstants>() == 224);
+
+// SAFETY: only made of f32 and u32, with no padding as asserted above
+unsafe impl bytemuck::Zeroable for FrameConstants {}
+unsafe impl bytemuck::Pod for FrameConstants {}
+
+crate::gpu_layout!(FrameConstants {
+    view,
+    projection,
+    view_projection,
+    camera_position,
+    extent,
+    time,
+    frame_index,
+});
+
+impl FrameConstants {
+    pub fn new(view: Mat4, projection: Mat4, camera_position: Vec3, time: f32) -> Self {
+        Self {
+            view,
+            projection,
+            view_projection: projection * view,
+            camera_position: camera_position.extend(1.0),
+            time,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum FrameConstantsCreateError {
+    #[error("uniform buffer creation failed")]
+    BufferCreation(#[from] BufferBuildError),
+
+    #[error("vulkan call to create the descriptor set layout failed")]
+    SetLayoutCreation(vk::Result),
+
+    #[error("vulkan call to create the descriptor pool failed")]
+    DescriptorPoolCreation(vk::Result),
+
+    #[error("vulkan call to allocate the descriptor set failed")]
+    DescriptorSetAllocation(vk::Result),
+}
+
+/// Uniform buffers and cached descriptor sets holding the [`FrameConstants`] of the frames in
+/// flight, one per frame slot so that a frame never overwrites the constants the GPU is reading.
+pub(crate) struct FrameConstantsBlock {
+    pub(crate) constants: FrameConstants,
+    frame_index: u32,
+
+    buffers: Vec<Buffer>,
+    pub(crate) set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_sets: Vec<vk::DescriptorSet>,
+
+    // bookkeeping
+    device_ref: ThreadSafeRwRef<Device>,
+}
+
+impl FrameConstantsBlock {
+    pub fn new(
+        device_ref: &ThreadSafeRwRef<Device>,
+        allocator_ref: &ThreadSafeRef<Allocator>,
+    ) -> Result<Self, FrameConstantsCreateError> {
+        let buffers = (0..FRAMES_IN_FLIGHT)
+            .map(|_| {
+                BufferBuilder::uniform_buffer_default(std::mem::size_of::<FrameConstants>() as u64)
+                    .with_name("frame constants")
+                    .with_tag(AllocTag::Uniform)
+                    .build_internal(device_ref.clone(), allocator_ref.clone())
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let device = device_ref.read();
+
+        let binding = vk::DescriptorSetLayoutBinding::default()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::ALL);
+        let layout_info =
+            vk::DescriptorSetLayoutCreateInfo::default().bindings(std::slice::from_ref(&binding));
+        let set_layout = unsafe { device.create_descriptor_set_layout(&layout_info, None) }
+            .map_err(FrameConstantsCreateError::SetLayoutCreation)?;
+
+        let pool_size = vk::DescriptorPoolSize::default()
+            .ty(vk::DescriptorType::UNIFORM_BUFFER)
+            .descriptor_count(FRAMES_IN_FLIGHT as u32);
+        let pool_info = vk::DescriptorPoolCreateInfo::default()
+            .max_sets(FRAMES_IN_FLIGHT as u32)
+            .pool_sizes(std::slice::from_ref(&pool_size));
+        let descriptor_pool = match unsafe { device.create_descriptor_pool(&pool_info, None) } {
+            Ok(pool) => pool,
+            Err(err) => {
+                unsafe { device.destroy_descriptor_set_layout(set_layout, None) };
+                return Err(FrameConstantsCreateError::DescriptorPoolCreation(err));
+            }
+        };
+
+        let set_layouts = [set_layout; FRAMES_IN_FLIGHT];
+        let allocate_info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&set_layouts);
+        let descriptor_sets = match unsafe { device.allocate_descriptor_sets(&allocate_info) } {
+            Ok(sets) => sets,
+            Err(err) => {
+                unsafe { device.destroy_descriptor_pool(descriptor_pool, None) };
+                unsafe { device.destroy_descriptor_set_layout(set_layout, None) };
+                return Err(FrameConstantsCreateError::DescriptorSetAllocation(err));
+            }
+        };
+
+        for (buffer, &descriptor_set) in buffers.iter().zip(&descriptor_sets) {
+            let buffer_info = vk::DescriptorBufferInfo::default()
+                .buffer(buffer.handle)
+                .offset(0)
+                .range(vk::WHOLE_SIZE);
+            let write = vk::WriteDescriptorSet::default()
+                .dst_set(descriptor_set)
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .buffer_info(std::slice::from_ref(&buffer_info));
+            unsafe { device.update_descriptor_sets(std::slice::from_ref(&write), &[]) };
+        }
+
+        Ok(Self {
+            constants: FrameConstants::default(),
+            frame_index: 0,
+            buffers,
+            set_layout,
+            descriptor_pool,
+            descriptor_sets,
+            device_ref: device_ref.clone(),
+        })
+    }
+
+    pub fn descriptor_set(&self, frame_slot: FrameSlotIndex) -> vk::DescriptorSet {
+        self.descriptor_sets[frame_slot.as_usize()]
+    }
+
+    /// Must only be called once the commands of the previous frame of the slot are known to be
+    /// complete.
+    pub fn upload(
+        &mut self,
+        extent: vk::Extent2D,
+        frame_slot: FrameSlotIndex,
+    ) -> Result<(), BufferDataUploadError> {
+        self.constants.extent = [extent.width as f32, extent.height as f32];
+        self.constants.frame_index = self.frame_index;
+        self.frame_index = self.frame_index.wrapping_add(1);
+
+        let mapped = self.buffers[frame_slot.as_usize()]
+            .mapped_mut::<FrameConstants>()
+            .and_then(|constants| constants.first_mut())
+            .ok_or(BufferDataUploadError::MemoryMapping)?;
+        *mapped = self.constants;
+
+        Ok(())
+    }
+}
+
+impl Drop for FrameConstantsBlock {
+    fn drop(&mut self) {
+        let device = self.device_ref.read();
+
+        unsafe { device.destroy_descriptor_pool(self.descriptor_pool, None) };
+        unsafe { device.destroy_descriptor_set_layout(self.set_layout, None) };
+    }
+}
@@ -0,0 +1,128 @@
+use ash::vk;
+
+use crate::utils::ThreadSafeRef;
+
+use super::{deletion_queue::DeletionQueue, staging::StagingBelt};
+
+/// Points of a rendered frame at which [`FrameHook`]s run, in this order.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum FrameStage {
+    /// The frame that last used the frame slot has completed on the GPU, its resources can be
+    /// reused.
+    AfterFenceWait,
+    /// Commands can be recorded before those of the render graph, after the swapchain image was
+    /// acquired.
+    BeforeGraphRecord,
+    /// The commands of the frame have been submitted.
+    AfterSubmit,
+    /// The swapchain image has been handed for presentation.
+    AfterPresent,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct HookId(u64);
+
+pub type FrameHook = Box<dyn FnMut(&mut FrameHookContext)>;
+
+/// What a [`FrameHook`] may access at the stage it runs.
+pub struct FrameHookContext<'a> {
+    stage: FrameStage,
+    frame_index: u64,
+    cmd_buffer: Option<vk::CommandBuffer>,
+    staging_belt: &'a mut StagingBelt,
+    deletion_queue: &'a ThreadSafeRef<DeletionQueue>,
+}
+
+impl<'a> FrameHookContext<'a> {
+    pub(crate) fn new(
+        stage: FrameStage,
+        frame_index: u64,
+        staging_belt: &'a mut StagingBelt,
+        deletion_queue: &'a ThreadSafeRef<DeletionQueue>,
+    ) -> Self {
+        Self {
+            stage,
+            frame_index,
+            cmd_buffer: None,
+            staging_belt,
+            deletion_queue,
+        }
+    }
+
+    pub(crate) fn with_cmd_buffer(mut self, cmd_buffer: vk::CommandBuffer) -> Self {
+        self.cmd_buffer = Some(cmd_buffer);
+        self
+    }
+
+    pub fn stage(&self) -> FrameStage {
+        self.stage
+    }
+
+    /// Number of frames submitted before this one.
+    pub fn frame_index(&self) -> u64 {
+        self.frame_index
+    }
+
+    /// Only available during [`FrameStage::BeforeGraphRecord`].
+    pub fn cmd_buffer(&self) -> Option<vk::CommandBuffer> {
+        self.cmd_buffer
+    }
+
+    /// Uploads written before the graph is recorded are visible to its passes, not to commands
+    /// recorded by the hook itself.
+    pub fn staging_belt(&mut self) -> &mut StagingBelt {
+        self.staging_belt
+    }
+
+    /// Keeps `resource` alive until the GPU is done with the frame being rendered.
+    pub fn defer_deletion<T: 'static>(&self, resource: T) {
+        self.deletion_queue.lock().defer(resource);
+    }
+}
+
+pub(crate) struct FrameHooks {
+    hooks: Vec<(HookId, FrameStage, FrameHook)>,
+    next_id: u64,
+}
+
+impl FrameHooks {
+    /// Engine hooks come first, so that user hooks of the same stage find the frame resources
+    /// already recycled.
+    pub fn new() -> Self {
+        let mut hooks = Self {
+            hooks: vec![],
+            next_id: 0,
+        };
+        hooks.add(
+            FrameStage::AfterFenceWait,
+            Box::new(|hook_ctx| hook_ctx.deletion_queue.lock().flush(hook_ctx.frame_index)),
+        );
+        hooks.add(
+            FrameStage::AfterFenceWait,
+            Box::new(|hook_ctx| hook_ctx.staging_belt.recycle(hook_ctx.frame_index)),
+        );
+
+        hooks
+    }
+
+    pub fn add(&mut self, stage: FrameStage, hook: FrameHook) -> HookId {
+        let id = HookId(self.next_id);
+        self.next_id += 1;
+        self.hooks.push((id, stage, hook));
+
+        id
+    }
+
+    pub fn remove(&mut self, id: HookId) {
+        self.hooks.retain(|(hook_id, _, _)| *hook_id != id);
+    }
+
+    /// Hooks of a stage run in registration order.
+    pub fn run(&mut self, hook_ctx: &mut FrameHookContext) {
+        for (_, stage, hook) in &mut self.hooks {
+            if *stage == hook_ctx.stage {
+                hook(hook_ctx);
+            }
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use std::time::{Duration, Instant};
+
+// sleeping overshoots by a scheduler-dependent amount, the end of the wait is spun instead
+const SPIN_DURATION: Duration = Duration::from_micros(500);
+// limits slightly below the refresh rate would alternate between one and two vblanks per frame
+const VSYNC_TOLERANCE: f32 = 1.05;
+
+/// Caps the frame rate by waiting before a frame until the minimum frame interval has elapsed
+/// since the start of the previous one. The cap is ignored when presentation is already throttled
+/// to the display refresh rate, at or below it.
+pub(crate) struct FrameLimiter {
+    min_interval: Option<Duration>,
+    // refresh interval of the display when presenting waits for vblanks
+    vsync_interval: Option<Duration>,
+    frame_start: Instant,
+    last_frame_interval: Duration,
+    last_frame_duration: Duration,
+}
+
+impl FrameLimiter {
+    pub fn new() -> Self {
+        Self {
+            min_interval: None,
+            vsync_interval: None,
+            frame_start: Instant::now(),
+            last_frame_interval: Duration::ZERO,
+            last_frame_duration: Duration::ZERO,
+        }
+    }
+
+    pub fn set_limit(&mut self, frame_rate: Option<f32>) {
+        self.min_interval = frame_rate.map(|frame_rate| Duration::from_secs_f32(1.0 / frame_rate));
+    }
+
+    pub fn limit(&self) -> Option<f32> {
+        self.min_interval
+            .map(|min_interval| 1.0 / min_interval.as_secs_f32())
+    }
+
+    /// `None` when presentation does not wait for vblanks or the refresh rate is unknown.
+    pub fn set_vsync_refresh_rate(&mut self, refresh_rate: Option<f32>) {
+        self.vsync_interval = refresh_rate
+            .filter(|&refresh_rate| refresh_rate > 0.0)
+            .map(|refresh_rate| Duration::from_secs_f32(1.0 / refresh_rate));
+    }
+
+    /// Interval actually enforced, if the limit is not redundant with vsync.
+    pub fn effective_interval(&self) -> Option<Duration> {
+        let min_interval = self.min_interval?;
+        match self.vsync_interval {
+            Some(vsync_interval) if min_interval <= vsync_interval.mul_f32(VSYNC_TOLERANCE) => None,
+            _ => Some(min_interval),
+        }
+    }
+
+    /// Time between the starts of the last two frames, limiter wait included.
+    pub fn last_frame_interval(&self) -> Duration {
+        self.last_frame_interval
+    }
+
+    /// Time spent on the last frame, from its start to its presentation.
+    pub fn last_frame_duration(&self) -> Duration {
+        self.last_frame_duration
+    }
+
+    /// When the event loop should be woken up for the next frame, slightly before it is due for
+    /// [`Self::wait`] to spin the rest.
+    pub fn wake_time(&self) -> Option<Instant> {
+        let deadline = self.frame_start + self.effective_interval()?;
+        Some(deadline.checked_sub(SPIN_DURATION).unwrap_or(deadline))
+    }
+
+    pub fn begin_frame(&mut self) {
+        let now = Instant::now();
+        self.last_frame_interval = now - self.frame_start;
+        self.frame_start = now;
+    }
+
+    pub fn end_frame(&mut self) {
+        self.last_frame_duration = self.frame_start.elapsed();
+    }
+
+    /// Waits until the next frame is due, before it begins.
+    pub fn wait(&self) {
+        let Some(min_interval) = self.effective_interval() else {
+            return;
+        };
+
+        let deadline = self.frame_start + min_interval;
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining > SPIN_DURATION {
+            std::thread::sleep(remaining - SPIN_DURATION);
+        }
+        while Instant::now() < deadline {
+            std::hint::spin_loop();
+        }
+    }
+}
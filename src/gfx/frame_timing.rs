@@ -0,0 +1,77 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// Frames kept by [`FrameTimingHistory`], 4 seconds at 60 frames per second.
+pub const FRAME_TIMING_HISTORY: usize = 240;
+
+/// CPU side timings of a rendered frame, in the order its steps happen.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FrameTiming {
+    pub frame_index: u64,
+    /// Waiting for the GPU to be done with the previous frame of the same slot.
+    pub fence_wait: Duration,
+    /// Waiting for a swapchain image to be available.
+    pub acquire_wait: Duration,
+    pub suboptimal_acquire: bool,
+    /// Recording and submitting the command buffers of the frame.
+    pub record: Duration,
+    /// The present call itself, which blocks with some present modes and drivers.
+    pub present: Duration,
+    /// When the present call returned, `None` when the swapchain was out of date and nothing was
+    /// presented.
+    pub presented_at: Option<Instant>,
+}
+
+impl FrameTiming {
+    /// Sum of the durations, not counting the updates between frames.
+    pub fn total(&self) -> Duration {
+        self.fence_wait + self.acquire_wait + self.record + self.present
+    }
+}
+
+/// Timings of the last [`FRAME_TIMING_HISTORY`] rendered frames, e.g. to graph them in a debug
+/// overlay.
+#[derive(Debug, Default)]
+pub struct FrameTimingHistory {
+    frames: VecDeque<FrameTiming>,
+}
+
+impl FrameTimingHistory {
+    /// From the oldest to the latest frame.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &FrameTiming> + ExactSizeIterator {
+        self.frames.iter()
+    }
+
+    pub fn latest(&self) -> Option<&FrameTiming> {
+        self.frames.back()
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Time between the presentation of the last two presented frames.
+    pub fn last_present_interval(&self) -> Option<Duration> {
+        let mut presents = self
+            .frames
+            .iter()
+            .rev()
+            .filter_map(|frame| frame.presented_at);
+        let latest = presents.next()?;
+
+        Some(latest.duration_since(presents.next()?))
+    }
+
+    pub(crate) fn push(&mut self, timing: FrameTiming) {
+        if self.frames.len() == FRAME_TIMING_HISTORY {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(timing);
+    }
+}
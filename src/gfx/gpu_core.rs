@@ -0,0 +1,185 @@
+use ash::vk;
+use winit::raw_window_handle::{RawDisplayHandle, RawWindowHandle};
+
+use crate::utils::{ThreadSafeRef, ThreadSafeRwRef};
+
+use super::{
+    allocator::{Allocator, report_leaks},
+    commands::CommandManager,
+    context::{ContextCreateError, ContextCreateInfo},
+    debug::DUMessenger,
+    device::{Device, PhysicalDevice},
+    instance::Instance,
+    overrides::EngineTunables,
+    surface::Surface,
+};
+
+/// Oldest Vulkan version the engine runs on, the default requested one.
+pub const MIN_API_VERSION: u32 = vk::make_api_version(0, 1, 3, 0);
+
+/// Vulkan objects above the device, kept by [`Context::rebuild`](super::context::Context::rebuild)
+/// when the device is created again.
+///
+/// Torn down in the order its fields are listed.
+pub(crate) struct InstanceObjects {
+    du_messenger: Option<DUMessenger>,
+    instance: Instance,
+    entry: ash::Entry,
+}
+
+impl InstanceObjects {
+    pub fn create(
+        create_info: &ContextCreateInfo,
+        tunables: &EngineTunables,
+        display_handle: Option<RawDisplayHandle>,
+    ) -> Result<Self, ContextCreateError> {
+        if create_info.requested_api_version < MIN_API_VERSION {
+            return Err(ContextCreateError::UnsupportedApiVersion(
+                create_info.requested_api_version,
+            ));
+        }
+
+        // SAFETY: This is basically foreign code execution, and there is not way to properly ensure safety
+        // here. It is unfortunately an uncontrollable risk we must accept.
+        let entry = unsafe { ash::Entry::load() }?;
+        let validation = tunables.validation.is_enabled();
+        let instance = Instance::create(&entry, create_info, display_handle, validation)?;
+        let du_messenger = DUMessenger::create(&entry, &instance, validation)?;
+
+        Ok(Self {
+            du_messenger,
+            instance,
+            entry,
+        })
+    }
+}
+
+/// Everything tied to the device rather than to a window, shareable by every presentation target.
+///
+/// Torn down by [`Self::destroy`], in the order its fields are listed.
+pub(crate) struct GpuCore {
+    pub(crate) command_manager: CommandManager,
+    pub(crate) allocator_ref: ThreadSafeRef<Allocator>,
+
+    pub(crate) device_ref: ThreadSafeRwRef<Device>,
+    pub(crate) physical_device: PhysicalDevice,
+    du_messenger: Option<DUMessenger>,
+    pub(crate) instance: Instance,
+    pub(crate) entry: ash::Entry,
+}
+
+impl GpuCore {
+    pub(crate) fn validation_messenger(&self) -> Option<&DUMessenger> {
+        self.du_messenger.as_ref()
+    }
+
+    /// Without a window the selected device only needs a graphics queue, nothing can be presented
+    /// from it. The instance objects are created when not given, e.g. kept from a lost device.
+    pub fn new(
+        create_info: &ContextCreateInfo,
+        tunables: &EngineTunables,
+        window: Option<(RawDisplayHandle, RawWindowHandle)>,
+        instance_objects: Option<InstanceObjects>,
+    ) -> Result<Self, ContextCreateError> {
+        let InstanceObjects {
+            du_messenger,
+            instance,
+            entry,
+        } = match instance_objects {
+            Some(instance_objects) => instance_objects,
+            None => InstanceObjects::create(
+                create_info,
+                tunables,
+                window.map(|(display_handle, _)| display_handle),
+            )?,
+        };
+
+        // only used to find a queue family able to present, the presentation creates its own
+        let probe_surface = window
+            .map(|(display_handle, window_handle)| {
+                Surface::create(&entry, &instance, display_handle, window_handle)
+            })
+            .transpose()?;
+        let physical_device = PhysicalDevice::select(
+            &instance,
+            create_info.requested_api_version,
+            probe_surface.as_ref(),
+            &tunables.device,
+            &create_info.requested_features,
+            &create_info.extra_device_extensions,
+        )?;
+        drop(probe_surface);
+
+        // These resources need to be stored as shared references as they are often needed for
+        // destruction and thus have to be stored in every sub-resource.
+        let device_ref = ThreadSafeRwRef::new(Device::create(
+            &instance,
+            &physical_device,
+            &create_info.requested_features,
+            &create_info.extra_device_extensions,
+        )?);
+        let allocator_ref = ThreadSafeRef::new(Allocator::create(
+            &instance,
+            &physical_device,
+            &device_ref.read(),
+        )?);
+
+        let command_manager =
+            CommandManager::try_new(device_ref.clone(), tunables.frames_in_flight)?;
+
+        Ok(Self {
+            command_manager,
+            allocator_ref,
+
+            device_ref,
+            physical_device,
+            du_messenger,
+            instance,
+            entry,
+        })
+    }
+
+    /// Expects the device to be idle and every object created from it to be destroyed already.
+    /// Survivors are reported, and fail a debug assertion; the device and instance are then
+    /// leaked rather than destroyed under them. Hands back the instance objects to create another
+    /// device from, `None` when they were leaked.
+    pub fn destroy(self) -> Option<InstanceObjects> {
+        let Self {
+            command_manager,
+            allocator_ref,
+            device_ref,
+            physical_device: _,
+            du_messenger,
+            instance,
+            entry,
+        } = self;
+
+        drop(command_manager);
+
+        let leak_count = report_leaks(&device_ref.read(), &allocator_ref.lock());
+        let instance_objects = if allocator_ref.is_shared() || device_ref.is_shared() {
+            log::error!("resources outlive the context, leaking the device and instance");
+            std::mem::forget((allocator_ref, device_ref, du_messenger, instance, entry));
+            None
+        } else {
+            log::debug!("destroying allocator");
+            drop(allocator_ref);
+            drop(device_ref);
+            Some(InstanceObjects {
+                du_messenger,
+                instance,
+                entry,
+            })
+        };
+
+        // a second panic while unwinding would abort, hiding the first one
+        if !std::thread::panicking() {
+            debug_assert_eq!(
+                leak_count, 0,
+                "resources created from the context must be dropped before it"
+            );
+        }
+
+        instance_objects
+    }
+}
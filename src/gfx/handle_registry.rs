@@ -0,0 +1,100 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use ash::vk;
+
+struct TrackedHandle {
+    name: String,
+    #[cfg(feature = "handle-backtraces")]
+    backtrace: std::backtrace::Backtrace,
+}
+
+/// Accounts for every vulkan object created by the engine, so that leaks are reported by name
+/// rather than by the raw handles the validation layers print at instance destruction.
+///
+/// Only enabled alongside the debug messenger, registration is a no-op otherwise.
+pub(crate) struct HandleRegistry {
+    live: Option<Mutex<HashMap<(vk::ObjectType, u64), TrackedHandle>>>,
+}
+
+impl HandleRegistry {
+    pub fn new() -> Self {
+        Self {
+            live: cfg!(debug_assertions).then(Mutex::default),
+        }
+    }
+
+    pub fn register<H: vk::Handle + Copy>(&self, handle: H, name: &str) {
+        let Some(live) = &self.live else {
+            return;
+        };
+
+        let tracked = TrackedHandle {
+            name: name.to_owned(),
+            #[cfg(feature = "handle-backtraces")]
+            backtrace: std::backtrace::Backtrace::force_capture(),
+        };
+        let previous = live
+            .lock()
+            .expect("handle registry lock should not be poisoned")
+            .insert((H::TYPE, handle.as_raw()), tracked);
+        if let Some(previous) = previous {
+            log::warn!(
+                "{:?} {:#x} registered twice (previously \"{}\")",
+                H::TYPE,
+                handle.as_raw(),
+                previous.name
+            );
+        }
+    }
+
+    pub fn unregister<H: vk::Handle + Copy>(&self, handle: H) {
+        let Some(live) = &self.live else {
+            return;
+        };
+
+        let removed = live
+            .lock()
+            .expect("handle registry lock should not be poisoned")
+            .remove(&(H::TYPE, handle.as_raw()));
+        if removed.is_none() {
+            log::warn!(
+                "{:?} {:#x} destroyed without being registered",
+                H::TYPE,
+                handle.as_raw()
+            );
+        }
+    }
+
+    pub fn live_count(&self) -> usize {
+        self.live.as_ref().map_or(0, |live| {
+            live.lock()
+                .expect("handle registry lock should not be poisoned")
+                .len()
+        })
+    }
+
+    /// Logs every handle still alive, returning how many there were.
+    pub fn report_survivors(&self) -> usize {
+        let Some(live) = &self.live else {
+            return 0;
+        };
+        let live = live
+            .lock()
+            .expect("handle registry lock should not be poisoned");
+        if live.is_empty() {
+            return 0;
+        }
+
+        let mut survivors = live.iter().collect::<Vec<_>>();
+        survivors.sort_by_key(|((object_type, _), tracked)| (object_type.as_raw(), &tracked.name));
+
+        log::warn!("vulkan objects outliving the context:");
+        for ((object_type, raw), tracked) in survivors {
+            log::warn!("  {object_type:?} {raw:#x} \"{}\"", tracked.name);
+            #[cfg(feature = "handle-backtraces")]
+            log::warn!("    created at:\n{}", tracked.backtrace);
+        }
+
+        live.len()
+    }
+}
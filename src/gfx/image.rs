@@ -5,7 +5,8 @@ use thiserror::Error;
 use crate::utils::{ThreadSafeRef, ThreadSafeRwRef};
 
 use super::{
-    allocator::{Allocation, Allocator},
+    allocator::{AllocTag, Allocation, Allocator},
+    barrier::BarrierBatch,
     context::Context,
     device::Device,
     render_graph::resource::ImageAttachmentInfo,
@@ -18,19 +19,20 @@ pub struct ImageState {
 
     pub layout: vk::ImageLayout,
     pub format: vk::Format,
+    pub usage: vk::ImageUsageFlags,
     pub extent: vk::Extent3D,
     pub extent_2d: vk::Extent2D,
     pub view_subresource_range: vk::ImageSubresourceRange,
 }
 
 impl ImageState {
+    /// The image and its old layout are filled in `image_memory_barrier`, whose new layout
+    /// becomes the tracked one.
     pub fn cmd_layout_transition(
         &mut self,
         device_ref: ThreadSafeRwRef<Device>,
         cmd_buffer: vk::CommandBuffer,
-        src_stage_mask: vk::PipelineStageFlags,
-        dst_stage_mask: vk::PipelineStageFlags,
-        image_memory_barrier: vk::ImageMemoryBarrier,
+        image_memory_barrier: vk::ImageMemoryBarrier2,
     ) {
         let image_memory_barrier = image_memory_barrier
             .image(self.handle)
@@ -38,17 +40,24 @@ impl ImageState {
         self.layout = image_memory_barrier.new_layout;
 
         let device = device_ref.read();
-        unsafe {
-            device.cmd_pipeline_barrier(
-                cmd_buffer,
-                src_stage_mask,
-                dst_stage_mask,
-                vk::DependencyFlags::empty(),
-                &[],
-                &[],
-                &[image_memory_barrier],
-            )
-        };
+        let dependency_info = vk::DependencyInfo::default()
+            .image_memory_barriers(std::slice::from_ref(&image_memory_barrier));
+        unsafe { device.cmd_pipeline_barrier2(cmd_buffer, &dependency_info) };
+    }
+
+    /// Same as [`Self::cmd_layout_transition`], but the barrier is queued into `batch` and only
+    /// recorded when it is flushed. The tracked layout is updated right away.
+    pub fn queue_into(
+        &mut self,
+        batch: &mut BarrierBatch,
+        image_memory_barrier: vk::ImageMemoryBarrier2<'static>,
+    ) {
+        let image_memory_barrier = image_memory_barrier
+            .image(self.handle)
+            .old_layout(self.layout);
+        self.layout = image_memory_barrier.new_layout;
+
+        batch.push_image_barrier(image_memory_barrier);
     }
 }
 
@@ -57,6 +66,7 @@ pub struct ImageCreateInfo<'a> {
     pub name: &'a str,
     pub image_info: vk::ImageCreateInfo<'a>,
     pub image_view_info: vk::ImageViewCreateInfo<'a>,
+    pub tag: AllocTag,
 }
 
 #[derive(Debug, Error)]
@@ -75,11 +85,11 @@ pub enum ImageBuildError {
 }
 
 impl<'a> ImageCreateInfo<'a> {
-    pub(crate) fn swapchain_depth_image(depth_extent: vk::Extent3D) -> Self {
+    pub(crate) fn swapchain_depth_image(depth_extent: vk::Extent3D, format: vk::Format) -> Self {
         let image_info = vk::ImageCreateInfo::default()
             .extent(depth_extent)
             .image_type(vk::ImageType::TYPE_2D)
-            .format(vk::Format::D32_SFLOAT)
+            .format(format)
             .mip_levels(1)
             .array_layers(1)
             .samples(vk::SampleCountFlags::TYPE_1)
@@ -89,9 +99,9 @@ impl<'a> ImageCreateInfo<'a> {
 
         let image_view_info = vk::ImageViewCreateInfo::default()
             .view_type(vk::ImageViewType::TYPE_2D)
-            .format(vk::Format::D32_SFLOAT)
+            .format(format)
             .subresource_range(vk::ImageSubresourceRange {
-                aspect_mask: vk::ImageAspectFlags::DEPTH,
+                aspect_mask: format_aspect(format),
                 base_mip_level: 0,
                 level_count: 1,
                 base_array_layer: 0,
@@ -102,17 +112,53 @@ impl<'a> ImageCreateInfo<'a> {
             name: "swapchain depth image",
             image_info,
             image_view_info,
+            tag: AllocTag::RenderTarget,
         }
     }
 
-    pub(crate) fn from_attachment_info(info: &'a ImageAttachmentInfo) -> Self {
-        let extent = match info.size {
-            super::render_graph::resource::AttachmentSize::SwapchainBased => {
-                vk::Extent3D::default()
-            }
-            super::render_graph::resource::AttachmentSize::Custom(extent3_d) => extent3_d,
-        };
+    /// Color target of offscreen rendering, which can also be viewed with the UNORM variant of
+    /// its format.
+    pub(crate) fn offscreen_color_image(extent: vk::Extent3D, format: vk::Format) -> Self {
+        let image_info = vk::ImageCreateInfo::default()
+            .flags(vk::ImageCreateFlags::MUTABLE_FORMAT)
+            .extent(extent)
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(format)
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(
+                vk::ImageUsageFlags::COLOR_ATTACHMENT
+                    | vk::ImageUsageFlags::TRANSFER_SRC
+                    | vk::ImageUsageFlags::TRANSFER_DST,
+            )
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
 
+        let image_view_info = vk::ImageViewCreateInfo::default()
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(format)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            });
+
+        Self {
+            name: "offscreen color image",
+            image_info,
+            image_view_info,
+            tag: AllocTag::RenderTarget,
+        }
+    }
+
+    /// `extent` is the size of the attachment, resolved against the swapchain.
+    pub(crate) fn from_attachment_info(
+        info: &'a ImageAttachmentInfo,
+        extent: vk::Extent3D,
+    ) -> Self {
         let image_info = vk::ImageCreateInfo::default()
             .extent(extent)
             .image_type(vk::ImageType::TYPE_2D)
@@ -128,7 +174,7 @@ impl<'a> ImageCreateInfo<'a> {
             .view_type(vk::ImageViewType::TYPE_2D)
             .format(info.format)
             .subresource_range(vk::ImageSubresourceRange {
-                aspect_mask: vk::ImageAspectFlags::COLOR,
+                aspect_mask: format_aspect(info.format),
                 base_mip_level: 0,
                 level_count: 1,
                 base_array_layer: 0,
@@ -139,15 +185,24 @@ impl<'a> ImageCreateInfo<'a> {
             name: &info.name,
             image_info,
             image_view_info,
+            tag: AllocTag::RenderTarget,
         }
     }
 
+    pub fn with_tag(mut self, tag: AllocTag) -> Self {
+        self.tag = tag;
+        self
+    }
+
     pub fn build(mut self, context: &Context) -> Result<Image, ImageBuildError> {
         if self.image_info.extent == vk::Extent3D::default() {
-            self.image_info.extent = context.swapchain.extent.into();
+            self.image_info.extent = context.swapchain_extent().into();
         }
 
-        self.build_from_base_structs(context.device_ref.clone(), context.allocator_ref.clone())
+        self.build_from_base_structs(
+            context.core.device_ref.clone(),
+            context.core.allocator_ref.clone(),
+        )
     }
 
     /// Called under the hood by [`Self::build`], which is the intended method to be called by user
@@ -162,6 +217,7 @@ impl<'a> ImageCreateInfo<'a> {
 
         let handle = unsafe { device.create_image(&self.image_info, None) }
             .map_err(ImageBuildError::VulkanCreation)?;
+        device.handle_registry.register(handle, self.name);
 
         let memory_requirements = unsafe { device.get_image_memory_requirements(handle) };
         let allocation_info = gpu_allocator::vulkan::AllocationCreateDesc {
@@ -171,7 +227,7 @@ impl<'a> ImageCreateInfo<'a> {
             linear: false,
             allocation_scheme: gpu_allocator::vulkan::AllocationScheme::DedicatedImage(handle),
         };
-        let _allocation = allocator.allocate(&allocation_info, allocator_ref.clone())?;
+        let _allocation = allocator.allocate(&allocation_info, self.tag, allocator_ref.clone())?;
 
         unsafe { device.bind_image_memory(handle, _allocation.memory(), _allocation.offset()) }
             .map_err(ImageBuildError::MemoryBind)?;
@@ -179,6 +235,7 @@ impl<'a> ImageCreateInfo<'a> {
         self.image_view_info.image = handle;
         let view = unsafe { device.create_image_view(&self.image_view_info, None) }
             .map_err(ImageBuildError::ImageViewCreation)?;
+        device.handle_registry.register(view, self.name);
 
         let state = ImageState {
             handle,
@@ -186,6 +243,7 @@ impl<'a> ImageCreateInfo<'a> {
 
             layout: self.image_info.initial_layout,
             format: self.image_info.format,
+            usage: self.image_info.usage,
             extent: self.image_info.extent,
             extent_2d: vk::Extent2D {
                 width: self.image_info.extent.width,
@@ -217,6 +275,8 @@ impl Drop for Image {
     fn drop(&mut self) {
         let device = self.device_ref.read();
 
+        device.handle_registry.unregister(self.state.view);
+        device.handle_registry.unregister(self.state.handle);
         unsafe { device.destroy_image_view(self.state.view, None) };
         unsafe { device.destroy_image(self.state.handle, None) };
     }
@@ -230,16 +290,26 @@ impl<'a> Image {
     pub fn cmd_layout_transition(
         &mut self,
         cmd_buffer: vk::CommandBuffer,
-        src_stage_mask: vk::PipelineStageFlags,
-        dst_stage_mask: vk::PipelineStageFlags,
-        image_memory_barrier: vk::ImageMemoryBarrier,
+        image_memory_barrier: vk::ImageMemoryBarrier2,
     ) {
-        self.state.cmd_layout_transition(
-            self.device_ref.clone(),
-            cmd_buffer,
-            src_stage_mask,
-            dst_stage_mask,
-            image_memory_barrier,
-        );
+        self.state
+            .cmd_layout_transition(self.device_ref.clone(), cmd_buffer, image_memory_barrier);
+    }
+}
+
+/// Aspects a view of an image with this format covers. Depth-stencil views cannot be sampled, a
+/// view of the depth aspect alone has to be created for that.
+pub fn format_aspect(format: vk::Format) -> vk::ImageAspectFlags {
+    match format {
+        vk::Format::D16_UNORM | vk::Format::X8_D24_UNORM_PACK32 | vk::Format::D32_SFLOAT => {
+            vk::ImageAspectFlags::DEPTH
+        }
+        vk::Format::D16_UNORM_S8_UINT
+        | vk::Format::D24_UNORM_S8_UINT
+        | vk::Format::D32_SFLOAT_S8_UINT => {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        }
+        vk::Format::S8_UINT => vk::ImageAspectFlags::STENCIL,
+        _ => vk::ImageAspectFlags::COLOR,
     }
 }
@@ -1,5 +1,5 @@
 use std::{
-    ffi::{CStr, CString, c_char},
+    ffi::{CStr, c_char},
     ops::Deref,
 };
 
@@ -7,6 +7,8 @@ use ash::{ext, vk};
 use thiserror::Error;
 use winit::raw_window_handle::RawDisplayHandle;
 
+use super::context::ContextCreateInfo;
+
 pub(crate) struct Instance {
     pub loader: ash::Instance,
 }
@@ -30,10 +32,9 @@ pub enum InstanceCreateError {
 impl Instance {
     pub fn create(
         entry: &ash::Entry,
-        application_name: &CString,
-        application_version: u32,
-        vk_version: u32,
-        display_handle: RawDisplayHandle,
+        create_info: &ContextCreateInfo,
+        display_handle: Option<RawDisplayHandle>,
+        validation: bool,
     ) -> Result<Self, InstanceCreateError> {
         let mut engine_version_numbers = option_env!("CARGO_PKG_VERSION")
             .unwrap_or("0.1.0.0")
@@ -47,19 +48,47 @@ impl Instance {
             engine_version_numbers.next().unwrap(),
         );
         let app_info = vk::ApplicationInfo::default()
-            .application_name(application_name)
-            .application_version(application_version)
+            .application_name(&create_info.application_name)
+            .application_version(create_info.application_version)
             .engine_name(c"miel")
             .engine_version(engine_version)
-            .api_version(vk_version);
-        let mut enabled_extensions = ash_window::enumerate_required_extensions(display_handle)
-            .map_err(InstanceCreateError::ExtensionQuery)?
-            .to_vec();
+            .api_version(create_info.requested_api_version);
+        // surface extensions are only needed to present to a display
+        let mut enabled_extensions = match display_handle {
+            Some(display_handle) => ash_window::enumerate_required_extensions(display_handle)
+                .map_err(InstanceCreateError::ExtensionQuery)?
+                .to_vec(),
+            None => vec![],
+        };
+        // formats with other color spaces cannot be selected without it
+        if display_handle.is_some()
+            && create_info
+                .swapchain_options
+                .formats
+                .needs_colorspace_extension()
+        {
+            enabled_extensions.push(ext::swapchain_colorspace::NAME.as_ptr());
+        }
         let mut enabled_layers = vec![];
-        if cfg!(debug_assertions) {
+        if validation {
             enabled_extensions.push(ext::debug_utils::NAME.as_ptr());
             enabled_layers.push(c"VK_LAYER_KHRONOS_validation".as_ptr());
         }
+        enabled_extensions.extend(
+            create_info
+                .extra_instance_extensions
+                .iter()
+                .map(|name| name.as_ptr()),
+        );
+        enabled_layers.extend(
+            create_info
+                .extra_instance_layers
+                .iter()
+                .map(|name| name.as_ptr()),
+        );
+        // both may have been requested, e.g. the debug utils by an application layer
+        dedup_names(&mut enabled_extensions);
+        dedup_names(&mut enabled_layers);
 
         log::debug!("resolved required instance extensions:");
         {
@@ -68,6 +97,11 @@ impl Instance {
                 log::debug!("\t{:?}", debug_str);
             }
         }
+        log::debug!("resolved instance layers:");
+        for ptr in &enabled_layers {
+            let debug_str = unsafe { CStr::from_ptr(ptr.cast::<c_char>()) };
+            log::debug!("\t{:?}", debug_str);
+        }
 
         let instance_create_info = vk::InstanceCreateInfo::default()
             .application_info(&app_info)
@@ -86,6 +120,18 @@ impl Instance {
     }
 }
 
+/// Removes repeated names, keeping the first occurrence of each.
+pub(crate) fn dedup_names(names: &mut Vec<*const c_char>) {
+    let mut seen = Vec::with_capacity(names.len());
+    // SAFETY: every pointer comes from a NUL-terminated string outliving the instance creation
+    names.retain(|&ptr| {
+        let name = unsafe { CStr::from_ptr(ptr) };
+        let is_new = !seen.contains(&name);
+        seen.push(name);
+        is_new
+    });
+}
+
 impl Drop for Instance {
     fn drop(&mut self) {
         log::debug!("destroying instance");
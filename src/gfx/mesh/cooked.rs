@@ -0,0 +1,595 @@
+use std::path::Path;
+
+use thiserror::Error;
+
+use crate::{
+    gfx::{context::Context, vertex::Vertex},
+    math::Vec3,
+    utils::ThreadSafeRef,
+};
+
+use super::{Mesh, MeshDataUploadError, MeshTopology, upload_mesh_data};
+#[cfg(any(feature = "obj", feature = "ply"))]
+use crate::gfx::vertex::simple::{SimpleVertex, SimpleVertexMeshLoadingError};
+
+// Cooked meshes are a fixed header followed by the mesh name, the raw vertex bytes and the raw
+// index bytes, in the byte order of the machine cooking them. Only little-endian targets are
+// supported, the magic number reveals files cooked otherwise.
+const MAGIC: u32 = u32::from_le_bytes(*b"MCMF");
+/// Version 2 added the topology, version 1 meshes are read as triangle lists.
+pub const COOKED_MESH_VERSION: u32 = 2;
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct MeshBounds {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl MeshBounds {
+    /// Bounds of the `R32G32B32_SFLOAT` positions found at [`Vertex::position_offset`].
+    pub fn from_vertices<VertexType>(vertices: &[VertexType]) -> Self
+    where
+        VertexType: Vertex + bytemuck::Pod,
+    {
+        let offset = VertexType::position_offset() as usize;
+        let mut positions = vertices.iter().map(|vertex| {
+            let bytes = &bytemuck::bytes_of(vertex)[offset..offset + 12];
+            Vec3::from_array(bytemuck::pod_read_unaligned(bytes))
+        });
+
+        let Some(first) = positions.next() else {
+            return Self::default();
+        };
+        positions.fold(
+            Self {
+                min: first,
+                max: first,
+            },
+            |bounds, position| Self {
+                min: bounds.min.min(position),
+                max: bounds.max.max(position),
+            },
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CookedMeshMeta {
+    pub name: String,
+    pub topology: MeshTopology,
+}
+
+// stored as a u32 in the header
+fn encode_topology(topology: MeshTopology) -> u32 {
+    match topology {
+        MeshTopology::TriangleList => 0,
+        MeshTopology::TriangleStrip {
+            primitive_restart: false,
+        } => 1,
+        MeshTopology::TriangleStrip {
+            primitive_restart: true,
+        } => 2,
+    }
+}
+
+fn decode_topology(raw: u32) -> Result<MeshTopology, CookedMeshReadError> {
+    match raw {
+        0 => Ok(MeshTopology::TriangleList),
+        1 => Ok(MeshTopology::TriangleStrip {
+            primitive_restart: false,
+        }),
+        2 => Ok(MeshTopology::TriangleStrip {
+            primitive_restart: true,
+        }),
+        raw => Err(CookedMeshReadError::InvalidTopology(raw)),
+    }
+}
+
+/// Mesh data read back from a cooked file, not uploaded yet.
+#[derive(Debug)]
+pub struct CookedMeshData<VertexType> {
+    pub vertices: Vec<VertexType>,
+    pub indices: Vec<u32>,
+    pub bounds: MeshBounds,
+    pub meta: CookedMeshMeta,
+}
+
+#[derive(Debug, Error)]
+pub enum CookedMeshReadError {
+    #[error("file reading failed")]
+    Io(#[from] std::io::Error),
+
+    #[error("not a cooked mesh file")]
+    InvalidMagic,
+
+    #[error("cooked mesh was written with a different byte order")]
+    EndiannessMismatch,
+
+    #[error("cooked mesh version {found} is not supported (supported up to {supported})")]
+    UnsupportedVersion { found: u32, supported: u32 },
+
+    #[error(
+        "cooked mesh vertex type does not match (expected hash {expected:#018x}, found {found:#018x})"
+    )]
+    VertexTypeMismatch { expected: u64, found: u64 },
+
+    #[error("invalid index width {0}")]
+    InvalidIndexWidth(u32),
+
+    #[error("invalid topology {0}")]
+    InvalidTopology(u32),
+
+    #[error("cooked mesh name is not valid UTF-8")]
+    InvalidName(#[from] std::str::Utf8Error),
+
+    #[error("cooked mesh is truncated")]
+    Truncated,
+}
+
+#[derive(Debug, Error)]
+pub enum CookedMeshLoadError {
+    #[error("cooked mesh reading failed")]
+    Read(#[from] CookedMeshReadError),
+
+    #[error("mesh data upload failed")]
+    Upload(#[from] MeshDataUploadError),
+}
+
+#[cfg(any(feature = "obj", feature = "ply"))]
+#[derive(Debug, Error)]
+pub enum CookError {
+    #[error("source mesh loading failed")]
+    SourceLoad(#[from] SimpleVertexMeshLoadingError),
+
+    #[error("unsupported source format {0:?}, expected obj or ply")]
+    UnsupportedSource(String),
+
+    #[error("cooked mesh writing failed")]
+    Write(#[from] std::io::Error),
+}
+
+/// Identifies a vertex type by its size and input description, which is all the layout of the
+/// cooked bytes depends on.
+pub fn vertex_type_hash<VertexType>() -> u64
+where
+    VertexType: Vertex,
+{
+    // FNV-1a, stable across builds unlike the std hasher
+    let mut hash = 0xcbf29ce484222325_u64;
+    let mut feed = |value: u32| {
+        for byte in value.to_le_bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    };
+
+    let description = VertexType::vertex_input_description();
+    feed(std::mem::size_of::<VertexType>() as u32);
+    for binding in &description.bindings {
+        feed(binding.binding);
+        feed(binding.stride);
+        feed(binding.input_rate.as_raw() as u32);
+    }
+    for attribute in &description.attributes {
+        feed(attribute.location);
+        feed(attribute.binding);
+        feed(attribute.format.as_raw() as u32);
+        feed(attribute.offset);
+    }
+
+    hash
+}
+
+/// Writes an engine-native mesh, loaded by [`load_cooked`] without any parsing.
+pub fn save_cooked<VertexType>(
+    path: &Path,
+    vertices: &[VertexType],
+    indices: &[u32],
+    bounds: MeshBounds,
+    meta: &CookedMeshMeta,
+) -> Result<(), std::io::Error>
+where
+    VertexType: Vertex + bytemuck::Pod,
+{
+    // most meshes fit in 16-bit indices, halving their size on disk
+    let index_width: u32 = match indices.iter().all(|&index| index <= u16::MAX as u32) {
+        true => 2,
+        false => 4,
+    };
+
+    let mut bytes = vec![];
+    let mut push = |data: &[u8]| bytes.extend_from_slice(data);
+    push(&MAGIC.to_ne_bytes());
+    push(&COOKED_MESH_VERSION.to_ne_bytes());
+    push(&vertex_type_hash::<VertexType>().to_ne_bytes());
+    push(&index_width.to_ne_bytes());
+    push(&encode_topology(meta.topology).to_ne_bytes());
+    push(&(vertices.len() as u64).to_ne_bytes());
+    push(&(indices.len() as u64).to_ne_bytes());
+    push(bytemuck::bytes_of(&bounds.min.to_array()));
+    push(bytemuck::bytes_of(&bounds.max.to_array()));
+    push(&(meta.name.len() as u32).to_ne_bytes());
+    push(meta.name.as_bytes());
+
+    push(bytemuck::cast_slice(vertices));
+    match index_width {
+        2 => {
+            let indices = indices
+                .iter()
+                .map(|&index| index as u16)
+                .collect::<Vec<_>>();
+            push(bytemuck::cast_slice(&indices));
+        }
+        _ => push(bytemuck::cast_slice(indices)),
+    }
+
+    std::fs::write(path, bytes)
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], CookedMeshReadError> {
+        if self.bytes.len() < count {
+            return Err(CookedMeshReadError::Truncated);
+        }
+
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn read<T: bytemuck::Pod>(&mut self) -> Result<T, CookedMeshReadError> {
+        Ok(bytemuck::pod_read_unaligned(
+            self.take(std::mem::size_of::<T>())?,
+        ))
+    }
+
+    fn read_slice<T: bytemuck::Pod>(&mut self, count: u64) -> Result<Vec<T>, CookedMeshReadError> {
+        let size = usize::try_from(count)
+            .ok()
+            .and_then(|count| count.checked_mul(std::mem::size_of::<T>()))
+            .ok_or(CookedMeshReadError::Truncated)?;
+
+        // counts come from the file, the bytes must be there before allocating for them
+        let bytes = self.take(size)?;
+        let mut values = vec![T::zeroed(); size / std::mem::size_of::<T>().max(1)];
+        bytemuck::cast_slice_mut::<T, u8>(&mut values).copy_from_slice(bytes);
+        Ok(values)
+    }
+}
+
+pub fn read_cooked<VertexType>(
+    path: &Path,
+) -> Result<CookedMeshData<VertexType>, CookedMeshReadError>
+where
+    VertexType: Vertex + bytemuck::Pod,
+{
+    let bytes = std::fs::read(path)?;
+    let mut reader = ByteReader { bytes: &bytes };
+
+    let magic: u32 = reader.read()?;
+    if magic == MAGIC.swap_bytes() {
+        return Err(CookedMeshReadError::EndiannessMismatch);
+    }
+    if magic != MAGIC {
+        return Err(CookedMeshReadError::InvalidMagic);
+    }
+
+    // older versions are upgraded while reading them
+    let version: u32 = reader.read()?;
+    if version == 0 || version > COOKED_MESH_VERSION {
+        return Err(CookedMeshReadError::UnsupportedVersion {
+            found: version,
+            supported: COOKED_MESH_VERSION,
+        });
+    }
+
+    let expected_hash = vertex_type_hash::<VertexType>();
+    let vertex_hash: u64 = reader.read()?;
+    if vertex_hash != expected_hash {
+        return Err(CookedMeshReadError::VertexTypeMismatch {
+            expected: expected_hash,
+            found: vertex_hash,
+        });
+    }
+
+    let index_width: u32 = reader.read()?;
+    let topology = match version {
+        1 => MeshTopology::TriangleList,
+        _ => decode_topology(reader.read()?)?,
+    };
+    let vertex_count: u64 = reader.read()?;
+    let index_count: u64 = reader.read()?;
+    let bounds = MeshBounds {
+        min: Vec3::from_array(reader.read()?),
+        max: Vec3::from_array(reader.read()?),
+    };
+    let name_length: u32 = reader.read()?;
+    let name = std::str::from_utf8(reader.take(name_length as usize)?)?.to_owned();
+
+    let vertices = reader.read_slice::<VertexType>(vertex_count)?;
+    let indices = match index_width {
+        2 => reader
+            .read_slice::<u16>(index_count)?
+            .into_iter()
+            .map(u32::from)
+            .collect(),
+        4 => reader.read_slice::<u32>(index_count)?,
+        width => return Err(CookedMeshReadError::InvalidIndexWidth(width)),
+    };
+
+    Ok(CookedMeshData {
+        vertices,
+        indices,
+        bounds,
+        meta: CookedMeshMeta { name, topology },
+    })
+}
+
+pub fn load_cooked<VertexType>(
+    path: &Path,
+    ctx: &mut Context,
+) -> Result<ThreadSafeRef<Mesh<VertexType>>, CookedMeshLoadError>
+where
+    VertexType: Vertex + bytemuck::Pod,
+{
+    let data = read_cooked::<VertexType>(path)?;
+    let upload_result = upload_mesh_data(&data.meta.name, &data.vertices, &data.indices, ctx)?;
+
+    Ok(ThreadSafeRef::new(Mesh {
+        name: data.meta.name,
+        vertices: data.vertices,
+        indices: data.indices,
+        topology: data.meta.topology,
+        vertex_buffer: upload_result.vertex_buffer,
+        index_buffer: upload_result.index_buffer,
+    }))
+}
+
+/// Converts an OBJ or PLY mesh to a cooked [`SimpleVertex`] mesh, picking the parser from the
+/// source extension.
+#[cfg(any(feature = "obj", feature = "ply"))]
+pub fn cook(source: &Path, destination: &Path) -> Result<(), CookError> {
+    let extension = source
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let (vertices, indices) = match extension.as_str() {
+        #[cfg(feature = "obj")]
+        "obj" => SimpleVertex::read_obj(source)?,
+        #[cfg(feature = "ply")]
+        "ply" => SimpleVertex::read_ply(source)?,
+        _ => return Err(CookError::UnsupportedSource(extension)),
+    };
+
+    let meta = CookedMeshMeta {
+        name: source
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("<invalid>")
+            .to_owned(),
+        topology: MeshTopology::TriangleList,
+    };
+    save_cooked(
+        destination,
+        &vertices,
+        &indices,
+        MeshBounds::from_vertices(&vertices),
+        &meta,
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::gfx::vertex::{point_cloud::PointCloudVertex, simple::SimpleVertex};
+
+    // header offsets
+    const VERSION_OFFSET: usize = 4;
+    const TOPOLOGY_OFFSET: usize = 20;
+    const VERTEX_COUNT_OFFSET: usize = 24;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("miel-cooked-{}-{name}", std::process::id()))
+    }
+
+    fn quad() -> (Vec<SimpleVertex>, Vec<u32>) {
+        let vertices = [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [1.0, 2.0, 0.0],
+            [0.0, 2.0, -1.0],
+        ]
+        .map(|position| SimpleVertex {
+            position: Vec3::from_array(position),
+        });
+
+        (vertices.to_vec(), vec![0, 1, 2, 2, 3, 0])
+    }
+
+    fn cooked_bytes(name: &str, indices: &[u32], meta: &CookedMeshMeta) -> Vec<u8> {
+        let (vertices, _) = quad();
+        let path = temp_path(name);
+        save_cooked(
+            &path,
+            &vertices,
+            indices,
+            MeshBounds::from_vertices(&vertices),
+            meta,
+        )
+        .unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        bytes
+    }
+
+    fn read_bytes<VertexType>(
+        name: &str,
+        bytes: &[u8],
+    ) -> Result<CookedMeshData<VertexType>, CookedMeshReadError>
+    where
+        VertexType: Vertex + bytemuck::Pod,
+    {
+        let path = temp_path(name);
+        std::fs::write(&path, bytes).unwrap();
+        let result = read_cooked(&path);
+        std::fs::remove_file(path).unwrap();
+
+        result
+    }
+
+    fn positions(vertices: &[SimpleVertex]) -> Vec<Vec3> {
+        vertices.iter().map(|vertex| vertex.position).collect()
+    }
+
+    #[test]
+    fn round_trip_keeps_mesh_data() {
+        let (vertices, _) = quad();
+        let meta = CookedMeshMeta {
+            name: "strip".to_owned(),
+            topology: MeshTopology::TriangleStrip {
+                primitive_restart: true,
+            },
+        };
+        // wide enough indices to need 32 bits
+        for indices in [vec![0, 1, 2, 3], vec![0, 1, 2, 3, u32::MAX, 70_000]] {
+            let bytes = cooked_bytes("round-trip", &indices, &meta);
+            let data = read_bytes::<SimpleVertex>("round-trip", &bytes).unwrap();
+
+            assert_eq!(positions(&data.vertices), positions(&vertices));
+            assert_eq!(data.indices, indices);
+            assert_eq!(data.bounds.min, Vec3::new(0.0, 0.0, -1.0));
+            assert_eq!(data.bounds.max, Vec3::new(1.0, 2.0, 0.0));
+            assert_eq!(data.meta, meta);
+        }
+    }
+
+    #[test]
+    fn version_1_meshes_are_triangle_lists() {
+        let (_, indices) = quad();
+        let mut bytes = cooked_bytes("version-1", &indices, &CookedMeshMeta::default());
+        bytes[VERSION_OFFSET..VERSION_OFFSET + 4].copy_from_slice(&1_u32.to_ne_bytes());
+        bytes.drain(TOPOLOGY_OFFSET..TOPOLOGY_OFFSET + 4);
+
+        let data = read_bytes::<SimpleVertex>("version-1", &bytes).unwrap();
+        assert_eq!(data.meta.topology, MeshTopology::TriangleList);
+        assert_eq!(data.indices, indices);
+    }
+
+    #[test]
+    fn invalid_headers_are_rejected() {
+        let (_, indices) = quad();
+        let bytes = cooked_bytes("headers", &indices, &CookedMeshMeta::default());
+
+        let mut swapped = bytes.clone();
+        swapped[..4].reverse();
+        assert!(matches!(
+            read_bytes::<SimpleVertex>("swapped", &swapped),
+            Err(CookedMeshReadError::EndiannessMismatch)
+        ));
+
+        let mut future = bytes.clone();
+        future[VERSION_OFFSET..VERSION_OFFSET + 4]
+            .copy_from_slice(&(COOKED_MESH_VERSION + 1).to_ne_bytes());
+        assert!(matches!(
+            read_bytes::<SimpleVertex>("future", &future),
+            Err(CookedMeshReadError::UnsupportedVersion { found, .. })
+                if found == COOKED_MESH_VERSION + 1
+        ));
+
+        let mut topology = bytes.clone();
+        topology[TOPOLOGY_OFFSET..TOPOLOGY_OFFSET + 4].copy_from_slice(&7_u32.to_ne_bytes());
+        assert!(matches!(
+            read_bytes::<SimpleVertex>("topology", &topology),
+            Err(CookedMeshReadError::InvalidTopology(7))
+        ));
+
+        assert!(matches!(
+            read_bytes::<PointCloudVertex>("vertex-type", &bytes),
+            Err(CookedMeshReadError::VertexTypeMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn truncated_meshes_are_rejected() {
+        let (_, indices) = quad();
+        let bytes = cooked_bytes("truncated", &indices, &CookedMeshMeta::default());
+
+        for length in [0, 3, TOPOLOGY_OFFSET, bytes.len() - 1] {
+            assert!(matches!(
+                read_bytes::<SimpleVertex>("truncated", &bytes[..length]),
+                Err(CookedMeshReadError::Truncated)
+            ));
+        }
+    }
+
+    #[test]
+    fn huge_counts_are_rejected_before_allocating() {
+        let (_, indices) = quad();
+        let mut bytes = cooked_bytes("huge", &indices, &CookedMeshMeta::default());
+        // a few hundred gigabytes of vertices
+        bytes[VERTEX_COUNT_OFFSET..VERTEX_COUNT_OFFSET + 8]
+            .copy_from_slice(&(1_u64 << 35).to_ne_bytes());
+
+        assert!(matches!(
+            read_bytes::<SimpleVertex>("huge", &bytes),
+            Err(CookedMeshReadError::Truncated)
+        ));
+    }
+
+    // benchmark-style, timings are compared within the same build
+    #[cfg(feature = "obj")]
+    #[test]
+    fn cooked_loading_is_cheaper_than_parsing() {
+        use std::{fmt::Write, time::Instant};
+
+        const GRID: u32 = 200;
+        let mut obj = String::new();
+        for y in 0..=GRID {
+            for x in 0..=GRID {
+                writeln!(obj, "v {x}.0 {y}.5 {}.25", (x * y) % 7).unwrap();
+            }
+        }
+        for y in 0..GRID {
+            for x in 0..GRID {
+                let corner = y * (GRID + 1) + x + 1;
+                let below = corner + GRID + 1;
+                writeln!(obj, "f {corner} {} {below}", corner + 1).unwrap();
+                writeln!(obj, "f {} {} {below}", corner + 1, below + 1).unwrap();
+            }
+        }
+        let source = temp_path("grid.obj");
+        let cooked = temp_path("grid.mcmf");
+        std::fs::write(&source, obj).unwrap();
+        cook(&source, &cooked).unwrap();
+
+        let fastest = |load: &dyn Fn() -> usize| {
+            (0..3)
+                .map(|_| {
+                    let start = Instant::now();
+                    assert!(load() > 0);
+                    start.elapsed()
+                })
+                .min()
+                .unwrap()
+        };
+        let parsing = fastest(&|| SimpleVertex::read_obj(&source).unwrap().1.len());
+        let loading = fastest(&|| read_cooked::<SimpleVertex>(&cooked).unwrap().indices.len());
+        std::fs::remove_file(source).unwrap();
+        std::fs::remove_file(cooked).unwrap();
+
+        assert!(
+            loading * 4 < parsing,
+            "cooked loading took {loading:?}, parsing {parsing:?}"
+        );
+    }
+}
@@ -0,0 +1,266 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicU64, Ordering},
+};
+
+use ash::vk;
+use thiserror::Error;
+
+use crate::{
+    gfx::{
+        allocator::{AllocTag, Allocator},
+        buffer::{Buffer, BufferBuildError},
+        commands::FRAMES_IN_FLIGHT,
+        context::Context,
+        deletion_queue::DeletionQueue,
+        device::Device,
+        vertex::Vertex,
+    },
+    utils::{ThreadSafeRef, ThreadSafeRwRef},
+};
+
+// one slot may be written while every frame in flight still reads its own
+const SLOT_COUNT: usize = FRAMES_IN_FLIGHT + 1;
+
+/// What [`DynamicMesh::update`] does with more vertices or indices than the buffers can hold.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum CapacityPolicy {
+    #[default]
+    Error,
+    /// Reallocates the buffers of every slot, the previous ones are released once no frame in
+    /// flight can read them anymore.
+    Grow,
+}
+
+#[derive(Debug, Error)]
+pub enum DynamicMeshUpdateError {
+    #[error("{count} {kind} do not fit in a capacity of {capacity}")]
+    CapacityExceeded {
+        kind: &'static str,
+        count: usize,
+        capacity: usize,
+    },
+
+    #[error("buffer reallocation failed")]
+    Reallocation(#[from] BufferBuildError),
+
+    #[error("buffer memory mapping failed")]
+    MemoryMapping,
+}
+
+#[derive(Debug)]
+struct DynamicMeshSlot {
+    vertex_buffer: Buffer,
+    index_buffer: Buffer,
+    // generation of the data last written to the buffers
+    generation: u64,
+}
+
+/// Geometry rewritten every frame, e.g. procedural ribbons. Buffers are host-visible and duplicated
+/// per frame slot, so updating never waits for the GPU to be done with the previous frame.
+pub struct DynamicMesh<VertexType>
+where
+    VertexType: Vertex + bytemuck::Pod,
+{
+    pub name: String,
+
+    vertices: Vec<VertexType>,
+    indices: Vec<u32>,
+    generation: u64,
+
+    slots: Vec<DynamicMeshSlot>,
+    vertex_capacity: usize,
+    index_capacity: usize,
+    capacity_policy: CapacityPolicy,
+
+    // bookkeeping
+    frame_counter: Arc<AtomicU64>,
+    deletion_queue: ThreadSafeRef<DeletionQueue>,
+    device_ref: ThreadSafeRwRef<Device>,
+    allocator_ref: ThreadSafeRef<Allocator>,
+}
+
+impl<VertexType> DynamicMesh<VertexType>
+where
+    VertexType: Vertex + bytemuck::Pod,
+{
+    pub fn new(
+        name: &str,
+        vertex_capacity: usize,
+        index_capacity: usize,
+        ctx: &mut Context,
+    ) -> Result<Self, BufferBuildError> {
+        let mut mesh = Self {
+            name: name.to_owned(),
+            vertices: vec![],
+            indices: vec![],
+            generation: 0,
+            slots: vec![],
+            vertex_capacity: vertex_capacity.max(1),
+            index_capacity: index_capacity.max(1),
+            capacity_policy: CapacityPolicy::default(),
+            frame_counter: ctx.core.command_manager.frame_counter.clone(),
+            deletion_queue: ctx.deletion_queue.clone(),
+            device_ref: ctx.core.device_ref.clone(),
+            allocator_ref: ctx.core.allocator_ref.clone(),
+        };
+        mesh.slots = (0..SLOT_COUNT)
+            .map(|slot_index| mesh.create_slot(slot_index))
+            .collect::<Result<_, _>>()?;
+
+        Ok(mesh)
+    }
+
+    pub fn with_capacity_policy(mut self, capacity_policy: CapacityPolicy) -> Self {
+        self.capacity_policy = capacity_policy;
+        self
+    }
+
+    pub fn vertex_capacity(&self) -> usize {
+        self.vertex_capacity
+    }
+
+    pub fn index_capacity(&self) -> usize {
+        self.index_capacity
+    }
+
+    /// Replaces the geometry drawn from the frame being recorded on, the frames still in flight
+    /// keep reading their own copy.
+    pub fn update(
+        &mut self,
+        vertices: &[VertexType],
+        indices: &[u32],
+    ) -> Result<(), DynamicMeshUpdateError> {
+        if vertices.len() > self.vertex_capacity || indices.len() > self.index_capacity {
+            match self.capacity_policy {
+                CapacityPolicy::Error if vertices.len() > self.vertex_capacity => {
+                    return Err(DynamicMeshUpdateError::CapacityExceeded {
+                        kind: "vertices",
+                        count: vertices.len(),
+                        capacity: self.vertex_capacity,
+                    });
+                }
+                CapacityPolicy::Error => {
+                    return Err(DynamicMeshUpdateError::CapacityExceeded {
+                        kind: "indices",
+                        count: indices.len(),
+                        capacity: self.index_capacity,
+                    });
+                }
+                CapacityPolicy::Grow => self.grow(vertices.len(), indices.len())?,
+            }
+        }
+
+        self.vertices.clear();
+        self.vertices.extend_from_slice(vertices);
+        self.indices.clear();
+        self.indices.extend_from_slice(indices);
+        self.generation += 1;
+
+        self.sync_current_slot()
+    }
+
+    /// Binds the buffers of the frame being recorded and draws every index.
+    pub fn cmd_bind_and_draw(
+        &mut self,
+        cmd_buffer: &vk::CommandBuffer,
+        device: &Device,
+    ) -> Result<(), DynamicMeshUpdateError> {
+        // the slot may hold data older than the last update if it was skipped for a few frames
+        self.sync_current_slot()?;
+        if self.indices.is_empty() {
+            return Ok(());
+        }
+
+        let slot = &self.slots[self.current_slot_index()];
+        let index_count = self
+            .indices
+            .len()
+            .try_into()
+            .expect("dynamic mesh index count should fit in a u32");
+        unsafe {
+            device.cmd_bind_vertex_buffers(*cmd_buffer, 0, &[slot.vertex_buffer.handle], &[0]);
+            device.cmd_bind_index_buffer(
+                *cmd_buffer,
+                slot.index_buffer.handle,
+                0,
+                vk::IndexType::UINT32,
+            );
+            device.cmd_draw_indexed(*cmd_buffer, index_count, 1, 0, 0, 0);
+        }
+
+        Ok(())
+    }
+
+    fn current_slot_index(&self) -> usize {
+        // frames are submitted in order, the slot of the frame being recorded was last read by a
+        // frame that has completed since
+        (self.frame_counter.load(Ordering::Acquire) % SLOT_COUNT as u64) as usize
+    }
+
+    fn sync_current_slot(&mut self) -> Result<(), DynamicMeshUpdateError> {
+        let slot_index = self.current_slot_index();
+        let slot = &mut self.slots[slot_index];
+        if slot.generation == self.generation {
+            return Ok(());
+        }
+
+        slot.vertex_buffer
+            .upload_data(bytemuck::cast_slice(&self.vertices))
+            .map_err(|_| DynamicMeshUpdateError::MemoryMapping)?;
+        slot.index_buffer
+            .upload_data(bytemuck::cast_slice(&self.indices))
+            .map_err(|_| DynamicMeshUpdateError::MemoryMapping)?;
+        slot.generation = self.generation;
+
+        Ok(())
+    }
+
+    fn grow(
+        &mut self,
+        vertex_count: usize,
+        index_count: usize,
+    ) -> Result<(), DynamicMeshUpdateError> {
+        self.vertex_capacity = self.vertex_capacity.max(vertex_count.next_power_of_two());
+        self.index_capacity = self.index_capacity.max(index_count.next_power_of_two());
+        log::debug!(
+            "growing dynamic mesh {} to {} vertices and {} indices",
+            self.name,
+            self.vertex_capacity,
+            self.index_capacity
+        );
+
+        for slot_index in 0..SLOT_COUNT {
+            let slot = self.create_slot(slot_index)?;
+            let previous_slot = std::mem::replace(&mut self.slots[slot_index], slot);
+            self.deletion_queue.lock().defer(previous_slot);
+        }
+
+        Ok(())
+    }
+
+    fn create_slot(&self, slot_index: usize) -> Result<DynamicMeshSlot, BufferBuildError> {
+        let vertex_size = self.vertex_capacity * std::mem::size_of::<VertexType>();
+        let vertex_buffer = Buffer::builder(vertex_size as u64)
+            .with_name(&format!("{} vertex data (slot {slot_index})", self.name))
+            .with_tag(AllocTag::Mesh)
+            .with_usage(vk::BufferUsageFlags::VERTEX_BUFFER)
+            .with_memory_location(gpu_allocator::MemoryLocation::CpuToGpu)
+            .build_internal(self.device_ref.clone(), self.allocator_ref.clone())?;
+
+        let index_size = self.index_capacity * std::mem::size_of::<u32>();
+        let index_buffer = Buffer::builder(index_size as u64)
+            .with_name(&format!("{} index data (slot {slot_index})", self.name))
+            .with_tag(AllocTag::Mesh)
+            .with_usage(vk::BufferUsageFlags::INDEX_BUFFER)
+            .with_memory_location(gpu_allocator::MemoryLocation::CpuToGpu)
+            .build_internal(self.device_ref.clone(), self.allocator_ref.clone())?;
+
+        // a new slot holds no data, it is filled before its first draw
+        Ok(DynamicMeshSlot {
+            vertex_buffer,
+            index_buffer,
+            generation: u64::MAX,
+        })
+    }
+}
@@ -0,0 +1,505 @@
+use std::{
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+    },
+};
+
+use thiserror::Error;
+
+#[cfg(any(feature = "obj", feature = "ply"))]
+use crate::gfx::vertex::simple::{SimpleVertex, SimpleVertexMeshLoadingError};
+use crate::{
+    gfx::{context::Context, vertex::Vertex},
+    utils::ThreadSafeRef,
+};
+
+use super::{
+    Mesh, MeshDataUploadError, MeshTopology, UploadData,
+    cooked::{CookedMeshReadError, read_cooked},
+    upload_mesh_data,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct LoadId(u64);
+
+/// Milestones of a load, in the order they are reached.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LoadStage {
+    Queued,
+    FileRead,
+    Parsed,
+    VerticesBuilt,
+    /// The GPU upload was queued, cancelling the load from now on only drops its result.
+    UploadQueued,
+    Done,
+}
+
+impl LoadStage {
+    /// Coarse completion ratio, between 0.0 and 1.0.
+    pub fn progress(&self) -> f32 {
+        match self {
+            Self::Queued => 0.0,
+            Self::FileRead => 0.2,
+            Self::Parsed => 0.5,
+            Self::VerticesBuilt => 0.7,
+            Self::UploadQueued => 0.9,
+            Self::Done => 1.0,
+        }
+    }
+}
+
+/// Sent by a [`MeshLoader`] as its loads progress, e.g. to drive a loading screen.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LoadEvent {
+    Progress { id: LoadId, stage: LoadStage },
+    Completed { id: LoadId },
+    Failed { id: LoadId },
+    Cancelled { id: LoadId },
+}
+
+#[derive(Debug, Error)]
+pub enum MeshLoadError {
+    #[error("load was cancelled")]
+    Cancelled,
+
+    #[error("mesh parsing failed")]
+    Parse(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    #[error("mesh data upload failed")]
+    Upload(#[from] MeshDataUploadError),
+
+    #[error("loading thread creation failed")]
+    ThreadSpawn(#[source] std::io::Error),
+}
+
+impl From<CookedMeshReadError> for MeshLoadError {
+    fn from(value: CookedMeshReadError) -> Self {
+        Self::Parse(Box::new(value))
+    }
+}
+
+#[cfg(any(feature = "obj", feature = "ply"))]
+impl From<SimpleVertexMeshLoadingError> for MeshLoadError {
+    fn from(value: SimpleVertexMeshLoadingError) -> Self {
+        Self::Parse(Box::new(value))
+    }
+}
+
+/// CPU-side result of a parser, uploaded by [`MeshLoader::poll`].
+#[derive(Debug)]
+pub struct ParsedMesh<VertexType> {
+    pub vertices: Vec<VertexType>,
+    pub indices: Vec<u32>,
+    pub topology: MeshTopology,
+}
+
+#[derive(Debug)]
+struct LoadState {
+    cancelled: AtomicBool,
+    stage: ThreadSafeRef<LoadStage>,
+}
+
+/// Handed to parsers so that they report their progress and stop early once cancelled.
+pub struct LoadCheckpoint {
+    id: LoadId,
+    state: Arc<LoadState>,
+    events: Sender<LoadEvent>,
+}
+
+impl LoadCheckpoint {
+    /// Records `stage` as reached, fails with [`MeshLoadError::Cancelled`] if the load was
+    /// cancelled, in which case the parser should return the error right away.
+    pub fn reach(&self, stage: LoadStage) -> Result<(), MeshLoadError> {
+        if self.state.cancelled.load(Ordering::Acquire) {
+            return Err(MeshLoadError::Cancelled);
+        }
+
+        *self.state.stage.lock() = stage;
+        // nobody listening is fine, events are only informative
+        let _ = self.events.send(LoadEvent::Progress { id: self.id, stage });
+
+        Ok(())
+    }
+}
+
+/// Pending load started by a [`MeshLoader`].
+pub struct MeshLoadHandle<VertexType>
+where
+    VertexType: Vertex,
+{
+    id: LoadId,
+    state: Arc<LoadState>,
+    result: ThreadSafeRef<Option<Result<ThreadSafeRef<Mesh<VertexType>>, MeshLoadError>>>,
+}
+
+impl<VertexType> MeshLoadHandle<VertexType>
+where
+    VertexType: Vertex,
+{
+    pub fn id(&self) -> LoadId {
+        self.id
+    }
+
+    /// Parsing stops at its next checkpoint and no GPU work is queued for the load. If the upload
+    /// was already queued, it completes but the mesh is dropped.
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::Release);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::Acquire)
+    }
+
+    pub fn stage(&self) -> LoadStage {
+        *self.state.stage.lock()
+    }
+
+    pub fn progress(&self) -> f32 {
+        self.stage().progress()
+    }
+
+    /// Gives the mesh or the load error once the load is over, and the handle back otherwise.
+    pub fn try_resolve(
+        self,
+    ) -> Result<Result<ThreadSafeRef<Mesh<VertexType>>, MeshLoadError>, Self> {
+        let result = self.result.lock().take();
+        result.ok_or(self)
+    }
+}
+
+struct ParsedLoad<VertexType>
+where
+    VertexType: Vertex,
+{
+    name: String,
+    state: Arc<LoadState>,
+    result_slot: ThreadSafeRef<Option<Result<ThreadSafeRef<Mesh<VertexType>>, MeshLoadError>>>,
+    parsed: Result<ParsedMesh<VertexType>, MeshLoadError>,
+    id: LoadId,
+}
+
+/// Parses meshes on background threads, then uploads them from [`Self::poll`], which is expected
+/// to be called once per update.
+pub struct MeshLoader<VertexType>
+where
+    VertexType: Vertex,
+{
+    next_id: u64,
+    parsed_sender: Sender<ParsedLoad<VertexType>>,
+    parsed_receiver: Receiver<ParsedLoad<VertexType>>,
+    event_sender: Sender<LoadEvent>,
+    event_receiver: Receiver<LoadEvent>,
+    in_flight: usize,
+}
+
+impl<VertexType> Default for MeshLoader<VertexType>
+where
+    VertexType: Vertex,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<VertexType> MeshLoader<VertexType>
+where
+    VertexType: Vertex,
+{
+    pub fn new() -> Self {
+        let (parsed_sender, parsed_receiver) = mpsc::channel();
+        let (event_sender, event_receiver) = mpsc::channel();
+
+        Self {
+            next_id: 0,
+            parsed_sender,
+            parsed_receiver,
+            event_sender,
+            event_receiver,
+            in_flight: 0,
+        }
+    }
+
+    /// Runs `parser` on a new thread. It should call [`LoadCheckpoint::reach`] for each stage it
+    /// goes through, up to [`LoadStage::VerticesBuilt`].
+    pub fn load_with<Parser>(&mut self, name: &str, parser: Parser) -> MeshLoadHandle<VertexType>
+    where
+        Parser: FnOnce(&LoadCheckpoint) -> Result<ParsedMesh<VertexType>, MeshLoadError>
+            + Send
+            + 'static,
+    {
+        let id = LoadId(self.next_id);
+        self.next_id += 1;
+        self.in_flight += 1;
+
+        let state = Arc::new(LoadState {
+            cancelled: AtomicBool::new(false),
+            stage: ThreadSafeRef::new(LoadStage::Queued),
+        });
+        let result_slot = ThreadSafeRef::new(None);
+        let handle = MeshLoadHandle {
+            id,
+            state: state.clone(),
+            result: result_slot.clone(),
+        };
+
+        let checkpoint = LoadCheckpoint {
+            id,
+            state: state.clone(),
+            events: self.event_sender.clone(),
+        };
+        let parsed_sender = self.parsed_sender.clone();
+        let name = name.to_owned();
+        let spawn_result = std::thread::Builder::new()
+            .name(format!("mesh loader \"{name}\""))
+            .spawn(move || {
+                let parsed = parser(&checkpoint);
+                // the data of a cancelled load is dropped here rather than after the next poll
+                let parsed = match parsed {
+                    Ok(_) if state.cancelled.load(Ordering::Acquire) => {
+                        Err(MeshLoadError::Cancelled)
+                    }
+                    parsed => parsed,
+                };
+
+                let _ = parsed_sender.send(ParsedLoad {
+                    name,
+                    state,
+                    result_slot,
+                    parsed,
+                    id,
+                });
+            });
+        if let Err(err) = spawn_result {
+            log::error!("mesh loading thread creation failed: {err}");
+            self.in_flight -= 1;
+            *handle.result.lock() = Some(Err(MeshLoadError::ThreadSpawn(err)));
+            let _ = self.event_sender.send(LoadEvent::Failed { id });
+        }
+
+        handle
+    }
+
+    pub fn load_cooked(&mut self, path: &Path) -> MeshLoadHandle<VertexType>
+    where
+        VertexType: bytemuck::Pod,
+    {
+        let path = path.to_owned();
+        self.load_with(&mesh_name(&path), move |checkpoint| {
+            checkpoint.reach(LoadStage::FileRead)?;
+            let data = read_cooked::<VertexType>(&path)?;
+            checkpoint.reach(LoadStage::Parsed)?;
+            checkpoint.reach(LoadStage::VerticesBuilt)?;
+
+            Ok(ParsedMesh {
+                vertices: data.vertices,
+                indices: data.indices,
+                topology: MeshTopology::TriangleList,
+            })
+        })
+    }
+
+    /// Uploads the meshes parsed since the last call, and resolves the handles of finished loads.
+    pub fn poll(&mut self, ctx: &mut Context) {
+        self.poll_with(|name, parsed| {
+            upload_mesh_data(name, &parsed.vertices, &parsed.indices, ctx)
+        });
+    }
+
+    // `upload` is never called for loads cancelled before their upload was queued
+    fn poll_with(
+        &mut self,
+        mut upload: impl FnMut(&str, &ParsedMesh<VertexType>) -> Result<UploadData, MeshDataUploadError>,
+    ) {
+        while let Ok(load) = self.parsed_receiver.try_recv() {
+            self.in_flight -= 1;
+            let id = load.id;
+            let result = load.parsed.and_then(|parsed| {
+                // checked again, the load may have been cancelled while waiting for the poll
+                if load.state.cancelled.load(Ordering::Acquire) {
+                    return Err(MeshLoadError::Cancelled);
+                }
+
+                *load.state.stage.lock() = LoadStage::UploadQueued;
+                let _ = self.event_sender.send(LoadEvent::Progress {
+                    id,
+                    stage: LoadStage::UploadQueued,
+                });
+                let upload_result = upload(&load.name, &parsed)?;
+                if load.state.cancelled.load(Ordering::Acquire) {
+                    return Err(MeshLoadError::Cancelled);
+                }
+
+                Ok(ThreadSafeRef::new(Mesh {
+                    name: load.name,
+                    vertices: parsed.vertices,
+                    indices: parsed.indices,
+                    topology: parsed.topology,
+                    vertex_buffer: upload_result.vertex_buffer,
+                    index_buffer: upload_result.index_buffer,
+                }))
+            });
+
+            let event = match &result {
+                Ok(_) => {
+                    *load.state.stage.lock() = LoadStage::Done;
+                    LoadEvent::Completed { id }
+                }
+                Err(MeshLoadError::Cancelled) => LoadEvent::Cancelled { id },
+                Err(err) => {
+                    log::error!("mesh load failed: {err}");
+                    LoadEvent::Failed { id }
+                }
+            };
+            *load.result_slot.lock() = Some(result);
+            let _ = self.event_sender.send(event);
+        }
+    }
+
+    /// Events sent since the last call, in the order they happened.
+    pub fn events(&self) -> impl Iterator<Item = LoadEvent> + '_ {
+        self.event_receiver.try_iter()
+    }
+
+    /// Number of loads whose parsing is not over yet.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight
+    }
+}
+
+#[cfg(feature = "obj")]
+impl MeshLoader<SimpleVertex> {
+    pub fn load_obj(&mut self, path: &Path) -> MeshLoadHandle<SimpleVertex> {
+        let path = path.to_owned();
+        self.load_with(&mesh_name(&path), move |checkpoint| {
+            // the OBJ parser reads the file itself, a load cancelled while queued never opens it
+            checkpoint.reach(LoadStage::FileRead)?;
+            let (vertices, indices) = SimpleVertex::read_obj(&path)?;
+            checkpoint.reach(LoadStage::Parsed)?;
+            checkpoint.reach(LoadStage::VerticesBuilt)?;
+
+            Ok(ParsedMesh {
+                vertices,
+                indices,
+                topology: MeshTopology::TriangleList,
+            })
+        })
+    }
+}
+
+#[cfg(feature = "ply")]
+impl MeshLoader<SimpleVertex> {
+    pub fn load_ply(&mut self, path: &Path) -> MeshLoadHandle<SimpleVertex> {
+        let path = path.to_owned();
+        self.load_with(&mesh_name(&path), move |checkpoint| {
+            // the PLY parser reads the file itself, a load cancelled while queued never opens it
+            checkpoint.reach(LoadStage::FileRead)?;
+            let (vertices, indices) = SimpleVertex::read_ply(&path)?;
+            checkpoint.reach(LoadStage::Parsed)?;
+            checkpoint.reach(LoadStage::VerticesBuilt)?;
+
+            Ok(ParsedMesh {
+                vertices,
+                indices,
+                topology: MeshTopology::TriangleList,
+            })
+        })
+    }
+}
+
+fn mesh_name(path: &Path) -> String {
+    path.file_stem()
+        .unwrap_or(std::ffi::OsStr::new("<unknown>"))
+        .to_str()
+        .unwrap_or("<invalid>")
+        .to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::gfx::vertex::simple::SimpleVertex;
+
+    use super::*;
+
+    const PARSER_STAGES: [LoadStage; 3] = [
+        LoadStage::FileRead,
+        LoadStage::Parsed,
+        LoadStage::VerticesBuilt,
+    ];
+
+    fn wait_until(mut condition: impl FnMut() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !condition() {
+            assert!(Instant::now() < deadline, "the loading thread is stuck");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    // Cancels a load blocked right after reaching `stage`, then lets its parser go on
+    fn cancel_at(stage: LoadStage) {
+        let mut loader = MeshLoader::<SimpleVertex>::new();
+        let (gate, gate_receiver) = mpsc::channel::<()>();
+        let handle = loader.load_with("gated", move |checkpoint| {
+            for stage in PARSER_STAGES {
+                // blocks until the test lets the parser go on, or cancelled it
+                let _ = gate_receiver.recv();
+                checkpoint.reach(stage)?;
+            }
+
+            Ok(ParsedMesh {
+                vertices: vec![],
+                indices: vec![],
+                topology: MeshTopology::TriangleList,
+            })
+        });
+
+        for _ in PARSER_STAGES
+            .iter()
+            .take_while(|&&reached| reached <= stage)
+        {
+            gate.send(()).unwrap();
+        }
+        wait_until(|| handle.stage() == stage);
+        handle.cancel();
+        drop(gate);
+
+        wait_until(|| {
+            loader.poll_with(|_, _| panic!("a cancelled load should not be uploaded"));
+            loader.in_flight() == 0
+        });
+
+        let id = handle.id();
+        let events: Vec<_> = loader.events().collect();
+        let progress: Vec<_> = PARSER_STAGES
+            .into_iter()
+            .take_while(|&reached| reached <= stage)
+            .map(|stage| LoadEvent::Progress { id, stage })
+            .collect();
+        assert_eq!(events[..progress.len()], progress);
+        assert_eq!(events[progress.len()..], [LoadEvent::Cancelled { id }]);
+        assert_eq!(handle.stage(), stage);
+        assert!(matches!(
+            handle.try_resolve(),
+            Ok(Err(MeshLoadError::Cancelled))
+        ));
+    }
+
+    #[test]
+    fn cancelling_after_the_file_read_stops_parsing() {
+        cancel_at(LoadStage::FileRead);
+    }
+
+    #[test]
+    fn cancelling_after_parsing_stops_building_vertices() {
+        cancel_at(LoadStage::Parsed);
+    }
+
+    #[test]
+    fn cancelling_built_vertices_skips_the_upload() {
+        cancel_at(LoadStage::VerticesBuilt);
+    }
+}
@@ -0,0 +1,357 @@
+pub mod cooked;
+pub mod dynamic;
+pub mod loader;
+pub mod primitives;
+
+use ash::vk;
+#[cfg(feature = "ply")]
+use ply_rs::ply;
+use thiserror::Error;
+
+use crate::gfx::{
+    allocator::AllocTag,
+    buffer::{Buffer, BufferBuildError},
+    commands::ImmediateCommandError,
+    context::Context,
+    debug,
+    device::Device,
+    vertex::Vertex,
+};
+#[cfg(feature = "ply")]
+use crate::{gfx::vertex::read_ply, utils::ThreadSafeRef};
+
+/// Index marking the end of a strip when primitive restart is enabled.
+pub const PRIMITIVE_RESTART_INDEX: u32 = u32::MAX;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum MeshTopology {
+    #[default]
+    TriangleList,
+    /// With `primitive_restart`, [`PRIMITIVE_RESTART_INDEX`] starts a new strip.
+    TriangleStrip { primitive_restart: bool },
+}
+
+impl MeshTopology {
+    pub fn primitive_topology(&self) -> vk::PrimitiveTopology {
+        match self {
+            Self::TriangleList => vk::PrimitiveTopology::TRIANGLE_LIST,
+            Self::TriangleStrip { .. } => vk::PrimitiveTopology::TRIANGLE_STRIP,
+        }
+    }
+
+    pub fn primitive_restart(&self) -> bool {
+        matches!(
+            self,
+            Self::TriangleStrip {
+                primitive_restart: true
+            }
+        )
+    }
+
+    /// Index triplets of every triangle drawn from `indices`. Strip triangles are given in the
+    /// winding of the first one, and the degenerate ones used to join strips are skipped.
+    pub fn triangles(&self, indices: &[u32]) -> Vec<[u32; 3]> {
+        match self {
+            Self::TriangleList => indices
+                .chunks_exact(3)
+                .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+                .collect(),
+            Self::TriangleStrip { primitive_restart } => {
+                let strips: Box<dyn Iterator<Item = &[u32]>> = if *primitive_restart {
+                    Box::new(indices.split(|&index| index == PRIMITIVE_RESTART_INDEX))
+                } else {
+                    Box::new(std::iter::once(indices))
+                };
+
+                strips
+                    .flat_map(|strip| strip.windows(3).enumerate())
+                    .map(|(position, window)| match position % 2 {
+                        0 => [window[0], window[1], window[2]],
+                        _ => [window[1], window[0], window[2]],
+                    })
+                    .filter(|[a, b, c]| a != b && b != c && a != c)
+                    .collect()
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Mesh<VertexType>
+where
+    VertexType: Vertex,
+{
+    pub name: String,
+
+    pub vertices: Vec<VertexType>,
+    pub indices: Vec<u32>,
+    pub topology: MeshTopology,
+    pub vertex_buffer: Buffer,
+    pub index_buffer: Buffer,
+}
+
+impl<VertexType> Mesh<VertexType>
+where
+    VertexType: Vertex,
+{
+    /// Topology and primitive restart state of the pipelines drawing this mesh, as set by
+    /// [`with_mesh_topology`](crate::gfx::pipeline::GraphicsPipelineBuilder::with_mesh_topology).
+    pub fn pipeline_topology(&self) -> (vk::PrimitiveTopology, bool) {
+        (
+            self.topology.primitive_topology(),
+            self.topology.primitive_restart(),
+        )
+    }
+
+    pub fn triangles(&self) -> Vec<[u32; 3]> {
+        self.topology.triangles(&self.indices)
+    }
+}
+
+/// Vertex-only geometry (e.g. scanned points), drawn without an index buffer.
+#[derive(Debug)]
+pub struct PointCloud<VertexType>
+where
+    VertexType: Vertex,
+{
+    pub name: String,
+
+    pub vertices: Vec<VertexType>,
+    pub vertex_buffer: Buffer,
+}
+
+#[derive(Error, Debug)]
+pub enum PointCloudLoadingError {
+    #[error("file reading failed")]
+    FileReadingError(#[from] std::io::Error),
+
+    #[error("file has no vertices")]
+    NoVertices,
+
+    #[error("vertex data upload failed")]
+    VertexBufferUpload(#[from] UploadError),
+}
+
+impl<VertexType> PointCloud<VertexType>
+where
+    VertexType: Vertex,
+{
+    pub fn from_vertices(
+        name: &str,
+        vertices: Vec<VertexType>,
+        ctx: &mut Context,
+    ) -> Result<Self, PointCloudLoadingError> {
+        if vertices.is_empty() {
+            return Err(PointCloudLoadingError::NoVertices);
+        }
+
+        let vertex_buffer = upload_vertex_buffer(name, &vertices, ctx)?;
+
+        Ok(Self {
+            name: name.to_owned(),
+            vertices,
+            vertex_buffer,
+        })
+    }
+
+    /// Loads the vertices of a PLY file, ignoring any face it may contain.
+    #[cfg(feature = "ply")]
+    pub fn load_from_path_ply(
+        path: &std::path::Path,
+        ctx: &mut Context,
+    ) -> Result<ThreadSafeRef<Self>, PointCloudLoadingError>
+    where
+        VertexType: ply::PropertyAccess,
+    {
+        let name = path
+            .file_stem()
+            .unwrap_or(std::ffi::OsStr::new("<unknown>"))
+            .to_str()
+            .unwrap_or("<invalid>")
+            .to_owned();
+
+        let (vertices, _) = read_ply::<VertexType>(path)?;
+
+        Ok(ThreadSafeRef::new(Self::from_vertices(
+            &name, vertices, ctx,
+        )?))
+    }
+
+    pub fn cmd_draw(&self, cmd_buffer: &vk::CommandBuffer, device: &Device) {
+        let vertex_count = self
+            .vertices
+            .len()
+            .try_into()
+            .expect("point cloud vertex count should fit in a u32");
+
+        unsafe {
+            device.cmd_bind_vertex_buffers(*cmd_buffer, 0, &[self.vertex_buffer.handle], &[0]);
+            device.cmd_draw(*cmd_buffer, vertex_count, 1, 0, 0);
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum UploadError {
+    #[error("staging buffer creation failed")]
+    StagingBufferCreation(BufferBuildError),
+
+    #[error("staging buffer memory mapping failed")]
+    MemoryMapping,
+
+    #[error("main buffer creation failed")]
+    MainBufferCreation(BufferBuildError),
+
+    #[error("memory copy failed")]
+    CopyCommand(ImmediateCommandError),
+}
+
+pub fn upload_vertex_buffer<VertexType>(
+    name: &str,
+    vertices: &[VertexType],
+    ctx: &mut Context,
+) -> Result<Buffer, UploadError>
+where
+    VertexType: Vertex,
+{
+    let vertex_data_size: u64 = std::mem::size_of_val(vertices).try_into().unwrap();
+    let mut vertex_staging_buffer = Buffer::builder(vertex_data_size)
+        .with_name(&format!("{} vertex staging", name))
+        .with_tag(AllocTag::Staging)
+        .with_usage(vk::BufferUsageFlags::TRANSFER_SRC)
+        .with_memory_location(gpu_allocator::MemoryLocation::CpuToGpu)
+        .build(ctx)
+        .map_err(UploadError::StagingBufferCreation)?;
+
+    // vertices are not required to be Pod, they are copied as is instead of through a typed slice
+    let vertex_staging_ptr = vertex_staging_buffer
+        .mapped_mut::<u8>()
+        .ok_or(UploadError::MemoryMapping)?
+        .as_mut_ptr()
+        .cast::<VertexType>();
+
+    unsafe {
+        std::ptr::copy_nonoverlapping(vertices.as_ptr(), vertex_staging_ptr, vertices.len());
+    };
+
+    let buffer_usage_flags =
+        vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::VERTEX_BUFFER;
+    // copied on the transfer queue, drawn from on the graphics one
+    let upload_queue_families = ctx.core.device_ref.read().upload_queue_families();
+
+    let vertex_buffer = Buffer::builder(vertex_data_size)
+        .with_name(&format!("{} vertex data", name))
+        .with_tag(AllocTag::Mesh)
+        .with_usage(buffer_usage_flags)
+        .with_shared_queue_families(&upload_queue_families)
+        .with_memory_location(gpu_allocator::MemoryLocation::GpuOnly)
+        .build(ctx)
+        .map_err(UploadError::MainBufferCreation)?;
+
+    let _operation = debug::operation(|| format!("mesh upload \"{name}\" vertex copy"));
+    ctx.core
+        .command_manager
+        .transfer_command(|cmd_buffer| {
+            let copy_info = vk::BufferCopy::default().size(vertex_data_size);
+
+            unsafe {
+                ctx.core.device_ref.read().cmd_copy_buffer(
+                    *cmd_buffer,
+                    vertex_staging_buffer.handle,
+                    vertex_buffer.handle,
+                    std::slice::from_ref(&copy_info),
+                );
+            }
+        })
+        .map_err(UploadError::CopyCommand)?;
+
+    Ok(vertex_buffer)
+}
+
+pub fn upload_index_buffer(
+    name: &str,
+    indices: &[u32],
+    ctx: &mut Context,
+) -> Result<Buffer, UploadError> {
+    let index_data_size: u64 = std::mem::size_of_val(indices).try_into().unwrap();
+    let mut index_staging_buffer = Buffer::builder(index_data_size)
+        .with_name(&format!("{} index staging", name))
+        .with_tag(AllocTag::Staging)
+        .with_usage(vk::BufferUsageFlags::TRANSFER_SRC)
+        .with_memory_location(gpu_allocator::MemoryLocation::CpuToGpu)
+        .build(ctx)
+        .map_err(UploadError::StagingBufferCreation)?;
+
+    let raw_indices =
+        bytemuck::try_cast_slice(indices).expect("casting from u32 to u8 should always (?) work");
+    index_staging_buffer
+        .mapped_mut::<u8>()
+        .ok_or(UploadError::MemoryMapping)?[..raw_indices.len()]
+        .copy_from_slice(raw_indices);
+
+    let buffer_usage_flags =
+        vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::INDEX_BUFFER;
+    let upload_queue_families = ctx.core.device_ref.read().upload_queue_families();
+
+    let index_buffer = Buffer::builder(index_data_size)
+        .with_name(&format!("{} index data", name))
+        .with_tag(AllocTag::Mesh)
+        .with_usage(buffer_usage_flags)
+        .with_shared_queue_families(&upload_queue_families)
+        .with_memory_location(gpu_allocator::MemoryLocation::GpuOnly)
+        .build(ctx)
+        .map_err(UploadError::MainBufferCreation)?;
+
+    let _operation = debug::operation(|| format!("mesh upload \"{name}\" index copy"));
+    ctx.core
+        .command_manager
+        .transfer_command(|cmd_buffer| {
+            let copy_info = vk::BufferCopy::default().size(index_data_size);
+
+            unsafe {
+                ctx.core.device_ref.read().cmd_copy_buffer(
+                    *cmd_buffer,
+                    index_staging_buffer.handle,
+                    index_buffer.handle,
+                    std::slice::from_ref(&copy_info),
+                );
+            }
+        })
+        .map_err(UploadError::CopyCommand)?;
+
+    Ok(index_buffer)
+}
+
+pub struct UploadData {
+    pub vertex_buffer: Buffer,
+    pub index_buffer: Buffer,
+}
+
+#[derive(Error, Debug)]
+pub enum MeshDataUploadError {
+    #[error("upload of vertex data failed")]
+    VertexBufferUpload(UploadError),
+
+    #[error("upload of index data failed")]
+    IndexBufferUpload(UploadError),
+}
+
+pub fn upload_mesh_data<VertexType>(
+    name: &str,
+    vertices: &[VertexType],
+    indices: &[u32],
+    ctx: &mut Context,
+) -> Result<UploadData, MeshDataUploadError>
+where
+    VertexType: Vertex,
+{
+    let vertex_buffer = upload_vertex_buffer(name, vertices, ctx)
+        .map_err(MeshDataUploadError::VertexBufferUpload)?;
+    let index_buffer =
+        upload_index_buffer(name, indices, ctx).map_err(MeshDataUploadError::IndexBufferUpload)?;
+
+    Ok(UploadData {
+        vertex_buffer,
+        index_buffer,
+    })
+}
@@ -0,0 +1,62 @@
+use crate::{
+    gfx::{context::Context, vertex::simple::SimpleVertex},
+    math::{Vec2, Vec3},
+    utils::ThreadSafeRef,
+};
+
+use super::{Mesh, MeshDataUploadError, MeshTopology, PRIMITIVE_RESTART_INDEX, upload_mesh_data};
+
+/// Flat grid of `columns` by `rows` quads in the XZ plane, centered on the origin and facing +Y.
+/// Each row of quads is a triangle strip, rows being separated by a restart index.
+pub fn strip_plane(
+    name: &str,
+    columns: u32,
+    rows: u32,
+    size: Vec2,
+    ctx: &mut Context,
+) -> Result<ThreadSafeRef<Mesh<SimpleVertex>>, MeshDataUploadError> {
+    let (vertices, indices) = strip_plane_data(columns, rows, size);
+    let upload_result = upload_mesh_data(name, &vertices, &indices, ctx)?;
+
+    Ok(ThreadSafeRef::new(Mesh {
+        name: name.to_owned(),
+        vertices,
+        indices,
+        topology: MeshTopology::TriangleStrip {
+            primitive_restart: true,
+        },
+        vertex_buffer: upload_result.vertex_buffer,
+        index_buffer: upload_result.index_buffer,
+    }))
+}
+
+fn strip_plane_data(columns: u32, rows: u32, size: Vec2) -> (Vec<SimpleVertex>, Vec<u32>) {
+    let (columns, rows) = (columns.max(1), rows.max(1));
+    let cell_size = size / Vec2::new(columns as f32, rows as f32);
+    let origin = -size / 2.0;
+
+    let vertices = (0..=rows)
+        .flat_map(|row| (0..=columns).map(move |column| (column, row)))
+        .map(|(column, row)| {
+            let position = origin + cell_size * Vec2::new(column as f32, row as f32);
+            SimpleVertex {
+                position: Vec3::new(position.x, 0.0, position.y),
+            }
+        })
+        .collect();
+
+    // starting on the near row keeps the first triangle, and so the whole strip, counter-clockwise
+    // seen from above
+    let row_length = columns + 1;
+    let mut indices = Vec::with_capacity(((2 * row_length + 1) * rows) as usize);
+    for row in 0..rows {
+        for column in 0..row_length {
+            indices.push(row * row_length + column);
+            indices.push((row + 1) * row_length + column);
+        }
+        indices.push(PRIMITIVE_RESTART_INDEX);
+    }
+    indices.pop();
+
+    (vertices, indices)
+}
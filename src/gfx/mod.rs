@@ -1,14 +1,43 @@
-pub(crate) mod allocator;
-pub(crate) mod debug;
+pub(crate) mod deletion_queue;
+pub(crate) mod frame_limiter;
+pub(crate) mod gpu_core;
+pub(crate) mod handle_registry;
 pub(crate) mod instance;
+#[cfg(feature = "png")]
+pub(crate) mod png;
+pub(crate) mod presentation;
 pub(crate) mod surface;
 
+pub mod allocator;
+pub mod barrier;
+pub mod breadcrumbs;
 pub mod buffer;
+pub mod color;
+pub mod command_buffers;
 pub mod commands;
 pub mod context;
+pub mod debug;
+pub mod deferred;
+pub mod defrag;
 pub mod device;
+pub mod features;
+pub mod feedback;
+pub mod frame_constants;
+pub mod frame_hooks;
+pub mod frame_timing;
 pub mod image;
 pub mod mesh;
+pub mod overrides;
+pub mod pipeline;
+pub mod readback;
+pub mod reflection;
 pub mod render_graph;
+pub mod render_scale;
+pub mod residency;
+pub mod resize;
+pub mod sampler;
+pub mod staging;
 pub mod swapchain;
+pub mod texture;
+pub mod timeline;
 pub mod vertex;
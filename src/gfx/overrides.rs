@@ -0,0 +1,395 @@
+use std::sync::Arc;
+
+use ash::vk;
+
+use super::{commands::FRAMES_IN_FLIGHT, device::PhysicalDeviceInfo, swapchain::PresentPreference};
+
+const VALIDATION: &str = "MIEL_VALIDATION";
+const DEVICE: &str = "MIEL_DEVICE";
+const PRESENT_MODE: &str = "MIEL_PRESENT_MODE";
+const DISABLE_PASS: &str = "MIEL_DISABLE_PASS";
+const FRAMES_IN_FLIGHT_VAR: &str = "MIEL_FRAMES_IN_FLIGHT";
+// recognized so that setting them is reported instead of silently doing nothing
+const UNSUPPORTED: [&str; 1] = ["MIEL_GPU_TIMING"];
+
+/// How the physical device is chosen among the compatible ones. Devices requested by index or
+/// name that are not available make the context creation fail.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum DeviceSelection {
+    /// Same as [`Self::PreferDiscrete`].
+    #[default]
+    Auto,
+    PreferDiscrete,
+    /// E.g. to save battery on laptops with two GPUs.
+    PreferIntegrated,
+    /// Index in the order devices are enumerated by the driver.
+    ByIndex(usize),
+    /// Case-insensitive substring of the device name.
+    ByName(String),
+    Custom(DeviceScorer),
+}
+
+/// Callback of [`DeviceSelection::Custom`], returning the index of the chosen device among the
+/// compatible ones, given discrete GPUs first.
+#[derive(Clone)]
+pub struct DeviceScorer(Arc<DeviceScoreFn>);
+
+type DeviceScoreFn = dyn Fn(&[PhysicalDeviceInfo]) -> usize + Send + Sync;
+
+impl DeviceScorer {
+    pub fn new(select: impl Fn(&[PhysicalDeviceInfo]) -> usize + Send + Sync + 'static) -> Self {
+        Self(Arc::new(select))
+    }
+
+    pub(crate) fn select(&self, candidates: &[PhysicalDeviceInfo]) -> usize {
+        (self.0)(candidates)
+    }
+}
+
+impl std::fmt::Debug for DeviceScorer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("DeviceScorer")
+    }
+}
+
+// the same callback, there is no comparing closures otherwise
+impl PartialEq for DeviceScorer {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for DeviceScorer {}
+
+/// Whether the validation layers and the debug messenger are enabled.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum ValidationMode {
+    /// Enabled in debug builds only.
+    #[default]
+    Auto,
+    ForceOn,
+    ForceOff,
+}
+
+impl ValidationMode {
+    pub fn is_enabled(self) -> bool {
+        match self {
+            Self::Auto => cfg!(debug_assertions),
+            Self::ForceOn => true,
+            Self::ForceOff => false,
+        }
+    }
+}
+
+impl From<bool> for ValidationMode {
+    fn from(enabled: bool) -> Self {
+        match enabled {
+            true => Self::ForceOn,
+            false => Self::ForceOff,
+        }
+    }
+}
+
+/// Engine knobs set programmatically through
+/// [`ContextCreateInfo`](super::context::ContextCreateInfo), which environment variables may
+/// override.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EngineTunables {
+    pub validation: ValidationMode,
+    pub device: DeviceSelection,
+    pub present_preference: PresentPreference,
+    /// Render passes with these names are left out of every bound render graph.
+    pub disabled_passes: Vec<String>,
+    /// Between 1, which serializes the CPU and the GPU, and [`FRAMES_IN_FLIGHT`], the default.
+    pub frames_in_flight: usize,
+}
+
+impl Default for EngineTunables {
+    fn default() -> Self {
+        Self {
+            validation: ValidationMode::Auto,
+            device: DeviceSelection::Auto,
+            present_preference: PresentPreference::default(),
+            disabled_passes: vec![],
+            frames_in_flight: FRAMES_IN_FLIGHT,
+        }
+    }
+}
+
+/// Tunables overridden through `MIEL_*` environment variables, read once when the context is
+/// created:
+/// - `MIEL_VALIDATION`: `1`/`true`/`on` or `0`/`false`/`off`
+/// - `MIEL_DEVICE`: device index, or a substring of its name
+/// - `MIEL_PRESENT_MODE`: `fifo`, `fifo_relaxed`, `mailbox` or `immediate`
+/// - `MIEL_DISABLE_PASS`: comma-separated render pass names
+/// - `MIEL_FRAMES_IN_FLIGHT`: between 1 and [`FRAMES_IN_FLIGHT`]
+///
+/// Invalid values are logged and ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EngineOverrides {
+    pub validation: Option<bool>,
+    pub device: Option<DeviceSelection>,
+    pub present_mode: Option<vk::PresentModeKHR>,
+    pub disabled_passes: Vec<String>,
+    pub frames_in_flight: Option<usize>,
+}
+
+impl EngineOverrides {
+    pub fn from_env() -> Self {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    pub(crate) fn from_vars(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        for name in UNSUPPORTED {
+            if lookup(name).is_some() {
+                log::warn!("ignoring {name}, this override is not supported");
+            }
+        }
+
+        Self {
+            validation: parse_var(&lookup, VALIDATION, parse_bool),
+            device: parse_var(&lookup, DEVICE, parse_device),
+            present_mode: parse_var(&lookup, PRESENT_MODE, parse_present_mode),
+            disabled_passes: parse_var(&lookup, DISABLE_PASS, parse_pass_list).unwrap_or_default(),
+            frames_in_flight: parse_var(&lookup, FRAMES_IN_FLIGHT_VAR, parse_frames_in_flight),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub(crate) fn apply(&self, tunables: &mut EngineTunables) {
+        if let Some(validation) = self.validation {
+            let validation = ValidationMode::from(validation);
+            log::info!(
+                "{VALIDATION} overrides validation: {:?} -> {validation:?}",
+                tunables.validation
+            );
+            tunables.validation = validation;
+        }
+        if let Some(device) = &self.device {
+            log::info!(
+                "{DEVICE} overrides device selection: {:?} -> {device:?}",
+                tunables.device
+            );
+            tunables.device = device.clone();
+        }
+        if let Some(present_mode) = self.present_mode {
+            let present_preference = PresentPreference::Explicit(present_mode);
+            log::info!(
+                "{PRESENT_MODE} overrides present preference: {:?} -> {present_preference:?}",
+                tunables.present_preference
+            );
+            tunables.present_preference = present_preference;
+        }
+        if !self.disabled_passes.is_empty() {
+            log::info!(
+                "{DISABLE_PASS} overrides disabled passes: {:?} -> {:?}",
+                tunables.disabled_passes,
+                self.disabled_passes
+            );
+            tunables.disabled_passes = self.disabled_passes.clone();
+        }
+        if let Some(frames_in_flight) = self.frames_in_flight {
+            log::info!(
+                "{FRAMES_IN_FLIGHT_VAR} overrides frames in flight: {} -> {frames_in_flight}",
+                tunables.frames_in_flight
+            );
+            tunables.frames_in_flight = frames_in_flight;
+        }
+    }
+}
+
+fn parse_var<T>(
+    lookup: &impl Fn(&str) -> Option<String>,
+    name: &str,
+    parse: fn(&str) -> Option<T>,
+) -> Option<T> {
+    let value = lookup(name)?;
+    let parsed = parse(value.trim());
+    if parsed.is_none() {
+        log::warn!("ignoring invalid value \"{value}\" for {name}");
+    }
+
+    parsed
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "1" | "true" | "on" => Some(true),
+        "0" | "false" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_device(value: &str) -> Option<DeviceSelection> {
+    if value.is_empty() {
+        return None;
+    }
+
+    Some(match value.parse() {
+        Ok(index) => DeviceSelection::ByIndex(index),
+        Err(_) => DeviceSelection::ByName(value.to_owned()),
+    })
+}
+
+fn parse_present_mode(value: &str) -> Option<vk::PresentModeKHR> {
+    match value.to_ascii_lowercase().as_str() {
+        "fifo" => Some(vk::PresentModeKHR::FIFO),
+        "fifo_relaxed" => Some(vk::PresentModeKHR::FIFO_RELAXED),
+        "mailbox" => Some(vk::PresentModeKHR::MAILBOX),
+        "immediate" => Some(vk::PresentModeKHR::IMMEDIATE),
+        _ => None,
+    }
+}
+
+fn parse_frames_in_flight(value: &str) -> Option<usize> {
+    value
+        .parse()
+        .ok()
+        .filter(|count| (1..=FRAMES_IN_FLIGHT).contains(count))
+}
+
+// An empty name is most likely a typo, the whole list is rejected rather than partially applied
+fn parse_pass_list(value: &str) -> Option<Vec<String>> {
+    value
+        .split(',')
+        .map(|name| {
+            let name = name.trim();
+            (!name.is_empty()).then(|| name.to_owned())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn overrides(vars: &[(&str, &str)]) -> EngineOverrides {
+        let vars = vars
+            .iter()
+            .map(|&(name, value)| (name.to_owned(), value.to_owned()))
+            .collect::<HashMap<_, _>>();
+        EngineOverrides::from_vars(|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn no_variables_override_nothing() {
+        assert!(overrides(&[]).is_empty());
+        // only reported
+        assert!(overrides(&[("MIEL_GPU_TIMING", "1")]).is_empty());
+    }
+
+    #[test]
+    fn valid_values_are_parsed() {
+        let parsed = overrides(&[
+            (VALIDATION, " ON "),
+            (DEVICE, "1"),
+            (PRESENT_MODE, "Mailbox"),
+            (DISABLE_PASS, "bloom composite, text overlay"),
+            (FRAMES_IN_FLIGHT_VAR, "1"),
+        ]);
+
+        assert_eq!(
+            parsed,
+            EngineOverrides {
+                validation: Some(true),
+                device: Some(DeviceSelection::ByIndex(1)),
+                present_mode: Some(vk::PresentModeKHR::MAILBOX),
+                disabled_passes: vec!["bloom composite".to_owned(), "text overlay".to_owned()],
+                frames_in_flight: Some(1),
+            }
+        );
+        assert_eq!(overrides(&[(VALIDATION, "0")]).validation, Some(false));
+        assert_eq!(
+            overrides(&[(DEVICE, "GeForce")]).device,
+            Some(DeviceSelection::ByName("GeForce".to_owned()))
+        );
+        assert_eq!(
+            overrides(&[(PRESENT_MODE, "fifo_relaxed")]).present_mode,
+            Some(vk::PresentModeKHR::FIFO_RELAXED)
+        );
+        assert_eq!(
+            overrides(&[(FRAMES_IN_FLIGHT_VAR, &FRAMES_IN_FLIGHT.to_string())]).frames_in_flight,
+            Some(FRAMES_IN_FLIGHT)
+        );
+    }
+
+    #[test]
+    fn invalid_values_are_ignored() {
+        let parsed = overrides(&[
+            (VALIDATION, "yes"),
+            (DEVICE, "  "),
+            (PRESENT_MODE, "vsync"),
+            (DISABLE_PASS, "bloom,,text overlay"),
+            (FRAMES_IN_FLIGHT_VAR, "0"),
+        ]);
+        assert!(parsed.is_empty());
+
+        let too_many = (FRAMES_IN_FLIGHT + 1).to_string();
+        assert_eq!(
+            overrides(&[(FRAMES_IN_FLIGHT_VAR, &too_many)]).frames_in_flight,
+            None
+        );
+        assert_eq!(
+            overrides(&[(FRAMES_IN_FLIGHT_VAR, "-1")]).frames_in_flight,
+            None
+        );
+        // one invalid variable does not discard the others
+        assert_eq!(
+            overrides(&[(VALIDATION, "maybe"), (PRESENT_MODE, "immediate")]),
+            EngineOverrides {
+                present_mode: Some(vk::PresentModeKHR::IMMEDIATE),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn overrides_take_precedence_over_tunables() {
+        let mut tunables = EngineTunables {
+            validation: ValidationMode::ForceOn,
+            device: DeviceSelection::PreferIntegrated,
+            present_preference: PresentPreference::Explicit(vk::PresentModeKHR::FIFO),
+            disabled_passes: vec!["shadow map".to_owned()],
+            frames_in_flight: FRAMES_IN_FLIGHT,
+        };
+        overrides(&[
+            (VALIDATION, "off"),
+            (DEVICE, "0"),
+            (PRESENT_MODE, "immediate"),
+            (DISABLE_PASS, "bloom"),
+            (FRAMES_IN_FLIGHT_VAR, "1"),
+        ])
+        .apply(&mut tunables);
+
+        assert_eq!(
+            tunables,
+            EngineTunables {
+                validation: ValidationMode::ForceOff,
+                device: DeviceSelection::ByIndex(0),
+                present_preference: PresentPreference::Explicit(vk::PresentModeKHR::IMMEDIATE),
+                disabled_passes: vec!["bloom".to_owned()],
+                frames_in_flight: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn tunables_are_kept_without_overrides() {
+        let tunables = EngineTunables {
+            validation: ValidationMode::ForceOn,
+            device: DeviceSelection::ByName("radeon".to_owned()),
+            disabled_passes: vec!["shadow map".to_owned()],
+            frames_in_flight: 1,
+            ..Default::default()
+        };
+        let mut overridden = tunables.clone();
+        overrides(&[(PRESENT_MODE, "invalid")]).apply(&mut overridden);
+
+        assert_eq!(overridden, tunables);
+    }
+}
@@ -0,0 +1,580 @@
+use std::io::Cursor;
+
+use ash::vk;
+use thiserror::Error;
+
+use crate::utils::ThreadSafeRwRef;
+
+use super::{
+    context::Context,
+    device::Device,
+    frame_constants::{FRAME_CONSTANTS_SET, FrameConstants},
+    mesh::MeshTopology,
+    reflection::{GpuLayout, LayoutMismatch, UniformBlock, reflect_uniform_blocks},
+    vertex::VertexInputDescription,
+};
+
+/// Reads SPIR-V words from raw bytes (e.g. from `include_bytes!`), taking care of alignment and
+/// endianness.
+pub fn spirv_from_bytes(bytes: &[u8]) -> Result<Vec<u32>, std::io::Error> {
+    ash::util::read_spv(&mut Cursor::new(bytes))
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BlendMode {
+    Opaque,
+    AlphaBlend,
+    Additive,
+}
+
+impl BlendMode {
+    fn attachment_state(&self) -> vk::PipelineColorBlendAttachmentState {
+        let state = vk::PipelineColorBlendAttachmentState::default()
+            .color_write_mask(vk::ColorComponentFlags::RGBA);
+
+        match self {
+            BlendMode::Opaque => state.blend_enable(false),
+            BlendMode::AlphaBlend => state
+                .blend_enable(true)
+                .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+                .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+                .color_blend_op(vk::BlendOp::ADD)
+                .src_alpha_blend_factor(vk::BlendFactor::ONE)
+                .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+                .alpha_blend_op(vk::BlendOp::ADD),
+            BlendMode::Additive => state
+                .blend_enable(true)
+                .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+                .dst_color_blend_factor(vk::BlendFactor::ONE)
+                .color_blend_op(vk::BlendOp::ADD)
+                .src_alpha_blend_factor(vk::BlendFactor::ONE)
+                .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+                .alpha_blend_op(vk::BlendOp::ADD),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum PipelineValidationError {
+    #[error("no vertex shader was provided")]
+    MissingVertexShader,
+
+    #[error("topology {0:?} is not supported")]
+    UnsupportedTopology(vk::PrimitiveTopology),
+
+    #[error("primitive restart is only supported for strip and fan topologies (got {0:?})")]
+    PrimitiveRestartOnList(vk::PrimitiveTopology),
+
+    #[error("device feature \"{0}\" is required but not enabled")]
+    FeatureNotEnabled(&'static str),
+
+    #[error("frame constants do not match the block declared by the shaders")]
+    FrameConstantsLayout(#[source] LayoutMismatch),
+}
+
+#[derive(Debug, Error)]
+pub enum PipelineBuildError {
+    #[error("pipeline description is invalid")]
+    Validation(#[from] PipelineValidationError),
+
+    #[error("vulkan creation of a shader module failed")]
+    ShaderModuleCreation(vk::Result),
+
+    #[error("vulkan creation of the pipeline layout failed")]
+    LayoutCreation(vk::Result),
+
+    #[error("vulkan creation of the pipeline failed")]
+    VulkanCreation(vk::Result),
+}
+
+pub struct GraphicsPipelineBuilder {
+    pub name: String,
+
+    pub vertex_shader: Vec<u32>,
+    pub fragment_shader: Option<Vec<u32>>,
+    pub vertex_input: VertexInputDescription,
+
+    pub topology: vk::PrimitiveTopology,
+    pub primitive_restart: bool,
+    pub polygon_mode: vk::PolygonMode,
+    pub line_width: f32,
+    pub cull_mode: vk::CullModeFlags,
+    pub front_face: vk::FrontFace,
+
+    pub color_formats: Vec<vk::Format>,
+    pub blend_mode: BlendMode,
+    pub depth_format: Option<vk::Format>,
+    pub depth_compare_op: Option<vk::CompareOp>,
+    pub depth_write: bool,
+    pub dynamic_depth_bias: bool,
+    pub dynamic_depth_bounds: bool,
+
+    pub frame_constants: bool,
+    pub descriptor_set_layouts: Vec<vk::DescriptorSetLayout>,
+    pub push_constant_ranges: Vec<vk::PushConstantRange>,
+}
+
+impl Default for GraphicsPipelineBuilder {
+    fn default() -> Self {
+        Self {
+            name: String::from("unnamed pipeline"),
+
+            vertex_shader: vec![],
+            fragment_shader: None,
+            vertex_input: VertexInputDescription {
+                bindings: vec![],
+                attributes: vec![],
+            },
+
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            primitive_restart: false,
+            polygon_mode: vk::PolygonMode::FILL,
+            line_width: 1.0,
+            cull_mode: vk::CullModeFlags::BACK,
+            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+
+            color_formats: vec![],
+            blend_mode: BlendMode::Opaque,
+            depth_format: None,
+            depth_compare_op: None,
+            depth_write: false,
+            dynamic_depth_bias: false,
+            dynamic_depth_bounds: false,
+
+            frame_constants: false,
+            descriptor_set_layouts: vec![],
+            push_constant_ranges: vec![],
+        }
+    }
+}
+
+impl GraphicsPipelineBuilder {
+    pub fn new(name: &str) -> Self {
+        Self::default().with_name(name)
+    }
+
+    pub fn with_name(mut self, name: &str) -> Self {
+        name.clone_into(&mut self.name);
+        self
+    }
+
+    pub fn with_vertex_shader(mut self, spirv: &[u32]) -> Self {
+        self.vertex_shader = spirv.to_vec();
+        self
+    }
+
+    pub fn with_fragment_shader(mut self, spirv: &[u32]) -> Self {
+        self.fragment_shader = Some(spirv.to_vec());
+        self
+    }
+
+    pub fn with_vertex_input(mut self, vertex_input: VertexInputDescription) -> Self {
+        self.vertex_input = vertex_input;
+        self
+    }
+
+    pub fn with_topology(mut self, topology: vk::PrimitiveTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    pub fn primitive_restart(mut self, enabled: bool) -> Self {
+        self.primitive_restart = enabled;
+        self
+    }
+
+    /// Sets the topology and primitive restart state to draw meshes of the given topology, e.g.
+    /// from [`Mesh::topology`](super::mesh::Mesh::topology).
+    pub fn with_mesh_topology(self, topology: MeshTopology) -> Self {
+        self.with_topology(topology.primitive_topology())
+            .primitive_restart(topology.primitive_restart())
+    }
+
+    pub fn with_polygon_mode(mut self, polygon_mode: vk::PolygonMode) -> Self {
+        self.polygon_mode = polygon_mode;
+        self
+    }
+
+    pub fn with_line_width(mut self, line_width: f32) -> Self {
+        self.line_width = line_width;
+        self
+    }
+
+    pub fn with_cull_mode(
+        mut self,
+        cull_mode: vk::CullModeFlags,
+        front_face: vk::FrontFace,
+    ) -> Self {
+        self.cull_mode = cull_mode;
+        self.front_face = front_face;
+        self
+    }
+
+    pub fn with_color_formats(mut self, color_formats: &[vk::Format]) -> Self {
+        self.color_formats = color_formats.to_vec();
+        self
+    }
+
+    /// Renders to no color attachment, e.g. for depth-only passes. This is the default, calling it
+    /// makes the intent explicit and undoes [`Self::with_color_formats`].
+    pub fn no_color_output(mut self) -> Self {
+        self.color_formats.clear();
+        self
+    }
+
+    pub fn with_blend_mode(mut self, blend_mode: BlendMode) -> Self {
+        self.blend_mode = blend_mode;
+        self
+    }
+
+    /// Enables depth testing with the given compare op against an attachment of `format`.
+    pub fn with_depth(
+        mut self,
+        format: vk::Format,
+        compare_op: vk::CompareOp,
+        write: bool,
+    ) -> Self {
+        self.depth_format = Some(format);
+        self.depth_compare_op = Some(compare_op);
+        self.depth_write = write;
+        self
+    }
+
+    /// Enables depth bias, with its factors set at record time (see
+    /// [`SimpleRenderPass::set_depth_bias`](super::render_graph::render_pass::SimpleRenderPass::set_depth_bias)).
+    pub fn dynamic_depth_bias(mut self) -> Self {
+        self.dynamic_depth_bias = true;
+        self
+    }
+
+    /// Enables the depth bounds test, with its bounds set at record time through
+    /// `cmd_set_depth_bounds`. Requires the `depthBounds` feature.
+    pub fn dynamic_depth_bounds(mut self) -> Self {
+        self.dynamic_depth_bounds = true;
+        self
+    }
+
+    /// Prepends the frame constants layout to the descriptor set layouts when built, user sets then
+    /// start at index 1.
+    pub fn with_frame_constants(mut self) -> Self {
+        self.frame_constants = true;
+        self
+    }
+
+    pub fn with_descriptor_set_layouts(mut self, layouts: &[vk::DescriptorSetLayout]) -> Self {
+        self.descriptor_set_layouts = layouts.to_vec();
+        self
+    }
+
+    pub fn with_push_constant_ranges(mut self, ranges: &[vk::PushConstantRange]) -> Self {
+        self.push_constant_ranges = ranges.to_vec();
+        self
+    }
+
+    pub fn validate(&self, device: &Device) -> Result<(), PipelineValidationError> {
+        if self.vertex_shader.is_empty() {
+            return Err(PipelineValidationError::MissingVertexShader);
+        }
+
+        match self.topology {
+            vk::PrimitiveTopology::POINT_LIST
+            | vk::PrimitiveTopology::LINE_LIST
+            | vk::PrimitiveTopology::TRIANGLE_LIST => {
+                if self.primitive_restart {
+                    return Err(PipelineValidationError::PrimitiveRestartOnList(
+                        self.topology,
+                    ));
+                }
+            }
+            vk::PrimitiveTopology::LINE_STRIP
+            | vk::PrimitiveTopology::TRIANGLE_STRIP
+            | vk::PrimitiveTopology::TRIANGLE_FAN => (),
+            // adjacency and patch topologies need geometry or tessellation stages
+            topology => return Err(PipelineValidationError::UnsupportedTopology(topology)),
+        }
+
+        let features = &device.enabled_features;
+        if self.line_width != 1.0 && features.wide_lines == vk::FALSE {
+            return Err(PipelineValidationError::FeatureNotEnabled("wideLines"));
+        }
+        if self.polygon_mode != vk::PolygonMode::FILL && features.fill_mode_non_solid == vk::FALSE {
+            return Err(PipelineValidationError::FeatureNotEnabled(
+                "fillModeNonSolid",
+            ));
+        }
+        if self.dynamic_depth_bounds && features.depth_bounds == vk::FALSE {
+            return Err(PipelineValidationError::FeatureNotEnabled("depthBounds"));
+        }
+
+        Ok(())
+    }
+
+    pub fn build(mut self, ctx: &Context) -> Result<GraphicsPipeline, PipelineBuildError> {
+        if self.frame_constants {
+            self.descriptor_set_layouts
+                .insert(0, ctx.frame_constants_layout());
+        }
+
+        self.build_internal(ctx.core.device_ref.clone())
+    }
+
+    pub(crate) fn build_internal(
+        self,
+        device_ref: ThreadSafeRwRef<Device>,
+    ) -> Result<GraphicsPipeline, PipelineBuildError> {
+        let device = device_ref.read();
+        self.validate(&device)?;
+
+        let uniform_blocks = self.reflect_uniform_blocks();
+        // a mismatch renders garbage without any validation error, better fail at startup
+        if cfg!(debug_assertions)
+            && self.frame_constants
+            && let Some(block) = uniform_blocks
+                .iter()
+                .find(|block| block.set == FRAME_CONSTANTS_SET && block.binding == 0)
+        {
+            block
+                .validate::<FrameConstants>()
+                .map_err(PipelineValidationError::FrameConstantsLayout)?;
+        }
+
+        let create_module = |code: &[u32]| {
+            let create_info = vk::ShaderModuleCreateInfo::default().code(code);
+            unsafe { device.create_shader_module(&create_info, None) }
+                .map_err(PipelineBuildError::ShaderModuleCreation)
+        };
+        let vertex_module = create_module(&self.vertex_shader)?;
+        let fragment_module = match &self.fragment_shader {
+            Some(code) => match create_module(code) {
+                Ok(module) => Some(module),
+                Err(err) => {
+                    unsafe { device.destroy_shader_module(vertex_module, None) };
+                    return Err(err);
+                }
+            },
+            None => None,
+        };
+
+        let result = self.create_pipeline(&device, vertex_module, fragment_module);
+
+        // modules are not needed anymore once the pipeline exists
+        unsafe { device.destroy_shader_module(vertex_module, None) };
+        if let Some(fragment_module) = fragment_module {
+            unsafe { device.destroy_shader_module(fragment_module, None) };
+        }
+
+        let (handle, layout) = result?;
+
+        Ok(GraphicsPipeline {
+            name: self.name,
+            handle,
+            layout,
+            topology: self.topology,
+            color_formats: self.color_formats,
+            depth_format: self.depth_format,
+            dynamic_depth_bias: self.dynamic_depth_bias,
+            dynamic_depth_bounds: self.dynamic_depth_bounds,
+            frame_constants: self.frame_constants,
+            uniform_blocks,
+            device_ref: device_ref.clone(),
+        })
+    }
+
+    // blocks used by both stages are declared identically by both
+    fn reflect_uniform_blocks(&self) -> Vec<UniformBlock> {
+        let mut uniform_blocks = reflect_uniform_blocks(&self.vertex_shader);
+        for block in self
+            .fragment_shader
+            .as_deref()
+            .map(reflect_uniform_blocks)
+            .unwrap_or_default()
+        {
+            if !uniform_blocks
+                .iter()
+                .any(|known| known.set == block.set && known.binding == block.binding)
+            {
+                uniform_blocks.push(block);
+            }
+        }
+
+        uniform_blocks
+    }
+
+    fn create_pipeline(
+        &self,
+        device: &Device,
+        vertex_module: vk::ShaderModule,
+        fragment_module: Option<vk::ShaderModule>,
+    ) -> Result<(vk::Pipeline, vk::PipelineLayout), PipelineBuildError> {
+        let mut stages = vec![
+            vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(vertex_module)
+                .name(c"main"),
+        ];
+        if let Some(fragment_module) = fragment_module {
+            stages.push(
+                vk::PipelineShaderStageCreateInfo::default()
+                    .stage(vk::ShaderStageFlags::FRAGMENT)
+                    .module(fragment_module)
+                    .name(c"main"),
+            );
+        }
+
+        let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::default()
+            .vertex_binding_descriptions(&self.vertex_input.bindings)
+            .vertex_attribute_descriptions(&self.vertex_input.attributes);
+        let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::default()
+            .topology(self.topology)
+            .primitive_restart_enable(self.primitive_restart);
+        let viewport_state = vk::PipelineViewportStateCreateInfo::default()
+            .viewport_count(1)
+            .scissor_count(1);
+        let rasterization_state = vk::PipelineRasterizationStateCreateInfo::default()
+            .polygon_mode(self.polygon_mode)
+            .line_width(self.line_width)
+            .cull_mode(self.cull_mode)
+            .front_face(self.front_face)
+            .depth_bias_enable(self.dynamic_depth_bias);
+        let multisample_state = vk::PipelineMultisampleStateCreateInfo::default()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+        let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::default()
+            .depth_test_enable(self.depth_compare_op.is_some())
+            .depth_write_enable(self.depth_write)
+            .depth_compare_op(self.depth_compare_op.unwrap_or(vk::CompareOp::ALWAYS))
+            .depth_bounds_test_enable(self.dynamic_depth_bounds);
+        let blend_attachments = vec![self.blend_mode.attachment_state(); self.color_formats.len()];
+        let color_blend_state =
+            vk::PipelineColorBlendStateCreateInfo::default().attachments(&blend_attachments);
+        let mut dynamic_states = vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        if self.dynamic_depth_bias {
+            dynamic_states.push(vk::DynamicState::DEPTH_BIAS);
+        }
+        if self.dynamic_depth_bounds {
+            dynamic_states.push(vk::DynamicState::DEPTH_BOUNDS);
+        }
+        let dynamic_state =
+            vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
+
+        let mut rendering_info = vk::PipelineRenderingCreateInfo::default()
+            .color_attachment_formats(&self.color_formats)
+            .depth_attachment_format(self.depth_format.unwrap_or(vk::Format::UNDEFINED));
+
+        let layout_info = vk::PipelineLayoutCreateInfo::default()
+            .set_layouts(&self.descriptor_set_layouts)
+            .push_constant_ranges(&self.push_constant_ranges);
+        let layout = unsafe { device.create_pipeline_layout(&layout_info, None) }
+            .map_err(PipelineBuildError::LayoutCreation)?;
+
+        let create_info = vk::GraphicsPipelineCreateInfo::default()
+            .stages(&stages)
+            .vertex_input_state(&vertex_input_state)
+            .input_assembly_state(&input_assembly_state)
+            .viewport_state(&viewport_state)
+            .rasterization_state(&rasterization_state)
+            .multisample_state(&multisample_state)
+            .depth_stencil_state(&depth_stencil_state)
+            .color_blend_state(&color_blend_state)
+            .dynamic_state(&dynamic_state)
+            .layout(layout)
+            .push_next(&mut rendering_info);
+
+        let handle = unsafe {
+            device.create_graphics_pipelines(
+                vk::PipelineCache::null(),
+                std::slice::from_ref(&create_info),
+                None,
+            )
+        };
+
+        match handle {
+            Ok(pipelines) => Ok((pipelines[0], layout)),
+            Err((_, err)) => {
+                unsafe { device.destroy_pipeline_layout(layout, None) };
+                Err(PipelineBuildError::VulkanCreation(err))
+            }
+        }
+    }
+}
+
+/// Attachments a pipeline renders to, declared by the passes binding it (see
+/// [`RenderPass::pipeline_outputs`](super::render_graph::render_pass::RenderPass::pipeline_outputs))
+/// to be checked against their attachments when the render graph is bound.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PipelineOutputs {
+    pub pipeline_name: String,
+    pub color_formats: Vec<vk::Format>,
+    pub depth_format: Option<vk::Format>,
+}
+
+pub struct GraphicsPipeline {
+    pub name: String,
+    pub handle: vk::Pipeline,
+    pub layout: vk::PipelineLayout,
+
+    pub topology: vk::PrimitiveTopology,
+    pub color_formats: Vec<vk::Format>,
+    pub depth_format: Option<vk::Format>,
+    pub dynamic_depth_bias: bool,
+    pub dynamic_depth_bounds: bool,
+    /// Whether the frame constants are expected at set 0.
+    pub frame_constants: bool,
+    /// Uniform blocks declared by the shaders, as reflected when built.
+    pub uniform_blocks: Vec<UniformBlock>,
+
+    // bookkeeping
+    device_ref: ThreadSafeRwRef<Device>,
+}
+
+impl GraphicsPipeline {
+    pub fn builder(name: &str) -> GraphicsPipelineBuilder {
+        GraphicsPipelineBuilder::new(name)
+    }
+
+    pub fn outputs(&self) -> PipelineOutputs {
+        PipelineOutputs {
+            pipeline_name: self.name.clone(),
+            color_formats: self.color_formats.clone(),
+            depth_format: self.depth_format,
+        }
+    }
+
+    /// Checks that `T` is laid out like the uniform block at `set` and `binding`, e.g. for material
+    /// parameters, naming the first member whose offset differs.
+    pub fn validate_uniform_layout<T: GpuLayout>(
+        &self,
+        set: u32,
+        binding: u32,
+    ) -> Result<(), LayoutMismatch> {
+        self.uniform_blocks
+            .iter()
+            .find(|block| block.set == set && block.binding == binding)
+            .ok_or(LayoutMismatch::MissingBlock { set, binding })?
+            .validate::<T>()
+    }
+
+    pub fn cmd_bind(&self, cmd_buffer: &vk::CommandBuffer, device: &Device) {
+        unsafe {
+            device.cmd_bind_pipeline(*cmd_buffer, vk::PipelineBindPoint::GRAPHICS, self.handle)
+        };
+    }
+}
+
+impl std::fmt::Debug for GraphicsPipeline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GraphicsPipeline")
+            .field("name", &self.name)
+            .field("handle", &self.handle)
+            .field("topology", &self.topology)
+            .finish()
+    }
+}
+
+impl Drop for GraphicsPipeline {
+    fn drop(&mut self) {
+        let device = self.device_ref.read();
+
+        unsafe { device.destroy_pipeline(self.handle, None) };
+        unsafe { device.destroy_pipeline_layout(self.layout, None) };
+    }
+}
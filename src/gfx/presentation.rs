@@ -0,0 +1,241 @@
+use ash::vk;
+use winit::raw_window_handle::{RawDisplayHandle, RawWindowHandle};
+
+use super::{
+    context::{ContextCreateError, RenderError},
+    gpu_core::GpuCore,
+    surface::{DeviceSetupError, Surface, SurfaceCreateError},
+    swapchain::{
+        DepthConfig, PresentPreference, SurfaceProperties, Swapchain, SwapchainCreateError,
+        SwapchainOptions,
+    },
+};
+
+/// What is known of the swapchain, kept while the context is suspended and has none.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct SwapchainSummary {
+    pub properties: SurfaceProperties,
+    pub unorm_format: Option<vk::Format>,
+    pub depth_format: Option<vk::Format>,
+}
+
+/// The surface of a window and the swapchain presenting to it, or the image standing in for them
+/// when rendering offscreen.
+pub(crate) struct Presentation {
+    // references the surface, which must outlive it
+    pub(crate) swapchain: Swapchain,
+    // `None` when rendering offscreen
+    pub(crate) surface: Option<Surface>,
+    // applied by the next swapchain recreation when changed
+    pub(crate) present_preference: PresentPreference,
+    // resolved once, the device does not change along with the surface
+    depth_format: Option<vk::Format>,
+    options: SwapchainOptions,
+}
+
+impl Presentation {
+    pub fn new(
+        core: &GpuCore,
+        display_handle: RawDisplayHandle,
+        window_handle: RawWindowHandle,
+        extent: vk::Extent2D,
+        present_preference: PresentPreference,
+        depth_config: &DepthConfig,
+        options: SwapchainOptions,
+    ) -> Result<Self, ContextCreateError> {
+        let depth_format = depth_config.select_format(&core.instance, &core.physical_device)?;
+
+        Self::with_depth_format(
+            core,
+            display_handle,
+            window_handle,
+            extent,
+            present_preference,
+            depth_format,
+            options,
+        )
+    }
+
+    /// Creates the presentation again for a window, e.g. when the application is resumed, with
+    /// the depth format already selected.
+    pub fn with_depth_format<E>(
+        core: &GpuCore,
+        display_handle: RawDisplayHandle,
+        window_handle: RawWindowHandle,
+        extent: vk::Extent2D,
+        present_preference: PresentPreference,
+        depth_format: Option<vk::Format>,
+        options: SwapchainOptions,
+    ) -> Result<Self, E>
+    where
+        E: From<SurfaceCreateError> + From<DeviceSetupError> + From<SwapchainCreateError>,
+    {
+        let mut surface = create_surface(core, display_handle, window_handle)?;
+        surface.setup_from_device(&core.physical_device, present_preference, &options.formats)?;
+
+        let swapchain = Swapchain::new(
+            &core.instance,
+            &core.physical_device,
+            core.device_ref.clone(),
+            &surface,
+            None,
+            extent,
+            depth_format,
+            &options,
+            core.allocator_ref.clone(),
+        )?;
+
+        Ok(Self {
+            swapchain,
+            surface: Some(surface),
+            present_preference,
+            depth_format,
+            options,
+        })
+    }
+
+    pub fn offscreen(
+        core: &GpuCore,
+        extent: vk::Extent2D,
+        depth_config: &DepthConfig,
+    ) -> Result<Self, ContextCreateError> {
+        let depth_format = depth_config.select_format(&core.instance, &core.physical_device)?;
+        let swapchain = Swapchain::offscreen(
+            &core.instance,
+            core.device_ref.clone(),
+            extent,
+            depth_format,
+            core.allocator_ref.clone(),
+        )?;
+
+        Ok(Self {
+            swapchain,
+            surface: None,
+            present_preference: PresentPreference::default(),
+            depth_format,
+            options: SwapchainOptions::default(),
+        })
+    }
+
+    pub fn summary(&self) -> SwapchainSummary {
+        SwapchainSummary {
+            properties: self.swapchain.properties(),
+            unorm_format: self.swapchain.unorm_format,
+            depth_format: self.swapchain.depth_format,
+        }
+    }
+
+    /// `suggested_size` is only used when the surface lets the swapchain pick its extent.
+    pub fn recreate_swapchain(
+        &mut self,
+        core: &GpuCore,
+        suggested_size: vk::Extent2D,
+    ) -> Result<(), RenderError> {
+        self.rebuild_swapchain(core, suggested_size, true)
+    }
+
+    fn rebuild_swapchain(
+        &mut self,
+        core: &GpuCore,
+        suggested_size: vk::Extent2D,
+        retire_current: bool,
+    ) -> Result<(), RenderError> {
+        let Some(surface) = self.surface.as_mut() else {
+            self.swapchain = Swapchain::offscreen(
+                &core.instance,
+                core.device_ref.clone(),
+                suggested_size,
+                self.depth_format,
+                core.allocator_ref.clone(),
+            )?;
+
+            return Ok(());
+        };
+
+        // the format may have changed along with the monitor, capabilities are queried again by
+        // the swapchain itself
+        surface.setup_from_device(
+            &core.physical_device,
+            self.present_preference,
+            &self.options.formats,
+        )?;
+        // retired rather than destroyed first, so that its queued images are still presented
+        self.swapchain = Swapchain::new(
+            &core.instance,
+            &core.physical_device,
+            core.device_ref.clone(),
+            surface,
+            retire_current.then_some(&self.swapchain),
+            suggested_size,
+            self.depth_format,
+            &self.options,
+            core.allocator_ref.clone(),
+        )?;
+
+        Ok(())
+    }
+
+    /// Whether a swapchain can be created for the surface, which has no area e.g. while its window
+    /// is minimized.
+    pub fn surface_has_area(&self, core: &GpuCore) -> Result<bool, SwapchainCreateError> {
+        let Some(capabilities) = self.surface_capabilities(core)? else {
+            return Ok(true);
+        };
+
+        // undefined extents are decided by the swapchain, from the window size
+        Ok(capabilities.current_extent.width != 0 && capabilities.current_extent.height != 0)
+    }
+
+    /// Current rotation of the surface, which the swapchain only follows once recreated.
+    pub fn surface_transform(
+        &self,
+        core: &GpuCore,
+    ) -> Result<vk::SurfaceTransformFlagsKHR, SwapchainCreateError> {
+        Ok(self
+            .surface_capabilities(core)?
+            .map_or(vk::SurfaceTransformFlagsKHR::IDENTITY, |capabilities| {
+                capabilities.current_transform
+            }))
+    }
+
+    fn surface_capabilities(
+        &self,
+        core: &GpuCore,
+    ) -> Result<Option<vk::SurfaceCapabilitiesKHR>, SwapchainCreateError> {
+        self.surface
+            .as_ref()
+            .map(|surface| surface.query_capabilities(&core.physical_device))
+            .transpose()
+            .map_err(SwapchainCreateError::CapabilitiesFetching)
+    }
+
+    /// Replaces a lost surface with a new one created from the window, along with the swapchain
+    /// presenting to it.
+    pub fn recreate_surface(
+        &mut self,
+        core: &GpuCore,
+        display_handle: RawDisplayHandle,
+        window_handle: RawWindowHandle,
+    ) -> Result<(), RenderError> {
+        let surface = create_surface(core, display_handle, window_handle)?;
+
+        // the old swapchain still references the previous surface, which must outlive it
+        // and cannot be retired by a swapchain of the new surface
+        let _previous_surface = self.surface.replace(surface);
+        self.rebuild_swapchain(core, self.swapchain.extent, false)
+    }
+}
+
+fn create_surface(
+    core: &GpuCore,
+    display_handle: RawDisplayHandle,
+    window_handle: RawWindowHandle,
+) -> Result<Surface, SurfaceCreateError> {
+    let surface = Surface::create(&core.entry, &core.instance, display_handle, window_handle)?;
+    if !surface.supports_queue_family(&core.physical_device, core.physical_device.present_qf_index)
+    {
+        return Err(SurfaceCreateError::PresentUnsupported);
+    }
+
+    Ok(surface)
+}
@@ -0,0 +1,465 @@
+use ash::vk;
+use thiserror::Error;
+
+use crate::utils::{ThreadSafeRef, ThreadSafeRwRef};
+
+use super::{
+    allocator::{AllocTag, Allocator},
+    buffer::{Buffer, BufferBuilder},
+    commands::is_frame_complete,
+    device::Device,
+    render_graph::resource::{FrameResources, ResourceID},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PixelValue {
+    U32(u32),
+    Raw(Vec<u8>),
+}
+
+#[derive(Debug, Clone, Error)]
+pub enum PixelReadbackError {
+    #[error("readback buffer creation failed: {0}")]
+    BufferCreation(String),
+
+    #[error("resource {0:?} is not part of the bound render graph anymore")]
+    ResourceLost(ResourceID),
+
+    #[error("readback buffer memory mapping failed")]
+    MemoryMapping,
+
+    #[error("resource {0:?} was resized before the copy")]
+    Resized(ResourceID),
+}
+
+#[derive(Debug, Error)]
+pub enum PixelReadError {
+    #[error("resource {0:?} is not part of the bound render graph")]
+    UnknownResource(ResourceID),
+
+    #[error("the context is suspended, there are no swapchain images to read")]
+    Suspended,
+
+    #[error("resource {0:?} was not created with TRANSFER_SRC usage")]
+    NotTransferSource(ResourceID),
+
+    #[error("pixel ({x}, {y}) is outside of the resource's {width}x{height} extent")]
+    OutOfBounds {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    },
+
+    #[error("reading back pixels of format {0:?} is not supported")]
+    UnsupportedFormat(vk::Format),
+}
+
+type ReadbackSlot = ThreadSafeRef<Option<Result<PixelValue, PixelReadbackError>>>;
+type ImageReadbackSlot = ThreadSafeRef<Option<Result<ImageData, PixelReadbackError>>>;
+
+/// Token returned by [`crate::gfx::context::Context::read_pixel`], resolved once the frame that
+/// copied the pixel has finished executing on the GPU (usually after as many frames as there are in flight).
+#[derive(Debug, Clone)]
+pub struct PixelReadback {
+    slot: ReadbackSlot,
+}
+
+impl PixelReadback {
+    pub fn is_ready(&self) -> bool {
+        self.slot.lock().is_some()
+    }
+
+    /// Returns `None` until the readback has been resolved, and the same result on every call
+    /// afterwards.
+    pub fn poll(&self) -> Option<Result<PixelValue, PixelReadbackError>> {
+        self.slot.lock().clone()
+    }
+}
+
+/// Every texel of an image, tightly packed row by row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageData {
+    pub width: u32,
+    pub height: u32,
+    pub format: vk::Format,
+    pub bytes: Vec<u8>,
+}
+
+#[cfg(feature = "png")]
+#[derive(Debug, Error)]
+pub enum ImageSaveError {
+    #[error("saving images of format {0:?} as PNG is not supported")]
+    UnsupportedFormat(vk::Format),
+
+    #[error("file writing failed")]
+    Io(#[from] std::io::Error),
+}
+
+impl ImageData {
+    /// Converts 8-bit color formats to RGBA, swizzling BGRA ones.
+    pub fn to_rgba8(&self) -> Option<Vec<u8>> {
+        match self.format {
+            vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SRGB => Some(self.bytes.clone()),
+            vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB => Some(
+                self.bytes
+                    .chunks_exact(4)
+                    .flat_map(|texel| [texel[2], texel[1], texel[0], texel[3]])
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+
+    #[cfg(feature = "png")]
+    pub fn save_png(&self, path: &std::path::Path) -> Result<(), ImageSaveError> {
+        let rgba = self
+            .to_rgba8()
+            .ok_or(ImageSaveError::UnsupportedFormat(self.format))?;
+
+        std::fs::write(
+            path,
+            super::png::encode_rgba8(self.width, self.height, &rgba),
+        )?;
+        Ok(())
+    }
+}
+
+/// Token returned by [`crate::gfx::context::Context::read_image`], resolved like
+/// [`PixelReadback`].
+#[derive(Debug, Clone)]
+pub struct ImageReadback {
+    slot: ImageReadbackSlot,
+}
+
+impl ImageReadback {
+    pub fn is_ready(&self) -> bool {
+        self.slot.lock().is_some()
+    }
+
+    /// Returns `None` until the readback has been resolved, and the same result on every call
+    /// afterwards.
+    pub fn poll(&self) -> Option<Result<ImageData, PixelReadbackError>> {
+        self.slot.lock().clone()
+    }
+}
+
+enum ReadbackTarget {
+    Pixel(ReadbackSlot),
+    Image(ImageReadbackSlot),
+}
+
+impl ReadbackTarget {
+    fn fail(&self, error: PixelReadbackError) {
+        match self {
+            Self::Pixel(slot) => *slot.lock() = Some(Err(error)),
+            Self::Image(slot) => *slot.lock() = Some(Err(error)),
+        }
+    }
+}
+
+struct PixelReadRequest {
+    resource: ResourceID,
+    offset: vk::Offset3D,
+    extent: vk::Extent2D,
+    format: vk::Format,
+    texel_size: u64,
+
+    target: ReadbackTarget,
+}
+
+impl PixelReadRequest {
+    fn size(&self) -> u64 {
+        self.texel_size * self.extent.width as u64 * self.extent.height as u64
+    }
+}
+
+struct PreparedReadback {
+    request: PixelReadRequest,
+    buffer: Buffer,
+}
+
+#[derive(Default)]
+pub(crate) struct PixelReadbackQueue {
+    requested: Vec<PixelReadRequest>,
+    prepared: Vec<PreparedReadback>,
+    // tagged with the frame recording their copy
+    in_flight: Vec<(u64, PreparedReadback)>,
+    frame_index: u64,
+}
+
+impl PixelReadbackQueue {
+    pub fn request(
+        &mut self,
+        resource: ResourceID,
+        (x, y): (u32, u32),
+        extent: vk::Extent2D,
+        format: vk::Format,
+    ) -> Result<PixelReadback, PixelReadError> {
+        if x >= extent.width || y >= extent.height {
+            return Err(PixelReadError::OutOfBounds {
+                x,
+                y,
+                width: extent.width,
+                height: extent.height,
+            });
+        }
+
+        let texel_size = texel_size(format).ok_or(PixelReadError::UnsupportedFormat(format))?;
+
+        let slot = ThreadSafeRef::new(None);
+        self.requested.push(PixelReadRequest {
+            resource,
+            offset: vk::Offset3D {
+                x: x as i32,
+                y: y as i32,
+                z: 0,
+            },
+            extent: vk::Extent2D {
+                width: 1,
+                height: 1,
+            },
+            format,
+            texel_size,
+            target: ReadbackTarget::Pixel(slot.clone()),
+        });
+
+        Ok(PixelReadback { slot })
+    }
+
+    pub fn request_image(
+        &mut self,
+        resource: ResourceID,
+        extent: vk::Extent2D,
+        format: vk::Format,
+    ) -> Result<ImageReadback, PixelReadError> {
+        let texel_size = texel_size(format).ok_or(PixelReadError::UnsupportedFormat(format))?;
+
+        let slot = ThreadSafeRef::new(None);
+        self.requested.push(PixelReadRequest {
+            resource,
+            offset: vk::Offset3D::default(),
+            extent,
+            format,
+            texel_size,
+            target: ReadbackTarget::Image(slot.clone()),
+        });
+
+        Ok(ImageReadback { slot })
+    }
+
+    pub fn has_pending_copies(&self) -> bool {
+        !self.prepared.is_empty()
+    }
+
+    /// Resolves the readbacks of completed frames, called once the frame slot of `frame_index` was
+    /// waited on.
+    pub fn resolve_completed(&mut self, frame_index: u64) {
+        self.frame_index = frame_index;
+        let (completed, in_flight) = std::mem::take(&mut self.in_flight)
+            .into_iter()
+            .partition(|&(frame, _)| is_frame_complete(frame, frame_index));
+        self.in_flight = in_flight;
+
+        for (_, readback) in completed {
+            let request = readback.request;
+            let Some(data) = readback.buffer.mapped::<u8>() else {
+                request.target.fail(PixelReadbackError::MemoryMapping);
+                continue;
+            };
+
+            let bytes = data[..request.size() as usize].to_vec();
+            match request.target {
+                ReadbackTarget::Pixel(slot) => {
+                    *slot.lock() = Some(Ok(pixel_value(request.format, bytes)));
+                }
+                ReadbackTarget::Image(slot) => {
+                    *slot.lock() = Some(Ok(ImageData {
+                        width: request.extent.width,
+                        height: request.extent.height,
+                        format: request.format,
+                        bytes,
+                    }));
+                }
+            }
+        }
+    }
+
+    /// Creates the destination buffers of every request made since the last frame.
+    pub fn prepare(
+        &mut self,
+        device_ref: &ThreadSafeRwRef<Device>,
+        allocator_ref: &ThreadSafeRef<Allocator>,
+    ) {
+        for request in self.requested.drain(..) {
+            let buffer = BufferBuilder::default(request.size())
+                .with_name("pixel readback")
+                .with_tag(AllocTag::Staging)
+                .with_usage(vk::BufferUsageFlags::TRANSFER_DST)
+                .with_memory_location(gpu_allocator::MemoryLocation::GpuToCpu)
+                .build_internal(device_ref.clone(), allocator_ref.clone());
+
+            match buffer {
+                Ok(buffer) => self.prepared.push(PreparedReadback { request, buffer }),
+                Err(err) => {
+                    request
+                        .target
+                        .fail(PixelReadbackError::BufferCreation(err.to_string()));
+                }
+            }
+        }
+    }
+
+    /// Records the copies of prepared readbacks whose resource matches `filter`.
+    pub fn record_copies<Filter>(
+        &mut self,
+        filter: Filter,
+        resources: &mut FrameResources,
+        cmd_buffer: vk::CommandBuffer,
+        device_ref: &ThreadSafeRwRef<Device>,
+    ) where
+        Filter: Fn(&ResourceID) -> bool,
+    {
+        let (to_record, kept) = std::mem::take(&mut self.prepared)
+            .into_iter()
+            .partition::<Vec<_>, _>(|readback| filter(&readback.request.resource));
+        self.prepared = kept;
+
+        for readback in to_record {
+            let Some(image) = resources.get_mut(&readback.request.resource) else {
+                readback
+                    .request
+                    .target
+                    .fail(PixelReadbackError::ResourceLost(readback.request.resource));
+                continue;
+            };
+            let request_end = (
+                readback.request.offset.x as u32 + readback.request.extent.width,
+                readback.request.offset.y as u32 + readback.request.extent.height,
+            );
+            if request_end.0 > image.extent_2d.width || request_end.1 > image.extent_2d.height {
+                readback
+                    .request
+                    .target
+                    .fail(PixelReadbackError::Resized(readback.request.resource));
+                continue;
+            }
+
+            let previous_layout = image.layout;
+            image.cmd_layout_transition(
+                device_ref.clone(),
+                cmd_buffer,
+                vk::ImageMemoryBarrier2::default()
+                    .src_stage_mask(vk::PipelineStageFlags2::ALL_GRAPHICS)
+                    .src_access_mask(
+                        vk::AccessFlags2::COLOR_ATTACHMENT_WRITE
+                            | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                    )
+                    .dst_stage_mask(vk::PipelineStageFlags2::COPY)
+                    .dst_access_mask(vk::AccessFlags2::TRANSFER_READ)
+                    .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+                    .subresource_range(image.view_subresource_range),
+            );
+
+            let copy_region = vk::BufferImageCopy::default()
+                .image_subresource(
+                    vk::ImageSubresourceLayers::default()
+                        .aspect_mask(image.view_subresource_range.aspect_mask)
+                        .mip_level(0)
+                        .base_array_layer(0)
+                        .layer_count(1),
+                )
+                .image_offset(readback.request.offset)
+                .image_extent(readback.request.extent.into());
+            let buffer_barrier = vk::BufferMemoryBarrier2::default()
+                .buffer(readback.buffer.handle)
+                .src_stage_mask(vk::PipelineStageFlags2::COPY)
+                .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                .dst_stage_mask(vk::PipelineStageFlags2::HOST)
+                .dst_access_mask(vk::AccessFlags2::HOST_READ)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .size(vk::WHOLE_SIZE);
+            {
+                let device = device_ref.read();
+                unsafe {
+                    device.cmd_copy_image_to_buffer(
+                        cmd_buffer,
+                        image.handle,
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        readback.buffer.handle,
+                        std::slice::from_ref(&copy_region),
+                    );
+                    device.cmd_pipeline_barrier2(
+                        cmd_buffer,
+                        &vk::DependencyInfo::default()
+                            .buffer_memory_barriers(std::slice::from_ref(&buffer_barrier)),
+                    );
+                }
+            }
+
+            // later passes expect the attachment to be left as they last saw it
+            if previous_layout != vk::ImageLayout::UNDEFINED {
+                image.cmd_layout_transition(
+                    device_ref.clone(),
+                    cmd_buffer,
+                    vk::ImageMemoryBarrier2::default()
+                        .src_stage_mask(vk::PipelineStageFlags2::COPY)
+                        .src_access_mask(vk::AccessFlags2::empty())
+                        .dst_stage_mask(vk::PipelineStageFlags2::ALL_GRAPHICS)
+                        .dst_access_mask(
+                            vk::AccessFlags2::COLOR_ATTACHMENT_READ
+                                | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE
+                                | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ
+                                | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                        )
+                        .new_layout(previous_layout)
+                        .subresource_range(image.view_subresource_range),
+                );
+            }
+
+            self.in_flight.push((self.frame_index, readback));
+        }
+    }
+}
+
+fn pixel_value(format: vk::Format, bytes: Vec<u8>) -> PixelValue {
+    match format {
+        vk::Format::R32_UINT => PixelValue::U32(u32::from_le_bytes(
+            bytes[..4]
+                .try_into()
+                .expect("R32_UINT texels are 4 bytes long"),
+        )),
+        _ => PixelValue::Raw(bytes),
+    }
+}
+
+fn texel_size(format: vk::Format) -> Option<u64> {
+    match format {
+        vk::Format::R8_UNORM | vk::Format::R8_UINT | vk::Format::R8_SRGB => Some(1),
+        vk::Format::R8G8_UNORM
+        | vk::Format::R16_UNORM
+        | vk::Format::R16_UINT
+        | vk::Format::R16_SFLOAT
+        | vk::Format::D16_UNORM => Some(2),
+        vk::Format::R8G8B8A8_UNORM
+        | vk::Format::R8G8B8A8_SRGB
+        | vk::Format::R8G8B8A8_UINT
+        | vk::Format::B8G8R8A8_UNORM
+        | vk::Format::B8G8R8A8_SRGB
+        | vk::Format::A2B10G10R10_UNORM_PACK32
+        | vk::Format::A2R10G10B10_UNORM_PACK32
+        | vk::Format::B10G11R11_UFLOAT_PACK32
+        | vk::Format::R16G16_SFLOAT
+        | vk::Format::R32_UINT
+        | vk::Format::R32_SINT
+        | vk::Format::R32_SFLOAT
+        | vk::Format::D32_SFLOAT => Some(4),
+        vk::Format::R16G16B16A16_SFLOAT
+        | vk::Format::R16G16B16A16_UNORM
+        | vk::Format::R32G32_UINT
+        | vk::Format::R32G32_SFLOAT => Some(8),
+        vk::Format::R32G32B32A32_UINT | vk::Format::R32G32B32A32_SFLOAT => Some(16),
+        _ => None,
+    }
+}
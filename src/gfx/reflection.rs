@@ -0,0 +1,585 @@
+use std::collections::HashMap;
+
+use thiserror::Error;
+
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+const HEADER_WORDS: usize = 5;
+
+// opcodes
+const OP_NAME: u32 = 5;
+const OP_MEMBER_NAME: u32 = 6;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+
+// decorations
+const DECORATION_BLOCK: u32 = 2;
+const DECORATION_ROW_MAJOR: u32 = 4;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+const STORAGE_CLASS_UNIFORM: u32 = 2;
+
+/// Member of a uniform block, as laid out by the shader.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReflectedMember {
+    pub name: String,
+    pub offset: u32,
+    pub size: u32,
+}
+
+/// Uniform block declared by a shader, e.g. `layout(std140, set = 1, binding = 0) uniform ...`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UniformBlock {
+    /// Name of the block type, not of the instance.
+    pub name: String,
+    pub set: u32,
+    pub binding: u32,
+    pub members: Vec<ReflectedMember>,
+}
+
+impl UniformBlock {
+    /// Bytes up to the end of the last member, without trailing padding.
+    pub fn size(&self) -> u32 {
+        self.members
+            .iter()
+            .map(|member| member.offset + member.size)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Checks that `T` matches the layout of the block member by member, see [`LayoutMismatch`].
+    pub fn validate<T: GpuLayout>(&self) -> Result<(), LayoutMismatch> {
+        for (member, field) in self.members.iter().zip(T::MEMBERS) {
+            if member.offset as usize != field.offset {
+                return Err(LayoutMismatch::MemberOffset {
+                    block: self.name.clone(),
+                    member: member.name.clone(),
+                    field: field.name,
+                    expected: member.offset,
+                    actual: field.offset,
+                });
+            }
+        }
+
+        if self.members.len() != T::MEMBERS.len() {
+            return Err(LayoutMismatch::MemberCount {
+                block: self.name.clone(),
+                expected: self.members.len(),
+                actual: T::MEMBERS.len(),
+            });
+        }
+
+        // std140 rounds the size of structs up to 16 bytes, a struct padded the same way is fine
+        let expected = self.size();
+        let actual = std::mem::size_of::<T>();
+        if actual < expected as usize || actual > expected.next_multiple_of(16) as usize {
+            return Err(LayoutMismatch::Size {
+                block: self.name.clone(),
+                expected,
+                actual,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Differences between a Rust struct and the uniform block it is uploaded to. Expected values are
+/// those of the shader, actual ones those of the struct.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum LayoutMismatch {
+    #[error("no uniform block is declared at set {set}, binding {binding}")]
+    MissingBlock { set: u32, binding: u32 },
+
+    #[error(
+        "member \"{member}\" of uniform block \"{block}\" is at offset {expected}, but field \
+         \"{field}\" is at offset {actual}"
+    )]
+    MemberOffset {
+        block: String,
+        member: String,
+        field: &'static str,
+        expected: u32,
+        actual: usize,
+    },
+
+    #[error("uniform block \"{block}\" has {expected} members, but the struct has {actual} fields")]
+    MemberCount {
+        block: String,
+        expected: usize,
+        actual: usize,
+    },
+
+    #[error("uniform block \"{block}\" is {expected} bytes, but the struct is {actual} bytes")]
+    Size {
+        block: String,
+        expected: u32,
+        actual: usize,
+    },
+}
+
+/// Field of a [`GpuLayout`] struct.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct GpuMember {
+    pub name: &'static str,
+    pub offset: usize,
+}
+
+/// Fields of a struct uploaded to a uniform block, in declaration order. Usually implemented
+/// through [`gpu_layout!`](crate::gpu_layout).
+pub trait GpuLayout: bytemuck::Pod {
+    const MEMBERS: &'static [GpuMember];
+}
+
+/// Implements [`GpuLayout`] for a struct from the list of its fields, in the order of the members
+/// of the block.
+///
+/// ```ignore
+/// miel::gpu_layout!(MaterialParams { base_color, roughness, metallic });
+/// ```
+#[macro_export]
+macro_rules! gpu_layout {
+    ($ty:ty { $($field:ident),* $(,)? }) => {
+        impl $crate::gfx::reflection::GpuLayout for $ty {
+            const MEMBERS: &'static [$crate::gfx::reflection::GpuMember] = &[
+                $(
+                    $crate::gfx::reflection::GpuMember {
+                        name: stringify!($field),
+                        offset: ::std::mem::offset_of!($ty, $field),
+                    },
+                )*
+            ];
+        }
+    };
+}
+
+#[derive(Debug)]
+enum SpirvType {
+    Scalar { width: u32 },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, count: u32 },
+    // stride is decorated on the array type, only the length matters
+    Array { length: u32 },
+    RuntimeArray,
+    Struct { members: Vec<u32> },
+}
+
+#[derive(Debug, Default)]
+struct Module {
+    names: HashMap<u32, String>,
+    member_names: HashMap<(u32, u32), String>,
+    types: HashMap<u32, SpirvType>,
+    // pointer type to pointee, for uniform pointers only
+    uniform_pointers: HashMap<u32, u32>,
+    constants: HashMap<u32, u32>,
+    // uniform variables and their pointer type
+    uniform_variables: Vec<(u32, u32)>,
+
+    decorations: HashMap<(u32, u32), u32>,
+    member_decorations: HashMap<(u32, u32, u32), u32>,
+}
+
+impl Module {
+    fn parse(spirv: &[u32]) -> Option<Self> {
+        if spirv.len() < HEADER_WORDS || spirv[0] != SPIRV_MAGIC {
+            return None;
+        }
+
+        let mut module = Self::default();
+        let mut words = &spirv[HEADER_WORDS..];
+        while let Some(&first_word) = words.first() {
+            let word_count = (first_word >> 16) as usize;
+            if word_count == 0 || word_count > words.len() {
+                return None;
+            }
+            module.parse_instruction(first_word & 0xffff, &words[1..word_count]);
+            words = &words[word_count..];
+        }
+
+        Some(module)
+    }
+
+    fn parse_instruction(&mut self, opcode: u32, operands: &[u32]) {
+        // decorations without a literal are stored with a value of 0
+        let literal = |index: usize| operands.get(index).copied().unwrap_or(0);
+        match (opcode, operands) {
+            (OP_NAME, [target, name @ ..]) => {
+                self.names.insert(*target, parse_string(name));
+            }
+            (OP_MEMBER_NAME, [ty, member, name @ ..]) => {
+                self.member_names.insert((*ty, *member), parse_string(name));
+            }
+            (OP_TYPE_INT | OP_TYPE_FLOAT, [result, width, ..]) => {
+                self.types
+                    .insert(*result, SpirvType::Scalar { width: *width });
+            }
+            (OP_TYPE_VECTOR, [result, component, count]) => {
+                self.types.insert(
+                    *result,
+                    SpirvType::Vector {
+                        component: *component,
+                        count: *count,
+                    },
+                );
+            }
+            (OP_TYPE_MATRIX, [result, column, count]) => {
+                self.types.insert(
+                    *result,
+                    SpirvType::Matrix {
+                        column: *column,
+                        count: *count,
+                    },
+                );
+            }
+            (OP_TYPE_ARRAY, [result, _, length]) => {
+                self.types
+                    .insert(*result, SpirvType::Array { length: *length });
+            }
+            (OP_TYPE_RUNTIME_ARRAY, [result, ..]) => {
+                self.types.insert(*result, SpirvType::RuntimeArray);
+            }
+            (OP_TYPE_STRUCT, [result, members @ ..]) => {
+                self.types.insert(
+                    *result,
+                    SpirvType::Struct {
+                        members: members.to_vec(),
+                    },
+                );
+            }
+            (OP_TYPE_POINTER, [result, STORAGE_CLASS_UNIFORM, pointee]) => {
+                self.uniform_pointers.insert(*result, *pointee);
+            }
+            (OP_CONSTANT, [_, result, value, ..]) => {
+                self.constants.insert(*result, *value);
+            }
+            (OP_VARIABLE, [pointer, result, STORAGE_CLASS_UNIFORM, ..]) => {
+                self.uniform_variables.push((*result, *pointer));
+            }
+            (OP_DECORATE, [target, decoration, ..]) => {
+                self.decorations.insert((*target, *decoration), literal(2));
+            }
+            (OP_MEMBER_DECORATE, [ty, member, decoration, ..]) => {
+                self.member_decorations
+                    .insert((*ty, *member, *decoration), literal(3));
+            }
+            _ => (),
+        }
+    }
+
+    // `decorated` is the struct and member index the type is used by, for matrix layouts
+    fn type_size(&self, ty: u32, decorated: Option<(u32, u32)>) -> Option<u32> {
+        let member_decoration = |decoration: u32| {
+            let (parent, member) = decorated?;
+            self.member_decorations
+                .get(&(parent, member, decoration))
+                .copied()
+        };
+
+        match self.types.get(&ty)? {
+            SpirvType::Scalar { width } => Some(width / 8),
+            SpirvType::Vector { component, count } => {
+                Some(self.type_size(*component, None)? * count)
+            }
+            SpirvType::Matrix { column, count } => {
+                let stride = member_decoration(DECORATION_MATRIX_STRIDE)?;
+                // row-major matrices are stored as rows, as many as there are components per column
+                let vector_count = match member_decoration(DECORATION_ROW_MAJOR) {
+                    Some(_) => match self.types.get(column)? {
+                        SpirvType::Vector { count, .. } => *count,
+                        _ => return None,
+                    },
+                    None => *count,
+                };
+                Some(stride * vector_count)
+            }
+            SpirvType::Array { length } => {
+                let stride = self
+                    .decorations
+                    .get(&(ty, DECORATION_ARRAY_STRIDE))
+                    .copied()?;
+                Some(stride * self.constants.get(length)?)
+            }
+            SpirvType::RuntimeArray => Some(0),
+            SpirvType::Struct { .. } => self
+                .struct_members(ty)?
+                .iter()
+                .map(|member| member.offset + member.size)
+                .max()
+                .or(Some(0)),
+        }
+    }
+
+    fn struct_members(&self, ty: u32) -> Option<Vec<ReflectedMember>> {
+        let SpirvType::Struct { members } = self.types.get(&ty)? else {
+            return None;
+        };
+
+        members
+            .iter()
+            .enumerate()
+            .map(|(index, &member_ty)| {
+                let index = index as u32;
+                Some(ReflectedMember {
+                    name: self
+                        .member_names
+                        .get(&(ty, index))
+                        .cloned()
+                        .unwrap_or_else(|| format!("member {index}")),
+                    offset: *self
+                        .member_decorations
+                        .get(&(ty, index, DECORATION_OFFSET))?,
+                    size: self.type_size(member_ty, Some((ty, index)))?,
+                })
+            })
+            .collect()
+    }
+
+    fn uniform_blocks(&self) -> Vec<UniformBlock> {
+        self.uniform_variables
+            .iter()
+            .filter_map(|&(variable, pointer)| {
+                let block_ty = *self.uniform_pointers.get(&pointer)?;
+                // storage buffers declared the old way are uniform `BufferBlock`s instead
+                if !self.decorations.contains_key(&(block_ty, DECORATION_BLOCK)) {
+                    return None;
+                }
+
+                Some(UniformBlock {
+                    name: self.names.get(&block_ty).cloned().unwrap_or_default(),
+                    set: *self
+                        .decorations
+                        .get(&(variable, DECORATION_DESCRIPTOR_SET))?,
+                    binding: *self.decorations.get(&(variable, DECORATION_BINDING))?,
+                    members: self.struct_members(block_ty)?,
+                })
+            })
+            .collect()
+    }
+}
+
+// nul-terminated UTF-8, packed little-endian in words
+fn parse_string(words: &[u32]) -> String {
+    let bytes = words
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .take_while(|&byte| byte != 0)
+        .collect::<Vec<_>>();
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Lists the uniform blocks of a SPIR-V module along with their layout, nothing for invalid
+/// modules. Blocks whose layout cannot be determined are left out.
+pub fn reflect_uniform_blocks(spirv: &[u32]) -> Vec<UniformBlock> {
+    Module::parse(spirv)
+        .map(|module| module.uniform_blocks())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gfx::{frame_constants::FrameConstants, pipeline::spirv_from_bytes};
+
+    use super::*;
+
+    const SCENE_VERT_SPV: &[u8] = include_bytes!("../../reime/assets/shaders/scene.vert.spv");
+    const BLOOM_SPVS: [&[u8]; 4] = [
+        include_bytes!("shaders/bloom_fullscreen.vert.spv"),
+        include_bytes!("shaders/bloom_downsample.frag.spv"),
+        include_bytes!("shaders/bloom_upsample.frag.spv"),
+        include_bytes!("shaders/bloom_composite.frag.spv"),
+    ];
+
+    #[repr(C)]
+    #[derive(Copy, Clone)]
+    struct Material {
+        base_color: [f32; 4],
+        roughness: f32,
+        metallic: f32,
+        _padding: [f32; 2],
+    }
+
+    unsafe impl bytemuck::Zeroable for Material {}
+    unsafe impl bytemuck::Pod for Material {}
+
+    crate::gpu_layout!(Material {
+        base_color,
+        roughness,
+        metallic
+    });
+
+    fn spirv(bytes: &[u8]) -> Vec<u32> {
+        spirv_from_bytes(bytes).expect("the module should be read")
+    }
+
+    fn member(name: &str, offset: u32, size: u32) -> ReflectedMember {
+        ReflectedMember {
+            name: name.to_owned(),
+            offset,
+            size,
+        }
+    }
+
+    fn material_block(members: Vec<ReflectedMember>) -> UniformBlock {
+        UniformBlock {
+            name: "Material".to_owned(),
+            set: 1,
+            binding: 0,
+            members,
+        }
+    }
+
+    #[test]
+    fn scene_vertex_shader_declares_the_frame_constants() {
+        let blocks = reflect_uniform_blocks(&spirv(SCENE_VERT_SPV));
+
+        assert_eq!(blocks.len(), 1);
+        let frame_constants = &blocks[0];
+        assert_eq!(frame_constants.name, "FrameConstants");
+        assert_eq!((frame_constants.set, frame_constants.binding), (0, 0));
+        assert_eq!(
+            frame_constants.members,
+            [
+                member("view", 0, 64),
+                member("projection", 64, 64),
+                member("view_projection", 128, 64),
+                member("camera_position", 192, 16),
+                member("extent", 208, 8),
+                member("time", 216, 4),
+                member("frame_index", 220, 4),
+            ]
+        );
+        assert_eq!(frame_constants.size(), 224);
+        assert_eq!(frame_constants.validate::<FrameConstants>(), Ok(()));
+    }
+
+    #[test]
+    fn samplers_and_push_constants_are_not_uniform_blocks() {
+        for bytes in BLOOM_SPVS {
+            let spirv = spirv(bytes);
+
+            assert!(Module::parse(&spirv).is_some());
+            assert!(reflect_uniform_blocks(&spirv).is_empty());
+        }
+    }
+
+    #[test]
+    fn matching_structs_are_valid() {
+        let block = material_block(vec![
+            member("base_color", 0, 16),
+            member("roughness", 16, 4),
+            member("metallic", 20, 4),
+        ]);
+
+        // padded up to the 16 bytes std140 rounds struct sizes to
+        assert_eq!(block.size(), 24);
+        assert_eq!(block.validate::<Material>(), Ok(()));
+    }
+
+    #[test]
+    fn member_offset_mismatches_are_reported() {
+        let block = material_block(vec![
+            member("base_color", 0, 16),
+            member("metallic", 16, 4),
+            member("roughness", 32, 4),
+        ]);
+
+        assert_eq!(
+            block.validate::<Material>(),
+            Err(LayoutMismatch::MemberOffset {
+                block: "Material".to_owned(),
+                member: "roughness".to_owned(),
+                field: "metallic",
+                expected: 32,
+                actual: 20,
+            })
+        );
+    }
+
+    #[test]
+    fn member_count_mismatches_are_reported() {
+        let block = material_block(vec![
+            member("base_color", 0, 16),
+            member("roughness", 16, 4),
+            member("metallic", 20, 4),
+            member("emissive", 24, 4),
+        ]);
+
+        assert_eq!(
+            block.validate::<Material>(),
+            Err(LayoutMismatch::MemberCount {
+                block: "Material".to_owned(),
+                expected: 4,
+                actual: 3,
+            })
+        );
+    }
+
+    #[test]
+    fn size_mismatches_are_reported() {
+        // the 32 bytes struct is only a byte short
+        let block = material_block(vec![
+            member("base_color", 0, 16),
+            member("roughness", 16, 4),
+            member("metallic", 20, 13),
+        ]);
+        assert_eq!(
+            block.validate::<Material>(),
+            Err(LayoutMismatch::Size {
+                block: "Material".to_owned(),
+                expected: 33,
+                actual: 32,
+            })
+        );
+
+        let block = material_block(vec![
+            member("base_color", 0, 16),
+            member("roughness", 16, 4),
+            member("metallic", 20, 20),
+        ]);
+        assert_eq!(
+            block.validate::<Material>(),
+            Err(LayoutMismatch::Size {
+                block: "Material".to_owned(),
+                expected: 40,
+                actual: 32,
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_modules_are_rejected() {
+        let scene_vert = spirv(SCENE_VERT_SPV);
+
+        let mut bad_magic = scene_vert.clone();
+        bad_magic[0] = SPIRV_MAGIC.swap_bytes();
+        assert!(Module::parse(&bad_magic).is_none());
+        assert!(reflect_uniform_blocks(&bad_magic).is_empty());
+
+        assert!(Module::parse(&scene_vert[..HEADER_WORDS - 1]).is_none());
+        assert!(Module::parse(&[]).is_none());
+
+        // cut right after the first word of the instruction following the middle of the module
+        let mut instruction_start = HEADER_WORDS;
+        while instruction_start < scene_vert.len() / 2 {
+            instruction_start += (scene_vert[instruction_start] >> 16) as usize;
+        }
+        assert!(scene_vert[instruction_start] >> 16 > 1);
+        let truncated = &scene_vert[..instruction_start + 1];
+        assert!(Module::parse(truncated).is_none());
+        assert!(reflect_uniform_blocks(truncated).is_empty());
+    }
+}
@@ -1,21 +1,61 @@
+pub mod passes;
 pub mod render_pass;
 pub mod resource;
+pub mod snapshot;
+pub mod typed_pass;
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
 
 use ash::vk;
-use render_pass::RenderPass;
-use resource::{GraphResourceRegistry, RegistryCreateError, ResourceInfoRegistry};
+use render_pass::{AttachmentValidationError, RenderPass, SimpleRenderPass, color_output_warnings};
+use resource::{
+    AttachmentSize, GraphResourceRegistry, RegistryCreateError, ResourceID, ResourceInfoRegistry,
+};
+use snapshot::{AttachmentSnapshot, PassSnapshot, RenderGraphSnapshot};
 use thiserror::Error;
 
 use crate::{
-    gfx::render_graph::resource::{FrameResources, ResourceAccessType},
-    utils::ThreadSafeRwRef,
+    gfx::render_graph::resource::{FrameInfo, FrameResources, ResourceAccessType},
+    utils::{ThreadSafeRef, ThreadSafeRwRef},
+};
+
+use super::{
+    allocator::Allocator,
+    barrier::{BarrierBatch, layout_src_scope},
+    breadcrumbs::Breadcrumbs,
+    color::Color,
+    commands::{BatchSubmitError, FrameSubmission},
+    context::Context,
+    debug,
+    deferred::DeferredResourceQueue,
+    deletion_queue::DeletionQueue,
+    device::Device,
+    feedback::{FeedbackBuffer, FeedbackCopy},
+    image::ImageBuildError,
+    readback::PixelReadbackQueue,
+    swapchain::{self, SurfaceProperties},
 };
 
-use super::{context::Context, device::Device, swapchain};
+/// Where the frame's recording is split into separate queue submissions, letting the GPU start on
+/// early passes (e.g. shadows) while the CPU still records later ones. Pass IDs are the indices of
+/// the passes in push order.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SubmissionHint {
+    SplitAfter(usize),
+    EveryNPasses(usize),
+}
 
 pub struct RenderGraphInfo {
     render_passes: Vec<Box<dyn RenderPass>>,
     resource_infos: ResourceInfoRegistry,
+    submission_hints: Vec<SubmissionHint>,
+    feedback_copies: Vec<FeedbackCopy>,
+
+    clear_color: Color,
+    clear_depth: Option<f32>,
 }
 
 impl RenderGraphInfo {
@@ -23,67 +63,490 @@ impl RenderGraphInfo {
         Self {
             render_passes: Default::default(),
             resource_infos: resources,
+            submission_hints: vec![],
+            feedback_copies: vec![],
+
+            clear_color: Color::BLACK,
+            clear_depth: None,
         }
     }
 
-    pub fn push_render_pass(mut self, render_pass: Box<dyn RenderPass>) -> Self {
-        self.render_passes.push(render_pass);
+    /// Color the swapchain color attachment is cleared to.
+    pub fn clear_color(mut self, color: Color) -> Self {
+        self.clear_color = color;
+        self
+    }
+
+    /// Value cleared depth attachments are set to, defaults to the far plane (1.0, or 0.0 with
+    /// reverse-Z).
+    pub fn clear_depth(mut self, depth: f32) -> Self {
+        self.clear_depth = Some(depth);
+        self
+    }
+
+    /// Without any hint, the whole frame is recorded and submitted as a single batch.
+    pub fn submission_hint(mut self, hint: SubmissionHint) -> Self {
+        self.submission_hints.push(hint);
+        self
+    }
+
+    pub fn push_render_pass(mut self, render_pass: impl RenderPass + 'static) -> Self {
+        self.add_render_pass(render_pass);
         self
     }
+
+    /// Same as [`Self::push_render_pass`], for effects adding several passes to a graph being
+    /// described, e.g. [`BloomEffect`](passes::bloom::BloomEffect).
+    pub fn add_render_pass(&mut self, render_pass: impl RenderPass + 'static) {
+        self.render_passes.push(Box::new(render_pass));
+    }
+
+    /// Registry the graph was described with, to declare the attachments of such effects.
+    pub fn resources_mut(&mut self) -> &mut ResourceInfoRegistry {
+        &mut self.resource_infos
+    }
+
+    /// Copies the counter of `feedback` back to the CPU at the end of every frame, after every
+    /// pass.
+    pub fn with_feedback<T: bytemuck::Pod>(mut self, feedback: &FeedbackBuffer<T>) -> Self {
+        self.feedback_copies.push(feedback.copy());
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatChange {
+    pub name: String,
+    pub old: vk::Format,
+    pub new: vk::Format,
+}
+
+/// Attachments that differ between two bound render graphs, matched by name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RenderGraphDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub format_changed: Vec<FormatChange>,
+}
+
+impl RenderGraphDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.format_changed.is_empty()
+    }
+
+    fn between(old: &HashMap<String, vk::Format>, new: &HashMap<String, vk::Format>) -> Self {
+        let mut diff = Self::default();
+        for (name, &new_format) in new {
+            match old.get(name) {
+                None => diff.added.push(name.clone()),
+                Some(&old_format) if old_format != new_format => {
+                    diff.format_changed.push(FormatChange {
+                        name: name.clone(),
+                        old: old_format,
+                        new: new_format,
+                    })
+                }
+                Some(_) => (),
+            }
+        }
+        diff.removed = old
+            .keys()
+            .filter(|name| !new.contains_key(*name))
+            .cloned()
+            .collect();
+
+        diff.added.sort();
+        diff.removed.sort();
+        diff.format_changed.sort_by(|a, b| a.name.cmp(&b.name));
+
+        diff
+    }
+
+    pub(crate) fn log(&self) {
+        for name in &self.added {
+            log::debug!("render graph rebind: attachment \"{name}\" added");
+        }
+        for name in &self.removed {
+            log::debug!("render graph rebind: attachment \"{name}\" removed");
+        }
+        for change in &self.format_changed {
+            log::debug!(
+                "render graph rebind: attachment \"{}\" format changed from {:?} to {:?}",
+                change.name,
+                change.old,
+                change.new
+            );
+        }
+    }
 }
 
 pub(crate) struct RenderGraph {
     render_passes: Vec<Box<dyn RenderPass>>,
     resources: GraphResourceRegistry,
+    // indexed by pass, whether its batch is submitted right after it
+    split_after: Vec<bool>,
+    // indexed by pass, recording time during the last frame
+    last_cpu_times: Vec<Duration>,
+    // multiplies the size of swapchain-relative attachments
+    render_scale: f32,
+    feedback_copies: Vec<FeedbackCopy>,
+
+    clear_color: Color,
+    clear_depth: f32,
 }
 
 #[derive(Debug, Error)]
 pub enum RenderGraphCreateError {
     #[error("resource registry creation failed")]
     ResourceCreation(#[from] RegistryCreateError),
+
+    #[error("render pass \"{pass}\" has invalid attachments")]
+    InvalidAttachments {
+        pass: String,
+        source: AttachmentValidationError,
+    },
+
+    #[error("render pass \"{pass}\" uses the swapchain depth attachment, which is disabled")]
+    SwapchainDepthDisabled { pass: String },
+
+    #[error("render pass \"{pass}\" runs on the async compute queue but declares attachments")]
+    AsyncComputeAttachments { pass: String },
 }
 
 #[derive(Debug, Error)]
 pub enum RenderGraphRunError {
     #[error("a resource requested by a render pass is invalid")]
     InvalidResource,
+
+    #[error("early batch submission failed")]
+    BatchSubmission(#[from] BatchSubmitError),
 }
 
 impl RenderGraph {
+    /// Only clears the swapchain, so that defined content is presented until a graph is bound.
     pub(crate) fn empty() -> Self {
+        log::info!("no render graph bound, frames only clear the swapchain");
+
         Self {
-            render_passes: vec![],
+            render_passes: vec![implicit_clear_pass()],
             resources: GraphResourceRegistry::default(),
+            split_after: vec![false],
+            last_cpu_times: vec![Duration::ZERO],
+            render_scale: 1.0,
+            feedback_copies: vec![],
+
+            clear_color: Color::BLACK,
+            clear_depth: 1.0,
         }
     }
 
     pub(crate) fn new(
-        info: RenderGraphInfo,
+        mut info: RenderGraphInfo,
         ctx: &mut Context,
     ) -> Result<Self, RenderGraphCreateError> {
+        let disabled_passes = &ctx.tunables.disabled_passes;
+        info.render_passes.retain(|render_pass| {
+            let disabled = disabled_passes
+                .iter()
+                .any(|name| name == render_pass.name());
+            if disabled {
+                log::info!(
+                    "render pass \"{}\" is disabled, skipping it",
+                    render_pass.name()
+                );
+            }
+            !disabled
+        });
+
+        if info.render_passes.is_empty() {
+            log::info!("bound render graph has no passes, frames only clear the swapchain");
+            info.render_passes.push(implicit_clear_pass());
+        }
+
+        for render_pass in &info.render_passes {
+            let attachment_infos = render_pass.attachment_infos();
+            if render_pass.runs_on_async_compute() {
+                if !attachment_infos.is_empty() {
+                    return Err(RenderGraphCreateError::AsyncComputeAttachments {
+                        pass: render_pass.name().to_owned(),
+                    });
+                }
+                continue;
+            }
+            if attachment_infos.is_empty() {
+                log::warn!(
+                    "render pass \"{}\" declares no attachments",
+                    render_pass.name()
+                );
+            }
+
+            attachment_infos.validate().map_err(|source| {
+                RenderGraphCreateError::InvalidAttachments {
+                    pass: render_pass.name().to_owned(),
+                    source,
+                }
+            })?;
+
+            for warning in color_output_warnings(render_pass.as_ref()) {
+                log::warn!("{warning}");
+            }
+
+            if ctx.swapchain_depth_format().is_none()
+                && attachment_infos.references(&ResourceID::SwapchainDSAttachment)
+            {
+                return Err(RenderGraphCreateError::SwapchainDepthDisabled {
+                    pass: render_pass.name().to_owned(),
+                });
+            }
+        }
+
+        let pass_count = info.render_passes.len();
+        let mut split_after = vec![false; pass_count];
+        for hint in &info.submission_hints {
+            match *hint {
+                SubmissionHint::SplitAfter(pass_index) if pass_index < pass_count => {
+                    split_after[pass_index] = true;
+                }
+                SubmissionHint::SplitAfter(pass_index) => {
+                    log::warn!(
+                        "ignoring submission split after pass {pass_index}, the graph only has {pass_count} passes"
+                    );
+                }
+                SubmissionHint::EveryNPasses(0) => {
+                    log::warn!("ignoring submission split every 0 passes");
+                }
+                SubmissionHint::EveryNPasses(n) => {
+                    for pass_index in (n - 1..pass_count).step_by(n) {
+                        split_after[pass_index] = true;
+                    }
+                }
+            }
+        }
+        // the last batch is always submitted by the command manager
+        if let Some(last) = split_after.last_mut() {
+            *last = false;
+        }
+
+        let clear_depth = info
+            .clear_depth
+            .unwrap_or(if ctx.reverse_z { 0.0 } else { 1.0 });
+
         let resources = info.resource_infos.create_resources(ctx)?;
 
         Ok(Self {
             render_passes: info.render_passes,
             resources,
+            split_after,
+            last_cpu_times: vec![Duration::ZERO; pass_count],
+            render_scale: ctx.render_scale(),
+            feedback_copies: info.feedback_copies,
+
+            clear_color: info.clear_color,
+            clear_depth,
         })
     }
 
+    pub(crate) fn resources(&self) -> &GraphResourceRegistry {
+        &self.resources
+    }
+
+    pub(crate) fn diff(&self, new: &RenderGraph) -> RenderGraphDiff {
+        RenderGraphDiff::between(&self.attachment_formats(), &new.attachment_formats())
+    }
+
+    fn attachment_formats(&self) -> HashMap<String, vk::Format> {
+        self.resources
+            .attachments
+            .values()
+            .map(|attachment| (attachment.info.name.clone(), attachment.info.format))
+            .collect()
+    }
+
+    /// Swapchain attachments are not owned by the graph, their formats must be provided.
+    pub(crate) fn snapshot(
+        &self,
+        swapchain_color_format: vk::Format,
+        swapchain_unorm_format: vk::Format,
+        swapchain_depth_format: vk::Format,
+    ) -> RenderGraphSnapshot {
+        let describe = |id: &ResourceID, access: ResourceAccessType| {
+            let (name, format) = match id {
+                ResourceID::SwapchainColorAttachment => {
+                    ("swapchain color".to_owned(), swapchain_color_format)
+                }
+                ResourceID::SwapchainColorAttachmentUnorm => {
+                    ("swapchain color unorm".to_owned(), swapchain_unorm_format)
+                }
+                ResourceID::SwapchainDSAttachment => {
+                    ("swapchain depth".to_owned(), swapchain_depth_format)
+                }
+                ResourceID::Other(uuid) => match self.resources.get(uuid) {
+                    Some(attachment) => (attachment.info.name.clone(), attachment.info.format),
+                    None => ("<unknown>".to_owned(), vk::Format::UNDEFINED),
+                },
+            };
+
+            AttachmentSnapshot {
+                name,
+                format: format!("{format:?}"),
+                access,
+            }
+        };
+
+        let passes = self
+            .render_passes
+            .iter()
+            .enumerate()
+            .map(|(id, render_pass)| {
+                let attachment_infos = render_pass.attachment_infos();
+                let mut color_attachments = attachment_infos
+                    .color_attachments
+                    .iter()
+                    .map(|(id, &access)| describe(id, access))
+                    .collect::<Vec<_>>();
+                color_attachments.sort_by(|a, b| a.name.cmp(&b.name));
+
+                PassSnapshot {
+                    id,
+                    name: render_pass.name().to_owned(),
+                    // passes cannot be disabled yet
+                    enabled: true,
+                    color_attachments,
+                    depth_stencil_attachment: attachment_infos.depth_stencil_attachment.map(
+                        |depth_stencil| describe(&depth_stencil.id, depth_stencil.access_type),
+                    ),
+                    cpu_ms: self
+                        .last_cpu_times
+                        .get(id)
+                        .map(|duration| duration.as_secs_f32() * 1000.0),
+                    gpu_ms: None,
+                }
+            })
+            .collect();
+
+        RenderGraphSnapshot { passes }
+    }
+
+    pub(crate) fn notify_surface_changed(
+        &mut self,
+        new_properties: &SurfaceProperties,
+        device_ref: &ThreadSafeRwRef<Device>,
+    ) {
+        for render_pass in &mut self.render_passes {
+            render_pass.on_surface_changed(new_properties, device_ref.clone());
+        }
+    }
+
+    /// Recreates the attachments whose size depends on the swapchain, if it changed.
+    pub(crate) fn resize_swapchain_dependent(
+        &mut self,
+        swapchain_extent: vk::Extent2D,
+        ctx_refs: (&ThreadSafeRwRef<Device>, &ThreadSafeRef<Allocator>),
+        deletion_queue: &ThreadSafeRef<DeletionQueue>,
+    ) -> Result<(), ImageBuildError> {
+        self.resources.resize_attachments(
+            AttachmentSize::depends_on_swapchain,
+            swapchain_extent,
+            self.render_scale,
+            ctx_refs,
+            deletion_queue,
+        )
+    }
+
+    /// Only recreates the swapchain-relative attachments.
+    pub(crate) fn set_render_scale(
+        &mut self,
+        render_scale: f32,
+        swapchain_extent: vk::Extent2D,
+        ctx_refs: (&ThreadSafeRwRef<Device>, &ThreadSafeRef<Allocator>),
+        deletion_queue: &ThreadSafeRef<DeletionQueue>,
+    ) -> Result<(), ImageBuildError> {
+        self.render_scale = render_scale;
+        self.resources.resize_attachments(
+            |size| matches!(size, AttachmentSize::SwapchainRelative(_)),
+            swapchain_extent,
+            render_scale,
+            ctx_refs,
+            deletion_queue,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn render(
         &mut self,
         swapchain_resources: swapchain::ImageResources<'_>,
-        &cmd_buffer: &vk::CommandBuffer,
+        frame_constants_set: vk::DescriptorSet,
+        submission: &mut FrameSubmission,
         device_ref: &ThreadSafeRwRef<Device>,
+        pixel_readbacks: &mut PixelReadbackQueue,
+        breadcrumbs: &mut Breadcrumbs,
+        deferred_resources: &ThreadSafeRef<DeferredResourceQueue>,
+        frame_info: FrameInfo,
     ) -> Result<(), RenderGraphRunError> {
-        let rendering_info = &vk::RenderingInfo::default()
-            .render_area(vk::Rect2D::default().extent(swapchain_resources.color_image.extent_2d))
-            .layer_count(1);
-        let mut resources = FrameResources::new(&mut self.resources, swapchain_resources);
-        for render_pass in &mut self.render_passes {
+        breadcrumbs.cmd_begin_frame(
+            self.render_passes
+                .iter()
+                .map(|render_pass| render_pass.name()),
+            submission.cmd_buffer(),
+            &device_ref.read(),
+        );
+
+        // readbacks are recorded right after the last pass writing to their resource
+        let mut readback_writers = HashMap::new();
+        if pixel_readbacks.has_pending_copies() {
+            for (pass_index, render_pass) in self.render_passes.iter().enumerate() {
+                for id in render_pass.attachment_infos().written_resources() {
+                    readback_writers.insert(id, pass_index);
+                    if let Some(alias) = id.swapchain_color_alias() {
+                        readback_writers.insert(alias, pass_index);
+                    }
+                }
+            }
+        }
+
+        let mut resources = FrameResources::new(
+            &mut self.resources,
+            swapchain_resources,
+            frame_constants_set,
+            deferred_resources.clone(),
+            self.render_scale,
+            frame_info,
+        );
+        let mut batch_uses_swapchain_image = false;
+        // attachment transitions of a pass are recorded with a single barrier
+        let mut barriers = BarrierBatch::new();
+        for (pass_index, render_pass) in self.render_passes.iter_mut().enumerate() {
+            let recording_start = Instant::now();
+            if render_pass.runs_on_async_compute() {
+                let _operation = debug::operation(|| {
+                    format!("async compute pass \"{}\" recording", render_pass.name())
+                });
+                submission.async_compute(|cmd_buffer| {
+                    render_pass.record_commands(&mut resources, &cmd_buffer, device_ref.clone())
+                })?;
+                resources.end_pass(render_pass.name());
+                breadcrumbs.cmd_pass_completed(
+                    pass_index,
+                    submission.cmd_buffer(),
+                    &device_ref.read(),
+                );
+
+                self.last_cpu_times[pass_index] = recording_start.elapsed();
+                if self.split_after[pass_index] {
+                    submission.split(batch_uses_swapchain_image)?;
+                    batch_uses_swapchain_image = false;
+                }
+                continue;
+            }
+            let barrier_operation = debug::operation(|| {
+                format!("render pass \"{}\" barrier emission", render_pass.name())
+            });
+            let cmd_buffer = submission.cmd_buffer();
             let attachment_info = render_pass.attachment_infos();
-            let pipeline_barrier = vk::ImageMemoryBarrier::default()
-                .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
-                .new_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+            batch_uses_swapchain_image |= attachment_info
+                .color_attachments
+                .keys()
+                .any(|id| id.swapchain_color_alias().is_some());
             for (&res_id, access_type) in &attachment_info.color_attachments {
                 let color_attachment = resources
                     .get_mut(&res_id)
@@ -91,88 +554,252 @@ impl RenderGraph {
 
                 if color_attachment.layout != vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL {
                     let dst_access_mask = match access_type {
-                        ResourceAccessType::ReadOnly => vk::AccessFlags::COLOR_ATTACHMENT_READ,
-                        ResourceAccessType::WriteOnly => vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                        ResourceAccessType::ReadOnly => vk::AccessFlags2::COLOR_ATTACHMENT_READ,
+                        ResourceAccessType::WriteOnly => vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
                         ResourceAccessType::ReadWrite => {
-                            vk::AccessFlags::COLOR_ATTACHMENT_READ
-                                | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                            vk::AccessFlags2::COLOR_ATTACHMENT_READ
+                                | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE
                         }
                     };
-                    let pipeline_barrier = pipeline_barrier
+                    let (src_stage_mask, src_access_mask) =
+                        layout_src_scope(color_attachment.layout);
+                    let pipeline_barrier = vk::ImageMemoryBarrier2::default()
+                        .src_stage_mask(src_stage_mask)
+                        .src_access_mask(src_access_mask)
+                        .dst_stage_mask(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)
                         .dst_access_mask(dst_access_mask)
+                        .new_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
                         .subresource_range(color_attachment.view_subresource_range);
-                    color_attachment.cmd_layout_transition(
-                        device_ref.clone(),
-                        cmd_buffer,
-                        vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-                        vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-                        pipeline_barrier,
-                    );
+                    color_attachment.queue_into(&mut barriers, pipeline_barrier);
                 }
             }
-            if let Some(res_id) = attachment_info.depth_stencil_attachment {
+            if let Some(depth_stencil) = &attachment_info.depth_stencil_attachment {
                 let depth_attachment = resources
-                    .get_mut(&res_id)
+                    .get_mut(&depth_stencil.id)
                     .ok_or(RenderGraphRunError::InvalidResource)?;
                 if depth_attachment.layout != vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL {
-                    let pipeline_barrier = vk::ImageMemoryBarrier::default()
-                        .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
-                        .dst_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ)
+                    let dst_access_mask = match depth_stencil.access_type {
+                        ResourceAccessType::ReadOnly => {
+                            vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ
+                        }
+                        ResourceAccessType::WriteOnly => {
+                            vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE
+                        }
+                        ResourceAccessType::ReadWrite => {
+                            vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ
+                                | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE
+                        }
+                    };
+                    let (src_stage_mask, src_access_mask) =
+                        layout_src_scope(depth_attachment.layout);
+                    let pipeline_barrier = vk::ImageMemoryBarrier2::default()
+                        .src_stage_mask(src_stage_mask)
+                        .src_access_mask(src_access_mask)
+                        .dst_stage_mask(
+                            vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS
+                                | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+                        )
+                        .dst_access_mask(dst_access_mask)
                         .subresource_range(depth_attachment.view_subresource_range)
                         .new_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
-                    depth_attachment.cmd_layout_transition(
-                        device_ref.clone(),
-                        cmd_buffer,
-                        vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
-                        vk::PipelineStageFlags::FRAGMENT_SHADER,
-                        pipeline_barrier,
-                    );
+                    depth_attachment.queue_into(&mut barriers, pipeline_barrier);
                 }
             }
+            for res_id in &attachment_info.sampled_images {
+                let sampled_image = resources
+                    .get_mut(res_id)
+                    .ok_or(RenderGraphRunError::InvalidResource)?;
+                if sampled_image.layout != vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL {
+                    // the usage that wrote the image last is waited on
+                    let (src_stage_mask, src_access_mask) = layout_src_scope(sampled_image.layout);
+                    let pipeline_barrier = vk::ImageMemoryBarrier2::default()
+                        .src_stage_mask(src_stage_mask)
+                        .src_access_mask(src_access_mask)
+                        .dst_stage_mask(
+                            vk::PipelineStageFlags2::VERTEX_SHADER
+                                | vk::PipelineStageFlags2::FRAGMENT_SHADER,
+                        )
+                        .dst_access_mask(vk::AccessFlags2::SHADER_SAMPLED_READ)
+                        .subresource_range(sampled_image.view_subresource_range)
+                        .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+                    sampled_image.queue_into(&mut barriers, pipeline_barrier);
+                }
+            }
+            barriers.cmd_flush(&device_ref.read(), cmd_buffer);
+            drop(barrier_operation);
+            let _operation =
+                debug::operation(|| format!("render pass \"{}\" recording", render_pass.name()));
+
+            let render_extent = pass_render_extent(attachment_info, &resources)?;
+            resources.set_render_extent(render_extent);
+            let rendering_info = vk::RenderingInfo::default()
+                .render_area(resources.scissor_full())
+                .layer_count(1);
 
             let mut color_attachments = vec![];
-            for &ca_id in attachment_info.color_attachments.keys() {
+            for (&ca_id, access_type) in &attachment_info.color_attachments {
                 let color_attachment_state = resources
-                    .get_mut(&ca_id)
+                    .get(&ca_id)
                     .ok_or(RenderGraphRunError::InvalidResource)?;
+                let view = resources
+                    .view(&ca_id)
+                    .ok_or(RenderGraphRunError::InvalidResource)?;
+
+                // passes reading from an attachment expect its previous content to be kept
+                let load_op = match access_type {
+                    ResourceAccessType::WriteOnly => vk::AttachmentLoadOp::CLEAR,
+                    ResourceAccessType::ReadOnly | ResourceAccessType::ReadWrite => {
+                        vk::AttachmentLoadOp::LOAD
+                    }
+                };
+
+                let clear_value = match ca_id {
+                    ResourceID::SwapchainColorAttachment
+                    | ResourceID::SwapchainColorAttachmentUnorm => self.clear_color.into(),
+                    _ => vk::ClearValue::default(),
+                };
 
                 let color_attachment = vk::RenderingAttachmentInfo::default()
-                    .image_view(color_attachment_state.view)
+                    .image_view(view)
                     .image_layout(color_attachment_state.layout)
-                    .load_op(vk::AttachmentLoadOp::CLEAR)
+                    .load_op(load_op)
                     .store_op(vk::AttachmentStoreOp::STORE)
-                    .clear_value(vk::ClearValue::default());
+                    .clear_value(clear_value);
 
                 color_attachments.push(color_attachment);
             }
             let rendering_info = rendering_info.color_attachments(&color_attachments);
 
-            let mut depth_attachment = vk::RenderingAttachmentInfo::default();
-            if let Some(da_id) = attachment_info.depth_stencil_attachment {
+            // depth-only passes render with an empty color attachment array, and passes without a
+            // depth attachment leave its pointer null rather than pointing to an unused one
+            let mut depth_attachment = None;
+            if let Some(depth_stencil) = &attachment_info.depth_stencil_attachment {
                 let depth_attachment_state = resources
-                    .get_mut(&da_id)
+                    .get_mut(&depth_stencil.id)
                     .ok_or(RenderGraphRunError::InvalidResource)?;
 
-                depth_attachment = depth_attachment
-                    .image_view(depth_attachment_state.view)
-                    .image_layout(depth_attachment_state.layout)
-                    .load_op(vk::AttachmentLoadOp::CLEAR)
-                    .store_op(vk::AttachmentStoreOp::STORE)
-                    .clear_value(vk::ClearValue::default());
+                depth_attachment = Some(
+                    vk::RenderingAttachmentInfo::default()
+                        .image_view(depth_attachment_state.view)
+                        .image_layout(depth_attachment_state.layout)
+                        .load_op(depth_stencil.ops.load)
+                        .store_op(depth_stencil.ops.store)
+                        .clear_value(vk::ClearValue {
+                            depth_stencil: vk::ClearDepthStencilValue {
+                                depth: self.clear_depth,
+                                stencil: 0,
+                            },
+                        }),
+                );
             }
-            let rendering_info = rendering_info.depth_attachment(&depth_attachment);
-
-            unsafe {
-                device_ref
-                    .read()
-                    .cmd_begin_rendering(cmd_buffer, &rendering_info)
+            let rendering_info = match &depth_attachment {
+                Some(depth_attachment) => rendering_info.depth_attachment(depth_attachment),
+                None => rendering_info,
             };
 
+            let begins_rendering = render_pass.begins_rendering();
+            if begins_rendering {
+                unsafe {
+                    device_ref
+                        .read()
+                        .cmd_begin_rendering(cmd_buffer, &rendering_info)
+                };
+            }
+            if let Some(depth_bias) = render_pass.depth_bias().filter(|_| begins_rendering) {
+                unsafe {
+                    device_ref.read().cmd_set_depth_bias(
+                        cmd_buffer,
+                        depth_bias.constant_factor,
+                        depth_bias.clamp,
+                        depth_bias.slope_factor,
+                    )
+                };
+            }
+
             render_pass.record_commands(&mut resources, &cmd_buffer, device_ref.clone());
+            resources.end_pass(render_pass.name());
+
+            if begins_rendering {
+                unsafe { device_ref.read().cmd_end_rendering(cmd_buffer) };
+            }
+            breadcrumbs.cmd_pass_completed(pass_index, cmd_buffer, &device_ref.read());
 
-            unsafe { device_ref.read().cmd_end_rendering(cmd_buffer) };
+            if pixel_readbacks.has_pending_copies() {
+                pixel_readbacks.record_copies(
+                    |id| readback_writers.get(id) == Some(&pass_index),
+                    &mut resources,
+                    cmd_buffer,
+                    device_ref,
+                );
+            }
+
+            self.last_cpu_times[pass_index] = recording_start.elapsed();
+
+            if self.split_after[pass_index] {
+                submission.split(batch_uses_swapchain_image)?;
+                batch_uses_swapchain_image = false;
+            }
+        }
+
+        FeedbackCopy::cmd_record_all(
+            &self.feedback_copies,
+            submission.cmd_buffer(),
+            &device_ref.read(),
+        );
+
+        // resources no pass writes to are read back as they are at the end of the frame
+        if pixel_readbacks.has_pending_copies() {
+            pixel_readbacks.record_copies(
+                |_| true,
+                &mut resources,
+                submission.cmd_buffer(),
+                device_ref,
+            );
         }
 
         Ok(())
     }
 }
+
+// Writing the swapchain image is enough for it to be cleared to the graph's clear color
+fn implicit_clear_pass() -> Box<dyn RenderPass> {
+    Box::new(
+        SimpleRenderPass::new("implicit swapchain clear", ()).add_color_attachment(
+            ResourceID::SwapchainColorAttachment,
+            ResourceAccessType::WriteOnly,
+        ),
+    )
+}
+
+// Passes may only render where all of their attachments exist
+fn pass_render_extent(
+    attachment_info: &render_pass::AttachmentInfo,
+    resources: &FrameResources,
+) -> Result<vk::Extent2D, RenderGraphRunError> {
+    let attachment_ids = attachment_info.color_attachments.keys().chain(
+        attachment_info
+            .depth_stencil_attachment
+            .iter()
+            .map(|ds| &ds.id),
+    );
+
+    let mut render_extent: Option<vk::Extent2D> = None;
+    for id in attachment_ids {
+        let extent = resources
+            .attachment_extent(id)
+            .ok_or(RenderGraphRunError::InvalidResource)?;
+        render_extent = Some(match render_extent {
+            Some(current) => vk::Extent2D {
+                width: current.width.min(extent.width),
+                height: current.height.min(extent.height),
+            },
+            None => extent,
+        });
+    }
+
+    Ok(render_extent.unwrap_or_else(|| {
+        resources
+            .attachment_extent(&ResourceID::SwapchainColorAttachment)
+            .unwrap_or_default()
+    }))
+}
@@ -0,0 +1,183 @@
+use ash::vk;
+
+use crate::{
+    gfx::{
+        barrier::BarrierBatch,
+        device::Device,
+        image::ImageState,
+        render_graph::{
+            render_pass::{AttachmentInfo, RenderPass},
+            resource::{FrameResources, ResourceAccessType, ResourceID},
+        },
+    },
+    utils::ThreadSafeRwRef,
+};
+
+/// Copies a color image onto another one, scaling it with linear filtering when their extents
+/// differ, e.g. to upscale attachments rendered at a
+/// [render scale](crate::gfx::context::Context::set_render_scale) onto the swapchain.
+///
+/// The source needs the `TRANSFER_SRC` usage, and the destination the `TRANSFER_DST` usage, which
+/// swapchain images have whenever the surface supports it.
+pub struct BlitPass {
+    source: ResourceID,
+    destination: ResourceID,
+    attachment_infos: AttachmentInfo,
+}
+
+impl BlitPass {
+    /// The destination is entirely overwritten.
+    pub fn new(source: ResourceID, destination: ResourceID) -> Self {
+        let mut attachment_infos = AttachmentInfo::default();
+        attachment_infos.add_color_attachment(destination, ResourceAccessType::WriteOnly);
+        attachment_infos.add_sampled_image(source);
+
+        Self {
+            source,
+            destination,
+            attachment_infos,
+        }
+    }
+}
+
+impl RenderPass for BlitPass {
+    fn name(&self) -> &str {
+        "blit"
+    }
+
+    fn attachment_infos(&self) -> &AttachmentInfo {
+        &self.attachment_infos
+    }
+
+    fn begins_rendering(&self) -> bool {
+        false
+    }
+
+    fn record_commands(
+        &mut self,
+        resources: &mut FrameResources,
+        cmd_buffer: &vk::CommandBuffer,
+        device_ref: ThreadSafeRwRef<Device>,
+    ) {
+        let (Some(source), Some(destination)) = (
+            resources.get(&self.source).cloned(),
+            resources.get(&self.destination).cloned(),
+        ) else {
+            log::warn!("blit source or destination is not a valid resource, skipping blit");
+            return;
+        };
+
+        let device = device_ref.read();
+        // the graph left the source readable by shaders and the destination as a color attachment,
+        // both are restored to these layouts once copied
+        let mut barriers = BarrierBatch::new();
+        barriers.push_image_barrier(transition(
+            &source,
+            (source.layout, vk::ImageLayout::TRANSFER_SRC_OPTIMAL),
+            (
+                vk::PipelineStageFlags2::FRAGMENT_SHADER,
+                vk::AccessFlags2::SHADER_READ,
+            ),
+            (
+                vk::PipelineStageFlags2::BLIT,
+                vk::AccessFlags2::TRANSFER_READ,
+            ),
+        ));
+        barriers.push_image_barrier(transition(
+            &destination,
+            (destination.layout, vk::ImageLayout::TRANSFER_DST_OPTIMAL),
+            (
+                vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+            ),
+            (
+                vk::PipelineStageFlags2::BLIT,
+                vk::AccessFlags2::TRANSFER_WRITE,
+            ),
+        ));
+        barriers.cmd_flush(&device, *cmd_buffer);
+
+        let region = vk::ImageBlit::default()
+            .src_subresource(subresource_layers(&source))
+            .src_offsets(full_offsets(source.extent))
+            .dst_subresource(subresource_layers(&destination))
+            .dst_offsets(full_offsets(destination.extent));
+        unsafe {
+            device.cmd_blit_image(
+                *cmd_buffer,
+                source.handle,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                destination.handle,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[region],
+                vk::Filter::LINEAR,
+            )
+        };
+
+        barriers.push_image_barrier(transition(
+            &source,
+            (vk::ImageLayout::TRANSFER_SRC_OPTIMAL, source.layout),
+            (
+                vk::PipelineStageFlags2::BLIT,
+                vk::AccessFlags2::TRANSFER_READ,
+            ),
+            (
+                vk::PipelineStageFlags2::FRAGMENT_SHADER,
+                vk::AccessFlags2::SHADER_READ,
+            ),
+        ));
+        barriers.push_image_barrier(transition(
+            &destination,
+            (vk::ImageLayout::TRANSFER_DST_OPTIMAL, destination.layout),
+            (
+                vk::PipelineStageFlags2::BLIT,
+                vk::AccessFlags2::TRANSFER_WRITE,
+            ),
+            (
+                vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+                vk::AccessFlags2::COLOR_ATTACHMENT_READ | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+            ),
+        ));
+        barriers.cmd_flush(&device, *cmd_buffer);
+    }
+}
+
+/// Layouts go `(old, new)`, scopes `(stages, accesses)`.
+fn transition(
+    image: &ImageState,
+    (old_layout, new_layout): (vk::ImageLayout, vk::ImageLayout),
+    (src_stage_mask, src_access_mask): (vk::PipelineStageFlags2, vk::AccessFlags2),
+    (dst_stage_mask, dst_access_mask): (vk::PipelineStageFlags2, vk::AccessFlags2),
+) -> vk::ImageMemoryBarrier2<'static> {
+    vk::ImageMemoryBarrier2::default()
+        .image(image.handle)
+        .old_layout(old_layout)
+        .new_layout(new_layout)
+        .src_stage_mask(src_stage_mask)
+        .src_access_mask(src_access_mask)
+        .dst_stage_mask(dst_stage_mask)
+        .dst_access_mask(dst_access_mask)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .subresource_range(image.view_subresource_range)
+}
+
+fn subresource_layers(image: &ImageState) -> vk::ImageSubresourceLayers {
+    let range = image.view_subresource_range;
+    vk::ImageSubresourceLayers::default()
+        .aspect_mask(range.aspect_mask)
+        .mip_level(range.base_mip_level)
+        .base_array_layer(range.base_array_layer)
+        .layer_count(range.layer_count)
+}
+
+fn full_offsets(extent: vk::Extent3D) -> [vk::Offset3D; 2] {
+    [
+        vk::Offset3D::default(),
+        vk::Offset3D {
+            x: extent.width as i32,
+            y: extent.height as i32,
+            z: extent.depth as i32,
+        },
+    ]
+}
@@ -0,0 +1,677 @@
+use std::sync::Arc;
+
+use ash::vk;
+use thiserror::Error;
+
+use crate::{
+    gfx::{
+        commands::FRAMES_IN_FLIGHT,
+        context::Context,
+        device::Device,
+        pipeline::{
+            BlendMode, GraphicsPipeline, GraphicsPipelineBuilder, PipelineBuildError,
+            PipelineOutputs, spirv_from_bytes,
+        },
+        render_graph::{
+            RenderGraphInfo,
+            render_pass::{AttachmentInfo, RenderPass},
+            resource::{
+                AttachmentSize, FrameResources, ImageAttachmentInfo, ResourceAccessType,
+                ResourceID, ResourceInfoInsertError,
+            },
+        },
+        sampler::{Sampler, SamplerCreateError},
+        swapchain::{self, SurfaceProperties},
+    },
+    utils::{ThreadSafeRef, ThreadSafeRwRef},
+};
+
+/// Fullscreen triangle drawn by every bloom pass, see [`BloomShaders`].
+pub const BLOOM_FULLSCREEN_VERT_GLSL: &str = include_str!("../../shaders/bloom_fullscreen.vert");
+pub const BLOOM_DOWNSAMPLE_FRAG_GLSL: &str = include_str!("../../shaders/bloom_downsample.frag");
+pub const BLOOM_UPSAMPLE_FRAG_GLSL: &str = include_str!("../../shaders/bloom_upsample.frag");
+pub const BLOOM_COMPOSITE_FRAG_GLSL: &str = include_str!("../../shaders/bloom_composite.frag");
+
+// compiled from the sources above, see `BloomShaders::default`
+const BLOOM_FULLSCREEN_VERT_SPV: &[u8] = include_bytes!("../../shaders/bloom_fullscreen.vert.spv");
+const BLOOM_DOWNSAMPLE_FRAG_SPV: &[u8] = include_bytes!("../../shaders/bloom_downsample.frag.spv");
+const BLOOM_UPSAMPLE_FRAG_SPV: &[u8] = include_bytes!("../../shaders/bloom_upsample.frag.spv");
+const BLOOM_COMPOSITE_FRAG_SPV: &[u8] = include_bytes!("../../shaders/bloom_composite.frag.spv");
+
+/// Push constants of every bloom shader, at offset 0 of the fragment stage.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct BloomPushConstants {
+    /// Size of a texel of the sampled level.
+    pub texel_size: [f32; 2],
+    /// Only applied by the first downsample, 0 for the others.
+    pub threshold: f32,
+    /// Only used by the composite, 0 while the bloom is disabled.
+    pub intensity: f32,
+}
+
+// SAFETY: only made of f32, without padding
+unsafe impl bytemuck::Zeroable for BloomPushConstants {}
+unsafe impl bytemuck::Pod for BloomPushConstants {}
+
+/// SPIR-V of the bloom shaders, compiled from the `BLOOM_*_GLSL` sources of this module.
+///
+/// The default shaders are precompiled and embedded in the crate, custom ones only need to keep the
+/// same interface.
+#[derive(Debug, Clone)]
+pub struct BloomShaders {
+    pub fullscreen_vertex: Vec<u32>,
+    pub downsample: Vec<u32>,
+    pub upsample: Vec<u32>,
+    pub composite: Vec<u32>,
+}
+
+impl Default for BloomShaders {
+    fn default() -> Self {
+        let read =
+            |bytes| spirv_from_bytes(bytes).expect("embedded bloom shaders should be valid SPIR-V");
+
+        Self {
+            fullscreen_vertex: read(BLOOM_FULLSCREEN_VERT_SPV),
+            downsample: read(BLOOM_DOWNSAMPLE_FRAG_SPV),
+            upsample: read(BLOOM_UPSAMPLE_FRAG_SPV),
+            composite: read(BLOOM_COMPOSITE_FRAG_SPV),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BloomSettings {
+    /// Factor of the bloom added to the scene.
+    pub intensity: f32,
+    /// Brightness under which pixels do not bloom, 0 to bloom everything.
+    pub threshold: f32,
+    /// Levels of the downsample chain, the first one being half the swapchain size.
+    pub level_count: u32,
+    pub format: vk::Format,
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        Self {
+            intensity: 0.05,
+            threshold: 1.0,
+            level_count: 5,
+            format: vk::Format::R16G16B16A16_SFLOAT,
+        }
+    }
+}
+
+impl BloomSettings {
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn with_level_count(mut self, level_count: u32) -> Self {
+        self.level_count = level_count;
+        self
+    }
+
+    /// Format of the levels of the downsample chain.
+    pub fn with_format(mut self, format: vk::Format) -> Self {
+        self.format = format;
+        self
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct BloomState {
+    enabled: bool,
+    intensity: f32,
+    threshold: f32,
+}
+
+/// Controls a bloom effect once its passes are in a render graph, changes applying from the next
+/// recorded frame.
+#[derive(Debug, Clone)]
+pub struct BloomHandle {
+    state: ThreadSafeRef<BloomState>,
+}
+
+impl BloomHandle {
+    /// A disabled bloom only copies the input to the output.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.state.lock().enabled = enabled;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.state.lock().enabled
+    }
+
+    pub fn set_intensity(&self, intensity: f32) {
+        self.state.lock().intensity = intensity;
+    }
+
+    pub fn intensity(&self) -> f32 {
+        self.state.lock().intensity
+    }
+
+    pub fn set_threshold(&self, threshold: f32) {
+        self.state.lock().threshold = threshold;
+    }
+
+    pub fn threshold(&self) -> f32 {
+        self.state.lock().threshold
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum BloomCreateError {
+    #[error("bloom needs at least one level")]
+    NoLevels,
+
+    #[error("bloom input {0:?} is not an attachment of the registry with the sampled usage")]
+    InvalidInput(ResourceID),
+
+    #[error("bloom input {0:?} is also its output")]
+    InputIsOutput(ResourceID),
+
+    #[error("bloom output {0:?} is not declared in the resource registry")]
+    UnknownOutput(ResourceID),
+
+    #[error("bloom level insertion failed")]
+    LevelInsertion(#[from] ResourceInfoInsertError),
+
+    #[error("bloom pipeline creation failed")]
+    PipelineCreation(#[from] PipelineBuildError),
+
+    #[error("bloom sampler creation failed")]
+    SamplerCreation(#[from] SamplerCreateError),
+
+    #[error("vulkan call to create the descriptor set layout failed")]
+    SetLayoutCreation(vk::Result),
+
+    #[error("vulkan call to create the descriptor pool failed")]
+    DescriptorPoolCreation(vk::Result),
+
+    #[error("vulkan call to allocate the descriptor sets failed")]
+    DescriptorSetAllocation(vk::Result),
+}
+
+/// Bloom made of a chain of downsample passes, each halving the previous level, the first one
+/// keeping only what is brighter than the threshold, followed by upsample passes blurring each
+/// level back over the larger one, and a composite adding the result to the input.
+///
+/// Levels are swapchain-relative attachments, following swapchain resizes and the render scale.
+pub struct BloomEffect;
+
+impl BloomEffect {
+    /// Declares the levels and pushes the passes, which write `output` entirely. `hdr_input` must
+    /// be an attachment of the registry with the `SAMPLED` usage, written by earlier passes.
+    pub fn add_to_graph(
+        info: &mut RenderGraphInfo,
+        hdr_input: ResourceID,
+        output: ResourceID,
+        settings: BloomSettings,
+        shaders: &BloomShaders,
+        ctx: &Context,
+    ) -> Result<BloomHandle, BloomCreateError> {
+        if settings.level_count == 0 {
+            return Err(BloomCreateError::NoLevels);
+        }
+        if hdr_input == output {
+            return Err(BloomCreateError::InputIsOutput(hdr_input));
+        }
+        if !info
+            .resources_mut()
+            .image_attachment_info(&hdr_input)
+            .is_some_and(|input_info| input_info.usage.contains(vk::ImageUsageFlags::SAMPLED))
+        {
+            return Err(BloomCreateError::InvalidInput(hdr_input));
+        }
+        let output_format = match output {
+            ResourceID::SwapchainColorAttachment => ctx.surface_properties().format,
+            ResourceID::SwapchainColorAttachmentUnorm => ctx.swapchain_unorm_format(),
+            _ => {
+                info.resources_mut()
+                    .image_attachment_info(&output)
+                    .ok_or(BloomCreateError::UnknownOutput(output))?
+                    .format
+            }
+        };
+
+        let levels = (0..settings.level_count)
+            .map(|level| {
+                info.resources_mut().add_image_attachment(
+                    ImageAttachmentInfo::new(&format!("bloom level {level}"))
+                        .size(AttachmentSize::SwapchainRelative(
+                            0.5_f32.powi(level as i32 + 1),
+                        ))
+                        .format(settings.format)
+                        .usage(
+                            vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+                        ),
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // one set per downsample and upsample, and one for the composite, for every frame slot
+        let pass_count = 2 * settings.level_count;
+        let shared = Arc::new(BloomShared::new(pass_count * FRAMES_IN_FLIGHT as u32, ctx)?);
+        let build_pipeline = |name: &str, fragment_shader: &[u32], format, blend_mode| {
+            bloom_pipeline_builder(name, &shaders.fullscreen_vertex, fragment_shader, &shared)
+                .with_color_formats(&[format])
+                .with_blend_mode(blend_mode)
+                .build(ctx)
+                .map(Arc::new)
+        };
+        let downsample_pipeline = build_pipeline(
+            "bloom downsample",
+            &shaders.downsample,
+            settings.format,
+            BlendMode::Opaque,
+        )?;
+        let upsample_pipeline = build_pipeline(
+            "bloom upsample",
+            &shaders.upsample,
+            settings.format,
+            BlendMode::Additive,
+        )?;
+        let composite_pipeline = build_pipeline(
+            "bloom composite",
+            &shaders.composite,
+            output_format,
+            BlendMode::Opaque,
+        )?;
+
+        let handle = BloomHandle {
+            state: ThreadSafeRef::new(BloomState {
+                enabled: true,
+                intensity: settings.intensity,
+                threshold: settings.threshold,
+            }),
+        };
+        let mut descriptor_sets = shared.descriptor_sets.iter().copied();
+        let mut new_pass =
+            |name: String, kind, source, target, pipeline: &Arc<GraphicsPipeline>| {
+                BloomPass::new(
+                    name,
+                    kind,
+                    source,
+                    target,
+                    pipeline.clone(),
+                    descriptor_sets.by_ref().take(FRAMES_IN_FLIGHT).collect(),
+                    &shared,
+                    &handle,
+                )
+            };
+
+        let mut source = hdr_input;
+        for (level, &target) in levels.iter().enumerate() {
+            let kind = BloomPassKind::Downsample { first: level == 0 };
+            let pass = new_pass(
+                format!("bloom downsample {level}"),
+                kind,
+                vec![source],
+                (target, ResourceAccessType::WriteOnly),
+                &downsample_pipeline,
+            );
+            info.add_render_pass(pass);
+            source = target;
+        }
+        for (level, pair) in levels.windows(2).enumerate().rev() {
+            let pass = new_pass(
+                format!("bloom upsample {level}"),
+                BloomPassKind::Upsample,
+                vec![pair[1]],
+                (pair[0], ResourceAccessType::ReadWrite),
+                &upsample_pipeline,
+            );
+            info.add_render_pass(pass);
+        }
+        let mut composite = new_pass(
+            "bloom composite".to_owned(),
+            BloomPassKind::Composite,
+            vec![hdr_input, levels[0]],
+            (output, ResourceAccessType::WriteOnly),
+            &composite_pipeline,
+        );
+        composite.rebuild_info = Some(CompositeRebuildInfo {
+            vertex_shader: shaders.fullscreen_vertex.clone(),
+            fragment_shader: shaders.composite.clone(),
+            output,
+        });
+        info.add_render_pass(composite);
+
+        Ok(handle)
+    }
+}
+
+fn bloom_pipeline_builder(
+    name: &str,
+    vertex_shader: &[u32],
+    fragment_shader: &[u32],
+    shared: &BloomShared,
+) -> GraphicsPipelineBuilder {
+    GraphicsPipelineBuilder::new(name)
+        .with_vertex_shader(vertex_shader)
+        .with_fragment_shader(fragment_shader)
+        .with_cull_mode(vk::CullModeFlags::NONE, vk::FrontFace::COUNTER_CLOCKWISE)
+        .with_descriptor_set_layouts(&[shared.set_layout])
+        .with_push_constant_ranges(&[vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: std::mem::size_of::<BloomPushConstants>() as u32,
+        }])
+}
+
+// sampler and descriptor sets of every pass of an effect
+struct BloomShared {
+    sampler: Sampler,
+    set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_sets: Vec<vk::DescriptorSet>,
+
+    // bookkeeping
+    device_ref: ThreadSafeRwRef<Device>,
+}
+
+impl BloomShared {
+    fn new(set_count: u32, ctx: &Context) -> Result<Self, BloomCreateError> {
+        let device_ref = ctx.core.device_ref.clone();
+        let sampler_info = vk::SamplerCreateInfo::default()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+        let sampler = Sampler::new("bloom sampler", &sampler_info, device_ref.clone())?;
+
+        let device = device_ref.read();
+        // the composite samples both the scene and the largest level
+        let bindings = [0, 1].map(|binding| {
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(binding)
+                .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+        });
+        let layout_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings);
+        let set_layout = unsafe { device.create_descriptor_set_layout(&layout_info, None) }
+            .map_err(BloomCreateError::SetLayoutCreation)?;
+
+        let pool_size = vk::DescriptorPoolSize::default()
+            .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(2 * set_count);
+        let pool_info = vk::DescriptorPoolCreateInfo::default()
+            .max_sets(set_count)
+            .pool_sizes(std::slice::from_ref(&pool_size));
+        let descriptor_pool = match unsafe { device.create_descriptor_pool(&pool_info, None) } {
+            Ok(pool) => pool,
+            Err(err) => {
+                unsafe { device.destroy_descriptor_set_layout(set_layout, None) };
+                return Err(BloomCreateError::DescriptorPoolCreation(err));
+            }
+        };
+
+        let set_layouts = vec![set_layout; set_count as usize];
+        let allocate_info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&set_layouts);
+        let descriptor_sets = match unsafe { device.allocate_descriptor_sets(&allocate_info) } {
+            Ok(sets) => sets,
+            Err(err) => {
+                unsafe { device.destroy_descriptor_pool(descriptor_pool, None) };
+                unsafe { device.destroy_descriptor_set_layout(set_layout, None) };
+                return Err(BloomCreateError::DescriptorSetAllocation(err));
+            }
+        };
+        drop(device);
+
+        Ok(Self {
+            sampler,
+            set_layout,
+            descriptor_pool,
+            descriptor_sets,
+            device_ref,
+        })
+    }
+}
+
+impl Drop for BloomShared {
+    fn drop(&mut self) {
+        let device = self.device_ref.read();
+
+        unsafe { device.destroy_descriptor_pool(self.descriptor_pool, None) };
+        unsafe { device.destroy_descriptor_set_layout(self.set_layout, None) };
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum BloomPassKind {
+    Downsample { first: bool },
+    Upsample,
+    Composite,
+}
+
+// the composite writes the swapchain in its format, which may change with the surface
+struct CompositeRebuildInfo {
+    vertex_shader: Vec<u32>,
+    fragment_shader: Vec<u32>,
+    output: ResourceID,
+}
+
+struct BloomPass {
+    name: String,
+    kind: BloomPassKind,
+    sources: Vec<ResourceID>,
+    attachment_infos: AttachmentInfo,
+
+    pipeline: Arc<GraphicsPipeline>,
+    // per frame slot, a set still read by a frame in flight must not be updated
+    descriptor_sets: Vec<vk::DescriptorSet>,
+    // views each descriptor set was last written with, attachments being recreated on resize
+    written_views: Vec<Vec<vk::ImageView>>,
+    rebuild_info: Option<CompositeRebuildInfo>,
+
+    shared: Arc<BloomShared>,
+    handle: BloomHandle,
+}
+
+impl BloomPass {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        name: String,
+        kind: BloomPassKind,
+        sources: Vec<ResourceID>,
+        (target, access_type): (ResourceID, ResourceAccessType),
+        pipeline: Arc<GraphicsPipeline>,
+        descriptor_sets: Vec<vk::DescriptorSet>,
+        shared: &Arc<BloomShared>,
+        handle: &BloomHandle,
+    ) -> Self {
+        let mut attachment_infos = AttachmentInfo::default();
+        attachment_infos.add_color_attachment(target, access_type);
+        for &source in &sources {
+            attachment_infos.add_sampled_image(source);
+        }
+
+        Self {
+            name,
+            kind,
+            sources,
+            attachment_infos,
+            pipeline,
+            written_views: vec![vec![]; descriptor_sets.len()],
+            descriptor_sets,
+            rebuild_info: None,
+            shared: shared.clone(),
+            handle: handle.clone(),
+        }
+    }
+
+    // the fence of the slot was waited on before recording this frame
+    fn update_descriptor_set(&mut self, slot: usize, views: Vec<vk::ImageView>, device: &Device) {
+        if views == self.written_views[slot] {
+            return;
+        }
+
+        let image_infos = views
+            .iter()
+            .map(|&view| {
+                vk::DescriptorImageInfo::default()
+                    .sampler(self.shared.sampler.handle)
+                    .image_view(view)
+                    .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            })
+            .collect::<Vec<_>>();
+        let writes = image_infos
+            .iter()
+            .enumerate()
+            .map(|(binding, image_info)| {
+                vk::WriteDescriptorSet::default()
+                    .dst_set(self.descriptor_sets[slot])
+                    .dst_binding(binding as u32)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(std::slice::from_ref(image_info))
+            })
+            .collect::<Vec<_>>();
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
+
+        self.written_views[slot] = views;
+    }
+}
+
+impl RenderPass for BloomPass {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn attachment_infos(&self) -> &AttachmentInfo {
+        &self.attachment_infos
+    }
+
+    fn pipeline_outputs(&self) -> Vec<PipelineOutputs> {
+        vec![self.pipeline.outputs()]
+    }
+
+    fn record_commands(
+        &mut self,
+        resources: &mut FrameResources,
+        cmd_buffer: &vk::CommandBuffer,
+        device_ref: ThreadSafeRwRef<Device>,
+    ) {
+        let state = *self.handle.state.lock();
+        // the composite still copies the input over when disabled
+        if !state.enabled && self.kind != BloomPassKind::Composite {
+            return;
+        }
+
+        let Some(views) = self
+            .sources
+            .iter()
+            .map(|source| resources.view(source))
+            .collect::<Option<Vec<_>>>()
+        else {
+            log::warn!(
+                "bloom pass \"{}\" source is not a valid resource",
+                self.name
+            );
+            return;
+        };
+        let source_extent = resources
+            .attachment_extent(&self.sources[0])
+            .unwrap_or_default();
+
+        let slot = resources.frame_info().slot;
+        let device = device_ref.read();
+        self.update_descriptor_set(slot, views, &device);
+
+        let push_constants = BloomPushConstants {
+            texel_size: [
+                1.0 / source_extent.width.max(1) as f32,
+                1.0 / source_extent.height.max(1) as f32,
+            ],
+            threshold: match self.kind {
+                BloomPassKind::Downsample { first: true } => state.threshold,
+                _ => 0.0,
+            },
+            intensity: if state.enabled { state.intensity } else { 0.0 },
+        };
+
+        self.pipeline.cmd_bind(cmd_buffer, &device);
+        unsafe {
+            device.cmd_set_viewport(*cmd_buffer, 0, &[resources.viewport_full()]);
+            device.cmd_set_scissor(*cmd_buffer, 0, &[resources.scissor_full()]);
+            device.cmd_bind_descriptor_sets(
+                *cmd_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline.layout,
+                0,
+                &[self.descriptor_sets[slot]],
+                &[],
+            );
+            device.cmd_push_constants(
+                *cmd_buffer,
+                self.pipeline.layout,
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                bytemuck::bytes_of(&push_constants),
+            );
+            device.cmd_draw(*cmd_buffer, 3, 1, 0, 0);
+        }
+    }
+
+    fn on_surface_changed(
+        &mut self,
+        new_properties: &SurfaceProperties,
+        device_ref: ThreadSafeRwRef<Device>,
+    ) {
+        let Some(rebuild_info) = &self.rebuild_info else {
+            return;
+        };
+        let format = match rebuild_info.output {
+            ResourceID::SwapchainColorAttachment => new_properties.format,
+            ResourceID::SwapchainColorAttachmentUnorm => {
+                swapchain::unorm_variant(new_properties.format).unwrap_or(new_properties.format)
+            }
+            _ => return,
+        };
+        if self.pipeline.color_formats == [format] {
+            return;
+        }
+
+        let pipeline = bloom_pipeline_builder(
+            "bloom composite",
+            &rebuild_info.vertex_shader,
+            &rebuild_info.fragment_shader,
+            &self.shared,
+        )
+        .with_color_formats(&[format])
+        .build_internal(device_ref);
+        match pipeline {
+            Ok(pipeline) => self.pipeline = Arc::new(pipeline),
+            Err(err) => log::error!("bloom composite pipeline recreation failed: {err}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn embedded_shaders_are_spirv() {
+        let shaders = BloomShaders::default();
+        for spirv in [
+            &shaders.fullscreen_vertex,
+            &shaders.downsample,
+            &shaders.upsample,
+            &shaders.composite,
+        ] {
+            assert_eq!(spirv.first(), Some(&0x0723_0203));
+        }
+    }
+}
@@ -0,0 +1,503 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex, Weak,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use ash::vk;
+
+use crate::{
+    gfx::{
+        allocator::{AllocTag, Allocator},
+        buffer::{Buffer, BufferBuildError},
+        commands::FRAMES_IN_FLIGHT,
+        context::Context,
+        deletion_queue::DeletionQueue,
+        device::Device,
+        mesh::{Mesh, cooked::MeshBounds},
+        pipeline::GraphicsPipeline,
+        render_graph::{
+            render_pass::{AttachmentInfo, RenderPass},
+            resource::FrameResources,
+        },
+        vertex::{Vertex, VertexInputDescription},
+    },
+    math::{Mat4, Vec3, Vec4},
+    utils::{ThreadSafeRef, ThreadSafeRwRef},
+};
+
+/// Vertex binding the per-object transforms are read from, as a per-instance `mat4`.
+pub const INSTANCE_BINDING: u32 = 1;
+const INSTANCE_STRIDE: usize = std::mem::size_of::<[f32; 16]>();
+
+// one slot may be written while every frame in flight still reads its own
+const SLOT_COUNT: usize = FRAMES_IN_FLIGHT + 1;
+
+// Bounds are computed once per mesh address, the weak reference tells if the address was reused
+type BoundsCache<VertexType> = HashMap<usize, (Weak<Mutex<Mesh<VertexType>>>, MeshBounds)>;
+
+/// What the last recording of a [`DrawListPass`] did.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct DrawListStats {
+    pub objects: u32,
+    pub culled: u32,
+    pub draws: u32,
+    pub instances: u32,
+    pub pipeline_binds: u32,
+}
+
+struct DrawItem<VertexType>
+where
+    VertexType: Vertex + bytemuck::Pod,
+{
+    mesh: ThreadSafeRef<Mesh<VertexType>>,
+    pipeline: ThreadSafeRef<GraphicsPipeline>,
+    transform: Mat4,
+}
+
+impl<VertexType> DrawItem<VertexType>
+where
+    VertexType: Vertex + bytemuck::Pod,
+{
+    fn batch_key(&self) -> (usize, usize) {
+        (self.pipeline.addr(), self.mesh.addr())
+    }
+}
+
+/// Consecutive sorted items sharing their (pipeline, mesh) key, drawn with a single instanced
+/// call.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct DrawRun {
+    start: usize,
+    length: usize,
+    binds_pipeline: bool,
+}
+
+// `keys` are sorted, so that every pipeline starts a single group of runs
+fn draw_runs(keys: &[(usize, usize)]) -> Vec<DrawRun> {
+    let mut runs: Vec<DrawRun> = vec![];
+    for (index, key) in keys.iter().enumerate() {
+        match runs.last_mut() {
+            Some(run) if keys[run.start] == *key => run.length += 1,
+            _ => runs.push(DrawRun {
+                start: index,
+                length: 1,
+                binds_pipeline: index == 0 || keys[index - 1].0 != key.0,
+            }),
+        }
+    }
+
+    runs
+}
+
+struct DrawListState<VertexType>
+where
+    VertexType: Vertex + bytemuck::Pod,
+{
+    items: Vec<DrawItem<VertexType>>,
+    culling_view_proj: Option<Mat4>,
+    last_stats: DrawListStats,
+}
+
+/// Shared handle used to queue objects for the next [`DrawListPass`] execution.
+///
+/// Objects pushed during `update` are drawn during the same frame, and the list is emptied once
+/// the pass has recorded its commands.
+pub struct DrawList<VertexType>
+where
+    VertexType: Vertex + bytemuck::Pod,
+{
+    state: ThreadSafeRef<DrawListState<VertexType>>,
+}
+
+impl<VertexType> Clone for DrawList<VertexType>
+where
+    VertexType: Vertex + bytemuck::Pod,
+{
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<VertexType> DrawList<VertexType>
+where
+    VertexType: Vertex + bytemuck::Pod,
+{
+    /// `pipeline` must read the transform through the vertex input of
+    /// [`DrawListPass::vertex_input`].
+    pub fn push(
+        &self,
+        mesh: &ThreadSafeRef<Mesh<VertexType>>,
+        pipeline: &ThreadSafeRef<GraphicsPipeline>,
+        transform: Mat4,
+    ) {
+        self.state.lock().items.push(DrawItem {
+            mesh: mesh.clone(),
+            pipeline: pipeline.clone(),
+            transform,
+        });
+    }
+
+    pub fn clear(&self) {
+        self.state.lock().items.clear();
+    }
+
+    /// Skips objects whose bounds are entirely outside of the frustum of `view_proj`, disabled
+    /// with `None`. Applies to every following frame.
+    pub fn set_frustum_culling(&self, view_proj: Option<Mat4>) {
+        self.state.lock().culling_view_proj = view_proj;
+    }
+
+    pub fn last_stats(&self) -> DrawListStats {
+        self.state.lock().last_stats
+    }
+}
+
+/// Draws the objects queued through its [`DrawList`], sorted by pipeline then mesh so that each
+/// pipeline is bound once and identical (pipeline, mesh) pairs are drawn with a single instanced
+/// call.
+pub struct DrawListPass<VertexType>
+where
+    VertexType: Vertex + bytemuck::Pod,
+{
+    attachment_infos: AttachmentInfo,
+    draw_list: DrawList<VertexType>,
+
+    instance_buffers: Vec<Buffer>,
+    instance_capacity: usize,
+    bounds_cache: BoundsCache<VertexType>,
+
+    // bookkeeping
+    frame_counter: Arc<AtomicU64>,
+    deletion_queue: ThreadSafeRef<DeletionQueue>,
+    device_ref: ThreadSafeRwRef<Device>,
+    allocator_ref: ThreadSafeRef<Allocator>,
+}
+
+impl<VertexType> DrawListPass<VertexType>
+where
+    VertexType: Vertex + bytemuck::Pod,
+{
+    pub fn new(
+        attachment_infos: AttachmentInfo,
+        instance_capacity: usize,
+        ctx: &Context,
+    ) -> Result<Self, BufferBuildError> {
+        let mut pass = Self {
+            attachment_infos,
+            draw_list: DrawList {
+                state: ThreadSafeRef::new(DrawListState {
+                    items: vec![],
+                    culling_view_proj: None,
+                    last_stats: DrawListStats::default(),
+                }),
+            },
+            instance_buffers: vec![],
+            instance_capacity: instance_capacity.max(1),
+            bounds_cache: HashMap::new(),
+            frame_counter: ctx.core.command_manager.frame_counter.clone(),
+            deletion_queue: ctx.deletion_queue.clone(),
+            device_ref: ctx.core.device_ref.clone(),
+            allocator_ref: ctx.core.allocator_ref.clone(),
+        };
+        pass.instance_buffers = (0..SLOT_COUNT)
+            .map(|slot_index| pass.create_instance_buffer(slot_index))
+            .collect::<Result<_, _>>()?;
+
+        Ok(pass)
+    }
+
+    pub fn draw_list(&self) -> DrawList<VertexType> {
+        self.draw_list.clone()
+    }
+
+    /// Vertex input of `VertexType` followed by the per-instance transform at
+    /// [`INSTANCE_BINDING`], as four `vec4` columns starting at `first_location`.
+    pub fn vertex_input(first_location: u32) -> VertexInputDescription {
+        let mut vertex_input = VertexType::vertex_input_description();
+        vertex_input.bindings.push(
+            vk::VertexInputBindingDescription::default()
+                .binding(INSTANCE_BINDING)
+                .stride(INSTANCE_STRIDE as u32)
+                .input_rate(vk::VertexInputRate::INSTANCE),
+        );
+        vertex_input
+            .attributes
+            .extend((0..4).map(|column| vk::VertexInputAttributeDescription {
+                location: first_location + column,
+                binding: INSTANCE_BINDING,
+                format: vk::Format::R32G32B32A32_SFLOAT,
+                offset: column * std::mem::size_of::<[f32; 4]>() as u32,
+            }));
+
+        vertex_input
+    }
+
+    fn create_instance_buffer(&self, slot_index: usize) -> Result<Buffer, BufferBuildError> {
+        Buffer::builder((self.instance_capacity * INSTANCE_STRIDE) as u64)
+            .with_name(&format!("draw list instances (slot {slot_index})"))
+            .with_tag(AllocTag::Mesh)
+            .with_usage(vk::BufferUsageFlags::VERTEX_BUFFER)
+            .with_memory_location(gpu_allocator::MemoryLocation::CpuToGpu)
+            .build_internal(self.device_ref.clone(), self.allocator_ref.clone())
+    }
+
+    fn grow(&mut self, instance_count: usize) -> Result<(), BufferBuildError> {
+        self.instance_capacity = instance_count.next_power_of_two();
+        log::debug!(
+            "growing draw list instance buffers to {} instances",
+            self.instance_capacity
+        );
+
+        for slot_index in 0..SLOT_COUNT {
+            let buffer = self.create_instance_buffer(slot_index)?;
+            let previous_buffer = std::mem::replace(&mut self.instance_buffers[slot_index], buffer);
+            self.deletion_queue.lock().defer(previous_buffer);
+        }
+
+        Ok(())
+    }
+
+    fn bounds(&mut self, mesh: &ThreadSafeRef<Mesh<VertexType>>) -> MeshBounds {
+        let addr = mesh.addr();
+        if let Some((weak, bounds)) = self.bounds_cache.get(&addr)
+            && weak.strong_count() > 0
+        {
+            return *bounds;
+        }
+
+        let bounds = MeshBounds::from_vertices(&mesh.lock().vertices);
+        self.bounds_cache.insert(addr, (mesh.downgrade(), bounds));
+        bounds
+    }
+}
+
+impl<VertexType> RenderPass for DrawListPass<VertexType>
+where
+    VertexType: Vertex + bytemuck::Pod,
+{
+    fn name(&self) -> &str {
+        "draw list"
+    }
+
+    fn attachment_infos(&self) -> &AttachmentInfo {
+        &self.attachment_infos
+    }
+
+    fn record_commands(
+        &mut self,
+        resources: &mut FrameResources,
+        cmd_buffer: &vk::CommandBuffer,
+        device_ref: ThreadSafeRwRef<Device>,
+    ) {
+        let (mut items, culling_view_proj) = {
+            let mut state = self.draw_list.state.lock();
+            (std::mem::take(&mut state.items), state.culling_view_proj)
+        };
+        let mut stats = DrawListStats {
+            objects: items.len() as u32,
+            ..Default::default()
+        };
+
+        if let Some(view_proj) = culling_view_proj {
+            self.bounds_cache
+                .retain(|_, (weak, _)| weak.strong_count() > 0);
+            items.retain(|item| {
+                let bounds = self.bounds(&item.mesh);
+                let visible = intersects_frustum(&bounds, &(view_proj * item.transform));
+                stats.culled += u32::from(!visible);
+                visible
+            });
+        }
+        items.sort_by_key(DrawItem::batch_key);
+
+        if items.len() > self.instance_capacity
+            && let Err(err) = self.grow(items.len())
+        {
+            log::error!("draw list instance buffer growth failed ({err}), skipping its draws");
+            self.draw_list.state.lock().last_stats = stats;
+            return;
+        }
+
+        let slot_index = (self.frame_counter.load(Ordering::Acquire) % SLOT_COUNT as u64) as usize;
+        let instance_buffer = &mut self.instance_buffers[slot_index];
+        let Some(transforms) = instance_buffer.mapped_mut::<[f32; 16]>() else {
+            log::error!("draw list instance buffer is not mapped, skipping its draws");
+            self.draw_list.state.lock().last_stats = stats;
+            return;
+        };
+        for (transform, item) in transforms.iter_mut().zip(&items) {
+            *transform = item.transform.to_cols_array();
+        }
+
+        let device = device_ref.read();
+        unsafe {
+            device.cmd_bind_vertex_buffers(
+                *cmd_buffer,
+                INSTANCE_BINDING,
+                &[instance_buffer.handle],
+                &[0],
+            )
+        };
+
+        let keys: Vec<_> = items.iter().map(DrawItem::batch_key).collect();
+        for run in draw_runs(&keys) {
+            let first = &items[run.start];
+            if run.binds_pipeline {
+                let pipeline = first.pipeline.lock();
+                pipeline.cmd_bind(cmd_buffer, &device);
+                unsafe {
+                    device.cmd_set_viewport(*cmd_buffer, 0, &[resources.viewport_full()]);
+                    device.cmd_set_scissor(*cmd_buffer, 0, &[resources.current_scissor()]);
+                };
+                if pipeline.frame_constants {
+                    resources.bind_frame_constants(cmd_buffer, pipeline.layout, &device);
+                }
+                stats.pipeline_binds += 1;
+            }
+
+            let mesh = first.mesh.lock();
+            let index_count = mesh
+                .indices
+                .len()
+                .try_into()
+                .expect("mesh index count should fit in a u32");
+            unsafe {
+                device.cmd_bind_vertex_buffers(*cmd_buffer, 0, &[mesh.vertex_buffer.handle], &[0]);
+                device.cmd_bind_index_buffer(
+                    *cmd_buffer,
+                    mesh.index_buffer.handle,
+                    0,
+                    vk::IndexType::UINT32,
+                );
+                device.cmd_draw_indexed(
+                    *cmd_buffer,
+                    index_count,
+                    run.length as u32,
+                    0,
+                    0,
+                    run.start as u32,
+                );
+            }
+            stats.draws += 1;
+            stats.instances += run.length as u32;
+        }
+
+        self.draw_list.state.lock().last_stats = stats;
+    }
+}
+
+// Bounds are culled if all of their corners lie outside of the same clip plane, which keeps some
+// invisible objects around frustum corners but never culls a visible one
+fn intersects_frustum(bounds: &MeshBounds, model_view_proj: &Mat4) -> bool {
+    let corners = (0..8).map(|corner| {
+        let position = Vec3::new(
+            if corner & 1 == 0 {
+                bounds.min.x
+            } else {
+                bounds.max.x
+            },
+            if corner & 2 == 0 {
+                bounds.min.y
+            } else {
+                bounds.max.y
+            },
+            if corner & 4 == 0 {
+                bounds.min.z
+            } else {
+                bounds.max.z
+            },
+        );
+        *model_view_proj * position.extend(1.0)
+    });
+    let corners: Vec<Vec4> = corners.collect();
+
+    // vulkan clip space: -w <= x, y <= w and 0 <= z <= w
+    let planes: [fn(&Vec4) -> bool; 6] = [
+        |clip| clip.x < -clip.w,
+        |clip| clip.x > clip.w,
+        |clip| clip.y < -clip.w,
+        |clip| clip.y > clip.w,
+        |clip| clip.z < 0.0,
+        |clip| clip.z > clip.w,
+    ];
+
+    !planes.iter().any(|outside| corners.iter().all(outside))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPAQUE: usize = 0x100;
+    const TRANSPARENT: usize = 0x200;
+    const CUBE: usize = 0x1000;
+    const SPHERE: usize = 0x2000;
+
+    fn sorted_runs(mut keys: Vec<(usize, usize)>) -> Vec<DrawRun> {
+        keys.sort();
+        draw_runs(&keys)
+    }
+
+    #[test]
+    fn an_empty_list_draws_nothing() {
+        assert!(draw_runs(&[]).is_empty());
+    }
+
+    #[test]
+    fn identical_keys_are_a_single_instanced_draw() {
+        let runs = sorted_runs(vec![(OPAQUE, CUBE); 3]);
+
+        assert_eq!(
+            runs,
+            [DrawRun {
+                start: 0,
+                length: 3,
+                binds_pipeline: true,
+            }]
+        );
+    }
+
+    #[test]
+    fn mixed_keys_bind_each_pipeline_once() {
+        let runs = sorted_runs(vec![
+            (TRANSPARENT, SPHERE),
+            (OPAQUE, CUBE),
+            (TRANSPARENT, CUBE),
+            (OPAQUE, SPHERE),
+            (OPAQUE, CUBE),
+            (TRANSPARENT, SPHERE),
+            (OPAQUE, CUBE),
+        ]);
+
+        // opaque cubes, opaque sphere, transparent cube, transparent spheres
+        assert_eq!(runs.len(), 4);
+        assert_eq!(runs.iter().filter(|run| run.binds_pipeline).count(), 2);
+        assert_eq!(
+            runs.iter().map(|run| run.length).collect::<Vec<_>>(),
+            [3, 1, 1, 2]
+        );
+        assert_eq!(
+            runs.iter().map(|run| run.start).collect::<Vec<_>>(),
+            [0, 3, 4, 5]
+        );
+        assert_eq!(
+            runs.iter()
+                .map(|run| run.binds_pipeline)
+                .collect::<Vec<_>>(),
+            [true, false, true, false]
+        );
+    }
+
+    #[test]
+    fn a_shared_mesh_is_drawn_once_per_pipeline() {
+        let runs = sorted_runs(vec![(OPAQUE, CUBE), (TRANSPARENT, CUBE), (OPAQUE, CUBE)]);
+
+        assert_eq!(runs.len(), 2);
+        assert!(runs.iter().all(|run| run.binds_pipeline));
+    }
+}
@@ -0,0 +1,284 @@
+// 8x16 bitmap glyphs for the printable ASCII range (0x20..=0x7E), rasterized from DejaVu Sans Mono.
+// Each glyph is 16 rows, the most significant bit of a row being its leftmost pixel.
+
+pub(crate) const GLYPH_WIDTH: u32 = 8;
+pub(crate) const GLYPH_HEIGHT: u32 = 16;
+
+pub(crate) const FIRST_CHAR: char = ' ';
+pub(crate) const LAST_CHAR: char = '~';
+
+// Drawn for anything outside of the table above
+pub(crate) const REPLACEMENT_GLYPH: [u8; 16] = [
+    0x00, 0x00, 0x7E, 0x42, 0x42, 0x42, 0x42, 0x42, 0x42, 0x42, 0x42, 0x42, 0x7E, 0x00, 0x00, 0x00,
+];
+
+#[rustfmt::skip]
+pub(crate) const GLYPHS: [[u8; 16]; 95] = [
+    // ' '
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+    // '!'
+    [0x00, 0x00, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00],
+    // '"'
+    [0x00, 0x00, 0x24, 0x24, 0x24, 0x24, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+    // '#'
+    [0x00, 0x00, 0x02, 0x12, 0x16, 0x7F, 0x34, 0x24, 0xFE, 0x6C, 0x68, 0x48, 0x00, 0x00, 0x00, 0x00],
+    // '$'
+    [0x00, 0x00, 0x08, 0x1C, 0x3E, 0x68, 0x68, 0x3C, 0x0E, 0x0A, 0x4E, 0x7C, 0x08, 0x08, 0x00, 0x00],
+    // '%'
+    [0x00, 0x00, 0x00, 0x70, 0x90, 0xD0, 0x76, 0x38, 0x4E, 0x09, 0x09, 0x0E, 0x00, 0x00, 0x00, 0x00],
+    // '&'
+    [0x00, 0x00, 0x3C, 0x20, 0x60, 0x20, 0x30, 0x59, 0xC9, 0xC6, 0x46, 0x7F, 0x00, 0x00, 0x00, 0x00],
+    // '\''
+    [0x00, 0x00, 0x10, 0x18, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+    // '('
+    [0x00, 0x00, 0x0C, 0x08, 0x18, 0x10, 0x10, 0x10, 0x10, 0x10, 0x18, 0x08, 0x08, 0x04, 0x00, 0x00],
+    // ')'
+    [0x00, 0x00, 0x30, 0x10, 0x18, 0x08, 0x08, 0x08, 0x08, 0x08, 0x18, 0x18, 0x10, 0x20, 0x00, 0x00],
+    // '*'
+    [0x00, 0x00, 0x00, 0x42, 0x3C, 0x18, 0x66, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+    // '+'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x7E, 0x18, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00],
+    // ','
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x10, 0x10, 0x00, 0x00],
+    // '-'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x3C, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+    // '.'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00],
+    // '/'
+    [0x00, 0x00, 0x02, 0x06, 0x04, 0x0C, 0x08, 0x18, 0x10, 0x30, 0x20, 0x60, 0x40, 0x00, 0x00, 0x00],
+    // '0'
+    [0x00, 0x00, 0x18, 0x24, 0x66, 0x42, 0x5A, 0x5A, 0x42, 0x66, 0x66, 0x3C, 0x00, 0x00, 0x00, 0x00],
+    // '1'
+    [0x00, 0x00, 0x38, 0x38, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x3E, 0x00, 0x00, 0x00, 0x00],
+    // '2'
+    [0x00, 0x00, 0x38, 0x6E, 0x06, 0x06, 0x04, 0x0C, 0x18, 0x30, 0x60, 0x7E, 0x00, 0x00, 0x00, 0x00],
+    // '3'
+    [0x00, 0x00, 0x7C, 0x4E, 0x06, 0x06, 0x1C, 0x0C, 0x06, 0x02, 0x06, 0x7C, 0x00, 0x00, 0x00, 0x00],
+    // '4'
+    [0x00, 0x00, 0x0C, 0x0C, 0x1C, 0x34, 0x24, 0x44, 0x4E, 0x7E, 0x04, 0x04, 0x00, 0x00, 0x00, 0x00],
+    // '5'
+    [0x00, 0x00, 0x7C, 0x60, 0x60, 0x60, 0x7C, 0x06, 0x06, 0x06, 0x06, 0x7C, 0x00, 0x00, 0x00, 0x00],
+    // '6'
+    [0x00, 0x00, 0x1C, 0x30, 0x60, 0x40, 0x7C, 0x66, 0x42, 0x42, 0x66, 0x3C, 0x00, 0x00, 0x00, 0x00],
+    // '7'
+    [0x00, 0x00, 0x7E, 0x06, 0x04, 0x04, 0x0C, 0x08, 0x18, 0x18, 0x10, 0x30, 0x00, 0x00, 0x00, 0x00],
+    // '8'
+    [0x00, 0x00, 0x3C, 0x66, 0x66, 0x66, 0x3C, 0x3C, 0x42, 0x42, 0x66, 0x3C, 0x00, 0x00, 0x00, 0x00],
+    // '9'
+    [0x00, 0x00, 0x38, 0x64, 0x46, 0x42, 0x46, 0x6E, 0x3A, 0x06, 0x04, 0x7C, 0x00, 0x00, 0x00, 0x00],
+    // ':'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00],
+    // ';'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x18, 0x18, 0x00, 0x00, 0x00, 0x18, 0x18, 0x10, 0x10, 0x00, 0x00],
+    // '<'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x06, 0x3C, 0x60, 0x70, 0x1E, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00],
+    // '='
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x00, 0x7E, 0x7E, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+    // '>'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x60, 0x3C, 0x06, 0x0E, 0x78, 0xC0, 0x00, 0x00, 0x00, 0x00, 0x00],
+    // '?'
+    [0x00, 0x00, 0x3C, 0x26, 0x06, 0x04, 0x0C, 0x18, 0x18, 0x00, 0x10, 0x18, 0x00, 0x00, 0x00, 0x00],
+    // '@'
+    [0x00, 0x00, 0x00, 0x3E, 0x62, 0x41, 0xDF, 0x93, 0x91, 0x93, 0xDF, 0x40, 0x60, 0x1E, 0x00, 0x00],
+    // 'A'
+    [0x00, 0x00, 0x18, 0x18, 0x3C, 0x3C, 0x24, 0x24, 0x7E, 0x7E, 0x42, 0xC3, 0x00, 0x00, 0x00, 0x00],
+    // 'B'
+    [0x00, 0x00, 0x7C, 0x6E, 0x42, 0x46, 0x7C, 0x6E, 0x42, 0x42, 0x66, 0x7C, 0x00, 0x00, 0x00, 0x00],
+    // 'C'
+    [0x00, 0x00, 0x1E, 0x32, 0x60, 0x60, 0x40, 0x40, 0x60, 0x60, 0x20, 0x1E, 0x00, 0x00, 0x00, 0x00],
+    // 'D'
+    [0x00, 0x00, 0x78, 0x7C, 0x46, 0x42, 0x42, 0x42, 0x42, 0x46, 0x4C, 0x78, 0x00, 0x00, 0x00, 0x00],
+    // 'E'
+    [0x00, 0x00, 0x7E, 0x60, 0x60, 0x60, 0x7E, 0x60, 0x60, 0x60, 0x60, 0x7E, 0x00, 0x00, 0x00, 0x00],
+    // 'F'
+    [0x00, 0x00, 0x3E, 0x60, 0x60, 0x60, 0x7E, 0x60, 0x60, 0x60, 0x60, 0x60, 0x00, 0x00, 0x00, 0x00],
+    // 'G'
+    [0x00, 0x00, 0x1C, 0x32, 0x60, 0x40, 0x40, 0x4E, 0x42, 0x62, 0x62, 0x3E, 0x00, 0x00, 0x00, 0x00],
+    // 'H'
+    [0x00, 0x00, 0x42, 0x42, 0x42, 0x42, 0x7E, 0x66, 0x42, 0x42, 0x42, 0x42, 0x00, 0x00, 0x00, 0x00],
+    // 'I'
+    [0x00, 0x00, 0x7E, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x7E, 0x00, 0x00, 0x00, 0x00],
+    // 'J'
+    [0x00, 0x00, 0x3C, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x4C, 0x78, 0x00, 0x00, 0x00, 0x00],
+    // 'K'
+    [0x00, 0x00, 0x42, 0x46, 0x4C, 0x58, 0x70, 0x78, 0x4C, 0x44, 0x46, 0x43, 0x00, 0x00, 0x00, 0x00],
+    // 'L'
+    [0x00, 0x00, 0x60, 0x60, 0x60, 0x60, 0x60, 0x60, 0x60, 0x60, 0x60, 0x7F, 0x00, 0x00, 0x00, 0x00],
+    // 'M'
+    [0x00, 0x00, 0x42, 0xE7, 0xE7, 0xFF, 0xDB, 0xDB, 0xC3, 0xC3, 0xC3, 0xC3, 0x00, 0x00, 0x00, 0x00],
+    // 'N'
+    [0x00, 0x00, 0x62, 0x62, 0x72, 0x52, 0x52, 0x4A, 0x4A, 0x4E, 0x46, 0x46, 0x00, 0x00, 0x00, 0x00],
+    // 'O'
+    [0x00, 0x00, 0x3C, 0x66, 0x66, 0x42, 0x42, 0x42, 0x42, 0x42, 0x66, 0x3C, 0x00, 0x00, 0x00, 0x00],
+    // 'P'
+    [0x00, 0x00, 0x7C, 0x6E, 0x62, 0x62, 0x66, 0x7C, 0x60, 0x60, 0x60, 0x60, 0x00, 0x00, 0x00, 0x00],
+    // 'Q'
+    [0x00, 0x00, 0x3C, 0x66, 0x66, 0x42, 0x42, 0x42, 0x42, 0x42, 0x66, 0x3C, 0x0C, 0x04, 0x00, 0x00],
+    // 'R'
+    [0x00, 0x00, 0x78, 0x6E, 0x46, 0x46, 0x46, 0x7C, 0x44, 0x46, 0x42, 0x43, 0x00, 0x00, 0x00, 0x00],
+    // 'S'
+    [0x00, 0x00, 0x3C, 0x66, 0x40, 0x60, 0x78, 0x1E, 0x06, 0x02, 0x46, 0x7C, 0x00, 0x00, 0x00, 0x00],
+    // 'T'
+    [0x00, 0x00, 0xFF, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00],
+    // 'U'
+    [0x00, 0x00, 0x42, 0x42, 0x42, 0x42, 0x42, 0x42, 0x42, 0x42, 0x66, 0x3C, 0x00, 0x00, 0x00, 0x00],
+    // 'V'
+    [0x00, 0x00, 0x42, 0x42, 0x42, 0x66, 0x26, 0x24, 0x24, 0x3C, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00],
+    // 'W'
+    [0x00, 0x00, 0x81, 0xC3, 0xC3, 0xDB, 0x5A, 0x5A, 0x7E, 0x66, 0x66, 0x66, 0x00, 0x00, 0x00, 0x00],
+    // 'X'
+    [0x00, 0x00, 0x42, 0x66, 0x24, 0x3C, 0x18, 0x18, 0x3C, 0x24, 0x62, 0xC3, 0x00, 0x00, 0x00, 0x00],
+    // 'Y'
+    [0x00, 0x00, 0x43, 0x42, 0x66, 0x3C, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00],
+    // 'Z'
+    [0x00, 0x00, 0x7E, 0x06, 0x06, 0x0C, 0x08, 0x18, 0x10, 0x20, 0x60, 0x7F, 0x00, 0x00, 0x00, 0x00],
+    // '['
+    [0x00, 0x00, 0x1C, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1C, 0x00, 0x00],
+    // '\\'
+    [0x00, 0x00, 0x40, 0x60, 0x20, 0x30, 0x10, 0x10, 0x18, 0x08, 0x0C, 0x04, 0x06, 0x00, 0x00, 0x00],
+    // ']'
+    [0x00, 0x00, 0x38, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x38, 0x00, 0x00],
+    // '^'
+    [0x00, 0x00, 0x18, 0x3C, 0x24, 0x42, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+    // '_'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x00, 0x00],
+    // '`'
+    [0x00, 0x20, 0x10, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+    // 'a'
+    [0x00, 0x00, 0x00, 0x00, 0x10, 0x7C, 0x06, 0x1E, 0x76, 0x46, 0x46, 0x7E, 0x00, 0x00, 0x00, 0x00],
+    // 'b'
+    [0x00, 0x00, 0x60, 0x60, 0x60, 0x7C, 0x66, 0x62, 0x62, 0x62, 0x66, 0x7C, 0x00, 0x00, 0x00, 0x00],
+    // 'c'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x3E, 0x20, 0x60, 0x60, 0x60, 0x20, 0x1E, 0x00, 0x00, 0x00, 0x00],
+    // 'd'
+    [0x00, 0x00, 0x06, 0x06, 0x06, 0x3E, 0x66, 0x46, 0x46, 0x46, 0x66, 0x3E, 0x00, 0x00, 0x00, 0x00],
+    // 'e'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x3C, 0x62, 0x62, 0x7E, 0x40, 0x60, 0x3E, 0x00, 0x00, 0x00, 0x00],
+    // 'f'
+    [0x00, 0x00, 0x0E, 0x18, 0x18, 0x7E, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00],
+    // 'g'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x3E, 0x66, 0x46, 0x46, 0x46, 0x66, 0x3E, 0x06, 0x04, 0x38, 0x00],
+    // 'h'
+    [0x00, 0x00, 0x60, 0x60, 0x60, 0x7C, 0x66, 0x62, 0x62, 0x62, 0x62, 0x62, 0x00, 0x00, 0x00, 0x00],
+    // 'i'
+    [0x00, 0x00, 0x18, 0x00, 0x00, 0x38, 0x18, 0x18, 0x18, 0x18, 0x18, 0x7E, 0x00, 0x00, 0x00, 0x00],
+    // 'j'
+    [0x00, 0x00, 0x08, 0x00, 0x00, 0x38, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x08, 0x18, 0x70, 0x00],
+    // 'k'
+    [0x00, 0x00, 0x60, 0x60, 0x60, 0x66, 0x6C, 0x78, 0x78, 0x6C, 0x66, 0x63, 0x00, 0x00, 0x00, 0x00],
+    // 'l'
+    [0x00, 0x00, 0x70, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x18, 0x0E, 0x00, 0x00, 0x00, 0x00],
+    // 'm'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x7E, 0x5A, 0x5A, 0x5A, 0x5A, 0x5A, 0x5A, 0x00, 0x00, 0x00, 0x00],
+    // 'n'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x7C, 0x66, 0x62, 0x62, 0x62, 0x62, 0x62, 0x00, 0x00, 0x00, 0x00],
+    // 'o'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x3C, 0x66, 0x42, 0x42, 0x42, 0x66, 0x3C, 0x00, 0x00, 0x00, 0x00],
+    // 'p'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x7C, 0x66, 0x62, 0x62, 0x62, 0x66, 0x7C, 0x60, 0x60, 0x40, 0x00],
+    // 'q'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x3E, 0x66, 0x46, 0x42, 0x46, 0x66, 0x3E, 0x02, 0x02, 0x02, 0x00],
+    // 'r'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x3F, 0x30, 0x30, 0x30, 0x30, 0x30, 0x30, 0x00, 0x00, 0x00, 0x00],
+    // 's'
+    [0x00, 0x00, 0x00, 0x00, 0x08, 0x3C, 0x60, 0x30, 0x1C, 0x06, 0x06, 0x7C, 0x00, 0x00, 0x00, 0x00],
+    // 't'
+    [0x00, 0x00, 0x00, 0x10, 0x30, 0x7E, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1E, 0x00, 0x00, 0x00, 0x00],
+    // 'u'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x62, 0x62, 0x62, 0x62, 0x66, 0x66, 0x3E, 0x00, 0x00, 0x00, 0x00],
+    // 'v'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x42, 0x66, 0x24, 0x24, 0x3C, 0x18, 0x18, 0x00, 0x00, 0x00, 0x00],
+    // 'w'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x81, 0xC3, 0x5A, 0x5A, 0x7E, 0x66, 0x66, 0x00, 0x00, 0x00, 0x00],
+    // 'x'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x66, 0x24, 0x18, 0x18, 0x3C, 0x24, 0x42, 0x00, 0x00, 0x00, 0x00],
+    // 'y'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x42, 0x66, 0x26, 0x24, 0x3C, 0x18, 0x18, 0x18, 0x30, 0x60, 0x00],
+    // 'z'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x3E, 0x04, 0x0C, 0x18, 0x30, 0x20, 0x7E, 0x00, 0x00, 0x00, 0x00],
+    // '{'
+    [0x00, 0x00, 0x0E, 0x18, 0x18, 0x18, 0x18, 0x30, 0x30, 0x18, 0x18, 0x18, 0x18, 0x0E, 0x00, 0x00],
+    // '|'
+    [0x00, 0x00, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x00],
+    // '}'
+    [0x00, 0x00, 0x70, 0x18, 0x18, 0x18, 0x18, 0x0C, 0x0C, 0x18, 0x18, 0x18, 0x18, 0x70, 0x00, 0x00],
+    // '~'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7B, 0x0E, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+];
+
+// Glyphs are laid out in rows of the atlas in table order, the replacement glyph coming last
+pub(crate) const ATLAS_COLUMNS: u32 = 16;
+pub(crate) const ATLAS_ROWS: u32 = (GLYPHS.len() as u32 + 1).div_ceil(ATLAS_COLUMNS);
+pub(crate) const ATLAS_WIDTH: u32 = ATLAS_COLUMNS * GLYPH_WIDTH;
+pub(crate) const ATLAS_HEIGHT: u32 = ATLAS_ROWS * GLYPH_HEIGHT;
+
+/// Atlas cell of the glyph drawn for `c`.
+pub(crate) fn glyph_cell(c: char) -> u32 {
+    match c {
+        FIRST_CHAR..=LAST_CHAR => c as u32 - FIRST_CHAR as u32,
+        _ => GLYPHS.len() as u32,
+    }
+}
+
+/// One coverage byte per pixel, 255 where a glyph is lit and 0 elsewhere, row after row.
+pub(crate) fn rasterize_atlas() -> Vec<u8> {
+    let mut pixels = vec![0; (ATLAS_WIDTH * ATLAS_HEIGHT) as usize];
+    for (cell, glyph) in GLYPHS.iter().chain([&REPLACEMENT_GLYPH]).enumerate() {
+        let cell_x = cell as u32 % ATLAS_COLUMNS * GLYPH_WIDTH;
+        let cell_y = cell as u32 / ATLAS_COLUMNS * GLYPH_HEIGHT;
+        for (row_index, &row) in glyph.iter().enumerate() {
+            let row_start = (cell_y + row_index as u32) * ATLAS_WIDTH + cell_x;
+            for column in 0..GLYPH_WIDTH {
+                if row & (0x80 >> column) != 0 {
+                    pixels[(row_start + column) as usize] = u8::MAX;
+                }
+            }
+        }
+    }
+
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell_pixels(pixels: &[u8], cell: u32) -> Vec<&[u8]> {
+        let cell_x = (cell % ATLAS_COLUMNS * GLYPH_WIDTH) as usize;
+        let cell_y = (cell / ATLAS_COLUMNS * GLYPH_HEIGHT) as usize;
+        (cell_y..cell_y + GLYPH_HEIGHT as usize)
+            .map(|y| {
+                let row_start = y * ATLAS_WIDTH as usize + cell_x;
+                &pixels[row_start..row_start + GLYPH_WIDTH as usize]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_printable_glyph_but_space_has_lit_pixels() {
+        for c in (FIRST_CHAR..=LAST_CHAR).filter(|&c| c != ' ') {
+            assert!(
+                GLYPHS[glyph_cell(c) as usize].iter().any(|&row| row != 0),
+                "glyph {c:?} is empty"
+            );
+        }
+    }
+
+    #[test]
+    fn atlas_cells_match_the_glyph_bitmaps() {
+        let pixels = rasterize_atlas();
+        assert_eq!(pixels.len(), (ATLAS_WIDTH * ATLAS_HEIGHT) as usize);
+
+        let underscore = cell_pixels(&pixels, glyph_cell('_'));
+        assert!(underscore[13].iter().all(|&pixel| pixel == u8::MAX));
+        assert!(underscore[12].iter().all(|&pixel| pixel == 0));
+
+        // the '!' bar covers the two middle columns
+        let exclamation = cell_pixels(&pixels, glyph_cell('!'));
+        assert_eq!(exclamation[2], [0, 0, 0, 255, 255, 0, 0, 0]);
+
+        let replacement = cell_pixels(&pixels, glyph_cell('\u{e9}'));
+        assert_eq!(glyph_cell('\n'), glyph_cell('\u{e9}'));
+        assert_eq!(replacement[2], [0, 255, 255, 255, 255, 255, 255, 0]);
+    }
+}
@@ -0,0 +1,7 @@
+pub mod blit;
+pub mod bloom;
+pub mod draw_list;
+mod font;
+
+pub mod shadow_map;
+pub mod text_overlay;
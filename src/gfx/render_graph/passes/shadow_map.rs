@@ -0,0 +1,291 @@
+use ash::vk;
+use thiserror::Error;
+
+use crate::{
+    gfx::{
+        context::Context,
+        device::Device,
+        image::format_aspect,
+        pipeline::{
+            GraphicsPipeline, GraphicsPipelineBuilder, PipelineBuildError, PipelineOutputs,
+        },
+        render_graph::{
+            render_pass::{AttachmentInfo, AttachmentOps, DepthBias, RenderPass},
+            resource::{
+                AttachmentSize, FrameResources, ImageAttachmentInfo, ResourceAccessType,
+                ResourceID, ResourceInfoInsertError, ResourceInfoRegistry,
+            },
+        },
+        sampler::{Sampler, SamplerCreateError},
+        vertex::VertexInputDescription,
+    },
+    math::Mat4,
+    utils::ThreadSafeRwRef,
+};
+
+/// Offset of the model matrix in the push constants of the shadow pipeline, the light view
+/// projection matrix being pushed by the pass at offset 0.
+pub const SHADOW_MODEL_MATRIX_OFFSET: u32 = 64;
+const PUSH_CONSTANTS_SIZE: u32 = 128;
+
+pub type LightViewProjProvider = Box<dyn FnMut() -> Mat4>;
+
+/// Binds and draws the shadow casters, with the depth-only pipeline already bound. The pipeline
+/// layout is given to push the model matrix of each caster at [`SHADOW_MODEL_MATRIX_OFFSET`].
+pub type ShadowDrawCallback =
+    Box<dyn FnMut(&mut FrameResources, &vk::CommandBuffer, vk::PipelineLayout, &Device)>;
+
+#[derive(Debug, Error)]
+pub enum ShadowMapPassCreateError {
+    #[error("shadow map target {0:?} is not declared in the resource registry")]
+    UnknownTarget(ResourceID),
+
+    #[error("shadow map target {id:?} has format {format:?}, which has no depth aspect")]
+    NotDepthFormat { id: ResourceID, format: vk::Format },
+
+    #[error("shadow map target {0:?} is missing the depth attachment or sampled usage")]
+    MissingUsage(ResourceID),
+
+    #[error("shadow map target insertion failed")]
+    TargetInsertion(#[from] ResourceInfoInsertError),
+
+    #[error("depth-only pipeline creation failed")]
+    PipelineCreation(#[from] PipelineBuildError),
+
+    #[error("shadow sampler creation failed")]
+    SamplerCreation(#[from] SamplerCreateError),
+}
+
+/// Describes a [`ShadowMapPass`].
+///
+/// The vertex shader receives the light view projection matrix and the model matrix of the
+/// caster as push constants:
+/// `layout(push_constant) uniform Shadow { mat4 light_view_proj; mat4 model; };`
+pub struct ShadowMapPassInfo {
+    pub resolution: u32,
+    pub format: vk::Format,
+    /// Declared in the registry with `resolution` and `format` when not provided.
+    pub target: Option<ResourceID>,
+
+    pub vertex_shader: Vec<u32>,
+    pub vertex_input: VertexInputDescription,
+    /// Given for a standard depth range, the factors are negated when the context uses reverse-z.
+    pub depth_bias: DepthBias,
+}
+
+impl ShadowMapPassInfo {
+    pub fn new(
+        resolution: u32,
+        vertex_shader: &[u32],
+        vertex_input: VertexInputDescription,
+    ) -> Self {
+        Self {
+            resolution,
+            format: vk::Format::D32_SFLOAT,
+            target: None,
+
+            vertex_shader: vertex_shader.to_vec(),
+            vertex_input,
+            depth_bias: DepthBias {
+                constant_factor: 1.25,
+                clamp: 0.0,
+                slope_factor: 1.75,
+            },
+        }
+    }
+
+    /// Only used for a target created by the pass.
+    pub fn with_format(mut self, format: vk::Format) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Renders into an already declared attachment, which needs a depth format along with the
+    /// `DEPTH_STENCIL_ATTACHMENT` and `SAMPLED` usages. Its size takes precedence over the
+    /// resolution.
+    pub fn with_target(mut self, target: ResourceID) -> Self {
+        self.target = Some(target);
+        self
+    }
+
+    pub fn with_depth_bias(mut self, constant_factor: f32, clamp: f32, slope_factor: f32) -> Self {
+        self.depth_bias = DepthBias {
+            constant_factor,
+            clamp,
+            slope_factor,
+        };
+        self
+    }
+}
+
+/// Renders the depth of shadow casters as seen from a light into a depth attachment.
+///
+/// Passes consuming the shadow map must declare its [`target`](Self::target) with
+/// [`add_sampled_image`](AttachmentInfo::add_sampled_image) to find it in a shader read-only layout,
+/// and sample it through [`shadow_sampler`](Self::shadow_sampler).
+pub struct ShadowMapPass {
+    target: ResourceID,
+    attachment_infos: AttachmentInfo,
+    depth_bias: DepthBias,
+
+    pipeline: GraphicsPipeline,
+    sampler: Sampler,
+
+    light_view_proj: LightViewProjProvider,
+    draw: ShadowDrawCallback,
+}
+
+impl ShadowMapPass {
+    pub fn new(
+        info: ShadowMapPassInfo,
+        light_view_proj: LightViewProjProvider,
+        draw: ShadowDrawCallback,
+        resource_infos: &mut ResourceInfoRegistry,
+        ctx: &Context,
+    ) -> Result<Self, ShadowMapPassCreateError> {
+        let (target, format) = match info.target {
+            Some(target) => {
+                let target_info = resource_infos
+                    .image_attachment_info(&target)
+                    .ok_or(ShadowMapPassCreateError::UnknownTarget(target))?;
+                if !format_aspect(target_info.format).contains(vk::ImageAspectFlags::DEPTH) {
+                    return Err(ShadowMapPassCreateError::NotDepthFormat {
+                        id: target,
+                        format: target_info.format,
+                    });
+                }
+                if !target_info.usage.contains(
+                    vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+                ) {
+                    return Err(ShadowMapPassCreateError::MissingUsage(target));
+                }
+
+                (target, target_info.format)
+            }
+            None => {
+                let target = resource_infos.add_image_attachment(
+                    ImageAttachmentInfo::new("shadow map")
+                        .size(AttachmentSize::Custom(vk::Extent3D {
+                            width: info.resolution,
+                            height: info.resolution,
+                            depth: 1,
+                        }))
+                        .format(info.format)
+                        .usage(
+                            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+                                | vk::ImageUsageFlags::SAMPLED,
+                        ),
+                )?;
+
+                (target, info.format)
+            }
+        };
+
+        let (compare_op, border_color, depth_bias) = if ctx.is_reverse_z() {
+            (
+                vk::CompareOp::GREATER,
+                vk::BorderColor::FLOAT_OPAQUE_BLACK,
+                DepthBias {
+                    constant_factor: -info.depth_bias.constant_factor,
+                    clamp: -info.depth_bias.clamp,
+                    slope_factor: -info.depth_bias.slope_factor,
+                },
+            )
+        } else {
+            (
+                vk::CompareOp::LESS,
+                vk::BorderColor::FLOAT_OPAQUE_WHITE,
+                info.depth_bias,
+            )
+        };
+
+        // no culling, so that single-sided geometry still casts shadows
+        let pipeline = GraphicsPipelineBuilder::new("shadow map")
+            .with_vertex_shader(&info.vertex_shader)
+            .with_vertex_input(info.vertex_input)
+            .with_cull_mode(vk::CullModeFlags::NONE, vk::FrontFace::COUNTER_CLOCKWISE)
+            .no_color_output()
+            .with_depth(format, compare_op, true)
+            .dynamic_depth_bias()
+            .with_push_constant_ranges(&[vk::PushConstantRange {
+                stage_flags: vk::ShaderStageFlags::VERTEX,
+                offset: 0,
+                size: PUSH_CONSTANTS_SIZE,
+            }])
+            .build(ctx)?;
+        let sampler = Sampler::shadow(compare_op, border_color, ctx.core.device_ref.clone())?;
+
+        let mut attachment_infos = AttachmentInfo::default();
+        attachment_infos.set_depth_stencil_attachment(
+            target,
+            ResourceAccessType::WriteOnly,
+            AttachmentOps::clear_store(),
+        );
+
+        Ok(Self {
+            target,
+            attachment_infos,
+            depth_bias,
+
+            pipeline,
+            sampler,
+
+            light_view_proj,
+            draw,
+        })
+    }
+
+    /// The depth attachment the shadow map is rendered into.
+    pub fn target(&self) -> ResourceID {
+        self.target
+    }
+
+    /// Comparison sampler to read the shadow map with, lookups outside of it are never shadowed.
+    /// The handle stays valid as long as the pass exists.
+    pub fn shadow_sampler(&self) -> vk::Sampler {
+        self.sampler.handle
+    }
+}
+
+impl RenderPass for ShadowMapPass {
+    fn name(&self) -> &str {
+        "shadow map"
+    }
+
+    fn attachment_infos(&self) -> &AttachmentInfo {
+        &self.attachment_infos
+    }
+
+    fn depth_bias(&self) -> Option<DepthBias> {
+        Some(self.depth_bias)
+    }
+
+    fn pipeline_outputs(&self) -> Vec<PipelineOutputs> {
+        vec![self.pipeline.outputs()]
+    }
+
+    fn record_commands(
+        &mut self,
+        resources: &mut FrameResources,
+        cmd_buffer: &vk::CommandBuffer,
+        device_ref: ThreadSafeRwRef<Device>,
+    ) {
+        let device = device_ref.read();
+        self.pipeline.cmd_bind(cmd_buffer, &device);
+
+        let light_view_proj = (self.light_view_proj)().to_cols_array();
+        unsafe {
+            device.cmd_set_viewport(*cmd_buffer, 0, &[resources.viewport_full()]);
+            device.cmd_set_scissor(*cmd_buffer, 0, &[resources.scissor_full()]);
+            device.cmd_push_constants(
+                *cmd_buffer,
+                self.pipeline.layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                bytemuck::cast_slice(&light_view_proj),
+            );
+        };
+
+        (self.draw)(resources, cmd_buffer, self.pipeline.layout, &device);
+    }
+}
@@ -0,0 +1,528 @@
+use ash::vk;
+use thiserror::Error;
+
+use crate::{
+    gfx::{
+        allocator::{AllocTag, Allocator},
+        buffer::{Buffer, BufferBuildError},
+        color::Color,
+        commands::FRAMES_IN_FLIGHT,
+        context::Context,
+        device::Device,
+        image::{Image, ImageBuildError},
+        pipeline::{BlendMode, GraphicsPipeline, GraphicsPipelineBuilder, PipelineOutputs},
+        render_graph::{
+            render_pass::{AttachmentInfo, RenderPass},
+            resource::{FrameResources, ResourceAccessType, ResourceID},
+        },
+        sampler::{Sampler, SamplerCreateError},
+        staging::{ImageDestination, StagingWriteError},
+        swapchain,
+        vertex::VertexInputDescription,
+    },
+    utils::{ThreadSafeRef, ThreadSafeRwRef},
+};
+
+use super::font::{self, ATLAS_HEIGHT, ATLAS_WIDTH, GLYPH_HEIGHT, GLYPH_WIDTH};
+
+const TEXT_OVERLAY_VERT_SPV: &[u8] = include_bytes!("../../shaders/text_overlay.vert.spv");
+const TEXT_OVERLAY_FRAG_SPV: &[u8] = include_bytes!("../../shaders/text_overlay.frag.spv");
+
+const INITIAL_GLYPH_CAPACITY: usize = 1024;
+
+// A glyph placed by `TextOverlay::print`, in pixel coordinates relative to the top-left of the
+// target
+#[derive(Debug, Clone, Copy)]
+struct PlacedGlyph {
+    x: i32,
+    y: i32,
+    cell: u32,
+}
+
+#[derive(Debug)]
+struct TextBatch {
+    color: Color,
+    glyphs: Vec<PlacedGlyph>,
+}
+
+/// Per-instance input of `text_overlay.vert`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+struct GlyphInstance {
+    position: [f32; 2],
+    color: [f32; 4],
+    cell: u32,
+}
+
+// SAFETY: only made of 4-byte fields, without padding
+unsafe impl bytemuck::Zeroable for GlyphInstance {}
+unsafe impl bytemuck::Pod for GlyphInstance {}
+
+impl GlyphInstance {
+    fn vertex_input() -> VertexInputDescription {
+        let attribute = |location, format, offset| vk::VertexInputAttributeDescription {
+            location,
+            binding: 0,
+            format,
+            offset: offset as u32,
+        };
+
+        VertexInputDescription {
+            bindings: vec![
+                vk::VertexInputBindingDescription::default()
+                    .binding(0)
+                    .stride(std::mem::size_of::<Self>() as u32)
+                    .input_rate(vk::VertexInputRate::INSTANCE),
+            ],
+            attributes: vec![
+                attribute(
+                    0,
+                    vk::Format::R32G32_SFLOAT,
+                    std::mem::offset_of!(Self, position),
+                ),
+                attribute(
+                    1,
+                    vk::Format::R32G32B32A32_SFLOAT,
+                    std::mem::offset_of!(Self, color),
+                ),
+                attribute(2, vk::Format::R32_UINT, std::mem::offset_of!(Self, cell)),
+            ],
+        }
+    }
+}
+
+/// Shared handle used to queue text for the next [`TextOverlayPass`] execution.
+///
+/// Text printed during `update` is drawn during the same frame, and the queue is emptied once the
+/// pass has recorded its commands.
+#[derive(Debug, Clone)]
+pub struct TextOverlay {
+    batches: ThreadSafeRef<Vec<TextBatch>>,
+}
+
+impl TextOverlay {
+    fn new() -> Self {
+        Self {
+            batches: ThreadSafeRef::new(vec![]),
+        }
+    }
+
+    /// Queues `text` with its top-left corner at (`x`, `y`), in pixels. Newlines start a new line
+    /// under `x`, and characters outside of printable ASCII are drawn as a replacement glyph.
+    pub fn print(&self, x: i32, y: i32, text: &str, color: Color) {
+        let mut glyphs = vec![];
+
+        let (mut pen_x, mut pen_y) = (x, y);
+        for c in text.chars() {
+            if c == '\n' {
+                pen_x = x;
+                pen_y += GLYPH_HEIGHT as i32;
+                continue;
+            }
+
+            if c != ' ' {
+                glyphs.push(PlacedGlyph {
+                    x: pen_x,
+                    y: pen_y,
+                    cell: font::glyph_cell(c),
+                });
+            }
+            pen_x += GLYPH_WIDTH as i32;
+        }
+
+        if !glyphs.is_empty() {
+            self.batches.lock().push(TextBatch { color, glyphs });
+        }
+    }
+
+    pub fn clear(&self) {
+        self.batches.lock().clear();
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum TextOverlayCreateError {
+    #[error("glyph atlas creation failed")]
+    AtlasCreation(#[from] ImageBuildError),
+
+    #[error("glyph atlas upload failed")]
+    AtlasUpload(#[from] StagingWriteError),
+
+    #[error("glyph instance buffer creation failed")]
+    InstanceBufferCreation(#[from] BufferBuildError),
+
+    #[error("glyph atlas sampler creation failed")]
+    SamplerCreation(#[from] SamplerCreateError),
+
+    #[error("text overlay shaders are not valid SPIR-V")]
+    InvalidShader(#[from] std::io::Error),
+
+    #[error("vulkan call to create the descriptor set layout failed")]
+    SetLayoutCreation(vk::Result),
+
+    #[error("vulkan call to create the descriptor pool failed")]
+    DescriptorPoolCreation(vk::Result),
+
+    #[error("vulkan call to allocate the descriptor set failed")]
+    DescriptorSetAllocation(vk::Result),
+}
+
+/// Draws the text queued through its [`TextOverlay`] on top of the target color attachment.
+///
+/// Glyphs are rasterized once into an atlas texture and drawn as alpha-blended quads, so printed
+/// colors may be translucent. Text is clipped to the current scissor.
+pub struct TextOverlayPass {
+    target: ResourceID,
+    attachment_infos: AttachmentInfo,
+    overlay: TextOverlay,
+    srgb_colors: bool,
+
+    // built once the format of the target view is known, see `record_commands`
+    pipeline: Option<GraphicsPipeline>,
+    vertex_shader: Vec<u32>,
+    fragment_shader: Vec<u32>,
+    _atlas: Image,
+    _sampler: Sampler,
+    set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_set: vk::DescriptorSet,
+    // per frame slot, a buffer still read by a frame in flight must not be written
+    instance_buffers: Vec<Buffer>,
+
+    // bookkeeping
+    device_ref: ThreadSafeRwRef<Device>,
+    allocator_ref: ThreadSafeRef<Allocator>,
+}
+
+impl TextOverlayPass {
+    /// The glyph atlas is uploaded through the [staging belt](Context::staging_belt), with the
+    /// copies of the next frame.
+    pub fn new(target: ResourceID, ctx: &mut Context) -> Result<Self, TextOverlayCreateError> {
+        let mut attachment_infos = AttachmentInfo::default();
+        attachment_infos.add_color_attachment(target, ResourceAccessType::ReadWrite);
+
+        let device_ref = ctx.core.device_ref.clone();
+        let allocator_ref = ctx.core.allocator_ref.clone();
+
+        let mut atlas = create_atlas(ctx)?;
+        let sampler_info = vk::SamplerCreateInfo::default()
+            .mag_filter(vk::Filter::NEAREST)
+            .min_filter(vk::Filter::NEAREST)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+        let sampler = Sampler::new("text overlay sampler", &sampler_info, device_ref.clone())?;
+        let instance_buffers = (0..FRAMES_IN_FLIGHT)
+            .map(|slot| {
+                create_instance_buffer(
+                    slot,
+                    INITIAL_GLYPH_CAPACITY,
+                    device_ref.clone(),
+                    allocator_ref.clone(),
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let vertex_shader = crate::gfx::pipeline::spirv_from_bytes(TEXT_OVERLAY_VERT_SPV)?;
+        let fragment_shader = crate::gfx::pipeline::spirv_from_bytes(TEXT_OVERLAY_FRAG_SPV)?;
+
+        let device = device_ref.read();
+        let binding = vk::DescriptorSetLayoutBinding::default()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT);
+        let layout_info =
+            vk::DescriptorSetLayoutCreateInfo::default().bindings(std::slice::from_ref(&binding));
+        let set_layout = unsafe { device.create_descriptor_set_layout(&layout_info, None) }
+            .map_err(TextOverlayCreateError::SetLayoutCreation)?;
+
+        let pool_size = vk::DescriptorPoolSize::default()
+            .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1);
+        let pool_info = vk::DescriptorPoolCreateInfo::default()
+            .max_sets(1)
+            .pool_sizes(std::slice::from_ref(&pool_size));
+        let descriptor_pool = match unsafe { device.create_descriptor_pool(&pool_info, None) } {
+            Ok(pool) => pool,
+            Err(err) => {
+                unsafe { device.destroy_descriptor_set_layout(set_layout, None) };
+                return Err(TextOverlayCreateError::DescriptorPoolCreation(err));
+            }
+        };
+
+        let allocate_info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(std::slice::from_ref(&set_layout));
+        let descriptor_set = match unsafe { device.allocate_descriptor_sets(&allocate_info) } {
+            Ok(sets) => sets[0],
+            Err(err) => {
+                unsafe { device.destroy_descriptor_pool(descriptor_pool, None) };
+                unsafe { device.destroy_descriptor_set_layout(set_layout, None) };
+                return Err(TextOverlayCreateError::DescriptorSetAllocation(err));
+            }
+        };
+
+        // the atlas is only sampled once the staged copy, recorded before any pass, transitioned it
+        atlas.state.layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+        let image_info = vk::DescriptorImageInfo::default()
+            .sampler(sampler.handle)
+            .image_view(atlas.state.view)
+            .image_layout(atlas.state.layout);
+        let write = vk::WriteDescriptorSet::default()
+            .dst_set(descriptor_set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(std::slice::from_ref(&image_info));
+        unsafe { device.update_descriptor_sets(std::slice::from_ref(&write), &[]) };
+        drop(device);
+
+        Ok(Self {
+            target,
+            attachment_infos,
+            overlay: TextOverlay::new(),
+            srgb_colors: false,
+            pipeline: None,
+            vertex_shader,
+            fragment_shader,
+            _atlas: atlas,
+            _sampler: sampler,
+            set_layout,
+            descriptor_pool,
+            descriptor_set,
+            instance_buffers,
+            device_ref,
+            allocator_ref,
+        })
+    }
+
+    /// Treats printed colors as sRGB values, written as is to the target. When the target is
+    /// viewed as sRGB, e.g. [`ResourceID::SwapchainColorAttachmentUnorm`] without a UNORM view,
+    /// they are decoded to linear first so that the view encodes them back.
+    pub fn with_srgb_colors(mut self) -> Self {
+        self.srgb_colors = true;
+        self
+    }
+
+    pub fn overlay(&self) -> TextOverlay {
+        self.overlay.clone()
+    }
+
+    // the format of the target only changes with the surface, once the device is idle
+    fn pipeline_for(&mut self, format: vk::Format) -> Option<&GraphicsPipeline> {
+        if self
+            .pipeline
+            .as_ref()
+            .is_none_or(|pipeline| pipeline.color_formats != [format])
+        {
+            let pipeline = GraphicsPipelineBuilder::new("text overlay")
+                .with_vertex_shader(&self.vertex_shader)
+                .with_fragment_shader(&self.fragment_shader)
+                .with_vertex_input(GlyphInstance::vertex_input())
+                .with_topology(vk::PrimitiveTopology::TRIANGLE_STRIP)
+                .with_cull_mode(vk::CullModeFlags::NONE, vk::FrontFace::COUNTER_CLOCKWISE)
+                .with_color_formats(&[format])
+                .with_blend_mode(BlendMode::AlphaBlend)
+                .with_descriptor_set_layouts(&[self.set_layout])
+                .with_push_constant_ranges(&[vk::PushConstantRange {
+                    stage_flags: vk::ShaderStageFlags::VERTEX,
+                    offset: 0,
+                    size: std::mem::size_of::<[f32; 2]>() as u32,
+                }])
+                .build_internal(self.device_ref.clone());
+            match pipeline {
+                Ok(pipeline) => self.pipeline = Some(pipeline),
+                Err(err) => {
+                    log::error!("text overlay pipeline creation failed: {err}");
+                    self.pipeline = None;
+                }
+            }
+        }
+
+        self.pipeline.as_ref()
+    }
+}
+
+impl RenderPass for TextOverlayPass {
+    fn name(&self) -> &str {
+        "text overlay"
+    }
+
+    fn attachment_infos(&self) -> &AttachmentInfo {
+        &self.attachment_infos
+    }
+
+    fn pipeline_outputs(&self) -> Vec<PipelineOutputs> {
+        self.pipeline
+            .iter()
+            .map(GraphicsPipeline::outputs)
+            .collect()
+    }
+
+    fn record_commands(
+        &mut self,
+        resources: &mut FrameResources,
+        cmd_buffer: &vk::CommandBuffer,
+        device_ref: ThreadSafeRwRef<Device>,
+    ) {
+        let batches = std::mem::take(&mut *self.overlay.batches.lock());
+        let (Some(format), Some(extent)) = (
+            resources.view_format(&self.target),
+            resources.attachment_extent(&self.target),
+        ) else {
+            log::warn!("text overlay target is not a valid resource, dropping queued text");
+            return;
+        };
+        let decode_srgb = self.srgb_colors && swapchain::unorm_variant(format).is_some();
+
+        let instances = batches
+            .iter()
+            .flat_map(|batch| {
+                let color = match decode_srgb {
+                    true => batch.color.srgb_to_linear(),
+                    false => batch.color,
+                };
+                batch.glyphs.iter().map(move |glyph| GlyphInstance {
+                    position: [glyph.x as f32, glyph.y as f32],
+                    color: [color.r, color.g, color.b, color.a],
+                    cell: glyph.cell,
+                })
+            })
+            .collect::<Vec<_>>();
+        if instances.is_empty() {
+            return;
+        }
+
+        // the fence of the slot was waited on before recording this frame
+        let slot = resources.frame_info().slot;
+        if self.instance_buffers[slot].size() < std::mem::size_of_val(instances.as_slice()) as u64 {
+            let buffer = create_instance_buffer(
+                slot,
+                instances.len().next_power_of_two(),
+                self.device_ref.clone(),
+                self.allocator_ref.clone(),
+            );
+            match buffer {
+                Ok(buffer) => self.instance_buffers[slot] = buffer,
+                Err(err) => {
+                    log::error!("text overlay buffer growth failed ({err}), dropping queued text");
+                    return;
+                }
+            }
+        }
+        let instance_buffer = &mut self.instance_buffers[slot];
+        let Some(mapped) = instance_buffer.mapped_mut::<GlyphInstance>() else {
+            log::error!("text overlay instance buffer is not mapped, dropping queued text");
+            return;
+        };
+        mapped[..instances.len()].copy_from_slice(&instances);
+        let instance_buffer = instance_buffer.handle;
+
+        let descriptor_set = self.descriptor_set;
+        let Some(pipeline) = self.pipeline_for(format) else {
+            return;
+        };
+
+        let target_size = [extent.width as f32, extent.height as f32];
+        let device = device_ref.read();
+        pipeline.cmd_bind(cmd_buffer, &device);
+        unsafe {
+            device.cmd_set_viewport(*cmd_buffer, 0, &[resources.viewport_full()]);
+            device.cmd_set_scissor(*cmd_buffer, 0, &[resources.current_scissor()]);
+            device.cmd_bind_descriptor_sets(
+                *cmd_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline.layout,
+                0,
+                &[descriptor_set],
+                &[],
+            );
+            device.cmd_push_constants(
+                *cmd_buffer,
+                pipeline.layout,
+                vk::ShaderStageFlags::VERTEX,
+                0,
+                bytemuck::bytes_of(&target_size),
+            );
+            device.cmd_bind_vertex_buffers(*cmd_buffer, 0, &[instance_buffer], &[0]);
+            device.cmd_draw(*cmd_buffer, 4, instances.len() as u32, 0, 0);
+        }
+    }
+}
+
+impl Drop for TextOverlayPass {
+    fn drop(&mut self) {
+        let device = self.device_ref.read();
+
+        unsafe { device.destroy_descriptor_pool(self.descriptor_pool, None) };
+        unsafe { device.destroy_descriptor_set_layout(self.set_layout, None) };
+    }
+}
+
+// R8 coverage of every glyph, staged for the next frame
+fn create_atlas(ctx: &mut Context) -> Result<Image, TextOverlayCreateError> {
+    let extent = vk::Extent3D {
+        width: ATLAS_WIDTH,
+        height: ATLAS_HEIGHT,
+        depth: 1,
+    };
+    let subresource_range = vk::ImageSubresourceRange {
+        aspect_mask: vk::ImageAspectFlags::COLOR,
+        base_mip_level: 0,
+        level_count: 1,
+        base_array_layer: 0,
+        layer_count: 1,
+    };
+    let mut create_info = Image::create_info();
+    create_info.name = "text overlay atlas";
+    create_info.image_info = vk::ImageCreateInfo::default()
+        .extent(extent)
+        .image_type(vk::ImageType::TYPE_2D)
+        .format(vk::Format::R8_UNORM)
+        .mip_levels(1)
+        .array_layers(1)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .tiling(vk::ImageTiling::OPTIMAL)
+        .usage(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE);
+    create_info.image_view_info = vk::ImageViewCreateInfo::default()
+        .view_type(vk::ImageViewType::TYPE_2D)
+        .format(vk::Format::R8_UNORM)
+        .subresource_range(subresource_range);
+    let atlas = create_info.build(ctx)?;
+
+    let staging_belt = ctx.staging_belt();
+    let source = staging_belt.write(&font::rasterize_atlas())?;
+    staging_belt.copy_to_image(
+        source,
+        ImageDestination {
+            image: atlas.state.handle,
+            current_layout: vk::ImageLayout::UNDEFINED,
+            final_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            subresource: vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+            offset: vk::Offset3D::default(),
+            extent,
+        },
+    );
+
+    Ok(atlas)
+}
+
+fn create_instance_buffer(
+    slot: usize,
+    capacity: usize,
+    device_ref: ThreadSafeRwRef<Device>,
+    allocator_ref: ThreadSafeRef<Allocator>,
+) -> Result<Buffer, BufferBuildError> {
+    Buffer::builder((capacity * std::mem::size_of::<GlyphInstance>()) as u64)
+        .with_name(&format!("text overlay glyphs (slot {slot})"))
+        .with_tag(AllocTag::Uniform)
+        .with_usage(vk::BufferUsageFlags::VERTEX_BUFFER)
+        .with_memory_location(gpu_allocator::MemoryLocation::CpuToGpu)
+        .build_internal(device_ref, allocator_ref)
+}
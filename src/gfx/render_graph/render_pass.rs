@@ -1,18 +1,195 @@
 use std::collections::HashMap;
 
 use ash::vk;
+use thiserror::Error;
 
 use crate::{
-    gfx::{device::Device, render_graph::resource::FrameResources},
+    gfx::{
+        device::Device, pipeline::PipelineOutputs, render_graph::resource::FrameResources,
+        swapchain::SurfaceProperties,
+    },
     utils::ThreadSafeRwRef,
 };
 
 use super::resource::{ResourceAccessType, ResourceID};
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AttachmentOps {
+    pub load: vk::AttachmentLoadOp,
+    pub store: vk::AttachmentStoreOp,
+}
+
+impl AttachmentOps {
+    pub fn new(load: vk::AttachmentLoadOp, store: vk::AttachmentStoreOp) -> Self {
+        Self { load, store }
+    }
+
+    pub fn clear_store() -> Self {
+        Self::new(vk::AttachmentLoadOp::CLEAR, vk::AttachmentStoreOp::STORE)
+    }
+
+    pub fn load_store() -> Self {
+        Self::new(vk::AttachmentLoadOp::LOAD, vk::AttachmentStoreOp::STORE)
+    }
+}
+
+impl Default for AttachmentOps {
+    fn default() -> Self {
+        Self::clear_store()
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct DepthStencilAttachment {
+    pub id: ResourceID,
+    pub access_type: ResourceAccessType,
+    pub ops: AttachmentOps,
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct AttachmentInfo {
     pub color_attachments: HashMap<ResourceID, ResourceAccessType>,
-    pub depth_stencil_attachment: Option<ResourceID>,
+    pub depth_stencil_attachment: Option<DepthStencilAttachment>,
+    /// Resources read through samplers, transitioned to `SHADER_READ_ONLY_OPTIMAL` before the pass.
+    pub sampled_images: Vec<ResourceID>,
+
+    // depth-stencil attachments declared while the slot was already taken, kept to be reported
+    // when the render graph is bound instead of being silently dropped
+    overridden_depth_stencil_attachments: Vec<ResourceID>,
+}
+
+#[derive(Debug, Error)]
+pub enum AttachmentValidationError {
+    #[error("more than one depth-stencil attachment declared ({0:?} and {1:?})")]
+    MultipleDepthStencilAttachments(ResourceID, ResourceID),
+
+    #[error("resource {0:?} is declared as both a color and a depth-stencil attachment")]
+    ColorAndDepthStencil(ResourceID),
+
+    #[error("read-only depth-stencil attachment {0:?} cannot be cleared on load")]
+    ClearedReadOnlyDepthStencil(ResourceID),
+
+    #[error("resource {0:?} is both sampled and used as an attachment by the same pass")]
+    SampledAttachment(ResourceID),
+
+    #[error("both views of the swapchain color image are used by the same pass")]
+    AliasedSwapchainViews,
+}
+
+impl AttachmentInfo {
+    pub fn add_color_attachment(&mut self, resource: ResourceID, access_type: ResourceAccessType) {
+        self.color_attachments.insert(resource, access_type);
+    }
+
+    pub fn add_sampled_image(&mut self, resource: ResourceID) {
+        if !self.sampled_images.contains(&resource) {
+            self.sampled_images.push(resource);
+        }
+    }
+
+    pub fn set_depth_stencil_attachment(
+        &mut self,
+        resource: ResourceID,
+        access_type: ResourceAccessType,
+        ops: AttachmentOps,
+    ) {
+        if let Some(previous) = self.depth_stencil_attachment
+            && previous.id != resource
+        {
+            self.overridden_depth_stencil_attachments.push(previous.id);
+        }
+
+        self.depth_stencil_attachment = Some(DepthStencilAttachment {
+            id: resource,
+            access_type,
+            ops,
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.color_attachments.is_empty() && self.depth_stencil_attachment.is_none()
+    }
+
+    /// Whether the resource is declared in any way, as an attachment or a sampled image.
+    pub fn references(&self, id: &ResourceID) -> bool {
+        self.color_attachments.contains_key(id)
+            || self
+                .depth_stencil_attachment
+                .is_some_and(|depth_stencil| depth_stencil.id == *id)
+            || self.sampled_images.contains(id)
+            || self.overridden_depth_stencil_attachments.contains(id)
+    }
+
+    pub fn written_resources(&self) -> impl Iterator<Item = ResourceID> + '_ {
+        let written_colors = self
+            .color_attachments
+            .iter()
+            .filter(|(_, access_type)| !matches!(access_type, ResourceAccessType::ReadOnly))
+            .map(|(&id, _)| id);
+        let written_depth_stencil = self
+            .depth_stencil_attachment
+            .filter(|depth_stencil| {
+                !matches!(depth_stencil.access_type, ResourceAccessType::ReadOnly)
+            })
+            .map(|depth_stencil| depth_stencil.id);
+
+        written_colors.chain(written_depth_stencil)
+    }
+
+    pub fn validate(&self) -> Result<(), AttachmentValidationError> {
+        // an image cannot be in an attachment and a shader read-only layout at the same time
+        if let Some(&sampled) = self.sampled_images.iter().find(|id| {
+            self.color_attachments.contains_key(id)
+                || self
+                    .depth_stencil_attachment
+                    .is_some_and(|depth_stencil| depth_stencil.id == **id)
+        }) {
+            return Err(AttachmentValidationError::SampledAttachment(sampled));
+        }
+
+        let mut used_resources = self.color_attachments.keys().chain(&self.sampled_images);
+        if used_resources
+            .clone()
+            .any(|id| id == &ResourceID::SwapchainColorAttachment)
+            && used_resources.any(|id| id == &ResourceID::SwapchainColorAttachmentUnorm)
+        {
+            return Err(AttachmentValidationError::AliasedSwapchainViews);
+        }
+
+        if let Some(depth_stencil) = &self.depth_stencil_attachment {
+            if let Some(&overridden) = self.overridden_depth_stencil_attachments.first() {
+                return Err(AttachmentValidationError::MultipleDepthStencilAttachments(
+                    overridden,
+                    depth_stencil.id,
+                ));
+            }
+
+            if self.color_attachments.contains_key(&depth_stencil.id) {
+                return Err(AttachmentValidationError::ColorAndDepthStencil(
+                    depth_stencil.id,
+                ));
+            }
+
+            if matches!(depth_stencil.access_type, ResourceAccessType::ReadOnly)
+                && depth_stencil.ops.load == vk::AttachmentLoadOp::CLEAR
+            {
+                return Err(AttachmentValidationError::ClearedReadOnlyDepthStencil(
+                    depth_stencil.id,
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Depth bias factors applied right after the pass begins rendering.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DepthBias {
+    pub constant_factor: f32,
+    /// Only honored if the `depthBiasClamp` feature is enabled, must be 0.0 otherwise.
+    pub clamp: f32,
+    pub slope_factor: f32,
 }
 
 pub trait RenderPass {
@@ -25,6 +202,109 @@ pub trait RenderPass {
         cmd_buffer: &vk::CommandBuffer,
         device_ref: ThreadSafeRwRef<Device>,
     );
+
+    /// Every pipeline bound by the pass must have been built with
+    /// [`dynamic_depth_bias`](crate::gfx::pipeline::GraphicsPipelineBuilder::dynamic_depth_bias)
+    /// if this returns a value.
+    fn depth_bias(&self) -> Option<DepthBias> {
+        None
+    }
+
+    /// Passes recording transfer commands, like blits, must return `false`: their attachments are
+    /// transitioned as usual, but no rendering scope is opened around their commands.
+    fn begins_rendering(&self) -> bool {
+        true
+    }
+
+    /// Compute passes returning `true` run on the async compute queue when the device has one,
+    /// overlapping with the graphics passes submitted before them, and are recorded like the
+    /// others otherwise. They cannot declare attachments, the buffers and images they access
+    /// must be shared with the graphics queue, see
+    /// [`Device::async_compute_queue_families`], and their writes may overlap with the previous
+    /// frames still in flight.
+    fn runs_on_async_compute(&self) -> bool {
+        false
+    }
+
+    /// Outputs of the pipelines bound by the pass, a warning is logged when binding the render
+    /// graph if their color output count differs from the declared color attachments.
+    fn pipeline_outputs(&self) -> Vec<PipelineOutputs> {
+        vec![]
+    }
+
+    /// Called after a swapchain recreation changed the format, color space or extent of the
+    /// presented images, before the next frame is recorded.
+    fn on_surface_changed(
+        &mut self,
+        _new_properties: &SurfaceProperties,
+        _device_ref: ThreadSafeRwRef<Device>,
+    ) {
+    }
+}
+
+// keeps passes boxed before being pushed to a render graph working, at the cost of a second box
+impl<P: RenderPass + ?Sized> RenderPass for Box<P> {
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    fn attachment_infos(&self) -> &AttachmentInfo {
+        (**self).attachment_infos()
+    }
+
+    fn record_commands(
+        &mut self,
+        resources: &mut FrameResources,
+        cmd_buffer: &vk::CommandBuffer,
+        device_ref: ThreadSafeRwRef<Device>,
+    ) {
+        (**self).record_commands(resources, cmd_buffer, device_ref);
+    }
+
+    fn depth_bias(&self) -> Option<DepthBias> {
+        (**self).depth_bias()
+    }
+
+    fn begins_rendering(&self) -> bool {
+        (**self).begins_rendering()
+    }
+
+    fn runs_on_async_compute(&self) -> bool {
+        (**self).runs_on_async_compute()
+    }
+
+    fn pipeline_outputs(&self) -> Vec<PipelineOutputs> {
+        (**self).pipeline_outputs()
+    }
+
+    fn on_surface_changed(
+        &mut self,
+        new_properties: &SurfaceProperties,
+        device_ref: ThreadSafeRwRef<Device>,
+    ) {
+        (**self).on_surface_changed(new_properties, device_ref);
+    }
+}
+
+/// Warnings for the pipelines declared by `render_pass` (see [`RenderPass::pipeline_outputs`])
+/// rendering to a different number of color attachments than the pass declares, checked when the
+/// render graph is bound.
+pub(crate) fn color_output_warnings(render_pass: &dyn RenderPass) -> Vec<String> {
+    let color_attachment_count = render_pass.attachment_infos().color_attachments.len();
+
+    render_pass
+        .pipeline_outputs()
+        .into_iter()
+        .filter(|outputs| outputs.color_formats.len() != color_attachment_count)
+        .map(|outputs| {
+            format!(
+                "pipeline \"{}\" of render pass \"{}\" has {} color outputs, but the pass declares {color_attachment_count} color attachments",
+                outputs.pipeline_name,
+                render_pass.name(),
+                outputs.color_formats.len(),
+            )
+        })
+        .collect()
 }
 
 pub type SimpleCommandRecorder<UserData> =
@@ -34,6 +314,8 @@ pub struct SimpleRenderPass<UserData> {
     pub name: String,
     pub attachment_infos: AttachmentInfo,
     pub user_data: UserData,
+    pub depth_bias: Option<DepthBias>,
+    pub pipeline_outputs: Vec<PipelineOutputs>,
 
     pub command_recorder: SimpleCommandRecorder<UserData>,
 }
@@ -44,6 +326,8 @@ impl<UserData> SimpleRenderPass<UserData> {
             name: name.to_owned(),
             user_data,
             attachment_infos: AttachmentInfo::default(),
+            depth_bias: None,
+            pipeline_outputs: vec![],
             command_recorder: Box::new(|_, _, _, _| {}),
         }
     }
@@ -59,13 +343,40 @@ impl<UserData> SimpleRenderPass<UserData> {
         access_type: ResourceAccessType,
     ) -> Self {
         self.attachment_infos
-            .color_attachments
-            .insert(ressource, access_type);
+            .add_color_attachment(ressource, access_type);
         self
     }
 
-    pub fn set_depth_stencil_attachment(mut self, ressource: ResourceID) -> Self {
-        self.attachment_infos.depth_stencil_attachment = Some(ressource);
+    pub fn set_depth_stencil_attachment(
+        mut self,
+        ressource: ResourceID,
+        access_type: ResourceAccessType,
+        ops: AttachmentOps,
+    ) -> Self {
+        self.attachment_infos
+            .set_depth_stencil_attachment(ressource, access_type, ops);
+        self
+    }
+
+    pub fn add_sampled_image(mut self, ressource: ResourceID) -> Self {
+        self.attachment_infos.add_sampled_image(ressource);
+        self
+    }
+
+    /// The bias is set once when the pass begins, every pipeline the recorder binds must declare
+    /// it as dynamic state.
+    pub fn set_depth_bias(mut self, constant_factor: f32, clamp: f32, slope_factor: f32) -> Self {
+        self.depth_bias = Some(DepthBias {
+            constant_factor,
+            clamp,
+            slope_factor,
+        });
+        self
+    }
+
+    /// Declares a pipeline bound by the recorder, see [`RenderPass::pipeline_outputs`].
+    pub fn add_pipeline_outputs(mut self, outputs: PipelineOutputs) -> Self {
+        self.pipeline_outputs.push(outputs);
         self
     }
 
@@ -87,6 +398,14 @@ impl<UserData> RenderPass for SimpleRenderPass<UserData> {
         &self.attachment_infos
     }
 
+    fn depth_bias(&self) -> Option<DepthBias> {
+        self.depth_bias
+    }
+
+    fn pipeline_outputs(&self) -> Vec<PipelineOutputs> {
+        self.pipeline_outputs.clone()
+    }
+
     fn record_commands(
         &mut self,
         resources: &mut FrameResources,
@@ -96,3 +415,185 @@ impl<UserData> RenderPass for SimpleRenderPass<UserData> {
         (self.command_recorder)(&mut self.user_data, resources, cmd_buffer, device_ref);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn resource(n: u128) -> ResourceID {
+        ResourceID::Other(Uuid::from_u128(n))
+    }
+
+    fn color_and_depth() -> AttachmentInfo {
+        let mut info = AttachmentInfo::default();
+        info.add_color_attachment(resource(1), ResourceAccessType::WriteOnly);
+        info.set_depth_stencil_attachment(
+            resource(2),
+            ResourceAccessType::ReadWrite,
+            AttachmentOps::clear_store(),
+        );
+        info
+    }
+
+    #[test]
+    fn valid_declarations_pass() {
+        let mut info = color_and_depth();
+        info.add_sampled_image(resource(3));
+        // declaring the same depth-stencil attachment again only updates it
+        info.set_depth_stencil_attachment(
+            resource(2),
+            ResourceAccessType::ReadOnly,
+            AttachmentOps::load_store(),
+        );
+
+        assert!(info.validate().is_ok());
+        assert!(AttachmentInfo::default().validate().is_ok());
+    }
+
+    #[test]
+    fn second_depth_stencil_attachment_is_rejected() {
+        let mut info = color_and_depth();
+        info.set_depth_stencil_attachment(
+            resource(3),
+            ResourceAccessType::WriteOnly,
+            AttachmentOps::clear_store(),
+        );
+
+        assert!(matches!(
+            info.validate(),
+            Err(AttachmentValidationError::MultipleDepthStencilAttachments(first, second))
+                if first == resource(2) && second == resource(3)
+        ));
+    }
+
+    #[test]
+    fn color_and_depth_stencil_use_of_one_resource_is_rejected() {
+        let mut info = color_and_depth();
+        info.add_color_attachment(resource(2), ResourceAccessType::ReadWrite);
+
+        assert!(matches!(
+            info.validate(),
+            Err(AttachmentValidationError::ColorAndDepthStencil(id)) if id == resource(2)
+        ));
+    }
+
+    #[test]
+    fn cleared_read_only_depth_stencil_is_rejected() {
+        let mut info = AttachmentInfo::default();
+        info.set_depth_stencil_attachment(
+            resource(2),
+            ResourceAccessType::ReadOnly,
+            AttachmentOps::clear_store(),
+        );
+
+        assert!(matches!(
+            info.validate(),
+            Err(AttachmentValidationError::ClearedReadOnlyDepthStencil(id)) if id == resource(2)
+        ));
+    }
+
+    #[test]
+    fn sampled_attachments_are_rejected() {
+        let mut sampled_color = color_and_depth();
+        sampled_color.add_sampled_image(resource(1));
+        let mut sampled_depth = color_and_depth();
+        sampled_depth.add_sampled_image(resource(2));
+
+        assert!(matches!(
+            sampled_color.validate(),
+            Err(AttachmentValidationError::SampledAttachment(id)) if id == resource(1)
+        ));
+        assert!(matches!(
+            sampled_depth.validate(),
+            Err(AttachmentValidationError::SampledAttachment(id)) if id == resource(2)
+        ));
+    }
+
+    #[test]
+    fn both_swapchain_views_in_one_pass_are_rejected() {
+        let mut as_attachments = AttachmentInfo::default();
+        as_attachments.add_color_attachment(
+            ResourceID::SwapchainColorAttachment,
+            ResourceAccessType::WriteOnly,
+        );
+        as_attachments.add_color_attachment(
+            ResourceID::SwapchainColorAttachmentUnorm,
+            ResourceAccessType::WriteOnly,
+        );
+        let mut sampled = AttachmentInfo::default();
+        sampled.add_color_attachment(
+            ResourceID::SwapchainColorAttachmentUnorm,
+            ResourceAccessType::WriteOnly,
+        );
+        sampled.add_sampled_image(ResourceID::SwapchainColorAttachment);
+
+        assert!(matches!(
+            as_attachments.validate(),
+            Err(AttachmentValidationError::AliasedSwapchainViews)
+        ));
+        assert!(matches!(
+            sampled.validate(),
+            Err(AttachmentValidationError::AliasedSwapchainViews)
+        ));
+    }
+
+    fn outputs(color_formats: &[vk::Format], depth_format: Option<vk::Format>) -> PipelineOutputs {
+        PipelineOutputs {
+            pipeline_name: "test pipeline".to_owned(),
+            color_formats: color_formats.to_vec(),
+            depth_format,
+        }
+    }
+
+    #[test]
+    fn depth_only_pipelines_match_depth_only_passes() {
+        let pass = SimpleRenderPass::new("depth pre-pass", ())
+            .set_depth_stencil_attachment(
+                resource(2),
+                ResourceAccessType::WriteOnly,
+                AttachmentOps::clear_store(),
+            )
+            .add_pipeline_outputs(outputs(&[], Some(vk::Format::D32_SFLOAT)));
+
+        assert!(color_output_warnings(&pass).is_empty());
+    }
+
+    #[test]
+    fn color_only_pipelines_match_color_only_passes() {
+        let pass = SimpleRenderPass::new("post process", ())
+            .add_color_attachment(resource(1), ResourceAccessType::WriteOnly)
+            .add_color_attachment(resource(3), ResourceAccessType::WriteOnly)
+            .add_pipeline_outputs(outputs(
+                &[vk::Format::R8G8B8A8_UNORM, vk::Format::R16G16B16A16_SFLOAT],
+                None,
+            ));
+
+        assert!(color_output_warnings(&pass).is_empty());
+    }
+
+    #[test]
+    fn color_output_count_mismatches_are_warned() {
+        let pass = SimpleRenderPass::new("forward", ())
+            .add_color_attachment(resource(1), ResourceAccessType::WriteOnly)
+            .add_pipeline_outputs(outputs(&[vk::Format::R8G8B8A8_UNORM], None))
+            // renders nothing to the color attachment
+            .add_pipeline_outputs(outputs(&[], Some(vk::Format::D32_SFLOAT)));
+
+        assert_eq!(
+            color_output_warnings(&pass),
+            [
+                "pipeline \"test pipeline\" of render pass \"forward\" has 0 color outputs, but the pass declares 1 color attachments"
+            ]
+        );
+    }
+
+    #[test]
+    fn passes_without_pipeline_declarations_are_not_checked() {
+        let pass = SimpleRenderPass::new("custom", ())
+            .add_color_attachment(resource(1), ResourceAccessType::WriteOnly);
+
+        assert!(color_output_warnings(&pass).is_empty());
+    }
+}
@@ -4,20 +4,47 @@ use ash::vk;
 use thiserror::Error;
 use uuid::Uuid;
 
-use crate::gfx::{
-    context::Context,
-    image::{Image, ImageBuildError, ImageCreateInfo, ImageState},
-    swapchain,
+use crate::{
+    gfx::{
+        allocator::Allocator,
+        buffer::BufferBuilder,
+        context::Context,
+        deferred::{DeferredBuffer, DeferredImage, DeferredResourceQueue},
+        deletion_queue::DeletionQueue,
+        device::Device,
+        frame_constants::FRAME_CONSTANTS_SET,
+        image::{Image, ImageBuildError, ImageCreateInfo, ImageState},
+        swapchain,
+    },
+    utils::{ThreadSafeRef, ThreadSafeRwRef},
 };
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum ResourceID {
     SwapchainColorAttachment,
+    /// The swapchain color image viewed as UNORM when its format is sRGB, so that colors authored
+    /// in sRGB, like most UI, are written as is instead of being encoded a second time.
+    ///
+    /// Without a UNORM view, this is the same view as [`Self::SwapchainColorAttachment`], and the
+    /// built-in UI passes convert their colors themselves.
+    SwapchainColorAttachmentUnorm,
     SwapchainDSAttachment,
     Other(Uuid),
 }
 
-#[derive(Debug, Copy, Clone)]
+impl ResourceID {
+    /// The other resource viewing the same image, if any.
+    pub(crate) fn swapchain_color_alias(&self) -> Option<ResourceID> {
+        match self {
+            Self::SwapchainColorAttachment => Some(Self::SwapchainColorAttachmentUnorm),
+            Self::SwapchainColorAttachmentUnorm => Some(Self::SwapchainColorAttachment),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ResourceAccessType {
     ReadOnly,
     WriteOnly,
@@ -27,9 +54,34 @@ pub enum ResourceAccessType {
 #[derive(Debug, Copy, Clone)]
 pub enum AttachmentSize {
     SwapchainBased,
+    /// The swapchain extent scaled by this factor and by the
+    /// [render scale](crate::gfx::context::Context::set_render_scale), at least 1 pixel wide.
+    SwapchainRelative(f32),
     Custom(vk::Extent3D),
 }
 
+impl AttachmentSize {
+    pub fn resolve(&self, swapchain_extent: vk::Extent2D, render_scale: f32) -> vk::Extent3D {
+        match *self {
+            Self::SwapchainBased => swapchain_extent.into(),
+            Self::SwapchainRelative(factor) => {
+                let scale =
+                    |size: u32| ((size as f32 * factor * render_scale).round() as u32).max(1);
+                vk::Extent3D {
+                    width: scale(swapchain_extent.width),
+                    height: scale(swapchain_extent.height),
+                    depth: 1,
+                }
+            }
+            Self::Custom(extent) => extent,
+        }
+    }
+
+    pub fn depends_on_swapchain(&self) -> bool {
+        !matches!(self, Self::Custom(_))
+    }
+}
+
 #[derive(Debug)]
 pub struct ImageAttachmentInfo {
     pub(crate) id: ResourceID,
@@ -110,7 +162,10 @@ impl ImageAttachment {
         attachment_info: ImageAttachmentInfo,
         ctx: &mut Context,
     ) -> Result<Self, ImageAttachmentCreateError> {
-        let image = ImageCreateInfo::from_attachment_info(&attachment_info).build(ctx)?;
+        let extent = attachment_info
+            .size
+            .resolve(ctx.swapchain_extent(), ctx.render_scale());
+        let image = ImageCreateInfo::from_attachment_info(&attachment_info, extent).build(ctx)?;
 
         Ok(Self {
             image,
@@ -142,7 +197,7 @@ impl ResourceInfoRegistry {
         info: ImageAttachmentInfo,
     ) -> Result<ResourceID, ResourceInfoInsertError> {
         let uuid = match info.id {
-            ResourceID::SwapchainColorAttachment => {
+            ResourceID::SwapchainColorAttachment | ResourceID::SwapchainColorAttachmentUnorm => {
                 unreachable!("Only a local resource can be added")
             }
             ResourceID::SwapchainDSAttachment => unreachable!("Only a local resource can be added"),
@@ -156,6 +211,14 @@ impl ResourceInfoRegistry {
         }
     }
 
+    /// Swapchain attachments are not part of any registry and always return `None`.
+    pub fn image_attachment_info(&self, id: &ResourceID) -> Option<&ImageAttachmentInfo> {
+        match id {
+            ResourceID::Other(uuid) => self.infos.get(uuid),
+            _ => None,
+        }
+    }
+
     pub(crate) fn create_resources(
         self,
         ctx: &mut Context,
@@ -195,31 +258,230 @@ impl GraphResourceRegistry {
         self.attachments.get(uuid)
     }
 
+    /// Recreates the attachments accepted by `filter` whose resolved size changed, the previous
+    /// images being kept alive until the frames using them are complete.
+    pub(crate) fn resize_attachments(
+        &mut self,
+        filter: impl Fn(&AttachmentSize) -> bool,
+        swapchain_extent: vk::Extent2D,
+        render_scale: f32,
+        ctx_refs: (&ThreadSafeRwRef<Device>, &ThreadSafeRef<Allocator>),
+        deletion_queue: &ThreadSafeRef<DeletionQueue>,
+    ) -> Result<(), ImageBuildError> {
+        let (device_ref, allocator_ref) = ctx_refs;
+        for attachment in self.attachments.values_mut() {
+            if !filter(&attachment.info.size) {
+                continue;
+            }
+
+            let extent = attachment.info.size.resolve(swapchain_extent, render_scale);
+            if extent == attachment.image.state.extent {
+                continue;
+            }
+
+            let image = ImageCreateInfo::from_attachment_info(&attachment.info, extent)
+                .build_from_base_structs(device_ref.clone(), allocator_ref.clone())?;
+            let previous = std::mem::replace(&mut attachment.image, image);
+            deletion_queue.lock().defer(previous);
+        }
+
+        Ok(())
+    }
+
     pub fn get_mut(&mut self, uuid: &Uuid) -> Option<&mut ImageAttachment> {
         self.attachments.get_mut(uuid)
     }
 }
 
+/// Counters of the frame being recorded, the same as the context reported to the update preceding
+/// it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FrameInfo {
+    /// Frames rendered before this one.
+    pub index: u64,
+    /// Slot among the [`FRAMES_IN_FLIGHT`](crate::gfx::commands::FRAMES_IN_FLIGHT) ones, e.g. to
+    /// index resources written by the CPU every frame.
+    pub slot: usize,
+    /// Acquired swapchain image, which does not follow the frame slots.
+    pub swapchain_image_index: u32,
+}
+
 pub struct FrameResources<'g, 'sc> {
     graph_resources: &'g mut GraphResourceRegistry,
     swapchain_resources: swapchain::ImageResources<'sc>,
+
+    // render area of the pass currently being recorded
+    render_extent: vk::Extent2D,
+    scissor_stack: ScissorStack,
+    frame_constants_set: vk::DescriptorSet,
+    deferred_resources: ThreadSafeRef<DeferredResourceQueue>,
+    render_scale: f32,
+    frame_info: FrameInfo,
 }
 
 impl<'g, 'sc> FrameResources<'g, 'sc> {
-    pub fn new(
+    pub(crate) fn new(
         graph_resources: &'g mut GraphResourceRegistry,
         swapchain_resources: swapchain::ImageResources<'sc>,
+        frame_constants_set: vk::DescriptorSet,
+        deferred_resources: ThreadSafeRef<DeferredResourceQueue>,
+        render_scale: f32,
+        frame_info: FrameInfo,
     ) -> Self {
+        let render_extent = swapchain_resources.color_image.extent_2d;
+
         Self {
             graph_resources,
             swapchain_resources,
+            render_extent,
+            scissor_stack: ScissorStack::default(),
+            frame_constants_set,
+            deferred_resources,
+            render_scale,
+            frame_info,
         }
     }
 
+    pub fn frame_info(&self) -> FrameInfo {
+        self.frame_info
+    }
+
+    /// Render area of the pass being recorded, the smallest extent among its attachments.
+    pub fn render_extent(&self) -> vk::Extent2D {
+        self.render_extent
+    }
+
+    pub(crate) fn set_render_extent(&mut self, extent: vk::Extent2D) {
+        self.render_extent = extent;
+    }
+
+    /// Checks that the pass popped every scissor it pushed, the stack starts empty for every pass.
+    pub(crate) fn end_pass(&mut self, pass_name: &str) {
+        debug_assert!(
+            self.scissor_stack.is_empty(),
+            "render pass \"{pass_name}\" left {} scissors pushed",
+            self.scissor_stack.len()
+        );
+        self.scissor_stack.clear();
+    }
+
+    /// View through which the resource is rendered to.
+    pub fn view(&self, id: &ResourceID) -> Option<vk::ImageView> {
+        match (id, self.swapchain_resources.color_unorm_view) {
+            (ResourceID::SwapchainColorAttachmentUnorm, Some(unorm_view)) => Some(unorm_view),
+            _ => self.get(id).map(|image| image.view),
+        }
+    }
+
+    /// Format of the [view](Self::view) of the resource, which passes writing colors authored in
+    /// sRGB to an sRGB view must convert to linear first.
+    pub fn view_format(&self, id: &ResourceID) -> Option<vk::Format> {
+        let format = self.get(id)?.format;
+        match (id, self.swapchain_resources.color_unorm_view) {
+            (ResourceID::SwapchainColorAttachmentUnorm, Some(_)) => {
+                Some(swapchain::unorm_variant(format).unwrap_or(format))
+            }
+            _ => Some(format),
+        }
+    }
+
+    pub fn attachment_extent(&self, id: &ResourceID) -> Option<vk::Extent2D> {
+        self.get(id).map(|image| image.extent_2d)
+    }
+
+    /// Viewport covering the whole render area of the current pass, with a [0, 1] depth range.
+    pub fn viewport_full(&self) -> vk::Viewport {
+        vk::Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: self.render_extent.width as f32,
+            height: self.render_extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        }
+    }
+
+    pub fn scissor_full(&self) -> vk::Rect2D {
+        vk::Rect2D::default().extent(self.render_extent)
+    }
+
+    /// Scissor on top of the stack, or the whole render area if none was pushed.
+    pub fn current_scissor(&self) -> vk::Rect2D {
+        self.scissor_stack.current(self.scissor_full())
+    }
+
+    /// Restricts rendering to `scissor`, clamped to the current scissor so that it never extends
+    /// it. Every push must be matched by a [`pop_scissor`](Self::pop_scissor) within the pass.
+    pub fn push_scissor(
+        &mut self,
+        scissor: vk::Rect2D,
+        cmd_buffer: &vk::CommandBuffer,
+        device: &Device,
+    ) {
+        let clamped = self.scissor_stack.push(scissor, self.scissor_full());
+
+        unsafe { device.cmd_set_scissor(*cmd_buffer, 0, &[clamped]) };
+    }
+
+    /// Restores the scissor that was current before the last push.
+    pub fn pop_scissor(&mut self, cmd_buffer: &vk::CommandBuffer, device: &Device) {
+        let restored = self.scissor_stack.pop(self.scissor_full());
+
+        unsafe { device.cmd_set_scissor(*cmd_buffer, 0, &[restored]) };
+    }
+
+    /// Binds the frame constants at set 0 of `pipeline_layout`, which must come from a pipeline
+    /// built with
+    /// [`with_frame_constants`](crate::gfx::pipeline::GraphicsPipelineBuilder::with_frame_constants).
+    pub fn bind_frame_constants(
+        &self,
+        cmd_buffer: &vk::CommandBuffer,
+        pipeline_layout: vk::PipelineLayout,
+        device: &Device,
+    ) {
+        unsafe {
+            device.cmd_bind_descriptor_sets(
+                *cmd_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline_layout,
+                FRAME_CONSTANTS_SET,
+                &[self.frame_constants_set],
+                &[],
+            )
+        };
+    }
+
+    /// Queues the creation of a buffer, which happens once the commands of this frame are
+    /// submitted: it can only be used from the next frame on.
+    pub fn request_buffer(&self, builder: BufferBuilder) -> DeferredBuffer {
+        self.deferred_resources.lock().request_buffer(builder)
+    }
+
+    /// Queues the creation of an image like [`Self::request_buffer`] does. Sizes depending on the
+    /// swapchain are resolved against its current extent and the current render scale.
+    pub fn request_image(&self, info: ImageAttachmentInfo) -> DeferredImage {
+        let info = match info.size {
+            AttachmentSize::SwapchainBased | AttachmentSize::SwapchainRelative(_) => {
+                let extent = info.size.resolve(
+                    self.swapchain_resources.color_image.extent_2d,
+                    self.render_scale,
+                );
+                info.size(AttachmentSize::Custom(extent))
+            }
+            AttachmentSize::Custom(_) => info,
+        };
+
+        self.deferred_resources.lock().request_image(info)
+    }
+
+    /// Both swapchain color resources give the state of the same image, whose view is the sRGB one
+    /// when the surface format is sRGB, see [`Self::view`].
     pub fn get(&self, id: &ResourceID) -> Option<&ImageState> {
         match id {
-            ResourceID::SwapchainColorAttachment => Some(self.swapchain_resources.color_image),
-            ResourceID::SwapchainDSAttachment => Some(&self.swapchain_resources.depth_image.state),
+            ResourceID::SwapchainColorAttachment | ResourceID::SwapchainColorAttachmentUnorm => {
+                Some(self.swapchain_resources.color_image)
+            }
+            ResourceID::SwapchainDSAttachment => self.swapchain_resources.depth_image.as_deref(),
             ResourceID::Other(uuid) => self
                 .graph_resources
                 .get(uuid)
@@ -229,9 +491,11 @@ impl<'g, 'sc> FrameResources<'g, 'sc> {
 
     pub fn get_mut(&mut self, id: &ResourceID) -> Option<&mut ImageState> {
         match id {
-            ResourceID::SwapchainColorAttachment => Some(&mut self.swapchain_resources.color_image),
+            ResourceID::SwapchainColorAttachment | ResourceID::SwapchainColorAttachmentUnorm => {
+                Some(&mut self.swapchain_resources.color_image)
+            }
             ResourceID::SwapchainDSAttachment => {
-                Some(&mut self.swapchain_resources.depth_image.state)
+                self.swapchain_resources.depth_image.as_deref_mut()
             }
             ResourceID::Other(uuid) => self
                 .graph_resources
@@ -240,3 +504,177 @@ impl<'g, 'sc> FrameResources<'g, 'sc> {
         }
     }
 }
+
+// Scissors pushed by the pass being recorded, already clamped to the render area and to the
+// scissors under them
+#[derive(Debug, Default)]
+struct ScissorStack(Vec<vk::Rect2D>);
+
+impl ScissorStack {
+    fn current(&self, full: vk::Rect2D) -> vk::Rect2D {
+        self.0.last().copied().unwrap_or(full)
+    }
+
+    // returns the clamped scissor
+    fn push(&mut self, scissor: vk::Rect2D, full: vk::Rect2D) -> vk::Rect2D {
+        let clamped = intersect_rects(&self.current(full), &scissor);
+        self.0.push(clamped);
+        clamped
+    }
+
+    // returns the scissor restored
+    fn pop(&mut self, full: vk::Rect2D) -> vk::Rect2D {
+        let popped = self.0.pop();
+        debug_assert!(popped.is_some(), "popped a scissor that was never pushed");
+
+        self.current(full)
+    }
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+// Disjoint rectangles result in an empty one, placed inside `bounds` so that its offset stays valid
+pub(crate) fn intersect_rects(bounds: &vk::Rect2D, rect: &vk::Rect2D) -> vk::Rect2D {
+    let bounds_end = [
+        i64::from(bounds.offset.x) + i64::from(bounds.extent.width),
+        i64::from(bounds.offset.y) + i64::from(bounds.extent.height),
+    ];
+    let rect_end = [
+        i64::from(rect.offset.x) + i64::from(rect.extent.width),
+        i64::from(rect.offset.y) + i64::from(rect.extent.height),
+    ];
+
+    let start_x = i64::from(rect.offset.x).clamp(i64::from(bounds.offset.x), bounds_end[0]);
+    let start_y = i64::from(rect.offset.y).clamp(i64::from(bounds.offset.y), bounds_end[1]);
+    let end_x = rect_end[0].clamp(start_x, bounds_end[0]);
+    let end_y = rect_end[1].clamp(start_y, bounds_end[1]);
+
+    // every value lies within `bounds`, which fits the Vulkan types
+    vk::Rect2D {
+        offset: vk::Offset2D {
+            x: start_x as i32,
+            y: start_y as i32,
+        },
+        extent: vk::Extent2D {
+            width: (end_x - start_x) as u32,
+            height: (end_y - start_y) as u32,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: i32, y: i32, width: u32, height: u32) -> vk::Rect2D {
+        vk::Rect2D {
+            offset: vk::Offset2D { x, y },
+            extent: vk::Extent2D { width, height },
+        }
+    }
+
+    #[test]
+    fn nested_rects_intersect_to_the_inner_one() {
+        let outer = rect(0, 0, 100, 100);
+        let inner = rect(10, 20, 30, 40);
+
+        assert_eq!(intersect_rects(&outer, &inner), inner);
+        assert_eq!(intersect_rects(&inner, &outer), inner);
+        assert_eq!(intersect_rects(&outer, &outer), outer);
+    }
+
+    #[test]
+    fn overlapping_rects_intersect_to_the_shared_area() {
+        let bounds = rect(0, 0, 100, 100);
+
+        assert_eq!(
+            intersect_rects(&bounds, &rect(50, -10, 100, 30)),
+            rect(50, 0, 50, 20)
+        );
+        assert_eq!(
+            intersect_rects(&bounds, &rect(-20, 90, 40, 40)),
+            rect(0, 90, 20, 10)
+        );
+    }
+
+    #[test]
+    fn disjoint_rects_intersect_to_an_empty_rect_inside_the_bounds() {
+        let bounds = rect(10, 10, 100, 100);
+
+        for disjoint in [
+            rect(200, 20, 10, 10),
+            rect(20, -50, 10, 10),
+            rect(-100, -100, 10, 10),
+            rect(i32::MAX - 5, i32::MAX - 5, u32::MAX, u32::MAX),
+        ] {
+            let intersection = intersect_rects(&bounds, &disjoint);
+            assert_eq!(intersection.extent.width * intersection.extent.height, 0);
+            assert_eq!(intersect_rects(&bounds, &intersection), intersection);
+        }
+    }
+
+    #[test]
+    fn edge_touching_rects_intersect_to_an_empty_rect() {
+        let bounds = rect(0, 0, 100, 100);
+
+        let right = intersect_rects(&bounds, &rect(100, 0, 50, 100));
+        assert_eq!(right, rect(100, 0, 0, 100));
+        let below = intersect_rects(&bounds, &rect(0, 100, 100, 50));
+        assert_eq!(below, rect(0, 100, 100, 0));
+        // sharing an edge from the inside keeps the whole rect
+        assert_eq!(
+            intersect_rects(&bounds, &rect(90, 0, 10, 100)),
+            rect(90, 0, 10, 100)
+        );
+    }
+
+    #[test]
+    fn pushed_scissors_are_clamped_to_the_ones_under_them() {
+        let full = rect(0, 0, 800, 600);
+        let mut scissors = ScissorStack::default();
+        assert_eq!(scissors.current(full), full);
+
+        assert_eq!(
+            scissors.push(rect(-10, 100, 400, 400), full),
+            rect(0, 100, 390, 400)
+        );
+        // never extends the scissor under it
+        assert_eq!(
+            scissors.push(rect(0, 0, 800, 600), full),
+            rect(0, 100, 390, 400)
+        );
+        assert_eq!(
+            scissors.push(rect(1000, 0, 10, 10), full).extent,
+            vk::Extent2D::default()
+        );
+        assert_eq!(scissors.len(), 3);
+    }
+
+    #[test]
+    fn popping_restores_the_previous_scissor() {
+        let full = rect(0, 0, 800, 600);
+        let mut scissors = ScissorStack::default();
+        let outer = scissors.push(rect(100, 100, 200, 200), full);
+        let inner = rect(150, 150, 20, 20);
+        assert_eq!(scissors.push(inner, full), inner);
+
+        assert_eq!(scissors.pop(full), outer);
+        assert_eq!(scissors.current(full), outer);
+        assert_eq!(scissors.pop(full), full);
+        assert!(scissors.is_empty());
+
+        // the render area changes between passes, an empty stack follows it
+        let smaller = rect(0, 0, 400, 300);
+        assert_eq!(scissors.current(smaller), smaller);
+    }
+}
@@ -0,0 +1,35 @@
+use super::resource::ResourceAccessType;
+
+/// Owned description of the bound render graph, for tools listing its passes without access to the
+/// graph itself.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RenderGraphSnapshot {
+    /// In execution order.
+    pub passes: Vec<PassSnapshot>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PassSnapshot {
+    /// Index of the pass in push order, as used by submission hints.
+    pub id: usize,
+    pub name: String,
+    pub enabled: bool,
+    pub color_attachments: Vec<AttachmentSnapshot>,
+    pub depth_stencil_attachment: Option<AttachmentSnapshot>,
+
+    /// Time spent recording the pass during the last frame.
+    pub cpu_ms: Option<f32>,
+    /// Not measured yet, the graph issues no timestamp queries.
+    pub gpu_ms: Option<f32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AttachmentSnapshot {
+    pub name: String,
+    /// Debug name of the `vk::Format`, e.g. `R8G8B8A8_SRGB`.
+    pub format: String,
+    pub access: ResourceAccessType,
+}
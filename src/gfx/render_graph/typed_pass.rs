@@ -0,0 +1,294 @@
+use ash::vk;
+
+use crate::{
+    gfx::{
+        device::Device, image::ImageState, pipeline::PipelineOutputs, swapchain::SurfaceProperties,
+    },
+    utils::ThreadSafeRwRef,
+};
+
+use super::{
+    render_pass::{AttachmentInfo, AttachmentOps, DepthBias, RenderPass},
+    resource::{FrameResources, ResourceAccessType, ResourceID},
+};
+
+/// State of a resource while the pass using it is recorded, its attachments and sampled images
+/// already being in the layout the pass declared.
+#[derive(Debug, Clone)]
+pub struct ResolvedImage {
+    pub state: ImageState,
+    /// May differ from the view of the state for the UNORM swapchain color view, see
+    /// [`FrameResources::view`].
+    pub view: vk::ImageView,
+    pub view_format: vk::Format,
+}
+
+impl ResolvedImage {
+    fn from_resources(id: &ResourceID, resources: &FrameResources) -> Option<Self> {
+        Some(Self {
+            state: resources.get(id)?.clone(),
+            view: resources.view(id)?,
+            view_format: resources.view_format(id)?,
+        })
+    }
+}
+
+/// A single resource used by a pass, declared to the render graph and resolved every frame.
+pub trait PassResource {
+    type Resolved;
+
+    fn declare(&self, attachment_infos: &mut AttachmentInfo);
+    fn resolve(&self, resources: &FrameResources) -> Option<Self::Resolved>;
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ColorTarget {
+    pub id: ResourceID,
+    pub access_type: ResourceAccessType,
+}
+
+impl ColorTarget {
+    pub fn new(id: ResourceID, access_type: ResourceAccessType) -> Self {
+        Self { id, access_type }
+    }
+}
+
+impl PassResource for ColorTarget {
+    type Resolved = ResolvedImage;
+
+    fn declare(&self, attachment_infos: &mut AttachmentInfo) {
+        attachment_infos.add_color_attachment(self.id, self.access_type);
+    }
+
+    fn resolve(&self, resources: &FrameResources) -> Option<ResolvedImage> {
+        ResolvedImage::from_resources(&self.id, resources)
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct DepthTarget {
+    pub id: ResourceID,
+    pub access_type: ResourceAccessType,
+    pub ops: AttachmentOps,
+}
+
+impl DepthTarget {
+    pub fn new(id: ResourceID, access_type: ResourceAccessType, ops: AttachmentOps) -> Self {
+        Self {
+            id,
+            access_type,
+            ops,
+        }
+    }
+}
+
+impl PassResource for DepthTarget {
+    type Resolved = ResolvedImage;
+
+    fn declare(&self, attachment_infos: &mut AttachmentInfo) {
+        attachment_infos.set_depth_stencil_attachment(self.id, self.access_type, self.ops);
+    }
+
+    fn resolve(&self, resources: &FrameResources) -> Option<ResolvedImage> {
+        ResolvedImage::from_resources(&self.id, resources)
+    }
+}
+
+/// Image read through a sampler, see [`AttachmentInfo::sampled_images`].
+#[derive(Debug, Copy, Clone)]
+pub struct SampledInput {
+    pub id: ResourceID,
+}
+
+impl SampledInput {
+    pub fn new(id: ResourceID) -> Self {
+        Self { id }
+    }
+}
+
+impl PassResource for SampledInput {
+    type Resolved = ResolvedImage;
+
+    fn declare(&self, attachment_infos: &mut AttachmentInfo) {
+        attachment_infos.add_sampled_image(self.id);
+    }
+
+    fn resolve(&self, resources: &FrameResources) -> Option<ResolvedImage> {
+        ResolvedImage::from_resources(&self.id, resources)
+    }
+}
+
+/// Every resource used by a pass, usually implemented through
+/// [`pass_resources!`](crate::pass_resources).
+pub trait PassResources {
+    type Resolved;
+
+    fn declare(&self, attachment_infos: &mut AttachmentInfo);
+    /// `None` when any of the resources is missing from the frame.
+    fn resolve(&self, resources: &FrameResources) -> Option<Self::Resolved>;
+}
+
+/// Defines a struct of [`PassResource`] fields along with the struct they resolve to, and
+/// implements [`PassResources`] for the former by going through every field.
+///
+/// ```ignore
+/// miel::pass_resources! {
+///     pub struct GBufferTargets => GBufferViews {
+///         pub albedo: ColorTarget,
+///         pub depth: DepthTarget,
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! pass_resources {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident => $resolved:ident {
+            $($field_vis:vis $field:ident: $kind:ty),* $(,)?
+        }
+    ) => {
+        $(#[$attr])*
+        $vis struct $name {
+            $($field_vis $field: $kind,)*
+        }
+
+        $vis struct $resolved {
+            $(
+                $field_vis $field:
+                    <$kind as $crate::gfx::render_graph::typed_pass::PassResource>::Resolved,
+            )*
+        }
+
+        impl $crate::gfx::render_graph::typed_pass::PassResources for $name {
+            type Resolved = $resolved;
+
+            fn declare(
+                &self,
+                attachment_infos: &mut $crate::gfx::render_graph::render_pass::AttachmentInfo,
+            ) {
+                $(
+                    $crate::gfx::render_graph::typed_pass::PassResource::declare(
+                        &self.$field,
+                        attachment_infos,
+                    );
+                )*
+            }
+
+            fn resolve(
+                &self,
+                resources: &$crate::gfx::render_graph::resource::FrameResources,
+            ) -> Option<$resolved> {
+                Some($resolved {
+                    $(
+                        $field: $crate::gfx::render_graph::typed_pass::PassResource::resolve(
+                            &self.$field,
+                            resources,
+                        )?,
+                    )*
+                })
+            }
+        }
+    };
+}
+
+/// Render pass whose resources are listed in a [`PassResources`] struct, given already resolved
+/// to [`Self::record`]. Bound to a render graph through a [`TypedPass`].
+pub trait TypedRenderPass {
+    type Resources: PassResources;
+
+    fn name(&self) -> &str;
+    fn resources(&self) -> &Self::Resources;
+
+    fn record(
+        &mut self,
+        resolved: &<Self::Resources as PassResources>::Resolved,
+        resources: &mut FrameResources,
+        cmd_buffer: &vk::CommandBuffer,
+        device_ref: ThreadSafeRwRef<Device>,
+    );
+
+    /// See [`RenderPass::depth_bias`].
+    fn depth_bias(&self) -> Option<DepthBias> {
+        None
+    }
+
+    /// See [`RenderPass::pipeline_outputs`].
+    fn pipeline_outputs(&self) -> Vec<PipelineOutputs> {
+        vec![]
+    }
+
+    /// See [`RenderPass::on_surface_changed`].
+    fn on_surface_changed(
+        &mut self,
+        _new_properties: &SurfaceProperties,
+        _device_ref: ThreadSafeRwRef<Device>,
+    ) {
+    }
+}
+
+/// Declares the resources of a [`TypedRenderPass`] once, when created.
+pub struct TypedPass<P: TypedRenderPass> {
+    pass: P,
+    attachment_infos: AttachmentInfo,
+}
+
+impl<P: TypedRenderPass> TypedPass<P> {
+    pub fn new(pass: P) -> Self {
+        let mut attachment_infos = AttachmentInfo::default();
+        pass.resources().declare(&mut attachment_infos);
+
+        Self {
+            pass,
+            attachment_infos,
+        }
+    }
+
+    pub fn inner(&self) -> &P {
+        &self.pass
+    }
+}
+
+impl<P: TypedRenderPass> RenderPass for TypedPass<P> {
+    fn name(&self) -> &str {
+        self.pass.name()
+    }
+
+    fn attachment_infos(&self) -> &AttachmentInfo {
+        &self.attachment_infos
+    }
+
+    fn record_commands(
+        &mut self,
+        resources: &mut FrameResources,
+        cmd_buffer: &vk::CommandBuffer,
+        device_ref: ThreadSafeRwRef<Device>,
+    ) {
+        // declared resources are checked when the graph is bound, this should not happen
+        let Some(resolved) = self.pass.resources().resolve(resources) else {
+            log::error!(
+                "render pass \"{}\" skipped, some of its resources are missing",
+                self.pass.name()
+            );
+            return;
+        };
+
+        self.pass
+            .record(&resolved, resources, cmd_buffer, device_ref);
+    }
+
+    fn depth_bias(&self) -> Option<DepthBias> {
+        self.pass.depth_bias()
+    }
+
+    fn pipeline_outputs(&self) -> Vec<PipelineOutputs> {
+        self.pass.pipeline_outputs()
+    }
+
+    fn on_surface_changed(
+        &mut self,
+        new_properties: &SurfaceProperties,
+        device_ref: ThreadSafeRwRef<Device>,
+    ) {
+        self.pass.on_surface_changed(new_properties, device_ref);
+    }
+}
@@ -0,0 +1,163 @@
+pub const MIN_RENDER_SCALE: f32 = 0.25;
+pub const MAX_RENDER_SCALE: f32 = 1.0;
+
+// weight of the newest frame time in the smoothed one
+const SMOOTHING: f32 = 0.1;
+// frame times within this fraction of the target leave the scale as is
+const HYSTERESIS: f32 = 0.1;
+const STEP: f32 = 0.05;
+// frames left for the smoothed frame time to reflect a scale change before the next one
+const COOLDOWN_FRAMES: u32 = 30;
+
+pub(crate) fn clamp_render_scale(render_scale: f32) -> f32 {
+    if render_scale.is_finite() {
+        render_scale.clamp(MIN_RENDER_SCALE, MAX_RENDER_SCALE)
+    } else {
+        MAX_RENDER_SCALE
+    }
+}
+
+/// Adjusts the render scale between frames to keep the frame time around a target, lowering it
+/// when frames are too slow and raising it back once there is headroom.
+#[derive(Debug, Clone)]
+pub struct RenderScaleController {
+    target_frame_ms: f32,
+    smoothed_frame_ms: Option<f32>,
+    cooldown: u32,
+}
+
+impl RenderScaleController {
+    pub fn new(target_frame_ms: f32) -> Self {
+        Self {
+            target_frame_ms,
+            smoothed_frame_ms: None,
+            cooldown: 0,
+        }
+    }
+
+    pub fn target_frame_ms(&self) -> f32 {
+        self.target_frame_ms
+    }
+
+    /// Feeds the time taken by the last frame, returns the scale to render the next ones at.
+    pub fn update(&mut self, frame_ms: f32, render_scale: f32) -> f32 {
+        let smoothed = match self.smoothed_frame_ms {
+            Some(smoothed) => smoothed + (frame_ms - smoothed) * SMOOTHING,
+            None => frame_ms,
+        };
+        self.smoothed_frame_ms = Some(smoothed);
+
+        if self.cooldown > 0 {
+            self.cooldown -= 1;
+            return render_scale;
+        }
+
+        let new_scale = if smoothed > self.target_frame_ms * (1.0 + HYSTERESIS) {
+            clamp_render_scale(render_scale - STEP)
+        } else if smoothed < self.target_frame_ms * (1.0 - HYSTERESIS) {
+            clamp_render_scale(render_scale + STEP)
+        } else {
+            render_scale
+        };
+        if new_scale != render_scale {
+            self.cooldown = COOLDOWN_FRAMES;
+        }
+
+        new_scale
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TARGET_MS: f32 = 16.0;
+
+    #[test]
+    fn slow_frames_lower_the_scale() {
+        let mut controller = RenderScaleController::new(TARGET_MS);
+        let scale = controller.update(TARGET_MS * 2.0, 1.0);
+        assert_eq!(scale, 1.0 - STEP);
+
+        // the smoothed frame time is still over the target, but the change has to show first
+        assert_eq!(controller.update(TARGET_MS * 2.0, scale), scale);
+    }
+
+    #[test]
+    fn fast_frames_raise_the_scale() {
+        let mut controller = RenderScaleController::new(TARGET_MS);
+        assert_eq!(controller.update(TARGET_MS / 2.0, 0.5), 0.5 + STEP);
+    }
+
+    #[test]
+    fn frame_times_within_the_hysteresis_keep_the_scale() {
+        for frame_ms in [
+            TARGET_MS * (1.0 + HYSTERESIS * 0.9),
+            TARGET_MS,
+            TARGET_MS * (1.0 - HYSTERESIS * 0.9),
+        ] {
+            let mut controller = RenderScaleController::new(TARGET_MS);
+            for _ in 0..100 {
+                assert_eq!(controller.update(frame_ms, 0.5), 0.5);
+            }
+        }
+    }
+
+    #[test]
+    fn single_spikes_are_smoothed_out() {
+        let mut controller = RenderScaleController::new(TARGET_MS);
+        controller.update(TARGET_MS, 1.0);
+
+        // smoothed to 16 + (32 - 16) * 0.1 = 17.6, within the hysteresis
+        assert_eq!(controller.update(TARGET_MS * 2.0, 1.0), 1.0);
+    }
+
+    #[test]
+    fn scale_changes_wait_for_the_cooldown() {
+        let mut controller = RenderScaleController::new(TARGET_MS);
+        let scale = controller.update(TARGET_MS * 4.0, 1.0);
+        assert_eq!(scale, 1.0 - STEP);
+
+        let mut unchanged_frames = 0;
+        while controller.update(TARGET_MS * 4.0, scale) == scale {
+            unchanged_frames += 1;
+        }
+        assert_eq!(unchanged_frames, COOLDOWN_FRAMES);
+    }
+
+    #[test]
+    fn the_scale_stays_within_bounds() {
+        let mut controller = RenderScaleController::new(TARGET_MS);
+        assert_eq!(
+            controller.update(TARGET_MS * 4.0, MIN_RENDER_SCALE),
+            MIN_RENDER_SCALE
+        );
+        // staying at a bound is no change, there is no cooldown to wait for
+        assert_eq!(
+            controller.update(TARGET_MS * 4.0, MIN_RENDER_SCALE + STEP / 2.0),
+            MIN_RENDER_SCALE
+        );
+
+        let mut controller = RenderScaleController::new(TARGET_MS);
+        assert_eq!(
+            controller.update(TARGET_MS / 4.0, MAX_RENDER_SCALE),
+            MAX_RENDER_SCALE
+        );
+        assert_eq!(
+            controller.update(TARGET_MS / 4.0, MAX_RENDER_SCALE - STEP / 2.0),
+            MAX_RENDER_SCALE
+        );
+    }
+
+    #[test]
+    fn scales_are_clamped() {
+        assert_eq!(clamp_render_scale(0.0), MIN_RENDER_SCALE);
+        assert_eq!(clamp_render_scale(-1.0), MIN_RENDER_SCALE);
+        assert_eq!(clamp_render_scale(MIN_RENDER_SCALE), MIN_RENDER_SCALE);
+        assert_eq!(clamp_render_scale(0.5), 0.5);
+        assert_eq!(clamp_render_scale(MAX_RENDER_SCALE), MAX_RENDER_SCALE);
+        assert_eq!(clamp_render_scale(2.0), MAX_RENDER_SCALE);
+        assert_eq!(clamp_render_scale(f32::NAN), MAX_RENDER_SCALE);
+        assert_eq!(clamp_render_scale(f32::INFINITY), MAX_RENDER_SCALE);
+    }
+}
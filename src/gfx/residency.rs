@@ -0,0 +1,493 @@
+use std::collections::HashMap;
+
+use ash::vk;
+
+use super::staging::{ImageDestination, StagingBelt, StagingWriteError};
+
+pub const DEFAULT_UPLOAD_BUDGET: u64 = 4 * 1024 * 1024;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TextureId(u64);
+
+/// A mip level to copy to its texture, the next finer one than those already resident.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MipUpload {
+    pub texture: TextureId,
+    pub mip_level: u32,
+    pub size: u64,
+}
+
+/// A mip level no longer counted as resident, whose memory may be released once no frame in flight
+/// samples it anymore.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MipEviction {
+    pub texture: TextureId,
+    pub mip_level: u32,
+}
+
+/// What to do with the textures this frame, evictions first.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResidencyPlan {
+    pub evictions: Vec<MipEviction>,
+    pub uploads: Vec<MipUpload>,
+}
+
+#[derive(Debug)]
+struct TextureEntry {
+    // indexed by mip level, the first one being the largest
+    mip_sizes: Vec<u64>,
+    // coarsest mips that are never evicted
+    pinned_mips: u32,
+    min_loaded_mip: u32,
+
+    // finest mip requested this frame and its priority
+    requested: Option<(u32, f32)>,
+}
+
+impl TextureEntry {
+    fn mip_count(&self) -> u32 {
+        self.mip_sizes.len() as u32
+    }
+
+    fn eviction_floor(&self) -> u32 {
+        self.mip_count().saturating_sub(self.pinned_mips)
+    }
+
+    fn resident_size(&self) -> u64 {
+        self.mip_sizes[self.min_loaded_mip as usize..].iter().sum()
+    }
+
+    // textures nobody asked for this frame are the first eviction candidates
+    fn priority(&self) -> f32 {
+        self.requested
+            .map_or(f32::NEG_INFINITY, |(_, priority)| priority)
+    }
+}
+
+/// Decides which mip levels of streamed textures are resident, from requests made every frame,
+/// e.g. by the material layer depending on the camera distance.
+///
+/// Only the bookkeeping is done here: the returned [`ResidencyPlan`] is carried out by the caller,
+/// uploads typically through [`stage_mip_upload`], and shaders can clamp their sampling to the
+/// [minimum loaded mip](Self::min_loaded_mip).
+#[derive(Debug)]
+pub struct TextureResidency {
+    textures: HashMap<TextureId, TextureEntry>,
+    next_id: u64,
+
+    upload_budget: u64,
+    memory_budget: Option<u64>,
+}
+
+impl Default for TextureResidency {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TextureResidency {
+    pub fn new() -> Self {
+        Self {
+            textures: HashMap::new(),
+            next_id: 0,
+            upload_budget: DEFAULT_UPLOAD_BUDGET,
+            memory_budget: None,
+        }
+    }
+
+    /// Bytes uploaded per frame. A single mip larger than the budget is still uploaded, alone.
+    pub fn with_upload_budget(mut self, bytes: u64) -> Self {
+        self.upload_budget = bytes;
+        self
+    }
+
+    /// Bytes of resident mips above which the lowest priority fine mips are evicted.
+    pub fn with_memory_budget(mut self, bytes: u64) -> Self {
+        self.memory_budget = Some(bytes);
+        self
+    }
+
+    /// Registers the full mip chain of a texture, of which only the `resident_mips` coarsest are
+    /// considered loaded. These are never evicted.
+    pub fn register(&mut self, mip_sizes: Vec<u64>, resident_mips: u32) -> TextureId {
+        assert!(
+            !mip_sizes.is_empty(),
+            "a streamed texture should have at least one mip"
+        );
+
+        let id = TextureId(self.next_id);
+        self.next_id += 1;
+
+        let mip_count = mip_sizes.len() as u32;
+        let pinned_mips = resident_mips.clamp(1, mip_count);
+        self.textures.insert(
+            id,
+            TextureEntry {
+                mip_sizes,
+                pinned_mips,
+                min_loaded_mip: mip_count - pinned_mips,
+                requested: None,
+            },
+        );
+
+        id
+    }
+
+    pub fn unregister(&mut self, texture: TextureId) {
+        self.textures.remove(&texture);
+    }
+
+    /// Asks for `mip_level` and every coarser one to be resident. Only the finest request of a
+    /// texture is kept every frame, with the highest priority it was requested at.
+    pub fn request(&mut self, texture: TextureId, mip_level: u32, priority: f32) {
+        let Some(entry) = self.textures.get_mut(&texture) else {
+            log::warn!("residency requested for unknown texture {texture:?}");
+            return;
+        };
+
+        let mip_level = mip_level.min(entry.mip_count() - 1);
+        entry.requested = Some(match entry.requested {
+            Some((requested_mip, requested_priority)) => (
+                requested_mip.min(mip_level),
+                requested_priority.max(priority),
+            ),
+            None => (mip_level, priority),
+        });
+    }
+
+    /// Finest mip level resident, `None` for unknown textures.
+    pub fn min_loaded_mip(&self, texture: TextureId) -> Option<u32> {
+        self.textures
+            .get(&texture)
+            .map(|entry| entry.min_loaded_mip)
+    }
+
+    pub fn resident_size(&self) -> u64 {
+        self.textures
+            .values()
+            .map(TextureEntry::resident_size)
+            .sum()
+    }
+
+    /// Plans this frame's evictions and uploads from the requests made since the last call, which
+    /// are then cleared. Mips are considered resident as soon as they are planned.
+    pub fn schedule(&mut self) -> ResidencyPlan {
+        let mut plan = ResidencyPlan::default();
+        let mut resident_size = self.resident_size();
+        if let Some(memory_budget) = self.memory_budget {
+            self.plan_evictions(memory_budget, &mut resident_size, &mut plan);
+        }
+        self.plan_uploads(resident_size, &mut plan);
+
+        for entry in self.textures.values_mut() {
+            entry.requested = None;
+        }
+
+        plan
+    }
+
+    fn plan_evictions(
+        &mut self,
+        memory_budget: u64,
+        resident_size: &mut u64,
+        plan: &mut ResidencyPlan,
+    ) {
+        let mut candidates = self
+            .textures
+            .iter()
+            .map(|(&id, entry)| (entry.priority(), id))
+            .collect::<Vec<_>>();
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+
+        // mips finer than requested go first, then those still needed, lowest priorities first
+        for evict_needed in [false, true] {
+            for &(_, id) in &candidates {
+                if *resident_size <= memory_budget {
+                    return;
+                }
+
+                let entry = self.textures.get_mut(&id).unwrap();
+                let floor = match (evict_needed, entry.requested) {
+                    (false, Some((requested_mip, _))) => requested_mip.min(entry.eviction_floor()),
+                    _ => entry.eviction_floor(),
+                };
+                while *resident_size > memory_budget && entry.min_loaded_mip < floor {
+                    plan.evictions.push(MipEviction {
+                        texture: id,
+                        mip_level: entry.min_loaded_mip,
+                    });
+                    *resident_size -= entry.mip_sizes[entry.min_loaded_mip as usize];
+                    entry.min_loaded_mip += 1;
+                }
+            }
+        }
+    }
+
+    fn plan_uploads(&mut self, mut resident_size: u64, plan: &mut ResidencyPlan) {
+        let mut requests = self
+            .textures
+            .iter()
+            .filter_map(|(&id, entry)| {
+                let (requested_mip, priority) = entry.requested?;
+                (requested_mip < entry.min_loaded_mip).then_some((priority, id))
+            })
+            .collect::<Vec<_>>();
+        requests.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+
+        let mut uploaded = 0;
+        'requests: for (_, id) in requests {
+            let entry = self.textures.get_mut(&id).unwrap();
+            let (requested_mip, _) = entry.requested.unwrap();
+
+            // residency stays contiguous, from the coarsest mip down to the finest loaded one
+            while entry.min_loaded_mip > requested_mip {
+                let mip_level = entry.min_loaded_mip - 1;
+                let size = entry.mip_sizes[mip_level as usize];
+
+                let fits_frame = uploaded == 0 || uploaded + size <= self.upload_budget;
+                let fits_memory = self
+                    .memory_budget
+                    .is_none_or(|memory_budget| resident_size + size <= memory_budget);
+                if !fits_frame {
+                    break 'requests;
+                }
+                if !fits_memory {
+                    break;
+                }
+
+                plan.uploads.push(MipUpload {
+                    texture: id,
+                    mip_level,
+                    size,
+                });
+                uploaded += size;
+                resident_size += size;
+                entry.min_loaded_mip = mip_level;
+            }
+        }
+    }
+}
+
+/// Writes the texels of a mip level to the staging belt and queues their copy to `image`, the
+/// whole level being replaced.
+pub fn stage_mip_upload(
+    staging_belt: &mut StagingBelt,
+    texels: &[u8],
+    image: vk::Image,
+    base_extent: vk::Extent3D,
+    aspect_mask: vk::ImageAspectFlags,
+    mip_level: u32,
+) -> Result<(), StagingWriteError> {
+    let staged = staging_belt.write(texels)?;
+    let mip_extent = |size: u32| (size >> mip_level).max(1);
+    staging_belt.copy_to_image(
+        staged,
+        ImageDestination {
+            image,
+            // the previous content of the level is discarded
+            current_layout: vk::ImageLayout::UNDEFINED,
+            final_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            subresource: vk::ImageSubresourceLayers::default()
+                .aspect_mask(aspect_mask)
+                .mip_level(mip_level)
+                .base_array_layer(0)
+                .layer_count(1),
+            offset: vk::Offset3D::default(),
+            extent: vk::Extent3D {
+                width: mip_extent(base_extent.width),
+                height: mip_extent(base_extent.height),
+                depth: mip_extent(base_extent.depth),
+            },
+        },
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upload(texture: TextureId, mip_level: u32, size: u64) -> MipUpload {
+        MipUpload {
+            texture,
+            mip_level,
+            size,
+        }
+    }
+
+    fn eviction(texture: TextureId, mip_level: u32) -> MipEviction {
+        MipEviction { texture, mip_level }
+    }
+
+    #[test]
+    fn only_the_coarsest_mips_are_resident_at_first() {
+        let mut residency = TextureResidency::new();
+        let texture = residency.register(vec![64, 16, 4, 1], 2);
+        // at least one mip is always resident
+        let at_least_one = residency.register(vec![64, 16], 0);
+
+        assert_eq!(residency.min_loaded_mip(texture), Some(2));
+        assert_eq!(residency.min_loaded_mip(at_least_one), Some(1));
+        assert_eq!(residency.resident_size(), 5 + 16);
+    }
+
+    #[test]
+    fn requests_keep_the_finest_mip_and_highest_priority() {
+        let mut residency = TextureResidency::new();
+        let texture = residency.register(vec![64, 16, 4, 1], 1);
+
+        residency.request(texture, 2, 1.0);
+        residency.request(texture, 1, 0.5);
+        residency.request(texture, 3, 3.0);
+        assert_eq!(residency.textures[&texture].requested, Some((1, 3.0)));
+
+        residency.schedule();
+        assert_eq!(residency.textures[&texture].requested, None);
+        // nothing is uploaded without a request
+        assert_eq!(residency.schedule(), ResidencyPlan::default());
+
+        residency.request(texture, 10, 1.0);
+        assert_eq!(residency.textures[&texture].requested, Some((3, 1.0)));
+    }
+
+    #[test]
+    fn uploads_follow_priority_then_registration_order() {
+        let mut residency = TextureResidency::new();
+        let first = residency.register(vec![64, 16, 4, 1], 1);
+        let second = residency.register(vec![64, 16, 4, 1], 1);
+        let third = residency.register(vec![64, 16, 4, 1], 1);
+
+        residency.request(third, 2, 1.0);
+        residency.request(first, 1, 1.0);
+        residency.request(second, 2, 5.0);
+        let plan = residency.schedule();
+
+        assert!(plan.evictions.is_empty());
+        // each texture is loaded mip by mip, from coarse to fine
+        assert_eq!(
+            plan.uploads,
+            [
+                upload(second, 2, 4),
+                upload(first, 2, 4),
+                upload(first, 1, 16),
+                upload(third, 2, 4),
+            ]
+        );
+        assert_eq!(residency.min_loaded_mip(first), Some(1));
+    }
+
+    #[test]
+    fn upload_budget_defers_the_rest_to_later_frames() {
+        let mut residency = TextureResidency::new().with_upload_budget(20);
+        let texture = residency.register(vec![64, 16, 4, 1], 1);
+
+        residency.request(texture, 0, 1.0);
+        assert_eq!(
+            residency.schedule().uploads,
+            [upload(texture, 2, 4), upload(texture, 1, 16)]
+        );
+
+        // a mip larger than the budget still goes through, alone
+        residency.request(texture, 0, 1.0);
+        assert_eq!(residency.schedule().uploads, [upload(texture, 0, 64)]);
+        assert_eq!(residency.min_loaded_mip(texture), Some(0));
+    }
+
+    #[test]
+    fn lower_priorities_never_jump_ahead_of_a_deferred_upload() {
+        let mut residency = TextureResidency::new().with_upload_budget(10);
+        let high = residency.register(vec![16, 4, 1], 1);
+        let low = residency.register(vec![1, 1], 1);
+
+        residency.request(high, 0, 10.0);
+        residency.request(low, 0, 1.0);
+
+        // the low priority mip would fit in what is left, but the frame is over once one doesn't
+        assert_eq!(residency.schedule().uploads, [upload(high, 1, 4)]);
+    }
+
+    #[test]
+    fn low_priority_requests_are_served_once_higher_ones_are_done() {
+        let mut residency = TextureResidency::new().with_upload_budget(4);
+        let high = residency.register(vec![4, 4, 4, 4], 1);
+        let low = residency.register(vec![4, 4], 1);
+
+        let mut served_frame = None;
+        for frame in 0..8 {
+            residency.request(high, 0, 10.0);
+            residency.request(low, 0, 1.0);
+            let plan = residency.schedule();
+            assert_eq!(plan.uploads.len(), 1, "frame {frame}");
+
+            if plan.uploads[0].texture == low {
+                served_frame = Some(frame);
+                break;
+            }
+        }
+
+        assert_eq!(served_frame, Some(3));
+        assert_eq!(residency.min_loaded_mip(high), Some(0));
+        assert_eq!(residency.min_loaded_mip(low), Some(0));
+    }
+
+    #[test]
+    fn memory_budget_skips_to_requests_that_fit() {
+        let mut residency = TextureResidency::new().with_memory_budget(10);
+        let large = residency.register(vec![100, 1], 1);
+        let small = residency.register(vec![2, 1], 1);
+
+        residency.request(large, 0, 10.0);
+        residency.request(small, 0, 1.0);
+        let plan = residency.schedule();
+
+        assert_eq!(plan.uploads, [upload(small, 0, 2)]);
+        assert_eq!(residency.min_loaded_mip(large), Some(1));
+        assert_eq!(residency.resident_size(), 4);
+    }
+
+    #[test]
+    fn evictions_take_unneeded_mips_first_then_lowest_priorities() {
+        let mut residency = TextureResidency::new().with_memory_budget(200);
+        let detailed = residency.register(vec![64, 16, 4, 1], 1);
+        let background = residency.register(vec![64, 16, 4, 1], 1);
+        residency.request(detailed, 0, 1.0);
+        residency.request(background, 0, 1.0);
+        assert_eq!(residency.schedule().uploads.len(), 6);
+        assert_eq!(residency.resident_size(), 170);
+
+        // pinned mips of new textures go over the budget
+        let pinned = residency.register(vec![50], 1);
+        residency.request(detailed, 2, 5.0);
+        residency.request(background, 0, 1.0);
+        let plan = residency.schedule();
+        assert_eq!(plan.evictions, [eviction(detailed, 0)]);
+        assert!(plan.uploads.is_empty());
+
+        // finer mips than requested are not enough anymore, the lowest priority needed ones follow
+        residency.register(vec![100], 1);
+        residency.request(detailed, 2, 5.0);
+        residency.request(background, 0, 1.0);
+        let plan = residency.schedule();
+        assert_eq!(
+            plan.evictions,
+            [eviction(detailed, 1), eviction(background, 0)]
+        );
+        assert!(plan.uploads.is_empty());
+        assert_eq!(residency.resident_size(), 176);
+        assert_eq!(residency.min_loaded_mip(pinned), Some(0));
+    }
+
+    #[test]
+    fn pinned_mips_are_never_evicted() {
+        let mut residency = TextureResidency::new().with_memory_budget(1);
+        let texture = residency.register(vec![64, 16, 4, 1], 2);
+
+        residency.request(texture, 3, 1.0);
+        let plan = residency.schedule();
+
+        assert!(plan.evictions.is_empty());
+        assert_eq!(residency.min_loaded_mip(texture), Some(2));
+        assert_eq!(residency.resident_size(), 5);
+    }
+}
@@ -0,0 +1,139 @@
+use ash::vk;
+
+/// Window resizes reported since the context was created, and the swapchain recreations they
+/// were folded into.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct ResizeStats {
+    pub resize_events: u64,
+    pub swapchain_recreations: u64,
+}
+
+/// Keeps only the latest size reported by the window, e.g. while dragging its corner, to be
+/// applied once at the next frame boundary.
+#[derive(Debug)]
+pub(crate) struct ResizeCoalescer {
+    applied: vk::Extent2D,
+    pending: Option<vk::Extent2D>,
+    stats: ResizeStats,
+}
+
+impl ResizeCoalescer {
+    pub fn new(extent: vk::Extent2D) -> Self {
+        Self {
+            applied: extent,
+            pending: None,
+            stats: ResizeStats::default(),
+        }
+    }
+
+    pub fn request(&mut self, extent: vk::Extent2D) {
+        self.stats.resize_events += 1;
+        // going back to the applied size cancels the resize altogether
+        self.pending = (extent != self.applied).then_some(extent);
+    }
+
+    pub fn pending(&self) -> Option<vk::Extent2D> {
+        self.pending
+    }
+
+    /// Resizes requested while the swapchain was recreated for `extent` stay pending.
+    pub fn mark_applied(&mut self, extent: vk::Extent2D) {
+        self.applied = extent;
+        if self.pending == Some(extent) {
+            self.pending = None;
+        }
+    }
+
+    /// Size the swapchain was last recreated for, or skipped at while it had no area.
+    pub fn applied(&self) -> vk::Extent2D {
+        self.applied
+    }
+
+    pub fn record_recreation(&mut self) {
+        self.stats.swapchain_recreations += 1;
+    }
+
+    pub fn stats(&self) -> ResizeStats {
+        self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extent(width: u32, height: u32) -> vk::Extent2D {
+        vk::Extent2D { width, height }
+    }
+
+    #[test]
+    fn bursts_coalesce_into_the_latest_size() {
+        let mut resizes = ResizeCoalescer::new(extent(800, 600));
+        for width in 801..=900 {
+            resizes.request(extent(width, 600));
+        }
+
+        assert_eq!(resizes.pending(), Some(extent(900, 600)));
+        assert_eq!(resizes.applied(), extent(800, 600));
+
+        resizes.mark_applied(extent(900, 600));
+        resizes.record_recreation();
+        assert_eq!(resizes.pending(), None);
+        assert_eq!(
+            resizes.stats(),
+            ResizeStats {
+                resize_events: 100,
+                swapchain_recreations: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn returning_to_the_applied_size_cancels_the_resize() {
+        let mut resizes = ResizeCoalescer::new(extent(800, 600));
+        resizes.request(extent(1024, 768));
+        resizes.request(extent(800, 600));
+
+        assert_eq!(resizes.pending(), None);
+        assert_eq!(resizes.stats().resize_events, 2);
+    }
+
+    #[test]
+    fn minimizing_is_applied_like_any_other_size() {
+        let mut resizes = ResizeCoalescer::new(extent(800, 600));
+        resizes.request(extent(0, 0));
+        assert_eq!(resizes.pending(), Some(extent(0, 0)));
+
+        // skipped rather than recreated, but still the size frames are skipped at
+        resizes.mark_applied(extent(0, 0));
+        assert_eq!(resizes.pending(), None);
+        assert_eq!(resizes.applied(), extent(0, 0));
+
+        // restoring to the size from before has to recreate the swapchain
+        resizes.request(extent(800, 600));
+        assert_eq!(resizes.pending(), Some(extent(800, 600)));
+
+        // a single zero dimension has no area either, and is kept as is
+        resizes.request(extent(800, 0));
+        assert_eq!(resizes.pending(), Some(extent(800, 0)));
+    }
+
+    #[test]
+    fn resizes_stay_pending_until_flushed() {
+        let mut resizes = ResizeCoalescer::new(extent(800, 600));
+        resizes.request(extent(1024, 768));
+        // reading it at a frame boundary does not consume it
+        assert_eq!(resizes.pending(), Some(extent(1024, 768)));
+        assert_eq!(resizes.pending(), Some(extent(1024, 768)));
+
+        // the window kept resizing while the swapchain was recreated for the previous size
+        resizes.request(extent(1280, 720));
+        resizes.mark_applied(extent(1024, 768));
+        assert_eq!(resizes.applied(), extent(1024, 768));
+        assert_eq!(resizes.pending(), Some(extent(1280, 720)));
+
+        resizes.mark_applied(extent(1280, 720));
+        assert_eq!(resizes.pending(), None);
+        assert_eq!(resizes.stats().swapchain_recreations, 0);
+    }
+}
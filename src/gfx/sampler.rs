@@ -0,0 +1,80 @@
+use ash::vk;
+use thiserror::Error;
+
+use crate::utils::ThreadSafeRwRef;
+
+use super::device::Device;
+
+#[derive(Debug, Error)]
+pub enum SamplerCreateError {
+    #[error("vulkan creation of the sampler failed")]
+    VulkanCreation(vk::Result),
+}
+
+pub struct Sampler {
+    pub name: String,
+    pub handle: vk::Sampler,
+
+    // bookkeeping
+    device_ref: ThreadSafeRwRef<Device>,
+}
+
+impl Sampler {
+    pub fn new(
+        name: &str,
+        create_info: &vk::SamplerCreateInfo,
+        device_ref: ThreadSafeRwRef<Device>,
+    ) -> Result<Self, SamplerCreateError> {
+        let device = device_ref.read();
+        let handle = unsafe { device.create_sampler(create_info, None) }
+            .map_err(SamplerCreateError::VulkanCreation)?;
+        device.handle_registry.register(handle, name);
+        drop(device);
+
+        Ok(Self {
+            name: name.to_owned(),
+            handle,
+            device_ref,
+        })
+    }
+
+    /// Depth comparison sampler with linear filtering (hardware PCF). Lookups outside of the
+    /// shadow map compare against `border_color`.
+    pub fn shadow(
+        compare_op: vk::CompareOp,
+        border_color: vk::BorderColor,
+        device_ref: ThreadSafeRwRef<Device>,
+    ) -> Result<Self, SamplerCreateError> {
+        let create_info = vk::SamplerCreateInfo::default()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+            .border_color(border_color)
+            .compare_enable(true)
+            .compare_op(compare_op)
+            .max_lod(vk::LOD_CLAMP_NONE);
+
+        Self::new("shadow sampler", &create_info, device_ref)
+    }
+}
+
+impl std::fmt::Debug for Sampler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sampler")
+            .field("name", &self.name)
+            .field("handle", &self.handle)
+            .finish()
+    }
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        let device = self.device_ref.read();
+
+        device.handle_registry.unregister(self.handle);
+        unsafe { device.destroy_sampler(self.handle, None) };
+    }
+}
@@ -0,0 +1,20 @@
+// Adds the largest bloom level to the scene. Mirrors
+// `miel::gfx::render_graph::passes::bloom::BloomPushConstants`.
+#version 450
+
+layout(set = 0, binding = 0) uniform sampler2D scene;
+layout(set = 0, binding = 1) uniform sampler2D bloom_level;
+
+layout(push_constant) uniform Bloom {
+    vec2 texel_size;
+    float threshold;
+    float intensity; // 0 while disabled
+} bloom;
+
+layout(location = 0) in vec2 uv;
+layout(location = 0) out vec4 color;
+
+void main() {
+    vec4 scene_color = texture(scene, uv);
+    color = vec4(scene_color.rgb + texture(bloom_level, uv).rgb * bloom.intensity, scene_color.a);
+}
@@ -0,0 +1,28 @@
+// Halves the previous bloom level, keeping only what is brighter than the threshold on the first
+// one. Mirrors `miel::gfx::render_graph::passes::bloom::BloomPushConstants`.
+#version 450
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(push_constant) uniform Bloom {
+    vec2 texel_size; // of the source
+    float threshold; // 0 past the first level
+    float intensity;
+} bloom;
+
+layout(location = 0) in vec2 uv;
+layout(location = 0) out vec4 color;
+
+void main() {
+    // four bilinear taps average a 4x4 texel area
+    vec2 offset = bloom.texel_size;
+    vec3 sum = texture(source, uv + vec2(-offset.x, -offset.y)).rgb
+             + texture(source, uv + vec2(offset.x, -offset.y)).rgb
+             + texture(source, uv + vec2(-offset.x, offset.y)).rgb
+             + texture(source, uv + vec2(offset.x, offset.y)).rgb;
+    vec3 average = sum * 0.25;
+
+    float brightness = max(average.r, max(average.g, average.b));
+    float contribution = max(brightness - bloom.threshold, 0.0) / max(brightness, 1e-4);
+    color = vec4(average * contribution, 1.0);
+}
@@ -0,0 +1,29 @@
+// Blurs the smaller bloom level over the larger one, added to it through additive blending.
+// Mirrors `miel::gfx::render_graph::passes::bloom::BloomPushConstants`.
+#version 450
+
+layout(set = 0, binding = 0) uniform sampler2D source;
+
+layout(push_constant) uniform Bloom {
+    vec2 texel_size; // of the source
+    float threshold;
+    float intensity;
+} bloom;
+
+layout(location = 0) in vec2 uv;
+layout(location = 0) out vec4 color;
+
+void main() {
+    // 3x3 tent filter
+    vec2 offset = bloom.texel_size;
+    vec3 sum = texture(source, uv).rgb * 4.0
+             + texture(source, uv + vec2(-offset.x, 0.0)).rgb * 2.0
+             + texture(source, uv + vec2(offset.x, 0.0)).rgb * 2.0
+             + texture(source, uv + vec2(0.0, -offset.y)).rgb * 2.0
+             + texture(source, uv + vec2(0.0, offset.y)).rgb * 2.0
+             + texture(source, uv + vec2(-offset.x, -offset.y)).rgb
+             + texture(source, uv + vec2(offset.x, -offset.y)).rgb
+             + texture(source, uv + vec2(-offset.x, offset.y)).rgb
+             + texture(source, uv + vec2(offset.x, offset.y)).rgb;
+    color = vec4(sum / 16.0, 1.0);
+}
@@ -0,0 +1,16 @@
+// Engine-managed frame constants, mirrors `miel::gfx::frame_constants::FrameConstants`.
+// Bound at set 0 by pipelines built with `GraphicsPipelineBuilder::with_frame_constants`.
+#ifndef MIEL_FRAME_CONSTANTS_GLSL
+#define MIEL_FRAME_CONSTANTS_GLSL
+
+layout(std140, set = 0, binding = 0) uniform FrameConstants {
+    mat4 view;
+    mat4 projection;
+    mat4 view_projection;
+    vec4 camera_position; // w is unused
+    vec2 extent;          // swapchain extent in pixels
+    float time;           // seconds, as provided by the application
+    uint frame_index;
+} frame;
+
+#endif
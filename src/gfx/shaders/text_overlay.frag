@@ -0,0 +1,12 @@
+// Glyph coverage is read from the red channel of the atlas and scales the alpha of the text.
+#version 450
+
+layout(set = 0, binding = 0) uniform sampler2D atlas;
+
+layout(location = 0) in vec2 uv;
+layout(location = 1) in vec4 glyph_color;
+layout(location = 0) out vec4 color;
+
+void main() {
+    color = vec4(glyph_color.rgb, glyph_color.a * texture(atlas, uv).r);
+}
@@ -0,0 +1,27 @@
+// Expands each glyph instance into a quad, drawn as a 4 vertex triangle strip. Mirrors
+// `miel::gfx::render_graph::passes::text_overlay::GlyphInstance`.
+#version 450
+
+const vec2 GLYPH_SIZE = vec2(8.0, 16.0);
+const uint ATLAS_COLUMNS = 16;
+const vec2 ATLAS_SIZE = vec2(128.0, 96.0);
+
+layout(push_constant) uniform Target {
+    vec2 size; // in pixels
+} target;
+
+layout(location = 0) in vec2 position; // top-left corner, in pixels
+layout(location = 1) in vec4 color;
+layout(location = 2) in uint cell;
+
+layout(location = 0) out vec2 uv;
+layout(location = 1) out vec4 glyph_color;
+
+void main() {
+    vec2 corner = vec2(gl_VertexIndex & 1, gl_VertexIndex >> 1);
+    vec2 cell_origin = vec2(cell % ATLAS_COLUMNS, cell / ATLAS_COLUMNS) * GLYPH_SIZE;
+
+    uv = (cell_origin + corner * GLYPH_SIZE) / ATLAS_SIZE;
+    glyph_color = color;
+    gl_Position = vec4((position + corner * GLYPH_SIZE) / target.size * 2.0 - 1.0, 0.0, 1.0);
+}
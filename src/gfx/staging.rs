@@ -0,0 +1,284 @@
+use ash::vk;
+use thiserror::Error;
+
+use crate::utils::{ThreadSafeRef, ThreadSafeRwRef};
+
+use super::{
+    allocator::{AllocTag, Allocator},
+    barrier::BarrierBatch,
+    buffer::{Buffer, BufferBuildError, BufferBuilder},
+    commands::is_frame_complete,
+    device::Device,
+};
+
+const DEFAULT_CHUNK_SIZE: u64 = 1024 * 1024;
+// satisfies the buffer offset alignment of buffer to image copies for every texel size
+const WRITE_ALIGNMENT: u64 = 16;
+
+#[derive(Debug, Error)]
+pub enum StagingWriteError {
+    #[error("staging chunk creation failed")]
+    ChunkCreation(#[from] BufferBuildError),
+
+    #[error("staging chunk memory mapping failed")]
+    MemoryMapping,
+}
+
+/// Location of data written to the [`StagingBelt`], only valid until the end of the current frame.
+#[derive(Debug, Copy, Clone)]
+pub struct StagedSlice {
+    chunk_index: usize,
+    offset: u64,
+    size: u64,
+}
+
+impl StagedSlice {
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+}
+
+/// Image region a [`StagedSlice`] is copied to. The image is transitioned from `current_layout` to
+/// `final_layout` around the copy.
+#[derive(Debug, Copy, Clone)]
+pub struct ImageDestination {
+    pub image: vk::Image,
+    pub current_layout: vk::ImageLayout,
+    pub final_layout: vk::ImageLayout,
+    pub subresource: vk::ImageSubresourceLayers,
+    pub offset: vk::Offset3D,
+    pub extent: vk::Extent3D,
+}
+
+enum StagedCopy {
+    Buffer {
+        source: StagedSlice,
+        destination: vk::Buffer,
+        offset: u64,
+    },
+    Image {
+        source: StagedSlice,
+        destination: ImageDestination,
+    },
+}
+
+struct StagingChunk {
+    buffer: Buffer,
+    used: u64,
+    // frame whose submitted copies read it, reusable once that frame has completed
+    in_flight: Option<u64>,
+}
+
+impl StagingChunk {
+    fn remaining(&self) -> u64 {
+        self.buffer.size() - self.used
+    }
+}
+
+/// Reusable CPU-visible chunks streaming small per-frame uploads to GPU-only resources.
+///
+/// Copies requested during a frame are all recorded at the beginning of that frame's command
+/// buffer, before the render graph runs, and chunks are recycled once that frame has completed.
+pub struct StagingBelt {
+    chunk_size: u64,
+    chunks: Vec<StagingChunk>,
+    copies: Vec<StagedCopy>,
+    frame_index: u64,
+
+    // bookkeeping
+    device_ref: ThreadSafeRwRef<Device>,
+    allocator_ref: ThreadSafeRef<Allocator>,
+}
+
+impl StagingBelt {
+    pub(crate) fn new(
+        device_ref: ThreadSafeRwRef<Device>,
+        allocator_ref: ThreadSafeRef<Allocator>,
+    ) -> Self {
+        Self {
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            chunks: vec![],
+            copies: vec![],
+            frame_index: 0,
+            device_ref,
+            allocator_ref,
+        }
+    }
+
+    /// Copies `data` to a staging chunk, allocating a new chunk if none has enough room left.
+    pub fn write(&mut self, data: &[u8]) -> Result<StagedSlice, StagingWriteError> {
+        let size = data.len() as u64;
+
+        let chunk_index = match self
+            .chunks
+            .iter()
+            .position(|chunk| chunk.in_flight.is_none() && chunk.remaining() >= size)
+        {
+            Some(index) => index,
+            None => self.allocate_chunk(size)?,
+        };
+
+        let chunk = &mut self.chunks[chunk_index];
+        let offset = chunk.used;
+        chunk
+            .buffer
+            .mapped_mut::<u8>()
+            .ok_or(StagingWriteError::MemoryMapping)?[offset as usize..(offset + size) as usize]
+            .copy_from_slice(data);
+        chunk.used = (offset + size)
+            .next_multiple_of(WRITE_ALIGNMENT)
+            .min(chunk.buffer.size());
+
+        Ok(StagedSlice {
+            chunk_index,
+            offset,
+            size,
+        })
+    }
+
+    /// `destination` must have been created with `TRANSFER_DST` usage and outlive the frame.
+    pub fn copy_to_buffer(&mut self, source: StagedSlice, destination: &Buffer, offset: u64) {
+        self.copies.push(StagedCopy::Buffer {
+            source,
+            destination: destination.handle,
+            offset,
+        });
+    }
+
+    pub fn copy_to_image(&mut self, source: StagedSlice, destination: ImageDestination) {
+        self.copies.push(StagedCopy::Image {
+            source,
+            destination,
+        });
+    }
+
+    fn allocate_chunk(&mut self, min_size: u64) -> Result<usize, StagingWriteError> {
+        // needing a chunk while the others are still in use by previous frames is expected
+        if self.chunks.iter().any(|chunk| chunk.in_flight.is_none()) {
+            log::warn!(
+                "staging belt overflow, allocating chunk #{} for a {min_size} bytes write",
+                self.chunks.len() + 1,
+            );
+        }
+
+        let buffer = BufferBuilder::staging_buffer_default(self.chunk_size.max(min_size))
+            .with_name("staging belt chunk")
+            .with_tag(AllocTag::Staging)
+            .build_internal(self.device_ref.clone(), self.allocator_ref.clone())?;
+        self.chunks.push(StagingChunk {
+            buffer,
+            used: 0,
+            in_flight: None,
+        });
+
+        Ok(self.chunks.len() - 1)
+    }
+
+    /// Records every copy requested since the last frame, followed by a barrier making them
+    /// visible to the whole pipeline.
+    pub(crate) fn record_copies(&mut self, cmd_buffer: vk::CommandBuffer) {
+        for chunk in &mut self.chunks {
+            if chunk.in_flight.is_none() && chunk.used > 0 {
+                chunk.in_flight = Some(self.frame_index);
+            }
+        }
+
+        if self.copies.is_empty() {
+            return;
+        }
+
+        let device = self.device_ref.read();
+        let mut barriers = BarrierBatch::new();
+        for copy in self.copies.drain(..) {
+            match copy {
+                StagedCopy::Buffer {
+                    source,
+                    destination,
+                    offset,
+                } => {
+                    let region = vk::BufferCopy::default()
+                        .src_offset(source.offset)
+                        .dst_offset(offset)
+                        .size(source.size);
+                    unsafe {
+                        device.cmd_copy_buffer(
+                            cmd_buffer,
+                            self.chunks[source.chunk_index].buffer.handle,
+                            destination,
+                            std::slice::from_ref(&region),
+                        )
+                    };
+                }
+                StagedCopy::Image {
+                    source,
+                    destination,
+                } => {
+                    let subresource_range = vk::ImageSubresourceRange::default()
+                        .aspect_mask(destination.subresource.aspect_mask)
+                        .base_mip_level(destination.subresource.mip_level)
+                        .level_count(1)
+                        .base_array_layer(destination.subresource.base_array_layer)
+                        .layer_count(destination.subresource.layer_count);
+                    let to_transfer = vk::ImageMemoryBarrier2::default()
+                        .image(destination.image)
+                        .old_layout(destination.current_layout)
+                        .new_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                        .src_stage_mask(vk::PipelineStageFlags2::NONE)
+                        .src_access_mask(vk::AccessFlags2::NONE)
+                        .dst_stage_mask(vk::PipelineStageFlags2::COPY)
+                        .dst_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                        .subresource_range(subresource_range);
+                    let to_final = to_transfer
+                        .old_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+                        .new_layout(destination.final_layout)
+                        .src_stage_mask(vk::PipelineStageFlags2::COPY)
+                        .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                        .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                        .dst_access_mask(vk::AccessFlags2::MEMORY_READ);
+
+                    let region = vk::BufferImageCopy::default()
+                        .buffer_offset(source.offset)
+                        .image_subresource(destination.subresource)
+                        .image_offset(destination.offset)
+                        .image_extent(destination.extent);
+                    barriers.push_image_barrier(to_transfer);
+                    barriers.cmd_flush(&device, cmd_buffer);
+                    unsafe {
+                        device.cmd_copy_buffer_to_image(
+                            cmd_buffer,
+                            self.chunks[source.chunk_index].buffer.handle,
+                            destination.image,
+                            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                            std::slice::from_ref(&region),
+                        )
+                    };
+                    barriers.push_image_barrier(to_final);
+                    barriers.cmd_flush(&device, cmd_buffer);
+                }
+            }
+        }
+
+        barriers.push_memory_barrier(
+            vk::MemoryBarrier2::default()
+                .src_stage_mask(vk::PipelineStageFlags2::ALL_TRANSFER)
+                .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                .dst_access_mask(vk::AccessFlags2::MEMORY_READ),
+        );
+        barriers.cmd_flush(&device, cmd_buffer);
+    }
+
+    /// Frees the chunks of completed frames, called once the frame slot of `frame_index` was
+    /// waited on.
+    pub(crate) fn recycle(&mut self, frame_index: u64) {
+        for chunk in &mut self.chunks {
+            if let Some(frame) = chunk.in_flight
+                && is_frame_complete(frame, frame_index)
+            {
+                chunk.used = 0;
+                chunk.in_flight = None;
+            }
+        }
+        self.frame_index = frame_index;
+    }
+}
@@ -2,14 +2,17 @@ use ash::{khr, vk};
 use thiserror::Error;
 use winit::raw_window_handle::{RawDisplayHandle, RawWindowHandle};
 
-use super::{device::PhysicalDevice, instance::Instance};
+use super::{
+    device::PhysicalDevice,
+    instance::Instance,
+    swapchain::{PresentPreference, SurfaceFormatPreference},
+};
 
 pub(crate) struct Surface {
     pub handle: vk::SurfaceKHR,
     pub loader: khr::surface::Instance,
 
     pub format: vk::SurfaceFormatKHR,
-    pub capabilities: vk::SurfaceCapabilitiesKHR,
     pub present_mode: vk::PresentModeKHR,
 }
 
@@ -17,13 +20,13 @@ pub(crate) struct Surface {
 pub enum SurfaceCreateError {
     #[error("vulkan call to create the surface failed")]
     VulkanCreation(vk::Result),
+
+    #[error("the present queue family cannot present to the surface")]
+    PresentUnsupported,
 }
 
 #[derive(Debug, Error)]
 pub enum DeviceSetupError {
-    #[error("vulkan call to fetch capabilities from surface failed")]
-    CapabilitiesFetching(vk::Result),
-
     #[error("vulkan call to enumerate present modes from surface failed")]
     PresentMoodeEnumeration(vk::Result),
 
@@ -54,7 +57,6 @@ impl Surface {
             handle,
             loader,
             format: vk::SurfaceFormatKHR::default(),
-            capabilities: vk::SurfaceCapabilitiesKHR::default(),
             present_mode: vk::PresentModeKHR::FIFO,
         })
     }
@@ -62,24 +64,20 @@ impl Surface {
     pub fn setup_from_device(
         &mut self,
         physical_device: &PhysicalDevice,
+        present_preference: PresentPreference,
+        format_preference: &SurfaceFormatPreference,
     ) -> Result<(), DeviceSetupError> {
-        let capabilities = unsafe {
-            self.loader
-                .get_physical_device_surface_capabilities(physical_device.handle, self.handle)
-        }
-        .map_err(DeviceSetupError::CapabilitiesFetching)?;
-        self.capabilities = capabilities;
-
         let present_modes = unsafe {
             self.loader
                 .get_physical_device_surface_present_modes(physical_device.handle, self.handle)
         }
         .map_err(DeviceSetupError::PresentMoodeEnumeration)?;
-        if let Some(&present_mode) = present_modes
-            .iter()
-            .find(|&&mode| mode == vk::PresentModeKHR::MAILBOX)
-        {
-            self.present_mode = present_mode;
+        self.present_mode = present_preference.select(&present_modes);
+        if present_preference.candidates().first() != Some(&self.present_mode) {
+            log::warn!(
+                "{present_preference:?} is not fully supported by the surface, falling back to {:?}",
+                self.present_mode
+            );
         }
 
         let available_formats = unsafe {
@@ -92,13 +90,15 @@ impl Surface {
             .first()
             .ok_or(DeviceSetupError::NoFormat)?;
 
-        let selected_format = available_formats
-            .into_iter()
-            .find(|&format| {
-                format.format == vk::Format::B8G8R8A8_SRGB
-                    && format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
-            })
-            .unwrap_or(format_fallback);
+        let selected_format = format_preference
+            .select(&available_formats)
+            .unwrap_or_else(|| {
+                log::warn!(
+                    "none of the preferred surface formats is supported, falling back to \
+                    {format_fallback:?}"
+                );
+                format_fallback
+            });
 
         log::debug!(
             "Selected surface format {:?} with colorspace {:?}",
@@ -109,6 +109,29 @@ impl Surface {
 
         Ok(())
     }
+
+    pub fn supports_queue_family(&self, physical_device: &PhysicalDevice, qf_index: u32) -> bool {
+        unsafe {
+            self.loader.get_physical_device_surface_support(
+                physical_device.handle,
+                qf_index,
+                self.handle,
+            )
+        }
+        .unwrap_or(false)
+    }
+
+    /// Capabilities change with the window size and the monitor the window is on, they must be
+    /// queried again for every swapchain creation.
+    pub fn query_capabilities(
+        &self,
+        physical_device: &PhysicalDevice,
+    ) -> Result<vk::SurfaceCapabilitiesKHR, vk::Result> {
+        unsafe {
+            self.loader
+                .get_physical_device_surface_capabilities(physical_device.handle, self.handle)
+        }
+    }
 }
 
 impl Drop for Surface {
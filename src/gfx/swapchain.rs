@@ -11,7 +11,9 @@ use crate::{
 
 use super::{
     allocator::Allocator,
-    device::Device,
+    barrier::layout_src_scope,
+    commands::{FRAMES_IN_FLIGHT, FrameSlotIndex},
+    device::{Device, PhysicalDevice},
     image::{Image, ImageBuildError, ImageCreateInfo},
     instance::Instance,
     surface::Surface,
@@ -24,29 +26,322 @@ pub(crate) enum NextImageState {
     OutOfDate,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PresentState {
+    Presented,
+    PresentedSuboptimal,
+    /// The image was not presented, the swapchain must be recreated.
+    OutOfDate,
+}
+
+/// Properties of the presented images, passes building pipelines against them must be rebuilt
+/// whenever they change.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SurfaceProperties {
+    pub format: vk::Format,
+    pub color_space: vk::ColorSpaceKHR,
+    /// Extent of the images, in the native orientation of the display when they are rotated by
+    /// the transform, see [`Self::display_extent`].
+    pub extent: vk::Extent2D,
+    /// Rotation the presented images are expected to already have, see
+    /// [`Context::pre_rotation`](super::context::Context::pre_rotation).
+    pub transform: vk::SurfaceTransformFlagsKHR,
+}
+
+impl SurfaceProperties {
+    /// The extent as the user sees it, e.g. to compute the aspect ratio of projections.
+    pub fn display_extent(&self) -> vk::Extent2D {
+        match is_rotated_by_quarter(self.transform) {
+            true => vk::Extent2D {
+                width: self.extent.height,
+                height: self.extent.width,
+            },
+            false => self.extent,
+        }
+    }
+}
+
+/// Depth attachment created along with every swapchain image, bound as
+/// [`ResourceID::SwapchainDSAttachment`](super::render_graph::resource::ResourceID).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DepthConfig {
+    /// Uses the first of these formats supported as a depth attachment by the device.
+    Enabled(Vec<vk::Format>),
+    /// Saves the memory of the depth images when nothing is depth tested, render graphs declaring
+    /// the swapchain depth attachment are then rejected.
+    Disabled,
+}
+
+impl Default for DepthConfig {
+    fn default() -> Self {
+        Self::Enabled(vec![vk::Format::D32_SFLOAT])
+    }
+}
+
+impl DepthConfig {
+    pub(crate) fn select_format(
+        &self,
+        instance: &Instance,
+        physical_device: &PhysicalDevice,
+    ) -> Result<Option<vk::Format>, SwapchainCreateError> {
+        let Self::Enabled(formats) = self else {
+            return Ok(None);
+        };
+
+        formats
+            .iter()
+            .copied()
+            .find(|&format| {
+                let properties = unsafe {
+                    instance.get_physical_device_format_properties(physical_device.handle, format)
+                };
+                properties
+                    .optimal_tiling_features
+                    .contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
+            })
+            .map(Some)
+            .ok_or_else(|| SwapchainCreateError::UnsupportedDepthFormats(formats.clone()))
+    }
+}
+
+/// Present mode wanted for the swapchain. Modes the surface does not support are skipped in the
+/// order given by [`candidates`](Self::candidates), ending with FIFO which every surface supports.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum PresentPreference {
+    /// FIFO, frames are shown at the vertical blank and rendering waits for it.
+    Vsync,
+    /// MAILBOX then FIFO, without tearing but replacing the queued frame with the latest one.
+    #[default]
+    LowLatency,
+    /// IMMEDIATE then MAILBOX then FIFO, frames are shown right away and may tear.
+    Immediate,
+    /// The given mode then FIFO.
+    Explicit(vk::PresentModeKHR),
+}
+
+impl PresentPreference {
+    /// Modes tried in order, the last one always being FIFO.
+    pub fn candidates(self) -> Vec<vk::PresentModeKHR> {
+        let mut candidates = match self {
+            Self::Vsync => vec![],
+            Self::LowLatency => vec![vk::PresentModeKHR::MAILBOX],
+            Self::Immediate => vec![vk::PresentModeKHR::IMMEDIATE, vk::PresentModeKHR::MAILBOX],
+            Self::Explicit(present_mode) => vec![present_mode],
+        };
+        candidates.push(vk::PresentModeKHR::FIFO);
+        candidates.dedup();
+
+        candidates
+    }
+
+    /// First candidate among the `available` modes.
+    pub fn select(self, available: &[vk::PresentModeKHR]) -> vk::PresentModeKHR {
+        self.candidates()
+            .into_iter()
+            .find(|mode| available.contains(mode))
+            .unwrap_or(vk::PresentModeKHR::FIFO)
+    }
+}
+
+/// How the swapchain images are composited with what is behind the window. Modes the surface
+/// does not support are skipped in the order given by [`candidates`](Self::candidates).
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum CompositeAlphaPreference {
+    /// The alpha channel is ignored and the window is opaque.
+    #[default]
+    Opaque,
+    /// Colors are expected to already be multiplied by their alpha.
+    PreMultiplied,
+    /// Colors are multiplied by their alpha by the compositor.
+    PostMultiplied,
+    /// The compositor decides, e.g. through a platform window property.
+    Inherit,
+}
+
+impl CompositeAlphaPreference {
+    /// Modes tried in order, the preferred one then every other from the most to the least
+    /// likely to show an opaque window as it was rendered.
+    pub fn candidates(self) -> Vec<vk::CompositeAlphaFlagsKHR> {
+        let preferred = match self {
+            Self::Opaque => vk::CompositeAlphaFlagsKHR::OPAQUE,
+            Self::PreMultiplied => vk::CompositeAlphaFlagsKHR::PRE_MULTIPLIED,
+            Self::PostMultiplied => vk::CompositeAlphaFlagsKHR::POST_MULTIPLIED,
+            Self::Inherit => vk::CompositeAlphaFlagsKHR::INHERIT,
+        };
+        let mut candidates = vec![preferred];
+        for fallback in [
+            vk::CompositeAlphaFlagsKHR::OPAQUE,
+            vk::CompositeAlphaFlagsKHR::INHERIT,
+            vk::CompositeAlphaFlagsKHR::PRE_MULTIPLIED,
+            vk::CompositeAlphaFlagsKHR::POST_MULTIPLIED,
+        ] {
+            if fallback != preferred {
+                candidates.push(fallback);
+            }
+        }
+
+        candidates
+    }
+
+    /// First candidate among the `supported` modes, surfaces support at least one.
+    pub fn select(self, supported: vk::CompositeAlphaFlagsKHR) -> vk::CompositeAlphaFlagsKHR {
+        self.candidates()
+            .into_iter()
+            .find(|&mode| supported.contains(mode))
+            .unwrap_or(vk::CompositeAlphaFlagsKHR::OPAQUE)
+    }
+}
+
+/// Surface formats tried in order against the ones the surface offers, the first one it offers
+/// being used when none matches. Defaults to 8-bit sRGB.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SurfaceFormatPreference(pub Vec<vk::SurfaceFormatKHR>);
+
+impl Default for SurfaceFormatPreference {
+    fn default() -> Self {
+        Self::sdr()
+    }
+}
+
+impl SurfaceFormatPreference {
+    pub fn sdr() -> Self {
+        Self(vec![vk::SurfaceFormatKHR {
+            format: vk::Format::B8G8R8A8_SRGB,
+            color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+        }])
+    }
+
+    /// Linear extended sRGB (scRGB) in half floats, then HDR10 in 10-bit, then 8-bit sRGB.
+    pub fn hdr() -> Self {
+        Self(vec![
+            vk::SurfaceFormatKHR {
+                format: vk::Format::R16G16B16A16_SFLOAT,
+                color_space: vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+            },
+            vk::SurfaceFormatKHR {
+                format: vk::Format::A2B10G10R10_UNORM_PACK32,
+                color_space: vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+            },
+        ])
+        .then(Self::sdr())
+    }
+
+    /// Appends the formats of `fallback`, tried once every one of `self` was.
+    pub fn then(mut self, fallback: Self) -> Self {
+        self.0.extend(fallback.0);
+        self
+    }
+
+    /// Color spaces besides sRGB need `VK_EXT_swapchain_colorspace` on the instance.
+    pub fn needs_colorspace_extension(&self) -> bool {
+        self.0
+            .iter()
+            .any(|format| format.color_space != vk::ColorSpaceKHR::SRGB_NONLINEAR)
+    }
+
+    /// First preferred format among the `available` ones.
+    pub fn select(&self, available: &[vk::SurfaceFormatKHR]) -> Option<vk::SurfaceFormatKHR> {
+        self.0
+            .iter()
+            .find(|preferred| available.contains(preferred))
+            .copied()
+    }
+}
+
+/// Swapchain settings fixed at context creation, applied to every recreation.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SwapchainOptions {
+    /// Clamped to what the surface supports, one more than its minimum when `None`.
+    pub desired_image_count: Option<u32>,
+    pub composite_alpha: CompositeAlphaPreference,
+    pub formats: SurfaceFormatPreference,
+}
+
 pub struct ImageResources<'a> {
     pub color_image: &'a mut ImageState,
-    pub depth_image: &'a mut Image,
+    /// UNORM view of `color_image`, when its format is sRGB and the device can view it as another.
+    pub color_unorm_view: Option<vk::ImageView>,
+    /// `None` when the swapchain depth is [disabled](DepthConfig::Disabled).
+    pub depth_image: Option<&'a mut ImageState>,
 }
 
 pub(crate) struct ImageContext {
     pub color_attachment: ImageState,
-    pub depth_attachment: Image,
+    pub unorm_view: Option<vk::ImageView>,
+    pub depth_attachment: Option<Image>,
 
     pub render_semaphore: vk::Semaphore,
 }
 
+/// Index of an image in the swapchain, as given by its acquisition. Images are not acquired in
+/// order nor at the pace frames are recorded, so resources used until an image is presented are
+/// indexed with it rather than with a [`FrameSlotIndex`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct SwapchainImageIndex(pub u32);
+
+impl SwapchainImageIndex {
+    pub fn as_usize(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Semaphores and fence of the frame being recorded, pairing the acquire semaphore of its frame
+/// slot with the render semaphore of the image it acquired.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct FrameSemaphores {
+    pub image_acquired: vk::Semaphore,
+    pub render_finished: vk::Semaphore,
+    pub present_fence: vk::Fence,
+}
+
+impl FrameSemaphores {
+    fn pair(frame_sync: &FrameSync, image: &ImageContext) -> Self {
+        // presentation waits on the semaphore of the image, which may be acquired again by a
+        // later frame slot while a previous one is still presented
+        Self {
+            image_acquired: frame_sync.image_acquired_semaphore,
+            render_finished: image.render_semaphore,
+            present_fence: frame_sync.present_fence,
+        }
+    }
+}
+
+// frame whose fence was reset but which was never submitted, e.g. after an error while recording
+#[derive(Debug, Clone, Copy)]
+struct UnsubmittedFrame {
+    frame_slot: FrameSlotIndex,
+    image_acquired: bool,
+}
+
+/// Sync objects reused by every frame recorded in the same frame slot.
+pub(crate) struct FrameSync {
+    // waited on by the first submission using the acquired image
+    pub image_acquired_semaphore: vk::Semaphore,
+    // signaled once the GPU is done with the frame, before the slot is reused
+    pub present_fence: vk::Fence,
+}
+
 pub(crate) struct Swapchain {
     pub handle: vk::SwapchainKHR,
     pub loader: khr::swapchain::Device,
 
     pub extent: vk::Extent2D,
+    pub transform: vk::SurfaceTransformFlagsKHR,
+    pub format: vk::SurfaceFormatKHR,
+    pub depth_format: Option<vk::Format>,
+    /// Format of the additional view of every image, when the surface format is sRGB and the
+    /// device supports mutable swapchain formats.
+    pub unorm_format: Option<vk::Format>,
     pub images: Vec<ImageContext>,
+    // single image standing in for the presented ones when rendering offscreen, which owns the
+    // view of its color attachment
+    offscreen_image: Option<Image>,
 
-    pub image_acquired_semaphore: vk::Semaphore,
-    pub present_fence: vk::Fence,
+    // one per frame slot, unrelated to the image count
+    frame_syncs: Vec<FrameSync>,
 
-    pub current_image_index: usize,
+    current_image_index: Option<SwapchainImageIndex>,
+    unsubmitted_frame: Option<UnsubmittedFrame>,
 
     // bookkeeping
     device_ref: ThreadSafeRwRef<Device>,
@@ -54,6 +349,9 @@ pub(crate) struct Swapchain {
 
 #[derive(Debug, Error)]
 pub enum SwapchainCreateError {
+    #[error("vulkan call to fetch surface capabilities failed")]
+    CapabilitiesFetching(vk::Result),
+
     #[error("vulkan call to create the swapchain failed")]
     VulkanCreation(vk::Result),
 
@@ -68,6 +366,27 @@ pub enum SwapchainCreateError {
 
     #[error("depth image building failed")]
     DepthImageBuilding(ImageBuildError),
+
+    #[error("offscreen color image building failed")]
+    OffscreenImageBuilding(ImageBuildError),
+
+    #[error("none of the depth formats {0:?} is supported as a depth attachment")]
+    UnsupportedDepthFormats(Vec<vk::Format>),
+
+    #[error("the surface has no area, e.g. its window is minimized")]
+    NoArea,
+}
+
+#[derive(Debug, Error)]
+pub enum FrameBeginError {
+    #[error("vulkan call to release the sync objects of an unsubmitted frame failed")]
+    UnsubmittedFrameRelease(vk::Result),
+
+    #[error("presentation fence sync failed")]
+    FenceSync(vk::Result),
+
+    #[error("presentation fence reset failed")]
+    FenceReset(vk::Result),
 }
 
 #[derive(Debug, Error)]
@@ -75,6 +394,9 @@ pub enum NextImageAcquireError {
     #[error("vulkan call to acquire next image index failed")]
     NextIndexAcquisition(vk::Result),
 
+    #[error("vulkan call to signal the offscreen image as acquired failed")]
+    OffscreenAcquisition(vk::Result),
+
     #[error("acquired index is out of range ({0}, max is {1})")]
     InvalidIndex(u32, usize),
 }
@@ -83,42 +405,67 @@ pub enum NextImageAcquireError {
 pub enum PresentError {
     #[error("vulkan call to present swapchain image failed")]
     Present(vk::Result),
+
+    #[error("vulkan call to wait for the offscreen image to be rendered failed")]
+    OffscreenPresent(vk::Result),
 }
 
 impl Swapchain {
+    /// `old_swapchain` is the swapchain being replaced for the same surface, whose images
+    /// already queued for presentation are still shown, if any.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         instance: &Instance,
+        physical_device: &PhysicalDevice,
         device_ref: ThreadSafeRwRef<Device>,
         surface: &Surface,
+        old_swapchain: Option<&Swapchain>,
         suggested_size: vk::Extent2D,
+        depth_format: Option<vk::Format>,
+        options: &SwapchainOptions,
         allocator_ref: ThreadSafeRef<Allocator>,
     ) -> Result<Self, SwapchainCreateError> {
         let device = device_ref.read();
         let loader = khr::swapchain::Device::new(instance, &device);
 
-        let mut min_image_count = surface.capabilities.min_image_count + 1;
-        if surface.capabilities.max_image_count > 0
-            && min_image_count > surface.capabilities.max_image_count
-        {
-            min_image_count = surface.capabilities.max_image_count;
+        let capabilities = surface
+            .query_capabilities(physical_device)
+            .map_err(SwapchainCreateError::CapabilitiesFetching)?;
+        // rendering to images already rotated saves the compositor from doing it on every present,
+        // which projections account for through the pre-rotation
+        let transform = capabilities.current_transform;
+        // some platforms report it before the window is resized to 0x0, if they ever do
+        let extent = swapchain_extent(&capabilities, suggested_size);
+        if extent.width == 0 || extent.height == 0 {
+            return Err(SwapchainCreateError::NoArea);
         }
 
-        let extent = match surface.capabilities.current_extent {
-            vk::Extent2D {
-                width: u32::MAX,
-                height: u32::MAX,
-            } => suggested_size,
-            _ => surface.capabilities.current_extent,
-        };
+        // a maximum of 0 means there is none
+        let mut min_image_count = options
+            .desired_image_count
+            .unwrap_or(capabilities.min_image_count + 1)
+            .max(capabilities.min_image_count);
+        if capabilities.max_image_count > 0 && min_image_count > capabilities.max_image_count {
+            min_image_count = capabilities.max_image_count;
+        }
+        let composite_alpha = options
+            .composite_alpha
+            .select(capabilities.supported_composite_alpha);
 
         let semaphore_info = vk::SemaphoreCreateInfo::default();
-        let present_semaphore = unsafe { device.create_semaphore(&semaphore_info, None) }
-            .map_err(SwapchainCreateError::RenderSyncObjectsCreation)?;
+        let frame_syncs = create_frame_syncs(&device)?;
 
-        let fence_info = vk::FenceCreateInfo::default().flags(vk::FenceCreateFlags::SIGNALED);
-        let present_fence = unsafe { device.create_fence(&fence_info, None) }
-            .map_err(SwapchainCreateError::RenderSyncObjectsCreation)?;
+        // copying out of the swapchain is what screenshots are made of, and blitting into it is
+        // how lower resolution renders are upscaled, request both when possible
+        let image_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT
+            | (capabilities.supported_usage_flags
+                & (vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST));
 
+        // images are shared between both families rather than transferred before every present
+        let queue_family_indices = [
+            physical_device.graphics_qf_index,
+            physical_device.present_qf_index,
+        ];
         let create_info = vk::SwapchainCreateInfoKHR::default()
             .surface(surface.handle)
             .min_image_count(min_image_count)
@@ -126,18 +473,48 @@ impl Swapchain {
             .image_color_space(surface.format.color_space)
             .image_extent(extent)
             .image_array_layers(1)
-            .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+            .image_usage(image_usage)
             .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
-            .pre_transform(surface.capabilities.current_transform)
-            .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
+            .pre_transform(transform)
+            .composite_alpha(composite_alpha)
             .present_mode(surface.present_mode)
-            .clipped(true);
+            .clipped(true)
+            .old_swapchain(
+                old_swapchain
+                    .filter(|old| old.offscreen_image.is_none())
+                    .map_or(vk::SwapchainKHR::null(), |old| old.handle),
+            );
+        let create_info = match physical_device.has_separate_present_queue() {
+            true => create_info
+                .image_sharing_mode(vk::SharingMode::CONCURRENT)
+                .queue_family_indices(&queue_family_indices),
+            false => create_info,
+        };
+
+        // UI authored in sRGB is written through a UNORM view so that it is not encoded twice
+        let unorm_format =
+            unorm_variant(surface.format.format).filter(|_| device.swapchain_mutable_format);
+        let view_formats = [surface.format.format, unorm_format.unwrap_or_default()];
+        let mut format_list = vk::ImageFormatListCreateInfo::default().view_formats(&view_formats);
+        let create_info = match unorm_format {
+            Some(_) => create_info
+                .flags(vk::SwapchainCreateFlagsKHR::MUTABLE_FORMAT)
+                .push_next(&mut format_list),
+            None => create_info,
+        };
 
         let handle = unsafe { loader.create_swapchain(&create_info, None) }
             .map_err(SwapchainCreateError::VulkanCreation)?;
 
         let images_handles = unsafe { loader.get_swapchain_images(handle) }
             .map_err(SwapchainCreateError::ImageFetching)?;
+        // the implementation may create more images than asked for
+        log::info!(
+            "swapchain created with {} images ({min_image_count} requested), {:?} present mode, \
+            {composite_alpha:?} composite alpha",
+            images_handles.len(),
+            surface.present_mode
+        );
         let image_view_create_info = vk::ImageViewCreateInfo::default()
             .view_type(vk::ImageViewType::TYPE_2D)
             .format(surface.format.format)
@@ -158,35 +535,62 @@ impl Swapchain {
             );
 
         let image_extent = extent.into();
-        let depth_image_info = ImageCreateInfo::swapchain_depth_image(image_extent);
+        let depth_image_info =
+            depth_format.map(|format| ImageCreateInfo::swapchain_depth_image(image_extent, format));
 
         let images = images_handles
             .into_iter()
             .map(|handle| {
                 let render_semaphore = unsafe { device.create_semaphore(&semaphore_info, None) }
                     .map_err(SwapchainCreateError::RenderSyncObjectsCreation)?;
+                device
+                    .handle_registry
+                    .register(render_semaphore, "swapchain render semaphore");
 
                 let image_view_create_info = image_view_create_info.image(handle);
                 let view = unsafe { device.create_image_view(&image_view_create_info, None) }
                     .map_err(SwapchainCreateError::ImageViewCreation)?;
+                device
+                    .handle_registry
+                    .register(view, "swapchain color attachment");
+
+                let unorm_view = unorm_format
+                    .map(|format| {
+                        let unorm_view_create_info = image_view_create_info.format(format);
+                        let view =
+                            unsafe { device.create_image_view(&unorm_view_create_info, None) }
+                                .map_err(SwapchainCreateError::ImageViewCreation)?;
+                        device
+                            .handle_registry
+                            .register(view, "swapchain color attachment unorm view");
+
+                        Ok(view)
+                    })
+                    .transpose()?;
 
                 let color_attachment = ImageState {
                     handle,
                     view,
                     layout: vk::ImageLayout::UNDEFINED,
                     format: surface.format.format,
+                    usage: create_info.image_usage,
                     extent: image_extent,
                     extent_2d: extent,
                     view_subresource_range: image_view_create_info.subresource_range,
                 };
 
                 let depth_attachment = depth_image_info
-                    .clone()
-                    .build_from_base_structs(device_ref.clone(), allocator_ref.clone())
+                    .as_ref()
+                    .map(|info| {
+                        info.clone()
+                            .build_from_base_structs(device_ref.clone(), allocator_ref.clone())
+                    })
+                    .transpose()
                     .map_err(SwapchainCreateError::DepthImageBuilding)?;
 
                 Ok(ImageContext {
                     color_attachment,
+                    unorm_view,
                     depth_attachment,
                     render_semaphore,
                 })
@@ -197,26 +601,225 @@ impl Swapchain {
             handle,
             loader,
             extent,
+            transform,
+            format: surface.format,
+            depth_format,
+            unorm_format,
             images,
-            image_acquired_semaphore: present_semaphore,
-            present_fence,
-            current_image_index: usize::MAX,
+            offscreen_image: None,
+            frame_syncs,
+            current_image_index: None,
+            unsubmitted_frame: None,
             device_ref: device_ref.clone(),
         })
     }
 
-    pub fn next_image(&mut self) -> Result<NextImageState, NextImageAcquireError> {
+    /// Stand-in for a swapchain when there is no window to present to: frames render to a single
+    /// image owned by the swapchain, left in `TRANSFER_SRC_OPTIMAL` for readbacks once rendered.
+    pub fn offscreen(
+        instance: &Instance,
+        device_ref: ThreadSafeRwRef<Device>,
+        extent: vk::Extent2D,
+        depth_format: Option<vk::Format>,
+        allocator_ref: ThreadSafeRef<Allocator>,
+    ) -> Result<Self, SwapchainCreateError> {
+        let format = vk::SurfaceFormatKHR {
+            format: vk::Format::R8G8B8A8_SRGB,
+            color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+        };
+        let unorm_format = unorm_variant(format.format);
+        let image_extent = extent.into();
+
+        let color_image = ImageCreateInfo::offscreen_color_image(image_extent, format.format)
+            .build_from_base_structs(device_ref.clone(), allocator_ref.clone())
+            .map_err(SwapchainCreateError::OffscreenImageBuilding)?;
+        let depth_attachment = depth_format
+            .map(|format| {
+                ImageCreateInfo::swapchain_depth_image(image_extent, format)
+                    .build_from_base_structs(device_ref.clone(), allocator_ref.clone())
+            })
+            .transpose()
+            .map_err(SwapchainCreateError::DepthImageBuilding)?;
+
+        let device = device_ref.read();
+        // only used for its destruction of a null handle, the extension may not even be enabled
+        let loader = khr::swapchain::Device::new(instance, &device);
+        let frame_syncs = create_frame_syncs(&device)?;
+
+        let semaphore_info = vk::SemaphoreCreateInfo::default();
+        let render_semaphore = unsafe { device.create_semaphore(&semaphore_info, None) }
+            .map_err(SwapchainCreateError::RenderSyncObjectsCreation)?;
+        device
+            .handle_registry
+            .register(render_semaphore, "offscreen render semaphore");
+
+        let unorm_view = unorm_format
+            .map(|format| {
+                let unorm_view_create_info = vk::ImageViewCreateInfo::default()
+                    .image(color_image.state.handle)
+                    .view_type(vk::ImageViewType::TYPE_2D)
+                    .format(format)
+                    .subresource_range(color_image.state.view_subresource_range);
+                let view = unsafe { device.create_image_view(&unorm_view_create_info, None) }
+                    .map_err(SwapchainCreateError::ImageViewCreation)?;
+                device
+                    .handle_registry
+                    .register(view, "offscreen color attachment unorm view");
+
+                Ok(view)
+            })
+            .transpose()?;
+
+        let images = vec![ImageContext {
+            color_attachment: color_image.state.clone(),
+            unorm_view,
+            depth_attachment,
+            render_semaphore,
+        }];
+
+        Ok(Self {
+            handle: vk::SwapchainKHR::null(),
+            loader,
+            extent,
+            transform: vk::SurfaceTransformFlagsKHR::IDENTITY,
+            format,
+            depth_format,
+            unorm_format,
+            images,
+            offscreen_image: Some(color_image),
+            frame_syncs,
+            current_image_index: None,
+            unsubmitted_frame: None,
+            device_ref: device_ref.clone(),
+        })
+    }
+
+    pub fn is_offscreen(&self) -> bool {
+        self.offscreen_image.is_some()
+    }
+
+    pub fn properties(&self) -> SurfaceProperties {
+        SurfaceProperties {
+            format: self.format.format,
+            color_space: self.format.color_space,
+            extent: self.extent,
+            transform: self.transform,
+        }
+    }
+
+    pub fn frame_sync(&self, frame_slot: FrameSlotIndex) -> &FrameSync {
+        &self.frame_syncs[frame_slot.as_usize()]
+    }
+
+    /// Waits for the GPU to be done with the previous frame of the slot, then resets its fence for
+    /// the frame about to be recorded.
+    pub fn begin_frame(&mut self, frame_slot: FrameSlotIndex) -> Result<(), FrameBeginError> {
+        self.release_unsubmitted_frame()?;
+
+        let device = self.device_ref.read();
+        let fences = [self.frame_sync(frame_slot).present_fence];
+        unsafe { device.wait_for_fences(&fences, true, u64::MAX) }
+            .map_err(FrameBeginError::FenceSync)?;
+        unsafe { device.reset_fences(&fences) }.map_err(FrameBeginError::FenceReset)?;
+        self.unsubmitted_frame = Some(UnsubmittedFrame {
+            frame_slot,
+            image_acquired: false,
+        });
+
+        Ok(())
+    }
+
+    /// Called once the last submission of the frame signaling its fence was made.
+    pub fn end_frame(&mut self) {
+        self.unsubmitted_frame = None;
+    }
+
+    // a frame failing after its fence was reset would leave it unsignaled for good, and its acquire
+    // semaphore signaled with no wait, which reusing it for an acquisition is invalid for
+    fn release_unsubmitted_frame(&mut self) -> Result<(), FrameBeginError> {
+        let Some(unsubmitted_frame) = self.unsubmitted_frame.take() else {
+            return Ok(());
+        };
+        log::debug!("releasing the sync objects of a frame that was never submitted");
+
+        let device = self.device_ref.read();
+        let frame_sync = self.frame_sync(unsubmitted_frame.frame_slot);
+        let wait_semaphores = [vk::SemaphoreSubmitInfo::default()
+            .semaphore(frame_sync.image_acquired_semaphore)
+            .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)];
+        let mut submit_info = vk::SubmitInfo2::default();
+        if unsubmitted_frame.image_acquired {
+            submit_info = submit_info.wait_semaphore_infos(&wait_semaphores);
+        }
+        unsafe {
+            device.queue_submit2(
+                device.graphics_queue.handle,
+                &[submit_info],
+                frame_sync.present_fence,
+            )
+        }
+        .map_err(FrameBeginError::UnsubmittedFrameRelease)?;
+
+        Ok(())
+    }
+
+    /// Sync objects for the frame of `frame_slot`, rendering to the last acquired image.
+    pub fn frame_semaphores(&self, frame_slot: FrameSlotIndex) -> FrameSemaphores {
+        FrameSemaphores::pair(
+            self.frame_sync(frame_slot),
+            self.image(self.current_image_index()),
+        )
+    }
+
+    pub fn image(&self, index: SwapchainImageIndex) -> &ImageContext {
+        &self.images[index.as_usize()]
+    }
+
+    /// Index of the last acquired image, the one the frame being recorded renders to.
+    pub fn current_image_index(&self) -> SwapchainImageIndex {
+        self.current_image_index
+            .expect("a swapchain image should be acquired before being used")
+    }
+
+    /// Acquires the image to render to, signaling the acquire semaphore of the frame slot.
+    pub fn next_image(
+        &mut self,
+        frame_slot: FrameSlotIndex,
+    ) -> Result<NextImageState, NextImageAcquireError> {
+        if self.is_offscreen() {
+            // nothing to wait for, the semaphore is signaled for the frame to wait on it as usual
+            let device = self.device_ref.read();
+            let signal_semaphores = [vk::SemaphoreSubmitInfo::default()
+                .semaphore(self.frame_sync(frame_slot).image_acquired_semaphore)
+                .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)];
+            let submit_info = vk::SubmitInfo2::default().signal_semaphore_infos(&signal_semaphores);
+            unsafe {
+                device.queue_submit2(
+                    device.graphics_queue.handle,
+                    &[submit_info],
+                    vk::Fence::null(),
+                )
+            }
+            .map_err(NextImageAcquireError::OffscreenAcquisition)?;
+            drop(device);
+            self.current_image_index = Some(SwapchainImageIndex(0));
+            self.mark_image_acquired();
+
+            return Ok(NextImageState::Ok);
+        }
+
         match unsafe {
             self.loader.acquire_next_image(
                 self.handle,
                 u64::MAX,
-                self.image_acquired_semaphore,
+                self.frame_sync(frame_slot).image_acquired_semaphore,
                 vk::Fence::null(),
             )
         } {
             Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => Ok(NextImageState::OutOfDate),
             Ok((index, is_suboptimal)) => {
-                self.current_image_index = index as usize;
+                self.current_image_index = Some(SwapchainImageIndex(index));
+                self.mark_image_acquired();
 
                 match is_suboptimal {
                     false => Ok(NextImageState::Ok),
@@ -227,26 +830,56 @@ impl Swapchain {
         }
     }
 
-    pub fn current_image_resources(&mut self) -> ImageResources {
-        let image = self.images.get_mut(self.current_image_index).unwrap();
+    fn mark_image_acquired(&mut self) {
+        if let Some(unsubmitted_frame) = &mut self.unsubmitted_frame {
+            unsubmitted_frame.image_acquired = true;
+        }
+    }
+
+    pub fn current_image_resources(&mut self) -> ImageResources<'_> {
+        let index = self.current_image_index();
+        let image = &mut self.images[index.as_usize()];
         ImageResources {
             color_image: &mut image.color_attachment,
-            depth_image: &mut image.depth_attachment,
+            color_unorm_view: image.unorm_view,
+            depth_image: image
+                .depth_attachment
+                .as_mut()
+                .map(|depth_attachment| &mut depth_attachment.state),
         }
     }
 
     pub fn ensure_presentable(&mut self, &cmd_buffer: &vk::CommandBuffer) {
+        // offscreen images are read back rather than presented
+        // presentation waits on the render semaphore, which the submission signals after every
+        // stage, no stage of its own is needed
+        let (final_layout, dst_access_mask, dst_stage_mask) = match self.is_offscreen() {
+            true => (
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                vk::AccessFlags2::TRANSFER_READ,
+                vk::PipelineStageFlags2::ALL_TRANSFER,
+            ),
+            false => (
+                vk::ImageLayout::PRESENT_SRC_KHR,
+                vk::AccessFlags2::NONE,
+                vk::PipelineStageFlags2::NONE,
+            ),
+        };
         let current_image_res = self.current_image_resources();
 
         let mut image_barriers = vec![];
-        if current_image_res.color_image.layout != vk::ImageLayout::PRESENT_SRC_KHR {
+        if current_image_res.color_image.layout != final_layout {
+            let (src_stage_mask, src_access_mask) =
+                layout_src_scope(current_image_res.color_image.layout);
             image_barriers.push(
-                vk::ImageMemoryBarrier::default()
+                vk::ImageMemoryBarrier2::default()
                     .image(current_image_res.color_image.handle)
                     .old_layout(current_image_res.color_image.layout)
-                    .new_layout(vk::ImageLayout::PRESENT_SRC_KHR)
-                    .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
-                    .dst_access_mask(vk::AccessFlags::empty())
+                    .new_layout(final_layout)
+                    .src_stage_mask(src_stage_mask)
+                    .src_access_mask(src_access_mask)
+                    .dst_stage_mask(dst_stage_mask)
+                    .dst_access_mask(dst_access_mask)
                     .subresource_range(
                         vk::ImageSubresourceRange::default()
                             .aspect_mask(ImageAspectFlags::COLOR)
@@ -257,38 +890,141 @@ impl Swapchain {
                     ),
             );
 
-            current_image_res.color_image.layout = vk::ImageLayout::PRESENT_SRC_KHR;
+            current_image_res.color_image.layout = final_layout;
         }
 
+        if image_barriers.is_empty() {
+            return;
+        }
         let device = self.device_ref.read();
-        unsafe {
-            device.cmd_pipeline_barrier(
-                cmd_buffer,
-                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
-                vk::DependencyFlags::empty(),
-                &[],
-                &[],
-                &image_barriers,
-            )
-        };
+        let dependency_info = vk::DependencyInfo::default().image_memory_barriers(&image_barriers);
+        unsafe { device.cmd_pipeline_barrier2(cmd_buffer, &dependency_info) };
     }
 
-    pub fn present(&self) -> Result<(), PresentError> {
+    /// Presents the current image once its render semaphore, signaled by the last submission of
+    /// the frame, is.
+    pub fn present(&self) -> Result<PresentState, PresentError> {
         let device = self.device_ref.read();
+        let index = self.current_image_index();
 
-        unsafe {
+        if self.is_offscreen() {
+            // the render semaphore is still waited on, for it to be signaled again next frame
+            let wait_semaphores = [vk::SemaphoreSubmitInfo::default()
+                .semaphore(self.image(index).render_semaphore)
+                .stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)];
+            let submit_info = vk::SubmitInfo2::default().wait_semaphore_infos(&wait_semaphores);
+            unsafe {
+                device.queue_submit2(
+                    device.graphics_queue.handle,
+                    &[submit_info],
+                    vk::Fence::null(),
+                )
+            }
+            .map_err(PresentError::OffscreenPresent)?;
+
+            return Ok(PresentState::Presented);
+        }
+
+        match unsafe {
             self.loader.queue_present(
-                device.graphics_queue.handle,
+                device.present_queue.handle,
                 &vk::PresentInfoKHR::default()
-                    .wait_semaphores(&[self.images[self.current_image_index].render_semaphore])
+                    .wait_semaphores(&[self.image(index).render_semaphore])
                     .swapchains(&[self.handle])
-                    .image_indices(&[self.current_image_index as u32]),
+                    .image_indices(&[index.0]),
             )
+        } {
+            Ok(false) => Ok(PresentState::Presented),
+            Ok(true) => Ok(PresentState::PresentedSuboptimal),
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => Ok(PresentState::OutOfDate),
+            Err(err) => Err(PresentError::Present(err)),
         }
-        .map_err(PresentError::Present)?;
+    }
+}
 
-        Ok(())
+fn create_frame_syncs(device: &Device) -> Result<Vec<FrameSync>, SwapchainCreateError> {
+    let semaphore_info = vk::SemaphoreCreateInfo::default();
+    (0..FRAMES_IN_FLIGHT)
+        .map(|_| {
+            let image_acquired_semaphore =
+                unsafe { device.create_semaphore(&semaphore_info, None) }
+                    .map_err(SwapchainCreateError::RenderSyncObjectsCreation)?;
+            device.handle_registry.register(
+                image_acquired_semaphore,
+                "swapchain image acquired semaphore",
+            );
+
+            let fence_info = vk::FenceCreateInfo::default().flags(vk::FenceCreateFlags::SIGNALED);
+            let present_fence = unsafe { device.create_fence(&fence_info, None) }
+                .map_err(SwapchainCreateError::RenderSyncObjectsCreation)?;
+            device
+                .handle_registry
+                .register(present_fence, "swapchain present fence");
+
+            Ok(FrameSync {
+                image_acquired_semaphore,
+                present_fence,
+            })
+        })
+        .collect()
+}
+
+fn is_rotated_by_quarter(transform: vk::SurfaceTransformFlagsKHR) -> bool {
+    transform.intersects(
+        vk::SurfaceTransformFlagsKHR::ROTATE_90
+            | vk::SurfaceTransformFlagsKHR::ROTATE_270
+            | vk::SurfaceTransformFlagsKHR::HORIZONTAL_MIRROR_ROTATE_90
+            | vk::SurfaceTransformFlagsKHR::HORIZONTAL_MIRROR_ROTATE_270,
+    )
+}
+
+/// UNORM format with the same memory layout as an sRGB one.
+pub(crate) fn unorm_variant(format: vk::Format) -> Option<vk::Format> {
+    match format {
+        vk::Format::B8G8R8A8_SRGB => Some(vk::Format::B8G8R8A8_UNORM),
+        vk::Format::R8G8B8A8_SRGB => Some(vk::Format::R8G8B8A8_UNORM),
+        vk::Format::A8B8G8R8_SRGB_PACK32 => Some(vk::Format::A8B8G8R8_UNORM_PACK32),
+        _ => None,
+    }
+}
+
+/// The surface dictates the extent unless it reports the special `0xFFFFFFFF` value, in which case
+/// the suggested size is used. Either way, the extent must fit in the supported bounds.
+fn swapchain_extent(
+    capabilities: &vk::SurfaceCapabilitiesKHR,
+    suggested_size: vk::Extent2D,
+) -> vk::Extent2D {
+    let extent = match capabilities.current_extent {
+        vk::Extent2D {
+            width: u32::MAX,
+            height: u32::MAX,
+        } => suggested_size,
+        current_extent => current_extent,
+    };
+    // both are given in the orientation the user sees, images are in the one of the display
+    let extent = match is_rotated_by_quarter(capabilities.current_transform) {
+        true => vk::Extent2D {
+            width: extent.height,
+            height: extent.width,
+        },
+        false => extent,
+    };
+
+    vk::Extent2D {
+        width: extent.width.clamp(
+            capabilities.min_image_extent.width,
+            capabilities
+                .max_image_extent
+                .width
+                .max(capabilities.min_image_extent.width),
+        ),
+        height: extent.height.clamp(
+            capabilities.min_image_extent.height,
+            capabilities
+                .max_image_extent
+                .height
+                .max(capabilities.min_image_extent.height),
+        ),
     }
 }
 
@@ -296,15 +1032,315 @@ impl Drop for Swapchain {
     fn drop(&mut self) {
         let device = self.device_ref.read();
         log::debug!("Waiting for device to be idle before destroying swapchain");
-        unsafe { device.device_wait_idle() }.expect("device should wait before shutting down");
+        // a lost device is still torn down, resources can be destroyed whatever their GPU state
+        if let Err(err) = unsafe { device.device_wait_idle() } {
+            log::warn!("waiting for the device to be idle failed: {err}");
+        }
 
         log::debug!("destroying swapchain");
-        unsafe { device.destroy_fence(self.present_fence, None) };
-        unsafe { device.destroy_semaphore(self.image_acquired_semaphore, None) };
+        for frame_sync in &self.frame_syncs {
+            device.handle_registry.unregister(frame_sync.present_fence);
+            device
+                .handle_registry
+                .unregister(frame_sync.image_acquired_semaphore);
+            unsafe { device.destroy_fence(frame_sync.present_fence, None) };
+            unsafe { device.destroy_semaphore(frame_sync.image_acquired_semaphore, None) };
+        }
         for image in &self.images {
+            device.handle_registry.unregister(image.render_semaphore);
             unsafe { device.destroy_semaphore(image.render_semaphore, None) };
-            unsafe { device.destroy_image_view(image.color_attachment.view, None) };
+            if self.offscreen_image.is_none() {
+                device
+                    .handle_registry
+                    .unregister(image.color_attachment.view);
+                unsafe { device.destroy_image_view(image.color_attachment.view, None) };
+            }
+            if let Some(unorm_view) = image.unorm_view {
+                device.handle_registry.unregister(unorm_view);
+                unsafe { device.destroy_image_view(unorm_view, None) };
+            }
         }
-        unsafe { self.loader.destroy_swapchain(self.handle, None) };
+        if self.offscreen_image.is_none() {
+            unsafe { self.loader.destroy_swapchain(self.handle, None) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use ash::vk::Handle;
+
+    use super::*;
+    use crate::gfx::commands::is_frame_complete;
+
+    const UNDEFINED_EXTENT: vk::Extent2D = vk::Extent2D {
+        width: u32::MAX,
+        height: u32::MAX,
+    };
+
+    fn extent(width: u32, height: u32) -> vk::Extent2D {
+        vk::Extent2D { width, height }
+    }
+
+    fn capabilities(
+        current: vk::Extent2D,
+        min: vk::Extent2D,
+        max: vk::Extent2D,
+    ) -> vk::SurfaceCapabilitiesKHR {
+        vk::SurfaceCapabilitiesKHR {
+            current_extent: current,
+            min_image_extent: min,
+            max_image_extent: max,
+            current_transform: vk::SurfaceTransformFlagsKHR::IDENTITY,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn current_extent_wins_over_the_suggested_size() {
+        let capabilities = capabilities(extent(800, 600), extent(1, 1), extent(4096, 4096));
+
+        assert_eq!(
+            swapchain_extent(&capabilities, extent(1920, 1080)),
+            extent(800, 600)
+        );
+    }
+
+    #[test]
+    fn undefined_current_extent_uses_the_suggested_size() {
+        let capabilities = capabilities(UNDEFINED_EXTENT, extent(1, 1), extent(4096, 4096));
+
+        assert_eq!(
+            swapchain_extent(&capabilities, extent(1920, 1080)),
+            extent(1920, 1080)
+        );
+    }
+
+    #[test]
+    fn suggested_size_is_clamped_to_the_image_extent_bounds() {
+        let capabilities = capabilities(UNDEFINED_EXTENT, extent(64, 32), extent(2048, 1024));
+
+        assert_eq!(
+            swapchain_extent(&capabilities, extent(16, 4000)),
+            extent(64, 1024)
+        );
+        assert_eq!(
+            swapchain_extent(&capabilities, extent(5000, 8)),
+            extent(2048, 32)
+        );
+        // the bounds themselves are inclusive
+        assert_eq!(
+            swapchain_extent(&capabilities, extent(64, 32)),
+            extent(64, 32)
+        );
+        assert_eq!(
+            swapchain_extent(&capabilities, extent(2048, 1024)),
+            extent(2048, 1024)
+        );
+    }
+
+    #[test]
+    fn stale_current_extent_is_clamped_too() {
+        let capabilities = capabilities(extent(3000, 10), extent(100, 100), extent(2000, 2000));
+
+        assert_eq!(
+            swapchain_extent(&capabilities, extent(1, 1)),
+            extent(2000, 100)
+        );
+    }
+
+    #[test]
+    fn maximum_below_minimum_resolves_to_the_minimum() {
+        let capabilities = capabilities(UNDEFINED_EXTENT, extent(256, 256), extent(128, 0));
+
+        assert_eq!(
+            swapchain_extent(&capabilities, extent(1024, 64)),
+            extent(256, 256)
+        );
+    }
+
+    #[test]
+    fn zero_area_is_kept_when_the_surface_allows_it() {
+        let capabilities = capabilities(UNDEFINED_EXTENT, extent(0, 0), extent(4096, 4096));
+
+        assert_eq!(
+            swapchain_extent(&capabilities, extent(0, 720)),
+            extent(0, 720)
+        );
+    }
+
+    #[test]
+    fn quarter_rotations_swap_the_extent_before_clamping() {
+        let mut capabilities = capabilities(UNDEFINED_EXTENT, extent(1, 1), extent(2000, 1000));
+        capabilities.current_transform = vk::SurfaceTransformFlagsKHR::ROTATE_90;
+
+        assert_eq!(
+            swapchain_extent(&capabilities, extent(1800, 1200)),
+            extent(1200, 1000)
+        );
+    }
+
+    const FIFO: vk::PresentModeKHR = vk::PresentModeKHR::FIFO;
+    const FIFO_RELAXED: vk::PresentModeKHR = vk::PresentModeKHR::FIFO_RELAXED;
+    const MAILBOX: vk::PresentModeKHR = vk::PresentModeKHR::MAILBOX;
+    const IMMEDIATE: vk::PresentModeKHR = vk::PresentModeKHR::IMMEDIATE;
+
+    fn assert_present_modes(
+        cases: &[(PresentPreference, &[vk::PresentModeKHR], vk::PresentModeKHR)],
+    ) {
+        for &(preference, available, expected) in cases {
+            assert_eq!(
+                preference.select(available),
+                expected,
+                "{preference:?} among {available:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn preferred_present_mode_is_used_when_available() {
+        let all = [FIFO, FIFO_RELAXED, MAILBOX, IMMEDIATE];
+        assert_present_modes(&[
+            (PresentPreference::Vsync, &all, FIFO),
+            (PresentPreference::LowLatency, &all, MAILBOX),
+            (PresentPreference::Immediate, &all, IMMEDIATE),
+            (
+                PresentPreference::Explicit(FIFO_RELAXED),
+                &all,
+                FIFO_RELAXED,
+            ),
+            (PresentPreference::Explicit(MAILBOX), &all, MAILBOX),
+        ]);
+    }
+
+    #[test]
+    fn missing_present_modes_fall_back_in_order() {
+        assert_present_modes(&[
+            (PresentPreference::Immediate, &[FIFO, MAILBOX], MAILBOX),
+            (PresentPreference::Immediate, &[MAILBOX, FIFO], MAILBOX),
+            (PresentPreference::Immediate, &[FIFO, FIFO_RELAXED], FIFO),
+            (PresentPreference::LowLatency, &[FIFO, IMMEDIATE], FIFO),
+            (
+                PresentPreference::Explicit(FIFO_RELAXED),
+                &[FIFO, MAILBOX],
+                FIFO,
+            ),
+            (
+                PresentPreference::Explicit(IMMEDIATE),
+                &[MAILBOX, FIFO],
+                FIFO,
+            ),
+        ]);
+    }
+
+    #[test]
+    fn fifo_only_surfaces_always_get_fifo() {
+        for preference in [
+            PresentPreference::Vsync,
+            PresentPreference::LowLatency,
+            PresentPreference::Immediate,
+            PresentPreference::Explicit(MAILBOX),
+            PresentPreference::Explicit(FIFO),
+        ] {
+            assert_present_modes(&[(preference, &[FIFO], FIFO), (preference, &[], FIFO)]);
+            assert_eq!(preference.candidates().last(), Some(&FIFO));
+        }
+    }
+
+    fn image_context(render_semaphore: u64) -> ImageContext {
+        ImageContext {
+            color_attachment: ImageState {
+                handle: vk::Image::null(),
+                view: vk::ImageView::null(),
+                layout: vk::ImageLayout::UNDEFINED,
+                format: vk::Format::B8G8R8A8_SRGB,
+                usage: vk::ImageUsageFlags::COLOR_ATTACHMENT,
+                extent: vk::Extent3D::default(),
+                extent_2d: vk::Extent2D::default(),
+                view_subresource_range: vk::ImageSubresourceRange::default(),
+            },
+            unorm_view: None,
+            depth_attachment: None,
+            render_semaphore: vk::Semaphore::from_raw(render_semaphore),
+        }
+    }
+
+    // Runs frames against a presentation engine handing images back in a scrambled order, checking
+    // that no semaphore is signaled again while a wait on its previous signal is still pending.
+    fn simulate_acquire_present_pairing(image_count: u64, frame_count: u64) {
+        let frame_syncs = (0..FRAMES_IN_FLIGHT as u64)
+            .map(|slot| FrameSync {
+                image_acquired_semaphore: vk::Semaphore::from_raw(100 + slot),
+                present_fence: vk::Fence::from_raw(200 + slot),
+            })
+            .collect::<Vec<_>>();
+        let images = (0..image_count)
+            .map(|index| image_context(300 + index))
+            .collect::<Vec<_>>();
+
+        // frames whose submissions may still wait on their acquire semaphore
+        let mut frames_in_flight = Vec::new();
+        // render semaphores still waited on by a presentation, with the image they present
+        let mut pending_presents = HashMap::new();
+        let mut seed = 0x2545_f491_u64;
+        for frame in 0..frame_count {
+            let frame_slot = FrameSlotIndex::from_frame_counter(frame, FRAMES_IN_FLIGHT);
+
+            // waiting on the fence of the slot completes every frame recorded FRAMES_IN_FLIGHT ago
+            frames_in_flight
+                .retain(|&(previous_frame, _)| !is_frame_complete(previous_frame, frame));
+
+            seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1);
+            let image_index = SwapchainImageIndex(((seed >> 33) % image_count) as u32);
+            // acquiring an image means the engine is done with its last presentation
+            pending_presents.retain(|_, &mut presented| presented != image_index);
+
+            let semaphores = FrameSemaphores::pair(
+                &frame_syncs[frame_slot.as_usize()],
+                &images[image_index.as_usize()],
+            );
+            assert_eq!(
+                semaphores.present_fence,
+                frame_syncs[frame_slot.as_usize()].present_fence
+            );
+
+            assert!(
+                frames_in_flight
+                    .iter()
+                    .all(|&(_, semaphore)| semaphore != semaphores.image_acquired),
+                "frame {frame} acquired with a semaphore an unfinished frame waits on"
+            );
+            frames_in_flight.push((frame, semaphores.image_acquired));
+            assert!(
+                !pending_presents.contains_key(&semaphores.render_finished),
+                "frame {frame} signaled the render semaphore of a pending presentation"
+            );
+
+            pending_presents.insert(semaphores.render_finished, image_index);
+        }
+    }
+
+    #[test]
+    fn acquire_and_present_semaphores_are_never_reused_while_pending() {
+        for image_count in 1..=4 {
+            simulate_acquire_present_pairing(image_count, 256);
+        }
+    }
+
+    #[test]
+    fn render_semaphores_follow_the_image_not_the_frame_slot() {
+        let frame_sync = FrameSync {
+            image_acquired_semaphore: vk::Semaphore::from_raw(1),
+            present_fence: vk::Fence::from_raw(2),
+        };
+
+        let first = FrameSemaphores::pair(&frame_sync, &image_context(10));
+        let second = FrameSemaphores::pair(&frame_sync, &image_context(11));
+
+        assert_eq!(first.image_acquired, second.image_acquired);
+        assert_eq!(first.render_finished.as_raw(), 10);
+        assert_eq!(second.render_finished.as_raw(), 11);
     }
 }
@@ -0,0 +1,248 @@
+use ash::vk;
+use thiserror::Error;
+
+use crate::utils::ThreadSafeRwRef;
+
+use super::{
+    allocator::AllocTag,
+    context::Context,
+    device::Device,
+    image::{Image, ImageBuildError},
+    sampler::{Sampler, SamplerCreateError},
+    staging::{ImageDestination, StagingWriteError},
+};
+
+#[derive(Debug, Error)]
+pub enum TextureCreateError {
+    #[error("{width}x{height} texture expects {expected} bytes of RGBA8 pixels, got {actual}")]
+    PixelCount {
+        width: u32,
+        height: u32,
+        expected: usize,
+        actual: usize,
+    },
+
+    #[error("image creation failed")]
+    ImageCreation(#[from] ImageBuildError),
+
+    #[error("pixel staging failed")]
+    Staging(#[from] StagingWriteError),
+
+    #[error("sampler creation failed")]
+    SamplerCreation(#[from] SamplerCreateError),
+
+    #[error("vulkan call to create the descriptor set layout failed")]
+    SetLayoutCreation(vk::Result),
+
+    #[error("vulkan call to create the descriptor pool failed")]
+    DescriptorPoolCreation(vk::Result),
+
+    #[error("vulkan call to allocate the descriptor set failed")]
+    DescriptorSetAllocation(vk::Result),
+}
+
+/// Sampled 2D image along with the descriptor set binding it, as a combined image sampler at
+/// binding 0 visible to fragment shaders:
+/// `layout(set = N, binding = 0) uniform sampler2D tex;`
+pub struct Texture {
+    image: Image,
+    sampler: Sampler,
+    set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_set: vk::DescriptorSet,
+
+    // bookkeeping
+    device_ref: ThreadSafeRwRef<Device>,
+}
+
+impl Texture {
+    /// Creates an sRGB texture sampled with linear filtering and repeating coordinates. Its pixels
+    /// are copied at the start of the next rendered frame, before any pass samples it.
+    pub fn from_rgba8(
+        name: &str,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+        ctx: &mut Context,
+    ) -> Result<Self, TextureCreateError> {
+        let expected = width as usize * height as usize * 4;
+        if pixels.len() != expected {
+            return Err(TextureCreateError::PixelCount {
+                width,
+                height,
+                expected,
+                actual: pixels.len(),
+            });
+        }
+
+        let format = vk::Format::R8G8B8A8_SRGB;
+        let extent = vk::Extent3D {
+            width,
+            height,
+            depth: 1,
+        };
+        let subresource_range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let mut image_create_info = Image::create_info().with_tag(AllocTag::Texture);
+        image_create_info.name = name;
+        image_create_info.image_info = vk::ImageCreateInfo::default()
+            .extent(extent)
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(format)
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+        image_create_info.image_view_info = vk::ImageViewCreateInfo::default()
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(format)
+            .subresource_range(subresource_range);
+        let mut image = image_create_info.build(ctx)?;
+
+        let staged = ctx.staging_belt().write(pixels)?;
+        ctx.staging_belt().copy_to_image(
+            staged,
+            ImageDestination {
+                image: image.state.handle,
+                current_layout: vk::ImageLayout::UNDEFINED,
+                final_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: 0,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                },
+                offset: vk::Offset3D::default(),
+                extent,
+            },
+        );
+        image.state.layout = vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL;
+
+        let device_ref = ctx.core.device_ref.clone();
+        let sampler_info = vk::SamplerCreateInfo::default()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+            .address_mode_u(vk::SamplerAddressMode::REPEAT)
+            .address_mode_v(vk::SamplerAddressMode::REPEAT)
+            .address_mode_w(vk::SamplerAddressMode::REPEAT);
+        let sampler = Sampler::new(name, &sampler_info, device_ref.clone())?;
+
+        let device = device_ref.read();
+        let binding = vk::DescriptorSetLayoutBinding::default()
+            .binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1)
+            .stage_flags(vk::ShaderStageFlags::FRAGMENT);
+        let layout_info =
+            vk::DescriptorSetLayoutCreateInfo::default().bindings(std::slice::from_ref(&binding));
+        let set_layout = unsafe { device.create_descriptor_set_layout(&layout_info, None) }
+            .map_err(TextureCreateError::SetLayoutCreation)?;
+
+        let pool_size = vk::DescriptorPoolSize::default()
+            .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .descriptor_count(1);
+        let pool_info = vk::DescriptorPoolCreateInfo::default()
+            .max_sets(1)
+            .pool_sizes(std::slice::from_ref(&pool_size));
+        let descriptor_pool = match unsafe { device.create_descriptor_pool(&pool_info, None) } {
+            Ok(pool) => pool,
+            Err(err) => {
+                unsafe { device.destroy_descriptor_set_layout(set_layout, None) };
+                return Err(TextureCreateError::DescriptorPoolCreation(err));
+            }
+        };
+
+        let allocate_info = vk::DescriptorSetAllocateInfo::default()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(std::slice::from_ref(&set_layout));
+        let descriptor_set = match unsafe { device.allocate_descriptor_sets(&allocate_info) } {
+            Ok(sets) => sets[0],
+            Err(err) => {
+                unsafe { device.destroy_descriptor_pool(descriptor_pool, None) };
+                unsafe { device.destroy_descriptor_set_layout(set_layout, None) };
+                return Err(TextureCreateError::DescriptorSetAllocation(err));
+            }
+        };
+
+        let image_info = vk::DescriptorImageInfo::default()
+            .sampler(sampler.handle)
+            .image_view(image.state.view)
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL);
+        let write = vk::WriteDescriptorSet::default()
+            .dst_set(descriptor_set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(std::slice::from_ref(&image_info));
+        unsafe { device.update_descriptor_sets(&[write], &[]) };
+        drop(device);
+
+        Ok(Self {
+            image,
+            sampler,
+            set_layout,
+            descriptor_pool,
+            descriptor_set,
+            device_ref,
+        })
+    }
+
+    /// Layout to give to the pipelines sampling the texture, see
+    /// [`with_descriptor_set_layouts`](super::pipeline::GraphicsPipelineBuilder::with_descriptor_set_layouts).
+    pub fn set_layout(&self) -> vk::DescriptorSetLayout {
+        self.set_layout
+    }
+
+    pub fn descriptor_set(&self) -> vk::DescriptorSet {
+        self.descriptor_set
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        self.image.state.extent_2d
+    }
+
+    pub fn cmd_bind(
+        &self,
+        cmd_buffer: &vk::CommandBuffer,
+        pipeline_layout: vk::PipelineLayout,
+        set: u32,
+        device: &Device,
+    ) {
+        unsafe {
+            device.cmd_bind_descriptor_sets(
+                *cmd_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline_layout,
+                set,
+                &[self.descriptor_set],
+                &[],
+            )
+        };
+    }
+}
+
+impl std::fmt::Debug for Texture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Texture")
+            .field("name", &self.image.name)
+            .field("extent", &self.image.state.extent_2d)
+            .field("sampler", &self.sampler)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for Texture {
+    fn drop(&mut self) {
+        let device = self.device_ref.read();
+
+        unsafe { device.destroy_descriptor_pool(self.descriptor_pool, None) };
+        unsafe { device.destroy_descriptor_set_layout(self.set_layout, None) };
+    }
+}
@@ -0,0 +1,131 @@
+use std::time::Duration;
+
+use ash::vk;
+use thiserror::Error;
+
+use crate::utils::ThreadSafeRwRef;
+
+use super::device::Device;
+
+#[derive(Debug, Error)]
+pub enum TimelineSemaphoreError {
+    #[error("vulkan creation of the timeline semaphore failed")]
+    VulkanCreation(vk::Result),
+
+    #[error("vulkan call to signal the timeline semaphore failed")]
+    Signal(vk::Result),
+
+    #[error("vulkan call to wait on the timeline semaphore failed")]
+    Wait(vk::Result),
+
+    #[error("vulkan call to get the timeline semaphore value failed")]
+    ValueQuery(vk::Result),
+}
+
+/// Semaphore holding a value which only grows, signaled and waited on by queues as well as by the
+/// host. Waiting on a value is waiting for every signal up to it, unlike binary semaphores which
+/// can only be waited on once per signal.
+pub struct TimelineSemaphore {
+    pub name: String,
+    pub handle: vk::Semaphore,
+
+    // bookkeeping
+    device_ref: ThreadSafeRwRef<Device>,
+}
+
+impl TimelineSemaphore {
+    pub fn new(
+        name: &str,
+        initial_value: u64,
+        device_ref: ThreadSafeRwRef<Device>,
+    ) -> Result<Self, TimelineSemaphoreError> {
+        let device = device_ref.read();
+        let mut type_info = vk::SemaphoreTypeCreateInfo::default()
+            .semaphore_type(vk::SemaphoreType::TIMELINE)
+            .initial_value(initial_value);
+        let create_info = vk::SemaphoreCreateInfo::default().push_next(&mut type_info);
+        let handle = unsafe { device.create_semaphore(&create_info, None) }
+            .map_err(TimelineSemaphoreError::VulkanCreation)?;
+        device.handle_registry.register(handle, name);
+        drop(device);
+
+        Ok(Self {
+            name: name.to_owned(),
+            handle,
+            device_ref,
+        })
+    }
+
+    /// Signals `value` from the host, which must be greater than the current one and than any
+    /// value still pending on a queue.
+    pub fn signal(&self, value: u64) -> Result<(), TimelineSemaphoreError> {
+        let signal_info = vk::SemaphoreSignalInfo::default()
+            .semaphore(self.handle)
+            .value(value);
+        unsafe { self.device_ref.read().signal_semaphore(&signal_info) }
+            .map_err(TimelineSemaphoreError::Signal)
+    }
+
+    /// Blocks until the semaphore reaches `value`, returns `false` if `timeout` elapsed first.
+    pub fn wait(&self, value: u64, timeout: Duration) -> Result<bool, TimelineSemaphoreError> {
+        let semaphores = [self.handle];
+        let values = [value];
+        let wait_info = vk::SemaphoreWaitInfo::default()
+            .semaphores(&semaphores)
+            .values(&values);
+        let timeout = u64::try_from(timeout.as_nanos()).unwrap_or(u64::MAX);
+        match unsafe { self.device_ref.read().wait_semaphores(&wait_info, timeout) } {
+            Ok(()) => Ok(true),
+            Err(vk::Result::TIMEOUT) => Ok(false),
+            Err(err) => Err(TimelineSemaphoreError::Wait(err)),
+        }
+    }
+
+    pub fn current_value(&self) -> Result<u64, TimelineSemaphoreError> {
+        unsafe {
+            self.device_ref
+                .read()
+                .get_semaphore_counter_value(self.handle)
+        }
+        .map_err(TimelineSemaphoreError::ValueQuery)
+    }
+
+    /// Waits on `value` at `stage_mask` in a queue submission.
+    pub fn wait_info(
+        &self,
+        value: u64,
+        stage_mask: vk::PipelineStageFlags2,
+    ) -> vk::SemaphoreSubmitInfo<'static> {
+        vk::SemaphoreSubmitInfo::default()
+            .semaphore(self.handle)
+            .value(value)
+            .stage_mask(stage_mask)
+    }
+
+    /// Signals `value` once the commands of a queue submission are done with `stage_mask`.
+    pub fn signal_info(
+        &self,
+        value: u64,
+        stage_mask: vk::PipelineStageFlags2,
+    ) -> vk::SemaphoreSubmitInfo<'static> {
+        self.wait_info(value, stage_mask)
+    }
+}
+
+impl std::fmt::Debug for TimelineSemaphore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TimelineSemaphore")
+            .field("name", &self.name)
+            .field("handle", &self.handle)
+            .finish()
+    }
+}
+
+impl Drop for TimelineSemaphore {
+    fn drop(&mut self) {
+        let device = self.device_ref.read();
+
+        device.handle_registry.unregister(self.handle);
+        unsafe { device.destroy_semaphore(self.handle, None) };
+    }
+}
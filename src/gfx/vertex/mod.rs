@@ -1,7 +1,9 @@
+pub mod point_cloud;
 pub mod simple;
 
 use ash::vk;
-use ply_rs::ply;
+#[cfg(feature = "ply")]
+use ply_rs::{parser, ply};
 
 pub struct VertexInputDescription {
     pub bindings: Vec<vk::VertexInputBindingDescription>,
@@ -19,10 +21,12 @@ pub trait Vertex: Copy + Sync + Send + 'static + std::fmt::Debug {
 }
 
 // Utilities for ser/deser
+#[cfg(feature = "ply")]
 pub(crate) struct Face {
     indices: Vec<u32>,
 }
 
+#[cfg(feature = "ply")]
 impl ply::PropertyAccess for Face {
     fn new() -> Self {
         Self {
@@ -38,3 +42,37 @@ impl ply::PropertyAccess for Face {
         }
     }
 }
+
+// Reads every vertex and face element of a PLY file, faces being absent for point clouds
+#[cfg(feature = "ply")]
+pub(crate) fn read_ply<VertexType>(
+    path: &std::path::Path,
+) -> Result<(Vec<VertexType>, Vec<Face>), std::io::Error>
+where
+    VertexType: ply::PropertyAccess,
+{
+    let file = std::fs::File::open(path)?;
+    let mut file = std::io::BufReader::new(file);
+
+    let vertex_parser = parser::Parser::<VertexType>::new();
+    let face_parser = parser::Parser::<Face>::new();
+
+    let header = vertex_parser.read_header(&mut file)?;
+
+    let mut vertices = vec![];
+    let mut faces = vec![];
+    for (_, element) in &header.elements {
+        #[allow(clippy::single_match)]
+        match element.name.as_ref() {
+            "vertex" => {
+                vertices = vertex_parser.read_payload_for_element(&mut file, element, &header)?;
+            }
+            "face" => {
+                faces = face_parser.read_payload_for_element(&mut file, element, &header)?;
+            }
+            _ => (),
+        }
+    }
+
+    Ok((vertices, faces))
+}
@@ -0,0 +1,83 @@
+use std::mem::offset_of;
+
+use ash::vk;
+#[cfg(feature = "ply")]
+use ply_rs::ply;
+
+use crate::math::Vec3;
+
+use super::{Vertex, VertexInputDescription};
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct PointCloudVertex {
+    pub position: Vec3,
+    pub color: Vec3,
+}
+
+// SAFETY: only made of f32 triplets, without padding
+unsafe impl bytemuck::Zeroable for PointCloudVertex {}
+unsafe impl bytemuck::Pod for PointCloudVertex {}
+
+impl Vertex for PointCloudVertex {
+    fn vertex_input_description() -> VertexInputDescription {
+        let main_binding = vk::VertexInputBindingDescription::default()
+            .binding(0)
+            .stride(
+                std::mem::size_of::<PointCloudVertex>()
+                    .try_into()
+                    .expect("unsupported architecture"),
+            )
+            .input_rate(vk::VertexInputRate::VERTEX);
+
+        let position = vk::VertexInputAttributeDescription::default()
+            .location(0)
+            .binding(0)
+            .format(vk::Format::R32G32B32_SFLOAT)
+            .offset(
+                offset_of!(PointCloudVertex, position)
+                    .try_into()
+                    .expect("unsupported architecture"),
+            );
+        let color = vk::VertexInputAttributeDescription::default()
+            .location(1)
+            .binding(0)
+            .format(vk::Format::R32G32B32_SFLOAT)
+            .offset(
+                offset_of!(PointCloudVertex, color)
+                    .try_into()
+                    .expect("unsupported architecture"),
+            );
+
+        VertexInputDescription {
+            bindings: vec![main_binding],
+            attributes: vec![position, color],
+        }
+    }
+}
+
+#[cfg(feature = "ply")]
+impl ply::PropertyAccess for PointCloudVertex {
+    fn new() -> Self {
+        Self {
+            position: Vec3::default(),
+            // points without color information should still be visible
+            color: Vec3::ONE,
+        }
+    }
+
+    fn set_property(&mut self, key: String, property: ply::Property) {
+        match (key.as_ref(), property) {
+            ("x", ply::Property::Float(v)) => self.position.x = v,
+            ("y", ply::Property::Float(v)) => self.position.y = v,
+            ("z", ply::Property::Float(v)) => self.position.z = v,
+            ("red", ply::Property::UChar(v)) => self.color.x = v as f32 / 255.0,
+            ("green", ply::Property::UChar(v)) => self.color.y = v as f32 / 255.0,
+            ("blue", ply::Property::UChar(v)) => self.color.z = v as f32 / 255.0,
+            ("red", ply::Property::Float(v)) => self.color.x = v,
+            ("green", ply::Property::Float(v)) => self.color.y = v,
+            ("blue", ply::Property::Float(v)) => self.color.z = v,
+            (_, _) => (),
+        }
+    }
+}
@@ -1,19 +1,23 @@
 use std::mem::offset_of;
 
 use ash::vk;
-use ply_rs::{parser, ply};
+#[cfg(feature = "ply")]
+use ply_rs::ply;
 use thiserror::Error;
 
+use crate::{gfx::mesh::MeshDataUploadError, math::Vec3};
+#[cfg(any(feature = "obj", feature = "ply"))]
 use crate::{
     gfx::{
         context::Context,
-        mesh::{Mesh, MeshDataUploadError, upload_mesh_data},
+        mesh::{Mesh, MeshTopology, upload_mesh_data},
     },
-    math::Vec3,
     utils::ThreadSafeRef,
 };
 
-use super::{Face, Vertex, VertexInputDescription};
+#[cfg(feature = "ply")]
+use super::{Face, read_ply};
+use super::{Vertex, VertexInputDescription};
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
@@ -21,6 +25,10 @@ pub struct SimpleVertex {
     pub position: Vec3,
 }
 
+// SAFETY: only made of f32 triplets, without padding
+unsafe impl bytemuck::Zeroable for SimpleVertex {}
+unsafe impl bytemuck::Pod for SimpleVertex {}
+
 impl Vertex for SimpleVertex {
     fn vertex_input_description() -> VertexInputDescription {
         let main_binding = vk::VertexInputBindingDescription::default()
@@ -49,6 +57,7 @@ impl Vertex for SimpleVertex {
     }
 }
 
+#[cfg(feature = "ply")]
 impl ply::PropertyAccess for SimpleVertex {
     fn new() -> Self {
         Self {
@@ -68,6 +77,7 @@ impl ply::PropertyAccess for SimpleVertex {
 
 #[derive(Error, Debug)]
 pub enum SimpleVertexMeshLoadingError {
+    #[cfg(feature = "obj")]
     #[error("obj file loading failed")]
     OBJLoad(#[from] tobj::LoadError),
 
@@ -76,9 +86,22 @@ pub enum SimpleVertexMeshLoadingError {
 
     #[error("file reading failed")]
     FileReadingError(#[from] std::io::Error),
+
+    #[cfg(feature = "ply")]
+    #[error("file has no faces, load it as a point cloud instead")]
+    NoFaces,
+
+    #[cfg(feature = "ply")]
+    #[error("face {face} references vertex {index}, but the file only has {vertex_count}")]
+    InvalidFaceIndex {
+        face: usize,
+        index: u32,
+        vertex_count: usize,
+    },
 }
 
 impl SimpleVertex {
+    #[cfg(feature = "obj")]
     pub fn load_model_from_path_obj(
         path: &std::path::Path,
         ctx: &mut Context,
@@ -90,28 +113,7 @@ impl SimpleVertex {
             .unwrap_or("<invalid>")
             .to_owned();
 
-        let (load_result, _) = tobj::load_obj(
-            path,
-            &tobj::LoadOptions {
-                triangulate: true,
-                single_index: true,
-                ..Default::default()
-            },
-        )?;
-
-        let mesh = &load_result[0].mesh;
-
-        let positions = mesh
-            .positions
-            .chunks_exact(3)
-            .map(|slice| Vec3::new(slice[0], slice[1], slice[2]))
-            .collect::<Vec<Vec3>>();
-
-        let mut vertices = Vec::with_capacity(positions.len());
-        for position in positions {
-            vertices.push(SimpleVertex { position });
-        }
-        let indices = mesh.indices.clone();
+        let (vertices, indices) = Self::read_obj(path)?;
 
         let upload_result = upload_mesh_data(&name, &vertices, &indices, ctx)?;
 
@@ -119,11 +121,13 @@ impl SimpleVertex {
             name,
             vertices,
             indices,
+            topology: MeshTopology::TriangleList,
             vertex_buffer: upload_result.vertex_buffer,
             index_buffer: upload_result.index_buffer,
         }))
     }
 
+    #[cfg(feature = "ply")]
     pub fn load_model_from_path_ply(
         path: &std::path::Path,
         ctx: &mut Context,
@@ -135,34 +139,7 @@ impl SimpleVertex {
             .unwrap_or("<invalid>")
             .to_owned();
 
-        let file = std::fs::File::open(path)?;
-        let mut file = std::io::BufReader::new(file);
-
-        let vertex_parser = parser::Parser::<Self>::new();
-        let face_parser = parser::Parser::<Face>::new();
-
-        let header = vertex_parser.read_header(&mut file)?;
-
-        let mut vertices = vec![];
-        let mut faces = vec![];
-        for (_, element) in &header.elements {
-            #[allow(clippy::single_match)]
-            match element.name.as_ref() {
-                "vertex" => {
-                    vertices =
-                        vertex_parser.read_payload_for_element(&mut file, element, &header)?;
-                }
-                "face" => {
-                    faces = face_parser.read_payload_for_element(&mut file, element, &header)?;
-                }
-                _ => (),
-            }
-        }
-
-        let mut indices = Vec::with_capacity(faces.len() * 3);
-        for face in faces {
-            indices.extend(face.indices.iter());
-        }
+        let (vertices, indices) = Self::read_ply(path)?;
 
         let upload_result = upload_mesh_data(&name, &vertices, &indices, ctx)?;
 
@@ -170,8 +147,176 @@ impl SimpleVertex {
             name,
             vertices,
             indices,
+            topology: MeshTopology::TriangleList,
             vertex_buffer: upload_result.vertex_buffer,
             index_buffer: upload_result.index_buffer,
         }))
     }
+
+    /// Reads the vertices and indices of the first model of an OBJ file, without uploading them.
+    #[cfg(feature = "obj")]
+    pub fn read_obj(
+        path: &std::path::Path,
+    ) -> Result<(Vec<Self>, Vec<u32>), SimpleVertexMeshLoadingError> {
+        let (load_result, _) = tobj::load_obj(
+            path,
+            &tobj::LoadOptions {
+                triangulate: true,
+                single_index: true,
+                ..Default::default()
+            },
+        )?;
+
+        let mesh = &load_result[0].mesh;
+
+        let positions = mesh
+            .positions
+            .chunks_exact(3)
+            .map(|slice| Vec3::new(slice[0], slice[1], slice[2]))
+            .collect::<Vec<Vec3>>();
+
+        let mut vertices = Vec::with_capacity(positions.len());
+        for position in positions {
+            vertices.push(SimpleVertex { position });
+        }
+        let indices = mesh.indices.clone();
+
+        Ok((vertices, indices))
+    }
+
+    /// Reads the vertices and faces of a PLY file, without uploading them. Faces with more than
+    /// 3 vertices are triangulated as fans, degenerate ones are skipped.
+    #[cfg(feature = "ply")]
+    pub fn read_ply(
+        path: &std::path::Path,
+    ) -> Result<(Vec<Self>, Vec<u32>), SimpleVertexMeshLoadingError> {
+        let (vertices, faces) = read_ply::<Self>(path)?;
+        if faces.is_empty() {
+            return Err(SimpleVertexMeshLoadingError::NoFaces);
+        }
+
+        let indices = triangulate_faces(&faces, vertices.len())?;
+
+        Ok((vertices, indices))
+    }
+}
+
+// Faces with fewer than 3 distinct vertices would only produce zero-area triangles
+#[cfg(feature = "ply")]
+fn triangulate_faces(
+    faces: &[Face],
+    vertex_count: usize,
+) -> Result<Vec<u32>, SimpleVertexMeshLoadingError> {
+    let mut indices = Vec::with_capacity(faces.len() * 3);
+    let mut degenerate_count = 0;
+    for (face_index, face) in faces.iter().map(|face| &face.indices).enumerate() {
+        if let Some(&index) = face.iter().find(|&&index| index as usize >= vertex_count) {
+            return Err(SimpleVertexMeshLoadingError::InvalidFaceIndex {
+                face: face_index,
+                index,
+                vertex_count,
+            });
+        }
+
+        let mut distinct = face.to_vec();
+        distinct.sort_unstable();
+        distinct.dedup();
+        if distinct.len() < 3 {
+            degenerate_count += 1;
+            continue;
+        }
+
+        for corner in 1..face.len() - 1 {
+            indices.extend([face[0], face[corner], face[corner + 1]]);
+        }
+    }
+
+    if degenerate_count > 0 {
+        log::warn!(
+            "skipped {degenerate_count} degenerate faces with fewer than 3 distinct vertices"
+        );
+    }
+
+    Ok(indices)
+}
+
+#[cfg(all(test, feature = "ply"))]
+mod tests {
+    use super::*;
+
+    fn faces(faces: &[&[u32]]) -> Vec<Face> {
+        faces
+            .iter()
+            .map(|indices| Face {
+                indices: indices.to_vec(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn quads_become_two_fan_triangles() {
+        let indices = triangulate_faces(&faces(&[&[0, 1, 2, 3]]), 4).unwrap();
+
+        assert_eq!(indices, [0, 1, 2, 0, 2, 3]);
+    }
+
+    #[test]
+    fn ngons_are_fanned_from_their_first_corner() {
+        let indices = triangulate_faces(&faces(&[&[0, 1, 2, 3, 4], &[5, 6, 7]]), 8).unwrap();
+
+        assert_eq!(indices, [0, 1, 2, 0, 2, 3, 0, 3, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn degenerate_faces_are_skipped() {
+        let indices =
+            triangulate_faces(&faces(&[&[0, 0, 1], &[2, 2, 2, 2], &[0, 1, 2], &[1]]), 3).unwrap();
+
+        assert_eq!(indices, [0, 1, 2]);
+    }
+
+    #[test]
+    fn out_of_range_indices_are_reported_with_their_face() {
+        let result = triangulate_faces(&faces(&[&[0, 1, 2], &[0, 2, 3, 7]]), 4);
+
+        assert!(matches!(
+            result,
+            Err(SimpleVertexMeshLoadingError::InvalidFaceIndex {
+                face: 1,
+                index: 7,
+                vertex_count: 4,
+            })
+        ));
+    }
+
+    #[test]
+    fn ascii_ply_with_mixed_faces_is_triangulated() {
+        let ply = "ply\n\
+                   format ascii 1.0\n\
+                   element vertex 5\n\
+                   property float x\n\
+                   property float y\n\
+                   property float z\n\
+                   element face 3\n\
+                   property list uchar uint vertex_indices\n\
+                   end_header\n\
+                   0 0 0\n\
+                   1 0 0\n\
+                   1 1 0\n\
+                   0 1 0\n\
+                   0.5 1.5 0\n\
+                   4 0 1 2 3\n\
+                   5 0 1 2 4 3\n\
+                   3 1 1 2\n";
+        let path = std::env::temp_dir().join(format!("miel-simple-{}.ply", std::process::id()));
+        std::fs::write(&path, ply).unwrap();
+
+        let result = SimpleVertex::read_ply(&path);
+        std::fs::remove_file(&path).unwrap();
+        let (vertices, indices) = result.unwrap();
+
+        assert_eq!(vertices.len(), 5);
+        assert_eq!(vertices[4].position, Vec3::new(0.5, 1.5, 0.0));
+        assert_eq!(indices, [0, 1, 2, 0, 2, 3, 0, 1, 2, 0, 2, 4, 0, 4, 3]);
+    }
 }
@@ -0,0 +1,145 @@
+use std::{collections::BTreeSet, time::Duration};
+
+use winit::{
+    event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent},
+    keyboard::{KeyCode, PhysicalKey},
+    platform::scancode::PhysicalKeyExtScancode,
+};
+
+/// Snapshot of the keyboard and mouse at the start of a frame.
+///
+/// Keys are kept as platform scancodes, which is what replay logs store, so that a replayed
+/// snapshot compares equal to the recorded one.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InputState {
+    pub(crate) pressed_keys: BTreeSet<u32>,
+    pub mouse: MouseState,
+}
+
+/// Mouse state at the start of a frame. Deltas are summed over every event received since the
+/// previous frame.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MouseState {
+    /// In physical pixels from the top-left of the window, `None` while the cursor is outside.
+    pub position: Option<[f32; 2]>,
+    /// Cursor motion in physical pixels, only while the cursor is inside the window. Raw device
+    /// motion while the cursor is [locked](crate::gfx::context::CursorMode::Locked).
+    pub delta: [f32; 2],
+    /// Unaccelerated device motion, which keeps coming when the cursor is grabbed or hits the
+    /// edge of the screen. Only accumulated while the window is focused.
+    pub raw_delta: [f32; 2],
+    /// Wheel motion reported in lines, by most mice.
+    pub scroll_lines: [f32; 2],
+    /// Wheel motion reported in pixels, by touchpads.
+    pub scroll_pixels: [f32; 2],
+    pub(crate) pressed_buttons: u32,
+}
+
+impl MouseState {
+    pub fn is_button_pressed(&self, button: MouseButton) -> bool {
+        mouse_button_bit(button).is_some_and(|bit| self.pressed_buttons & bit != 0)
+    }
+}
+
+impl InputState {
+    pub fn is_key_pressed(&self, key: KeyCode) -> bool {
+        PhysicalKey::Code(key)
+            .to_scancode()
+            .is_some_and(|scancode| self.pressed_keys.contains(&scancode))
+    }
+
+    pub fn is_mouse_button_pressed(&self, button: MouseButton) -> bool {
+        self.mouse.is_button_pressed(button)
+    }
+
+    /// Resets the deltas once they have been handed to a frame.
+    pub(crate) fn end_frame(&mut self) {
+        let mouse = &mut self.mouse;
+        mouse.delta = [0.0; 2];
+        mouse.raw_delta = [0.0; 2];
+        mouse.scroll_lines = [0.0; 2];
+        mouse.scroll_pixels = [0.0; 2];
+    }
+
+    // the cursor stays in place while locked, only raw motion tells how the mouse moved
+    pub(crate) fn handle_mouse_motion(&mut self, (x, y): (f64, f64), cursor_locked: bool) {
+        self.mouse.raw_delta[0] += x as f32;
+        self.mouse.raw_delta[1] += y as f32;
+        if cursor_locked {
+            self.mouse.delta[0] += x as f32;
+            self.mouse.delta[1] += y as f32;
+        }
+    }
+
+    pub(crate) fn handle_window_event(&mut self, event: &WindowEvent, cursor_locked: bool) {
+        match event {
+            WindowEvent::KeyboardInput { event, .. } => {
+                let Some(scancode) = event.physical_key.to_scancode() else {
+                    return;
+                };
+                match event.state {
+                    ElementState::Pressed => self.pressed_keys.insert(scancode),
+                    ElementState::Released => self.pressed_keys.remove(&scancode),
+                };
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let Some(bit) = mouse_button_bit(*button) else {
+                    return;
+                };
+                match state {
+                    ElementState::Pressed => self.mouse.pressed_buttons |= bit,
+                    ElementState::Released => self.mouse.pressed_buttons &= !bit,
+                }
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                let position = [position.x as f32, position.y as f32];
+                // entering the window is not a motion
+                if let Some([previous_x, previous_y]) = self.mouse.position
+                    && !cursor_locked
+                {
+                    self.mouse.delta[0] += position[0] - previous_x;
+                    self.mouse.delta[1] += position[1] - previous_y;
+                }
+                self.mouse.position = Some(position);
+            }
+            WindowEvent::CursorLeft { .. } => self.mouse.position = None,
+            WindowEvent::MouseWheel { delta, .. } => match delta {
+                MouseScrollDelta::LineDelta(x, y) => {
+                    self.mouse.scroll_lines[0] += x;
+                    self.mouse.scroll_lines[1] += y;
+                }
+                MouseScrollDelta::PixelDelta(position) => {
+                    self.mouse.scroll_pixels[0] += position.x as f32;
+                    self.mouse.scroll_pixels[1] += position.y as f32;
+                }
+            },
+            // keys released while unfocused would otherwise stay pressed
+            WindowEvent::Focused(false) => {
+                self.pressed_keys.clear();
+                self.mouse.pressed_buttons = 0;
+            }
+            _ => (),
+        }
+    }
+}
+
+fn mouse_button_bit(button: MouseButton) -> Option<u32> {
+    match button {
+        MouseButton::Left => Some(1 << 0),
+        MouseButton::Right => Some(1 << 1),
+        MouseButton::Middle => Some(1 << 2),
+        MouseButton::Back => Some(1 << 3),
+        MouseButton::Forward => Some(1 << 4),
+        MouseButton::Other(index) if index < 27 => Some(1 << (5 + index)),
+        MouseButton::Other(_) => None,
+    }
+}
+
+/// Everything [`ApplicationState::update`](crate::application::ApplicationState::update) may
+/// depend on besides the state itself, either live or read back from a replay log.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FrameInput {
+    /// Time elapsed since the previous update, zero for the first one.
+    pub delta_time: Duration,
+    pub input: InputState,
+}
@@ -1,10 +1,30 @@
+//! ## Cargo features
+//!
+//! - `obj` (default): mesh loading from OBJ files
+//! - `ply` (default): mesh and point cloud loading from PLY files
+//! - `png` (default): PNG encoding of read back images, along with the screenshot hotkeys
+//! - `serde`: serialization of render graph snapshots
+//! - `handle-backtraces`: creation backtraces of vulkan objects leaked in debug builds
+//!
+//! Applications providing their own meshes and captures can use the minimal build, with
+//! `default-features = false`.
+
 // re-exports
 pub use ash;
 pub use winit;
 
 pub mod application;
+pub mod benchmark;
 pub mod gfx;
+pub mod input;
 pub mod math;
+pub mod monitor;
+pub mod prelude;
+pub mod replay;
+pub mod user_event;
 pub mod utils;
+pub mod window_control;
 
+#[cfg(feature = "png")]
+mod capture;
 mod debug;
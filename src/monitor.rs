@@ -0,0 +1,111 @@
+use winit::{
+    monitor::{MonitorHandle, VideoModeHandle},
+    window::Window,
+};
+
+/// Display connected to the system, see
+/// [`Context::monitors`](crate::gfx::context::Context::monitors).
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonitorInfo {
+    /// `None` when the platform does not name its monitors.
+    pub name: Option<String>,
+    /// Resolution of the current video mode, in physical pixels.
+    pub size: (u32, u32),
+    /// Top-left corner on the desktop, in physical pixels.
+    pub position: (i32, i32),
+    /// In Hz, `None` when the platform does not report it.
+    pub refresh_rate: Option<f32>,
+    pub scale_factor: f64,
+    /// Modes the monitor can be switched to for exclusive fullscreen, from the largest and
+    /// fastest.
+    pub video_modes: Vec<VideoModeInfo>,
+
+    pub(crate) handle: MonitorHandle,
+}
+
+/// Resolution, color depth and refresh rate a monitor can be switched to, see
+/// [`FullscreenMode::Exclusive`](crate::gfx::context::FullscreenMode::Exclusive).
+#[derive(Debug, Clone, PartialEq)]
+pub struct VideoModeInfo {
+    /// In physical pixels.
+    pub size: (u32, u32),
+    pub bit_depth: u16,
+    /// In Hz.
+    pub refresh_rate: f32,
+
+    pub(crate) handle: VideoModeHandle,
+}
+
+/// Video mode wanted when the window is created, before the modes can be listed. The mode of the
+/// monitor closest to it is used.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct VideoModeRequest {
+    pub size: (u32, u32),
+    /// The fastest mode of that size when `None`.
+    pub refresh_rate: Option<f32>,
+}
+
+impl MonitorInfo {
+    pub(crate) fn new(handle: MonitorHandle) -> Self {
+        let size = handle.size();
+        let position = handle.position();
+        let mut video_modes = handle
+            .video_modes()
+            .map(VideoModeInfo::new)
+            .collect::<Vec<_>>();
+        video_modes.sort_by(|a, b| a.handle.cmp(&b.handle));
+
+        Self {
+            name: handle.name(),
+            size: (size.width, size.height),
+            position: (position.x, position.y),
+            refresh_rate: handle
+                .refresh_rate_millihertz()
+                .map(|millihertz| millihertz as f32 / 1000.0),
+            scale_factor: handle.scale_factor(),
+            video_modes,
+            handle,
+        }
+    }
+
+    /// Mode with the requested size, and the closest refresh rate, or the largest mode smaller
+    /// than it when there is none.
+    pub fn closest_video_mode(&self, request: VideoModeRequest) -> Option<&VideoModeInfo> {
+        let mut same_size = self
+            .video_modes
+            .iter()
+            .filter(|mode| mode.size == request.size);
+        let same_size_mode = match request.refresh_rate {
+            Some(refresh_rate) => same_size.min_by(|a, b| {
+                let distance = |mode: &VideoModeInfo| (mode.refresh_rate - refresh_rate).abs();
+                distance(a).total_cmp(&distance(b))
+            }),
+            None => same_size.next(),
+        };
+
+        // modes are sorted from the largest and fastest, the first one that fits is the best
+        let (width, height) = request.size;
+        same_size_mode.or_else(|| {
+            self.video_modes
+                .iter()
+                .find(|mode| mode.size.0 <= width && mode.size.1 <= height)
+        })
+    }
+}
+
+impl VideoModeInfo {
+    pub(crate) fn new(handle: VideoModeHandle) -> Self {
+        let size = handle.size();
+
+        Self {
+            size: (size.width, size.height),
+            bit_depth: handle.bit_depth(),
+            refresh_rate: handle.refresh_rate_millihertz() as f32 / 1000.0,
+            handle,
+        }
+    }
+}
+
+pub(crate) fn available_monitors(window: &Window) -> Vec<MonitorInfo> {
+    window.available_monitors().map(MonitorInfo::new).collect()
+}
@@ -0,0 +1,23 @@
+//! Types a typical application touches, `use miel::prelude::*;` to bring them all in.
+
+pub use crate::{
+    application::{Application, ApplicationState, ControlFlow, InputResponse, WindowCreationInfo},
+    gfx::{
+        buffer::Buffer,
+        color::Color,
+        context::{Context, ContextCreateInfo},
+        image::Image,
+        mesh::Mesh,
+        render_graph::{
+            RenderGraphInfo,
+            render_pass::{AttachmentOps, RenderPass, SimpleRenderPass},
+            resource::{
+                FrameResources, ImageAttachmentInfo, ResourceAccessType, ResourceID,
+                ResourceInfoRegistry,
+            },
+        },
+    },
+    input::FrameInput,
+    math::{EulerRot, Mat4, Quat, Vec2, Vec3, Vec4},
+    user_event::UserEvent,
+};
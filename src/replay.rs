@@ -0,0 +1,358 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use thiserror::Error;
+
+use crate::input::{FrameInput, InputState};
+
+// Replay logs are a header followed by one record per frame:
+//   delta time in nanoseconds (u64), pressed mouse buttons (u32), cursor presence (u8) followed by
+//   its position (2 f32) when present, cursor delta, raw mouse delta, scroll in lines and scroll in
+//   pixels (2 f32 each), pressed key count (u16) and their scancodes (u32 each)
+// All values are little-endian.
+//
+// Replaying a log guarantees that `update` sees the same inputs and deltas bit for bit. Anything
+// else a state depends on is not recorded and may still differ between runs: GPU readback
+// latency, thread scheduling, wall clock reads of its own, and events received through the key
+// and mouse hooks of the state, which are always live.
+const MAGIC: [u8; 4] = *b"MRPL";
+pub const REPLAY_LOG_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Default)]
+pub enum ReplayMode {
+    #[default]
+    Off,
+    Record(PathBuf),
+    Replay(PathBuf),
+}
+
+#[derive(Debug, Error)]
+pub enum ReplayLogError {
+    #[error("replay log access failed")]
+    Io(#[from] std::io::Error),
+
+    #[error("not a replay log")]
+    InvalidMagic,
+
+    #[error("replay log version {found} is not supported (expected {supported})")]
+    UnsupportedVersion { found: u32, supported: u32 },
+
+    #[error("replay log is truncated")]
+    Truncated,
+}
+
+pub struct ReplayRecorder {
+    writer: BufWriter<File>,
+}
+
+impl ReplayRecorder {
+    pub fn create(path: &Path) -> Result<Self, ReplayLogError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&REPLAY_LOG_VERSION.to_le_bytes())?;
+
+        Ok(Self { writer })
+    }
+
+    pub fn record(&mut self, frame: &FrameInput) -> Result<(), ReplayLogError> {
+        let input = &frame.input;
+        let mouse = &input.mouse;
+        let delta_nanos = u64::try_from(frame.delta_time.as_nanos()).unwrap_or(u64::MAX);
+
+        let mut record = vec![];
+        record.extend_from_slice(&delta_nanos.to_le_bytes());
+        record.extend_from_slice(&mouse.pressed_buttons.to_le_bytes());
+        match mouse.position {
+            Some([x, y]) => {
+                record.push(1);
+                record.extend_from_slice(&x.to_le_bytes());
+                record.extend_from_slice(&y.to_le_bytes());
+            }
+            None => record.push(0),
+        }
+        for [x, y] in [
+            mouse.delta,
+            mouse.raw_delta,
+            mouse.scroll_lines,
+            mouse.scroll_pixels,
+        ] {
+            record.extend_from_slice(&x.to_le_bytes());
+            record.extend_from_slice(&y.to_le_bytes());
+        }
+        let key_count = u16::try_from(input.pressed_keys.len()).unwrap_or(u16::MAX);
+        record.extend_from_slice(&key_count.to_le_bytes());
+        for scancode in input.pressed_keys.iter().take(key_count as usize) {
+            record.extend_from_slice(&scancode.to_le_bytes());
+        }
+
+        self.writer.write_all(&record)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), ReplayLogError> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Reads the frames of a replay log, which does not need a window or a context to be played back.
+pub struct ReplayReader {
+    reader: BufReader<File>,
+}
+
+impl ReplayReader {
+    pub fn open(path: &Path) -> Result<Self, ReplayLogError> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        let mut magic = [0; 4];
+        read_exact(&mut reader, &mut magic)?;
+        if magic != MAGIC {
+            return Err(ReplayLogError::InvalidMagic);
+        }
+
+        let version = u32::from_le_bytes(read_array(&mut reader)?);
+        if version != REPLAY_LOG_VERSION {
+            return Err(ReplayLogError::UnsupportedVersion {
+                found: version,
+                supported: REPLAY_LOG_VERSION,
+            });
+        }
+
+        Ok(Self { reader })
+    }
+
+    /// Returns `None` once every frame has been read.
+    pub fn next_frame(&mut self) -> Result<Option<FrameInput>, ReplayLogError> {
+        let mut delta_nanos = [0; 8];
+        match self.reader.read(&mut delta_nanos[..1])? {
+            0 => return Ok(None),
+            _ => read_exact(&mut self.reader, &mut delta_nanos[1..])?,
+        }
+
+        let mut input = InputState::default();
+        let mouse = &mut input.mouse;
+        mouse.pressed_buttons = u32::from_le_bytes(read_array(&mut self.reader)?);
+        let [has_cursor] = read_array(&mut self.reader)?;
+        if has_cursor != 0 {
+            mouse.position = Some(read_vec2(&mut self.reader)?);
+        }
+        mouse.delta = read_vec2(&mut self.reader)?;
+        mouse.raw_delta = read_vec2(&mut self.reader)?;
+        mouse.scroll_lines = read_vec2(&mut self.reader)?;
+        mouse.scroll_pixels = read_vec2(&mut self.reader)?;
+        let key_count = u16::from_le_bytes(read_array(&mut self.reader)?);
+        for _ in 0..key_count {
+            input
+                .pressed_keys
+                .insert(u32::from_le_bytes(read_array(&mut self.reader)?));
+        }
+
+        Ok(Some(FrameInput {
+            delta_time: Duration::from_nanos(u64::from_le_bytes(delta_nanos)),
+            input,
+        }))
+    }
+}
+
+impl Iterator for ReplayReader {
+    type Item = Result<FrameInput, ReplayLogError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_frame().transpose()
+    }
+}
+
+fn read_exact(reader: &mut impl Read, buffer: &mut [u8]) -> Result<(), ReplayLogError> {
+    reader.read_exact(buffer).map_err(|err| match err.kind() {
+        std::io::ErrorKind::UnexpectedEof => ReplayLogError::Truncated,
+        _ => ReplayLogError::Io(err),
+    })
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> Result<[u8; N], ReplayLogError> {
+    let mut array = [0; N];
+    read_exact(reader, &mut array)?;
+    Ok(array)
+}
+
+fn read_vec2(reader: &mut impl Read) -> Result<[f32; 2], ReplayLogError> {
+    Ok([
+        f32::from_le_bytes(read_array(reader)?),
+        f32::from_le_bytes(read_array(reader)?),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+
+    use super::*;
+    use crate::math::{EulerRot, Mat4, Quat, Vec3};
+
+    // scancodes moving the test camera forward and backward
+    const FORWARD_KEY: u32 = 17;
+    const BACKWARD_KEY: u32 = 31;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("miel-replay-{}-{name}", std::process::id()))
+    }
+
+    fn frames() -> Vec<FrameInput> {
+        (0..120u32)
+            .map(|i| {
+                let mut input = InputState::default();
+                let mouse = &mut input.mouse;
+                mouse.pressed_buttons = (i / 10) % 4;
+                mouse.position = (i % 7 != 0).then(|| [i as f32 * 3.25, 720.0 - i as f32 / 3.0]);
+                mouse.delta = [(i as f32 * 0.37).sin() * 12.5, (i as f32 * 0.11).cos()];
+                mouse.raw_delta = [mouse.delta[0] * 1.5, mouse.delta[1] * 1.5];
+                mouse.scroll_lines = [0.0, if i % 13 == 0 { 1.0 } else { 0.0 }];
+                mouse.scroll_pixels = [0.0, (i % 5) as f32 * 0.1];
+                if i % 3 != 0 {
+                    input.pressed_keys.insert(FORWARD_KEY);
+                }
+                if i % 11 == 0 {
+                    input.pressed_keys.insert(BACKWARD_KEY);
+                }
+
+                FrameInput {
+                    delta_time: Duration::from_nanos(16_666_667 + u64::from(i) * 37),
+                    input,
+                }
+            })
+            .collect()
+    }
+
+    fn record(name: &str, frames: &[FrameInput]) -> PathBuf {
+        let path = temp_path(name);
+        let mut recorder = ReplayRecorder::create(&path).unwrap();
+        for frame in frames {
+            recorder.record(frame).unwrap();
+        }
+        recorder.flush().unwrap();
+
+        path
+    }
+
+    fn replay(path: &Path) -> Vec<FrameInput> {
+        let frames = ReplayReader::open(path)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        frames
+    }
+
+    // a first-person camera turning with the mouse while a button is held and moving with keys
+    fn camera_transforms_hash(frames: &[FrameInput]) -> u64 {
+        let (mut yaw, mut pitch) = (0.0f32, 0.0f32);
+        let mut position = Vec3::ZERO;
+        let mut hasher = DefaultHasher::new();
+        for frame in frames {
+            let mouse = &frame.input.mouse;
+            if mouse.pressed_buttons != 0 {
+                yaw -= mouse.delta[0] * 0.005;
+                pitch = (pitch - mouse.delta[1] * 0.005).clamp(-1.5, 1.5);
+            }
+            let rotation = Quat::from_euler(EulerRot::YXZ, yaw, pitch, 0.0);
+            let keys = &frame.input.pressed_keys;
+            let speed = match (keys.contains(&FORWARD_KEY), keys.contains(&BACKWARD_KEY)) {
+                (true, false) => 3.0,
+                (false, true) => -3.0,
+                _ => 0.0,
+            } + mouse.scroll_lines[1]
+                + mouse.scroll_pixels[1];
+            position += rotation * Vec3::NEG_Z * speed * frame.delta_time.as_secs_f32();
+
+            let transform = Mat4::from_rotation_translation(rotation, position);
+            for value in transform.to_cols_array() {
+                value.to_bits().hash(&mut hasher);
+            }
+        }
+
+        hasher.finish()
+    }
+
+    #[test]
+    fn recorded_frames_are_read_back_identically() {
+        let frames = frames();
+
+        let replayed = replay(&record("round-trip", &frames));
+
+        assert_eq!(replayed, frames);
+    }
+
+    #[test]
+    fn headless_replay_reproduces_camera_transforms() {
+        let frames = frames();
+        let live_hash = camera_transforms_hash(&frames);
+
+        let replayed = replay(&record("camera", &frames));
+        assert_eq!(camera_transforms_hash(&replayed), live_hash);
+
+        // the hash does see a single microsecond of difference
+        let mut drifted = frames;
+        drifted[61].delta_time += Duration::from_micros(1);
+        assert_ne!(camera_transforms_hash(&drifted), live_hash);
+    }
+
+    #[test]
+    fn empty_log_has_no_frames() {
+        let replayed = replay(&record("empty", &[]));
+
+        assert!(replayed.is_empty());
+    }
+
+    #[test]
+    fn foreign_files_are_rejected() {
+        let path = temp_path("magic");
+        std::fs::write(&path, b"PNG\x89\x02\x00\x00\x00").unwrap();
+
+        let result = ReplayReader::open(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(ReplayLogError::InvalidMagic)));
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let path = temp_path("version");
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&(REPLAY_LOG_VERSION + 1).to_le_bytes());
+        std::fs::write(&path, bytes).unwrap();
+
+        let result = ReplayReader::open(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(
+            result,
+            Err(ReplayLogError::UnsupportedVersion { found, supported })
+                if found == REPLAY_LOG_VERSION + 1 && supported == REPLAY_LOG_VERSION
+        ));
+    }
+
+    #[test]
+    fn truncated_frames_are_reported() {
+        let path = record("truncated", &frames()[..2]);
+        let length = std::fs::metadata(&path).unwrap().len();
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(length - 1)
+            .unwrap();
+
+        let mut reader = ReplayReader::open(&path).unwrap();
+        let first = reader.next_frame();
+        let second = reader.next_frame();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(first, Ok(Some(_))));
+        assert!(matches!(second, Err(ReplayLogError::Truncated)));
+    }
+}
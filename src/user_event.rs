@@ -0,0 +1,100 @@
+use std::{any::Any, marker::PhantomData};
+
+use winit::event_loop::{EventLoopClosed, EventLoopProxy};
+
+/// Value posted through an [`EventProxy`], whatever its type, delivered to
+/// [`ApplicationState::on_user_event`](crate::application::ApplicationState::on_user_event).
+pub struct UserEvent {
+    value: Box<dyn Any + Send>,
+    type_name: &'static str,
+}
+
+impl UserEvent {
+    fn new<T: Send + 'static>(value: T) -> Self {
+        Self {
+            value: Box::new(value),
+            type_name: std::any::type_name::<T>(),
+        }
+    }
+
+    pub fn is<T: 'static>(&self) -> bool {
+        self.value.is::<T>()
+    }
+
+    pub fn downcast_ref<T: 'static>(&self) -> Option<&T> {
+        self.value.downcast_ref()
+    }
+
+    /// Takes the value out of the event, which is given back when it is not a `T`, e.g. to try
+    /// another type.
+    pub fn downcast<T: 'static>(self) -> Result<T, Self> {
+        match self.value.downcast() {
+            Ok(value) => Ok(*value),
+            Err(value) => Err(Self {
+                value,
+                type_name: self.type_name,
+            }),
+        }
+    }
+
+    /// Name of the type of the value, for diagnostics only.
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+}
+
+impl std::fmt::Debug for UserEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UserEvent")
+            .field("type_name", &self.type_name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Posts events of type `T` to the application from any thread, e.g. results of background work,
+/// waking its event loop up. Events are delivered in the order they were sent, before the next
+/// update of the active state.
+pub struct EventProxy<T> {
+    proxy: EventLoopProxy<UserEvent>,
+    _event: PhantomData<fn(T)>,
+}
+
+impl<T: Send + 'static> EventProxy<T> {
+    pub(crate) fn new(proxy: EventLoopProxy<UserEvent>) -> Self {
+        Self {
+            proxy,
+            _event: PhantomData,
+        }
+    }
+
+    /// Fails once the event loop has exited, giving the event back.
+    pub fn send(&self, event: T) -> Result<(), EventLoopClosed<T>> {
+        self.proxy
+            .send_event(UserEvent::new(event))
+            .map_err(|EventLoopClosed(event)| {
+                EventLoopClosed(
+                    event
+                        .downcast()
+                        .unwrap_or_else(|_| unreachable!("event type is fixed by the proxy")),
+                )
+            })
+    }
+}
+
+// derived impls would require `T: Clone`
+impl<T> Clone for EventProxy<T> {
+    fn clone(&self) -> Self {
+        Self {
+            proxy: self.proxy.clone(),
+            _event: PhantomData,
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for EventProxy<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventProxy")
+            .field("event_type", &std::any::type_name::<T>())
+            .finish()
+    }
+}
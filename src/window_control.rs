@@ -0,0 +1,71 @@
+use winit::window::{UserAttentionType, Window};
+
+/// Changes to the window requested by the states, applied by the application once the current
+/// update returns.
+#[derive(Debug, Default)]
+pub(crate) struct WindowRequests {
+    title: Option<String>,
+    resizable: Option<bool>,
+    decorations: Option<bool>,
+    attention: Option<Option<UserAttentionType>>,
+}
+
+impl WindowRequests {
+    pub fn apply(&mut self, window: &Window) {
+        let Self {
+            title,
+            resizable,
+            decorations,
+            attention,
+        } = std::mem::take(self);
+
+        if let Some(title) = title {
+            window.set_title(&title);
+        }
+        if let Some(resizable) = resizable {
+            window.set_resizable(resizable);
+        }
+        if let Some(decorations) = decorations {
+            window.set_decorations(decorations);
+        }
+        if let Some(attention) = attention {
+            window.request_user_attention(attention);
+        }
+    }
+}
+
+/// Window of the application, as seen from a [`Context`](crate::gfx::context::Context). Changes
+/// are applied once the current update returns, only the last one of each kind when made several
+/// times in the same update.
+#[derive(Debug)]
+pub struct WindowControl<'a> {
+    requests: &'a mut WindowRequests,
+}
+
+impl<'a> WindowControl<'a> {
+    pub(crate) fn new(requests: &'a mut WindowRequests) -> Self {
+        Self { requests }
+    }
+
+    pub fn set_title(&mut self, title: &str) -> &mut Self {
+        self.requests.title = Some(title.to_owned());
+        self
+    }
+
+    pub fn set_resizable(&mut self, resizable: bool) -> &mut Self {
+        self.requests.resizable = Some(resizable);
+        self
+    }
+
+    pub fn set_decorations(&mut self, decorations: bool) -> &mut Self {
+        self.requests.decorations = Some(decorations);
+        self
+    }
+
+    /// Flashes the window or its taskbar entry until it is focused, depending on the platform.
+    /// `None` cancels a previous request.
+    pub fn request_attention(&mut self, attention: Option<UserAttentionType>) -> &mut Self {
+        self.requests.attention = Some(attention);
+        self
+    }
+}
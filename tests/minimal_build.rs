@@ -0,0 +1,28 @@
+use std::{path::Path, process::Command};
+
+// Loaders and the PNG encoder are only compiled with their feature, code leaking out of its
+// `cfg` breaks the smallest build while every default one keeps passing
+#[test]
+fn builds_without_default_features() {
+    let status = Command::new(env!("CARGO"))
+        .args([
+            "check",
+            "--package",
+            "miel",
+            "--lib",
+            "--no-default-features",
+        ])
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        // the target directory of the running tests may still be locked
+        .env(
+            "CARGO_TARGET_DIR",
+            Path::new(env!("CARGO_TARGET_TMPDIR")).join("minimal-build"),
+        )
+        .status()
+        .expect("cargo should be runnable");
+
+    assert!(
+        status.success(),
+        "`cargo check --no-default-features` failed"
+    );
+}